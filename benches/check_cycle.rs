@@ -0,0 +1,64 @@
+//! Benchmarks of a full check cycle with the lookups answered from memory.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use dnschecker::check::{self, Check, Context};
+use dnschecker::server::Shared;
+use std::env;
+use std::sync::Arc;
+
+/// Stands in for the "resolve_dns" check with a fixed answer.
+struct StaticDns(Vec<String>);
+
+impl Check for StaticDns {
+    fn name(&self) -> &'static str {
+        "resolve_dns"
+    }
+
+    fn run(&self, context: &mut Context) {
+        context.dns_ips = self.0.clone();
+        context.dns_ip = context.dns_ips.first().cloned().unwrap_or_default();
+    }
+}
+
+/// Stands in for the "fetch_wan_ip" check with fixed interfaces, selecting one like the real check.
+struct StaticWan(Vec<(String, String)>);
+
+impl Check for StaticWan {
+    fn name(&self) -> &'static str {
+        "fetch_wan_ip"
+    }
+
+    fn run(&self, context: &mut Context) {
+        context.wan_ips = self.0.clone();
+        context.wan_source = "api".to_string();
+        check::select_interface(context);
+    }
+}
+
+fn check_cycle(c: &mut Criterion) {
+    // Keep the alarm state out of /tmp and build only the checks of the cycle
+    let directory = tempfile::tempdir().unwrap();
+    env::set_var("LOCKFILE", directory.path().join("telegram.lock"));
+    env::set_var("CHECKS", "resolve_dns,fetch_wan_ip,compare");
+    env::set_var("INTERFACE", "igb0,igb1");
+
+    let compare = check::pipeline(&Arc::new(Shared::default()))
+        .unwrap()
+        .into_iter()
+        .find(|check| check.name() == "compare")
+        .unwrap();
+    let checks: Vec<Box<dyn Check>> = vec![
+        Box::new(StaticDns(vec!["203.0.113.7".to_string()])),
+        Box::new(StaticWan(vec![
+            ("igb0".to_string(), "203.0.113.7".to_string()),
+            ("igb1".to_string(), "198.51.100.4".to_string()),
+        ])),
+        compare,
+    ];
+    c.bench_function("check cycle in sync", |b| {
+        b.iter(|| check::run_pipeline(&checks, "home.example.com"))
+    });
+}
+
+criterion_group!(benches, check_cycle);
+criterion_main!(benches);
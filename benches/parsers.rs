@@ -0,0 +1,56 @@
+//! Benchmarks of the parsers of router API and Telegram responses.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use dnschecker::{api, telegram};
+
+/// A router API response with a WAN interface and a few others, as returned by the router on every check.
+fn router_response() -> String {
+    let interface = |name: &str, ipv4: &str, ipv6: &str| {
+        serde_json::json!({
+            "name": name,
+            "status": "up",
+            "ipv4": [{"ipaddr": ipv4, "subnetbits": 24}],
+            "ipv6": [
+                {"ipaddr": "fe80::1", "subnetbits": 64},
+                {"ipaddr": ipv6, "subnetbits": 56},
+            ],
+        })
+    };
+    serde_json::json!({
+        "igb0": interface("WAN", "203.0.113.7", "2001:db8:1234:5600::1"),
+        "igb1": interface("LAN", "192.168.1.1", "2001:db8:1234:5601::1"),
+        "igb2": interface("DMZ", "192.168.2.1", "2001:db8:1234:5602::1"),
+        "wg0": interface("VPN", "10.0.0.1", "fd00::1"),
+    })
+    .to_string()
+}
+
+fn parsers(c: &mut Criterion) {
+    let response = router_response();
+    c.bench_function("api::parse_json", |b| {
+        b.iter(|| api::parse_json(black_box(response.clone()), "igb0"))
+    });
+    c.bench_function("api::parse_ipv6_prefix", |b| {
+        b.iter(|| api::parse_ipv6_prefix(black_box(response.clone()), "igb0", None))
+    });
+
+    let response = serde_json::json!({
+        "ok": true,
+        "result": {
+            "message_id": 42,
+            "chat": {"id": -1001234567890i64, "title": "Alerts", "type": "supergroup"},
+            "date": 1714550400,
+            "text": "IP address mismatch for home.example.com",
+        },
+    })
+    .to_string();
+    c.bench_function("telegram::parse_json", |b| {
+        b.iter(|| telegram::parse_json(black_box(response.clone())))
+    });
+    c.bench_function("telegram::parse_message", |b| {
+        b.iter(|| telegram::parse_message(black_box(&response)))
+    });
+}
+
+criterion_group!(benches, parsers);
+criterion_main!(benches);
@@ -0,0 +1,69 @@
+//! Benchmarks of resolver lookups against a name server on the loopback interface.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use dnschecker::dns;
+use std::net::{SocketAddr, UdpSocket};
+use trust_dns_resolver::proto::op::{Message, MessageType};
+use trust_dns_resolver::proto::rr::rdata::A;
+use trust_dns_resolver::proto::rr::{RData, Record, RecordType};
+
+/// Starts a name server on the loopback interface that answers every A query with 192.0.2.1
+/// and every other query with no records.
+///
+/// # Returns
+///
+/// * A `SocketAddr` that holds the address of the name server.
+fn mock_server() -> SocketAddr {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let mut buffer = [0u8; 512];
+        loop {
+            let (length, from) = match server.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(_) => continue,
+            };
+            let query = match Message::from_vec(&buffer[..length]) {
+                Ok(query) => query,
+                Err(_) => continue,
+            };
+            let mut response = Message::new();
+            response
+                .set_id(query.id())
+                .set_message_type(MessageType::Response)
+                .set_recursion_available(true)
+                .add_queries(query.queries().to_vec());
+            for question in query.queries() {
+                if question.query_type() == RecordType::A {
+                    response.add_answer(Record::from_rdata(
+                        question.name().clone(),
+                        300,
+                        RData::A(A::new(192, 0, 2, 1)),
+                    ));
+                }
+            }
+            server.send_to(&response.to_vec().unwrap(), from).ok();
+        }
+    });
+    addr
+}
+
+fn resolver(c: &mut Criterion) {
+    let addr = mock_server();
+    let resolver = addr.to_string();
+    assert_eq!(
+        dns::try_resolve_addresses("home.example.com", &resolver).unwrap(),
+        ["192.0.2.1"]
+    );
+
+    // Every lookup builds its resolver, like the "resolve_dns" check on every cycle
+    c.bench_function("dns::try_resolve_addresses", |b| {
+        b.iter(|| dns::try_resolve_addresses("home.example.com", &resolver))
+    });
+    c.bench_function("dns::query_server", |b| {
+        b.iter(|| dns::query_server(addr, "home.example.com.", RecordType::A))
+    });
+}
+
+criterion_group!(benches, resolver);
+criterion_main!(benches);
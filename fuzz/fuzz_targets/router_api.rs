@@ -0,0 +1,12 @@
+#![no_main]
+
+//! Feeds arbitrary responses to the parsers of the router API.
+
+use dnschecker::api;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (String, Option<u8>)| {
+    let (response, prefix_len) = input;
+    api::parse_json(response.clone(), "igb0");
+    api::parse_ipv6_prefix(response, "igb0", prefix_len);
+});
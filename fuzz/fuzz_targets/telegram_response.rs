@@ -0,0 +1,12 @@
+#![no_main]
+
+//! Feeds arbitrary responses to the parsers of the Telegram Bot API.
+
+use dnschecker::telegram;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|response: String| {
+    telegram::parse_json(response.clone());
+    telegram::parse_message(&response);
+    telegram::migrated_chat_id(&response);
+});
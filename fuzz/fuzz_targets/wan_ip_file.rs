@@ -0,0 +1,17 @@
+#![no_main]
+
+//! Feeds arbitrary files to the formats of the passive source.
+
+use dnschecker::passive::Format;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|contents: String| {
+    for format in [
+        Format::Plain,
+        Format::Ddclient,
+        Format::Json("wan.ip".to_string()),
+        Format::Log,
+    ] {
+        format.extract(&contents);
+    }
+});
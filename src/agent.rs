@@ -0,0 +1,365 @@
+use crate::events;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::vars::{
+    display_hostname, expand_apex, get_var_from_env, normalize_hostname, parse_duration,
+};
+use crate::{dns, http, kubernetes};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use shuteye::sleep;
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The answer of an agent for the monitored hostname, as pushed to the aggregator.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentReport {
+    /// The name of the agent, e.g. the site it runs in.
+    pub agent: String,
+    /// The hostname the agent resolved.
+    pub hostname: String,
+    /// The IPv4 address the agent resolved, empty if the hostname could not be resolved.
+    pub ip: String,
+    /// The time of the lookup on the agent, in RFC 3339 format.
+    pub timestamp: String,
+}
+
+/// The latest report of each agent, as received by the aggregator.
+#[derive(Debug, Default)]
+pub struct AgentRegistry {
+    reports: Mutex<BTreeMap<String, (AgentReport, Instant)>>,
+}
+
+impl AgentRegistry {
+    /// Stores a report, replacing the previous report of the same agent.
+    pub fn record(&self, report: AgentReport) {
+        let mut reports = self.reports.lock().unwrap_or_else(|e| e.into_inner());
+        reports.insert(report.agent.clone(), (report, Instant::now()));
+    }
+
+    /// Returns the reports received within the given age, ordered by agent name.
+    pub fn fresh(&self, max_age: Duration) -> Vec<AgentReport> {
+        let reports = self.reports.lock().unwrap_or_else(|e| e.into_inner());
+        reports
+            .values()
+            .filter(|(_, received)| received.elapsed() <= max_age)
+            .map(|(report, _)| report.clone())
+            .collect()
+    }
+}
+
+/// Runs the checker as an agent that reports its DNS answer to an aggregator.
+///
+/// This function retrieves the following environment variables:
+///
+/// * "DNS_HOSTNAME" (and "DNS_ZONE"): the monitored hostname, as on the aggregator.
+/// * "AGENT_TOKEN": the token shared with the aggregator.
+/// * "AGENT_NAME": the name of the agent, defaulting to the pod identity or the host name.
+/// * "AGENT_RESOLVER": the resolver to query, defaulting to "system" (see `dns::resolver_config`).
+///
+/// If "DNS_HOSTNAME" or "AGENT_TOKEN" is missing or invalid, it exits the process.
+/// It then resolves the hostname and pushes the answer to the aggregator using the `push_report` function
+/// every `interval` until a signal is received.
+///
+/// # Arguments
+///
+/// * `url`: A `&str` that specifies the URL of the aggregator, from "AGGREGATOR_URL".
+/// * `interval`: A `Duration` that specifies the time between two reports.
+/// * `sig_received`: A `&AtomicBool` that is set when the process should stop.
+/// * `tick`: A `&dyn Fn()` that is called on every iteration, e.g. to update the liveness probe.
+pub fn run_agent(url: &str, interval: Duration, sig_received: &AtomicBool, tick: &dyn Fn()) {
+    let hostname = get_var_from_env("DNS_HOSTNAME")
+        .map_err(|e| e.to_string())
+        .and_then(|hostname| {
+            normalize_hostname(&expand_apex(
+                &hostname,
+                &env::var("DNS_ZONE").unwrap_or_default(),
+            ))
+        });
+    let (hostname, token) = match (hostname, get_var_from_env("AGENT_TOKEN")) {
+        (Ok(hostname), Ok(token)) => (hostname, token),
+        (Err(e), _) => {
+            log::error!("Invalid DNS_HOSTNAME: {}", e);
+            std::process::exit(1);
+        }
+        (_, Err(_)) => std::process::exit(1),
+    };
+    let name = env::var("AGENT_NAME")
+        .ok()
+        .or_else(kubernetes::pod_identity)
+        .or_else(|| env::var("HOSTNAME").ok())
+        .unwrap_or("agent".to_string());
+    let resolver = env::var("AGENT_RESOLVER").unwrap_or("system".to_string());
+    log::info!(
+        "Running as agent {}, reporting {} to {}",
+        name,
+        hostname,
+        url
+    );
+
+    let mut next_report = Instant::now();
+    while !sig_received.load(Ordering::SeqCst) {
+        tick();
+        if Instant::now() >= next_report {
+            let report = AgentReport {
+                agent: name.clone(),
+                hostname: hostname.clone(),
+                ip: dns::resolve_hostname_using(&hostname, &resolver),
+                timestamp: Local::now().to_rfc3339(),
+            };
+            log::debug!("{} resolves to {}", hostname, report.ip);
+            if !push_report(url, &token, &report) {
+                log::warn!("Failed to report to the aggregator");
+            }
+            next_report = Instant::now() + interval;
+        }
+        sleep(Duration::new(1, 0));
+    }
+}
+
+/// Pushes a report to the aggregator.
+///
+/// This function sends the report as JSON in a POST request to the "/report" endpoint of the aggregator,
+/// authenticated with the shared token as a bearer token.
+///
+/// # Arguments
+///
+/// * `url`: A `&str` that specifies the URL of the aggregator, e.g. "https://checker.example.com:8080".
+/// * `token`: A `&str` that specifies the token shared with the aggregator.
+/// * `report`: A `&AgentReport` that holds the report.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether the aggregator accepted the report.
+pub fn push_report(url: &str, token: &str, report: &AgentReport) -> bool {
+    let client = match http::client() {
+        Ok(client) => client,
+        Err(e) => {
+            log::warn!("{}", e);
+            return false;
+        }
+    };
+    let endpoint = format!("{}/report", url.trim_end_matches('/'));
+    match client
+        .post(&endpoint)
+        .bearer_auth(token)
+        .json(report)
+        .send()
+    {
+        Ok(response) if response.status().is_success() => true,
+        Ok(response) => {
+            log::warn!("Aggregator answered with status {}", response.status());
+            false
+        }
+        Err(e) => {
+            log::warn!("Failed to send report: {}", e);
+            false
+        }
+    }
+}
+
+/// Retrieves how long a report of an agent counts, from the "AGENT_MAX_AGE" environment variable (e.g. "5m").
+///
+/// # Returns
+///
+/// * A `Duration` that holds the maximum age, defaulting to 5 minutes.
+pub fn max_age() -> Duration {
+    env::var("AGENT_MAX_AGE")
+        .ok()
+        .and_then(|value| parse_duration(&value))
+        .unwrap_or(Duration::from_secs(5 * 60))
+}
+
+/// Compares the answers of the agents with the local answer and reports disagreements.
+///
+/// This function takes the hostname, the IP address from DNS and the fresh reports of the agents as arguments.
+/// If the local lookup failed, the comparison is skipped.
+///
+/// The agents that disagree (see `disagreements`) are compared with the state stored in the agents file
+/// ("AGENTS_FILE", defaulting to "agent_state" in the state directory), and an "agents" message is sent
+/// when the set of disagreeing agents changes, or when all agents agree again.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `dns_ips`: A `&[String]` that holds the addresses from DNS.
+/// * `reports`: A `&[AgentReport]` that holds the fresh reports of the agents.
+pub fn check_agents(hostname: &str, dns_ips: &[String], reports: &[AgentReport]) {
+    if dns_ips.is_empty() {
+        log::debug!("No local DNS answer, skipping agent comparison");
+        return;
+    }
+    let agents_file = state_path("AGENTS_FILE", "agent_state");
+    let disagreeing = disagreements(hostname, dns_ips, reports);
+    let dns_ip = dns_ips.join(", ");
+    let state = disagreeing
+        .iter()
+        .map(|report| format!("{}={}", report.agent, report.ip))
+        .collect::<Vec<String>>()
+        .join("\n");
+    let previous = read_state(&agents_file);
+    if previous == state {
+        return;
+    }
+    if !disagreeing.is_empty() || !previous.is_empty() {
+        let text = agents_message(hostname, &dns_ip, &disagreeing);
+        log::info!("{}", text);
+        if !events::alert(&Message::new("agents", &text).with_field("dns_ip", &dns_ip)) {
+            log::warn!("Failed to send agents message");
+            return;
+        }
+    }
+    write_state(&agents_file, &state);
+}
+
+/// Selects the reports for the hostname whose answer is not one of the addresses of the local answer.
+///
+/// Agents report a single address, so with round-robin records any of the local addresses agrees.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `dns_ips`: A `&[String]` that holds the addresses from DNS.
+/// * `reports`: A `&[AgentReport]` that holds the reports of the agents.
+///
+/// # Returns
+///
+/// * A `Vec<AgentReport>` that holds the disagreeing reports. An agent that could not resolve the hostname disagrees.
+fn disagreements(hostname: &str, dns_ips: &[String], reports: &[AgentReport]) -> Vec<AgentReport> {
+    reports
+        .iter()
+        .filter(|report| report.hostname == hostname && !dns_ips.contains(&report.ip))
+        .cloned()
+        .collect()
+}
+
+/// Builds the text of the agents message.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `dns_ip`: A `&str` that specifies the addresses from DNS.
+/// * `disagreeing`: A `&[AgentReport]` that holds the disagreeing reports, empty if all agents agree.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message.
+fn agents_message(hostname: &str, dns_ip: &str, disagreeing: &[AgentReport]) -> String {
+    let hostname = display_hostname(hostname);
+    if disagreeing.is_empty() {
+        return format!(
+            "All agents agree again: {} resolves to {}",
+            hostname, dns_ip
+        );
+    }
+    let answers: Vec<String> = disagreeing
+        .iter()
+        .map(|report| {
+            let ip = if report.ip.is_empty() {
+                "no answer"
+            } else {
+                &report.ip
+            };
+            format!("{}: {}", report.agent, ip)
+        })
+        .collect();
+    format!(
+        "Agents disagree on {}!\nLocal: {}\n{}",
+        hostname,
+        dns_ip,
+        answers.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    fn report(agent: &str, ip: &str) -> AgentReport {
+        AgentReport {
+            agent: agent.to_string(),
+            hostname: "home.example.com".to_string(),
+            ip: ip.to_string(),
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_disagreements() {
+        let reports = vec![
+            report("office", "1.1.1.1"),
+            report("cloud", "2.2.2.2"),
+            report("mobile", ""),
+            AgentReport {
+                hostname: "other.example.com".to_string(),
+                ..report("lab", "3.3.3.3")
+            },
+        ];
+        let result = disagreements("home.example.com", &["1.1.1.1".to_string()], &reports);
+        let agents: Vec<&str> = result.iter().map(|report| report.agent.as_str()).collect();
+        assert_eq!(agents, ["cloud", "mobile"]);
+
+        // Assert that any address of a round-robin answer agrees
+        let round_robin = vec!["1.1.1.1".to_string(), "2.2.2.2".to_string()];
+        let agreeing = disagreements("home.example.com", &round_robin, &reports);
+        let agents: Vec<&str> = agreeing
+            .iter()
+            .map(|report| report.agent.as_str())
+            .collect();
+        assert_eq!(agents, ["mobile"]);
+
+        let text = agents_message("home.example.com", "1.1.1.1", &result);
+        assert!(text.contains("cloud: 2.2.2.2"));
+        assert!(text.contains("mobile: no answer"));
+        assert!(agents_message("home.example.com", "1.1.1.1", &[]).contains("agree again"));
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry = AgentRegistry::default();
+        registry.record(report("office", "1.1.1.1"));
+        registry.record(report("office", "2.2.2.2"));
+        registry.record(report("cloud", "2.2.2.2"));
+
+        // Assert that the latest report of each agent is kept
+        let fresh = registry.fresh(Duration::from_secs(60));
+        assert_eq!(
+            fresh,
+            [report("cloud", "2.2.2.2"), report("office", "2.2.2.2")]
+        );
+
+        // Assert that expired reports are left out
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(registry.fresh(Duration::from_millis(1)).is_empty());
+    }
+
+    #[test]
+    fn test_push_report() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/report")
+                .header("Authorization", "Bearer secret")
+                .json_body_obj(&report("office", "1.1.1.1"));
+            then.status(202);
+        });
+
+        assert!(push_report(
+            &server.base_url(),
+            "secret",
+            &report("office", "1.1.1.1")
+        ));
+        mock.assert();
+
+        // Assert that a rejected report is reported as a failure
+        assert!(!push_report(
+            &server.base_url(),
+            "wrong",
+            &report("office", "1.1.1.1")
+        ));
+    }
+}
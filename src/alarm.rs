@@ -0,0 +1,394 @@
+use crate::api;
+use crate::events;
+use crate::leader;
+use crate::notify::Message;
+use crate::state::{read_state, remove_state, state_path, write_state};
+use crate::telegram;
+use crate::vars::display_hostname;
+use chrono::Duration as ChronoDuration;
+use chrono::{DateTime, Local};
+
+/// Raises or clears the alarm when there is an IP address mismatch between the router and the DNS server.
+///
+/// This function takes the monitored hostname, a router IP address, a DNS server IP address
+/// and whether they are considered in sync as arguments.
+/// It first retrieves the lockfile paths from environment variables and constructs the text of the alarm.
+/// It checks if an alarm has already been sent by reading the timestamp from the lockfile.
+/// If an alarm has already been sent and the IP addresses are in sync again, it resets the alarm.
+/// If an alarm has not been sent and the IP addresses are not in sync, it publishes an alarm to every configured channel
+/// and hook using the `events::alert` function.
+/// While another instance leads (see `leader::is_leader`), it leaves the alarm state untouched.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname; it is shown in its Unicode form.
+/// * `router_ip`: A `&str` that specifies the router IP address.
+/// * `dns_ip`: A `&str` that specifies the DNS server IP address.
+/// * `in_sync`: A `bool` that specifies whether the IP addresses are considered in sync;
+///   usually whether they are equal, unless a comparison script decided otherwise.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether the function succeeded.
+/// * If the function succeeds, it returns `true`.
+/// * If the function fails, it returns `false`.
+pub fn process(hostname: &str, router_ip: &str, dns_ip: &str, in_sync: bool) -> bool {
+    if !leader::is_leader() {
+        // A follower does not send alerts, so recording one as sent would keep the leader from sending it
+        log::trace!("Another instance leads, leaving the alarm state to it");
+        return true;
+    }
+    let (lockfile, ok_lockfile) = lockfile_paths();
+    let text = format!(
+        "IP address mismatch between router and DNS server!\nHostname: {}\nRouter IP: {}\nDNS IP: {}",
+        display_hostname(hostname),
+        router_ip,
+        dns_ip
+    );
+    let message = Message::new("mismatch", &text)
+        .with_field("hostname", hostname)
+        .with_field("router_ip", router_ip)
+        .with_field("dns_ip", dns_ip);
+
+    let alarm_sent = read_timestamp_from_file(&lockfile, ALARM_HOURS);
+    let ok_sent = read_timestamp_from_file(&ok_lockfile, RECOVERY_HOURS);
+    match next_action(alarm_sent, ok_sent, in_sync) {
+        Action::Recovery => {
+            log::debug!("IP addresses are the same again, resetting alarm");
+            create_timestamp(&ok_lockfile);
+            reset_alarm(&lockfile, hostname, router_ip).is_ok()
+        }
+        Action::Alarm => {
+            log::info!("Sending alarm");
+            if events::alert(&api::with_router_context(message)) {
+                create_timestamp(&lockfile);
+                true
+            } else {
+                false
+            }
+        }
+        Action::Unchanged => {
+            if alarm_sent && !in_sync {
+                telegram::update_incident();
+            }
+            log::trace!("IP addresses are the same, not sending alarm");
+            true
+        }
+    }
+}
+
+/// How long a sent alarm suppresses further alarms, in hours.
+pub const ALARM_HOURS: i64 = 6;
+
+/// How long a sent recovery message suppresses new alarms, in hours.
+pub const RECOVERY_HOURS: i64 = 1;
+
+/// What `process` does with the result of a comparison.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// Sends the alarm.
+    Alarm,
+    /// Sends the recovery message and clears the alarm.
+    Recovery,
+    /// Sends nothing.
+    Unchanged,
+}
+
+/// Decides what `process` does with the result of a comparison, the state machine of the alarm.
+///
+/// # Arguments
+///
+/// * `alarm_sent`: A `bool` that specifies whether an alarm was sent in the last `ALARM_HOURS` hours and not cleared.
+/// * `ok_sent`: A `bool` that specifies whether a recovery message was sent in the last `RECOVERY_HOURS` hours.
+/// * `in_sync`: A `bool` that specifies whether the IP addresses are considered in sync.
+///
+/// # Returns
+///
+/// * An `Action` that specifies what to send.
+pub fn next_action(alarm_sent: bool, ok_sent: bool, in_sync: bool) -> Action {
+    if alarm_sent && in_sync {
+        Action::Recovery
+    } else if !alarm_sent && !in_sync && !ok_sent {
+        Action::Alarm
+    } else {
+        Action::Unchanged
+    }
+}
+
+/// Retrieves the paths of the alarm and recovery lockfiles.
+///
+/// The paths are taken from the "LOCKFILE" and "OK_LOCKFILE" environment variables,
+/// defaulting to "telegram.lock" and "telegram_ok.lock" in the state directory (see `state::state_dir`).
+///
+/// # Returns
+///
+/// * A `(String, String)` that holds the alarm lockfile and the recovery lockfile paths.
+fn lockfile_paths() -> (String, String) {
+    let lockfile = state_path("LOCKFILE", "telegram.lock");
+    let ok_lockfile = state_path("OK_LOCKFILE", "telegram_ok.lock");
+    (lockfile, ok_lockfile)
+}
+
+/// Describes the alarm state, e.g. for a dump of the internal state.
+///
+/// # Returns
+///
+/// * A `Vec<String>` that holds a line for the alarm lockfile and one for the recovery lockfile,
+///   with the time the alarm or recovery was sent, if any.
+pub fn status() -> Vec<String> {
+    let (lockfile, ok_lockfile) = lockfile_paths();
+    [("Alarm", lockfile), ("Recovery", ok_lockfile)]
+        .iter()
+        .map(|(name, path)| {
+            let sent = read_state(path);
+            if sent.is_empty() {
+                format!("{}: not sent ({})", name, path)
+            } else {
+                format!("{}: sent {} ({})", name, sent, path)
+            }
+        })
+        .collect()
+}
+
+/// Clears the alarm state so the next mismatch is alerted again.
+///
+/// This function takes a flag whether to notify as an argument.
+/// It removes the alarm and recovery lockfiles using the `reset_lockfile` function; missing lockfiles are not an error.
+/// If `notify` is set, it publishes a "manually acknowledged" message using the `events::alert` function.
+///
+/// # Arguments
+///
+/// * `send_message`: A `bool` that specifies whether a message is sent about the reset.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds a message if the function succeeds.
+/// * If the function fails, it returns an `Err` with a message.
+pub fn force_reset(send_message: bool) -> Result<String, String> {
+    let (lockfile, ok_lockfile) = lockfile_paths();
+    for path in [lockfile, ok_lockfile] {
+        if !read_state(&path).is_empty() {
+            reset_lockfile(&path)?;
+        }
+    }
+    log::info!("Alarm state manually reset");
+    let message = Message::new(
+        "reset",
+        "Alarm manually acknowledged, alarm state has been reset",
+    );
+    if send_message && !events::alert(&message) {
+        return Err("Alarm state reset, but failed to send notification".to_string());
+    }
+    Ok("Alarm state reset".to_string())
+}
+
+/// Sends a reset message when the IP addresses of the router and the DNS server are the same again.
+///
+/// This function takes the lockfile path, the monitored hostname and the IP address as arguments.
+/// It publishes the message to every configured channel and hook using the `events::alert` function.
+/// If the function fails, it logs a warning and returns an `Err` with a message.
+///
+/// If the function succeeds, it logs an info message, resets the lockfile using the `reset_lockfile` function, and returns an `Ok` with a message.
+///
+/// # Arguments
+///
+/// * `lockfile`: A `&str` that specifies the lockfile path.
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `ip`: A `&str` that specifies the IP address of the router and the DNS server.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds a message if the function succeeds.
+/// * If the function fails, it returns an `Err` with a message.
+fn reset_alarm(lockfile: &str, hostname: &str, ip: &str) -> Result<String, String> {
+    let mut message = Message::new("recovery", "IP addresses are the same again")
+        .with_field("hostname", hostname)
+        .with_field("router_ip", ip)
+        .with_field("dns_ip", ip);
+    if let Some(duration) = alarm_duration(lockfile) {
+        message = message.with_field("duration", &duration);
+    }
+    if events::alert(&message) {
+        log::info!("Alarm has been reset");
+        reset_lockfile(lockfile)?;
+        Ok("Alarm has been reset".to_string())
+    } else {
+        log::warn!("Failed to reset alarm");
+        Err("Failed to reset alarm".to_string())
+    }
+}
+
+/// Formats how long ago the alarm was raised, e.g. "2h 5m".
+///
+/// # Arguments
+///
+/// * `lockfile`: A `&str` that specifies the lockfile path.
+///
+/// # Returns
+///
+/// * An `Option<String>` that holds the duration, or `None` if the lockfile holds no valid timestamp.
+fn alarm_duration(lockfile: &str) -> Option<String> {
+    let raised = DateTime::parse_from_rfc2822(&read_state(lockfile)).ok()?;
+    let minutes = Local::now()
+        .signed_duration_since(raised)
+        .num_minutes()
+        .max(0);
+    Some(format!("{}h {}m", minutes / 60, minutes % 60))
+}
+
+/// Creates a timestamp and writes it to a lockfile.
+///
+/// This function takes a lockfile path as an argument.
+/// It creates a timestamp using the `DateTime::to_rfc2822` method and the current local time,
+/// and replaces the contents of the lockfile with it using the `write_state` function.
+///
+/// If the lockfile cannot be written, e.g. on a read-only filesystem, the timestamp is kept in memory,
+/// so the alarm is not sent again until the process restarts.
+///
+/// # Arguments
+///
+/// * `lockfile`: A `&str` that specifies the lockfile path.
+fn create_timestamp(lockfile: &str) {
+    let timestamp = DateTime::to_rfc2822(&Local::now());
+    if write_state(lockfile, &timestamp) {
+        log::info!("Timestamp written to file");
+    }
+}
+
+/// Removes a lockfile.
+///
+/// This function takes a lockfile path as an argument.
+/// It attempts to remove the file at the lockfile path, or its state kept in memory, using the `remove_state` function.
+/// If the function fails, it returns an `Err` with a message.
+///
+/// If the function succeeds, it logs an info message and returns an `Ok` with a message.
+///
+/// # Arguments
+///
+/// * `lockfile`: A `&str` that specifies the lockfile path.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds a message if the function succeeds.
+/// * If the function fails, it returns an `Err` with a message.
+fn reset_lockfile(lockfile: &str) -> Result<String, String> {
+    match remove_state(lockfile) {
+        Ok(_) => Ok("Lockfile reset".to_string()),
+        Err(e) => Err(format!("Failed to reset lockfile: {}", e)),
+    }
+}
+
+/// Reads a timestamp from a lockfile and checks if it's less than 24 hours old.
+///
+/// This function takes a lockfile path as an argument.
+/// It first reads the lockfile using the `read_state` function, which also returns a timestamp kept in memory.
+/// If the lockfile is missing or empty, it returns `false`.
+///
+/// It then attempts to parse the contents of the file into a `DateTime` using the `DateTime::parse_from_rfc2822` method.
+/// If the method fails, it logs a warning and returns `false`.
+///
+/// It then gets the current local time and checks if the duration since the timestamp is less than 24 hours.
+/// If it is, it logs an info message and returns `true`.
+/// If it's not, it logs an info message and returns `false`.
+///
+/// # Arguments
+///
+/// * `lockfile`: A `&str` that specifies the lockfile path.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether the timestamp is less than 24 hours old.
+pub fn read_timestamp_from_file(lockfile: &str, hours: i64) -> bool {
+    let contents = read_state(lockfile);
+    if !contents.is_empty() {
+        log::info!("Timestamp: {:?}", contents);
+
+        if let Ok(timestamp) = DateTime::parse_from_rfc2822(&contents) {
+            let current = Local::now();
+            if current.signed_duration_since(timestamp) < ChronoDuration::try_hours(hours).unwrap()
+            {
+                log::info!("Less than 24 hours since last alarm, not sending alarm!");
+                true
+            } else {
+                log::info!("More than 24 hours since last alarm, sending alarm!");
+                false
+            }
+        } else {
+            log::info!("Failed to parse timestamp, creating new timestamp file");
+            false
+        }
+    } else {
+        log::debug!("No lockfile found, alarm not previously sent");
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::offset::TimeZone;
+    use std::fs::File;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_create_timestamp() {
+        // Create a temporary file
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let file_path = temp_file.path().to_str().unwrap().to_string();
+
+        // Call the function with the temporary file
+        create_timestamp(&file_path);
+
+        // Open the file and read its contents
+        let mut file = File::open(&file_path).unwrap();
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+
+        // Check if the contents can be parsed as a timestamp
+        let parsed = DateTime::parse_from_rfc2822(&contents);
+        assert!(parsed.is_ok());
+
+        // Check if the timestamp is recent (within the last minute)
+        let timestamp = parsed.unwrap();
+        let current = Local::now();
+        assert!(
+            current.signed_duration_since(timestamp) < chrono::Duration::try_minutes(1).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_read_timestamp_from_file() {
+        // Create a temporary file
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let file_path = temp_file.path().to_str().unwrap().to_string();
+
+        // Write a known timestamp to the file (older than 24 hours)
+        let mut file = File::create(&file_path).unwrap();
+        let timestamp_old = chrono::Local
+            .with_ymd_and_hms(2022, 1, 1, 0, 0, 0)
+            .unwrap()
+            .to_rfc2822();
+        writeln!(file, "{}", timestamp_old).unwrap();
+
+        // Call the function with the temporary file
+        let result_old = read_timestamp_from_file(&file_path, 24);
+
+        // Assert that the function returns false (because the timestamp is more than 24 hours ago)
+        assert!(!result_old);
+
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let file_path = temp_file.path().to_str().unwrap().to_string();
+
+        // Write a known timestamp to the file (older than 24 hours)
+        let mut file = File::create(&file_path).unwrap();
+
+        let timestamp = DateTime::to_rfc2822(&Local::now());
+        file.write_all(timestamp.as_bytes()).unwrap();
+
+        // Call the function with the temporary file again
+        let result_new = read_timestamp_from_file(&file_path, 24);
+
+        // Assert that the function returns true (because the timestamp is within 24 hours)
+        assert!(result_new);
+    }
+}
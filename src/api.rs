@@ -1,39 +1,294 @@
-use crate::vars::get_var_from_env;
-use reqwest;
+use crate::chaos::{self, Fault};
+use crate::dependencies::DEPENDENCIES;
+use crate::dns;
+use crate::http;
+use crate::notify::Message;
+use crate::replay;
+use crate::vars::{get_var_from_env, parse_duration, split_list};
 use serde_json::Value;
+use std::env;
+use std::net::Ipv6Addr;
 use std::time::Duration;
 
-/// Makes an API request and parses the response.
+/// Makes an API request and retrieves the WAN IP addresses of the configured interfaces.
 ///
-/// This function retrieves the values of the "API_KEY", "API_SECRET", "INTERFACE", and "URL" environment variables.
-/// It then builds a `reqwest::Client` and makes a request to the endpoint specified by the "URL" environment variable.
-/// The response is then parsed into a JSON object.
-/// The function then retrieves the "ipv4" field of the object specified by the "INTERFACE" environment variable from the JSON object.
+/// This function retrieves the value of the "INTERFACE" environment variable, which holds a comma-separated list of interfaces.
+/// It then fetches the interface overview from the router API using the `fetch_interfaces` function.
+/// The function then retrieves the address of each configured interface using the `parse_wan_ips` function.
 ///
 /// # Returns
 ///
-/// * A `String` that holds the value of the "ipv4" field of the object specified by the "INTERFACE" environment variable.
-/// * If any step fails, it returns an empty `String`.
-pub fn get_api() -> String {
-    let username: String = get_var_from_env("API_KEY").unwrap();
-    let password: String = get_var_from_env("API_SECRET").unwrap();
-    let interface: String = get_var_from_env("INTERFACE").unwrap();
-    let url: String = get_var_from_env("URL").unwrap();
-
-    let client = match build_client() {
+/// * A `Vec<(String, String)>` that holds the interface name and address of each interface, in configured order,
+///   the IPv4 addresses before the IPv6 addresses.
+/// * If "INTERFACE" is not set or the request fails, it returns an empty `Vec`.
+pub fn get_wan_ips() -> Vec<(String, String)> {
+    let interfaces: String = match get_var_from_env("INTERFACE") {
         Ok(value) => value,
-        Err(value) => return value,
+        Err(_) => return Vec::new(),
     };
-    let response = match call_endpoint(client, url, username, password) {
+
+    let response_text = match fetch_interfaces() {
         Ok(value) => value,
-        Err(value) => return value,
+        Err(_) => return Vec::new(),
     };
-    let response_text = match get_response(response) {
+    replay::capture_api_response(&response_text);
+
+    parse_wan_ips(&response_text, &split_list(&interfaces))
+}
+
+/// Extracts the WAN IP addresses of the configured interfaces from the interface overview of the router API.
+///
+/// This function retrieves the "ipv4" field of each interface using the `parse_json` function.
+/// If the hostname is compared over IPv6 as well (see `dns::address_family`), it also retrieves the global IPv6 address
+/// of each interface using the `parse_ipv6_address` function.
+/// Interfaces without an address (e.g. a failover uplink that is down) are left out.
+///
+/// # Arguments
+///
+/// * `response_text`: A `&str` that holds the interface overview.
+/// * `interfaces`: A `&[String]` that holds the configured interfaces, primary first.
+///
+/// # Returns
+///
+/// * A `Vec<(String, String)>` that holds the interface name and address of each interface, in configured order,
+///   the IPv4 addresses before the IPv6 addresses.
+pub fn parse_wan_ips(response_text: &str, interfaces: &[String]) -> Vec<(String, String)> {
+    let family = dns::address_family();
+    let mut wan_ips = Vec::new();
+    if family.has_ipv4() {
+        wan_ips.extend(interfaces.iter().map(|interface| {
+            let wan_ip = parse_json(response_text.to_string(), interface);
+            (interface.clone(), wan_ip)
+        }));
+    }
+    if family.has_ipv6() {
+        wan_ips.extend(interfaces.iter().map(|interface| {
+            let wan_ip = parse_ipv6_address(response_text, interface);
+            (interface.clone(), wan_ip)
+        }));
+    }
+    wan_ips.retain(|(_, wan_ip)| !wan_ip.is_empty());
+    wan_ips
+}
+
+/// Selects the WAN IP address to compare the DNS record with.
+///
+/// This function takes the WAN IP addresses of the configured interfaces, the IP address from DNS and a policy as arguments.
+/// With the "any" policy, the interface whose address matches the DNS record is selected, so the record may point at any uplink.
+/// With the "primary" policy (the default), the first interface that has an address is selected,
+/// which is the primary uplink or the first working failover uplink.
+/// If no interface matches under the "any" policy, it falls back to the "primary" policy.
+///
+/// # Arguments
+///
+/// * `wan_ips`: A `&[(String, String)]` that holds the interface name and IPv4 address of each interface.
+/// * `dns_ip`: A `&str` that specifies the IP address from DNS.
+/// * `policy`: A `&str` that specifies the policy, either "any" or "primary".
+///
+/// # Returns
+///
+/// * A `(String, String)` that holds the selected interface name and IPv4 address.
+/// * If there are no WAN IP addresses, it returns two empty `String`s.
+pub fn select_wan_ip(wan_ips: &[(String, String)], dns_ip: &str, policy: &str) -> (String, String) {
+    if policy.eq_ignore_ascii_case("any") {
+        if let Some(selected) = wan_ips.iter().find(|(_, wan_ip)| wan_ip == dns_ip) {
+            return selected.clone();
+        }
+    }
+    wan_ips.first().cloned().unwrap_or_default()
+}
+
+/// Retrieves the IPv6 prefix of an interface from the router API.
+///
+/// This function takes the name of an interface and an optional prefix length as arguments.
+/// It fetches the interface overview from the router API using the `fetch_interfaces` function
+/// and extracts the prefix using the `parse_ipv6_prefix` function.
+///
+/// # Arguments
+///
+/// * `interface`: A `&str` that specifies the name of the interface to get the prefix from.
+/// * `prefix_len`: An `Option<u8>` that overrides the prefix length reported by the router.
+///
+/// # Returns
+///
+/// * A `String` that holds the prefix in CIDR notation, e.g. "2001:db8:1200::/56".
+/// * If any step fails, it returns an empty `String`.
+pub fn get_ipv6_prefix(interface: &str, prefix_len: Option<u8>) -> String {
+    let response_text = match fetch_interfaces() {
         Ok(value) => value,
         Err(value) => return value,
     };
 
-    parse_json(response_text, &interface)
+    parse_ipv6_prefix(response_text, interface, prefix_len)
+}
+
+/// Fetches the interface overview from the router API.
+///
+/// This function retrieves the values of the "API_KEY", "API_SECRET", and "URL" environment variables.
+/// It then builds a `reqwest::Client` and makes a request to the endpoint specified by the "URL" environment variable.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds the body of the response if the request succeeds.
+/// * If any step fails, including a missing variable, it returns an `Err` with an empty `String`.
+fn fetch_interfaces() -> Result<String, String> {
+    let username: String = get_var_from_env("API_KEY").map_err(|_| String::new())?;
+    let password: String = get_var_from_env("API_SECRET").map_err(|_| String::new())?;
+    let url: String = get_var_from_env("URL").map_err(|_| String::new())?;
+
+    if chaos::inject(Fault::ApiError) {
+        log::warn!("Router API returned 500 Internal Server Error");
+        return Err(String::new());
+    }
+    let client = build_client()?;
+    let response = call_endpoint(client, url, username, password)?;
+    get_response(response)
+}
+
+/// Adds the recent reboot of the router to an IP change alert.
+///
+/// This function is enabled by setting the "ROUTER_UPTIME_URL" environment variable to the system information endpoint
+/// of the router, e.g. "https://router/api/diagnostics/system/system_time" on OPNsense or
+/// "https://router/api/v2/status/system" on pfSense with the REST API package. It is called with the router API credentials.
+///
+/// If the router rebooted within "ROUTER_REBOOT_WINDOW" (defaulting to 1 hour), a line such as
+/// "Router rebooted 3 minutes ago" is appended to the text and the uptime in seconds is added as the "router_uptime" field,
+/// since a reboot is the usual reason for a new WAN IP address. If the uptime cannot be fetched, the message is returned unchanged.
+///
+/// # Arguments
+///
+/// * `message`: A `Message` that holds the alert.
+///
+/// # Returns
+///
+/// * A `Message` that holds the alert with the reboot context, if any.
+pub fn with_router_context(mut message: Message) -> Message {
+    let url = match env::var("ROUTER_UPTIME_URL") {
+        Ok(url) if !url.is_empty() => url,
+        _ => return message,
+    };
+    let window = env::var("ROUTER_REBOOT_WINDOW")
+        .ok()
+        .and_then(|value| parse_duration(&value))
+        .unwrap_or(Duration::from_secs(3600));
+    let uptime = fetch_uptime(&url);
+    DEPENDENCIES.record("router uptime", &uptime);
+    match uptime {
+        Ok(uptime) if uptime < window => {
+            message.text = format!("{}\n{}", message.text, reboot_line(uptime));
+            message.with_field("router_uptime", &uptime.as_secs().to_string())
+        }
+        Ok(_) => message,
+        Err(e) => {
+            log::warn!("{}", e);
+            message
+        }
+    }
+}
+
+/// Fetches the uptime of the router from its system information endpoint.
+///
+/// # Arguments
+///
+/// * `url`: A `&str` that specifies the URL of the endpoint.
+///
+/// # Returns
+///
+/// * A `Result<Duration, String>` that holds the uptime, or an `Err` with a message if the request fails
+///   or the answer holds no uptime.
+fn fetch_uptime(url: &str) -> Result<Duration, String> {
+    let username: String = get_var_from_env("API_KEY").unwrap_or_default();
+    let password: String = get_var_from_env("API_SECRET").unwrap_or_default();
+    let failed = |_| format!("Failed to fetch the router uptime from {}", url);
+    let client = build_client().map_err(failed)?;
+    let response = call_endpoint(client, url.to_string(), username, password).map_err(failed)?;
+    let response_text = get_response(response).map_err(failed)?;
+    parse_uptime(&response_text)
+        .ok_or_else(|| format!("No router uptime found in the answer of {}", url))
+}
+
+/// Parses the uptime from the answer of a system information endpoint.
+///
+/// The "uptime" field is looked up at the top level (OPNsense) or in "data" (pfSense REST API).
+/// It can be a number of seconds, or a text such as "3 days, 04:12:33" or "1 Day 02 Hours 03 Minutes 04 Seconds".
+///
+/// # Arguments
+///
+/// * `response_text`: A `&str` that holds the JSON answer.
+///
+/// # Returns
+///
+/// * An `Option<Duration>` that holds the uptime, or `None` if the answer holds no valid uptime.
+pub fn parse_uptime(response_text: &str) -> Option<Duration> {
+    let json: Value = serde_json::from_str(response_text).ok()?;
+    let uptime = json
+        .get("uptime")
+        .or_else(|| json.get("data").and_then(|data| data.get("uptime")))?;
+    if let Some(seconds) = uptime.as_f64() {
+        return (seconds >= 0.0).then(|| Duration::from_secs(seconds as u64));
+    }
+    let mut seconds = 0;
+    let mut found = false;
+    let mut number: Option<u64> = None;
+    let tokens = uptime
+        .as_str()?
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|token| !token.is_empty());
+    for token in tokens {
+        if token.contains(':') {
+            let parts = token
+                .split(':')
+                .map(|part| part.parse::<u64>().ok())
+                .collect::<Option<Vec<u64>>>()?;
+            seconds += match parts[..] {
+                [hours, minutes] => hours * 3600 + minutes * 60,
+                [hours, minutes, secs] => hours * 3600 + minutes * 60 + secs,
+                _ => return None,
+            };
+            found = true;
+        } else if let Ok(value) = token.parse::<u64>() {
+            number = Some(value);
+        } else if let Some(value) = number.take() {
+            let unit = token.to_lowercase();
+            let multiplier = if unit.starts_with("day") {
+                86400
+            } else if unit.starts_with("hour") {
+                3600
+            } else if unit.starts_with("min") {
+                60
+            } else if unit.starts_with("sec") {
+                1
+            } else {
+                return None;
+            };
+            seconds += value * multiplier;
+            found = true;
+        }
+    }
+    if let Some(value) = number {
+        seconds += value;
+        found = true;
+    }
+    found.then(|| Duration::from_secs(seconds))
+}
+
+/// Describes a recent reboot of the router.
+///
+/// # Arguments
+///
+/// * `uptime`: A `Duration` that specifies the uptime of the router.
+///
+/// # Returns
+///
+/// * A `String` such as "Router rebooted 3 minutes ago" or "Router rebooted 1h 5m ago".
+fn reboot_line(uptime: Duration) -> String {
+    let minutes = uptime.as_secs() / 60;
+    match minutes {
+        0 => "Router rebooted less than a minute ago".to_string(),
+        1 => "Router rebooted 1 minute ago".to_string(),
+        2..=59 => format!("Router rebooted {} minutes ago", minutes),
+        _ => format!("Router rebooted {}h {}m ago", minutes / 60, minutes % 60),
+    }
 }
 
 /// Parses a JSON string and extracts a specific value from it.
@@ -58,7 +313,7 @@ pub fn get_api() -> String {
 ///
 /// * A `String` that holds the value of the "ipv4" field of the object specified by the interface.
 /// * If any step fails, it returns an empty `String`.
-fn parse_json(response_text: String, interface: &str) -> String {
+pub fn parse_json(response_text: String, interface: &str) -> String {
     let json: Value = match serde_json::from_str(&response_text) {
         Ok(json) => json,
         Err(e) => {
@@ -81,8 +336,124 @@ fn parse_json(response_text: String, interface: &str) -> String {
             return String::new();
         }
     };
-    let value = value.get(0).unwrap().get("ipaddr").unwrap();
-    value.as_str().unwrap().to_string()
+    match value
+        .get(0)
+        .and_then(|value| value.get("ipaddr"))
+        .and_then(Value::as_str)
+    {
+        Some(value) => value.to_string(),
+        None => {
+            log::warn!("No IPv4 address found on \"{}\"", interface);
+            String::new()
+        }
+    }
+}
+
+/// Parses a JSON string and extracts the IPv6 prefix of an interface.
+///
+/// This function takes a JSON string, the name of an interface and an optional prefix length as arguments.
+/// It looks up the "ipv6" field of the object specified by the interface and takes the first global address,
+/// skipping link-local (fe80::/10) and unique local (fc00::/7) addresses.
+/// The address is then masked to the prefix length, which defaults to the "subnetbits" reported by the router
+/// and is capped at 128.
+///
+/// # Arguments
+///
+/// * `response_text`: A `String` that holds the JSON string to parse.
+/// * `interface`: A `&str` that specifies the name of the interface to get the prefix from.
+/// * `prefix_len`: An `Option<u8>` that overrides the prefix length reported by the router.
+///
+/// # Returns
+///
+/// * A `String` that holds the prefix in CIDR notation.
+/// * If any step fails, it returns an empty `String`.
+pub fn parse_ipv6_prefix(response_text: String, interface: &str, prefix_len: Option<u8>) -> String {
+    let json: Value = match serde_json::from_str(&response_text) {
+        Ok(json) => json,
+        Err(e) => {
+            log::warn!("Failed to parse JSON: {:?}", e);
+            return String::new();
+        }
+    };
+    let addresses = match json.get(interface).and_then(|value| value.get("ipv6")) {
+        Some(Value::Array(addresses)) => addresses,
+        _ => {
+            log::warn!("Failed to get \"ipv6\" of \"{}\" from JSON", interface);
+            return String::new();
+        }
+    };
+    let (ip, bits) = match global_ipv6(addresses) {
+        Some(global) => global,
+        None => {
+            log::warn!("No global IPv6 address found on \"{}\"", interface);
+            return String::new();
+        }
+    };
+    let bits = prefix_len.map(u64::from).unwrap_or(bits).min(128) as u32;
+    let mask = u128::MAX.checked_shl(128 - bits).unwrap_or(0);
+    let network = Ipv6Addr::from(u128::from(ip) & mask);
+    format!("{}/{}", network, bits)
+}
+
+/// Parses a JSON string and extracts the global IPv6 address of an interface.
+///
+/// This function looks up the "ipv6" field of the object specified by the interface and takes the first global address,
+/// like the `parse_ipv6_prefix` function.
+///
+/// # Arguments
+///
+/// * `response_text`: A `&str` that holds the JSON string to parse.
+/// * `interface`: A `&str` that specifies the name of the interface to get the address from.
+///
+/// # Returns
+///
+/// * A `String` that holds the address, e.g. "2001:db8:1200::1".
+/// * If any step fails, it returns an empty `String`.
+pub fn parse_ipv6_address(response_text: &str, interface: &str) -> String {
+    let json: Value = match serde_json::from_str(response_text) {
+        Ok(json) => json,
+        Err(e) => {
+            log::warn!("Failed to parse JSON: {:?}", e);
+            return String::new();
+        }
+    };
+    match json
+        .get(interface)
+        .and_then(|value| value.get("ipv6"))
+        .and_then(Value::as_array)
+        .and_then(|addresses| global_ipv6(addresses))
+    {
+        Some((ip, _)) => ip.to_string(),
+        None => {
+            log::warn!("No global IPv6 address found on \"{}\"", interface);
+            String::new()
+        }
+    }
+}
+
+/// Finds the first global address in the "ipv6" field of an interface,
+/// skipping link-local (fe80::/10) and unique local (fc00::/7) addresses.
+///
+/// # Arguments
+///
+/// * `addresses`: A `&[Value]` that holds the addresses reported by the router.
+///
+/// # Returns
+///
+/// * An `Option<(Ipv6Addr, u64)>` that holds the address and its "subnetbits" (defaulting to 64), or `None` if there is no global address.
+fn global_ipv6(addresses: &[Value]) -> Option<(Ipv6Addr, u64)> {
+    addresses.iter().find_map(|address| {
+        let ip: Ipv6Addr = address.get("ipaddr")?.as_str()?.parse().ok()?;
+        let first = ip.segments()[0];
+        if first & 0xffc0 == 0xfe80 || first & 0xfe00 == 0xfc00 {
+            return None;
+        }
+        let bits = address
+            .get("subnetbits")
+            .and_then(Value::as_u64)
+            .unwrap_or(64);
+        Some((ip, bits))
+    })
 }
 
 /// Extracts the body of an HTTP response as a string.
@@ -155,8 +526,11 @@ fn call_endpoint(
 
 /// Creates a new `reqwest::blocking::Client` instance with certain configurations.
 ///
-/// This function first creates a `reqwest::blocking::ClientBuilder` instance using the `reqwest::blocking::Client::builder` method.
-/// It then configures the builder to accept invalid certificates using the `reqwest::blocking::ClientBuilder::danger_accept_invalid_certs` method.
+/// This function first creates a `reqwest::blocking::ClientBuilder` instance using the `http::client_builder` function,
+/// so the shared outbound settings such as the additional root certificates apply.
+/// Unless the "API_VERIFY_TLS" environment variable is "true", it then configures the builder to accept invalid certificates
+/// using the `reqwest::blocking::ClientBuilder::danger_accept_invalid_certs` method, since routers usually have self-signed certificates.
+/// With verification enabled, the router certificate can be trusted by adding its CA to "EXTRA_CA_DIR".
 /// It builds the `reqwest::blocking::Client` instance using the `reqwest::blocking::ClientBuilder::build` method.
 /// If the method fails, it logs a warning and returns an `Err` with an empty `String`.
 ///
@@ -165,8 +539,9 @@ fn call_endpoint(
 /// * A `Result<reqwest::blocking::Client, String>` that holds the `reqwest::blocking::Client` instance if the method succeeds.
 /// * If the method fails, it returns an `Err` with an empty `String`.
 fn build_client() -> Result<reqwest::blocking::Client, String> {
-    let mut client_builder = reqwest::blocking::Client::builder();
-    client_builder = client_builder.danger_accept_invalid_certs(true);
+    let mut client_builder = http::client_builder();
+    let verify = std::env::var("API_VERIFY_TLS").unwrap_or("false".to_string()) == "true";
+    client_builder = client_builder.danger_accept_invalid_certs(!verify);
     let client = client_builder.build();
     let client = match client {
         Ok(client) => client,
@@ -182,6 +557,7 @@ fn build_client() -> Result<reqwest::blocking::Client, String> {
 mod tests {
     use super::*;
     use httpmock::MockServer;
+    use proptest::prelude::*;
 
     #[test]
     fn test_get_api() {
@@ -198,15 +574,93 @@ mod tests {
         std::env::set_var("API_SECRET", "password");
         std::env::set_var("URL", server.url("/test"));
         // Call the function with the mock server's URL
-        let result = get_api();
+        let result = get_wan_ips();
 
         // Assert that the function returns the expected output
-        assert_eq!(result, "127.0.0.1");
+        assert_eq!(result, vec![("igb3".to_string(), "127.0.0.1".to_string())]);
 
         // Assert that the mock was called
         mock.assert();
     }
 
+    #[test]
+    fn test_parse_uptime() {
+        // OPNsense
+        assert_eq!(
+            parse_uptime("{\"uptime\": \"3 days, 04:12:33\"}"),
+            Some(Duration::from_secs(3 * 86400 + 4 * 3600 + 12 * 60 + 33))
+        );
+        assert_eq!(
+            parse_uptime("{\"uptime\": \"00:03\"}"),
+            Some(Duration::from_secs(180))
+        );
+        // pfSense REST API
+        assert_eq!(
+            parse_uptime("{\"data\": {\"uptime\": \"1 Day 02 Hours 03 Minutes 04 Seconds\"}}"),
+            Some(Duration::from_secs(86400 + 2 * 3600 + 3 * 60 + 4))
+        );
+        assert_eq!(
+            parse_uptime("{\"uptime\": 420}"),
+            Some(Duration::from_secs(420))
+        );
+        assert_eq!(parse_uptime("{\"uptime\": \"3 fortnights\"}"), None);
+        assert_eq!(parse_uptime("{\"datetime\": \"now\"}"), None);
+
+        assert_eq!(
+            reboot_line(Duration::from_secs(200)),
+            "Router rebooted 3 minutes ago"
+        );
+        assert_eq!(
+            reboot_line(Duration::from_secs(3900)),
+            "Router rebooted 1h 5m ago"
+        );
+    }
+
+    #[test]
+    fn test_with_router_context() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method("GET").path("/system_time");
+            then.status(200).body("{\"uptime\": \"00:03:20\"}");
+        });
+        std::env::set_var("ROUTER_UPTIME_URL", server.url("/system_time"));
+        let message = with_router_context(Message::new("wan_change", "WAN IP address changed"));
+        std::env::remove_var("ROUTER_UPTIME_URL");
+
+        assert_eq!(
+            message.text,
+            "WAN IP address changed\nRouter rebooted 3 minutes ago"
+        );
+        assert_eq!(
+            message.fields,
+            vec![("router_uptime".to_string(), "200".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_select_wan_ip() {
+        let wan_ips = vec![
+            ("igb3".to_string(), "1.1.1.1".to_string()),
+            ("igb4".to_string(), "2.2.2.2".to_string()),
+        ];
+
+        // Assert that the primary policy always selects the first uplink
+        let result = select_wan_ip(&wan_ips, "2.2.2.2", "primary");
+        assert_eq!(result, ("igb3".to_string(), "1.1.1.1".to_string()));
+
+        // Assert that the any policy selects the uplink matching DNS
+        let result = select_wan_ip(&wan_ips, "2.2.2.2", "any");
+        assert_eq!(result, ("igb4".to_string(), "2.2.2.2".to_string()));
+
+        // Assert that the any policy falls back to the first uplink
+        let result = select_wan_ip(&wan_ips, "3.3.3.3", "any");
+        assert_eq!(result, ("igb3".to_string(), "1.1.1.1".to_string()));
+
+        // Assert that no uplinks select nothing
+        let result = select_wan_ip(&[], "3.3.3.3", "any");
+        assert_eq!(result, (String::new(), String::new()));
+    }
+
     #[test]
     fn test_parse_json() {
         let interface = "igb3";
@@ -223,7 +677,86 @@ mod tests {
 
         // Assert that the function returns an empty string
         assert_eq!(result, "");
+
+        // Call the function with an interface that has no IPv4 address
+        let result = parse_json(String::from("{\"igb3\": {\"ipv4\": []}}"), interface);
+
+        // Assert that the function returns an empty string
+        assert_eq!(result, "");
+    }
+    #[test]
+    fn test_parse_ipv6_prefix() {
+        let response = String::from(
+            "{\"igb0\": {\"ipv6\": [{\"ipaddr\": \"fe80::1\", \"subnetbits\": 64}, {\"ipaddr\": \"2001:db8:12:3456::1\", \"subnetbits\": 64}]}}",
+        );
+
+        // Assert that the link-local address is skipped and the router's prefix length is used
+        let result = parse_ipv6_prefix(response.clone(), "igb0", None);
+        assert_eq!(result, "2001:db8:12:3456::/64");
+        assert_eq!(parse_ipv6_address(&response, "igb0"), "2001:db8:12:3456::1");
+
+        // Assert that the prefix length can be overridden with the delegated length
+        let result = parse_ipv6_prefix(response, "igb0", Some(56));
+        assert_eq!(result, "2001:db8:12:3400::/56");
+
+        // Assert that an interface without IPv6 returns an empty string
+        let result = parse_ipv6_prefix(String::from("{\"igb0\": {\"ipv4\": []}}"), "igb0", None);
+        assert_eq!(result, "");
+
+        // Assert that an overlong prefix length is capped
+        let result = parse_ipv6_prefix(
+            String::from("{\"igb0\": {\"ipv6\": [{\"ipaddr\": \"2001:db8::1\"}]}}"),
+            "igb0",
+            Some(200),
+        );
+        assert_eq!(result, "2001:db8::1/128");
+    }
+
+    /// Generates JSON values of any type, nested up to a few levels.
+    fn json_value() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            any::<u64>().prop_map(Value::from),
+            any::<f64>().prop_map(Value::from),
+            ".{0,40}".prop_map(Value::from),
+            "(2001:db8|fe80|fd00)::[0-9a-f]{1,4}".prop_map(Value::from),
+            "[0-9]{1,3}\\.[0-9]{1,3}\\.[0-9]{1,3}\\.[0-9]{1,3}".prop_map(Value::from),
+        ];
+        leaf.prop_recursive(3, 32, 4, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..4).prop_map(Value::from),
+                prop::collection::btree_map("ipv4|ipv6|ipaddr|subnetbits|igb0|.{0,8}", inner, 0..4)
+                    .prop_map(|map| Value::Object(map.into_iter().collect())),
+            ]
+        })
     }
+
+    proptest! {
+        /// Asserts that the router API parsers do not panic on input that is not JSON.
+        #[test]
+        fn test_parse_arbitrary_text(response in ".*", prefix_len in any::<Option<u8>>()) {
+            parse_json(response.clone(), "igb0");
+            parse_ipv6_prefix(response, "igb0", prefix_len);
+        }
+
+        /// Asserts that the router API parsers do not panic on JSON of an unexpected shape,
+        /// e.g. from other firmware versions, and only return valid prefixes.
+        #[test]
+        fn test_parse_malformed_json(value in json_value(), prefix_len in any::<Option<u8>>()) {
+            let response = serde_json::json!({"igb0": value}).to_string();
+            parse_json(response.clone(), "igb0");
+            let prefix = parse_ipv6_prefix(response, "igb0", prefix_len);
+            if let Some((address, bits)) = prefix.split_once('/') {
+                prop_assert!(address.parse::<Ipv6Addr>().is_ok());
+                prop_assert!(bits.parse::<u8>().is_ok_and(|bits| bits <= 128));
+            } else {
+                prop_assert_eq!(prefix, "");
+            }
+        }
+    }
+
     #[test]
     fn test_get_response() {
         let server = MockServer::start();
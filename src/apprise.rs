@@ -0,0 +1,185 @@
+use crate::http;
+use crate::notify::{Message, Notifier};
+use crate::vars::split_list;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::time::Duration;
+
+/// Delivers notifications through an Apprise API server, which fans them out to the services it is configured for.
+pub struct AppriseNotifier {
+    url: String,
+    tag: Option<String>,
+    routes: BTreeMap<String, String>,
+}
+
+impl AppriseNotifier {
+    /// Creates an Apprise notifier from the environment.
+    ///
+    /// The notifier is enabled by setting "APPRISE_URL" to the notify endpoint of an Apprise API server,
+    /// usually with the key of a stored configuration, e.g. "http://apprise:8000/notify/dnschecker".
+    /// Messages carry the tag "APPRISE_TAG", so only the services with that tag are notified;
+    /// "APPRISE_ROUTES" overrides the tag per event as a list of "event=tag" pairs, e.g. "mismatch=pager,heartbeat=log".
+    /// Without a tag, Apprise notifies every service of the configuration.
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<AppriseNotifier>` that holds the notifier, or `None` if "APPRISE_URL" is not set.
+    pub fn from_env() -> Option<AppriseNotifier> {
+        let url = env::var("APPRISE_URL").ok().filter(|url| !url.is_empty())?;
+        let routes = env::var("APPRISE_ROUTES").unwrap_or_default();
+        Some(AppriseNotifier {
+            url,
+            tag: env::var("APPRISE_TAG").ok().filter(|tag| !tag.is_empty()),
+            routes: parse_routes(&routes),
+        })
+    }
+
+    /// Returns the tag of a message: the route of its event, or the default tag.
+    fn tag(&self, message: &Message) -> Option<&str> {
+        self.routes
+            .get(&message.event)
+            .or(self.tag.as_ref())
+            .map(String::as_str)
+    }
+}
+
+impl Notifier for AppriseNotifier {
+    fn name(&self) -> &str {
+        "apprise"
+    }
+
+    /// Posts the message to the Apprise API server, see `body`.
+    fn send(&self, message: &Message) -> bool {
+        let client = match http::client() {
+            Ok(client) => client,
+            Err(e) => {
+                log::warn!("{}", e);
+                return false;
+            }
+        };
+        let response = client
+            .post(&self.url)
+            .json(&body(message, self.tag(message)))
+            .timeout(Duration::from_secs(10))
+            .send();
+        match response {
+            Ok(response) if response.status().is_success() => true,
+            Ok(response) => {
+                log::warn!("Apprise returned {}", response.status());
+                false
+            }
+            Err(e) => {
+                log::warn!("Failed to make HTTP request: {:?}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Builds the body of an Apprise notification.
+///
+/// The title names the event, and the notification type lets Apprise pick the icon or color of the services that support it:
+/// "failure" for alerts, "success" for recoveries and "info" for routine messages.
+///
+/// # Arguments
+///
+/// * `message`: A `&Message` that holds the notification.
+/// * `tag`: An `Option<&str>` that specifies the tag of the services to notify.
+///
+/// # Returns
+///
+/// * A `Value` that holds the JSON body.
+fn body(message: &Message, tag: Option<&str>) -> Value {
+    let notification_type = match message.event.as_str() {
+        "recovery" | "reset" => "success",
+        "heartbeat" | "suppressed" => "info",
+        _ => "failure",
+    };
+    let mut body = json!({
+        "title": format!("dnschecker: {}", message.event),
+        "body": message.text,
+        "type": notification_type,
+        "format": "text",
+    });
+    if let Some(tag) = tag {
+        body["tag"] = Value::from(tag);
+    }
+    body
+}
+
+/// Parses the tag routes of the events.
+///
+/// # Arguments
+///
+/// * `value`: A `&str` that holds a list of "event=tag" pairs; invalid entries are logged and skipped.
+///
+/// # Returns
+///
+/// * A `BTreeMap<String, String>` that holds the tag of each event.
+fn parse_routes(value: &str) -> BTreeMap<String, String> {
+    split_list(value)
+        .into_iter()
+        .filter_map(|route| match route.split_once('=') {
+            Some((event, tag)) if !event.trim().is_empty() && !tag.trim().is_empty() => {
+                Some((event.trim().to_lowercase(), tag.trim().to_string()))
+            }
+            _ => {
+                log::warn!("Ignoring invalid Apprise route: {}", route);
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+
+    #[test]
+    fn test_body() {
+        let notifier = AppriseNotifier {
+            url: String::new(),
+            tag: Some("homelab".to_string()),
+            routes: parse_routes("mismatch=pager, invalid, heartbeat=log"),
+        };
+        let message = Message::new("mismatch", "IP address mismatch!");
+        assert_eq!(
+            body(&message, notifier.tag(&message)),
+            json!({
+                "title": "dnschecker: mismatch",
+                "body": "IP address mismatch!",
+                "type": "failure",
+                "format": "text",
+                "tag": "pager",
+            })
+        );
+
+        // Assert that unrouted events carry the default tag
+        let message = Message::new("recovery", "IP addresses are the same again");
+        let result = body(&message, notifier.tag(&message));
+        assert_eq!(result["type"], "success");
+        assert_eq!(result["tag"], "homelab");
+        assert_eq!(body(&message, None).get("tag"), None);
+    }
+
+    #[test]
+    fn test_send() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method("POST")
+                .path("/notify/dnschecker")
+                .json_body_partial(r#"{"body": "Heartbeat for home.example.com", "type": "info"}"#);
+            then.status(200).body("Notification(s) sent.");
+        });
+        let notifier = AppriseNotifier {
+            url: server.url("/notify/dnschecker"),
+            tag: None,
+            routes: BTreeMap::new(),
+        };
+
+        assert!(notifier.send(&Message::new("heartbeat", "Heartbeat for home.example.com")));
+        mock.assert();
+    }
+}
@@ -0,0 +1,273 @@
+use crate::dns;
+use crate::events;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::tsig::TsigKey;
+use crate::vars::{display_hostname, split_list};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+
+/// The settings of the zone transfer check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZoneTransfer {
+    /// The name server the zone is transferred from, usually the primary.
+    pub server: SocketAddr,
+    /// The zone, in lower case and without the trailing dot.
+    pub zone: String,
+    /// The TSIG key that authenticates the transfer, if any.
+    pub key: Option<TsigKey>,
+    /// The names besides the monitored hostname that are expected to point to the WAN IP address.
+    pub expected: Vec<String>,
+}
+
+impl ZoneTransfer {
+    /// Creates the settings from the environment.
+    ///
+    /// This function retrieves the name server from the "AXFR_SERVER" environment variable (e.g. "192.0.2.53" or "192.0.2.53:5353").
+    /// If it is not set, the zone transfer check is disabled.
+    /// The zone is taken from "AXFR_ZONE", defaulting to "DNS_ZONE", the TSIG key from "AXFR_TSIG_KEY" (see `TsigKey::from_env`),
+    /// and the names expected to point to the WAN IP address from the comma-separated list in "AXFR_EXPECTED".
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<Option<ZoneTransfer>, String>` that holds the settings, or `None` if "AXFR_SERVER" is not set.
+    /// * If the server, the zone or the key is invalid or missing, it returns an `Err` with a message.
+    pub fn from_env() -> Result<Option<ZoneTransfer>, String> {
+        let server = match env::var("AXFR_SERVER") {
+            Ok(server) if !server.is_empty() => server,
+            _ => return Ok(None),
+        };
+        let server = dns::server_addr(&server)
+            .ok_or(format!("Invalid name server in AXFR_SERVER: {}", server))?;
+        let zone = env::var("AXFR_ZONE")
+            .or_else(|_| env::var("DNS_ZONE"))
+            .ok()
+            .filter(|zone| !zone.is_empty())
+            .ok_or("AXFR_SERVER is set, but neither AXFR_ZONE nor DNS_ZONE is".to_string())?;
+        Ok(Some(ZoneTransfer {
+            server,
+            zone: normalize(&zone),
+            key: TsigKey::from_env()?,
+            expected: split_list(&env::var("AXFR_EXPECTED").unwrap_or_default())
+                .iter()
+                .map(|name| normalize(name))
+                .collect(),
+        }))
+    }
+}
+
+/// Verifies the record of the hostname at the source of truth, the zone on the primary name server.
+///
+/// This function transfers the zone using the `dns::transfer_zone` function and checks it using the `zone_problems` function:
+/// the hostname must point to the WAN IP address, and no other name may point to it unless it is expected.
+/// The problems are compared with the state stored in the zone transfer file ("AXFR_FILE", defaulting to "axfr_state" in the state directory),
+/// and an "axfr" message is sent when they change, including when they are resolved.
+/// If the WAN IP address is unknown or the zone cannot be transferred, it logs a warning and keeps the state.
+///
+/// # Arguments
+///
+/// * `transfer`: A `&ZoneTransfer` that holds the settings.
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `wan_ip`: A `&str` that specifies the WAN IP address.
+pub fn check_zone(transfer: &ZoneTransfer, hostname: &str, wan_ip: &str) {
+    let wan_ip = match wan_ip.parse::<IpAddr>() {
+        Ok(wan_ip) => wan_ip,
+        Err(_) => {
+            log::debug!("No WAN IP address, skipping zone transfer check");
+            return;
+        }
+    };
+    let axfr_file = state_path("AXFR_FILE", "axfr_state");
+
+    let records = match dns::transfer_zone(transfer.server, &transfer.zone, transfer.key.as_ref()) {
+        Ok(records) => records,
+        Err(e) => {
+            log::warn!("{}", e);
+            return;
+        }
+    };
+    log::debug!(
+        "Transferred {} records of {} from {}",
+        records.len(),
+        transfer.zone,
+        transfer.server
+    );
+    let problems = zone_problems(
+        &normalize(hostname),
+        wan_ip,
+        &dns::addresses(&records),
+        &transfer.expected,
+    );
+    let state = problems.join("\n");
+    let previous = read_state(&axfr_file);
+    if previous == state {
+        return;
+    }
+    if previous.is_empty() {
+        log::info!("Tracking the record of {} in {}", hostname, transfer.zone);
+    }
+    if !previous.is_empty() || !problems.is_empty() {
+        let text = axfr_message(hostname, &transfer.zone, &problems);
+        log::info!("{}", text);
+        let message = Message::new("axfr", &text)
+            .with_field("hostname", hostname)
+            .with_field("zone", &transfer.zone);
+        if !events::alert(&message) {
+            log::warn!("Failed to send zone transfer message");
+            return;
+        }
+    }
+    write_state(&axfr_file, &state);
+}
+
+/// Lists the problems of a zone.
+///
+/// The following problems are reported:
+///
+/// * The hostname has no address in the zone, or another address than the WAN IP address.
+/// * Another name points to the WAN IP address without being expected, e.g. a forgotten record that exposes the network.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname, in lower case and without the trailing dot.
+/// * `wan_ip`: An `IpAddr` that specifies the WAN IP address.
+/// * `addresses`: A `&BTreeMap<String, BTreeSet<IpAddr>>` that holds the addresses of each name in the zone, see `dns::addresses`.
+/// * `expected`: A `&[String]` that holds the other names expected to point to the WAN IP address.
+///
+/// # Returns
+///
+/// * A `Vec<String>` that holds a description of each problem, empty if the zone is as expected.
+fn zone_problems(
+    hostname: &str,
+    wan_ip: IpAddr,
+    addresses: &BTreeMap<String, BTreeSet<IpAddr>>,
+    expected: &[String],
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    // Addresses of the other family, e.g. AAAA records next to an IPv4 WAN IP address, are not compared
+    let hostname_ips: Option<BTreeSet<&IpAddr>> = addresses.get(hostname).map(|ips| {
+        ips.iter()
+            .filter(|ip| ip.is_ipv4() == wan_ip.is_ipv4())
+            .collect()
+    });
+    match hostname_ips.filter(|ips| !ips.is_empty()) {
+        None => problems.push(format!("{} has no address in the zone", hostname)),
+        Some(ips) if ips.len() != 1 || !ips.contains(&wan_ip) => {
+            let ips: Vec<String> = ips.iter().map(|ip| ip.to_string()).collect();
+            problems.push(format!(
+                "{} points to {} instead of {}",
+                hostname,
+                ips.join(", "),
+                wan_ip
+            ));
+        }
+        Some(_) => {}
+    }
+    for (name, ips) in addresses {
+        if name != hostname && ips.contains(&wan_ip) && !expected.contains(name) {
+            problems.push(format!("{} also points to {}", name, wan_ip));
+        }
+    }
+    problems
+}
+
+/// Builds the text of the zone transfer message.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `zone`: A `&str` that specifies the zone.
+/// * `problems`: A `&[String]` that holds the problems, empty if they were resolved.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message.
+fn axfr_message(hostname: &str, zone: &str, problems: &[String]) -> String {
+    if problems.is_empty() {
+        return format!(
+            "The zone matches the WAN IP address again\nHostname: {}\nZone: {}",
+            display_hostname(hostname),
+            zone
+        );
+    }
+    format!(
+        "The zone does not match the WAN IP address!\nHostname: {}\nZone: {}\n{}",
+        display_hostname(hostname),
+        zone,
+        problems
+            .iter()
+            .map(|problem| format!("- {}", problem))
+            .collect::<Vec<String>>()
+            .join("\n")
+    )
+}
+
+/// Converts a name to punycode in lower case without the trailing dot, as the names of `dns::addresses`.
+fn normalize(name: &str) -> String {
+    idna::domain_to_ascii(name)
+        .unwrap_or_else(|_| name.to_string())
+        .trim_end_matches('.')
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone(entries: &[(&str, &str)]) -> BTreeMap<String, BTreeSet<IpAddr>> {
+        let mut addresses: BTreeMap<String, BTreeSet<IpAddr>> = BTreeMap::new();
+        for (name, ip) in entries {
+            addresses
+                .entry(name.to_string())
+                .or_default()
+                .insert(ip.parse().unwrap());
+        }
+        addresses
+    }
+
+    #[test]
+    fn test_zone_problems() {
+        let wan_ip: IpAddr = "1.1.1.1".parse().unwrap();
+        let expected = vec!["vpn.example.com".to_string()];
+
+        let addresses = zone(&[
+            ("home.example.com", "1.1.1.1"),
+            ("home.example.com", "2001:db8::1"),
+            ("vpn.example.com", "1.1.1.1"),
+            ("www.example.com", "2.2.2.2"),
+        ]);
+        assert!(zone_problems("home.example.com", wan_ip, &addresses, &expected).is_empty());
+
+        // Assert that a stale record and an unexpected name pointing to the WAN IP address are reported
+        let addresses = zone(&[
+            ("home.example.com", "3.3.3.3"),
+            ("home.example.com", "1.1.1.1"),
+            ("nas.example.com", "1.1.1.1"),
+        ]);
+        assert_eq!(
+            zone_problems("home.example.com", wan_ip, &addresses, &expected),
+            [
+                "home.example.com points to 1.1.1.1, 3.3.3.3 instead of 1.1.1.1",
+                "nas.example.com also points to 1.1.1.1"
+            ]
+        );
+
+        let addresses = zone(&[("www.example.com", "2.2.2.2")]);
+        assert_eq!(
+            zone_problems("home.example.com", wan_ip, &addresses, &[]),
+            ["home.example.com has no address in the zone"]
+        );
+    }
+
+    #[test]
+    fn test_axfr_message() {
+        let result = axfr_message(
+            "home.example.com",
+            "example.com",
+            &["nas.example.com also points to 1.1.1.1".to_string()],
+        );
+        assert!(result.starts_with("The zone does not match the WAN IP address!"));
+        assert!(result.ends_with("\n- nas.example.com also points to 1.1.1.1"));
+    }
+}
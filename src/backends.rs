@@ -0,0 +1,155 @@
+use crate::events;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::vars::{display_hostname, parse_duration};
+use std::env;
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::time::Duration;
+
+/// Verifies that every address of the hostname answers on a TCP port.
+///
+/// This function retrieves the value of the "BACKEND_PORT" environment variable (e.g. "443").
+/// If it is not set, the backend check is disabled and the function returns immediately.
+///
+/// It connects to the port of every address from DNS using the `dead_backends` function, waiting at most
+/// "BACKEND_TIMEOUT" (defaulting to 3 seconds) per address. This catches round-robin records that point at a dead backend.
+/// The dead addresses are compared with the state stored in the backends file ("BACKENDS_FILE", defaulting to "backend_state" in the state directory),
+/// and a "backends" message is sent when the set of dead addresses changes, including when all of them answer again.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `dns_ips`: A `&[String]` that holds the addresses from DNS.
+pub fn check_backends(hostname: &str, dns_ips: &[String]) {
+    let port = match env::var("BACKEND_PORT") {
+        Ok(port) => match port.parse::<u16>() {
+            Ok(port) => port,
+            Err(_) => {
+                log::warn!("Invalid BACKEND_PORT: {}", port);
+                return;
+            }
+        },
+        Err(_) => return,
+    };
+    if dns_ips.is_empty() {
+        log::debug!("No addresses from DNS, skipping backend check");
+        return;
+    }
+    let timeout = env::var("BACKEND_TIMEOUT")
+        .ok()
+        .and_then(|value| parse_duration(&value))
+        .unwrap_or(Duration::from_secs(3));
+    let backends_file = state_path("BACKENDS_FILE", "backend_state");
+
+    let dead = dead_backends(dns_ips, port, timeout);
+    let state = dead.join("\n");
+    let previous = read_state(&backends_file);
+    if previous == state {
+        return;
+    }
+    if !dead.is_empty() || !previous.is_empty() {
+        let text = backends_message(hostname, port, dns_ips.len(), &dead);
+        log::info!("{}", text);
+        let message = Message::new("backends", &text)
+            .with_field("hostname", hostname)
+            .with_field("dead", &dead.join(","));
+        if !events::alert(&message) {
+            log::warn!("Failed to send backends message");
+            return;
+        }
+    }
+    write_state(&backends_file, &state);
+}
+
+/// Probes the addresses on a TCP port.
+///
+/// # Arguments
+///
+/// * `addresses`: A `&[String]` that holds the addresses to probe.
+/// * `port`: A `u16` that specifies the TCP port.
+/// * `timeout`: A `Duration` that specifies how long to wait for each connection.
+///
+/// # Returns
+///
+/// * A `Vec<String>` that holds the addresses that did not accept a connection, in the order given.
+fn dead_backends(addresses: &[String], port: u16, timeout: Duration) -> Vec<String> {
+    addresses
+        .iter()
+        .filter(|address| {
+            let alive = address.parse::<IpAddr>().is_ok_and(|ip| {
+                TcpStream::connect_timeout(&SocketAddr::new(ip, port), timeout).is_ok()
+            });
+            log::debug!("Backend {}:{} answers: {}", address, port, alive);
+            !alive
+        })
+        .cloned()
+        .collect()
+}
+
+/// Builds the text of the backends message.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `port`: A `u16` that specifies the probed TCP port.
+/// * `total`: A `usize` that specifies the number of addresses from DNS.
+/// * `dead`: A `&[String]` that holds the dead addresses, empty if all of them answer again.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message.
+fn backends_message(hostname: &str, port: u16, total: usize, dead: &[String]) -> String {
+    let hostname = display_hostname(hostname);
+    if dead.is_empty() {
+        return format!(
+            "All addresses of {} answer on port {} again",
+            hostname, port
+        );
+    }
+    format!(
+        "{} of {} addresses of {} do not answer on port {}!\n{}",
+        dead.len(),
+        total,
+        hostname,
+        port,
+        dead.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_dead_backends() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let addresses = vec![
+            "127.0.0.1".to_string(),
+            "not an address".to_string(),
+            "::1".to_string(),
+        ];
+
+        // Assert that the listening address answers and the others are dead
+        let result = dead_backends(&addresses, port, Duration::from_secs(1));
+        assert_eq!(result, ["not an address", "::1"]);
+
+        // Assert that a closed port is dead
+        drop(listener);
+        let result = dead_backends(&addresses[..1], port, Duration::from_secs(1));
+        assert_eq!(result, ["127.0.0.1"]);
+    }
+
+    #[test]
+    fn test_backends_message() {
+        let result = backends_message("www.example.com", 443, 3, &["192.0.2.2".to_string()]);
+        assert!(
+            result.starts_with("1 of 3 addresses of www.example.com do not answer on port 443!")
+        );
+        assert!(result.ends_with("192.0.2.2"));
+        assert!(
+            backends_message("www.example.com", 443, 3, &[]).contains("answer on port 443 again")
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use crate::vars::parse_duration;
+use std::cell::Cell;
+use std::env;
+use std::time::{Duration, Instant};
+
+/// A circuit breaker that skips a failing source for a while.
+///
+/// After "BREAKER_THRESHOLD" consecutive failures (defaulting to 3) the breaker opens and the source is skipped
+/// for "BREAKER_COOLDOWN" (defaulting to 5 minutes), so a dead router API or resolver does not delay every check by its full timeout.
+/// After the cooldown one attempt is let through to probe for recovery: a success closes the breaker, a failure opens it again.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    /// The name of the source, for logging.
+    name: String,
+    threshold: u32,
+    cooldown: Duration,
+    failures: Cell<u32>,
+    open_until: Cell<Option<Instant>>,
+}
+
+impl CircuitBreaker {
+    /// Creates a closed circuit breaker for a source, configured from the environment.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: A `&str` that specifies the name of the source, e.g. "api".
+    pub fn from_env(name: &str) -> CircuitBreaker {
+        let threshold = env::var("BREAKER_THRESHOLD")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(3);
+        let cooldown = env::var("BREAKER_COOLDOWN")
+            .ok()
+            .and_then(|value| parse_duration(&value))
+            .unwrap_or(Duration::from_secs(5 * 60));
+        CircuitBreaker::new(name, threshold, cooldown)
+    }
+
+    /// Creates a closed circuit breaker.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: A `&str` that specifies the name of the source.
+    /// * `threshold`: A `u32` that specifies the number of consecutive failures that open the breaker; 0 disables the breaker.
+    /// * `cooldown`: A `Duration` that specifies how long the source is skipped.
+    pub fn new(name: &str, threshold: u32, cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            name: name.to_string(),
+            threshold,
+            cooldown,
+            failures: Cell::new(0),
+            open_until: Cell::new(None),
+        }
+    }
+
+    /// Returns whether the source may be used: the breaker is closed, or its cooldown is over and a probe is due.
+    pub fn allows(&self) -> bool {
+        match self.open_until.get() {
+            Some(open_until) if Instant::now() < open_until => {
+                log::debug!("Skipping {}, circuit breaker is open", self.name);
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Records the outcome of using the source.
+    ///
+    /// # Arguments
+    ///
+    /// * `success`: A `bool` that indicates whether the source worked.
+    pub fn record(&self, success: bool) {
+        if success {
+            if self.open_until.take().is_some() {
+                log::info!("{} recovered, closing circuit breaker", self.name);
+            }
+            self.failures.set(0);
+            return;
+        }
+        let failures = self.failures.get() + 1;
+        self.failures.set(failures);
+        if self.threshold > 0 && failures >= self.threshold {
+            log::warn!(
+                "{} failed {} times, skipping it for {:?}",
+                self.name,
+                failures,
+                self.cooldown
+            );
+            self.open_until.set(Some(Instant::now() + self.cooldown));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new("api", 2, Duration::from_millis(50));
+
+        // Assert that the breaker opens after the threshold of consecutive failures
+        breaker.record(false);
+        assert!(breaker.allows());
+        breaker.record(false);
+        assert!(!breaker.allows());
+
+        // Assert that a probe is let through after the cooldown, and a failed probe opens the breaker again
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allows());
+        breaker.record(false);
+        assert!(!breaker.allows());
+
+        // Assert that a successful probe closes the breaker and resets the failures
+        std::thread::sleep(Duration::from_millis(60));
+        breaker.record(true);
+        breaker.record(false);
+        assert!(breaker.allows());
+    }
+}
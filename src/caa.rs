@@ -0,0 +1,169 @@
+use crate::dns;
+use crate::events;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::vars::{display_hostname, split_list};
+use std::env;
+use trust_dns_resolver::proto::rr::RecordType;
+
+/// Verifies the CAA records of a zone and reports changes.
+///
+/// This function retrieves the value of the "CAA_ZONE" environment variable (e.g. "example.com").
+/// If it is not set, the CAA check is disabled and the function returns immediately.
+///
+/// It looks up the CAA records of the zone and compares them with the records stored in the CAA file
+/// ("CAA_FILE", defaulting to "caa_records" in the state directory). A "caa" message is sent when the records change,
+/// and when records listed in "CAA_EXPECTED" (e.g. `0 issue "letsencrypt.org"`) are missing.
+/// The first records seen are only stored unless expected records are missing.
+/// If the lookup fails, it logs a warning and keeps the stored records.
+pub fn check_caa() {
+    let zone = match env::var("CAA_ZONE") {
+        Ok(zone) => zone,
+        Err(_) => return,
+    };
+    let caa_file = state_path("CAA_FILE", "caa_records");
+    let expected = split_list(&env::var("CAA_EXPECTED").unwrap_or_default());
+
+    let records = match dns::lookup_records(&zone, RecordType::CAA) {
+        Ok(records) => records,
+        Err(e) => {
+            log::warn!("{}", e);
+            return;
+        }
+    };
+    // An empty state file means nothing was stored yet, so the absence of records is stored as "none"
+    let state = if records.is_empty() {
+        "none".to_string()
+    } else {
+        records.join("\n")
+    };
+    let previous = read_state(&caa_file);
+    if previous == state {
+        return;
+    }
+    let previous_records: Vec<String> = match previous.as_str() {
+        "" | "none" => Vec::new(),
+        previous => previous.lines().map(String::from).collect(),
+    };
+    let missing = missing_records(&expected, &records);
+    if previous.is_empty() && missing.is_empty() {
+        log::info!("Tracking {} CAA records of {}", records.len(), zone);
+    } else {
+        let text = caa_message(&zone, &previous_records, &records, &missing);
+        log::info!("{}", text);
+        if !events::alert(&Message::new("caa", &text).with_field("zone", &zone)) {
+            log::warn!("Failed to send CAA message");
+            return;
+        }
+    }
+    write_state(&caa_file, &state);
+}
+
+/// Normalizes a CAA record for comparison, collapsing whitespace and ignoring case.
+fn normalize_record(record: &str) -> String {
+    record
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Selects the expected records that the zone does not publish.
+///
+/// # Arguments
+///
+/// * `expected`: A `&[String]` that holds the expected records, e.g. `0 issue "letsencrypt.org"`.
+/// * `records`: A `&[String]` that holds the published records.
+///
+/// # Returns
+///
+/// * A `Vec<String>` that holds the missing records.
+fn missing_records(expected: &[String], records: &[String]) -> Vec<String> {
+    let published: Vec<String> = records.iter().map(|r| normalize_record(r)).collect();
+    expected
+        .iter()
+        .filter(|record| !published.contains(&normalize_record(record)))
+        .cloned()
+        .collect()
+}
+
+/// Builds the text of the CAA message.
+///
+/// # Arguments
+///
+/// * `zone`: A `&str` that specifies the zone.
+/// * `previous`: A `&[String]` that holds the previously published records.
+/// * `records`: A `&[String]` that holds the published records.
+/// * `missing`: A `&[String]` that holds the expected records that are not published.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message.
+fn caa_message(zone: &str, previous: &[String], records: &[String], missing: &[String]) -> String {
+    let mut lines = vec![if missing.is_empty() {
+        format!("CAA records of {} changed", display_hostname(zone))
+    } else {
+        format!(
+            "Expected CAA records of {} are missing!",
+            display_hostname(zone)
+        )
+    }];
+    lines.extend(
+        previous
+            .iter()
+            .filter(|record| !records.contains(record))
+            .map(|record| format!("Removed: {}", record)),
+    );
+    lines.extend(
+        records
+            .iter()
+            .filter(|record| !previous.contains(record))
+            .map(|record| format!("Added: {}", record)),
+    );
+    lines.extend(missing.iter().map(|record| format!("Missing: {}", record)));
+    if records.is_empty() {
+        lines.push("The zone publishes no CAA records, any CA may issue certificates".to_string());
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(records: &[&str]) -> Vec<String> {
+        records.iter().map(|record| record.to_string()).collect()
+    }
+
+    #[test]
+    fn test_missing_records() {
+        let records = list(&[
+            "0 issue \"letsencrypt.org\"",
+            "0 iodef \"mailto:ca@example.com\"",
+        ]);
+
+        assert!(missing_records(&list(&["0  issue \"LetsEncrypt.org\""]), &records).is_empty());
+        assert_eq!(
+            missing_records(&list(&["0 issuewild \";\""]), &records),
+            ["0 issuewild \";\""]
+        );
+    }
+
+    #[test]
+    fn test_caa_message() {
+        let previous = list(&["0 issue \"letsencrypt.org\""]);
+
+        let result = caa_message("example.com", &previous, &[], &previous);
+        assert!(result.starts_with("Expected CAA records of example.com are missing!"));
+        assert!(result.contains("Removed: 0 issue \"letsencrypt.org\""));
+        assert!(result.contains("Missing: 0 issue \"letsencrypt.org\""));
+        assert!(result.contains("any CA may issue"));
+
+        let records = list(&["0 issue \"letsencrypt.org\"", "0 issue \"pki.goog\""]);
+        let result = caa_message("example.com", &previous, &records, &[]);
+        assert_eq!(
+            result,
+            "CAA records of example.com changed\nAdded: 0 issue \"pki.goog\""
+        );
+    }
+}
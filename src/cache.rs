@@ -0,0 +1,276 @@
+use crate::vars::parse_duration;
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The cache of resolver answers shared by every lookup of the process, see `dns::try_resolve_addresses`.
+pub static DNS_CACHE: DnsCache = DnsCache::new();
+
+/// The lifetime of the first negative answer without a negative TTL, doubled for every further one.
+const NEGATIVE_BASE: Duration = Duration::from_secs(5);
+
+/// An answer in the cache.
+#[derive(Debug, Clone)]
+struct Entry {
+    /// The records of the answer, empty for a negative answer.
+    records: Vec<String>,
+    /// When the answer expires.
+    expires: Instant,
+    /// The number of negative answers in a row, for the lifetime of the next one.
+    negative_streak: u32,
+}
+
+/// A positive and negative cache of resolver answers, keyed by resolver, name and record type.
+///
+/// The resolvers are built once and shared (see `dns::shared_resolver`), but their own cache is disabled so a reused
+/// resolver never serves stale records; this cache is the only one, so without it every verification queries the upstream
+/// resolvers again, whatever the TTL of the records. Positive answers are kept for their TTL, negative answers (no records)
+/// for the negative TTL of the zone or, if the resolver does not report one, for an exponentially growing time.
+/// Both are capped by "DNS_CACHE_MAX_TTL", so a record with a TTL of a day still gets verified regularly.
+/// Failed lookups are never cached, including SERVFAIL and REFUSED answers, which carry no records either.
+///
+/// An expired answer is kept for another "DNS_CACHE_MAX_TTL" before it is purged, so the negative streak survives
+/// until the name is looked up again after the answer expired.
+#[derive(Debug)]
+pub struct DnsCache {
+    entries: Mutex<BTreeMap<(String, String, String), Entry>>,
+    bypass: AtomicBool,
+}
+
+impl Default for DnsCache {
+    fn default() -> DnsCache {
+        DnsCache::new()
+    }
+}
+
+impl DnsCache {
+    /// Creates an empty cache.
+    pub const fn new() -> DnsCache {
+        DnsCache {
+            entries: Mutex::new(BTreeMap::new()),
+            bypass: AtomicBool::new(false),
+        }
+    }
+
+    /// Bypasses the cache, e.g. for diagnostic commands run with "--no-cache".
+    ///
+    /// # Arguments
+    ///
+    /// * `bypass`: A `bool` that specifies whether lookups skip the cache.
+    pub fn set_bypass(&self, bypass: bool) {
+        self.bypass.store(bypass, Ordering::SeqCst);
+    }
+
+    /// Returns whether the cache is used: it is enabled by setting "DNS_CACHE" to "true" and not bypassed.
+    pub fn is_enabled(&self) -> bool {
+        env::var("DNS_CACHE").as_deref() == Ok("true") && !self.bypass.load(Ordering::SeqCst)
+    }
+
+    /// Looks up a cached answer.
+    ///
+    /// # Arguments
+    ///
+    /// * `resolver`: A `&str` that describes the resolver, see `dns::resolver_config`.
+    /// * `name`: A `&str` that specifies the name.
+    /// * `record_type`: A `&str` that specifies the record type, e.g. "A".
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<Vec<String>>` that holds the records, empty for a negative answer,
+    ///   or `None` if the cache is disabled or holds no valid answer.
+    pub fn get(&self, resolver: &str, name: &str, record_type: &str) -> Option<Vec<String>> {
+        if !self.is_enabled() {
+            return None;
+        }
+        self.get_at(&key(resolver, name, record_type), Instant::now())
+    }
+
+    /// Stores an answer.
+    ///
+    /// # Arguments
+    ///
+    /// * `resolver`: A `&str` that describes the resolver, see `dns::resolver_config`.
+    /// * `name`: A `&str` that specifies the name.
+    /// * `record_type`: A `&str` that specifies the record type, e.g. "A".
+    /// * `records`: A `&[String]` that holds the records, empty for a negative answer, a NOERROR or NXDOMAIN answer without records.
+    /// * `ttl`: An `Option<Duration>` that holds the TTL of the answer; `None` for a negative answer without a negative TTL.
+    pub fn insert(
+        &self,
+        resolver: &str,
+        name: &str,
+        record_type: &str,
+        records: &[String],
+        ttl: Option<Duration>,
+    ) {
+        if !self.is_enabled() {
+            return;
+        }
+        self.insert_at(
+            key(resolver, name, record_type),
+            records,
+            ttl,
+            max_ttl(),
+            Instant::now(),
+        );
+    }
+
+    /// Removes every answer.
+    pub fn clear(&self) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    fn get_at(&self, key: &(String, String, String), now: Instant) -> Option<Vec<String>> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(key)
+            .filter(|entry| entry.expires > now)
+            .map(|entry| entry.records.clone())
+    }
+
+    fn insert_at(
+        &self,
+        key: (String, String, String),
+        records: &[String],
+        ttl: Option<Duration>,
+        max_ttl: Duration,
+        now: Instant,
+    ) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let previous_streak = entries.get(&key).map_or(0, |entry| entry.negative_streak);
+        let negative_streak = if records.is_empty() {
+            previous_streak.saturating_add(1)
+        } else {
+            0
+        };
+        let lifetime = ttl
+            .unwrap_or_else(|| {
+                NEGATIVE_BASE.saturating_mul(2u32.saturating_pow(negative_streak.saturating_sub(1)))
+            })
+            .min(max_ttl);
+        entries.retain(|_, entry| entry.expires + max_ttl > now);
+        entries.insert(
+            key,
+            Entry {
+                records: records.to_vec(),
+                expires: now + lifetime,
+                negative_streak,
+            },
+        );
+    }
+}
+
+/// Builds the key of an answer, with the name in lower case and without the trailing dot.
+fn key(resolver: &str, name: &str, record_type: &str) -> (String, String, String) {
+    (
+        resolver.to_string(),
+        name.trim_end_matches('.').to_lowercase(),
+        record_type.to_string(),
+    )
+}
+
+/// Retrieves the longest time an answer is kept from the "DNS_CACHE_MAX_TTL" environment variable.
+///
+/// # Returns
+///
+/// * A `Duration` that holds the time, defaulting to 5 minutes.
+fn max_ttl() -> Duration {
+    env::var("DNS_CACHE_MAX_TTL")
+        .ok()
+        .and_then(|value| parse_duration(&value))
+        .unwrap_or(Duration::from_secs(5 * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dns_cache() {
+        let cache = DnsCache::new();
+        let now = Instant::now();
+        let max_ttl = Duration::from_secs(300);
+        let addresses = vec!["192.0.2.1".to_string()];
+
+        // Assert that a positive answer is kept for its TTL, whatever the case of the name
+        cache.insert_at(
+            key("google", "Home.example.com.", "A"),
+            &addresses,
+            Some(Duration::from_secs(60)),
+            max_ttl,
+            now,
+        );
+        let home = key("google", "home.example.com", "A");
+        assert_eq!(cache.get_at(&home, now), Some(addresses.clone()));
+        assert_eq!(cache.get_at(&home, now + Duration::from_secs(61)), None);
+        assert_eq!(
+            cache.get_at(&key("cloudflare", "home.example.com", "A"), now),
+            None
+        );
+
+        // Assert that the TTL is capped
+        cache.insert_at(
+            home.clone(),
+            &addresses,
+            Some(Duration::from_secs(86400)),
+            max_ttl,
+            now,
+        );
+        assert_eq!(cache.get_at(&home, now + Duration::from_secs(301)), None);
+
+        // Assert that negative answers without a negative TTL are kept exponentially longer
+        let missing = key("google", "missing.example.com", "A");
+        cache.insert_at(missing.clone(), &[], None, max_ttl, now);
+        assert_eq!(
+            cache.get_at(&missing, now + Duration::from_secs(4)),
+            Some(Vec::new())
+        );
+        assert_eq!(cache.get_at(&missing, now + Duration::from_secs(6)), None);
+        cache.insert_at(missing.clone(), &[], None, max_ttl, now);
+        assert!(cache
+            .get_at(&missing, now + Duration::from_secs(9))
+            .is_some());
+        assert!(cache
+            .get_at(&missing, now + Duration::from_secs(11))
+            .is_none());
+
+        // Assert that the streak survives the expiry of the answer, even when another answer is stored in between
+        let later = now + Duration::from_secs(30);
+        let other = key("google", "other.example.com", "A");
+        cache.insert_at(other.clone(), &addresses, None, max_ttl, later);
+        cache.insert_at(missing.clone(), &[], None, max_ttl, later);
+        assert!(cache
+            .get_at(&missing, later + Duration::from_secs(19))
+            .is_some());
+        assert!(cache
+            .get_at(&missing, later + Duration::from_secs(21))
+            .is_none());
+
+        // Assert that the streak starts over once the expired answer is purged
+        let much_later = later + Duration::from_secs(20) + max_ttl;
+        cache.insert_at(other, &addresses, None, max_ttl, much_later);
+        cache.insert_at(missing.clone(), &[], None, max_ttl, much_later);
+        assert!(cache
+            .get_at(&missing, much_later + Duration::from_secs(6))
+            .is_none());
+
+        // Assert that a negative TTL is honored
+        cache.insert_at(
+            missing.clone(),
+            &[],
+            Some(Duration::from_secs(1)),
+            max_ttl,
+            now,
+        );
+        assert!(cache
+            .get_at(&missing, now + Duration::from_secs(2))
+            .is_none());
+
+        cache.clear();
+        assert_eq!(cache.get_at(&home, now), None);
+    }
+}
@@ -0,0 +1,131 @@
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The number of verifications started, see `start_cycle`.
+static CYCLES: AtomicU64 = AtomicU64::new(0);
+
+/// The number of Telegram requests made while their fault is injected.
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+/// An artificial failure that can be injected to exercise the alerting, retry and fallback paths in staging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Every DNS lookup of the cycle times out.
+    DnsTimeout,
+    /// The router API answers "500 Internal Server Error".
+    ApiError,
+    /// Telegram answers "429 Too Many Requests".
+    TelegramRateLimit,
+}
+
+impl Fault {
+    /// The faults, in the order they are listed at startup.
+    const ALL: [Fault; 3] = [Fault::DnsTimeout, Fault::ApiError, Fault::TelegramRateLimit];
+
+    /// The environment variable that sets how often the fault is injected.
+    fn var(&self) -> &'static str {
+        match self {
+            Fault::DnsTimeout => "CHAOS_DNS_TIMEOUT_EVERY",
+            Fault::ApiError => "CHAOS_API_ERROR_EVERY",
+            Fault::TelegramRateLimit => "CHAOS_TELEGRAM_429_EVERY",
+        }
+    }
+
+    /// Retrieves how often the fault is injected.
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<u64>` that holds N to inject the fault every Nth time, or `None` if it is not injected.
+    fn every(&self) -> Option<u64> {
+        env::var(self.var())
+            .ok()
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .filter(|every| *every > 0)
+    }
+}
+
+/// Returns whether the fault injection is compiled in, with the "chaos" feature.
+fn compiled() -> bool {
+    cfg!(feature = "chaos")
+}
+
+/// Logs the faults that are injected, once at startup.
+///
+/// Fault injection is only compiled into builds with the "chaos" feature, so a production build ignores the variables;
+/// a warning is logged if they are set anyway.
+pub fn announce() {
+    let faults: Vec<String> = Fault::ALL
+        .iter()
+        .filter_map(|fault| {
+            fault
+                .every()
+                .map(|every| format!("{:?} every {}", fault, every))
+        })
+        .collect();
+    if faults.is_empty() {
+        return;
+    }
+    if compiled() {
+        log::warn!("Injecting failures: {}", faults.join(", "));
+    } else {
+        log::warn!(
+            "CHAOS_* variables are ignored, the checker was built without the chaos feature"
+        );
+    }
+}
+
+/// Starts the next verification cycle.
+///
+/// The DNS and router API faults are injected in every Nth cycle, counted from the first verification.
+pub fn start_cycle() {
+    CYCLES.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Decides whether a fault is injected now.
+///
+/// The DNS timeout and the router API error hit every Nth verification cycle as a whole (see `start_cycle`),
+/// so the retries of a lookup within that cycle fail as well. The Telegram rate limit hits every Nth request
+/// to the Bot API, since alerts are rare compared to cycles.
+///
+/// # Arguments
+///
+/// * `fault`: A `Fault` that specifies the fault.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether the fault is injected; always `false` without the "chaos" feature.
+pub fn inject(fault: Fault) -> bool {
+    if !compiled() {
+        return false;
+    }
+    let every = match fault.every() {
+        Some(every) => every,
+        None => return false,
+    };
+    let count = match fault {
+        Fault::DnsTimeout | Fault::ApiError => CYCLES.load(Ordering::SeqCst),
+        Fault::TelegramRateLimit => REQUESTS.fetch_add(1, Ordering::SeqCst) + 1,
+    };
+    let injected = is_due(count, every);
+    if injected {
+        log::warn!("Injecting {:?}", fault);
+    }
+    injected
+}
+
+/// Returns whether the Nth occurrence is hit by a fault injected every `every` times.
+fn is_due(count: u64, every: u64) -> bool {
+    count > 0 && count.is_multiple_of(every)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_due() {
+        let hits: Vec<u64> = (0..10).filter(|count| is_due(*count, 3)).collect();
+        assert_eq!(hits, vec![3, 6, 9]);
+        assert!((1..5).all(|count| is_due(count, 1)));
+    }
+}
@@ -0,0 +1,209 @@
+/// The most columns of a chart; longer histories are merged into this many columns (see `Sample::merge`).
+const MAX_COLUMNS: usize = 120;
+
+/// The width of a column in pixels.
+const COLUMN_WIDTH: usize = 3;
+
+/// The height of the row of an instance in pixels.
+const ROW_HEIGHT: usize = 24;
+
+/// The space around the chart and between its rows in pixels.
+const PADDING: usize = 4;
+
+/// The colors of the chart, as RGB.
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+const IN_SYNC: [u8; 3] = [46, 160, 67];
+const OUT_OF_SYNC: [u8; 3] = [207, 34, 46];
+const UNKNOWN: [u8; 3] = [140, 149, 159];
+
+/// One verification in a chart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    /// Whether DNS matched the WAN IP address, `None` if unknown, e.g. because the instance did not answer.
+    pub in_sync: Option<bool>,
+    /// How long the verification took in milliseconds, if known.
+    pub latency_ms: Option<u64>,
+}
+
+impl Sample {
+    /// Merges the samples of a column: out of sync if any sample is, the highest latency.
+    fn merge(samples: &[Sample]) -> Sample {
+        let in_sync = if samples.iter().any(|sample| sample.in_sync == Some(false)) {
+            Some(false)
+        } else if samples.iter().any(|sample| sample.in_sync == Some(true)) {
+            Some(true)
+        } else {
+            None
+        };
+        Sample {
+            in_sync,
+            latency_ms: samples.iter().filter_map(|sample| sample.latency_ms).max(),
+        }
+    }
+}
+
+/// Renders the history of verifications as a PNG sparkline, one row per history.
+///
+/// Each column is a bar: green in sync, red out of sync and grey unknown. Its height is the latency relative
+/// to the highest latency of the chart, at least a quarter of the row; bars without a latency fill the row.
+/// A history longer than `MAX_COLUMNS` is merged into that many columns, a shorter one is stretched to fill them.
+///
+/// # Arguments
+///
+/// * `rows`: A `&[Vec<Sample>]` that holds the samples of each row, oldest first.
+///
+/// # Returns
+///
+/// * A `Result<Vec<u8>, String>` that holds the PNG image, or an `Err` with a message if it cannot be encoded.
+pub fn sparkline_png(rows: &[Vec<Sample>]) -> Result<Vec<u8>, String> {
+    let width = 2 * PADDING + MAX_COLUMNS * COLUMN_WIDTH;
+    let height = 2 * PADDING + rows.len().max(1) * (ROW_HEIGHT + PADDING) - PADDING;
+    let mut pixels: Vec<u8> = BACKGROUND
+        .iter()
+        .copied()
+        .cycle()
+        .take(width * height * 3)
+        .collect();
+    let max_latency = rows
+        .iter()
+        .flatten()
+        .filter_map(|sample| sample.latency_ms)
+        .max()
+        .unwrap_or(0);
+    for (index, row) in rows.iter().enumerate() {
+        let bottom = PADDING + index * (ROW_HEIGHT + PADDING) + ROW_HEIGHT;
+        for (column, sample) in columns(row).iter().enumerate() {
+            let bar = match sample.latency_ms {
+                Some(latency) if max_latency > 0 => {
+                    (ROW_HEIGHT as u64 * latency / max_latency).max(ROW_HEIGHT as u64 / 4) as usize
+                }
+                _ => ROW_HEIGHT,
+            };
+            let color = match sample.in_sync {
+                Some(true) => IN_SYNC,
+                Some(false) => OUT_OF_SYNC,
+                None => UNKNOWN,
+            };
+            let left = PADDING + column * COLUMN_WIDTH;
+            // Leave a pixel between two bars
+            for y in bottom - bar..bottom {
+                for x in left..left + COLUMN_WIDTH - 1 {
+                    let offset = (y * width + x) * 3;
+                    pixels[offset..offset + 3].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+    encode_png(width as u32, height as u32, &pixels)
+}
+
+/// Spreads the samples of a row over the columns of the chart.
+///
+/// # Arguments
+///
+/// * `samples`: A `&[Sample]` that holds the samples, oldest first.
+///
+/// # Returns
+///
+/// * A `Vec<Sample>` that holds a sample per column, `MAX_COLUMNS` of them, or none if there are no samples.
+fn columns(samples: &[Sample]) -> Vec<Sample> {
+    if samples.is_empty() {
+        return Vec::new();
+    }
+    (0..MAX_COLUMNS)
+        .map(|column| {
+            let start = column * samples.len() / MAX_COLUMNS;
+            let end = ((column + 1) * samples.len() / MAX_COLUMNS).max(start + 1);
+            Sample::merge(&samples[start..end])
+        })
+        .collect()
+}
+
+/// Encodes an RGB image as PNG.
+///
+/// # Arguments
+///
+/// * `width`: A `u32` that specifies the width of the image in pixels.
+/// * `height`: A `u32` that specifies the height of the image in pixels.
+/// * `pixels`: A `&[u8]` that holds the red, green and blue value of each pixel, row by row.
+///
+/// # Returns
+///
+/// * A `Result<Vec<u8>, String>` that holds the PNG file, or an `Err` with a message if it cannot be encoded.
+fn encode_png(width: u32, height: u32, pixels: &[u8]) -> Result<Vec<u8>, String> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels))
+        .map_err(|e| format!("Failed to encode chart: {}", e))?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes a PNG into its width and rows of pixels.
+    fn decode(png: &[u8]) -> (usize, Vec<Vec<u8>>) {
+        let mut reader = png::Decoder::new(png).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(info.color_type, png::ColorType::Rgb);
+        let width = info.width as usize;
+        let rows = pixels.chunks(width * 3).map(|row| row.to_vec()).collect();
+        (width, rows)
+    }
+
+    #[test]
+    fn test_sparkline_png() {
+        let sample = |in_sync, latency_ms| Sample {
+            in_sync,
+            latency_ms,
+        };
+        let rows = vec![
+            vec![
+                sample(Some(true), Some(100)),
+                sample(Some(false), Some(400)),
+            ],
+            vec![sample(None, None)],
+        ];
+        let (width, pixels) = decode(&sparkline_png(&rows).unwrap());
+        assert_eq!(width, 2 * PADDING + MAX_COLUMNS * COLUMN_WIDTH);
+        assert_eq!(pixels.len(), 2 * PADDING + 2 * ROW_HEIGHT + PADDING);
+
+        let pixel = |x: usize, y: usize| pixels[y][x * 3..x * 3 + 3].to_vec();
+        let bottom = PADDING + ROW_HEIGHT - 1;
+        let last_column = PADDING + (MAX_COLUMNS - 1) * COLUMN_WIDTH;
+
+        // Assert that the first row is stretched: in sync with a quarter bar, then out of sync with a full bar
+        assert_eq!(pixel(PADDING, bottom), IN_SYNC);
+        assert_eq!(pixel(PADDING, bottom + 1 - ROW_HEIGHT / 4), IN_SYNC);
+        assert_eq!(pixel(PADDING, bottom - ROW_HEIGHT / 4), BACKGROUND);
+        assert_eq!(pixel(last_column, PADDING), OUT_OF_SYNC);
+        assert_eq!(pixel(last_column + COLUMN_WIDTH - 1, bottom), BACKGROUND);
+
+        // Assert that a sample without latency fills the second row in grey
+        assert_eq!(pixel(last_column, PADDING + ROW_HEIGHT + PADDING), UNKNOWN);
+    }
+
+    #[test]
+    fn test_columns() {
+        let sample = |in_sync| Sample {
+            in_sync,
+            latency_ms: Some(10),
+        };
+        assert!(columns(&[]).is_empty());
+
+        // Assert that a mismatch is kept when samples are merged into a column
+        let mut samples = vec![sample(Some(true)); MAX_COLUMNS * 2];
+        samples[1] = sample(Some(false));
+        samples[2] = sample(None);
+        let merged = columns(&samples);
+        assert_eq!(merged.len(), MAX_COLUMNS);
+        assert_eq!(merged[0].in_sync, Some(false));
+        assert_eq!(merged[1].in_sync, Some(true));
+    }
+}
@@ -0,0 +1,1122 @@
+use crate::breaker::CircuitBreaker;
+use crate::compare::ComparePolicies;
+use crate::dependencies::DEPENDENCIES;
+use crate::dns::AddressFamily;
+use crate::passive::{self, FileSource};
+use crate::script::{CompareScript, Decision};
+use crate::server::Shared;
+use crate::vars::{normalize_hostname, parse_duration, split_list};
+use crate::{
+    agent, alarm, api, axfr, backends, caa, chaos, client_subnet, ct, delegation, dns, dnssec,
+    events, failover, mail, nameservers, planned, prefix, propagation, slo, split_horizon, ttl,
+    wildcard,
+};
+use chrono::{DateTime, FixedOffset, Local};
+use ipnet::IpNet;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The time between two verifications of the checker loop.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The checks run when "CHECKS" is not set, in their default order.
+pub const DEFAULT_CHECKS: [&str; 22] = [
+    "resolve_dns",
+    "fetch_wan_ip",
+    "compare",
+    "history",
+    "slo",
+    "wan_change",
+    "failover",
+    "wildcard",
+    "prefix",
+    "agents",
+    "split_horizon",
+    "client_subnet",
+    "propagation",
+    "dnssec",
+    "delegation",
+    "nameservers",
+    "axfr",
+    "caa",
+    "mail_records",
+    "certificates",
+    "ttl",
+    "backends",
+];
+
+/// The state of one verification, passed from check to check.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Context {
+    /// The monitored hostname.
+    pub hostname: String,
+    /// The IP address from DNS, empty if it could not be resolved.
+    pub dns_ip: String,
+    /// All addresses from DNS of the address family (see `dns::address_family`), IPv4 first; `dns_ip` is the first of them.
+    pub dns_ips: Vec<String>,
+    /// The details of the DNS response, only kept when the queries are sent directly (see "DNS_QUERY_MODE").
+    pub dns_response: Option<dns::DnsResponse>,
+    /// The interface name and address of each configured interface that has an address.
+    pub wan_ips: Vec<(String, String)>,
+    /// The source the WAN IP addresses were taken from, see `passive::sources`.
+    pub wan_source: String,
+    /// The interface selected for the comparison.
+    pub interface: String,
+    /// The address of the selected interface, empty if it could not be retrieved.
+    pub wan_ip: String,
+    /// Whether the addresses are in sync, `None` if they were not compared.
+    pub in_sync: Option<bool>,
+    /// How long each check took, in the order they ran.
+    pub durations: Vec<(String, Duration)>,
+}
+
+impl Context {
+    /// Creates the context of a verification of the hostname.
+    pub fn new(hostname: &str) -> Context {
+        Context {
+            hostname: hostname.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A step of the verification.
+///
+/// Checks read and extend the `Context` left by the checks before them,
+/// and raise events (alarms, recoveries, failovers…) on the event bus, see the `events` module.
+/// Checks are `Send`, so a `Checker` holding the pipeline can be moved to another thread.
+pub trait Check: Send {
+    /// The name of the check, as used in "CHECKS".
+    fn name(&self) -> &'static str;
+
+    /// The names of the checks that must run before this one.
+    fn requires(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Runs the check.
+    fn run(&self, context: &mut Context);
+}
+
+/// Resolves the hostname to its IP addresses using the `dns::try_resolve_family` function.
+///
+/// The records looked up are the A records, the AAAA records or both, see `dns::address_family`.
+/// The resolvers are tried in order: the upstream resolvers (see `dns::default_resolver`), then the resolvers
+/// in "DNS_FALLBACK_RESOLVERS" (e.g. "cloudflare,quad9").
+/// Each resolver is wrapped in a `CircuitBreaker`, so a resolver that keeps failing is skipped for a while.
+///
+/// If "DNS_QUERY_MODE" is "raw", or "DNS_CLIENT_SUBNET" is set, the queries are sent directly using the `dns::query_addresses` function,
+/// and the details of the response are kept in the context. With "DNS_CLIENT_SUBNET" the hostname is resolved as seen from that network.
+/// These queries only look up the A records.
+struct ResolveDns {
+    resolvers: Vec<(String, CircuitBreaker)>,
+    raw: bool,
+    subnet: Option<IpNet>,
+    family: AddressFamily,
+}
+
+impl ResolveDns {
+    /// Creates the check with the resolvers, the query mode and the client network from the environment.
+    fn from_env() -> Result<ResolveDns, String> {
+        let primary = dns::default_resolver();
+        if dns::use_system() && primary != "system" {
+            return Err(format!(
+                "DNS_USE_SYSTEM uses the resolvers of the host, but DNS_RESOLVERS is {}",
+                primary
+            ));
+        }
+        dns::validate_resolver(&primary).map_err(|e| format!("Invalid DNS_RESOLVERS: {}", e))?;
+        let mut names = vec![primary];
+        names.extend(split_list(
+            &env::var("DNS_FALLBACK_RESOLVERS").unwrap_or_default(),
+        ));
+        let raw = match env::var("DNS_QUERY_MODE").as_deref() {
+            Ok("raw") => true,
+            Ok("resolver") | Err(_) => false,
+            Ok(mode) => {
+                return Err(format!(
+                    "Invalid DNS_QUERY_MODE {}, expected resolver or raw",
+                    mode
+                ))
+            }
+        };
+        let subnet = client_subnet::client_subnet()?;
+        if (raw || subnet.is_some()) && dns::is_tls_resolver(&names[0]) {
+            return Err(
+                "DNS_QUERY_MODE raw and DNS_CLIENT_SUBNET send their queries over UDP, they cannot use a DNS-over-TLS resolver"
+                    .to_string(),
+            );
+        }
+        let family = dns::address_family();
+        if (raw || subnet.is_some()) && family != AddressFamily::V4 {
+            log::warn!("Direct DNS queries only look up A records, ignoring DNS_ADDRESS_FAMILY");
+        }
+        Ok(ResolveDns {
+            raw,
+            family,
+            resolvers: names
+                .into_iter()
+                .map(|name| {
+                    let breaker = CircuitBreaker::from_env(&format!("Resolver {}", name));
+                    (name, breaker)
+                })
+                .collect(),
+            subnet,
+        })
+    }
+}
+
+impl Check for ResolveDns {
+    fn name(&self) -> &'static str {
+        "resolve_dns"
+    }
+
+    fn run(&self, context: &mut Context) {
+        context.dns_ips = Vec::new();
+        context.dns_response = None;
+        let mut error = None;
+        for (resolver, breaker) in &self.resolvers {
+            if !breaker.allows() {
+                continue;
+            }
+            let result = if self.raw || self.subnet.is_some() {
+                dns::query_addresses(&context.hostname, resolver, self.subnet).map(|response| {
+                    log::debug!("{}: {}", context.hostname, response.summary());
+                    let addresses = response.addresses();
+                    context.dns_response = Some(response);
+                    addresses
+                })
+            } else {
+                dns::try_resolve_family(&context.hostname, resolver, self.family)
+            };
+            DEPENDENCIES.record(&format!("resolver {}", resolver), &result);
+            match result {
+                Ok(dns_ips) => {
+                    breaker.record(true);
+                    context.dns_ips = dns_ips;
+                    error = None;
+                    break;
+                }
+                Err(e) => {
+                    log::warn!("{}", e);
+                    breaker.record(false);
+                    error = Some(e);
+                }
+            }
+        }
+        context.dns_ip = context.dns_ips.first().cloned().unwrap_or_default();
+        if context.dns_ip.is_empty() {
+            log::warn!("Failed to get IP address");
+        }
+        if let Some(error) = error {
+            events::publish(&events::Event::CheckError {
+                check: self.name(),
+                error: &error,
+            });
+        }
+    }
+}
+
+/// A source of the WAN IP addresses.
+enum WanIpSource {
+    /// The router API, see `api::get_wan_ips`.
+    Api,
+    /// The file of another DDNS client, as the only interface "file".
+    File(FileSource),
+    /// The addresses of a reference hostname, each as an interface named after the hostname.
+    Hostname(String),
+}
+
+impl WanIpSource {
+    /// The name of the source, as used in "WAN_IP_SOURCE".
+    fn name(&self) -> &'static str {
+        match self {
+            WanIpSource::Api => "api",
+            WanIpSource::File(_) => "file",
+            WanIpSource::Hostname(_) => "hostname",
+        }
+    }
+
+    /// Retrieves the WAN IP addresses from the source.
+    ///
+    /// # Returns
+    ///
+    /// * A `Vec<(String, String)>` that holds the interface name and IPv4 address of each interface, empty if the source failed.
+    fn wan_ips(&self) -> Vec<(String, String)> {
+        match self {
+            WanIpSource::Api => api::get_wan_ips(),
+            WanIpSource::File(source) => {
+                let wan_ip = source.wan_ip();
+                if wan_ip.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![("file".to_string(), wan_ip)]
+                }
+            }
+            WanIpSource::Hostname(hostname) => {
+                match dns::try_resolve_addresses(hostname, &dns::default_resolver()) {
+                    Ok(addresses) => addresses
+                        .into_iter()
+                        .map(|address| (hostname.clone(), address))
+                        .collect(),
+                    Err(e) => {
+                        log::warn!("{}", e);
+                        Vec::new()
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Retrieves the reference hostname of the "hostname" source from the "REFERENCE_HOSTNAME" environment variable.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds the normalized hostname, or an `Err` with a message if it is missing or invalid.
+fn reference_hostname() -> Result<String, String> {
+    let hostname = env::var("REFERENCE_HOSTNAME").map_err(|_| {
+        "REFERENCE_HOSTNAME must be set when WAN_IP_SOURCE includes hostname".to_string()
+    })?;
+    normalize_hostname(&hostname).map_err(|e| format!("Invalid REFERENCE_HOSTNAME: {}", e))
+}
+
+/// Retrieves the WAN IP addresses of the configured interfaces
+/// and selects the one to compare with according to the "INTERFACE_POLICY" environment variable.
+///
+/// The sources in "WAN_IP_SOURCE" (see `passive::sources`) are tried in order until one returns an address.
+/// Each source is wrapped in a `CircuitBreaker`, so a dead router API does not delay every check by its timeout.
+struct FetchWanIp {
+    sources: Vec<(WanIpSource, CircuitBreaker)>,
+}
+
+impl FetchWanIp {
+    /// Creates the check with the sources from the environment.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<FetchWanIp, String>` that holds the check, or an `Err` with a message if a source is unknown or invalid.
+    fn from_env() -> Result<FetchWanIp, String> {
+        let mut sources = Vec::new();
+        for name in passive::sources() {
+            let source = match name.as_str() {
+                "api" => WanIpSource::Api,
+                "file" => WanIpSource::File(FileSource::from_env()?),
+                "hostname" => WanIpSource::Hostname(reference_hostname()?),
+                _ => {
+                    return Err(format!(
+                        "Unknown source {} in WAN_IP_SOURCE, expected api, file or hostname",
+                        name
+                    ))
+                }
+            };
+            sources.push((
+                source,
+                CircuitBreaker::from_env(&format!("Source {}", name)),
+            ));
+        }
+        Ok(FetchWanIp { sources })
+    }
+}
+
+impl Check for FetchWanIp {
+    fn name(&self) -> &'static str {
+        "fetch_wan_ip"
+    }
+
+    fn run(&self, context: &mut Context) {
+        context.wan_ips = Vec::new();
+        for (source, breaker) in &self.sources {
+            if !breaker.allows() {
+                continue;
+            }
+            context.wan_source = source.name().to_string();
+            context.wan_ips = source.wan_ips();
+            breaker.record(!context.wan_ips.is_empty());
+            let dependency = format!("source {}", source.name());
+            if context.wan_ips.is_empty() {
+                DEPENDENCIES.record_failure(&dependency, "No WAN IP address returned");
+            } else {
+                DEPENDENCIES.record_success(&dependency);
+            }
+            if !context.wan_ips.is_empty() {
+                break;
+            }
+        }
+        select_interface(context);
+        if context.wan_ip.is_empty() {
+            log::warn!("Failed to get WAN IP address");
+            events::publish(&events::Event::CheckError {
+                check: self.name(),
+                error: "Failed to get WAN IP address",
+            });
+        }
+    }
+}
+
+/// Selects the interface to compare with from the WAN IP addresses of the context,
+/// according to the "INTERFACE_POLICY" environment variable (see `api::select_wan_ip`).
+///
+/// # Arguments
+///
+/// * `context`: A `&mut Context` that holds the addresses from DNS and the WAN IP addresses; the selection is stored in it.
+pub fn select_interface(context: &mut Context) {
+    let policy = env::var("INTERFACE_POLICY").unwrap_or("primary".to_string());
+    // With round-robin records, prefer the record that points at one of the interfaces
+    let dns_ip = context
+        .dns_ips
+        .iter()
+        .find(|dns_ip| context.wan_ips.iter().any(|(_, wan_ip)| wan_ip == *dns_ip))
+        .unwrap_or(&context.dns_ip);
+    let (interface, wan_ip) = api::select_wan_ip(&context.wan_ips, dns_ip, &policy);
+    context.interface = interface;
+    context.wan_ip = wan_ip;
+}
+
+/// Compares the IP address from DNS with the WAN IP address and raises or clears the alarm using the `alarm::process` function.
+///
+/// If either IP address is empty, it logs a warning and skips the comparison.
+/// If a comparison script is configured (see `script::CompareScript`), it decides whether the addresses are in sync;
+/// otherwise the addresses from DNS are matched against the WAN IP addresses according to the policy of the hostname
+/// (see `ComparePolicies`).
+/// During the window of a planned change (see `planned::PlannedChange`), DNS already showing the new address is accepted,
+/// and a confirmation is sent once it is observed (see `planned::confirm_change`).
+/// A mismatch with a WAN IP address that shows the uplink is down (see `failover::wan_down_reason`)
+/// raises a "wan_down" message instead of the alarm, unless the address is taken from a reference hostname.
+struct Compare {
+    script: Option<CompareScript>,
+    policies: ComparePolicies,
+}
+
+impl Check for Compare {
+    fn name(&self) -> &'static str {
+        "compare"
+    }
+
+    fn requires(&self) -> &'static [&'static str] {
+        &["resolve_dns", "fetch_wan_ip"]
+    }
+
+    fn run(&self, context: &mut Context) {
+        log::debug!(
+            "The IP address of {} is: {}, WAN IP address is: {} ({})",
+            context.hostname,
+            context.dns_ip,
+            context.wan_ip,
+            context.interface
+        );
+        let in_sync = match compare_addresses(
+            self.script.as_ref(),
+            &self.policies,
+            context,
+            Local::now().fixed_offset(),
+        ) {
+            Some(in_sync) => in_sync,
+            None => return,
+        };
+        planned::confirm_change(
+            &context.hostname,
+            &context.dns_ips,
+            self.policies.planned_changes(),
+        );
+
+        // A mismatch with an uplink that is down is reported as such, not as a stale DNS record
+        let reason = if in_sync || context.wan_source == "hostname" {
+            None
+        } else {
+            failover::wan_down_reason(&context.wan_ip)
+        };
+        failover::check_wan_down(
+            &context.hostname,
+            &context.interface,
+            &context.wan_ip,
+            reason,
+        );
+        if reason.is_some() {
+            log::info!("WAN uplink is down, skipping mismatch alarm");
+            return;
+        }
+        context.in_sync = Some(in_sync);
+
+        let dns_ips = context.dns_ips.join(", ");
+        if !in_sync {
+            log::info!("IP address is different");
+            if !alarm::process(&context.hostname, &context.wan_ip, &dns_ips, false) {
+                log::warn!("Failed to send alarm");
+            } else {
+                log::info!("Alarm sent");
+            }
+        } else if !alarm::process(&context.hostname, &context.wan_ip, &dns_ips, true) {
+            log::warn!("Failed to send recovery message");
+        }
+    }
+}
+
+/// Decides whether the IP addresses from DNS are in sync with the WAN IP address, without raising or clearing the alarm.
+///
+/// If either IP address is empty or the comparison script skips the comparison, the addresses are not compared.
+///
+/// # Arguments
+///
+/// * `script`: An `Option<&CompareScript>` that holds the comparison script, if one is configured.
+/// * `policies`: A `&ComparePolicies` that holds the comparison policy of each hostname.
+/// * `context`: A `&Context` that holds the addresses.
+/// * `now`: A `DateTime<FixedOffset>` that specifies the time of the verification, for the windows of the planned changes.
+///
+/// # Returns
+///
+/// * An `Option<bool>` that holds whether the addresses are in sync, or `None` if they were not compared.
+pub fn compare_addresses(
+    script: Option<&CompareScript>,
+    policies: &ComparePolicies,
+    context: &Context,
+    now: DateTime<FixedOffset>,
+) -> Option<bool> {
+    if context.dns_ip.is_empty() || context.wan_ip.is_empty() {
+        log::warn!("Since one of the IP addresses is empty, skipping comparison");
+        return None;
+    }
+    let decision = match script {
+        Some(script) => script.decide(context),
+        None => Decision::Default,
+    };
+    let in_sync = match decision {
+        Decision::Default => {
+            let in_sync = policies.for_hostname(&context.hostname).in_sync_per_family(
+                &context.dns_ips,
+                &context.wan_ip,
+                &context.wan_ips,
+            );
+            if !in_sync && policies.accepts_planned(&context.hostname, &context.dns_ips, now) {
+                log::info!(
+                    "{} already resolves to the address of a planned change, accepting it",
+                    context.hostname
+                );
+                true
+            } else {
+                in_sync
+            }
+        }
+        Decision::InSync => true,
+        Decision::Mismatch => false,
+        Decision::Skip => {
+            log::info!("Comparison skipped by COMPARE_SCRIPT");
+            return None;
+        }
+    };
+    if decision != Decision::Default {
+        log::debug!("COMPARE_SCRIPT decided in sync: {}", in_sync);
+    }
+    Some(in_sync)
+}
+
+/// Turns on the history of the verifications.
+///
+/// The check runs nothing itself: the history is written by `history::HistorySubscriber` when the verification
+/// is published on the event bus, so it holds the final result of every check. Leaving "history" out of "CHECKS"
+/// turns the history off.
+struct RecordHistory;
+
+impl Check for RecordHistory {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    fn requires(&self) -> &'static [&'static str] {
+        &["resolve_dns", "fetch_wan_ip"]
+    }
+
+    fn run(&self, _context: &mut Context) {}
+}
+
+/// Verifies that the latency of the DNS lookups meets its objective using the `slo::check_slo` function.
+///
+/// The latencies are read from the history, so it needs the "history" check,
+/// and as the whole history is read, it is run once per "SLO_INTERVAL" (see `Throttled`).
+struct Slo {
+    slo: Option<slo::LatencySlo>,
+}
+
+impl Check for Slo {
+    fn name(&self) -> &'static str {
+        "slo"
+    }
+
+    fn requires(&self) -> &'static [&'static str] {
+        &["history"]
+    }
+
+    fn run(&self, context: &mut Context) {
+        if let Some(slo) = &self.slo {
+            slo::check_slo(&context.hostname, slo);
+        }
+    }
+}
+
+/// Reports changes of the WAN IP address using the `failover::check_wan_ip` function.
+struct WanChange;
+
+impl Check for WanChange {
+    fn name(&self) -> &'static str {
+        "wan_change"
+    }
+
+    fn requires(&self) -> &'static [&'static str] {
+        &["fetch_wan_ip"]
+    }
+
+    fn run(&self, context: &mut Context) {
+        failover::check_wan_ip(&context.interface, &context.wan_ip);
+    }
+}
+
+/// Reports transitions between WAN uplinks using the `failover::check_uplink` function.
+struct Failover;
+
+impl Check for Failover {
+    fn name(&self) -> &'static str {
+        "failover"
+    }
+
+    fn requires(&self) -> &'static [&'static str] {
+        &["fetch_wan_ip"]
+    }
+
+    fn run(&self, context: &mut Context) {
+        failover::check_uplink(&context.wan_ips, &context.hostname, &context.dns_ip);
+    }
+}
+
+/// Checks that the wildcard record follows the WAN IP address using the `wildcard::check_wildcard` function.
+struct Wildcard;
+
+impl Check for Wildcard {
+    fn name(&self) -> &'static str {
+        "wildcard"
+    }
+
+    fn requires(&self) -> &'static [&'static str] {
+        &["fetch_wan_ip"]
+    }
+
+    fn run(&self, context: &mut Context) {
+        wildcard::check_wildcard(&context.wan_ip);
+    }
+}
+
+/// Checks whether the delegated IPv6 prefix changed using the `prefix::check_prefix` function.
+struct Prefix;
+
+impl Check for Prefix {
+    fn name(&self) -> &'static str {
+        "prefix"
+    }
+
+    fn run(&self, _context: &mut Context) {
+        prefix::check_prefix();
+    }
+}
+
+/// Compares the answers of the agents with the DNS answer using the `agent::check_agents` function.
+struct Agents {
+    shared: Arc<Shared>,
+}
+
+impl Check for Agents {
+    fn name(&self) -> &'static str {
+        "agents"
+    }
+
+    fn requires(&self) -> &'static [&'static str] {
+        &["resolve_dns"]
+    }
+
+    fn run(&self, context: &mut Context) {
+        let reports = self.shared.agents.fresh(agent::max_age());
+        if !reports.is_empty() {
+            agent::check_agents(&context.hostname, &context.dns_ips, &reports);
+        }
+    }
+}
+
+/// Probes every address from DNS using the `backends::check_backends` function.
+struct Backends;
+
+impl Check for Backends {
+    fn name(&self) -> &'static str {
+        "backends"
+    }
+
+    fn requires(&self) -> &'static [&'static str] {
+        &["resolve_dns"]
+    }
+
+    fn run(&self, context: &mut Context) {
+        backends::check_backends(&context.hostname, &context.dns_ips);
+    }
+}
+
+/// Compares the answers of the internal and the external resolver using the `split_horizon::check_split_horizon` function.
+struct SplitHorizon {
+    allow: Vec<IpNet>,
+}
+
+impl Check for SplitHorizon {
+    fn name(&self) -> &'static str {
+        "split_horizon"
+    }
+
+    fn run(&self, context: &mut Context) {
+        split_horizon::check_split_horizon(&context.hostname, &self.allow);
+    }
+}
+
+/// Compares the answers for several client networks using the `client_subnet::check_client_subnets` function.
+struct ClientSubnet {
+    subnets: Vec<IpNet>,
+}
+
+impl Check for ClientSubnet {
+    fn name(&self) -> &'static str {
+        "client_subnet"
+    }
+
+    fn run(&self, context: &mut Context) {
+        client_subnet::check_client_subnets(&context.hostname, &self.subnets);
+    }
+}
+
+/// Compares the answers of several resolvers with the WAN IP address using the `propagation::check_propagation` function.
+struct Propagation {
+    resolvers: Vec<String>,
+}
+
+impl Check for Propagation {
+    fn name(&self) -> &'static str {
+        "propagation"
+    }
+
+    fn requires(&self) -> &'static [&'static str] {
+        &["fetch_wan_ip"]
+    }
+
+    fn run(&self, context: &mut Context) {
+        propagation::check_propagation(&context.hostname, &context.wan_ip, &self.resolvers);
+    }
+}
+
+/// Verifies that the answer for the hostname passes DNSSEC validation using the `dnssec::check_dnssec` function.
+struct Dnssec;
+
+impl Check for Dnssec {
+    fn name(&self) -> &'static str {
+        "dnssec"
+    }
+
+    fn run(&self, context: &mut Context) {
+        dnssec::check_dnssec(&context.hostname);
+    }
+}
+
+/// Verifies the delegation of a zone using the `delegation::check_delegation` function.
+///
+/// The delegation changes rarely and is verified by querying the name servers of the parent zone,
+/// so it is run once per "DELEGATION_INTERVAL" (see `Throttled`).
+struct Delegation;
+
+impl Check for Delegation {
+    fn name(&self) -> &'static str {
+        "delegation"
+    }
+
+    fn run(&self, _context: &mut Context) {
+        delegation::check_delegation();
+    }
+}
+
+/// Watches the name servers of the zone using the `nameservers::check_nameservers` function.
+///
+/// The name servers change rarely, so they are looked up once per "NS_INTERVAL" (see `Throttled`).
+struct Nameservers;
+
+impl Check for Nameservers {
+    fn name(&self) -> &'static str {
+        "nameservers"
+    }
+
+    fn run(&self, context: &mut Context) {
+        nameservers::check_nameservers(&context.hostname);
+    }
+}
+
+/// Watches Certificate Transparency logs using the `ct::check_certificates` function.
+///
+/// The logs are polled from a public service, so it is run once per "CT_INTERVAL" (see `Throttled`).
+struct Certificates;
+
+impl Check for Certificates {
+    fn name(&self) -> &'static str {
+        "certificates"
+    }
+
+    fn run(&self, context: &mut Context) {
+        ct::check_certificates(&context.hostname);
+    }
+}
+
+/// Watches the TTL of the monitored record using the `ttl::check_ttl` function.
+///
+/// The TTL is retrieved from the authoritative name servers, so it is run once per "TTL_INTERVAL" (see `Throttled`).
+struct Ttl;
+
+impl Check for Ttl {
+    fn name(&self) -> &'static str {
+        "ttl"
+    }
+
+    fn run(&self, context: &mut Context) {
+        ttl::check_ttl(&context.hostname);
+    }
+}
+
+/// Runs a check at most once per interval, for checks that query external services.
+struct Throttled {
+    check: Box<dyn Check>,
+    interval: Duration,
+    last_run: Mutex<Option<Instant>>,
+}
+
+impl Throttled {
+    /// Wraps a check that runs at most once per the interval in an environment variable.
+    ///
+    /// # Arguments
+    ///
+    /// * `check`: A `Box<dyn Check>` that holds the check.
+    /// * `var`: A `&str` that specifies the environment variable with the interval, e.g. "DELEGATION_INTERVAL".
+    /// * `default`: A `Duration` that specifies the interval if the variable is not set or invalid.
+    fn new(check: Box<dyn Check>, var: &str, default: Duration) -> Throttled {
+        Throttled {
+            check,
+            interval: env::var(var)
+                .ok()
+                .and_then(|value| parse_duration(&value))
+                .unwrap_or(default),
+            last_run: Mutex::new(None),
+        }
+    }
+}
+
+impl Check for Throttled {
+    fn name(&self) -> &'static str {
+        self.check.name()
+    }
+
+    fn requires(&self) -> &'static [&'static str] {
+        self.check.requires()
+    }
+
+    fn run(&self, context: &mut Context) {
+        {
+            let mut last_run = self.last_run.lock().unwrap_or_else(|e| e.into_inner());
+            if last_run.is_some_and(|last_run| last_run.elapsed() < self.interval) {
+                return;
+            }
+            *last_run = Some(Instant::now());
+        }
+        self.check.run(context);
+    }
+}
+
+/// Verifies the record at the primary name server using the `axfr::check_zone` function.
+///
+/// The whole zone is transferred, so it is run once per "AXFR_INTERVAL" (see `Throttled`).
+struct ZoneTransfer {
+    transfer: Option<axfr::ZoneTransfer>,
+}
+
+impl Check for ZoneTransfer {
+    fn name(&self) -> &'static str {
+        "axfr"
+    }
+
+    fn requires(&self) -> &'static [&'static str] {
+        &["fetch_wan_ip"]
+    }
+
+    fn run(&self, context: &mut Context) {
+        if let Some(transfer) = &self.transfer {
+            axfr::check_zone(transfer, &context.hostname, &context.wan_ip);
+        }
+    }
+}
+
+/// Verifies the CAA records of a zone using the `caa::check_caa` function.
+struct Caa;
+
+impl Check for Caa {
+    fn name(&self) -> &'static str {
+        "caa"
+    }
+
+    fn run(&self, _context: &mut Context) {
+        caa::check_caa();
+    }
+}
+
+/// Detects drift of the mail-related TXT records using the `mail::check_mail_records` function.
+struct MailRecords;
+
+impl Check for MailRecords {
+    fn name(&self) -> &'static str {
+        "mail_records"
+    }
+
+    fn run(&self, _context: &mut Context) {
+        mail::check_mail_records();
+    }
+}
+
+/// Creates a check by name.
+///
+/// # Arguments
+///
+/// * `name`: A `&str` that specifies the name of the check.
+/// * `shared`: A `&Arc<Shared>` that holds the state shared with the HTTP listener.
+///
+/// # Returns
+///
+/// * A `Result<Box<dyn Check>, String>` that holds the check.
+/// * If there is no check with that name or it cannot be set up, it returns an `Err` with a message.
+fn create_check(name: &str, shared: &Arc<Shared>) -> Result<Box<dyn Check>, String> {
+    Ok(match name {
+        "resolve_dns" => Box::new(ResolveDns::from_env()?),
+        "fetch_wan_ip" => Box::new(FetchWanIp::from_env()?),
+        "compare" => Box::new(Compare {
+            script: CompareScript::from_env()?,
+            policies: ComparePolicies::from_env()?,
+        }),
+        "history" => Box::new(RecordHistory),
+        "slo" => Box::new(Throttled::new(
+            Box::new(Slo {
+                slo: slo::LatencySlo::from_env()?,
+            }),
+            "SLO_INTERVAL",
+            Duration::from_secs(5 * 60),
+        )),
+        "wan_change" => Box::new(WanChange),
+        "failover" => Box::new(Failover),
+        "wildcard" => Box::new(Wildcard),
+        "prefix" => Box::new(Prefix),
+        "agents" => Box::new(Agents {
+            shared: shared.clone(),
+        }),
+        "delegation" => Box::new(Throttled::new(
+            Box::new(Delegation),
+            "DELEGATION_INTERVAL",
+            Duration::from_secs(60 * 60),
+        )),
+        "nameservers" => Box::new(Throttled::new(
+            Box::new(Nameservers),
+            "NS_INTERVAL",
+            Duration::from_secs(15 * 60),
+        )),
+        "axfr" => Box::new(Throttled::new(
+            Box::new(ZoneTransfer {
+                transfer: axfr::ZoneTransfer::from_env()?,
+            }),
+            "AXFR_INTERVAL",
+            Duration::from_secs(5 * 60),
+        )),
+        "certificates" => Box::new(Throttled::new(
+            Box::new(Certificates),
+            "CT_INTERVAL",
+            Duration::from_secs(60 * 60),
+        )),
+        "ttl" => Box::new(Throttled::new(
+            Box::new(Ttl),
+            "TTL_INTERVAL",
+            Duration::from_secs(15 * 60),
+        )),
+        "backends" => Box::new(Backends),
+        "caa" => Box::new(Caa),
+        "mail_records" => Box::new(MailRecords),
+        "split_horizon" => Box::new(SplitHorizon {
+            allow: split_horizon::allowlist()?,
+        }),
+        "client_subnet" => Box::new(ClientSubnet {
+            subnets: client_subnet::subnets()?,
+        }),
+        "propagation" => Box::new(Propagation {
+            resolvers: propagation::resolvers()?,
+        }),
+        "dnssec" => Box::new(Dnssec),
+        _ => {
+            return Err(format!(
+                "Unknown check {} in CHECKS, expected one of: {}",
+                name,
+                DEFAULT_CHECKS.join(", ")
+            ))
+        }
+    })
+}
+
+/// Assembles the check pipeline from the "CHECKS" environment variable.
+///
+/// This function retrieves the comma-separated list of check names in "CHECKS" (e.g. "resolve_dns,fetch_wan_ip,compare").
+/// If it is not set, it uses `DEFAULT_CHECKS`. The checks run in the order of the list.
+///
+/// # Arguments
+///
+/// * `shared`: A `&Arc<Shared>` that holds the state shared with the HTTP listener.
+///
+/// # Returns
+///
+/// * A `Result<Vec<Box<dyn Check>>, String>` that holds the checks in order.
+/// * If a name is unknown, listed twice, a check is listed before a check it requires, or a check cannot be set up,
+///   it returns an `Err` with a message.
+pub fn pipeline(shared: &Arc<Shared>) -> Result<Vec<Box<dyn Check>>, String> {
+    build_pipeline(&configured_names(), shared)
+}
+
+/// Returns whether a check is listed in "CHECKS", or is a default check if it is not set.
+///
+/// # Arguments
+///
+/// * `name`: A `&str` that specifies the name of the check, e.g. "history".
+pub fn enabled(name: &str) -> bool {
+    configured_names()
+        .iter()
+        .any(|configured| configured == name)
+}
+
+/// Retrieves the names of the checks in "CHECKS", or `DEFAULT_CHECKS` if it is not set.
+fn configured_names() -> Vec<String> {
+    match env::var("CHECKS") {
+        Ok(checks) => split_list(&checks),
+        Err(_) => DEFAULT_CHECKS.iter().map(|name| name.to_string()).collect(),
+    }
+}
+
+/// Assembles a check pipeline from a list of check names.
+///
+/// # Arguments
+///
+/// * `names`: A `&[String]` that holds the names of the checks in order.
+/// * `shared`: A `&Arc<Shared>` that holds the state shared with the HTTP listener.
+///
+/// # Returns
+///
+/// * A `Result<Vec<Box<dyn Check>>, String>` that holds the checks in order, or an `Err` with a message.
+fn build_pipeline(names: &[String], shared: &Arc<Shared>) -> Result<Vec<Box<dyn Check>>, String> {
+    let mut checks: Vec<Box<dyn Check>> = Vec::new();
+    for name in names {
+        let check = create_check(name, shared)?;
+        if checks
+            .iter()
+            .any(|existing| existing.name() == check.name())
+        {
+            return Err(format!("Check {} is listed twice in CHECKS", name));
+        }
+        for required in check.requires() {
+            if !checks.iter().any(|existing| existing.name() == *required) {
+                return Err(format!(
+                    "Check {} requires {} to run before it in CHECKS",
+                    name, required
+                ));
+            }
+        }
+        checks.push(check);
+    }
+    Ok(checks)
+}
+
+/// Runs the checks in order for a hostname.
+///
+/// # Arguments
+///
+/// * `checks`: A `&[Box<dyn Check>]` that holds the pipeline.
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+///
+/// # Returns
+///
+/// * A `Context` that holds the results of the checks and how long each of them took.
+pub fn run_pipeline(checks: &[Box<dyn Check>], hostname: &str) -> Context {
+    chaos::start_cycle();
+    let mut context = Context::new(hostname);
+    for check in checks {
+        log::trace!("Running check {}", check.name());
+        let started = Instant::now();
+        check.run(&mut context);
+        context
+            .durations
+            .push((check.name().to_string(), started.elapsed()));
+    }
+    context
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_build_pipeline() {
+        let shared = Arc::new(Shared::default());
+
+        // Assert that the default pipeline is valid
+        let checks = build_pipeline(&names(&DEFAULT_CHECKS), &shared).unwrap();
+        let built: Vec<&str> = checks.iter().map(|check| check.name()).collect();
+        assert_eq!(built, DEFAULT_CHECKS);
+
+        // Assert that checks can be left out and reordered
+        assert!(
+            build_pipeline(&names(&["fetch_wan_ip", "resolve_dns", "compare"]), &shared).is_ok()
+        );
+        assert!(build_pipeline(&names(&["prefix"]), &shared).is_ok());
+
+        // Assert that unknown, duplicate and misordered checks are rejected
+        assert!(build_pipeline(&names(&["resolve_dns", "compre"]), &shared).is_err());
+        assert!(build_pipeline(&names(&["prefix", "prefix"]), &shared).is_err());
+        assert!(
+            build_pipeline(&names(&["compare", "resolve_dns", "fetch_wan_ip"]), &shared).is_err()
+        );
+    }
+
+    /// Counts how often it runs.
+    struct Counter(Arc<AtomicU32>);
+
+    impl Check for Counter {
+        fn name(&self) -> &'static str {
+            "counter"
+        }
+
+        fn run(&self, _context: &mut Context) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_throttled() {
+        let runs = Arc::new(AtomicU32::new(0));
+        let check = Throttled::new(
+            Box::new(Counter(runs.clone())),
+            "DNSCHECKER_TEST_UNSET_INTERVAL",
+            Duration::from_millis(50),
+        );
+        let mut context = Context::new("home.example.com");
+
+        // Assert that the check runs first and then once per interval
+        check.run(&mut context);
+        check.run(&mut context);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        std::thread::sleep(Duration::from_millis(60));
+        check.run(&mut context);
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(check.name(), "counter");
+    }
+}
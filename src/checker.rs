@@ -0,0 +1,428 @@
+use crate::check::{self, Check, Context};
+use crate::config::{self, Config};
+use crate::dns::DnsResponse;
+use crate::events;
+use crate::passive;
+use crate::secrets;
+use crate::server::Shared;
+use crate::vars::{expand_apex, get_var_from_env, normalize_hostname};
+use chrono::{DateTime, Local};
+use futures_core::Stream;
+use shuteye::sleep;
+use std::env;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// The default time between two verifications of `Checker::watch`, the same as the binary.
+const DEFAULT_INTERVAL: Duration = check::CHECK_INTERVAL;
+
+/// The settings of the router API, required unless the WAN IP address is taken from other sources (see `passive::is_passive`).
+const ROUTER_API_VARS: [&str; 4] = ["API_KEY", "API_SECRET", "URL", "INTERFACE"];
+
+/// Whether `Checker::new` was called, as the settings it applies are shared by the whole process.
+static CREATED: AtomicBool = AtomicBool::new(false);
+
+/// The result of one verification, as returned to programs embedding the checker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckOutcome {
+    /// The monitored hostname.
+    pub hostname: String,
+    /// All IPv4 addresses from DNS in the order of the answer, empty if the hostname could not be resolved.
+    pub dns_ips: Vec<String>,
+    /// The details of the DNS response, only kept when the queries are sent directly (see "DNS_QUERY_MODE").
+    pub dns_response: Option<DnsResponse>,
+    /// The interface name and address of each configured interface that has an address.
+    pub wan_ips: Vec<(String, String)>,
+    /// The interface selected for the comparison.
+    pub interface: String,
+    /// The address of the selected interface, empty if it could not be retrieved.
+    pub wan_ip: String,
+    /// The source the WAN IP addresses were taken from.
+    pub wan_source: String,
+    /// Whether DNS matches the WAN IP address, `None` if they were not compared.
+    pub in_sync: Option<bool>,
+    /// When the verification finished.
+    pub checked_at: DateTime<Local>,
+}
+
+impl From<Context> for CheckOutcome {
+    fn from(context: Context) -> CheckOutcome {
+        CheckOutcome {
+            hostname: context.hostname,
+            dns_ips: context.dns_ips,
+            dns_response: context.dns_response,
+            wan_ips: context.wan_ips,
+            interface: context.interface,
+            wan_ip: context.wan_ip,
+            wan_source: context.wan_source,
+            in_sync: context.in_sync,
+            checked_at: Local::now(),
+        }
+    }
+}
+
+/// An event of `Checker::watch`, one per verification.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The verification did not change the state: DNS is still in sync, still out of sync, or was not compared.
+    Checked(CheckOutcome),
+    /// DNS stopped matching the WAN IP address, or did not match it on the first verification.
+    Mismatch(CheckOutcome),
+    /// DNS matches the WAN IP address again after a mismatch.
+    Recovered(CheckOutcome),
+}
+
+impl Event {
+    /// Returns the result of the verification that raised the event.
+    pub fn outcome(&self) -> &CheckOutcome {
+        match self {
+            Event::Checked(outcome) | Event::Mismatch(outcome) | Event::Recovered(outcome) => {
+                outcome
+            }
+        }
+    }
+}
+
+/// The checker engine, for programs that embed it instead of running the `dnschecker` binary.
+///
+/// The checker runs the same check pipeline as the binary (see `check::pipeline`), so it raises and clears alarms
+/// on the configured channels as well. It does not start the HTTP listener or the netlink watcher.
+///
+/// # Process environment
+///
+/// The settings are read from the environment of the process, so `Checker::new` sets the variables of its config
+/// with `std::env::set_var`, and the resolved secrets replace their references there. This changes the environment
+/// of the whole embedding program: create the checker before the program starts threads of its own, e.g. before
+/// starting an async runtime, as `set_var` is not thread-safe. Only one checker can be created per process,
+/// as a second one would share the settings of the first; `Checker::new` returns an `Err` when called again,
+/// also after a failed call, which has already applied its settings. Once created, the checker is `Send`
+/// and can run on any thread, as `watch` does.
+///
+/// ```no_run
+/// use dnschecker::{Checker, Event};
+/// use dnschecker::config::Config;
+/// use futures_util::StreamExt;
+///
+/// let config = Config::from_toml(
+///     r#"
+///     dns_hostname = "home.example.com"
+///     url = "https://192.168.1.1"
+///     api_key = "your_api_key"
+///     api_secret = "your_api_secret"
+///     interface = "igb3"
+///     "#,
+/// )
+/// .unwrap();
+/// let checker = Checker::new(config).unwrap();
+/// let runtime = tokio::runtime::Runtime::new().unwrap();
+/// runtime.block_on(async {
+///     let mut events = checker.watch();
+///     while let Some(event) = events.next().await {
+///         if let Event::Mismatch(outcome) = event {
+///             println!("{} points at {:?}", outcome.hostname, outcome.dns_ips);
+///         }
+///     }
+/// });
+/// ```
+pub struct Checker {
+    hostname: String,
+    checks: Vec<Box<dyn Check>>,
+    interval: Duration,
+    in_sync: Option<bool>,
+}
+
+impl Checker {
+    /// Creates a checker from a config.
+    ///
+    /// This function applies the config like a config file of the binary: it sets the settings as environment variables
+    /// of the process (see "Process environment" above), environment variables that are already set take precedence,
+    /// and references to cloud secret managers are resolved in place using the `secrets::resolve_references` function.
+    /// It then retrieves the hostname from "DNS_HOSTNAME", expands "@" to the zone in "DNS_ZONE",
+    /// normalizes it using the `normalize_hostname` function, verifies that the router API settings
+    /// "API_KEY", "API_SECRET", "URL" and "INTERFACE" are set if "WAN_IP_SOURCE" includes "api" (the default),
+    /// and assembles the check pipeline.
+    ///
+    /// # Arguments
+    ///
+    /// * `config`: A `Config` that holds the settings, e.g. from `Config::from_toml`. Use `Config::default()` to only use the environment.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<Checker, String>` that holds the checker.
+    /// * If a checker was created before, the config is invalid, "DNS_HOSTNAME" is missing or invalid,
+    ///   a router API setting is missing, or the pipeline cannot be set up, it returns an `Err` with a message.
+    pub fn new(config: Config) -> Result<Checker, String> {
+        claim_once(&CREATED)?;
+        config::apply(&config)?;
+        secrets::resolve_references()?;
+        let hostname = get_var_from_env("DNS_HOSTNAME")
+            .map_err(|_| "Environment variable DNS_HOSTNAME is not set".to_string())?;
+        let hostname = normalize_hostname(&expand_apex(
+            &hostname,
+            &env::var("DNS_ZONE").unwrap_or_default(),
+        ))?;
+        if !passive::is_passive() {
+            let missing: Vec<&str> = ROUTER_API_VARS
+                .into_iter()
+                .filter(|name| env::var(name).is_err())
+                .collect();
+            if !missing.is_empty() {
+                return Err(format!(
+                    "Environment variables {} must be set when WAN_IP_SOURCE includes api",
+                    missing.join(", ")
+                ));
+            }
+        }
+        let checks = check::pipeline(&Arc::new(Shared::default()))?;
+        Ok(Checker {
+            hostname,
+            checks,
+            interval: DEFAULT_INTERVAL,
+            in_sync: None,
+        })
+    }
+
+    /// Sets the time between two verifications of `watch`, 10 seconds by default.
+    pub fn with_interval(mut self, interval: Duration) -> Checker {
+        self.interval = interval;
+        self
+    }
+
+    /// Returns the time between two verifications of `watch`.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns the monitored hostname, normalized to its ASCII form.
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    /// Runs one verification.
+    ///
+    /// The verification is published on the event bus (see `events::publish`), so the metrics
+    /// and the history are kept like in the binary.
+    ///
+    /// This function blocks until the verification is done: the DNS lookups run on a runtime of their own
+    /// and the router API is called with a blocking HTTP client. In async code, use `watch`,
+    /// or call it through `tokio::task::spawn_blocking`.
+    ///
+    /// # Returns
+    ///
+    /// * A `CheckOutcome` that holds the result of the verification.
+    ///
+    /// # Panics
+    ///
+    /// * If it is called from within an async runtime, e.g. in a tokio task, as tokio does not allow blocking there.
+    pub fn check_once(&mut self) -> CheckOutcome {
+        let context = check::run_pipeline(&self.checks, &self.hostname);
+        events::publish(&events::Event::Checked(&context));
+        let outcome = CheckOutcome::from(context);
+        if outcome.in_sync.is_some() {
+            self.in_sync = outcome.in_sync;
+        }
+        outcome
+    }
+
+    /// Runs one verification and classifies it by the state before it, like every step of `watch`.
+    ///
+    /// # Returns
+    ///
+    /// * An `Event` that holds the result of the verification.
+    ///
+    /// # Panics
+    ///
+    /// * If it is called from within an async runtime, see `check_once`.
+    pub fn check_event(&mut self) -> Event {
+        let previous = self.in_sync;
+        transition(previous, self.check_once())
+    }
+
+    /// Runs a verification right away and then once per interval on a thread of its own, see `with_interval`.
+    ///
+    /// The checks are blocking, so they run on a separate thread (see `watch_blocking`) that sends the events
+    /// to the stream; the stream does not need a particular async runtime.
+    /// The stream never ends; drop it to stop watching, and the thread stops after its current verification.
+    ///
+    /// # Returns
+    ///
+    /// * An `impl Stream<Item = Event>` that yields an event per verification, see `Event`.
+    pub fn watch(mut self) -> impl Stream<Item = Event> + Unpin {
+        let (sender, receiver) = mpsc::channel(16);
+        std::thread::spawn(move || {
+            for event in self.watch_blocking() {
+                if sender.blocking_send(event).is_err() {
+                    break;
+                }
+            }
+        });
+        EventStream { receiver }
+    }
+
+    /// Runs a verification right away and then once per interval, see `with_interval`, on the calling thread.
+    ///
+    /// Every call to `next` sleeps until the next verification is due and runs it.
+    /// The iterator never ends; stop iterating to stop watching.
+    ///
+    /// # Returns
+    ///
+    /// * An `impl Iterator<Item = Event>` that yields an event per verification, see `Event`.
+    ///
+    /// # Panics
+    ///
+    /// * If it is iterated from within an async runtime, see `check_once`.
+    pub fn watch_blocking(&mut self) -> impl Iterator<Item = Event> + '_ {
+        let mut first = true;
+        std::iter::from_fn(move || {
+            if !first {
+                sleep(self.interval);
+            }
+            first = false;
+            Some(self.check_event())
+        })
+    }
+}
+
+/// Sets a flag that may only be set once, see `Checker::new`.
+///
+/// # Arguments
+///
+/// * `flag`: A `&AtomicBool` that records whether it was set before.
+///
+/// # Returns
+///
+/// * A `Result<(), String>` that is `Ok` the first time, or an `Err` with a message on every later call.
+fn claim_once(flag: &AtomicBool) -> Result<(), String> {
+    if flag.swap(true, Ordering::SeqCst) {
+        return Err(
+            "A checker was already created in this process, only one is supported".to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// The events of `Checker::watch`, received from the thread that runs the verifications.
+struct EventStream {
+    receiver: mpsc::Receiver<Event>,
+}
+
+impl Stream for EventStream {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Event>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// Classifies a verification by the state before it.
+///
+/// # Arguments
+///
+/// * `previous`: An `Option<bool>` that specifies whether DNS was in sync at the last compared verification, `None` if there was none.
+/// * `outcome`: A `CheckOutcome` that holds the result of the verification.
+///
+/// # Returns
+///
+/// * An `Event` that holds the outcome.
+fn transition(previous: Option<bool>, outcome: CheckOutcome) -> Event {
+    match (previous, outcome.in_sync) {
+        (Some(false), Some(true)) => Event::Recovered(outcome),
+        (Some(true) | None, Some(false)) => Event::Mismatch(outcome),
+        _ => Event::Checked(outcome),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outcome() {
+        let context = Context {
+            dns_ip: "1.1.1.1".to_string(),
+            dns_ips: vec!["1.1.1.1".to_string()],
+            wan_ips: vec![("igb0".to_string(), "1.1.1.1".to_string())],
+            interface: "igb0".to_string(),
+            wan_ip: "1.1.1.1".to_string(),
+            in_sync: Some(true),
+            ..Context::new("home.example.com")
+        };
+        let outcome = CheckOutcome::from(context);
+        assert_eq!(outcome.hostname, "home.example.com");
+        assert_eq!(outcome.dns_ips, ["1.1.1.1"]);
+        assert_eq!(outcome.interface, "igb0");
+        assert_eq!(outcome.in_sync, Some(true));
+    }
+
+    #[test]
+    fn test_claim_once() {
+        // Assert that only the first claim succeeds, with a flag of its own so the process-wide one stays untouched
+        let flag = AtomicBool::new(false);
+        assert_eq!(claim_once(&flag), Ok(()));
+        assert!(claim_once(&flag).is_err_and(|e| e.contains("already created")));
+        assert!(claim_once(&flag).is_err());
+    }
+
+    #[test]
+    fn test_watch() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Checker>();
+
+        // Assert that the verifications run on their own thread and arrive on the stream
+        let checker = Checker {
+            hostname: "home.example.com".to_string(),
+            checks: Vec::new(),
+            interval: Duration::from_millis(1),
+            in_sync: None,
+        };
+        let mut events = checker.watch();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        for _ in 0..2 {
+            let event = runtime.block_on(futures_util::StreamExt::next(&mut events));
+            assert_eq!(event.unwrap().outcome().hostname, "home.example.com");
+        }
+    }
+
+    #[test]
+    fn test_transition() {
+        let outcome = |in_sync| CheckOutcome {
+            in_sync,
+            ..CheckOutcome::from(Context::new("home.example.com"))
+        };
+
+        assert!(matches!(
+            transition(None, outcome(Some(false))),
+            Event::Mismatch(_)
+        ));
+        assert!(matches!(
+            transition(Some(true), outcome(Some(false))),
+            Event::Mismatch(_)
+        ));
+        assert!(matches!(
+            transition(Some(false), outcome(Some(true))),
+            Event::Recovered(_)
+        ));
+
+        // Assert that unchanged and uncompared results are plain checks
+        assert!(matches!(
+            transition(Some(false), outcome(Some(false))),
+            Event::Checked(_)
+        ));
+        assert!(matches!(
+            transition(None, outcome(Some(true))),
+            Event::Checked(_)
+        ));
+        assert!(matches!(
+            transition(Some(false), outcome(None)),
+            Event::Checked(_)
+        ));
+        assert_eq!(
+            transition(None, outcome(None)).outcome().hostname,
+            "home.example.com"
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use crate::dns;
+use crate::events;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::vars::{display_hostname, split_list};
+use ipnet::IpNet;
+use std::env;
+use std::net::IpAddr;
+
+/// Retrieves the client network the hostname is resolved for.
+///
+/// This function parses the address or network in the "DNS_CLIENT_SUBNET" environment variable (e.g. "203.0.113.0/24").
+/// If it is set, the hostname is resolved with EDNS Client Subnet, see `dns::query_addresses`.
+///
+/// # Returns
+///
+/// * A `Result<Option<IpNet>, String>` that holds the network, or `None` if the variable is not set.
+/// * If the value is invalid, it returns an `Err` with a message.
+pub fn client_subnet() -> Result<Option<IpNet>, String> {
+    match env::var("DNS_CLIENT_SUBNET") {
+        Ok(value) if !value.trim().is_empty() => {
+            parse_subnet(value.trim(), "DNS_CLIENT_SUBNET").map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Retrieves the client networks whose answers are compared.
+///
+/// This function parses the comma-separated list of addresses or networks in the "CLIENT_SUBNETS" environment variable
+/// (e.g. "203.0.113.0/24,198.51.100.0/24"). If it is not set, the list is empty and the comparison is disabled.
+///
+/// # Returns
+///
+/// * A `Result<Vec<IpNet>, String>` that holds the networks.
+/// * If an entry is invalid, or only one network is given, it returns an `Err` with a message.
+pub fn subnets() -> Result<Vec<IpNet>, String> {
+    let subnets = split_list(&env::var("CLIENT_SUBNETS").unwrap_or_default())
+        .iter()
+        .map(|entry| parse_subnet(entry, "CLIENT_SUBNETS"))
+        .collect::<Result<Vec<IpNet>, String>>()?;
+    if subnets.len() == 1 {
+        return Err("CLIENT_SUBNETS needs at least two networks to compare".to_string());
+    }
+    Ok(subnets)
+}
+
+/// Parses a client network.
+///
+/// # Arguments
+///
+/// * `entry`: A `&str` that holds a network (e.g. "203.0.113.0/24") or a single address, which is a network of one address.
+/// * `var`: A `&str` that specifies the environment variable the entry was taken from, for the error message.
+///
+/// # Returns
+///
+/// * A `Result<IpNet, String>` that holds the network with the host bits cleared, or an `Err` with a message.
+fn parse_subnet(entry: &str, var: &str) -> Result<IpNet, String> {
+    entry
+        .parse::<IpNet>()
+        .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+        .map(|network| network.trunc())
+        .map_err(|_| format!("Invalid address or network in {}: {}", var, entry))
+}
+
+/// Verifies that the hostname resolves to the same addresses from every client network.
+///
+/// This function returns immediately if no networks are given (see `subnets`).
+/// The hostname is resolved once per network with EDNS Client Subnet, using the resolver in "CLIENT_SUBNET_RESOLVER",
+/// defaulting to the upstream resolvers (see `dns::default_resolver`). The resolver must pass the network on to the name servers.
+///
+/// The answers agree if every network that got an answer got the same set of addresses; networks whose lookup failed are skipped.
+/// The result is compared with the state stored in the client subnet file ("CLIENT_SUBNET_FILE", defaulting to "client_subnet_state" in the state directory),
+/// and a "client_subnet" message is sent when the answers start or stop differing, e.g. when a regional view of a GeoDNS record was not updated.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `subnets`: A `&[IpNet]` that holds the client networks.
+pub fn check_client_subnets(hostname: &str, subnets: &[IpNet]) {
+    if subnets.is_empty() {
+        return;
+    }
+    let resolver = env::var("CLIENT_SUBNET_RESOLVER").unwrap_or_else(|_| dns::default_resolver());
+    let client_subnet_file = state_path("CLIENT_SUBNET_FILE", "client_subnet_state");
+
+    let mut answers = Vec::new();
+    for subnet in subnets {
+        match dns::query_addresses(hostname, &resolver, Some(*subnet)) {
+            Ok(response) => {
+                let mut addresses = response.addresses();
+                addresses.sort();
+                log::debug!("{} resolves to {:?} from {}", hostname, addresses, subnet);
+                answers.push((*subnet, addresses));
+            }
+            Err(err) => log::warn!("{}", err),
+        }
+    }
+    if answers.len() < 2 {
+        log::debug!("Too few client networks got an answer, skipping client subnet check");
+        return;
+    }
+
+    let agree = answers.windows(2).all(|pair| pair[0].1 == pair[1].1);
+    let state = if agree { "ok" } else { "mismatch" };
+    let previous = read_state(&client_subnet_file);
+    if previous == state {
+        return;
+    }
+    if previous.is_empty() && state == "ok" {
+        log::info!("Answers for {} agree across client networks", hostname);
+    } else {
+        let text = client_subnet_message(hostname, &answers, agree);
+        log::info!("{}", text);
+        let message = Message::new("client_subnet", &text)
+            .with_field("hostname", hostname)
+            .with_field("answers", &format_answers(&answers, "; "));
+        if !events::alert(&message) {
+            log::warn!("Failed to send client subnet message");
+            return;
+        }
+    }
+    write_state(&client_subnet_file, state);
+}
+
+/// Formats the answer of each client network, e.g. "203.0.113.0/24: 192.0.2.1".
+///
+/// # Arguments
+///
+/// * `answers`: A `&[(IpNet, Vec<String>)]` that holds the addresses each network got.
+/// * `separator`: A `&str` that separates the networks.
+///
+/// # Returns
+///
+/// * A `String` that holds the answers; a network without addresses is shown with "no answer".
+fn format_answers(answers: &[(IpNet, Vec<String>)], separator: &str) -> String {
+    answers
+        .iter()
+        .map(|(subnet, addresses)| {
+            if addresses.is_empty() {
+                format!("{}: no answer", subnet)
+            } else {
+                format!("{}: {}", subnet, addresses.join(", "))
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(separator)
+}
+
+/// Builds the text of the client subnet message.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `answers`: A `&[(IpNet, Vec<String>)]` that holds the addresses each network got.
+/// * `agree`: A `bool` that indicates whether the answers agree.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message.
+fn client_subnet_message(hostname: &str, answers: &[(IpNet, Vec<String>)], agree: bool) -> String {
+    let headline = if agree {
+        "DNS answers agree across client networks again"
+    } else {
+        "DNS answers differ between client networks!"
+    };
+    format!(
+        "{}\nHostname: {}\n{}",
+        headline,
+        display_hostname(hostname),
+        format_answers(answers, "\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_subnet() {
+        assert_eq!(
+            parse_subnet("203.0.113.7/24", "CLIENT_SUBNETS"),
+            Ok("203.0.113.0/24".parse().unwrap())
+        );
+        assert_eq!(
+            parse_subnet("2001:db8::1", "CLIENT_SUBNETS"),
+            Ok("2001:db8::1/128".parse().unwrap())
+        );
+        assert!(parse_subnet("203.0.113.0/33", "CLIENT_SUBNETS").is_err());
+    }
+
+    #[test]
+    fn test_client_subnet_message() {
+        let answers = vec![
+            (
+                "203.0.113.0/24".parse().unwrap(),
+                vec!["192.0.2.1".to_string()],
+            ),
+            ("198.51.100.0/24".parse().unwrap(), vec![]),
+        ];
+        let result = client_subnet_message("home.example.com", &answers, false);
+        assert!(result.starts_with("DNS answers differ between client networks!"));
+        assert!(result.contains("203.0.113.0/24: 192.0.2.1\n"));
+        assert!(result.ends_with("198.51.100.0/24: no answer"));
+    }
+}
@@ -0,0 +1,555 @@
+use crate::chart::{self, Sample};
+use crate::digest;
+use crate::events;
+use crate::http;
+use crate::notify::Message;
+use crate::vars::{display_hostname, parse_duration, split_list};
+use chrono::{DateTime, Local};
+use serde_json::{json, Value};
+use shuteye::sleep;
+use std::collections::{BTreeMap, VecDeque};
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Another checker whose status is shown in the cluster view.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peer {
+    /// The name of the instance in the view, e.g. the site it monitors.
+    pub name: String,
+    /// The base URL of its HTTP listener, e.g. "https://office.example.com:8080".
+    pub url: String,
+}
+
+impl Peer {
+    /// Parses a peer.
+    ///
+    /// The entry is "name=url", or just the URL, in which case the instance is named after the host of the URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry`: A `&str` that holds the entry.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<Peer, String>` that holds the peer, or an `Err` with a message if the URL is invalid.
+    pub fn parse(entry: &str) -> Result<Peer, String> {
+        let (name, url) = match entry.split_once('=') {
+            Some((name, url)) => (Some(name.trim()), url.trim()),
+            None => (None, entry.trim()),
+        };
+        let parsed = url::Url::parse(url)
+            .ok()
+            .filter(|parsed| matches!(parsed.scheme(), "http" | "https"))
+            .ok_or_else(|| format!("Invalid URL in CLUSTER_PEERS: {}", url))?;
+        let name = match name {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => parsed.host_str().unwrap_or(url).to_string(),
+        };
+        Ok(Peer {
+            name,
+            url: url.trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+/// Retrieves the other instances of the cluster view.
+///
+/// This function parses the comma-separated list of peers in the "CLUSTER_PEERS" environment variable,
+/// e.g. "home=https://home.example.com:8080,office=https://office.example.com:8080", see `Peer::parse`.
+/// If it is not set, the list is empty and the checker does not run as cluster view.
+///
+/// # Returns
+///
+/// * A `Result<Vec<Peer>, String>` that holds the peers, or an `Err` with a message if an entry is invalid
+///   or two peers have the same name.
+pub fn peers() -> Result<Vec<Peer>, String> {
+    let peers = split_list(&env::var("CLUSTER_PEERS").unwrap_or_default())
+        .iter()
+        .map(|entry| Peer::parse(entry))
+        .collect::<Result<Vec<Peer>, String>>()?;
+    for (index, peer) in peers.iter().enumerate() {
+        if peers[..index].iter().any(|other| other.name == peer.name) {
+            return Err(format!("Duplicate name in CLUSTER_PEERS: {}", peer.name));
+        }
+    }
+    Ok(peers)
+}
+
+/// The latest poll of a peer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerStatus {
+    /// The polled instance.
+    pub peer: Peer,
+    /// The status the instance served on "/status" (see `server::Shared::status`) when it last answered.
+    pub status: Option<Value>,
+    /// When the instance last answered.
+    pub seen: Option<DateTime<Local>>,
+    /// The error of the last poll, `None` if the instance answered.
+    pub error: Option<String>,
+}
+
+impl PeerStatus {
+    /// Returns the latest verification of the instance, as served in the "check" field of its status.
+    fn check(&self) -> Option<&Value> {
+        self.status
+            .as_ref()
+            .and_then(|status| status.get("check"))
+            .filter(|check| check.is_object())
+    }
+
+    /// Classifies the instance: "unreachable", "pending" before its first verification, "in_sync" or "out_of_sync".
+    pub fn state(&self) -> &'static str {
+        if self.error.is_some() {
+            return "unreachable";
+        }
+        match self.check().and_then(|check| check["in_sync"].as_bool()) {
+            Some(true) => "in_sync",
+            Some(false) => "out_of_sync",
+            None => "pending",
+        }
+    }
+
+    /// Converts the poll to a sample of the digest chart, with the total latency of the latest verification.
+    fn sample(&self) -> Sample {
+        let in_sync = match self.state() {
+            "in_sync" => Some(true),
+            "out_of_sync" => Some(false),
+            _ => None,
+        };
+        let latency_ms = self
+            .check()
+            .filter(|_| in_sync.is_some())
+            .and_then(|check| check["latencies_ms"].as_object())
+            .filter(|latencies| !latencies.is_empty())
+            .map(|latencies| latencies.values().filter_map(Value::as_u64).sum());
+        Sample {
+            in_sync,
+            latency_ms,
+        }
+    }
+
+    /// Returns the names of the dependencies that were failing when the instance last answered.
+    fn failing(&self) -> Vec<String> {
+        self.status
+            .as_ref()
+            .and_then(|status| status["dependencies"].as_object())
+            .map(|dependencies| {
+                dependencies
+                    .iter()
+                    .filter(|(_, health)| health["healthy"] == Value::Bool(false))
+                    .map(|(name, _)| name.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Converts the poll to JSON, with the time of the last answer in RFC 3339.
+    fn to_json(&self) -> Value {
+        json!({
+            "name": self.peer.name,
+            "url": self.peer.url,
+            "state": self.state(),
+            "seen": self.seen.map(|seen| seen.to_rfc3339()),
+            "error": self.error,
+            "status": self.status,
+        })
+    }
+
+    /// Describes the instance in one line, e.g. "office: in sync, home.example.com is 198.51.100.4".
+    fn line(&self) -> String {
+        let field = |name: &str| {
+            self.check()
+                .and_then(|check| check[name].as_str())
+                .unwrap_or("")
+                .to_string()
+        };
+        let mut line = match self.state() {
+            "unreachable" => format!(
+                "{}: unreachable ({})",
+                self.peer.name,
+                self.error.as_deref().unwrap_or("")
+            ),
+            "pending" => format!("{}: no verification yet", self.peer.name),
+            "in_sync" => format!(
+                "{}: in sync, {} is {}",
+                self.peer.name,
+                display_hostname(&field("hostname")),
+                field("dns_ip")
+            ),
+            _ => format!(
+                "{}: OUT OF SYNC, {} is {} but the WAN IP is {}",
+                self.peer.name,
+                display_hostname(&field("hostname")),
+                field("dns_ip"),
+                field("wan_ip")
+            ),
+        };
+        let failing = self.failing();
+        if !failing.is_empty() {
+            line.push_str(&format!(", failing: {}", failing.join(", ")));
+        }
+        line
+    }
+}
+
+/// The most polls of a peer kept for the digest chart, about a week at the default interval.
+const MAX_TRAIL: usize = 10_000;
+
+/// The latest poll of each peer, as kept by the cluster view, and the polls since the last digest.
+#[derive(Debug, Default)]
+pub struct ClusterView {
+    peers: Mutex<BTreeMap<String, PeerStatus>>,
+    trails: Mutex<BTreeMap<String, VecDeque<Sample>>>,
+}
+
+impl ClusterView {
+    /// Stores the result of a poll, keeping the status of the last answer when the peer is unreachable.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer`: A `&Peer` that specifies the polled instance.
+    /// * `result`: A `Result<Value, String>` that holds the status it served, or the error of the poll.
+    pub fn record(&self, peer: &Peer, result: Result<Value, String>) {
+        let mut peers = self.peers.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = peers.entry(peer.name.clone()).or_insert(PeerStatus {
+            peer: peer.clone(),
+            status: None,
+            seen: None,
+            error: None,
+        });
+        match result {
+            Ok(status) => {
+                entry.status = Some(status);
+                entry.seen = Some(Local::now());
+                entry.error = None;
+            }
+            Err(e) => entry.error = Some(e),
+        }
+        let sample = entry.sample();
+        drop(peers);
+        let mut trails = self.trails.lock().unwrap_or_else(|e| e.into_inner());
+        let trail = trails.entry(peer.name.clone()).or_default();
+        if trail.len() >= MAX_TRAIL {
+            trail.pop_front();
+        }
+        trail.push_back(sample);
+    }
+
+    /// Returns the polls of each peer since the last call, ordered by name, and starts new trails.
+    pub fn take_trails(&self) -> Vec<Vec<Sample>> {
+        let mut trails = self.trails.lock().unwrap_or_else(|e| e.into_inner());
+        std::mem::take(&mut *trails)
+            .into_values()
+            .map(Vec::from)
+            .collect()
+    }
+
+    /// Returns the latest poll of each peer, ordered by name.
+    pub fn snapshot(&self) -> Vec<PeerStatus> {
+        let peers = self.peers.lock().unwrap_or_else(|e| e.into_inner());
+        peers.values().cloned().collect()
+    }
+}
+
+/// Converts the polls to the combined status served on "/cluster".
+///
+/// # Arguments
+///
+/// * `statuses`: A `&[PeerStatus]` that holds the latest poll of each peer.
+///
+/// # Returns
+///
+/// * A `Value` that holds the number of instances per state in "summary" and the poll of each in "instances".
+pub fn to_json(statuses: &[PeerStatus]) -> Value {
+    let mut summary: BTreeMap<&str, usize> = ["in_sync", "out_of_sync", "pending", "unreachable"]
+        .into_iter()
+        .map(|state| (state, 0))
+        .collect();
+    for status in statuses {
+        *summary.entry(status.state()).or_default() += 1;
+    }
+    json!({
+        "summary": summary,
+        "instances": statuses.iter().map(PeerStatus::to_json).collect::<Vec<Value>>(),
+    })
+}
+
+/// Describes the polls, one line per instance, for the `cluster` command and the digest.
+///
+/// # Arguments
+///
+/// * `statuses`: A `&[PeerStatus]` that holds the latest poll of each peer.
+///
+/// # Returns
+///
+/// * A `Vec<String>` that holds the lines, starting with a summary, e.g. "2 of 3 instances in sync".
+pub fn dashboard_lines(statuses: &[PeerStatus]) -> Vec<String> {
+    let in_sync = statuses
+        .iter()
+        .filter(|status| status.state() == "in_sync")
+        .count();
+    let mut lines = vec![format!(
+        "{} of {} instances in sync",
+        in_sync,
+        statuses.len()
+    )];
+    lines.extend(statuses.iter().map(PeerStatus::line));
+    lines
+}
+
+/// Retrieves the status of an instance from its "/status" endpoint.
+///
+/// The instance must permit the address of the cluster view in its "HTTP_ALLOW" list,
+/// and accept the token as its "HTTP_TOKEN" unless it serves the status with "HTTP_PUBLIC_STATUS".
+///
+/// # Arguments
+///
+/// * `url`: A `&str` that specifies the base URL of the instance.
+/// * `token`: An `Option<&str>` that holds the token sent as a bearer token, if any.
+///
+/// # Returns
+///
+/// * A `Result<Value, String>` that holds the status, or an `Err` with a message if the instance does not answer with JSON.
+pub fn fetch_status(url: &str, token: Option<&str>) -> Result<Value, String> {
+    let mut request = http::client()?.get(format!("{}/status", url));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request
+        .send()
+        .map_err(|e| format!("Failed to fetch status: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Instance answered with status {}",
+            response.status()
+        ));
+    }
+    response
+        .json::<Value>()
+        .map_err(|e| format!("Invalid status: {}", e))
+}
+
+/// Polls every peer at once and stores the results in the view.
+///
+/// The requests carry the token in the "CLUSTER_TOKEN" environment variable, if it is set (see `fetch_status`).
+///
+/// # Arguments
+///
+/// * `peers`: A `&[Peer]` that holds the peers.
+/// * `view`: A `&ClusterView` that stores the results.
+pub fn poll(peers: &[Peer], view: &ClusterView) {
+    let token = env::var("CLUSTER_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty());
+    let token = token.as_deref();
+    std::thread::scope(|scope| {
+        for peer in peers {
+            scope.spawn(move || {
+                let result = fetch_status(&peer.url, token);
+                if let Err(e) = &result {
+                    log::warn!("Failed to poll {}: {}", peer.name, e);
+                }
+                view.record(peer, result);
+            });
+        }
+    });
+}
+
+/// Retrieves a duration of the cluster view from the environment.
+///
+/// # Arguments
+///
+/// * `name`: A `&str` that specifies the environment variable.
+/// * `default`: A `Duration` that is used if it is not set or invalid.
+fn duration_var(name: &str, default: Duration) -> Duration {
+    env::var(name)
+        .ok()
+        .and_then(|value| parse_duration(&value))
+        .unwrap_or(default)
+}
+
+/// Runs the checker as cluster view of other instances.
+///
+/// This function polls the "/status" endpoint of every peer (see `poll`) every "CLUSTER_INTERVAL" (defaulting to one minute)
+/// until a signal is received. The combined status is served on "/cluster" by the HTTP listener.
+///
+/// Every "CLUSTER_DIGEST_INTERVAL" (e.g. "12h", defaulting to "1d", "0" disables it) a "cluster_digest" message
+/// with one line per instance (see `dashboard_lines`) is sent, starting one interval after startup.
+/// With "DIGEST_CHART" set to "true", the digest carries a chart of the polls since the previous one, see `send_digest`.
+///
+/// # Arguments
+///
+/// * `peers`: A `&[Peer]` that holds the peers, from "CLUSTER_PEERS".
+/// * `view`: A `&ClusterView` that stores the polls, shared with the HTTP listener.
+/// * `sig_received`: A `&AtomicBool` that is set when the process should stop.
+/// * `tick`: A `&dyn Fn()` that is called on every iteration, e.g. to update the liveness probe.
+pub fn run_cluster(peers: &[Peer], view: &ClusterView, sig_received: &AtomicBool, tick: &dyn Fn()) {
+    let interval = duration_var("CLUSTER_INTERVAL", Duration::from_secs(60));
+    let digest_interval =
+        duration_var("CLUSTER_DIGEST_INTERVAL", Duration::from_secs(24 * 60 * 60));
+    let names: Vec<&str> = peers.iter().map(|peer| peer.name.as_str()).collect();
+    log::info!("Running as cluster view of {}", names.join(", "));
+
+    let mut next_poll = Instant::now();
+    let mut next_digest = Instant::now() + digest_interval;
+    while !sig_received.load(Ordering::SeqCst) {
+        tick();
+        if Instant::now() >= next_poll {
+            poll(peers, view);
+            next_poll = Instant::now() + interval;
+        }
+        if !digest_interval.is_zero() && Instant::now() >= next_digest {
+            if !send_digest(&view.snapshot(), &view.take_trails()) {
+                log::warn!("Failed to send cluster digest");
+            }
+            next_digest = Instant::now() + digest_interval;
+        }
+        sleep(Duration::new(1, 0));
+    }
+}
+
+/// Sends the digest of the cluster as a "cluster_digest" message.
+///
+/// If "DIGEST_CHART" is "true", the message carries a PNG chart of the polls (see `chart::sparkline_png`),
+/// with a row per instance in the order of the lines, which channels that support images send after the text.
+///
+/// # Arguments
+///
+/// * `statuses`: A `&[PeerStatus]` that holds the latest poll of each peer.
+/// * `trails`: A `&[Vec<Sample>]` that holds the polls of each peer since the previous digest, in the same order.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether the message was sent.
+fn send_digest(statuses: &[PeerStatus], trails: &[Vec<Sample>]) -> bool {
+    let chart = digest::chart_enabled() && trails.iter().any(|trail| !trail.is_empty());
+    let mut text = format!(
+        "DNS checker cluster digest\n{}",
+        dashboard_lines(statuses).join("\n")
+    );
+    if chart {
+        text.push_str("\nChart: a row per instance in this order, green in sync, red out of sync, grey unknown; bar height is the check latency.");
+    }
+    log::info!("{}", text);
+    let unhealthy = statuses
+        .iter()
+        .filter(|status| status.state() != "in_sync")
+        .count();
+    let mut message = Message::new("cluster_digest", &text)
+        .with_field("instances", &statuses.len().to_string())
+        .with_field("unhealthy", &unhealthy.to_string());
+    if chart {
+        match chart::sparkline_png(trails) {
+            Ok(png) => message = message.with_image(png),
+            Err(e) => log::warn!("{}", e),
+        }
+    }
+    events::alert(&message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    fn peer(name: &str) -> Peer {
+        Peer {
+            name: name.to_string(),
+            url: format!("https://{}.example.com:8080", name),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Peer::parse("home=https://home.example.com:8080/").unwrap(),
+            peer("home")
+        );
+        assert_eq!(
+            Peer::parse(" http://192.0.2.1:8080").unwrap(),
+            Peer {
+                name: "192.0.2.1".to_string(),
+                url: "http://192.0.2.1:8080".to_string(),
+            }
+        );
+        assert!(Peer::parse("home=home.example.com:8080").is_err());
+        assert!(Peer::parse("home=ftp://home.example.com").is_err());
+    }
+
+    #[test]
+    fn test_view() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/status")
+                .header("Authorization", "Bearer cluster");
+            then.status(200).json_body(json!({
+                "instance": "office",
+                "check": {
+                    "hostname": "office.example.com",
+                    "dns_ip": "198.51.100.4",
+                    "wan_ip": "203.0.113.7",
+                    "in_sync": false,
+                },
+                "dependencies": {
+                    "resolver google": { "healthy": true },
+                    "source api": { "healthy": false },
+                },
+            }));
+        });
+        let office = Peer::parse(&format!("office={}", server.base_url())).unwrap();
+        let home = peer("home");
+        let view = ClusterView::default();
+        view.record(
+            &home,
+            Ok(json!({
+                "check": {
+                    "hostname": "home.example.com",
+                    "dns_ip": "192.0.2.1",
+                    "in_sync": true,
+                    "latencies_ms": { "resolve_dns": 30, "fetch_wan_ip": 12 },
+                },
+            })),
+        );
+        view.record(&office, fetch_status(&office.url, Some("cluster")));
+
+        let statuses = view.snapshot();
+        assert_eq!(
+            dashboard_lines(&statuses),
+            [
+                "1 of 2 instances in sync",
+                "home: in sync, home.example.com is 192.0.2.1",
+                "office: OUT OF SYNC, office.example.com is 198.51.100.4 but the WAN IP is 203.0.113.7, \
+                 failing: source api",
+            ]
+        );
+
+        // Assert that an unreachable peer keeps the status of its last answer
+        view.record(&home, Err("connection refused".to_string()));
+        let statuses = view.snapshot();
+        assert_eq!(statuses[0].state(), "unreachable");
+        assert!(statuses[0].status.is_some());
+        let combined = to_json(&statuses);
+        assert_eq!(
+            combined["summary"],
+            json!({ "in_sync": 0, "out_of_sync": 1, "pending": 0, "unreachable": 1 })
+        );
+        assert_eq!(combined["instances"][1]["status"]["instance"], "office");
+
+        // Assert that every poll is kept for the digest chart until it is taken
+        let sample = |in_sync, latency_ms| Sample {
+            in_sync,
+            latency_ms,
+        };
+        assert_eq!(
+            view.take_trails(),
+            [
+                vec![sample(Some(true), Some(42)), sample(None, None)],
+                vec![sample(Some(false), None)],
+            ]
+        );
+        assert!(view.take_trails().is_empty());
+    }
+}
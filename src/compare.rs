@@ -0,0 +1,321 @@
+use crate::planned::{self, PlannedChange};
+use crate::vars::{normalize_hostname, split_list};
+use chrono::{DateTime, FixedOffset};
+use ipnet::IpNet;
+use std::collections::BTreeMap;
+use std::env;
+use std::net::IpAddr;
+
+/// How the addresses from DNS are matched against the WAN IP addresses.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComparePolicy {
+    /// DNS holds exactly one address, the selected WAN IP address.
+    Exact,
+    /// The selected WAN IP address is one of the addresses from DNS, e.g. for round-robin records.
+    AnyOf,
+    /// Every address from DNS is a WAN IP address of a configured interface or in the given networks.
+    Subset(Vec<IpNet>),
+    /// Every address from DNS is in the networks accepted for the hostname, whatever the WAN IP address is.
+    Accepted(Vec<IpNet>),
+}
+
+impl ComparePolicy {
+    /// Creates the policy from the environment.
+    ///
+    /// This function retrieves the value of the "COMPARE_POLICY" environment variable: "exact" (the default), "any_of" or "subset".
+    /// For the "subset" policy, the additional allowed addresses are taken from the comma-separated list of addresses
+    /// or networks in "COMPARE_ALLOWED" (e.g. "203.0.113.10" or "203.0.113.0/28").
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<ComparePolicy, String>` that holds the policy, or an `Err` with a message if a value is invalid.
+    pub fn from_env() -> Result<ComparePolicy, String> {
+        match env::var("COMPARE_POLICY").as_deref() {
+            Err(_) | Ok("exact") => Ok(ComparePolicy::Exact),
+            Ok("any_of") => Ok(ComparePolicy::AnyOf),
+            Ok("subset") => split_list(&env::var("COMPARE_ALLOWED").unwrap_or_default())
+                .iter()
+                .map(|entry| {
+                    entry
+                        .parse::<IpNet>()
+                        .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                        .map_err(|_| {
+                            format!("Invalid address or network in COMPARE_ALLOWED: {}", entry)
+                        })
+                })
+                .collect::<Result<Vec<IpNet>, String>>()
+                .map(ComparePolicy::Subset),
+            Ok(other) => Err(format!(
+                "Unknown COMPARE_POLICY {}, expected exact, any_of or subset",
+                other
+            )),
+        }
+    }
+
+    /// Checks whether the addresses from DNS are in sync with the WAN IP addresses.
+    ///
+    /// # Arguments
+    ///
+    /// * `dns_ips`: A `&[String]` that holds the addresses from DNS.
+    /// * `wan_ip`: A `&str` that specifies the selected WAN IP address.
+    /// * `wan_ips`: A `&[(String, String)]` that holds the interface name and address of each configured interface.
+    ///
+    /// # Returns
+    ///
+    /// * A `bool` that indicates whether the addresses match under the policy. Without addresses from DNS they never match.
+    pub fn in_sync(&self, dns_ips: &[String], wan_ip: &str, wan_ips: &[(String, String)]) -> bool {
+        match self {
+            ComparePolicy::Exact => dns_ips.len() == 1 && dns_ips[0] == wan_ip,
+            ComparePolicy::AnyOf => dns_ips.iter().any(|ip| ip == wan_ip),
+            ComparePolicy::Accepted(accepted) => {
+                !dns_ips.is_empty()
+                    && dns_ips.iter().all(|dns_ip| {
+                        dns_ip
+                            .parse::<IpAddr>()
+                            .is_ok_and(|ip| accepted.iter().any(|network| network.contains(&ip)))
+                    })
+            }
+            ComparePolicy::Subset(allowed) => {
+                !dns_ips.is_empty()
+                    && dns_ips.iter().all(|dns_ip| {
+                        dns_ip == wan_ip
+                            || wan_ips.iter().any(|(_, ip)| ip == dns_ip)
+                            || dns_ip
+                                .parse::<IpAddr>()
+                                .is_ok_and(|ip| allowed.iter().any(|network| network.contains(&ip)))
+                    })
+            }
+        }
+    }
+
+    /// Checks whether the addresses from DNS are in sync with the WAN IP addresses, comparing IPv4 and IPv6 separately.
+    ///
+    /// For a dual-stack hostname (see `dns::address_family`), the A records are compared with the IPv4 addresses
+    /// of the interfaces and the AAAA records with their IPv6 addresses, each under the policy, and both must be in sync.
+    /// A family the router reports no address of is not compared, e.g. when the WAN IP addresses come from a file.
+    /// For a single family, this is the same as `in_sync`.
+    ///
+    /// # Arguments
+    ///
+    /// * `dns_ips`: A `&[String]` that holds the addresses from DNS.
+    /// * `wan_ip`: A `&str` that specifies the selected WAN IP address.
+    /// * `wan_ips`: A `&[(String, String)]` that holds the interface name and address of each configured interface.
+    ///
+    /// # Returns
+    ///
+    /// * A `bool` that indicates whether the addresses of every compared family match under the policy.
+    pub fn in_sync_per_family(
+        &self,
+        dns_ips: &[String],
+        wan_ip: &str,
+        wan_ips: &[(String, String)],
+    ) -> bool {
+        let mut in_sync = true;
+        for ipv6 in [false, true] {
+            let of_family = |ip: &str| ip.contains(':') == ipv6;
+            let family_wan_ips: Vec<(String, String)> = wan_ips
+                .iter()
+                .filter(|(_, ip)| of_family(ip))
+                .cloned()
+                .collect();
+            let selected = if of_family(wan_ip) {
+                wan_ip
+            } else {
+                match family_wan_ips.first() {
+                    Some((_, ip)) => ip.as_str(),
+                    None => continue,
+                }
+            };
+            if selected.is_empty() {
+                continue;
+            }
+            let family_dns_ips: Vec<String> =
+                dns_ips.iter().filter(|ip| of_family(ip)).cloned().collect();
+            in_sync &= self.in_sync(&family_dns_ips, selected, &family_wan_ips);
+        }
+        in_sync
+    }
+}
+
+/// The comparison policy of every hostname.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComparePolicies {
+    /// The policy of hostnames without accepted addresses, see `ComparePolicy::from_env`.
+    default: ComparePolicy,
+    /// The policy of each hostname with accepted addresses, by normalized hostname.
+    hosts: BTreeMap<String, ComparePolicy>,
+    /// The planned changes, whose new address is accepted during their window, see `planned::planned_changes`.
+    planned: Vec<PlannedChange>,
+}
+
+impl ComparePolicies {
+    /// Creates the policies from the environment.
+    ///
+    /// This function retrieves the default policy using the `ComparePolicy::from_env` function.
+    /// It then parses the comma-separated list of "hostname=address" entries in the "ACCEPTED_IPS" environment variable,
+    /// e.g. "home.example.com=203.0.113.10,home.example.com=198.51.100.0/24" for a primary and a backup uplink.
+    /// The addresses and networks of each hostname form its `ComparePolicy::Accepted` policy.
+    /// The planned changes are taken from "PLANNED_CHANGES" using the `planned::planned_changes` function.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<ComparePolicies, String>` that holds the policies, or an `Err` with a message if an entry is invalid.
+    pub fn from_env() -> Result<ComparePolicies, String> {
+        let mut hosts: BTreeMap<String, Vec<IpNet>> = BTreeMap::new();
+        for entry in split_list(&env::var("ACCEPTED_IPS").unwrap_or_default()) {
+            let invalid = || {
+                format!(
+                    "Invalid entry in ACCEPTED_IPS, expected hostname=address: {}",
+                    entry
+                )
+            };
+            let (hostname, address) = entry.split_once('=').ok_or_else(invalid)?;
+            let hostname = normalize_hostname(hostname.trim()).map_err(|_| invalid())?;
+            let address = address.trim();
+            let network = address
+                .parse::<IpNet>()
+                .or_else(|_| address.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| invalid())?;
+            hosts.entry(hostname).or_default().push(network);
+        }
+        Ok(ComparePolicies {
+            default: ComparePolicy::from_env()?,
+            hosts: hosts
+                .into_iter()
+                .map(|(hostname, networks)| (hostname, ComparePolicy::Accepted(networks)))
+                .collect(),
+            planned: planned::planned_changes()?,
+        })
+    }
+
+    /// Returns the policy of a hostname.
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname`: A `&str` that specifies the normalized hostname.
+    pub fn for_hostname(&self, hostname: &str) -> &ComparePolicy {
+        self.hosts.get(hostname).unwrap_or(&self.default)
+    }
+
+    /// Checks whether the addresses from DNS are the new address of a planned change of the hostname.
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname`: A `&str` that specifies the normalized hostname.
+    /// * `dns_ips`: A `&[String]` that holds the addresses from DNS.
+    /// * `now`: A `DateTime<FixedOffset>` that specifies the time of the verification.
+    ///
+    /// # Returns
+    ///
+    /// * A `bool` that indicates whether the window of a change of the hostname is open and DNS already shows it,
+    ///   so the new address is accepted even though the WAN IP address has not moved yet.
+    pub fn accepts_planned(
+        &self,
+        hostname: &str,
+        dns_ips: &[String],
+        now: DateTime<FixedOffset>,
+    ) -> bool {
+        planned::active_change(&self.planned, hostname, now)
+            .is_some_and(|change| change.observed(dns_ips))
+    }
+
+    /// Returns the planned changes, see `planned::confirm_change`.
+    pub fn planned_changes(&self) -> &[PlannedChange] {
+        &self.planned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ips(list: &[&str]) -> Vec<String> {
+        list.iter().map(|ip| ip.to_string()).collect()
+    }
+
+    #[test]
+    fn test_in_sync() {
+        let wan_ips = vec![
+            ("igb3".to_string(), "1.1.1.1".to_string()),
+            ("igb4".to_string(), "2.2.2.2".to_string()),
+        ];
+
+        // Assert that the exact policy only accepts a single matching record
+        let policy = ComparePolicy::Exact;
+        assert!(policy.in_sync(&ips(&["1.1.1.1"]), "1.1.1.1", &wan_ips));
+        assert!(!policy.in_sync(&ips(&["1.1.1.1", "3.3.3.3"]), "1.1.1.1", &wan_ips));
+        assert!(!policy.in_sync(&[], "1.1.1.1", &wan_ips));
+
+        // Assert that the any-of policy accepts round-robin records containing the WAN IP address
+        let policy = ComparePolicy::AnyOf;
+        assert!(policy.in_sync(&ips(&["3.3.3.3", "1.1.1.1"]), "1.1.1.1", &wan_ips));
+        assert!(!policy.in_sync(&ips(&["3.3.3.3"]), "1.1.1.1", &wan_ips));
+
+        // Assert that the subset policy accepts records within the WAN addresses and allowed networks
+        let policy = ComparePolicy::Subset(vec!["203.0.113.0/28".parse().unwrap()]);
+        assert!(policy.in_sync(&ips(&["2.2.2.2", "203.0.113.5"]), "1.1.1.1", &wan_ips));
+        assert!(!policy.in_sync(&ips(&["1.1.1.1", "203.0.113.20"]), "1.1.1.1", &wan_ips));
+        assert!(!policy.in_sync(&[], "1.1.1.1", &wan_ips));
+
+        // Assert that the accepted policy ignores the WAN IP address
+        let policy = ComparePolicy::Accepted(vec!["203.0.113.0/28".parse().unwrap()]);
+        assert!(policy.in_sync(&ips(&["203.0.113.5"]), "1.1.1.1", &wan_ips));
+        assert!(!policy.in_sync(&ips(&["1.1.1.1"]), "1.1.1.1", &wan_ips));
+    }
+
+    #[test]
+    fn test_in_sync_per_family() {
+        let wan_ips = vec![
+            ("igb3".to_string(), "1.1.1.1".to_string()),
+            ("igb3".to_string(), "2001:db8::1".to_string()),
+        ];
+        let policy = ComparePolicy::Exact;
+
+        // Assert that each family is compared with the addresses of the same family
+        assert!(policy.in_sync_per_family(&ips(&["1.1.1.1", "2001:db8::1"]), "1.1.1.1", &wan_ips));
+        assert!(!policy.in_sync_per_family(&ips(&["1.1.1.1", "2001:db8::2"]), "1.1.1.1", &wan_ips));
+        assert!(!policy.in_sync_per_family(&ips(&["1.1.1.1"]), "1.1.1.1", &wan_ips));
+
+        // Assert that a family the router reports no address of is not compared
+        assert!(policy.in_sync_per_family(
+            &ips(&["1.1.1.1", "2001:db8::2"]),
+            "1.1.1.1",
+            &wan_ips[..1]
+        ));
+        assert!(policy.in_sync_per_family(&ips(&["2001:db8::1"]), "2001:db8::1", &wan_ips[1..]));
+    }
+
+    #[test]
+    fn test_for_hostname() {
+        let policies = ComparePolicies {
+            default: ComparePolicy::Exact,
+            hosts: BTreeMap::from([(
+                "home.example.com".to_string(),
+                ComparePolicy::Accepted(vec!["203.0.113.10/32".parse().unwrap()]),
+            )]),
+            planned: vec![PlannedChange::parse(
+                "home.example.com=5.6.7.8@2024-07-01T00:00:00Z..2024-07-02T00:00:00Z",
+            )
+            .unwrap()],
+        };
+        assert!(matches!(
+            policies.for_hostname("home.example.com"),
+            ComparePolicy::Accepted(_)
+        ));
+        assert_eq!(
+            policies.for_hostname("vpn.example.com"),
+            &ComparePolicy::Exact
+        );
+
+        // Assert that the new address of a planned change is only accepted during its window
+        let at = |time| DateTime::parse_from_rfc3339(time).unwrap();
+        let dns_ips = vec!["5.6.7.8".to_string()];
+        assert!(policies.accepts_planned("home.example.com", &dns_ips, at("2024-07-01T12:00:00Z")));
+        assert!(!policies.accepts_planned(
+            "home.example.com",
+            &dns_ips,
+            at("2024-07-02T12:00:00Z")
+        ));
+        assert!(!policies.accepts_planned("vpn.example.com", &dns_ips, at("2024-07-01T12:00:00Z")));
+    }
+}
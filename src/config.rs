@@ -0,0 +1,2031 @@
+use crate::vars::{parse_duration, split_list};
+use crate::{crypt, secrets};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// The type of the value of a setting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    /// Any string.
+    String,
+    /// A non-negative integer.
+    Integer,
+    /// `true` or `false`.
+    Boolean,
+    /// A duration such as "30s", "15m", "1h" or "7d".
+    Duration,
+    /// A list of strings, given as an array or a comma-separated string.
+    List,
+    /// One of the given strings.
+    Choice(&'static [&'static str]),
+}
+
+/// A setting that can be given as an environment variable or as a key of the config file.
+#[derive(Debug)]
+pub struct Setting {
+    /// The name of the environment variable, e.g. "DNS_HOSTNAME". The config file key is the name in lower case.
+    pub name: &'static str,
+    /// The type of the value.
+    pub kind: Kind,
+    /// A short description, used in the schema.
+    pub description: &'static str,
+}
+
+/// Declares a setting.
+const fn setting(name: &'static str, kind: Kind, description: &'static str) -> Setting {
+    Setting {
+        name,
+        kind,
+        description,
+    }
+}
+
+/// All settings of the application.
+pub const SETTINGS: &[Setting] = &[
+    setting(
+        "RUST_LOG",
+        Kind::String,
+        "Logging level: ERROR, WARN, INFO, DEBUG or TRACE",
+    ),
+    setting(
+        "LOG_FORMAT",
+        Kind::Choice(&["text", "json", "journald", "eventlog"]),
+        "Format of the log output",
+    ),
+    setting(
+        "DEPLOYMENT_MODE",
+        Kind::Choice(&["default", "kubernetes"]),
+        "Kubernetes mode enables the probes and JSON logs",
+    ),
+    setting(
+        "SHUTDOWN_TIMEOUT",
+        Kind::Duration,
+        "Time allowed for a graceful shutdown",
+    ),
+    setting(
+        "TELEGRAM_TOKEN",
+        Kind::String,
+        "Token of the Telegram bot (required)",
+    ),
+    setting(
+        "CHAT_ID",
+        Kind::String,
+        "ID of the Telegram chat, or @channel (required)",
+    ),
+    setting(
+        "CHAT_ID_FILE",
+        Kind::String,
+        "Path where a migrated chat ID is persisted",
+    ),
+    setting(
+        "TELEGRAM_EDIT",
+        Kind::Boolean,
+        "Edit the alert message with updates instead of sending new messages",
+    ),
+    setting(
+        "TELEGRAM_INCIDENT_FILE",
+        Kind::String,
+        "Path where the ID of the alert message of an ongoing mismatch is stored",
+    ),
+    setting(
+        "TELEGRAM_CONTEXT",
+        Kind::Boolean,
+        "Add the ASN, reverse DNS name and city of the new WAN IP address to the alert",
+    ),
+    setting(
+        "TELEGRAM_LOCATION",
+        Kind::Boolean,
+        "Send the location of the new WAN IP address as a map pin after the alert",
+    ),
+    setting(
+        "TELEGRAM_BACKUP_TOKEN",
+        Kind::String,
+        "Token of the backup bot used while the primary bot fails",
+    ),
+    setting(
+        "TELEGRAM_BACKUP_CHAT_ID",
+        Kind::String,
+        "ID of the backup chat used while the primary bot fails",
+    ),
+    setting(
+        "TELEGRAM_FALLBACK_AFTER",
+        Kind::Integer,
+        "Consecutive failed deliveries after which the backup is used",
+    ),
+    setting(
+        "TELEGRAM_FALLBACK_RETRY",
+        Kind::Duration,
+        "Time between attempts with the primary bot while the backup is used",
+    ),
+    setting(
+        "GEOIP_API",
+        Kind::String,
+        "URL of the ip-api.com compatible GeoIP service",
+    ),
+    setting("URL", Kind::String, "URL of the router API (required)"),
+    setting(
+        "API_KEY",
+        Kind::String,
+        "API key of the router API (required)",
+    ),
+    setting(
+        "API_SECRET",
+        Kind::String,
+        "API secret of the router API (required)",
+    ),
+    setting(
+        "API_VERIFY_TLS",
+        Kind::Boolean,
+        "Verify the certificate of the router API",
+    ),
+    setting(
+        "ROUTER_UPTIME_URL",
+        Kind::String,
+        "URL of the system information endpoint of the router, for reboot context in IP change alerts",
+    ),
+    setting(
+        "ROUTER_REBOOT_WINDOW",
+        Kind::Duration,
+        "Uptime below which IP change alerts mention the router reboot",
+    ),
+    setting(
+        "INTERFACE",
+        Kind::List,
+        "Router interfaces to look up, primary first (required)",
+    ),
+    setting(
+        "WAN_IP_SOURCE",
+        Kind::List,
+        "Sources of the WAN IP address in order of preference (api, file, hostname)",
+    ),
+    setting(
+        "REFERENCE_HOSTNAME",
+        Kind::String,
+        "Hostname the monitored hostname must follow with the hostname source",
+    ),
+    setting(
+        "DNS_RESOLVERS",
+        Kind::String,
+        "Upstream resolvers: google, cloudflare, quad9, system, a list of name servers as IP[:port], or DNS-over-TLS servers",
+    ),
+    setting(
+        "DNS_USE_SYSTEM",
+        Kind::Boolean,
+        "Use the resolvers of the host from /etc/resolv.conf instead of Google",
+    ),
+    setting(
+        "DNS_RETRIES",
+        Kind::Integer,
+        "Number of retries of a failed lookup",
+    ),
+    setting(
+        "DNS_RETRY_DELAY",
+        Kind::Duration,
+        "Delay before the first retry of a failed lookup",
+    ),
+    setting(
+        "DNS_RETRY_BACKOFF",
+        Kind::String,
+        "Factor the delay between retries of a failed lookup grows by",
+    ),
+    setting(
+        "DNS_RETRY_MAX_DELAY",
+        Kind::Duration,
+        "Longest delay between retries of a failed lookup",
+    ),
+    setting(
+        "DNS_FALLBACK_RESOLVERS",
+        Kind::List,
+        "Resolvers used when the upstream resolvers fail",
+    ),
+    setting(
+        "DNS_ADDRESS_FAMILY",
+        Kind::Choice(&["ipv4", "ipv6", "dual"]),
+        "Records that are resolved and compared: A, AAAA or both",
+    ),
+    setting(
+        "DNS_QUERY_MODE",
+        Kind::Choice(&["resolver", "raw"]),
+        "How the hostname is resolved: through a resolver, or with direct queries that keep the response details",
+    ),
+    setting(
+        "DNS_CACHE",
+        Kind::Boolean,
+        "Whether resolver answers are cached for their TTL",
+    ),
+    setting(
+        "DNS_CACHE_MAX_TTL",
+        Kind::Duration,
+        "Longest time a cached answer is used",
+    ),
+    setting(
+        "DNS_CLIENT_SUBNET",
+        Kind::String,
+        "Client network the hostname is resolved for with EDNS Client Subnet",
+    ),
+    setting(
+        "BREAKER_THRESHOLD",
+        Kind::Integer,
+        "Consecutive failures after which a source is skipped",
+    ),
+    setting(
+        "BREAKER_COOLDOWN",
+        Kind::Duration,
+        "Time a failing source is skipped",
+    ),
+    setting(
+        "SOURCE_FILE",
+        Kind::String,
+        "File of another DDNS client holding the WAN IP address",
+    ),
+    setting(
+        "SOURCE_FORMAT",
+        Kind::Choice(&["plain", "ddclient", "json", "log"]),
+        "Format of SOURCE_FILE",
+    ),
+    setting(
+        "SOURCE_JSON_KEY",
+        Kind::String,
+        "Dot-separated key of the address in a JSON SOURCE_FILE",
+    ),
+    setting(
+        "NETLINK_WATCH",
+        Kind::Boolean,
+        "Whether route and address changes trigger an immediate check (Linux)",
+    ),
+    setting(
+        "NETLINK_INTERFACES",
+        Kind::List,
+        "Interfaces whose changes trigger an immediate check",
+    ),
+    setting(
+        "INTERFACE_POLICY",
+        Kind::Choice(&["primary", "any"]),
+        "Which interface DNS must match",
+    ),
+    setting(
+        "COMPARE_POLICY",
+        Kind::Choice(&["exact", "any_of", "subset"]),
+        "How the addresses from DNS are matched against the WAN IP addresses",
+    ),
+    setting(
+        "COMPARE_ALLOWED",
+        Kind::List,
+        "Additional addresses or networks DNS may point at with the subset policy",
+    ),
+    setting(
+        "ACCEPTED_IPS",
+        Kind::List,
+        "Addresses or networks accepted per hostname, as hostname=address entries",
+    ),
+    setting(
+        "PLANNED_CHANGES",
+        Kind::List,
+        "Planned moves of a record to a new address, as hostname=address@start[..end] entries",
+    ),
+    setting(
+        "PLANNED_CHANGES_FILE",
+        Kind::String,
+        "Path where the confirmed planned changes are stored",
+    ),
+    setting(
+        "WARN_ONLY_HOSTNAMES",
+        Kind::List,
+        "Hostnames whose mismatches are only logged, never sent to a channel",
+    ),
+    setting(
+        "DNS_HOSTNAME",
+        Kind::String,
+        "Hostname to look up, or @ for the zone apex (required)",
+    ),
+    setting("DNS_ZONE", Kind::String, "Zone of the monitored record"),
+    setting(
+        "WILDCARD_ZONE",
+        Kind::String,
+        "Zone whose wildcard record is verified",
+    ),
+    setting(
+        "WILDCARD_FILE",
+        Kind::String,
+        "Path where the wildcard state is stored",
+    ),
+    setting(
+        "DELEGATION_ZONE",
+        Kind::String,
+        "Zone whose delegation is verified against the parent zone",
+    ),
+    setting(
+        "DELEGATION_INTERVAL",
+        Kind::Duration,
+        "Time between two verifications of the delegation",
+    ),
+    setting(
+        "DELEGATION_FILE",
+        Kind::String,
+        "Path where the delegation state is stored",
+    ),
+    setting(
+        "AXFR_SERVER",
+        Kind::String,
+        "Name server the zone is transferred from to verify the record",
+    ),
+    setting(
+        "AXFR_ZONE",
+        Kind::String,
+        "Zone transferred from the name server",
+    ),
+    setting(
+        "AXFR_TSIG_KEY",
+        Kind::String,
+        "Name of the TSIG key that authenticates the zone transfer",
+    ),
+    setting(
+        "AXFR_TSIG_ALGORITHM",
+        Kind::Choice(&crate::tsig::ALGORITHMS),
+        "Algorithm of the TSIG key",
+    ),
+    setting(
+        "AXFR_TSIG_SECRET",
+        Kind::String,
+        "Secret of the TSIG key in base64",
+    ),
+    setting(
+        "AXFR_EXPECTED",
+        Kind::List,
+        "Names besides the hostname expected to point to the WAN IP address",
+    ),
+    setting(
+        "AXFR_INTERVAL",
+        Kind::Duration,
+        "Time between two zone transfers",
+    ),
+    setting(
+        "AXFR_FILE",
+        Kind::String,
+        "Path where the zone transfer state is stored",
+    ),
+    setting(
+        "CAA_ZONE",
+        Kind::String,
+        "Zone whose CAA records are monitored",
+    ),
+    setting(
+        "CAA_EXPECTED",
+        Kind::List,
+        "CAA records the zone must publish",
+    ),
+    setting(
+        "CAA_FILE",
+        Kind::String,
+        "Path where the CAA records are stored",
+    ),
+    setting(
+        "MAIL_RECORDS",
+        Kind::List,
+        "Names whose SPF, DMARC and DKIM records are monitored",
+    ),
+    setting(
+        "MAIL_RECORDS_FILE",
+        Kind::String,
+        "Path where the expected mail records are stored",
+    ),
+    setting(
+        "CT_WATCH",
+        Kind::Boolean,
+        "Whether Certificate Transparency logs are watched for new certificates",
+    ),
+    setting(
+        "CT_URL",
+        Kind::String,
+        "URL of the crt.sh compatible Certificate Transparency service",
+    ),
+    setting(
+        "CT_ISSUERS",
+        Kind::List,
+        "Issuers whose new certificates are expected",
+    ),
+    setting(
+        "CT_INTERVAL",
+        Kind::Duration,
+        "Time between two polls of the Certificate Transparency logs",
+    ),
+    setting(
+        "CT_FILE",
+        Kind::String,
+        "Path where the IDs of the seen certificates are stored",
+    ),
+    setting(
+        "NS_WATCH",
+        Kind::Boolean,
+        "Whether the name servers of the zone are watched for changes",
+    ),
+    setting(
+        "NS_ZONE",
+        Kind::String,
+        "Zone whose name servers are watched, defaulting to DNS_ZONE",
+    ),
+    setting(
+        "NS_INTERVAL",
+        Kind::Duration,
+        "Time between two lookups of the name servers",
+    ),
+    setting(
+        "NS_FILE",
+        Kind::String,
+        "Path where the name servers of the zone are stored",
+    ),
+    setting(
+        "TTL_WATCH",
+        Kind::Boolean,
+        "Whether the TTL of the monitored record is watched for changes",
+    ),
+    setting(
+        "TTL_INTERVAL",
+        Kind::Duration,
+        "Time between two retrievals of the TTL",
+    ),
+    setting(
+        "TTL_FILE",
+        Kind::String,
+        "Path where the TTL of the monitored record is stored",
+    ),
+    setting(
+        "INTERNAL_RESOLVER",
+        Kind::String,
+        "Internal resolver compared with the external resolver",
+    ),
+    setting(
+        "EXTERNAL_RESOLVER",
+        Kind::String,
+        "External resolver compared with the internal resolver",
+    ),
+    setting(
+        "SPLIT_HORIZON_ALLOW",
+        Kind::List,
+        "Internal answers expected to differ from the external answer",
+    ),
+    setting(
+        "SPLIT_HORIZON_FILE",
+        Kind::String,
+        "Path where the split-horizon state is stored",
+    ),
+    setting(
+        "PROPAGATION_RESOLVERS",
+        Kind::List,
+        "Resolvers whose answers for the hostname are compared with the WAN IP address after an update",
+    ),
+    setting(
+        "PROPAGATION_FILE",
+        Kind::String,
+        "Path where the resolvers that are still stale are stored",
+    ),
+    setting(
+        "DNSSEC_VALIDATION",
+        Kind::Boolean,
+        "Verify that the answer for the hostname passes DNSSEC validation",
+    ),
+    setting(
+        "DNSSEC_FILE",
+        Kind::String,
+        "Path where the DNSSEC validation state is stored",
+    ),
+    setting(
+        "CLIENT_SUBNETS",
+        Kind::List,
+        "Client networks whose answers for the hostname are compared",
+    ),
+    setting(
+        "CLIENT_SUBNET_RESOLVER",
+        Kind::String,
+        "Resolver queried with EDNS Client Subnet for the client networks",
+    ),
+    setting(
+        "CLIENT_SUBNET_FILE",
+        Kind::String,
+        "Path where the client subnet state is stored",
+    ),
+    setting(
+        "UPLINK_FILE",
+        Kind::String,
+        "Path where the active uplink is stored",
+    ),
+    setting(
+        "WAN_IP_FILE",
+        Kind::String,
+        "Path where the last WAN IP address is stored",
+    ),
+    setting(
+        "METRICS_FILE",
+        Kind::String,
+        "Path where the counters are stored across restarts",
+    ),
+    setting(
+        "DEPENDENCIES_FILE",
+        Kind::String,
+        "Path where the health of the resolvers, WAN IP sources and channels is stored",
+    ),
+    setting(
+        "ZABBIX_SERVER",
+        Kind::String,
+        "Zabbix server the check results are sent to, as host[:port]",
+    ),
+    setting(
+        "ZABBIX_HOST",
+        Kind::String,
+        "Name of the Zabbix host that holds the trapper items",
+    ),
+    setting(
+        "ICINGA_API",
+        Kind::String,
+        "URL of the Icinga 2 API passive check results are submitted to",
+    ),
+    setting("ICINGA_USER", Kind::String, "Icinga 2 API user"),
+    setting(
+        "ICINGA_PASSWORD",
+        Kind::String,
+        "Password of the Icinga 2 API user",
+    ),
+    setting(
+        "ICINGA_HOST",
+        Kind::String,
+        "Name of the Icinga host of the passive service",
+    ),
+    setting(
+        "ICINGA_SERVICE",
+        Kind::String,
+        "Name of the passive Icinga service",
+    ),
+    setting(
+        "DUMP_FILE",
+        Kind::String,
+        "Path the state dump is written to on SIGUSR1",
+    ),
+    setting(
+        "WAN_DOWN_FILE",
+        Kind::String,
+        "Path where the WAN down state is stored",
+    ),
+    setting(
+        "PREFIX_INTERFACE",
+        Kind::String,
+        "Interface whose IPv6 prefix is tracked",
+    ),
+    setting(
+        "PREFIX_LENGTH",
+        Kind::Integer,
+        "Length of the delegated IPv6 prefix",
+    ),
+    setting(
+        "PREFIX_FILE",
+        Kind::String,
+        "Path where the IPv6 prefix is stored",
+    ),
+    setting(
+        "CHECKS",
+        Kind::List,
+        "Checks run on every verification, in order",
+    ),
+    setting(
+        "COMPARE_SCRIPT",
+        Kind::String,
+        "Path of a Rhai comparison script",
+    ),
+    setting(
+        "HEARTBEAT_INTERVAL",
+        Kind::Duration,
+        "Interval of the heartbeat log line, 0 disables it",
+    ),
+    setting(
+        "DIGEST_INTERVAL",
+        Kind::Duration,
+        "Time between two digests of the checks; 0, the default, disables them",
+    ),
+    setting(
+        "DIGEST_CHART",
+        Kind::Boolean,
+        "Attach a chart of the checks since the previous digest to the digest and the cluster digest",
+    ),
+    setting("HISTORY_FILE", Kind::String, "Path of the check history"),
+    setting(
+        "SLO_LATENCY_MS",
+        Kind::Integer,
+        "Latency in milliseconds the DNS lookups must stay under",
+    ),
+    setting(
+        "SLO_TARGET",
+        Kind::String,
+        "Percentage of lookups that must stay under the latency objective",
+    ),
+    setting(
+        "SLO_WINDOW",
+        Kind::Duration,
+        "Rolling window the latency objective is evaluated over",
+    ),
+    setting(
+        "SLO_MIN_LOOKUPS",
+        Kind::Integer,
+        "Number of lookups in the window needed to evaluate the latency objective",
+    ),
+    setting(
+        "SLO_INTERVAL",
+        Kind::Duration,
+        "Time between two evaluations of the latency objective",
+    ),
+    setting(
+        "SLO_FILE",
+        Kind::String,
+        "Path where the latency objective state is stored",
+    ),
+    setting(
+        "RECORD_FILE",
+        Kind::String,
+        "Path where the raw inputs of every check cycle are recorded",
+    ),
+    setting(
+        "CHAOS_DNS_TIMEOUT_EVERY",
+        Kind::Integer,
+        "Make every DNS lookup time out in every Nth check cycle (chaos builds only)",
+    ),
+    setting(
+        "CHAOS_API_ERROR_EVERY",
+        Kind::Integer,
+        "Make the router API fail with 500 in every Nth check cycle (chaos builds only)",
+    ),
+    setting(
+        "CHAOS_TELEGRAM_429_EVERY",
+        Kind::Integer,
+        "Make every Nth Telegram request fail with 429 (chaos builds only)",
+    ),
+    setting("STATE_DIR", Kind::String, "Directory of the state files"),
+    setting(
+        "STATE_BACKEND",
+        Kind::String,
+        "Backend the state is kept in: file, sqlite or redis",
+    ),
+    setting(
+        "STATE_DATABASE",
+        Kind::String,
+        "Path of the SQLite database of the state",
+    ),
+    setting("REDIS_URL", Kind::String, "URL of the Redis server of the state"),
+    setting("REDIS_PREFIX", Kind::String, "Prefix of the Redis keys"),
+    setting(
+        "LEADER_ELECTION",
+        Kind::Boolean,
+        "Only send notifications from the replica that holds the leader lease",
+    ),
+    setting(
+        "LEADER_LEASE",
+        Kind::Duration,
+        "Time the leader lease is valid without being renewed",
+    ),
+    setting(
+        "ALERT_DEDUP_WINDOW",
+        Kind::Duration,
+        "Time an alert sent by one replica is not sent again by the others",
+    ),
+    setting("LOCKFILE", Kind::String, "Path of the alarm lockfile"),
+    setting("OK_LOCKFILE", Kind::String, "Path of the recovery lockfile"),
+    setting(
+        "WEBHOOK_URL",
+        Kind::String,
+        "URL that receives every alert as JSON",
+    ),
+    setting(
+        "WEBHOOK_SECRET",
+        Kind::String,
+        "Secret used to sign webhook payloads",
+    ),
+    setting(
+        "SLACK_WEBHOOK_URL",
+        Kind::String,
+        "Slack incoming webhook that receives every alert",
+    ),
+    setting(
+        "SLACK_LINK_URL",
+        Kind::String,
+        "Public URL of the HTTP listener linked from Slack alerts",
+    ),
+    setting(
+        "SLACK_BOT_TOKEN",
+        Kind::String,
+        "Slack bot token with the files:write scope, to upload the images of alerts",
+    ),
+    setting(
+        "SLACK_CHANNEL_ID",
+        Kind::String,
+        "ID of the Slack channel images are uploaded to",
+    ),
+    setting(
+        "APPRISE_URL",
+        Kind::String,
+        "Notify endpoint of an Apprise API server that receives every alert",
+    ),
+    setting(
+        "APPRISE_TAG",
+        Kind::String,
+        "Tag of the Apprise services to notify",
+    ),
+    setting(
+        "APPRISE_ROUTES",
+        Kind::List,
+        "Apprise tag per event, as event=tag pairs",
+    ),
+    setting(
+        "SIGNAL_API_URL",
+        Kind::String,
+        "Base URL of a signal-cli-rest-api server that sends every alert to Signal",
+    ),
+    setting(
+        "SIGNAL_NUMBER",
+        Kind::String,
+        "Registered Signal number the alerts are sent from",
+    ),
+    setting(
+        "SIGNAL_RECIPIENTS",
+        Kind::List,
+        "Signal numbers and group IDs that receive the alerts",
+    ),
+    setting(
+        "WHATSAPP_PHONE_NUMBER_ID",
+        Kind::String,
+        "ID of the WhatsApp business phone number that sends every alert",
+    ),
+    setting(
+        "WHATSAPP_TOKEN",
+        Kind::String,
+        "Access token of the WhatsApp Business Cloud API",
+    ),
+    setting(
+        "WHATSAPP_RECIPIENTS",
+        Kind::List,
+        "WhatsApp numbers that receive the alerts",
+    ),
+    setting(
+        "WHATSAPP_TEMPLATE",
+        Kind::String,
+        "Name of the approved WhatsApp template the alerts are sent with",
+    ),
+    setting(
+        "WHATSAPP_LANGUAGE",
+        Kind::String,
+        "Language code of the WhatsApp template",
+    ),
+    setting(
+        "XMPP_JID",
+        Kind::String,
+        "XMPP account that sends every alert, as user@domain",
+    ),
+    setting("XMPP_PASSWORD", Kind::String, "Password of the XMPP account"),
+    setting(
+        "XMPP_SERVER",
+        Kind::String,
+        "XMPP server as host[:port], looked up from the domain if not set",
+    ),
+    setting(
+        "XMPP_RECIPIENTS",
+        Kind::List,
+        "XMPP addresses that receive the alerts",
+    ),
+    setting(
+        "XMPP_ROOMS",
+        Kind::List,
+        "XMPP group chats (MUC) that receive the alerts",
+    ),
+    setting(
+        "XMPP_NICK",
+        Kind::String,
+        "Nickname of the account in the XMPP group chats",
+    ),
+    setting(
+        "FEISHU_WEBHOOK_URL",
+        Kind::String,
+        "Webhook of a Feishu or Lark custom bot that receives every alert",
+    ),
+    setting(
+        "FEISHU_SECRET",
+        Kind::String,
+        "Signing secret of the Feishu or Lark bot",
+    ),
+    setting(
+        "DINGTALK_WEBHOOK_URL",
+        Kind::String,
+        "Webhook of a DingTalk custom robot that receives every alert",
+    ),
+    setting(
+        "DINGTALK_SECRET",
+        Kind::String,
+        "Signing secret of the DingTalk robot",
+    ),
+    setting(
+        "SNMP_MANAGER",
+        Kind::String,
+        "SNMP manager that receives traps on mismatches and recoveries, as host[:port]",
+    ),
+    setting(
+        "SNMP_COMMUNITY",
+        Kind::String,
+        "SNMPv2c community of the traps",
+    ),
+    setting("SNMP_TRAP_OID", Kind::String, "Base OID of the traps"),
+    setting(
+        "ON_MISMATCH",
+        Kind::String,
+        "Command run when the addresses differ",
+    ),
+    setting(
+        "ON_RECOVERY",
+        Kind::String,
+        "Command run when the addresses match again",
+    ),
+    setting(
+        "ON_RESET",
+        Kind::String,
+        "Command run when the alarm is reset manually",
+    ),
+    setting(
+        "ON_SLO",
+        Kind::String,
+        "Command run when the latency objective of the lookups is breached or met again",
+    ),
+    setting(
+        "ON_WAN_CHANGE",
+        Kind::String,
+        "Command run when the WAN IP address changes",
+    ),
+    setting(
+        "ON_FAILOVER",
+        Kind::String,
+        "Command run when the active uplink changes",
+    ),
+    setting(
+        "ON_WILDCARD",
+        Kind::String,
+        "Command run when the wildcard record breaks or recovers",
+    ),
+    setting(
+        "ON_PREFIX",
+        Kind::String,
+        "Command run when the IPv6 prefix changes",
+    ),
+    setting(
+        "ON_SPLIT_HORIZON",
+        Kind::String,
+        "Command run when internal and external DNS start or stop disagreeing",
+    ),
+    setting(
+        "ON_PROPAGATION",
+        Kind::String,
+        "Command run when the resolvers that still answer with a stale address change",
+    ),
+    setting(
+        "ON_DNSSEC",
+        Kind::String,
+        "Command run when the DNSSEC validation of the hostname fails or passes again",
+    ),
+    setting(
+        "ON_PLANNED_CHANGE",
+        Kind::String,
+        "Command run when a planned change is observed in DNS",
+    ),
+    setting(
+        "ON_CLIENT_SUBNET",
+        Kind::String,
+        "Command run when the answers for the client networks start or stop differing",
+    ),
+    setting(
+        "ON_DELEGATION",
+        Kind::String,
+        "Command run when the delegation of the zone becomes inconsistent or is fixed",
+    ),
+    setting(
+        "ON_NAMESERVERS",
+        Kind::String,
+        "Command run when the name servers of the zone change",
+    ),
+    setting(
+        "ON_AXFR",
+        Kind::String,
+        "Command run when the zone stops or starts matching the WAN IP address",
+    ),
+    setting(
+        "ON_CAA",
+        Kind::String,
+        "Command run when the CAA records change",
+    ),
+    setting(
+        "ON_MAIL_RECORDS",
+        Kind::String,
+        "Command run when the SPF, DMARC or DKIM records change",
+    ),
+    setting(
+        "ON_CERTIFICATE",
+        Kind::String,
+        "Command run when an unexpected certificate is logged",
+    ),
+    setting(
+        "ON_TTL",
+        Kind::String,
+        "Command run when the TTL of the monitored record changes",
+    ),
+    setting(
+        "ON_SUPPRESSED",
+        Kind::String,
+        "Command run when messages were suppressed by the alert limit",
+    ),
+    setting(
+        "ALERT_LIMIT",
+        Kind::Integer,
+        "Maximum number of messages per alert window",
+    ),
+    setting(
+        "ALERT_WINDOW",
+        Kind::Duration,
+        "Sliding window of the alert limit",
+    ),
+    setting(
+        "ON_WAN_DOWN",
+        Kind::String,
+        "Command run when the WAN uplink goes down or comes back",
+    ),
+    setting(
+        "ON_BACKENDS",
+        Kind::String,
+        "Command run when addresses of the hostname stop or start answering",
+    ),
+    setting(
+        "ON_AGENTS",
+        Kind::String,
+        "Command run when agents start or stop disagreeing",
+    ),
+    setting("ON_DIGEST", Kind::String, "Command run on every digest"),
+    setting(
+        "ON_CLUSTER_DIGEST",
+        Kind::String,
+        "Command run on every cluster digest",
+    ),
+    setting(
+        "HOOK_TIMEOUT",
+        Kind::Duration,
+        "Time a hook command may run",
+    ),
+    setting(
+        "EXTRA_CA_DIR",
+        Kind::String,
+        "Directory of additional root certificates",
+    ),
+    setting(
+        "OUTBOUND_IP_FAMILY",
+        Kind::Choice(&["any", "ipv4", "ipv6"]),
+        "Address family of outbound calls",
+    ),
+    setting(
+        "OUTBOUND_HTTP_VERSION",
+        Kind::Choice(&["auto", "http1", "http2"]),
+        "HTTP version of outbound connections",
+    ),
+    setting(
+        "OUTBOUND_KEEPALIVE",
+        Kind::Boolean,
+        "Whether idle outbound connections are kept for reuse",
+    ),
+    setting(
+        "OUTBOUND_POOL_IDLE_TIMEOUT",
+        Kind::Duration,
+        "How long idle outbound connections are kept for reuse",
+    ),
+    setting(
+        "OUTBOUND_TCP_KEEPALIVE",
+        Kind::Duration,
+        "Interval of the TCP keep-alive probes of outbound connections",
+    ),
+    setting(
+        "OUTBOUND_ADDRESS",
+        Kind::String,
+        "Source IP address of outbound calls",
+    ),
+    setting(
+        "OUTBOUND_INTERFACE",
+        Kind::String,
+        "Network interface of outbound calls",
+    ),
+    setting(
+        "LISTEN_ADDR",
+        Kind::String,
+        "Address of the HTTP listener, e.g. 0.0.0.0:8080",
+    ),
+    setting(
+        "TLS_CERT",
+        Kind::String,
+        "PEM certificate chain of the HTTP listener",
+    ),
+    setting(
+        "TLS_KEY",
+        Kind::String,
+        "PEM private key of the HTTP listener",
+    ),
+    setting(
+        "HTTP_TOKEN",
+        Kind::String,
+        "Bearer token of the HTTP listener",
+    ),
+    setting(
+        "HTTP_BASIC_AUTH",
+        Kind::String,
+        "user:password for basic authentication",
+    ),
+    setting(
+        "HTTP_PUBLIC_STATUS",
+        Kind::Boolean,
+        "Serve /status without credentials",
+    ),
+    setting(
+        "HTTP_ALLOW",
+        Kind::List,
+        "Client addresses or networks allowed to connect",
+    ),
+    setting("TRIGGER_TOKEN", Kind::String, "Old name of HTTP_TOKEN"),
+    setting(
+        "AGGREGATOR_URL",
+        Kind::String,
+        "URL of the aggregator; runs the checker as an agent",
+    ),
+    setting(
+        "AGENT_TOKEN",
+        Kind::String,
+        "Token shared between the agents and the aggregator",
+    ),
+    setting("AGENT_NAME", Kind::String, "Name the agent reports as"),
+    setting(
+        "AGENT_RESOLVER",
+        Kind::String,
+        "Resolver queried by the agent",
+    ),
+    setting(
+        "AGENT_MAX_AGE",
+        Kind::Duration,
+        "Time an agent report counts on the aggregator",
+    ),
+    setting(
+        "AGENTS_FILE",
+        Kind::String,
+        "Path where the agent disagreement state is stored",
+    ),
+    setting(
+        "CLUSTER_PEERS",
+        Kind::List,
+        "Instances to poll; runs the checker as cluster view",
+    ),
+    setting(
+        "CLUSTER_TOKEN",
+        Kind::String,
+        "Bearer token sent to the /status endpoints of the instances",
+    ),
+    setting(
+        "CLUSTER_INTERVAL",
+        Kind::Duration,
+        "Time between two polls of the cluster view",
+    ),
+    setting(
+        "CLUSTER_DIGEST_INTERVAL",
+        Kind::Duration,
+        "Time between two cluster digests; 0 disables them",
+    ),
+
+    setting(
+        "BACKEND_PORT",
+        Kind::Integer,
+        "TCP port probed on every address of the hostname",
+    ),
+    setting(
+        "BACKEND_TIMEOUT",
+        Kind::Duration,
+        "Time to wait for each backend to answer",
+    ),
+    setting(
+        "BACKENDS_FILE",
+        Kind::String,
+        "Path where the dead backends are stored",
+    ),
+];
+
+/// The contents of a config file.
+///
+/// The keys are the names of the settings in lower case, e.g. `dns_hostname`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Config {
+    values: BTreeMap<String, Value>,
+}
+
+impl Config {
+    /// Parses a config file, detecting the format by the extension of its path.
+    ///
+    /// Files ending in ".yaml" or ".yml" are parsed as YAML, all other files as TOML.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: A `&str` that specifies the path of the file.
+    /// * `text`: A `&str` that holds the contents of the file.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<Config, String>` that holds the config, or an `Err` with the parse error.
+    pub fn parse(path: &str, text: &str) -> Result<Config, String> {
+        if path.ends_with(".yaml") || path.ends_with(".yml") {
+            Config::from_yaml(text)
+        } else {
+            Config::from_toml(text)
+        }
+    }
+
+    /// Parses a TOML config file.
+    ///
+    /// # Arguments
+    ///
+    /// * `text`: A `&str` that holds the contents of the file.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<Config, String>` that holds the config, or an `Err` with the parse error.
+    pub fn from_toml(text: &str) -> Result<Config, String> {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+
+    /// Parses a YAML config file.
+    ///
+    /// An empty file is an empty config.
+    ///
+    /// # Arguments
+    ///
+    /// * `text`: A `&str` that holds the contents of the file.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<Config, String>` that holds the config, or an `Err` with the parse error.
+    pub fn from_yaml(text: &str) -> Result<Config, String> {
+        if text.trim().is_empty() {
+            return Ok(Config::default());
+        }
+        serde_yaml::from_str(text).map_err(|e| e.to_string())
+    }
+
+    /// Reads a config directory, e.g. a mounted Kubernetes ConfigMap or Secret.
+    ///
+    /// Every regular file of the directory is a setting: the file name is the key (in upper or lower case)
+    /// and the contents, without the trailing newline, are the value.
+    /// Hidden files, such as the "..data" link Kubernetes creates, and directories are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir`: A `&str` that specifies the directory.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<Config, String>` that holds the config, or an `Err` with a message if the directory cannot be read.
+    pub fn from_dir(dir: &str) -> Result<Config, String> {
+        let entries = std::fs::read_dir(dir).map_err(|e| e.to_string())?;
+        let mut values = BTreeMap::new();
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let path = entry.path();
+            if name.starts_with('.') || !path.is_file() {
+                continue;
+            }
+            let text = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let value = text.trim_end_matches(['\r', '\n']).to_string();
+            values.insert(name.to_lowercase(), Value::String(value));
+        }
+        Ok(Config { values })
+    }
+
+    /// Merges the files included by a config file.
+    ///
+    /// The "include" key lists files, or directories whose ".toml", ".yaml" and ".yml" files are included in name order
+    /// (a conf.d directory), relative to the directory of the including file, e.g. `include = ["hosts.toml", "conf.d"]`.
+    /// The included files are merged in the order listed. A key, or a profile (see `select_profile`), that is set in
+    /// several included files must have the same value in each of them, otherwise the merge fails; the settings of the
+    /// including file itself take precedence over those it includes. Included files cannot include further files.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: A `&str` that specifies the path of the including file.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<Config, String>` that holds the merged config, without the "include" key.
+    /// * If an included file cannot be read or parsed, or two included files conflict, it returns an `Err` with a message.
+    pub fn merge_includes(mut self, path: &str) -> Result<Config, String> {
+        let includes = match self.values.remove("include") {
+            None => return Ok(self),
+            Some(Value::String(include)) => vec![include],
+            Some(Value::Array(items)) => items
+                .into_iter()
+                .map(|item| match item {
+                    Value::String(include) => Ok(include),
+                    _ => Err("include: expected a list of paths".to_string()),
+                })
+                .collect::<Result<Vec<String>, String>>()?,
+            Some(_) => return Err("include: expected a list of paths".to_string()),
+        };
+        let base = Path::new(path).parent().unwrap_or(Path::new(""));
+        let mut files = Vec::new();
+        for include in includes {
+            let include = base.join(include);
+            if !include.is_dir() {
+                files.push(include);
+                continue;
+            }
+            let entries = std::fs::read_dir(&include)
+                .map_err(|e| format!("Failed to read {}: {}", include.display(), e))?;
+            let mut paths: Vec<PathBuf> = entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.is_file()
+                        && path.extension().is_some_and(|extension| {
+                            extension == "toml" || extension == "yaml" || extension == "yml"
+                        })
+                })
+                .collect();
+            paths.sort();
+            files.extend(paths);
+        }
+
+        // Profiles are merged one by one, so included files can each add their own
+        let mut merged: BTreeMap<String, (Value, String)> = BTreeMap::new();
+        for file in files {
+            let source = file.display().to_string();
+            let text = std::fs::read_to_string(&file)
+                .map_err(|e| format!("Failed to read {}: {}", source, e))?;
+            let config = Config::parse(&source, &text)
+                .map_err(|e| format!("Failed to parse {}: {}", source, e))?;
+            for (key, value) in config.values {
+                let entries = match (key.as_str(), value) {
+                    ("include", _) => {
+                        return Err(format!("{}: included files cannot include others", source))
+                    }
+                    ("profiles", Value::Object(profiles)) => profiles
+                        .into_iter()
+                        .map(|(name, profile)| (format!("profiles.{}", name), profile))
+                        .collect(),
+                    (_, value) => vec![(key, value)],
+                };
+                for (key, value) in entries {
+                    match merged.get(&key) {
+                        Some((existing, first)) if *existing != value => {
+                            return Err(format!(
+                                "{} is set differently in {} and {}",
+                                key, first, source
+                            ))
+                        }
+                        Some(_) => {}
+                        None => {
+                            merged.insert(key, (value, source.clone()));
+                        }
+                    }
+                }
+            }
+        }
+        for (key, (value, _)) in merged {
+            match key.strip_prefix("profiles.") {
+                Some(name) => {
+                    let profiles = self
+                        .values
+                        .entry("profiles".to_string())
+                        .or_insert_with(|| Value::Object(serde_json::Map::new()));
+                    if let Value::Object(profiles) = profiles {
+                        profiles.entry(name.to_string()).or_insert(value);
+                    }
+                }
+                None => {
+                    self.values.entry(key).or_insert(value);
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    /// Selects a profile of the config.
+    ///
+    /// A config file can hold named profiles under the "profiles" key, e.g. `[profiles.home]` and `[profiles.office]`
+    /// in TOML, each with the settings that differ from the top-level settings, the base every profile inherits from.
+    /// A profile can also inherit from another profile by naming it in its "inherits" key. The settings of the
+    /// selected profile override those it inherits, the base settings are overridden by all of them.
+    ///
+    /// Without a requested profile, the profile "default" is selected if the file has one.
+    ///
+    /// # Arguments
+    ///
+    /// * `profile`: An `Option<&str>` that specifies the name of the profile to select.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<Config, String>` that holds the base settings merged with those of the profile, without the profiles.
+    /// * If the profile does not exist, the profiles are malformed or inherit from each other in a cycle,
+    ///   it returns an `Err` with a message.
+    pub fn select_profile(mut self, profile: Option<&str>) -> Result<Config, String> {
+        let profiles = match self.values.remove("profiles") {
+            Some(Value::Object(profiles)) => profiles,
+            Some(_) => return Err("profiles: expected a table of profiles".to_string()),
+            None => serde_json::Map::new(),
+        };
+        let name = match profile {
+            Some(name) => name,
+            None if profiles.contains_key("default") => "default",
+            None => return Ok(self),
+        };
+
+        // Collect the profile and those it inherits from, the selected one first
+        let mut chain: Vec<(&str, &serde_json::Map<String, Value>)> = Vec::new();
+        let mut next = Some(name);
+        while let Some(name) = next {
+            if chain.iter().any(|(seen, _)| *seen == name) {
+                return Err(format!("profile {} inherits from itself", name));
+            }
+            let settings = match profiles.get(name) {
+                Some(Value::Object(settings)) => settings,
+                Some(_) => return Err(format!("profile {}: expected a table of settings", name)),
+                None if profiles.is_empty() => {
+                    return Err(format!(
+                        "unknown profile {}, the config has no profiles",
+                        name
+                    ))
+                }
+                None => {
+                    let names: Vec<&str> = profiles.keys().map(String::as_str).collect();
+                    return Err(format!(
+                        "unknown profile {}, available profiles: {}",
+                        name,
+                        names.join(", ")
+                    ));
+                }
+            };
+            next = match settings.get("inherits") {
+                Some(Value::String(parent)) => Some(parent.as_str()),
+                Some(_) => return Err(format!("profile {}: inherits: expected a string", name)),
+                None => None,
+            };
+            chain.push((name, settings));
+        }
+        for (_, settings) in chain.iter().rev() {
+            for (key, value) in settings.iter().filter(|(key, _)| *key != "inherits") {
+                self.values.insert(key.clone(), value.clone());
+            }
+        }
+        Ok(self)
+    }
+
+    /// Converts the config to environment variables.
+    ///
+    /// This function looks up every key in `SETTINGS`, replaces the placeholders of environment variables in its value
+    /// using the `interpolate_value` function, and converts the result to the string form of the environment variable
+    /// using the `to_env_value` function, so an interpolated value is type-checked as well. Encrypted values are decrypted
+    /// using the `decrypt_value` function.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<Vec<(String, String)>, String>` that holds the names and values of the environment variables.
+    /// * If a key is unknown, a value has the wrong type, refers to a missing environment variable or cannot be decrypted,
+    ///   it returns an `Err` with a message for each problem, suggesting the closest known key for a misspelled one.
+    pub fn to_env(&self) -> Result<Vec<(String, String)>, String> {
+        let mut vars = Vec::new();
+        let mut errors = Vec::new();
+        for (key, value) in &self.values {
+            match find_setting(key) {
+                Some(setting) => match interpolate_value(value)
+                    .and_then(|value| to_env_value(setting, &value))
+                    .and_then(decrypt_value)
+                {
+                    Ok(value) => vars.push((setting.name.to_string(), value)),
+                    Err(e) => errors.push(format!("{}: {}", key, e)),
+                },
+                None => errors.push(match suggest(key) {
+                    Some(suggestion) => {
+                        format!("unknown key {}, did you mean {}?", key, suggestion)
+                    }
+                    None => format!("unknown key {}", key),
+                }),
+            }
+        }
+        if errors.is_empty() {
+            Ok(vars)
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+}
+
+/// Replaces the placeholders of environment variables in a config value.
+///
+/// A placeholder is `${NAME}`, replaced with the value of the environment variable "NAME", or `${NAME:-default}`,
+/// replaced with the default if the variable is not set or empty. `$${` is replaced with a literal `${`,
+/// and a `$` that does not start a placeholder is kept as it is.
+///
+/// # Arguments
+///
+/// * `value`: A `String` that holds the value from the config.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds the value with the placeholders replaced.
+/// * If a variable without a default is not set or a placeholder is malformed, it returns an `Err` with a message.
+fn interpolate(value: String) -> Result<String, String> {
+    if !value.contains("${") {
+        return Ok(value);
+    }
+    let mut result = String::new();
+    let mut rest = value.as_str();
+    while let Some(at) = rest.find("${") {
+        if rest[..at].ends_with('$') {
+            result.push_str(&rest[..at - 1]);
+            result.push_str("${");
+            rest = &rest[at + 2..];
+            continue;
+        }
+        result.push_str(&rest[..at]);
+        let end = rest[at + 2..]
+            .find('}')
+            .ok_or(format!("unterminated placeholder in {}", &rest[at..]))?;
+        let placeholder = &rest[at + 2..at + 2 + end];
+        let (name, default) = match placeholder.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (placeholder, None),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("invalid placeholder ${{{}}}", placeholder));
+        }
+        match (env::var(name), default) {
+            (Ok(value), Some(default)) if value.is_empty() => result.push_str(default),
+            (Ok(value), _) => result.push_str(&value),
+            (Err(_), Some(default)) => result.push_str(default),
+            (Err(_), None) => {
+                return Err(format!(
+                    "environment variable {} is not set, use ${{{}:-default}} for a default",
+                    name, name
+                ))
+            }
+        }
+        rest = &rest[at + 2 + end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Replaces the placeholders of environment variables in a string value, or in each string of a list (see `interpolate`).
+///
+/// # Arguments
+///
+/// * `value`: A `&serde_json::Value` that holds the value from the config file.
+///
+/// # Returns
+///
+/// * A `Result<serde_json::Value, String>` that holds the value with the placeholders replaced, or an `Err` with a message.
+fn interpolate_value(value: &Value) -> Result<Value, String> {
+    match value {
+        Value::String(text) => interpolate(text.clone()).map(Value::String),
+        Value::Array(items) => items
+            .iter()
+            .map(interpolate_value)
+            .collect::<Result<Vec<Value>, String>>()
+            .map(Value::Array),
+        _ => Ok(value.clone()),
+    }
+}
+
+/// Decrypts a config value if it is encrypted (see `crypt::encrypt`).
+///
+/// # Arguments
+///
+/// * `value`: A `String` that holds the value from the config.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds the plain value, or an `Err` with a message if it cannot be decrypted.
+fn decrypt_value(value: String) -> Result<String, String> {
+    if !crypt::is_encrypted(&value) {
+        return Ok(value);
+    }
+    crypt::decrypt(&crypt::config_key()?, &value)
+}
+
+/// Looks up the setting of a config file key.
+fn find_setting(key: &str) -> Option<&'static Setting> {
+    SETTINGS
+        .iter()
+        .find(|setting| setting.name.to_lowercase() == key)
+}
+
+/// Suggests the known key closest to a misspelled one.
+///
+/// # Arguments
+///
+/// * `key`: A `&str` that holds the unknown key.
+///
+/// # Returns
+///
+/// * An `Option<String>` that holds the closest key, or `None` if no key is close enough.
+fn suggest(key: &str) -> Option<String> {
+    SETTINGS
+        .iter()
+        .map(|setting| setting.name.to_lowercase())
+        .map(|name| (edit_distance(key, &name), name))
+        .filter(|(distance, name)| *distance <= 3.max(name.len() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, name)| name)
+}
+
+/// Computes the Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Converts the value of a config file key to the value of its environment variable.
+///
+/// # Arguments
+///
+/// * `setting`: A `&Setting` that specifies the setting.
+/// * `value`: A `&serde_json::Value` that holds the value from the config file.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds the value, or an `Err` with a message if it has the wrong type.
+fn to_env_value(setting: &Setting, value: &Value) -> Result<String, String> {
+    match (setting.kind, value) {
+        (Kind::String, Value::String(text)) => Ok(text.clone()),
+        (Kind::String, Value::Number(number)) => Ok(number.to_string()),
+        (Kind::Integer, Value::Number(number)) if number.is_u64() => Ok(number.to_string()),
+        (Kind::Integer, Value::String(text)) if text.parse::<u64>().is_ok() => Ok(text.clone()),
+        (Kind::Boolean, Value::Bool(flag)) => Ok(flag.to_string()),
+        (Kind::Boolean, Value::String(text)) if text == "true" || text == "false" => {
+            Ok(text.clone())
+        }
+        (Kind::Duration, Value::Number(number)) if number.is_u64() => Ok(number.to_string()),
+        (Kind::Duration, Value::String(text)) if parse_duration(text).is_some() => Ok(text.clone()),
+        (Kind::List, Value::String(text)) => Ok(text.clone()),
+        (Kind::List, Value::Array(items)) => items
+            .iter()
+            .map(|item| match item {
+                Value::String(text) => Ok(text.clone()),
+                _ => Err("expected a list of strings".to_string()),
+            })
+            .collect::<Result<Vec<String>, String>>()
+            .map(|items| items.join(",")),
+        (Kind::Choice(choices), Value::String(text)) if choices.contains(&text.as_str()) => {
+            Ok(text.clone())
+        }
+        (kind, _) => Err(format!("expected {}, found {}", describe(kind), value)),
+    }
+}
+
+/// Describes the expected value of a kind, used in error messages.
+fn describe(kind: Kind) -> String {
+    match kind {
+        Kind::String => "a string".to_string(),
+        Kind::Integer => "a non-negative integer".to_string(),
+        Kind::Boolean => "true or false".to_string(),
+        Kind::Duration => "a duration such as \"30s\", \"15m\" or \"1h\"".to_string(),
+        Kind::List => "a list of strings".to_string(),
+        Kind::Choice(choices) => format!("one of {}", choices.join(", ")),
+    }
+}
+
+/// Builds the JSON Schema of the config file.
+///
+/// # Returns
+///
+/// * A `serde_json::Value` that holds the schema; unknown keys are not allowed.
+pub fn schema() -> Value {
+    let properties: serde_json::Map<String, Value> = SETTINGS
+        .iter()
+        .map(|setting| {
+            let mut property = match setting.kind {
+                Kind::String => json!({ "type": "string" }),
+                Kind::Integer => json!({ "type": "integer", "minimum": 0 }),
+                Kind::Boolean => json!({ "type": "boolean" }),
+                Kind::Duration => json!({
+                    "type": ["string", "integer"],
+                    "pattern": "^[0-9]+[dhms]?$",
+                    "minimum": 0
+                }),
+                Kind::List => json!({
+                    "type": ["array", "string"],
+                    "items": { "type": "string" }
+                }),
+                Kind::Choice(choices) => json!({ "enum": choices }),
+            };
+            property["description"] = json!(setting.description);
+            (setting.name.to_lowercase(), property)
+        })
+        .collect();
+    let mut profile = properties.clone();
+    profile.insert(
+        "inherits".to_string(),
+        json!({ "type": "string", "description": "Profile whose settings this profile inherits" }),
+    );
+    let mut properties = properties;
+    properties.insert(
+        "include".to_string(),
+        json!({
+            "type": ["array", "string"],
+            "items": { "type": "string" },
+            "description": "Config files or conf.d directories merged into this file"
+        }),
+    );
+    properties.insert(
+        "profiles".to_string(),
+        json!({
+            "type": "object",
+            "description": "Named profiles, each overriding the top-level settings",
+            "additionalProperties": {
+                "type": "object",
+                "properties": profile,
+                "additionalProperties": false
+            }
+        }),
+    );
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "dnschecker configuration",
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false
+    })
+}
+
+/// Loads the config directories and the config file and applies them to the environment.
+///
+/// This function first reads every directory in the comma-separated "CONFIG_DIR" environment variable
+/// (e.g. a mounted ConfigMap and a mounted Secret) using the `Config::from_dir` function.
+/// It then reads the file in the "CONFIG_FILE" environment variable as TOML or YAML using the `Config::parse` function,
+/// merges the files it includes using the `Config::merge_includes` function, and selects the profile in the
+/// "CONFIG_PROFILE" environment variable using the `Config::select_profile` function.
+///
+/// It sets an environment variable for each key. Environment variables that are already set take precedence,
+/// so the order of precedence is: environment, config directories in the order listed, config file.
+/// Finally, references to secrets in cloud secret managers are replaced using the `secrets::resolve_references` function.
+///
+/// # Returns
+///
+/// * A `Result<(), String>` that is `Ok` if there is no config or it was applied.
+/// * If a directory or the file cannot be read, parsed or contains unknown keys or invalid values, it returns an `Err` with a message.
+pub fn load_config() -> Result<(), String> {
+    for dir in split_list(&env::var("CONFIG_DIR").unwrap_or_default()) {
+        let config = Config::from_dir(&dir)
+            .map_err(|e| format!("Failed to read config directory {}: {}", dir, e))?;
+        apply(&config).map_err(|e| format!("Invalid config directory {}:\n{}", dir, e))?;
+    }
+    if let Ok(path) = env::var("CONFIG_FILE") {
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read config file {}: {}", path, e))?;
+        let config = Config::parse(&path, &text)
+            .map_err(|e| format!("Failed to parse config file {}: {}", path, e))?
+            .merge_includes(&path)
+            .and_then(|config| config.select_profile(env::var("CONFIG_PROFILE").ok().as_deref()))
+            .map_err(|e| format!("Invalid config file {}: {}", path, e))?;
+        apply(&config).map_err(|e| format!("Invalid config file {}:\n{}", path, e))?;
+    }
+    secrets::resolve_references()
+        .map_err(|e| format!("Failed to read secrets from the secret manager:\n{}", e))
+}
+
+/// Sets the environment variables of a config that are not set yet.
+///
+/// # Arguments
+///
+/// * `config`: A `&Config` that holds the settings.
+///
+/// # Returns
+///
+/// * A `Result<(), String>` that is `Ok` if the config was applied, or an `Err` with the problems found by `Config::to_env`.
+pub fn apply(config: &Config) -> Result<(), String> {
+    for (name, value) in config.to_env()? {
+        if env::var(&name).is_err() {
+            env::set_var(name, value);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_env() {
+        let config = Config::from_toml(
+            r#"
+                dns_hostname = "home.example.com"
+                interface = ["igb3", "igb4"]
+                prefix_length = 56
+                api_verify_tls = true
+                heartbeat_interval = "1h"
+            "#,
+        )
+        .unwrap();
+
+        let vars = config.to_env().unwrap();
+
+        assert!(vars.contains(&("DNS_HOSTNAME".to_string(), "home.example.com".to_string())));
+        assert!(vars.contains(&("INTERFACE".to_string(), "igb3,igb4".to_string())));
+        assert!(vars.contains(&("PREFIX_LENGTH".to_string(), "56".to_string())));
+        assert!(vars.contains(&("API_VERIFY_TLS".to_string(), "true".to_string())));
+    }
+
+    #[test]
+    fn test_encrypted_value() {
+        env::set_var("CONFIG_KEY", crypt::generate_key());
+        let encrypted = crypt::encrypt(&crypt::config_key().unwrap(), "123456:ABC-DEF");
+        let config = Config::from_toml(&format!("telegram_token = \"{}\"", encrypted)).unwrap();
+
+        let vars = config.to_env().unwrap();
+        assert_eq!(
+            vars,
+            vec![("TELEGRAM_TOKEN".to_string(), "123456:ABC-DEF".to_string())]
+        );
+
+        // Assert that a missing key is reported for the setting
+        env::remove_var("CONFIG_KEY");
+        assert!(config
+            .to_env()
+            .unwrap_err()
+            .starts_with("telegram_token: encrypted value, but neither CONFIG_KEY"));
+    }
+
+    #[test]
+    fn test_from_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("DNS_HOSTNAME"), "home.example.com\n").unwrap();
+        std::fs::write(dir.path().join("prefix_length"), "56").unwrap();
+        std::fs::write(dir.path().join("..data"), "ignored").unwrap();
+        std::fs::create_dir(dir.path().join("..2024_01_01")).unwrap();
+
+        let config = Config::from_dir(dir.path().to_str().unwrap()).unwrap();
+        let mut vars = config.to_env().unwrap();
+        vars.sort();
+
+        assert_eq!(
+            vars,
+            vec![
+                ("DNS_HOSTNAME".to_string(), "home.example.com".to_string()),
+                ("PREFIX_LENGTH".to_string(), "56".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_yaml() {
+        let yaml =
+            "dns_hostname: home.example.com\ninterface:\n  - igb3\n  - igb4\nprefix_length: 56\n";
+        let toml = "dns_hostname = \"home.example.com\"\ninterface = [\"igb3\", \"igb4\"]\nprefix_length = 56\n";
+
+        // Assert that both formats yield the same settings
+        let from_yaml = Config::parse("/config/dnschecker.yaml", yaml).unwrap();
+        let from_toml = Config::parse("/config/dnschecker.toml", toml).unwrap();
+        assert_eq!(from_yaml.to_env(), from_toml.to_env());
+
+        // Assert that an empty YAML file is an empty config
+        assert_eq!(
+            Config::parse("empty.yml", "").unwrap().to_env(),
+            Ok(Vec::new())
+        );
+
+        // Assert that the format is detected by the extension
+        assert!(Config::parse("dnschecker.toml", yaml).is_err());
+    }
+
+    #[test]
+    fn test_to_env_errors() {
+        let config = Config::from_toml(
+            r#"
+                telegram_bot_token = "123:abc"
+                heartbeat_interval = "soon"
+                interface_policy = "first"
+                completely_unrelated = 1
+            "#,
+        )
+        .unwrap();
+
+        let errors = config.to_env().unwrap_err();
+
+        assert!(errors.contains("unknown key telegram_bot_token, did you mean telegram_token?"));
+        assert!(errors.contains("heartbeat_interval: expected a duration"));
+        assert!(errors.contains("interface_policy: expected one of primary, any"));
+        assert!(errors
+            .lines()
+            .any(|line| line == "unknown key completely_unrelated"));
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema = schema();
+
+        assert_eq!(schema["additionalProperties"], false);
+        assert_eq!(schema["properties"]["prefix_length"]["type"], "integer");
+        assert_eq!(
+            schema["properties"]["outbound_ip_family"]["enum"],
+            json!(["any", "ipv4", "ipv6"])
+        );
+        assert_eq!(
+            schema["properties"].as_object().unwrap().len(),
+            SETTINGS.len() + 2
+        );
+        assert_eq!(
+            schema["properties"]["profiles"]["additionalProperties"]["properties"]["inherits"]
+                ["type"],
+            "string"
+        );
+    }
+
+    #[test]
+    fn test_interpolate() {
+        env::set_var("DNSCHECKER_TEST_ROUTER", "192.168.1.1");
+        env::set_var("DNSCHECKER_TEST_EMPTY", "");
+        env::remove_var("DNSCHECKER_TEST_MISSING");
+
+        assert_eq!(
+            interpolate("https://${DNSCHECKER_TEST_ROUTER}/api".to_string()),
+            Ok("https://192.168.1.1/api".to_string())
+        );
+        assert_eq!(
+            interpolate("${DNSCHECKER_TEST_MISSING:-a}${DNSCHECKER_TEST_EMPTY:-b}".to_string()),
+            Ok("ab".to_string())
+        );
+        assert_eq!(
+            interpolate("pa$$word $${DNSCHECKER_TEST_ROUTER} $5".to_string()),
+            Ok("pa$$word ${DNSCHECKER_TEST_ROUTER} $5".to_string())
+        );
+
+        // Assert that the errors name the problem
+        assert_eq!(
+            interpolate("${DNSCHECKER_TEST_MISSING}".to_string()),
+            Err("environment variable DNSCHECKER_TEST_MISSING is not set, use ${DNSCHECKER_TEST_MISSING:-default} for a default".to_string())
+        );
+        assert!(interpolate("${DNSCHECKER_TEST_ROUTER".to_string())
+            .unwrap_err()
+            .starts_with("unterminated placeholder"));
+        assert_eq!(
+            interpolate("${not valid}".to_string()),
+            Err("invalid placeholder ${not valid}".to_string())
+        );
+
+        let config = Config::from_toml("api_key = \"${DNSCHECKER_TEST_MISSING}\"").unwrap();
+        assert!(config
+            .to_env()
+            .unwrap_err()
+            .starts_with("api_key: environment variable DNSCHECKER_TEST_MISSING is not set"));
+
+        // Assert that placeholders work for typed keys, and the interpolated value is type-checked
+        env::set_var("DNSCHECKER_TEST_INTERVAL", "15m");
+        env::set_var("DNSCHECKER_TEST_VERIFY", "false");
+        let config = Config::from_toml(
+            "dns_retry_delay = \"${DNSCHECKER_TEST_INTERVAL}\"\napi_verify_tls = \"${DNSCHECKER_TEST_VERIFY}\"",
+        )
+        .unwrap();
+        let mut vars = config.to_env().unwrap();
+        vars.sort();
+        assert_eq!(
+            vars,
+            vec![
+                ("API_VERIFY_TLS".to_string(), "false".to_string()),
+                ("DNS_RETRY_DELAY".to_string(), "15m".to_string()),
+            ]
+        );
+        let config = Config::from_toml("api_verify_tls = \"${DNSCHECKER_TEST_INTERVAL}\"").unwrap();
+        assert!(config
+            .to_env()
+            .unwrap_err()
+            .starts_with("api_verify_tls: expected true or false"));
+    }
+
+    #[test]
+    fn test_merge_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        std::fs::create_dir(path("conf.d")).unwrap();
+        std::fs::write(
+            path("dnschecker.toml"),
+            "include = [\"hosts.toml\", \"conf.d\"]\nchat_id = \"-100\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            path("hosts.toml"),
+            "dns_hostname = \"home.example.com\"\nchat_id = \"-200\"\n[profiles.office]\ndns_hostname = \"office.example.com\"\n",
+        )
+        .unwrap();
+        std::fs::write(path("conf.d/10-telegram.yaml"), "telegram_token: abc\n").unwrap();
+        std::fs::write(path("conf.d/20-same.toml"), "telegram_token = \"abc\"\n").unwrap();
+        std::fs::write(path("conf.d/README"), "ignored").unwrap();
+
+        let main = path("dnschecker.toml").to_str().unwrap().to_string();
+        let load = |main: &str| {
+            let text = std::fs::read_to_string(main).unwrap();
+            Config::parse(main, &text).unwrap().merge_includes(main)
+        };
+        let mut vars = load(&main)
+            .unwrap()
+            .select_profile(Some("office"))
+            .unwrap()
+            .to_env()
+            .unwrap();
+        vars.sort();
+
+        // Assert that the including file overrides its includes, and equal values do not conflict
+        assert_eq!(
+            vars,
+            vec![
+                ("CHAT_ID".to_string(), "-100".to_string()),
+                ("DNS_HOSTNAME".to_string(), "office.example.com".to_string()),
+                ("TELEGRAM_TOKEN".to_string(), "abc".to_string()),
+            ]
+        );
+
+        // Assert that included files with different values conflict
+        std::fs::write(path("conf.d/30-other.toml"), "telegram_token = \"xyz\"\n").unwrap();
+        let error = load(&main).unwrap_err();
+        assert!(error.starts_with("telegram_token is set differently in"));
+        assert!(error.ends_with("30-other.toml"));
+    }
+
+    #[test]
+    fn test_select_profile() {
+        let text = r#"
+            url = "https://192.168.1.1"
+            dns_hostname = "home.example.com"
+            heartbeat_interval = "1h"
+
+            [profiles.default]
+            dns_hostname = "default.example.com"
+
+            [profiles.office]
+            url = "https://10.0.0.1"
+            dns_hostname = "office.example.com"
+
+            [profiles.branch]
+            inherits = "office"
+            dns_hostname = "branch.example.com"
+        "#;
+        let env_of = |profile| {
+            let mut vars = Config::from_toml(text)
+                .unwrap()
+                .select_profile(profile)
+                .unwrap()
+                .to_env()
+                .unwrap();
+            vars.sort();
+            vars
+        };
+
+        // Assert that a profile overrides the base and inherits from its parent
+        assert_eq!(
+            env_of(Some("branch")),
+            vec![
+                ("DNS_HOSTNAME".to_string(), "branch.example.com".to_string()),
+                ("HEARTBEAT_INTERVAL".to_string(), "1h".to_string()),
+                ("URL".to_string(), "https://10.0.0.1".to_string()),
+            ]
+        );
+        assert!(env_of(None).contains(&(
+            "DNS_HOSTNAME".to_string(),
+            "default.example.com".to_string()
+        )));
+
+        let error = Config::from_toml(text)
+            .unwrap()
+            .select_profile(Some("home"))
+            .unwrap_err();
+        assert_eq!(
+            error,
+            "unknown profile home, available profiles: branch, default, office"
+        );
+        let cycle = "[profiles.a]\ninherits = \"b\"\n[profiles.b]\ninherits = \"a\"\n";
+        assert_eq!(
+            Config::from_toml(cycle)
+                .unwrap()
+                .select_profile(Some("a"))
+                .unwrap_err(),
+            "profile a inherits from itself"
+        );
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+}
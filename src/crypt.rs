@@ -0,0 +1,130 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::Engine;
+use std::env;
+
+/// The prefix of an encrypted value in the config, followed by the base64 encoded nonce and ciphertext.
+const PREFIX: &str = "enc:v1:";
+
+/// The length of an AES-GCM nonce in bytes.
+const NONCE_LEN: usize = 12;
+
+/// Returns whether a config value is encrypted.
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// Retrieves the key that encrypted config values are decrypted with.
+///
+/// This function retrieves the base64 encoded 256-bit key from the "CONFIG_KEY" environment variable,
+/// or from the file in the "CONFIG_KEY_FILE" environment variable (e.g. a mounted secret).
+/// The key is never read from the config itself, so the config can be kept in git.
+///
+/// # Returns
+///
+/// * A `Result<Key<Aes256Gcm>, String>` that holds the key, or an `Err` with a message if it is missing or invalid.
+pub fn config_key() -> Result<Key<Aes256Gcm>, String> {
+    let encoded = match (env::var("CONFIG_KEY"), env::var("CONFIG_KEY_FILE")) {
+        (Ok(key), _) => key,
+        (Err(_), Ok(path)) => std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read CONFIG_KEY_FILE {}: {}", path, e))?,
+        _ => {
+            return Err(
+                "encrypted value, but neither CONFIG_KEY nor CONFIG_KEY_FILE is set".to_string(),
+            )
+        }
+    };
+    let key = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|_| "CONFIG_KEY is not valid base64".to_string())?;
+    if key.len() != 32 {
+        return Err(format!("CONFIG_KEY must be 32 bytes, got {}", key.len()));
+    }
+    Ok(*Key::<Aes256Gcm>::from_slice(&key))
+}
+
+/// Generates a random key for `config_key`.
+///
+/// # Returns
+///
+/// * A `String` that holds the base64 encoded key.
+pub fn generate_key() -> String {
+    base64::engine::general_purpose::STANDARD.encode(Aes256Gcm::generate_key(OsRng))
+}
+
+/// Encrypts a config value with AES-256-GCM.
+///
+/// # Arguments
+///
+/// * `key`: A `&Key<Aes256Gcm>` that holds the key.
+/// * `plaintext`: A `&str` that holds the value, e.g. the Telegram bot token.
+///
+/// # Returns
+///
+/// * A `String` that holds "enc:v1:" followed by the base64 encoded random nonce and ciphertext.
+pub fn encrypt(key: &Key<Aes256Gcm>, plaintext: &str) -> String {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = Aes256Gcm::new(key)
+        .encrypt(&nonce, plaintext.as_bytes())
+        .expect("AES-GCM encrypts values of any config length");
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&ciphertext);
+    format!(
+        "{}{}",
+        PREFIX,
+        base64::engine::general_purpose::STANDARD.encode(sealed)
+    )
+}
+
+/// Decrypts a config value encrypted by `encrypt`.
+///
+/// # Arguments
+///
+/// * `key`: A `&Key<Aes256Gcm>` that holds the key.
+/// * `value`: A `&str` that holds the encrypted value, starting with "enc:v1:".
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds the value, or an `Err` with a message if it was not encrypted with the key or was modified.
+pub fn decrypt(key: &Key<Aes256Gcm>, value: &str) -> Result<String, String> {
+    let encoded = value
+        .strip_prefix(PREFIX)
+        .ok_or("value is not encrypted".to_string())?;
+    let sealed = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|_| "encrypted value is not valid base64".to_string())?;
+    if sealed.len() < NONCE_LEN {
+        return Err("encrypted value is too short".to_string());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let plaintext = Aes256Gcm::new(key)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "cannot decrypt value, wrong CONFIG_KEY or modified value".to_string())?;
+    String::from_utf8(plaintext).map_err(|_| "decrypted value is not UTF-8".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt() {
+        let key = Aes256Gcm::generate_key(OsRng);
+        let encrypted = encrypt(&key, "123456:ABC-DEF");
+
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("ABC-DEF"));
+        assert_eq!(decrypt(&key, &encrypted).unwrap(), "123456:ABC-DEF");
+
+        // Assert that a different key or a modified value is rejected
+        let other = Aes256Gcm::generate_key(OsRng);
+        assert!(decrypt(&other, &encrypted).is_err());
+        let engine = base64::engine::general_purpose::STANDARD;
+        let mut sealed = engine.decode(&encrypted[PREFIX.len()..]).unwrap();
+        *sealed.last_mut().unwrap() ^= 1;
+        let modified = format!("{}{}", PREFIX, engine.encode(sealed));
+        assert!(decrypt(&key, &modified).is_err());
+        assert!(decrypt(&key, "enc:v1:AAAA").is_err());
+        assert!(decrypt(&key, "plain").is_err());
+    }
+}
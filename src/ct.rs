@@ -0,0 +1,221 @@
+use crate::events;
+use crate::http;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::vars::{display_hostname, split_list};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::env;
+use std::time::Duration;
+
+/// A certificate logged in Certificate Transparency, as listed by crt.sh.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CtEntry {
+    /// The crt.sh ID of the certificate.
+    pub id: u64,
+    /// The distinguished name of the issuer, e.g. "C=US, O=Let's Encrypt, CN=R3".
+    pub issuer_name: String,
+    /// The names covered by the certificate, separated by newlines.
+    pub name_value: String,
+    /// The start of the validity of the certificate.
+    #[serde(default)]
+    pub not_before: String,
+}
+
+/// Watches Certificate Transparency logs for new certificates covering the hostname.
+///
+/// This function is enabled by setting the "CT_WATCH" environment variable to "true".
+/// It retrieves the certificates logged for the hostname from the crt.sh compatible service in "CT_URL"
+/// (defaulting to "https://crt.sh") using the `fetch_certificates` function.
+///
+/// The IDs of the certificates are compared with the IDs stored in the CT file ("CT_FILE", defaulting to "ct_seen" in the state directory).
+/// On the first check the certificates are only stored. After that, a "certificate" message is sent for every new certificate,
+/// unless its issuer contains one of the expected issuers in "CT_ISSUERS" (e.g. "Let's Encrypt"), case-insensitively.
+/// If the certificates cannot be retrieved, it logs a warning and keeps the stored IDs.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+pub fn check_certificates(hostname: &str) {
+    if env::var("CT_WATCH").as_deref() != Ok("true") {
+        return;
+    }
+    let url = env::var("CT_URL").unwrap_or("https://crt.sh".to_string());
+    let ct_file = state_path("CT_FILE", "ct_seen");
+    let issuers = split_list(&env::var("CT_ISSUERS").unwrap_or_default());
+
+    let entries = match fetch_certificates(&url, hostname) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("{}", e);
+            return;
+        }
+    };
+    let previous = read_state(&ct_file);
+    let seen: BTreeSet<u64> = previous
+        .lines()
+        .filter_map(|line| line.parse().ok())
+        .collect();
+    let mut ids: BTreeSet<u64> = entries.iter().map(|entry| entry.id).collect();
+    if previous.is_empty() {
+        log::info!("Tracking {} certificates of {}", ids.len(), hostname);
+    } else {
+        for entry in unexpected_certificates(&entries, &seen, &issuers) {
+            let text = certificate_message(hostname, &url, entry);
+            log::info!("{}", text);
+            let message = Message::new("certificate", &text)
+                .with_field("hostname", hostname)
+                .with_field("certificate_id", &entry.id.to_string())
+                .with_field("issuer", &entry.issuer_name);
+            if !events::alert(&message) {
+                log::warn!("Failed to send certificate message");
+                // Leave the certificate out so it is reported again on the next check
+                ids.remove(&entry.id);
+            }
+        }
+    }
+    let state: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+    write_state(&ct_file, &state.join("\n"));
+}
+
+/// Retrieves the certificates logged for a hostname.
+///
+/// # Arguments
+///
+/// * `url`: A `&str` that specifies the URL of the crt.sh compatible service.
+/// * `hostname`: A `&str` that specifies the hostname.
+///
+/// # Returns
+///
+/// * A `Result<Vec<CtEntry>, String>` that holds the certificates, or an `Err` with a message if the request fails.
+pub fn fetch_certificates(url: &str, hostname: &str) -> Result<Vec<CtEntry>, String> {
+    let client = http::client()?;
+    let response = client
+        .get(url.trim_end_matches('/'))
+        .query(&[("q", hostname), ("output", "json")])
+        // crt.sh is slow for names with many certificates
+        .timeout(Duration::from_secs(60))
+        .send()
+        .map_err(|e| format!("Failed to query Certificate Transparency logs: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Certificate Transparency service returned {}",
+            response.status()
+        ));
+    }
+    response.json::<Vec<CtEntry>>().map_err(|e| {
+        format!(
+            "Invalid answer from Certificate Transparency service: {}",
+            e
+        )
+    })
+}
+
+/// Selects the new certificates that were not issued by an expected issuer.
+///
+/// # Arguments
+///
+/// * `entries`: A `&[CtEntry]` that holds the logged certificates.
+/// * `seen`: A `&BTreeSet<u64>` that holds the IDs of the certificates seen before.
+/// * `issuers`: A `&[String]` that holds the expected issuers; if empty, every new certificate is selected.
+///
+/// # Returns
+///
+/// * A `Vec<&CtEntry>` that holds the unexpected certificates, each certificate once.
+fn unexpected_certificates<'a>(
+    entries: &'a [CtEntry],
+    seen: &BTreeSet<u64>,
+    issuers: &[String],
+) -> Vec<&'a CtEntry> {
+    let mut reported = BTreeSet::new();
+    entries
+        .iter()
+        .filter(|entry| !seen.contains(&entry.id) && reported.insert(entry.id))
+        .filter(|entry| {
+            let issuer = entry.issuer_name.to_lowercase();
+            !issuers
+                .iter()
+                .any(|expected| issuer.contains(&expected.to_lowercase()))
+        })
+        .collect()
+}
+
+/// Builds the text of the certificate message.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `url`: A `&str` that specifies the URL of the crt.sh compatible service.
+/// * `entry`: A `&CtEntry` that holds the certificate.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message.
+fn certificate_message(hostname: &str, url: &str, entry: &CtEntry) -> String {
+    let names: Vec<&str> = entry.name_value.lines().collect();
+    format!(
+        "New certificate issued for {}!\nIssuer: {}\nNames: {}\nValid from: {}\n{}/?id={}",
+        display_hostname(hostname),
+        entry.issuer_name,
+        names.join(", "),
+        entry.not_before,
+        url.trim_end_matches('/'),
+        entry.id
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    fn entry(id: u64, issuer: &str) -> CtEntry {
+        CtEntry {
+            id,
+            issuer_name: issuer.to_string(),
+            name_value: "home.example.com\nwww.home.example.com".to_string(),
+            not_before: "2024-05-01T00:00:00".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_fetch_certificates() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/")
+                .query_param("q", "home.example.com")
+                .query_param("output", "json");
+            then.status(200).body(
+                r#"[{"issuer_ca_id":1,"issuer_name":"C=US, O=Let's Encrypt, CN=R3","common_name":"home.example.com","name_value":"home.example.com\nwww.home.example.com","id":42,"not_before":"2024-05-01T00:00:00"}]"#,
+            );
+        });
+
+        let result = fetch_certificates(&server.base_url(), "home.example.com").unwrap();
+        mock.assert();
+        assert_eq!(result, [entry(42, "C=US, O=Let's Encrypt, CN=R3")]);
+    }
+
+    #[test]
+    fn test_unexpected_certificates() {
+        let entries = vec![
+            entry(1, "C=US, O=Let's Encrypt, CN=R3"),
+            entry(2, "C=US, O=Let's Encrypt, CN=R10"),
+            entry(3, "C=XX, O=Rogue CA"),
+            entry(3, "C=XX, O=Rogue CA"),
+        ];
+        let seen = BTreeSet::from([1]);
+
+        let result = unexpected_certificates(&entries, &seen, &["let's encrypt".to_string()]);
+        let ids: Vec<u64> = result.iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, [3]);
+
+        let result = unexpected_certificates(&entries, &seen, &[]);
+        let ids: Vec<u64> = result.iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, [2, 3]);
+
+        let text = certificate_message("home.example.com", "https://crt.sh/", result[1]);
+        assert!(text.contains("Names: home.example.com, www.home.example.com"));
+        assert!(text.ends_with("https://crt.sh/?id=3"));
+    }
+}
@@ -0,0 +1,273 @@
+use crate::dns;
+use crate::events;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::vars::display_hostname;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use trust_dns_resolver::proto::rr::RecordType;
+
+/// Verifies that the delegation of a zone is consistent between the parent zone and the zone itself.
+///
+/// This function retrieves the value of the "DELEGATION_ZONE" environment variable (e.g. "example.com").
+/// If it is not set, the delegation check is disabled and the function returns immediately.
+///
+/// It inspects the delegation using the `inspect_delegation` function and compares the problems found
+/// with the state stored in the delegation file ("DELEGATION_FILE", defaulting to "delegation_state" in the state directory).
+/// A "delegation" message is sent when the problems change, including when they are resolved.
+/// If the delegation cannot be inspected at all, e.g. without network access, it logs a warning and keeps the state.
+pub fn check_delegation() {
+    let zone = match env::var("DELEGATION_ZONE") {
+        Ok(zone) => zone.trim_end_matches('.').to_lowercase(),
+        Err(_) => return,
+    };
+    let delegation_file = state_path("DELEGATION_FILE", "delegation_state");
+
+    let problems = match inspect_delegation(&zone) {
+        Ok(problems) => problems,
+        Err(e) => {
+            log::warn!("Failed to inspect the delegation of {}: {}", zone, e);
+            return;
+        }
+    };
+    let state = problems.join("\n");
+    let previous = read_state(&delegation_file);
+    if previous == state {
+        return;
+    }
+    if previous.is_empty() {
+        log::info!("Tracking the delegation of {}", zone);
+    }
+    if !previous.is_empty() || !problems.is_empty() {
+        let text = delegation_message(&zone, &problems);
+        log::info!("{}", text);
+        if !events::alert(&Message::new("delegation", &text).with_field("zone", &zone)) {
+            log::warn!("Failed to send delegation message");
+            return;
+        }
+    }
+    write_state(&delegation_file, &state);
+}
+
+/// Inspects the delegation of a zone.
+///
+/// This function queries a name server of the parent zone directly for the NS records of the zone,
+/// which answers with a referral listing the delegated name servers and their glue addresses.
+/// It then queries every delegated name server directly for the NS records of the zone
+/// and checks the result using the `delegation_problems` function.
+///
+/// # Arguments
+///
+/// * `zone`: A `&str` that specifies the zone, in lower case and without the trailing dot.
+///
+/// # Returns
+///
+/// * A `Result<Vec<String>, String>` that holds the problems found, empty if the delegation is consistent.
+/// * If no name server of the parent zone answers, it returns an `Err` with a message.
+fn inspect_delegation(zone: &str) -> Result<Vec<String>, String> {
+    let parent = zone
+        .split_once('.')
+        .map(|(_, parent)| parent)
+        .unwrap_or(".");
+    let parent_servers = dns::lookup_ns(parent)?;
+    let referral = parent_servers
+        .iter()
+        .flat_map(|server| dns::lookup_addresses(server))
+        .find_map(|ip| dns::query_server(SocketAddr::new(ip, 53), zone, RecordType::NS).ok())
+        .ok_or(format!("No name server of {} answered", parent))?;
+
+    let mut delegated = dns::ns_names(referral.name_servers());
+    delegated.extend(dns::ns_names(referral.answers()));
+    let glue = dns::addresses(referral.additionals());
+
+    let mut resolved = BTreeMap::new();
+    let mut served = BTreeMap::new();
+    for server in &delegated {
+        let mut addresses = dns::lookup_addresses(server);
+        if addresses.is_empty() {
+            addresses.extend(glue.get(server).into_iter().flatten());
+        }
+        let answer = addresses.iter().find_map(|ip| {
+            dns::query_server(SocketAddr::new(*ip, 53), zone, RecordType::NS)
+                .ok()
+                .filter(|response| response.authoritative())
+                .map(|response| dns::ns_names(response.answers()))
+        });
+        resolved.insert(server.clone(), addresses.into_iter().collect());
+        served.insert(server.clone(), answer);
+    }
+    Ok(delegation_problems(
+        zone, &delegated, &glue, &resolved, &served,
+    ))
+}
+
+/// Lists the problems of a delegation.
+///
+/// The following problems are reported:
+///
+/// * The parent zone does not delegate the zone.
+/// * A delegated name server does not answer authoritatively for the zone (lame delegation).
+/// * The NS records served by the zone differ from the delegation in the parent zone.
+/// * A glue address in the parent zone is not an address of the name server (stale glue).
+///
+/// # Arguments
+///
+/// * `zone`: A `&str` that specifies the zone.
+/// * `delegated`: A `&BTreeSet<String>` that holds the name servers delegated by the parent zone.
+/// * `glue`: A `&BTreeMap<String, BTreeSet<IpAddr>>` that holds the glue addresses from the parent zone.
+/// * `resolved`: A `&BTreeMap<String, BTreeSet<IpAddr>>` that holds the addresses of each name server.
+/// * `served`: A `&BTreeMap<String, Option<BTreeSet<String>>>` that holds the NS records served by each name server,
+///   `None` if it did not answer authoritatively.
+///
+/// # Returns
+///
+/// * A `Vec<String>` that holds a description of each problem, empty if the delegation is consistent.
+fn delegation_problems(
+    zone: &str,
+    delegated: &BTreeSet<String>,
+    glue: &BTreeMap<String, BTreeSet<IpAddr>>,
+    resolved: &BTreeMap<String, BTreeSet<IpAddr>>,
+    served: &BTreeMap<String, Option<BTreeSet<String>>>,
+) -> Vec<String> {
+    if delegated.is_empty() {
+        return vec![format!("The parent zone does not delegate {}", zone)];
+    }
+    let join = |names: Vec<&String>| {
+        names
+            .iter()
+            .map(|name| name.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ")
+    };
+    let mut problems = Vec::new();
+    let mut in_zone = BTreeSet::new();
+    for (server, answer) in served {
+        match answer {
+            Some(names) => in_zone.extend(names.iter().cloned()),
+            None => problems.push(format!(
+                "Lame delegation: {} does not answer authoritatively",
+                server
+            )),
+        }
+    }
+    if !in_zone.is_empty() {
+        let only_parent: Vec<&String> = delegated.difference(&in_zone).collect();
+        if !only_parent.is_empty() {
+            problems.push(format!("Only in the parent zone: {}", join(only_parent)));
+        }
+        let only_zone: Vec<&String> = in_zone.difference(delegated).collect();
+        if !only_zone.is_empty() {
+            problems.push(format!("Only in the zone: {}", join(only_zone)));
+        }
+    }
+    for (server, addresses) in glue {
+        let actual = match resolved.get(server) {
+            Some(actual) if !actual.is_empty() => actual,
+            _ => continue,
+        };
+        let stale: Vec<String> = addresses
+            .difference(actual)
+            .map(|ip| ip.to_string())
+            .collect();
+        if !stale.is_empty() {
+            problems.push(format!("Stale glue for {}: {}", server, stale.join(", ")));
+        }
+    }
+    problems
+}
+
+/// Builds the text of the delegation message.
+///
+/// # Arguments
+///
+/// * `zone`: A `&str` that specifies the zone.
+/// * `problems`: A `&[String]` that holds the problems found, empty if they are resolved.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message.
+fn delegation_message(zone: &str, problems: &[String]) -> String {
+    if problems.is_empty() {
+        return format!(
+            "Delegation of {} is consistent again",
+            display_hostname(zone)
+        );
+    }
+    format!(
+        "Delegation of {} is inconsistent!\n{}",
+        display_hostname(zone),
+        problems.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> BTreeSet<String> {
+        list.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn ips(list: &[&str]) -> BTreeSet<IpAddr> {
+        list.iter().map(|ip| ip.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_delegation_problems() {
+        let delegated = names(&["ns1.example.com", "ns2.example.com"]);
+        let glue = BTreeMap::from([("ns1.example.com".to_string(), ips(&["192.0.2.1"]))]);
+        let resolved = BTreeMap::from([
+            (
+                "ns1.example.com".to_string(),
+                ips(&["192.0.2.1", "2001:db8::1"]),
+            ),
+            ("ns2.example.com".to_string(), ips(&["198.51.100.1"])),
+        ]);
+        let served = BTreeMap::from([
+            ("ns1.example.com".to_string(), Some(delegated.clone())),
+            ("ns2.example.com".to_string(), Some(delegated.clone())),
+        ]);
+
+        // Assert that a consistent delegation has no problems
+        let result = delegation_problems("example.com", &delegated, &glue, &resolved, &served);
+        assert!(result.is_empty());
+
+        // Assert that lame servers, differing NS records and stale glue are reported
+        let served = BTreeMap::from([
+            (
+                "ns1.example.com".to_string(),
+                Some(names(&["ns1.example.com", "ns3.example.com"])),
+            ),
+            ("ns2.example.com".to_string(), None),
+        ]);
+        let glue = BTreeMap::from([("ns1.example.com".to_string(), ips(&["192.0.2.9"]))]);
+        let result = delegation_problems("example.com", &delegated, &glue, &resolved, &served);
+        assert_eq!(
+            result,
+            [
+                "Lame delegation: ns2.example.com does not answer authoritatively",
+                "Only in the parent zone: ns2.example.com",
+                "Only in the zone: ns3.example.com",
+                "Stale glue for ns1.example.com: 192.0.2.9",
+            ]
+        );
+
+        // Assert that a missing delegation is reported
+        let result = delegation_problems(
+            "example.com",
+            &BTreeSet::new(),
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+        );
+        assert_eq!(result, ["The parent zone does not delegate example.com"]);
+    }
+
+    #[test]
+    fn test_delegation_message() {
+        let result = delegation_message("example.com", &["Stale glue".to_string()]);
+        assert!(result.starts_with("Delegation of example.com is inconsistent!"));
+        assert!(delegation_message("example.com", &[]).contains("consistent again"));
+    }
+}
@@ -0,0 +1,334 @@
+use crate::events::{Event, Subscriber};
+use crate::state::{read_state, state_path, write_state};
+use chrono::{DateTime, Local};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// The health of the external dependencies of the process, shared by every module.
+pub static DEPENDENCIES: Dependencies = Dependencies::new();
+
+/// The health of one external dependency, e.g. a resolver, the router API or a notification channel.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DependencyHealth {
+    /// When the dependency last answered, `None` if it never did.
+    pub last_success: Option<DateTime<Local>>,
+    /// When the dependency last failed, `None` if it never did.
+    pub last_failure: Option<DateTime<Local>>,
+    /// A summary of the last error, kept after the dependency recovers.
+    pub last_error: String,
+    /// The number of failures since the last success.
+    pub consecutive_failures: u64,
+    /// The number of successful calls, for the failure rate.
+    pub successes: u64,
+    /// The number of failed calls, for the failure rate.
+    pub failures: u64,
+}
+
+impl DependencyHealth {
+    /// Returns whether the last call to the dependency failed.
+    pub fn is_failing(&self) -> bool {
+        self.consecutive_failures > 0
+    }
+
+    /// Returns the percentage of calls that failed, `None` if the dependency was never called.
+    pub fn failure_rate(&self) -> Option<f64> {
+        let calls = self.successes + self.failures;
+        (calls > 0).then(|| self.failures as f64 * 100.0 / calls as f64)
+    }
+
+    /// Converts the health to JSON, with the timestamps in RFC 3339.
+    fn to_json(&self) -> Value {
+        json!({
+            "healthy": !self.is_failing(),
+            "last_success": self.last_success.map(|time| time.to_rfc3339()),
+            "last_failure": self.last_failure.map(|time| time.to_rfc3339()),
+            "last_error": self.last_error,
+            "consecutive_failures": self.consecutive_failures,
+            "successes": self.successes,
+            "failures": self.failures,
+        })
+    }
+
+    /// Restores the health from JSON written by `to_json`; missing or invalid values are left empty.
+    fn from_json(value: &Value) -> DependencyHealth {
+        let time = |name: &str| {
+            value[name]
+                .as_str()
+                .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                .map(|time| time.with_timezone(&Local))
+        };
+        DependencyHealth {
+            last_success: time("last_success"),
+            last_failure: time("last_failure"),
+            last_error: value["last_error"].as_str().unwrap_or_default().to_string(),
+            consecutive_failures: value["consecutive_failures"].as_u64().unwrap_or_default(),
+            successes: value["successes"].as_u64().unwrap_or_default(),
+            failures: value["failures"].as_u64().unwrap_or_default(),
+        }
+    }
+}
+
+/// The health of every external dependency the checker called, by name, e.g. "resolver google",
+/// "source api" (the router API) or "notifier telegram".
+///
+/// The health is persisted in the dependencies file (see `dependencies_file`),
+/// so the `status` command can read it and it survives restarts.
+#[derive(Debug)]
+pub struct Dependencies {
+    entries: Mutex<BTreeMap<String, DependencyHealth>>,
+}
+
+impl Default for Dependencies {
+    fn default() -> Dependencies {
+        Dependencies::new()
+    }
+}
+
+impl Dependencies {
+    /// Creates an empty registry.
+    pub const fn new() -> Dependencies {
+        Dependencies {
+            entries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Records that a dependency answered.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: A `&str` that specifies the dependency, e.g. "resolver google".
+    pub fn record_success(&self, name: &str) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let health = entries.entry(name.to_string()).or_default();
+        health.last_success = Some(Local::now());
+        health.consecutive_failures = 0;
+        health.successes += 1;
+    }
+
+    /// Records that a dependency failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: A `&str` that specifies the dependency, e.g. "resolver google".
+    /// * `error`: A `&str` that summarizes the error.
+    pub fn record_failure(&self, name: &str, error: &str) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let health = entries.entry(name.to_string()).or_default();
+        health.last_failure = Some(Local::now());
+        health.last_error = error.to_string();
+        health.consecutive_failures += 1;
+        health.failures += 1;
+    }
+
+    /// Records the outcome of a call to a dependency, see `record_success` and `record_failure`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: A `&str` that specifies the dependency.
+    /// * `result`: A `&Result<T, String>` that holds the outcome of the call.
+    pub fn record<T>(&self, name: &str, result: &Result<T, String>) {
+        match result {
+            Ok(_) => self.record_success(name),
+            Err(e) => self.record_failure(name, e),
+        }
+    }
+
+    /// Returns a copy of the health of every dependency, by name.
+    pub fn snapshot(&self) -> BTreeMap<String, DependencyHealth> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Restores the health from a file written by `save`.
+    ///
+    /// If the file does not exist or cannot be parsed, the registry is left as it is.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: A `&str` that specifies the path of the dependencies file.
+    pub fn load(&self, path: &str) {
+        match load_dependencies(path) {
+            Ok(stored) => {
+                self.entries
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .extend(stored);
+            }
+            Err(e) => log::warn!("{}", e),
+        }
+    }
+
+    /// Stores the health of every dependency in a file as JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: A `&str` that specifies the path of the dependencies file.
+    pub fn save(&self, path: &str) {
+        write_state(path, &to_json(&self.snapshot()).to_string());
+    }
+}
+
+/// Reads the health of the dependencies from a file written by `Dependencies::save`.
+///
+/// # Arguments
+///
+/// * `path`: A `&str` that specifies the path of the dependencies file.
+///
+/// # Returns
+///
+/// * A `Result<BTreeMap<String, DependencyHealth>, String>` that holds the health by name, empty if the file does not exist.
+/// * If the file cannot be parsed, it returns an `Err` with a message.
+pub fn load_dependencies(path: &str) -> Result<BTreeMap<String, DependencyHealth>, String> {
+    let stored = read_state(path);
+    if stored.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    let values: Value = serde_json::from_str(&stored)
+        .map_err(|e| format!("Ignoring invalid dependencies file {}: {}", path, e))?;
+    Ok(values
+        .as_object()
+        .map(|entries| {
+            entries
+                .iter()
+                .map(|(name, value)| (name.clone(), DependencyHealth::from_json(value)))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Converts the health of the dependencies to JSON, as served on "/status".
+///
+/// # Arguments
+///
+/// * `entries`: A `&BTreeMap<String, DependencyHealth>` that holds the health by name.
+///
+/// # Returns
+///
+/// * A `Value` that holds an object with one entry per dependency.
+pub fn to_json(entries: &BTreeMap<String, DependencyHealth>) -> Value {
+    Value::Object(
+        entries
+            .iter()
+            .map(|(name, health)| (name.clone(), health.to_json()))
+            .collect(),
+    )
+}
+
+/// Describes the health of the dependencies, one line each, for the `status` command and the state dump.
+///
+/// # Arguments
+///
+/// * `entries`: A `&BTreeMap<String, DependencyHealth>` that holds the health by name.
+///
+/// # Returns
+///
+/// * A `Vec<String>` that holds the lines, e.g. "resolver google: failing (3 times), last success 2024-01-01T12:00:00+00:00, last error: timeout".
+pub fn status_lines(entries: &BTreeMap<String, DependencyHealth>) -> Vec<String> {
+    if entries.is_empty() {
+        return vec!["No dependency called yet".to_string()];
+    }
+    let time = |time: Option<DateTime<Local>>| match time {
+        Some(time) => time.to_rfc3339(),
+        None => "never".to_string(),
+    };
+    entries
+        .iter()
+        .map(|(name, health)| {
+            let mut line = if health.is_failing() {
+                format!(
+                    "{}: failing ({} times), last success {}",
+                    name,
+                    health.consecutive_failures,
+                    time(health.last_success)
+                )
+            } else {
+                format!("{}: ok, last success {}", name, time(health.last_success))
+            };
+            if let Some(last_failure) = health.last_failure {
+                line.push_str(&format!(
+                    ", last failure {}, last error: {}",
+                    last_failure.to_rfc3339(),
+                    health.last_error
+                ));
+            }
+            line
+        })
+        .collect()
+}
+
+/// Stores the health of the dependencies in `DEPENDENCIES` in the dependencies file after every verification.
+pub struct DependencySubscriber;
+
+impl Subscriber for DependencySubscriber {
+    fn name(&self) -> &str {
+        "dependencies"
+    }
+
+    fn handles(&self, event: &Event) -> bool {
+        matches!(event, Event::Checked(_))
+    }
+
+    fn receive(&self, _event: &Event) -> bool {
+        DEPENDENCIES.save(&dependencies_file());
+        true
+    }
+}
+
+/// Retrieves the path of the dependencies file from the "DEPENDENCIES_FILE" environment variable.
+///
+/// # Returns
+///
+/// * A `String` that holds the path, defaulting to "dependencies_state" in the state directory.
+pub fn dependencies_file() -> String {
+    state_path("DEPENDENCIES_FILE", "dependencies_state")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dependencies() {
+        let dependencies = Dependencies::new();
+        dependencies.record_success("resolver google");
+        dependencies.record::<()>("notifier telegram", &Err("timeout".to_string()));
+        dependencies.record_failure("notifier telegram", "HTTP 502");
+
+        let entries = dependencies.snapshot();
+        assert!(!entries["resolver google"].is_failing());
+        assert_eq!(entries["notifier telegram"].consecutive_failures, 2);
+        assert_eq!(entries["notifier telegram"].last_error, "HTTP 502");
+
+        let lines = status_lines(&entries);
+        assert!(lines[0].starts_with(
+            "notifier telegram: failing (2 times), last success never, last failure "
+        ));
+        assert!(lines[0].ends_with(", last error: HTTP 502"));
+        assert!(lines[1].starts_with("resolver google: ok, last success "));
+
+        // Assert that a success clears the failures but keeps the last error
+        dependencies.record_success("notifier telegram");
+        let health = &dependencies.snapshot()["notifier telegram"];
+        assert!(!health.is_failing());
+        assert_eq!(health.last_error, "HTTP 502");
+        assert!((health.failure_rate().unwrap() - 66.67).abs() < 0.01);
+
+        // Assert that the health survives a restart
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        dependencies.save(path);
+        let restored = Dependencies::new();
+        restored.load(path);
+        assert_eq!(
+            to_json(&restored.snapshot()),
+            to_json(&dependencies.snapshot())
+        );
+        assert_eq!(
+            to_json(&restored.snapshot())["resolver google"]["healthy"],
+            true
+        );
+    }
+}
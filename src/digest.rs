@@ -0,0 +1,179 @@
+use crate::chart::{self, Sample};
+use crate::events;
+use crate::history::{self, CheckRecord};
+use crate::notify::Message;
+use crate::vars::parse_duration;
+use chrono::{DateTime, Local};
+use std::env;
+use std::time::Duration;
+
+/// Retrieves the time between two digests of the checker.
+///
+/// The interval is read from the "DIGEST_INTERVAL" environment variable, e.g. "1d".
+/// An interval of "0", the default, disables the digest.
+///
+/// # Returns
+///
+/// * A `Duration` that holds the digest interval.
+pub fn digest_interval() -> Duration {
+    match env::var("DIGEST_INTERVAL") {
+        Ok(value) => parse_duration(&value).unwrap_or_else(|| {
+            log::warn!(
+                "Invalid DIGEST_INTERVAL \"{}\", disabling the digest",
+                value
+            );
+            Duration::ZERO
+        }),
+        Err(_) => Duration::ZERO,
+    }
+}
+
+/// Returns whether digests carry a chart, i.e. "DIGEST_CHART" is "true".
+pub fn chart_enabled() -> bool {
+    env::var("DIGEST_CHART").is_ok_and(|value| value == "true")
+}
+
+/// Converts a history record to a sample of the chart, with the total latency of the verification.
+fn sample(record: &CheckRecord) -> Sample {
+    Sample {
+        in_sync: Some(record.in_sync),
+        latency_ms: (!record.latencies.is_empty()).then(|| record.latencies.values().sum()),
+    }
+}
+
+/// Builds the "digest" message of the checker.
+///
+/// The message counts the verifications since the previous digest and how many of them were out of sync,
+/// followed by the in-sync percentages (see `history::uptime_summary`).
+/// If `chart` is set and there were verifications, it carries a PNG chart of them (see `chart::sparkline_png`).
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the hostname.
+/// * `records`: A `&[CheckRecord]` that holds the history.
+/// * `since`: A `DateTime<Local>` that specifies when the previous digest was sent.
+/// * `chart`: A `bool` that specifies whether the chart is attached.
+///
+/// # Returns
+///
+/// * A `Message` that holds the digest.
+pub fn digest_message(
+    hostname: &str,
+    records: &[CheckRecord],
+    since: DateTime<Local>,
+    chart: bool,
+) -> Message {
+    let mut period: Vec<&CheckRecord> = records
+        .iter()
+        .filter(|record| record.timestamp >= since)
+        .collect();
+    period.sort_by_key(|record| record.timestamp);
+    let out_of_sync = period.iter().filter(|record| !record.in_sync).count();
+    let mut text = format!(
+        "DNS checker digest for {}\n{} checks since {}, {} out of sync\nDNS in sync: {}",
+        hostname,
+        period.len(),
+        since.format("%Y-%m-%d %H:%M"),
+        out_of_sync,
+        history::uptime_summary(records)
+    );
+    let png = if chart && !period.is_empty() {
+        let samples = period.iter().map(|record| sample(record)).collect();
+        chart::sparkline_png(&[samples])
+            .map_err(|e| log::warn!("{}", e))
+            .ok()
+    } else {
+        None
+    };
+    if png.is_some() {
+        text.push_str("\nChart: green in sync, red out of sync; bar height is the check latency.");
+    }
+    let message = Message::new("digest", &text)
+        .with_field("hostname", hostname)
+        .with_field("checks", &period.len().to_string())
+        .with_field("out_of_sync", &out_of_sync.to_string());
+    match png {
+        Some(png) => message.with_image(png),
+        None => message,
+    }
+}
+
+/// Sends the digest of the checker as a "digest" message, built from the history (see `digest_message`).
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the hostname.
+/// * `since`: A `DateTime<Local>` that specifies when the previous digest was sent.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether the message was sent.
+pub fn send_digest(hostname: &str, since: DateTime<Local>) -> bool {
+    let records = history::load_history(&history::history_file());
+    let message = digest_message(hostname, &records, since, chart_enabled());
+    log::info!("{}", message.text);
+    events::alert(&message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration as ChronoDuration;
+    use std::collections::BTreeMap;
+
+    fn record(minutes_ago: i64, in_sync: bool, latency_ms: Option<u64>) -> CheckRecord {
+        CheckRecord {
+            timestamp: (Local::now() - ChronoDuration::minutes(minutes_ago)).fixed_offset(),
+            hostname: "home.example.com".to_string(),
+            dns_ip: "192.0.2.1".to_string(),
+            wan_ip: "192.0.2.1".to_string(),
+            in_sync,
+            latencies: latency_ms
+                .map(|latency| BTreeMap::from([("dns".to_string(), latency)]))
+                .unwrap_or_default(),
+        }
+    }
+
+    #[test]
+    fn test_digest_message() {
+        let records = vec![
+            record(120, false, Some(10)),
+            record(30, true, Some(20)),
+            record(20, false, None),
+            record(10, true, Some(40)),
+        ];
+        let since = Local::now() - ChronoDuration::hours(1);
+
+        let message = digest_message("home.example.com", &records, since, true);
+        assert_eq!(message.event, "digest");
+        assert!(message.text.contains("\n3 checks since "));
+        assert!(message.text.contains(", 1 out of sync\n"));
+        assert!(message.text.ends_with("bar height is the check latency."));
+        assert!(message
+            .fields
+            .contains(&("checks".to_string(), "3".to_string())));
+        assert!(message.image.is_some());
+
+        // Assert that the chart is left out when disabled or without checks in the period
+        assert!(digest_message("home.example.com", &records, since, false)
+            .image
+            .is_none());
+        let message = digest_message("home.example.com", &records[..1], since, true);
+        assert!(message.image.is_none());
+        assert!(message.text.contains("\n0 checks since "));
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut latencies = record(0, true, Some(20));
+        latencies.latencies.insert("api".to_string(), 30);
+        assert_eq!(
+            sample(&latencies),
+            Sample {
+                in_sync: Some(true),
+                latency_ms: Some(50)
+            }
+        );
+        assert_eq!(sample(&record(0, false, None)).latency_ms, None);
+    }
+}
@@ -0,0 +1,179 @@
+use crate::http;
+use crate::notify::{Message, Notifier};
+use base64::Engine;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::env;
+use std::time::Duration;
+use url::Url;
+
+/// Delivers notifications to a DingTalk group through a custom robot webhook.
+pub struct DingTalkNotifier {
+    url: String,
+    secret: Option<String>,
+}
+
+impl DingTalkNotifier {
+    /// Creates a DingTalk notifier from the environment.
+    ///
+    /// The notifier is enabled by setting "DINGTALK_WEBHOOK_URL" to the webhook of a custom robot,
+    /// e.g. "https://oapi.dingtalk.com/robot/send?access_token=<token>". If the robot uses the "signature"
+    /// security setting, "DINGTALK_SECRET" holds its secret (starting with "SEC"), see `signed_url`.
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<DingTalkNotifier>` that holds the notifier, or `None` if "DINGTALK_WEBHOOK_URL" is not set.
+    pub fn from_env() -> Option<DingTalkNotifier> {
+        let url = env::var("DINGTALK_WEBHOOK_URL")
+            .ok()
+            .filter(|url| !url.is_empty())?;
+        Some(DingTalkNotifier {
+            url,
+            secret: env::var("DINGTALK_SECRET")
+                .ok()
+                .filter(|secret| !secret.is_empty()),
+        })
+    }
+}
+
+impl Notifier for DingTalkNotifier {
+    fn name(&self) -> &str {
+        "dingtalk"
+    }
+
+    /// Posts the message as a text message to the webhook.
+    ///
+    /// DingTalk answers errors such as a wrong signature with status 200 and a non-zero "errcode",
+    /// so the message only counts as delivered if the code is 0.
+    fn send(&self, message: &Message) -> bool {
+        let url = match &self.secret {
+            Some(secret) => match signed_url(&self.url, secret, Utc::now().timestamp_millis()) {
+                Ok(url) => url,
+                Err(e) => {
+                    log::warn!("{}", e);
+                    return false;
+                }
+            },
+            None => self.url.clone(),
+        };
+        let client = match http::client() {
+            Ok(client) => client,
+            Err(e) => {
+                log::warn!("{}", e);
+                return false;
+            }
+        };
+        let response = client
+            .post(url)
+            .json(&json!({
+                "msgtype": "text",
+                "text": {"content": message.text},
+            }))
+            .timeout(Duration::from_secs(10))
+            .send();
+        match response {
+            Ok(response) if response.status().is_success() => {
+                let answer: Value = response.json().unwrap_or_default();
+                match answer["errcode"].as_i64() {
+                    Some(0) => true,
+                    _ => {
+                        log::warn!(
+                            "DingTalk returned code {}: {}",
+                            answer["errcode"],
+                            answer["errmsg"].as_str().unwrap_or("no message")
+                        );
+                        false
+                    }
+                }
+            }
+            Ok(response) => {
+                log::warn!("DingTalk returned {}", response.status());
+                false
+            }
+            Err(e) => {
+                log::warn!("Failed to make HTTPS request: {:?}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Signs the webhook URL of a DingTalk robot.
+///
+/// DingTalk expects the "timestamp" in milliseconds and the "sign" query parameters: the base64 encoded
+/// HMAC-SHA256 of "<timestamp>\n<secret>" with the secret as the key. The timestamp must be within an hour
+/// of the time of the DingTalk servers.
+///
+/// # Arguments
+///
+/// * `url`: A `&str` that specifies the webhook URL, with the access token.
+/// * `secret`: A `&str` that specifies the secret of the robot.
+/// * `timestamp`: An `i64` that specifies the current time in milliseconds since the epoch.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds the signed URL, or an `Err` with a message if the URL is invalid.
+fn signed_url(url: &str, secret: &str, timestamp: i64) -> Result<String, String> {
+    let mut url = Url::parse(url).map_err(|e| format!("Invalid DINGTALK_WEBHOOK_URL: {}", e))?;
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}\n{}", timestamp, secret).as_bytes());
+    let sign = base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes());
+    url.query_pairs_mut()
+        .append_pair("timestamp", &timestamp.to_string())
+        .append_pair("sign", &sign);
+    Ok(url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+
+    #[test]
+    fn test_signed_url() {
+        assert_eq!(
+            signed_url(
+                "https://oapi.dingtalk.com/robot/send?access_token=abc",
+                "secret",
+                1700000000000
+            ),
+            Ok("https://oapi.dingtalk.com/robot/send?access_token=abc&timestamp=1700000000000&sign=OuzzJR5%2BxZ4%2FEYwqtNt6sMYZQMTa%2FHEGvc9miJe7XzY%3D".to_string())
+        );
+        assert!(signed_url("not a url", "secret", 1700000000000).is_err());
+    }
+
+    #[test]
+    fn test_send() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method("POST")
+                .path("/robot/send")
+                .query_param("access_token", "abc")
+                .query_param_exists("sign")
+                .json_body(json!({"msgtype": "text", "text": {"content": "IP address mismatch!"}}));
+            then.status(200).body(r#"{"errcode": 0, "errmsg": "ok"}"#);
+        });
+        let notifier = DingTalkNotifier {
+            url: server.url("/robot/send?access_token=abc"),
+            secret: Some("secret".to_string()),
+        };
+        assert!(notifier.send(&Message::new("mismatch", "IP address mismatch!")));
+        mock.assert();
+
+        // Assert that an error code fails the delivery
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method("POST");
+            then.status(200)
+                .body(r#"{"errcode": 310000, "errmsg": "sign not match"}"#);
+        });
+        let notifier = DingTalkNotifier {
+            url: server.url("/robot/send?access_token=abc"),
+            secret: None,
+        };
+        assert!(!notifier.send(&Message::new("mismatch", "IP address mismatch!")));
+    }
+}
@@ -1,16 +1,46 @@
-use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
-use trust_dns_resolver::Resolver; // Import the ResolverConfig and ResolverOpts structs
+use crate::cache::DNS_CACHE;
+use crate::chaos::{self, Fault};
+use crate::http;
+use crate::tsig::{self, TsigKey};
+use crate::vars::{parse_duration, split_list};
+use ipnet::IpNet;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, StreamOwned};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::future::Future;
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use trust_dns_resolver::config::{
+    LookupIpStrategy, NameServerConfigGroup, Protocol, ResolverConfig, ResolverOpts,
+};
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::lookup::Lookup;
+use trust_dns_resolver::lookup_ip::LookupIp;
+use trust_dns_resolver::proto::op::{Edns, Message, MessageType, OpCode, Query, ResponseCode};
+use trust_dns_resolver::proto::rr::rdata::opt::{ClientSubnet, EdnsOption};
+use trust_dns_resolver::proto::rr::{Name, RData, Record, RecordType};
+use trust_dns_resolver::system_conf::read_system_conf;
+use trust_dns_resolver::TokioAsyncResolver;
+
+/// The resolvers built so far, by description and lookup strategy, see `shared_resolver`.
+static RESOLVERS: Mutex<BTreeMap<String, TokioAsyncResolver>> = Mutex::new(BTreeMap::new());
 
 /// Resolves a hostname to its corresponding IPv4 address.
 ///
 /// This function takes a hostname as an argument.
-/// It creates a `ResolverConfig` using the `ResolverConfig::google` function, which uses Google's DNS resolver.
+/// It creates a `ResolverConfig` for the upstream resolvers (see `default_resolver`), Google's DNS resolver unless configured otherwise.
 /// It also creates default `ResolverOpts` using the `ResolverOpts::default` function.
 ///
-/// It then creates a `Resolver` using the `Resolver::new` function with the `ResolverConfig` and `ResolverOpts`.
-/// If the function fails, it logs a warning and returns an empty `String`.
+/// It then takes the `TokioAsyncResolver` built once for this configuration (see `shared_resolver`).
+/// If the resolver cannot be built, it logs a warning and returns an empty `String`.
 ///
-/// It then attempts to look up the IP address of the hostname using the `Resolver::lookup_ip` function.
+/// Internationalized hostnames are converted to punycode using IDNA before the lookup.
+///
+/// It then attempts to look up the IP address of the hostname using the `TokioAsyncResolver::lookup_ip` function.
 /// If the function fails, it logs a warning and returns an empty `String`.
 ///
 /// It then iterates over the returned IP addresses and finds the first IPv4 address.
@@ -26,44 +56,2200 @@ use trust_dns_resolver::Resolver; // Import the ResolverConfig and ResolverOpts
 /// * A `String` that holds the IPv4 address of the hostname if the function succeeds.
 /// * If any step fails, it returns an empty `String`.
 pub fn resolve_hostname(hostname: &str) -> String {
-    let resolver = match Resolver::new(ResolverConfig::google(), ResolverOpts::default()) {
-        Ok(resolver) => resolver,
+    resolve_hostname_using(hostname, &default_resolver())
+}
+
+/// Resolves a hostname to its corresponding IPv4 address using the given resolver.
+///
+/// This function works like `resolve_hostname`, but queries the resolver described by the `resolver_config` function,
+/// e.g. the resolver of the local network or the internal resolver of a split-horizon setup.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the hostname to resolve.
+/// * `resolver`: A `&str` that describes the resolver, see `resolver_config`.
+///
+/// # Returns
+///
+/// * A `String` that holds the IPv4 address of the hostname if the function succeeds.
+/// * If any step fails, it returns an empty `String`.
+pub fn resolve_hostname_using(hostname: &str, resolver: &str) -> String {
+    match try_resolve_addresses(hostname, resolver) {
+        Ok(addresses) => addresses.into_iter().next().unwrap_or_default(),
         Err(err) => {
-            log::warn!("Failed to build resolver: {}", err);
-            return String::new();
+            log::warn!("{}", err);
+            String::new()
         }
-    };
+    }
+}
 
-    match resolver.lookup_ip(hostname) {
-        Ok(response) => {
-            let ipv4_address = response
-                .iter()
-                .find(|ip| ip.is_ipv4())
-                .map(|ip| ip.to_string())
-                .unwrap_or_else(|| {
-                    log::warn!("No IPv4 addresses found for hostname: {}", hostname);
-                    String::new()
-                });
-            ipv4_address
+/// Resolves a hostname to all of its IPv4 addresses using the given resolver.
+///
+/// This function works like `resolve_hostname_using`, but returns every A record instead of the first one,
+/// sorted, so the answers for round-robin records can be compared as sets whatever their order.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the hostname to resolve.
+/// * `resolver`: A `&str` that describes the resolver, see `resolver_config`.
+///
+/// # Returns
+///
+/// * A `Vec<String>` that holds the IPv4 addresses of the hostname, sorted.
+/// * If any step fails, it returns an empty `Vec`.
+pub fn resolve_addresses_using(hostname: &str, resolver: &str) -> Vec<String> {
+    match try_resolve_addresses(hostname, resolver) {
+        Ok(mut addresses) => {
+            addresses.sort();
+            addresses.dedup();
+            addresses
         }
         Err(err) => {
-            log::warn!(
+            log::warn!("{}", err);
+            Vec::new()
+        }
+    }
+}
+
+/// Resolves a hostname to its addresses of an address family using the upstream resolvers, see `default_resolver`.
+///
+/// This function works like `resolve_hostname`, but returns the AAAA records, or both the A and AAAA records,
+/// e.g. to compare a dual-stack hostname with the IPv4 and IPv6 addresses of the router.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the hostname to resolve.
+/// * `family`: An `AddressFamily` that specifies the records to look up.
+///
+/// # Returns
+///
+/// * A `Vec<String>` that holds the addresses, IPv4 first, see `try_resolve_family`.
+/// * If any step fails, it returns an empty `Vec`.
+pub fn resolve_hostname_as(hostname: &str, family: AddressFamily) -> Vec<String> {
+    match try_resolve_family(hostname, &default_resolver(), family) {
+        Ok(addresses) => addresses,
+        Err(err) => {
+            log::warn!("{}", err);
+            Vec::new()
+        }
+    }
+}
+
+/// The address records of a hostname that are resolved and compared.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddressFamily {
+    /// The A records only.
+    V4,
+    /// The AAAA records only.
+    V6,
+    /// Both the A and the AAAA records.
+    Dual,
+}
+
+impl AddressFamily {
+    /// The record types of the family, as used in the cache, e.g. "A" or "A+AAAA".
+    fn record_type(self) -> &'static str {
+        match self {
+            AddressFamily::V4 => "A",
+            AddressFamily::V6 => "AAAA",
+            AddressFamily::Dual => "A+AAAA",
+        }
+    }
+
+    /// Returns whether an address belongs to the family.
+    pub fn includes(self, ip: &IpAddr) -> bool {
+        match self {
+            AddressFamily::V4 => ip.is_ipv4(),
+            AddressFamily::V6 => ip.is_ipv6(),
+            AddressFamily::Dual => true,
+        }
+    }
+
+    /// Returns whether the family includes IPv4 addresses.
+    pub fn has_ipv4(self) -> bool {
+        self != AddressFamily::V6
+    }
+
+    /// Returns whether the family includes IPv6 addresses.
+    pub fn has_ipv6(self) -> bool {
+        self != AddressFamily::V4
+    }
+
+    /// Returns the lookup strategy of a resolver that looks up the records of the family.
+    fn strategy(self) -> LookupIpStrategy {
+        match self {
+            AddressFamily::V4 => LookupIpStrategy::Ipv4Only,
+            AddressFamily::V6 => LookupIpStrategy::Ipv6Only,
+            AddressFamily::Dual => LookupIpStrategy::Ipv4AndIpv6,
+        }
+    }
+}
+
+/// Retrieves the address family of the monitored hostname.
+///
+/// This function parses the "DNS_ADDRESS_FAMILY" environment variable ("ipv4", "ipv6" or "dual").
+/// If it is not set, it defaults to "ipv4". If it cannot be parsed, it logs a warning and uses the default.
+///
+/// # Returns
+///
+/// * An `AddressFamily` that specifies the records to resolve and compare.
+pub fn address_family() -> AddressFamily {
+    let value = env::var("DNS_ADDRESS_FAMILY").unwrap_or("ipv4".to_string());
+    match value.to_lowercase().as_str() {
+        "ipv4" => AddressFamily::V4,
+        "ipv6" => AddressFamily::V6,
+        "dual" => AddressFamily::Dual,
+        _ => {
+            log::warn!("Invalid DNS_ADDRESS_FAMILY {}, using ipv4", value);
+            AddressFamily::V4
+        }
+    }
+}
+
+/// Resolves a hostname to all of its IPv4 addresses, distinguishing failures of the resolver from missing records.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the hostname to resolve.
+/// * `resolver`: A `&str` that describes the resolver, see `resolver_config`.
+///
+/// # Returns
+///
+/// * A `Result<Vec<String>, String>` that holds the IPv4 addresses in the order of the answer, empty if the hostname has no IPv4 address.
+/// * If the resolver cannot be built or does not answer, it returns an `Err` with a message.
+pub fn try_resolve_addresses(hostname: &str, resolver: &str) -> Result<Vec<String>, String> {
+    try_resolve_family(hostname, resolver, AddressFamily::V4)
+}
+
+/// Resolves a hostname to all of its addresses of an address family, distinguishing failures of the resolver from missing records.
+///
+/// If the cache is enabled (see `cache::DnsCache`), answers are kept for their TTL and looked up there first.
+/// Otherwise the hostname is looked up with the `lookup_family` function, and a failed lookup, e.g. a transient "SERVFAIL",
+/// is retried with exponential backoff (see `RetryPolicy`) before giving up.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the hostname to resolve.
+/// * `resolver`: A `&str` that describes the resolver, see `resolver_config` and `tls_servers`.
+/// * `family`: An `AddressFamily` that specifies the records to look up.
+///
+/// # Returns
+///
+/// * A `Result<Vec<String>, String>` that holds the addresses, the IPv4 addresses first and each family in the order of the answer;
+///   empty if the hostname has no address of the family.
+/// * If the resolver cannot be built or does not answer, it returns an `Err` with a message.
+pub fn try_resolve_family(
+    hostname: &str,
+    resolver: &str,
+    family: AddressFamily,
+) -> Result<Vec<String>, String> {
+    let hostname = &idna::domain_to_ascii(hostname).unwrap_or_else(|_| hostname.to_string());
+    let record_type = family.record_type();
+    if let Some(addresses) = DNS_CACHE.get(resolver, hostname, record_type) {
+        log::debug!("Using cached addresses of {} from {}", hostname, resolver);
+        return Ok(addresses);
+    }
+    let (addresses, ttl) =
+        RetryPolicy::from_env().run(|| lookup_family(hostname, resolver, family))?;
+    if addresses.is_empty() {
+        log::warn!(
+            "No {} records found for hostname: {}",
+            record_type,
+            hostname
+        );
+    }
+    DNS_CACHE.insert(resolver, hostname, record_type, &addresses, ttl);
+    Ok(addresses)
+}
+
+/// Looks up the addresses of an address family of a hostname once.
+///
+/// DNS-over-TLS resolvers are queried with the `resolve_tls` function.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the hostname to resolve, in ASCII.
+/// * `resolver`: A `&str` that describes the resolver, see `resolver_config` and `tls_servers`.
+/// * `family`: An `AddressFamily` that specifies the records to look up.
+///
+/// # Returns
+///
+/// * A `Result<(Vec<String>, Option<Duration>), String>` that holds the addresses, IPv4 first, and how long they may be cached;
+///   the addresses are empty if the hostname has no address of the family.
+/// * If the resolver cannot be built or does not answer, or answers with an error such as SERVFAIL, it returns an `Err` with a message.
+fn lookup_family(
+    hostname: &str,
+    resolver: &str,
+    family: AddressFamily,
+) -> Result<(Vec<String>, Option<Duration>), String> {
+    if chaos::inject(Fault::DnsTimeout) {
+        return Err(format!(
+            "Failed to resolve {} with {}: request timed out",
+            hostname, resolver
+        ));
+    }
+    if is_tls_resolver(resolver) {
+        return resolve_tls(hostname, resolver, family);
+    }
+    let resolver = shared_resolver(resolver, family.strategy())?;
+    family_answer(hostname, family, block_on(resolver.lookup_ip(hostname))?)
+}
+
+/// Converts the result of an address lookup into the addresses of an address family.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the hostname that was resolved.
+/// * `family`: An `AddressFamily` that specifies the records that were looked up.
+/// * `result`: A `Result<LookupIp, ResolveError>` that holds the result of the lookup.
+///
+/// # Returns
+///
+/// * A `Result<(Vec<String>, Option<Duration>), String>` that holds the addresses, IPv4 first, and how long they may be cached;
+///   the addresses are empty if the resolver answered that the hostname has none, see `is_negative_answer`.
+/// * If the lookup failed, including with a SERVFAIL or REFUSED answer, it returns an `Err` with a message.
+fn family_answer(
+    hostname: &str,
+    family: AddressFamily,
+    result: Result<LookupIp, ResolveError>,
+) -> Result<(Vec<String>, Option<Duration>), String> {
+    match result {
+        Ok(response) => {
+            let mut ips: Vec<IpAddr> = response.iter().filter(|ip| family.includes(ip)).collect();
+            ips.sort_by_key(IpAddr::is_ipv6);
+            let ttl = response
+                .valid_until()
+                .saturating_duration_since(Instant::now());
+            Ok((ips.iter().map(IpAddr::to_string).collect(), Some(ttl)))
+        }
+        Err(err) if is_negative_answer(&err) => Ok((Vec::new(), negative_ttl(&err))),
+        Err(err) => Err(format!(
+            "Failed to lookup IP address: {} for hostname: {}",
+            err, hostname
+        )),
+    }
+}
+
+/// Returns whether a failed lookup is an answer that the name has no such records.
+///
+/// The resolver reports SERVFAIL, REFUSED and NOTIMP answers as "no records found" too,
+/// but only NOERROR and NXDOMAIN answers say anything about the name; the others are failures, like in `lookup_tls`.
+///
+/// # Arguments
+///
+/// * `err`: A `&ResolveError` that holds the error of the lookup.
+///
+/// # Returns
+///
+/// * A `bool` that is true if the resolver answered NOERROR or NXDOMAIN without records.
+fn is_negative_answer(err: &ResolveError) -> bool {
+    matches!(
+        err.kind(),
+        ResolveErrorKind::NoRecordsFound {
+            response_code: ResponseCode::NoError | ResponseCode::NXDomain,
+            ..
+        }
+    )
+}
+
+/// How failed lookups are retried, see `try_resolve_family`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// The number of retries after the first attempt; 0 disables retries.
+    retries: u32,
+    /// The delay before the first retry.
+    delay: Duration,
+    /// The factor the delay grows by with every retry.
+    backoff: f64,
+    /// The longest delay between two retries.
+    max_delay: Duration,
+}
+
+/// The most retries `DNS_RETRIES` accepts; with the default delays they already take minutes.
+const MAX_RETRIES: u32 = 10;
+
+impl RetryPolicy {
+    /// Creates the retry policy from the environment.
+    ///
+    /// This function retrieves the number of retries from the "DNS_RETRIES" environment variable (defaulting to 2),
+    /// the delay before the first retry from "DNS_RETRY_DELAY" (e.g. "2s", defaulting to one second),
+    /// the factor the delay is multiplied by before each further retry from "DNS_RETRY_BACKOFF" (defaulting to 2),
+    /// and the longest delay from "DNS_RETRY_MAX_DELAY" (defaulting to 10 seconds, the interval between checks).
+    /// Invalid values, and more retries than `MAX_RETRIES`, fall back to the defaults.
+    pub fn from_env() -> RetryPolicy {
+        let retries = env::var("DNS_RETRIES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|retries| {
+                let accepted = *retries <= MAX_RETRIES;
+                if !accepted {
+                    log::warn!(
+                        "DNS_RETRIES {} exceeds {}, using the default",
+                        retries,
+                        MAX_RETRIES
+                    );
+                }
+                accepted
+            })
+            .unwrap_or(2);
+        let delay = env::var("DNS_RETRY_DELAY")
+            .ok()
+            .and_then(|value| parse_duration(&value))
+            .unwrap_or(Duration::from_secs(1));
+        let backoff = env::var("DNS_RETRY_BACKOFF")
+            .ok()
+            .and_then(|value| value.parse::<f64>().ok())
+            .filter(|backoff| backoff.is_finite() && *backoff >= 1.0)
+            .unwrap_or(2.0);
+        let max_delay = env::var("DNS_RETRY_MAX_DELAY")
+            .ok()
+            .and_then(|value| parse_duration(&value))
+            .unwrap_or(Duration::from_secs(10));
+        RetryPolicy {
+            retries,
+            delay,
+            backoff,
+            max_delay,
+        }
+    }
+
+    /// Returns the delay before a retry.
+    ///
+    /// # Arguments
+    ///
+    /// * `retry`: A `u32` that specifies the retry, starting at 0 for the first one.
+    ///
+    /// # Returns
+    ///
+    /// * A `Duration` that holds the initial delay multiplied by the backoff factor once per earlier retry,
+    ///   but at most the longest delay, also when the product overflows.
+    fn delay(&self, retry: u32) -> Duration {
+        let factor = self.backoff.powi(retry.min(i32::MAX as u32) as i32);
+        Duration::try_from_secs_f64(self.delay.as_secs_f64() * factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+
+    /// Runs a lookup and retries it while it fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `lookup`: An `FnMut() -> Result<T, String>` that runs the lookup once.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<T, String>` that holds the result of the first attempt that succeeded,
+    ///   or the error of the last attempt if every attempt failed.
+    pub fn run<T>(&self, mut lookup: impl FnMut() -> Result<T, String>) -> Result<T, String> {
+        let mut retry = 0;
+        loop {
+            match lookup() {
+                Ok(result) => return Ok(result),
+                Err(err) if retry < self.retries => {
+                    let delay = self.delay(retry);
+                    log::warn!("{}, retrying in {:?}", err, delay);
+                    std::thread::sleep(delay);
+                    retry += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Resolves a hostname to its addresses of an address family using a DNS-over-TLS resolver.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the hostname to resolve, in ASCII.
+/// * `resolver`: A `&str` that describes the resolver, see `tls_servers`.
+/// * `family`: An `AddressFamily` that specifies the records to look up.
+///
+/// # Returns
+///
+/// * A `Result<(Vec<String>, Option<Duration>), String>` that holds the addresses, IPv4 first, and the TTL of the answers, see `lookup_tls`.
+/// * If no server of the resolver answers, it returns an `Err` with a message.
+fn resolve_tls(
+    hostname: &str,
+    resolver: &str,
+    family: AddressFamily,
+) -> Result<(Vec<String>, Option<Duration>), String> {
+    let record_types: &[RecordType] = match family {
+        AddressFamily::V4 => &[RecordType::A],
+        AddressFamily::V6 => &[RecordType::AAAA],
+        AddressFamily::Dual => &[RecordType::A, RecordType::AAAA],
+    };
+    let mut addresses = Vec::new();
+    let mut ttl: Option<Duration> = None;
+    for record_type in record_types {
+        let (data, record_ttl) = lookup_tls(resolver, hostname, *record_type).map_err(|err| {
+            format!(
                 "Failed to lookup IP address: {} for hostname: {}",
-                err,
-                hostname
+                err, hostname
+            )
+        })?;
+        addresses.extend(
+            data.iter()
+                .filter_map(RData::ip_addr)
+                .map(|ip| ip.to_string()),
+        );
+        ttl = match (ttl, record_ttl) {
+            (Some(ttl), Some(record_ttl)) => Some(ttl.min(record_ttl)),
+            (ttl, record_ttl) => ttl.or(record_ttl),
+        };
+    }
+    Ok((addresses, ttl))
+}
+
+/// A record in the answer of a raw query, see `query_addresses`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DnsAnswer {
+    /// The owner name of the record, e.g. "home.example.com.".
+    pub name: String,
+    /// The type of the record, e.g. "A" or "CNAME".
+    pub record_type: String,
+    /// The time to live of the record in seconds.
+    pub ttl: u32,
+    /// The data of the record, e.g. the address of an A record.
+    pub data: String,
+}
+
+/// The details of the response to a raw query that the resolvers hide, see `query_addresses`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DnsResponse {
+    /// The name server that answered.
+    pub server: SocketAddr,
+    /// The response code, e.g. "NOERROR", "NXDOMAIN" or "SERVFAIL".
+    pub response_code: String,
+    /// Whether the answer is authoritative (AA).
+    pub authoritative: bool,
+    /// Whether the answer was truncated (TC), so records may be missing.
+    pub truncated: bool,
+    /// Whether the server offers recursion (RA).
+    pub recursion_available: bool,
+    /// The records in the answer section, in order.
+    pub answers: Vec<DnsAnswer>,
+}
+
+impl DnsResponse {
+    /// Returns the IPv4 addresses of the A records in the answer, in order.
+    pub fn addresses(&self) -> Vec<String> {
+        self.answers
+            .iter()
+            .filter(|answer| answer.record_type == "A")
+            .map(|answer| answer.data.clone())
+            .collect()
+    }
+
+    /// Describes the response on one line, e.g. "NOERROR from 8.8.8.8:53, flags: ra, answers: home.example.com. 60 A 1.1.1.1".
+    pub fn summary(&self) -> String {
+        let flags: Vec<&str> = [
+            (self.authoritative, "aa"),
+            (self.truncated, "tc"),
+            (self.recursion_available, "ra"),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, flag)| *flag)
+        .collect();
+        let answers: Vec<String> = self
+            .answers
+            .iter()
+            .map(|answer| {
+                format!(
+                    "{} {} {} {}",
+                    answer.name, answer.ttl, answer.record_type, answer.data
+                )
+            })
+            .collect();
+        format!(
+            "{} from {}, flags: {}, answers: {}",
+            self.response_code,
+            self.server,
+            if flags.is_empty() {
+                "none".to_string()
+            } else {
+                flags.join(" ")
+            },
+            if answers.is_empty() {
+                "none".to_string()
+            } else {
+                answers.join(", ")
+            }
+        )
+    }
+}
+
+/// Resolves a hostname by sending queries directly, keeping the details of the response.
+///
+/// Unlike `try_resolve_addresses`, which hides the response behind the resolver, this function returns the response code,
+/// the AA and TC flags, the TTL of every answer record and the name server that answered, which helps debugging propagation problems.
+/// The name servers of the resolver are queried over UDP, one after the other, until one answers with "NOERROR" or "NXDOMAIN".
+///
+/// If a client network is given, it is sent as EDNS Client Subnet (RFC 7871), so the answer is the one a client in that network gets,
+/// e.g. the record behind a regional view of a GeoDNS service.
+/// Not every resolver passes the network on: Google Public DNS does, Cloudflare does not.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the hostname to resolve.
+/// * `resolver`: A `&str` that describes the resolver, see `resolver_config`.
+/// * `subnet`: An `Option<IpNet>` that specifies the client network, e.g. "203.0.113.0/24", or `None` to send none.
+///
+/// # Returns
+///
+/// * A `Result<DnsResponse, String>` that holds the details of the response; `DnsResponse::addresses` holds the IPv4 addresses.
+/// * If the resolver cannot be built or none of its name servers answers, it returns an `Err` with a message.
+pub fn query_addresses(
+    hostname: &str,
+    resolver: &str,
+    subnet: Option<IpNet>,
+) -> Result<DnsResponse, String> {
+    let (config, _) =
+        resolver_config(resolver).map_err(|err| format!("Failed to build resolver: {}", err))?;
+    let hostname = idna::domain_to_ascii(hostname).unwrap_or_else(|_| hostname.to_string());
+
+    let mut error = format!("Resolver {} has no name server", resolver);
+    for server in config
+        .name_servers()
+        .iter()
+        .filter(|server| server.protocol == Protocol::Udp)
+    {
+        let response = match query_recursive(server.socket_addr, &hostname, RecordType::A, subnet) {
+            Ok(response) => response,
+            Err(err) => {
+                error = err;
+                continue;
+            }
+        };
+        let response_code = format!("{:?}", response.response_code()).to_uppercase();
+        if !matches!(
+            response.response_code(),
+            ResponseCode::NoError | ResponseCode::NXDomain
+        ) {
+            error = format!("{} answered {}", server.socket_addr, response_code);
+            continue;
+        }
+        let response = DnsResponse {
+            server: server.socket_addr,
+            response_code,
+            authoritative: response.authoritative(),
+            truncated: response.truncated(),
+            recursion_available: response.recursion_available(),
+            answers: response
+                .answers()
+                .iter()
+                .map(|record| DnsAnswer {
+                    name: record.name().to_ascii(),
+                    record_type: record.record_type().to_string(),
+                    ttl: record.ttl(),
+                    data: record
+                        .data()
+                        .map(|data| data.to_string())
+                        .unwrap_or_default(),
+                })
+                .collect(),
+        };
+        if response.truncated {
+            log::warn!(
+                "Truncated answer for hostname: {} from {}, records may be missing",
+                hostname,
+                response.server
             );
-            String::new()
         }
+        if response.addresses().is_empty() {
+            log::warn!("No IPv4 addresses found for hostname: {}", hostname);
+        }
+        return Ok(response);
     }
+    Err(format!(
+        "Failed to lookup IP address: {} for hostname: {}",
+        error, hostname
+    ))
 }
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    #[test]
-    fn test_resolve_hostname() {
-        let result = resolve_hostname("localhost");
+/// Retrieves the upstream resolvers from the "DNS_RESOLVERS" environment variable.
+///
+/// The resolvers are used for every lookup that does not name its own resolver: the monitored hostname
+/// (before the resolvers in "DNS_FALLBACK_RESOLVERS"), the reference hostname and the lookups of the zone checks.
+/// The value is a description as accepted by `resolver_config`, e.g. "cloudflare" or "192.0.2.53,192.0.2.54:5353"
+/// to query an own authoritative server, or of DNS-over-TLS servers as accepted by `tls_servers`, e.g. "cloudflare-tls".
+/// If it is not set and "DNS_USE_SYSTEM" is "true", the resolvers of the host are used (see `use_system`).
+///
+/// # Returns
+///
+/// * A `String` that describes the resolvers, defaulting to "google".
+pub fn default_resolver() -> String {
+    resolvers_or_default(env::var("DNS_RESOLVERS").ok(), use_system())
+}
 
-        assert_eq!(result, "127.0.0.1");
+/// Returns whether the resolvers of the host are the upstream resolvers, with "DNS_USE_SYSTEM" set to "true".
+///
+/// The resolvers are then built from "/etc/resolv.conf" (see `resolver_config`) instead of Google,
+/// so lookups see the same answers as the other hosts of the network, e.g. the internal view of a split-horizon zone.
+pub fn use_system() -> bool {
+    env::var("DNS_USE_SYSTEM").is_ok_and(|value| value == "true")
+}
+
+/// Selects the upstream resolvers.
+///
+/// # Arguments
+///
+/// * `resolvers`: An `Option<String>` that holds the value of "DNS_RESOLVERS", if it is set.
+/// * `use_system`: A `bool` that indicates whether "DNS_USE_SYSTEM" is enabled.
+///
+/// # Returns
+///
+/// * A `String` that holds the configured resolvers, or "system" or "google" if none are configured.
+fn resolvers_or_default(resolvers: Option<String>, use_system: bool) -> String {
+    match resolvers.filter(|resolvers| !resolvers.trim().is_empty()) {
+        Some(resolvers) => resolvers,
+        None if use_system => "system".to_string(),
+        None => "google".to_string(),
+    }
+}
+
+/// Returns the resolver of a description, for the lookups through the upstream resolvers.
+///
+/// # Arguments
+///
+/// * `description`: A `&str` that describes the resolver, see `resolver_config`.
+///
+/// # Returns
+///
+/// * A `Result<TokioAsyncResolver, String>` that holds the resolver, or an `Err` with a message if it cannot be built.
+fn lookup_resolver(description: &str) -> Result<TokioAsyncResolver, String> {
+    shared_resolver(description, LookupIpStrategy::default())
+}
+
+/// Returns the resolver of a description and lookup strategy, building it on first use.
+///
+/// Each resolver is built once and reused by every later lookup, so its connections to the name servers are kept
+/// instead of opening new sockets on every check. Its own cache is disabled: answers are cached by `DNS_CACHE`,
+/// which honours "DNS_CACHE" and is bypassed by "--no-cache", so a reused resolver must not serve stale records.
+///
+/// # Arguments
+///
+/// * `description`: A `&str` that describes the resolver, see `resolver_config`.
+/// * `strategy`: A `LookupIpStrategy` that specifies the address records looked up by `lookup_ip`.
+///
+/// # Returns
+///
+/// * A `Result<TokioAsyncResolver, String>` that holds the resolver, or an `Err` with a message if it cannot be built.
+fn shared_resolver(
+    description: &str,
+    strategy: LookupIpStrategy,
+) -> Result<TokioAsyncResolver, String> {
+    let key = format!("{}/{:?}", description, strategy);
+    let mut resolvers = RESOLVERS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(resolver) = resolvers.get(&key) {
+        return Ok(resolver.clone());
+    }
+    let (config, mut opts) =
+        resolver_config(description).map_err(|e| format!("Failed to build resolver: {}", e))?;
+    opts.ip_strategy = strategy;
+    opts.cache_size = 0;
+    let _guard = runtime()?.enter();
+    let resolver = TokioAsyncResolver::tokio(config, opts);
+    resolvers.insert(key, resolver.clone());
+    Ok(resolver)
+}
+
+/// Builds the resolver of the monitored hostname at startup (see `default_resolver` and `address_family`),
+/// so the checks reuse it from the first one on.
+///
+/// If it cannot be built, e.g. because "/etc/resolv.conf" is unreadable, a warning is logged
+/// and the lookups report the error when they need the resolver.
+pub fn prepare_resolver() {
+    let description = default_resolver();
+    if is_tls_resolver(&description) {
+        return;
+    }
+    if let Err(e) = shared_resolver(&description, address_family().strategy()) {
+        log::warn!("{}", e);
+    }
+}
+
+/// Returns the runtime the resolvers run on, started on first use.
+///
+/// # Returns
+///
+/// * A `Result<&Runtime, String>` that holds the runtime, or an `Err` with a message if it cannot be started.
+fn runtime() -> Result<&'static Runtime, String> {
+    static RUNTIME: OnceLock<Result<Runtime, String>> = OnceLock::new();
+    RUNTIME
+        .get_or_init(|| {
+            tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name("dns-resolver")
+                .enable_all()
+                .build()
+                .map_err(|e| format!("Failed to start the resolver runtime: {}", e))
+        })
+        .as_ref()
+        .map_err(String::clone)
+}
+
+/// Runs a lookup of a resolver to completion, blocking the calling thread.
+///
+/// # Arguments
+///
+/// * `lookup`: A `Future` that performs the lookup.
+///
+/// # Returns
+///
+/// * A `Result` that holds the output of the lookup, or an `Err` with a message if the runtime cannot be started.
+fn block_on<F: Future>(lookup: F) -> Result<F::Output, String> {
+    Ok(runtime()?.block_on(lookup))
+}
+
+/// Builds the configuration of a resolver from its description.
+///
+/// The description is one of:
+///
+/// * "google", "cloudflare", "quad9" or "opendns" for these public resolvers.
+/// * "system" for the resolvers of the host (from "/etc/resolv.conf").
+/// * A comma-separated list of IP addresses of name servers, e.g. "192.168.1.1" or "10.0.0.53,10.0.0.54".
+///   A name server on another port than 53 is given with its port, e.g. "127.0.0.1:5353" or "[::1]:5353".
+///
+/// DNS-over-TLS resolvers (see `tls_servers`) are queried without a `Resolver`, so they have no configuration.
+///
+/// # Arguments
+///
+/// * `resolver`: A `&str` that describes the resolver.
+///
+/// # Returns
+///
+/// * A `Result<(ResolverConfig, ResolverOpts), String>` that holds the configuration, or an `Err` with a message if the description is invalid.
+pub fn resolver_config(resolver: &str) -> Result<(ResolverConfig, ResolverOpts), String> {
+    let config = match resolver {
+        resolver if is_tls_resolver(resolver) => {
+            return Err(format!(
+                "DNS-over-TLS resolver {} only supports address and record lookups",
+                resolver
+            ))
+        }
+        "google" => ResolverConfig::google(),
+        "cloudflare" => ResolverConfig::cloudflare(),
+        "quad9" => ResolverConfig::quad9(),
+        "opendns" => ResolverConfig::from_parts(
+            None,
+            vec![],
+            NameServerConfigGroup::from_ips_clear(&OPENDNS, 53, true),
+        ),
+        "system" => return read_system_conf().map_err(|e| e.to_string()),
+        servers => {
+            let addrs = split_list(servers)
+                .iter()
+                .map(|server| server_addr(server))
+                .collect::<Option<Vec<SocketAddr>>>()
+                .ok_or(format!("Invalid resolver: {}", servers))?;
+            if addrs.is_empty() {
+                return Err("Empty resolver".to_string());
+            }
+            let mut name_servers = NameServerConfigGroup::new();
+            for addr in addrs {
+                name_servers.merge(NameServerConfigGroup::from_ips_clear(
+                    &[addr.ip()],
+                    addr.port(),
+                    true,
+                ));
+            }
+            ResolverConfig::from_parts(None, vec![], name_servers)
+        }
+    };
+    Ok((config, ResolverOpts::default()))
+}
+
+/// The addresses of the OpenDNS resolvers, which trust-dns has no preset for.
+const OPENDNS: [IpAddr; 2] = [
+    IpAddr::V4(std::net::Ipv4Addr::new(208, 67, 222, 222)),
+    IpAddr::V4(std::net::Ipv4Addr::new(208, 67, 220, 220)),
+];
+
+/// The public resolvers whose answers are compared by default, see `resolve_with_each`.
+pub const PUBLIC_RESOLVERS: [&str; 4] = ["google", "cloudflare", "quad9", "opendns"];
+
+/// Resolves a hostname with each of several resolvers at once.
+///
+/// The resolvers are queried in parallel, one thread each, so a slow resolver does not delay the others.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the hostname to resolve.
+/// * `resolvers`: A `&[String]` that holds the descriptions of the resolvers, see `resolver_config` and `tls_servers`.
+///
+/// # Returns
+///
+/// * A `Vec<(String, Result<Vec<String>, String>)>` that holds, in the order of the resolvers, each resolver
+///   with its IPv4 addresses of the hostname, sorted, or an `Err` with a message if it did not answer.
+pub fn resolve_with_each(
+    hostname: &str,
+    resolvers: &[String],
+) -> Vec<(String, Result<Vec<String>, String>)> {
+    std::thread::scope(|scope| {
+        let lookups: Vec<_> = resolvers
+            .iter()
+            .map(|resolver| {
+                scope.spawn(move || {
+                    let mut addresses = try_resolve_addresses(hostname, resolver)?;
+                    addresses.sort();
+                    addresses.dedup();
+                    Ok(addresses)
+                })
+            })
+            .collect();
+        resolvers
+            .iter()
+            .zip(lookups)
+            .map(|(resolver, lookup)| {
+                let result = lookup
+                    .join()
+                    .unwrap_or_else(|_| Err(format!("Lookup with {} panicked", resolver)));
+                (resolver.clone(), result)
+            })
+            .collect()
+    })
+}
+
+/// Checks the description of a resolver, see `resolver_config` and `tls_servers`.
+///
+/// # Arguments
+///
+/// * `resolver`: A `&str` that describes the resolver.
+///
+/// # Returns
+///
+/// * A `Result<(), String>` that is `Ok` if the description is valid, or an `Err` with a message.
+pub fn validate_resolver(resolver: &str) -> Result<(), String> {
+    if is_tls_resolver(resolver) {
+        tls_servers(resolver).map(|_| ())
+    } else {
+        resolver_config(resolver).map(|_| ())
+    }
+}
+
+/// The public DNS-over-TLS resolvers known by name, with their addresses and the name in their certificates.
+const TLS_RESOLVERS: [(&str, &str); 3] = [
+    ("google-tls", "8.8.8.8#dns.google,8.8.4.4#dns.google"),
+    (
+        "cloudflare-tls",
+        "1.1.1.1#cloudflare-dns.com,1.0.0.1#cloudflare-dns.com",
+    ),
+    (
+        "quad9-tls",
+        "9.9.9.9#dns.quad9.net,149.112.112.112#dns.quad9.net",
+    ),
+];
+
+/// A DNS-over-TLS server (RFC 7858).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsServer {
+    /// The address and port of the server.
+    pub addr: SocketAddr,
+    /// The name the certificate of the server is verified against.
+    pub server_name: String,
+}
+
+/// Returns whether a resolver description names DNS-over-TLS servers, see `tls_servers`.
+pub fn is_tls_resolver(resolver: &str) -> bool {
+    resolver.starts_with("tls:") || TLS_RESOLVERS.iter().any(|(name, _)| *name == resolver)
+}
+
+/// Parses the servers of a DNS-over-TLS resolver from its description.
+///
+/// The description is one of:
+///
+/// * "google-tls", "cloudflare-tls" or "quad9-tls" for these public resolvers.
+/// * "tls:" followed by a comma-separated list of servers as IP[:port][#name], e.g. "tls:192.0.2.53#dns.example.com"
+///   or "tls:[2001:db8::53]:8853#dns.example.com". The port defaults to 853. The certificate of the server is verified
+///   against the name, or against the IP address if no name is given.
+///
+/// # Arguments
+///
+/// * `resolver`: A `&str` that describes the resolver.
+///
+/// # Returns
+///
+/// * A `Result<Vec<TlsServer>, String>` that holds the servers, or an `Err` with a message if the description is invalid.
+pub fn tls_servers(resolver: &str) -> Result<Vec<TlsServer>, String> {
+    let servers = TLS_RESOLVERS
+        .iter()
+        .find(|(name, _)| *name == resolver)
+        .map(|(_, servers)| *servers)
+        .or_else(|| resolver.strip_prefix("tls:"))
+        .ok_or(format!("Not a DNS-over-TLS resolver: {}", resolver))?;
+    let servers = split_list(servers)
+        .iter()
+        .map(|server| {
+            let (address, name) = match server.split_once('#') {
+                Some((address, name)) => (address, Some(name)),
+                None => (server.as_str(), None),
+            };
+            let addr = match address.parse::<IpAddr>() {
+                Ok(ip) => SocketAddr::new(ip, 853),
+                Err(_) => address
+                    .parse::<SocketAddr>()
+                    .map_err(|_| format!("Invalid DNS-over-TLS server: {}", server))?,
+            };
+            let server_name = match name {
+                Some(name) if !name.is_empty() => name.to_string(),
+                _ => addr.ip().to_string(),
+            };
+            Ok(TlsServer { addr, server_name })
+        })
+        .collect::<Result<Vec<TlsServer>, String>>()?;
+    if servers.is_empty() {
+        return Err("Empty resolver".to_string());
+    }
+    Ok(servers)
+}
+
+/// Parses the address of a name server, e.g. "192.168.1.1", or "127.0.0.1:5353" or "[::1]:5353" on another port than 53.
+///
+/// # Arguments
+///
+/// * `server`: A `&str` that holds the address.
+///
+/// # Returns
+///
+/// * An `Option<SocketAddr>` that holds the address and port, or `None` if the address is invalid.
+pub fn server_addr(server: &str) -> Option<SocketAddr> {
+    match server.parse::<IpAddr>() {
+        Ok(ip) => Some(SocketAddr::new(ip, 53)),
+        Err(_) => server.parse::<SocketAddr>().ok(),
+    }
+}
+
+/// Looks up the name servers of a zone.
+///
+/// # Arguments
+///
+/// * `zone`: A `&str` that specifies the zone, e.g. "example.com".
+///
+/// # Returns
+///
+/// * A `Result<Vec<String>, String>` that holds the names of the name servers in lower case and without the trailing dot,
+///   or an `Err` with a message if the lookup fails.
+pub fn lookup_ns(zone: &str) -> Result<Vec<String>, String> {
+    let description = default_resolver();
+    if let Some(servers) = DNS_CACHE.get(&description, zone, "NS") {
+        return Ok(servers);
+    }
+    if is_tls_resolver(&description) {
+        let (data, ttl) = lookup_tls(&description, zone, RecordType::NS)
+            .map_err(|e| format!("Failed to lookup NS records of {}: {}", zone, e))?;
+        let servers: Vec<String> = data
+            .iter()
+            .filter_map(|data| match data {
+                RData::NS(ns) => Some(normalize_name(&ns.0)),
+                _ => None,
+            })
+            .collect();
+        DNS_CACHE.insert(&description, zone, "NS", &servers, ttl);
+        return Ok(servers);
+    }
+    let response = block_on(lookup_resolver(&description)?.ns_lookup(zone))?
+        .map_err(|e| format!("Failed to lookup NS records of {}: {}", zone, e))?;
+    let servers: Vec<String> = response.iter().map(|ns| normalize_name(&ns.0)).collect();
+    let ttl = response
+        .valid_until()
+        .saturating_duration_since(Instant::now());
+    DNS_CACHE.insert(&description, zone, "NS", &servers, Some(ttl));
+    Ok(servers)
+}
+
+/// Finds the zone of a hostname, the closest name above or at the hostname that has NS records.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the hostname, without the trailing dot.
+///
+/// # Returns
+///
+/// * A `Result<(String, Vec<String>), String>` that holds the zone and the names of its name servers,
+///   or an `Err` with a message if no zone is found.
+pub fn find_zone(hostname: &str) -> Result<(String, Vec<String>), String> {
+    let mut name = hostname;
+    loop {
+        if let Ok(servers) = lookup_ns(name) {
+            if !servers.is_empty() {
+                return Ok((name.to_string(), servers));
+            }
+        }
+        match name.split_once('.') {
+            Some((_, parent)) if parent.contains('.') => name = parent,
+            _ => return Err(format!("No zone found for {}", hostname)),
+        }
+    }
+}
+
+/// Looks up the records of a type at a name.
+///
+/// # Arguments
+///
+/// * `name`: A `&str` that specifies the name, e.g. "example.com".
+/// * `record_type`: A `RecordType` that specifies the type of the records, e.g. `RecordType::CAA`.
+///
+/// # Returns
+///
+/// * A `Result<Vec<String>, String>` that holds the records in presentation format, sorted, empty if the name has no such records.
+/// * If the lookup fails, it returns an `Err` with a message.
+pub fn lookup_records(name: &str, record_type: RecordType) -> Result<Vec<String>, String> {
+    let description = default_resolver();
+    if let Some(records) = DNS_CACHE.get(&description, name, &record_type.to_string()) {
+        return Ok(records);
+    }
+    if is_tls_resolver(&description) {
+        let (data, ttl) = lookup_tls(&description, name, record_type).map_err(|e| {
+            format!(
+                "Failed to lookup {} records of {}: {}",
+                record_type, name, e
+            )
+        })?;
+        let mut records: Vec<String> = data.iter().map(RData::to_string).collect();
+        records.sort();
+        records.dedup();
+        DNS_CACHE.insert(&description, name, &record_type.to_string(), &records, ttl);
+        return Ok(records);
+    }
+    let result = block_on(lookup_resolver(&description)?.lookup(name, record_type))?;
+    records_answer(&description, name, record_type, result)
+}
+
+/// Converts the result of a record lookup into the records and caches them.
+///
+/// Only answers are cached: the records, or no records for a NOERROR or NXDOMAIN answer (see `is_negative_answer`).
+/// A SERVFAIL or REFUSED answer is a failure of the resolver, so it is never cached as a negative answer.
+///
+/// # Arguments
+///
+/// * `description`: A `&str` that describes the resolver that was queried, see `resolver_config`.
+/// * `name`: A `&str` that specifies the name that was looked up.
+/// * `record_type`: A `RecordType` that specifies the type of the records that were looked up.
+/// * `result`: A `Result<Lookup, ResolveError>` that holds the result of the lookup.
+///
+/// # Returns
+///
+/// * A `Result<Vec<String>, String>` that holds the records in presentation format, sorted, empty if the name has no such records.
+/// * If the lookup failed, it returns an `Err` with a message.
+fn records_answer(
+    description: &str,
+    name: &str,
+    record_type: RecordType,
+    result: Result<Lookup, ResolveError>,
+) -> Result<Vec<String>, String> {
+    match result {
+        Ok(response) => {
+            let mut records: Vec<String> = response
+                .record_iter()
+                .filter(|record| record.record_type() == record_type)
+                .filter_map(|record| record.data().map(|data| data.to_string()))
+                .collect();
+            records.sort();
+            records.dedup();
+            let ttl = response
+                .valid_until()
+                .saturating_duration_since(Instant::now());
+            DNS_CACHE.insert(
+                description,
+                name,
+                &record_type.to_string(),
+                &records,
+                Some(ttl),
+            );
+            Ok(records)
+        }
+        Err(err) if is_negative_answer(&err) => {
+            DNS_CACHE.insert(
+                description,
+                name,
+                &record_type.to_string(),
+                &[],
+                negative_ttl(&err),
+            );
+            Ok(Vec::new())
+        }
+        Err(err) => Err(format!(
+            "Failed to lookup {} records of {}: {}",
+            record_type, name, err
+        )),
+    }
+}
+
+/// Retrieves the negative TTL of a lookup that found no records, for the cache.
+///
+/// # Arguments
+///
+/// * `err`: A `&ResolveError` that holds the error of the lookup.
+///
+/// # Returns
+///
+/// * An `Option<Duration>` that holds the negative TTL from the SOA record of the zone, or `None` if the answer has none.
+fn negative_ttl(err: &ResolveError) -> Option<Duration> {
+    match err.kind() {
+        ResolveErrorKind::NoRecordsFound {
+            negative_ttl: Some(ttl),
+            ..
+        } => Some(Duration::from_secs(u64::from(*ttl))),
+        _ => None,
+    }
+}
+
+/// Looks up all IPv4 and IPv6 addresses of a name.
+///
+/// # Arguments
+///
+/// * `name`: A `&str` that specifies the name, e.g. "ns1.example.com".
+///
+/// # Returns
+///
+/// * A `Vec<IpAddr>` that holds the addresses, IPv4 first. If the lookup fails, it logs a warning and returns an empty `Vec`.
+pub fn lookup_addresses(name: &str) -> Vec<IpAddr> {
+    let description = default_resolver();
+    if is_tls_resolver(&description) {
+        return match resolve_tls(name, &description, AddressFamily::Dual) {
+            Ok((addresses, _)) => addresses.iter().filter_map(|ip| ip.parse().ok()).collect(),
+            Err(err) => {
+                log::warn!("Failed to lookup addresses of {}: {}", name, err);
+                Vec::new()
+            }
+        };
+    }
+    let response = match lookup_resolver(&description).and_then(|resolver| {
+        block_on(resolver.lookup_ip(name)).map(|response| response.map_err(|e| e.to_string()))
+    }) {
+        Ok(response) => response,
+        Err(err) => {
+            log::warn!("{}", err);
+            return Vec::new();
+        }
+    };
+    match response {
+        Ok(response) => {
+            let mut addresses: Vec<IpAddr> = response.iter().collect();
+            addresses.sort_by_key(|ip| ip.is_ipv6());
+            addresses
+        }
+        Err(err) => {
+            log::warn!("Failed to lookup addresses of {}: {}", name, err);
+            Vec::new()
+        }
+    }
+}
+
+/// Sends a query directly to a name server, without asking for recursion.
+///
+/// Unlike the lookups through a resolver, this returns the raw response, so referrals from the name servers of a parent zone
+/// and the authoritative answer flag can be inspected.
+///
+/// # Arguments
+///
+/// * `server`: A `SocketAddr` that specifies the address and port of the name server.
+/// * `name`: A `&str` that specifies the name to query.
+/// * `record_type`: A `RecordType` that specifies the type of the records to query.
+///
+/// # Returns
+///
+/// * A `Result<Message, String>` that holds the response, or an `Err` with a message if the server does not answer in time.
+pub fn query_server(
+    server: SocketAddr,
+    name: &str,
+    record_type: RecordType,
+) -> Result<Message, String> {
+    let mut query = new_query(name, record_type)?;
+    query.set_recursion_desired(false);
+    exchange(server, &query)
+}
+
+/// Sends a recursive query to a resolver, optionally with an EDNS Client Subnet option.
+///
+/// The option carries the network of the client, truncated to its prefix length,
+/// so resolvers and name servers that support it answer as they would for a client in that network.
+///
+/// # Arguments
+///
+/// * `server`: A `SocketAddr` that specifies the address and port of the resolver.
+/// * `name`: A `&str` that specifies the name to query.
+/// * `record_type`: A `RecordType` that specifies the type of the records to query.
+/// * `subnet`: An `Option<IpNet>` that specifies the client network, e.g. "203.0.113.0/24", or `None` to send none.
+///
+/// # Returns
+///
+/// * A `Result<Message, String>` that holds the response, or an `Err` with a message if the server does not answer in time.
+pub fn query_recursive(
+    server: SocketAddr,
+    name: &str,
+    record_type: RecordType,
+    subnet: Option<IpNet>,
+) -> Result<Message, String> {
+    let mut edns = Edns::new();
+    edns.set_max_payload(1232);
+    if let Some(subnet) = subnet {
+        edns.options_mut()
+            .insert(EdnsOption::Subnet(ClientSubnet::new(
+                subnet.network(),
+                subnet.prefix_len(),
+                0,
+            )));
+    }
+    let mut query = new_query(name, record_type)?;
+    query.set_recursion_desired(true).set_edns(edns);
+    exchange(server, &query)
+}
+
+/// Sends a recursive query for the A records of a name to a validating resolver, asking for DNSSEC records.
+///
+/// The query sets the "DNSSEC OK" bit, so a validating resolver reports a validated answer with the "AD" bit,
+/// and answers "SERVFAIL" if the validation fails. With "CD" (checking disabled) it answers without validating.
+///
+/// # Arguments
+///
+/// * `server`: A `SocketAddr` that specifies the address and port of the resolver.
+/// * `name`: A `&str` that specifies the name to query.
+/// * `checking_disabled`: A `bool` that indicates whether the resolver is asked not to validate the answer.
+///
+/// # Returns
+///
+/// * A `Result<Message, String>` that holds the response, or an `Err` with a message if the server does not answer in time.
+pub fn query_dnssec(
+    server: SocketAddr,
+    name: &str,
+    checking_disabled: bool,
+) -> Result<Message, String> {
+    let mut edns = Edns::new();
+    edns.set_max_payload(1232);
+    edns.set_dnssec_ok(true);
+    let mut query = new_query(name, RecordType::A)?;
+    query
+        .set_recursion_desired(true)
+        .set_checking_disabled(checking_disabled)
+        .set_edns(edns);
+    exchange(server, &query)
+}
+
+/// Builds a query for one name, with a random ID.
+///
+/// The ID is drawn from the random number generator of the operating system, so an off-path attacker
+/// cannot predict it to spoof a response, see `exchange`.
+///
+/// # Arguments
+///
+/// * `name`: A `&str` that specifies the name to query.
+/// * `record_type`: A `RecordType` that specifies the type of the records to query.
+///
+/// # Returns
+///
+/// * A `Result<Message, String>` that holds the query, or an `Err` with a message if the name is invalid
+///   or no random ID can be drawn.
+fn new_query(name: &str, record_type: RecordType) -> Result<Message, String> {
+    let name = Name::from_ascii(name).map_err(|e| format!("Invalid name {}: {}", name, e))?;
+    let mut id = [0u8; 2];
+    getrandom::getrandom(&mut id).map_err(|e| format!("Failed to draw a query ID: {}", e))?;
+    let id = u16::from_be_bytes(id);
+    let mut query = Message::new();
+    query
+        .set_id(id)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .add_query(Query::query(name, record_type));
+    Ok(query)
+}
+
+/// How long `exchange` waits for the response to a query.
+const EXCHANGE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Sends a query over UDP and waits for the response with the same ID and question.
+///
+/// Datagrams from other addresses, that are not DNS messages, or with another ID or question, are ignored, so a spoofed response
+/// has to guess the random ID of the query (see `new_query`) as well as its port.
+/// The response has to arrive within `EXCHANGE_TIMEOUT` of sending the query, however many datagrams are ignored.
+///
+/// # Arguments
+///
+/// * `server`: A `SocketAddr` that specifies the address and port of the server.
+/// * `query`: A `&Message` that holds the query.
+///
+/// # Returns
+///
+/// * A `Result<Message, String>` that holds the response, or an `Err` with a message if the server does not answer in time.
+fn exchange(server: SocketAddr, query: &Message) -> Result<Message, String> {
+    let id = query.id();
+    let request = query.to_vec().map_err(|e| e.to_string())?;
+
+    let local: SocketAddr = if server.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(local).map_err(|e| e.to_string())?;
+    socket
+        .send_to(&request, server)
+        .map_err(|e| format!("Failed to query {}: {}", server, e))?;
+    let deadline = Instant::now() + EXCHANGE_TIMEOUT;
+    let mut buffer = [0u8; 4096];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(format!("No answer from {}: timed out", server));
+        }
+        socket
+            .set_read_timeout(Some(remaining))
+            .map_err(|e| e.to_string())?;
+        let (length, from) = socket
+            .recv_from(&mut buffer)
+            .map_err(|e| format!("No answer from {}: {}", server, e))?;
+        if from != server {
+            continue;
+        }
+        let response = match Message::from_vec(&buffer[..length]) {
+            Ok(response) => response,
+            Err(e) => {
+                log::debug!("Ignoring an invalid answer from {}: {}", server, e);
+                continue;
+            }
+        };
+        if response.id() == id && response.queries() == query.queries() {
+            return Ok(response);
+        }
+    }
+}
+
+/// Looks up the records of a type at a name using a DNS-over-TLS resolver.
+///
+/// The servers of the resolver are queried one after the other until one answers with "NOERROR" or "NXDOMAIN".
+///
+/// # Arguments
+///
+/// * `resolver`: A `&str` that describes the resolver, see `tls_servers`.
+/// * `name`: A `&str` that specifies the name, in ASCII.
+/// * `record_type`: A `RecordType` that specifies the type of the records.
+///
+/// # Returns
+///
+/// * A `Result<(Vec<RData>, Option<Duration>), String>` that holds the data of the records of the type in the answer,
+///   and the TTL for the cache: the lowest TTL of the records, or the negative TTL from the SOA record if there are none.
+/// * If no server answers, it returns an `Err` with a message.
+fn lookup_tls(
+    resolver: &str,
+    name: &str,
+    record_type: RecordType,
+) -> Result<(Vec<RData>, Option<Duration>), String> {
+    let servers = tls_servers(resolver)?;
+    let config = http::tls_config()?;
+    let mut error = format!("Resolver {} has no name server", resolver);
+    for server in &servers {
+        let mut query = new_query(name, record_type)?;
+        query.set_recursion_desired(true);
+        let response = match exchange_tls(server, config.clone(), &query) {
+            Ok(response) => response,
+            Err(err) => {
+                error = err;
+                continue;
+            }
+        };
+        if !matches!(
+            response.response_code(),
+            ResponseCode::NoError | ResponseCode::NXDomain
+        ) {
+            error = format!(
+                "{} answered {}",
+                server.addr,
+                format!("{:?}", response.response_code()).to_uppercase()
+            );
+            continue;
+        }
+        let records: Vec<&Record> = response
+            .answers()
+            .iter()
+            .filter(|record| record.record_type() == record_type)
+            .collect();
+        let ttl = if records.is_empty() {
+            response
+                .name_servers()
+                .iter()
+                .find_map(|record| match record.data() {
+                    Some(RData::SOA(soa)) => Some(record.ttl().min(soa.minimum())),
+                    _ => None,
+                })
+        } else {
+            records.iter().map(|record| record.ttl()).min()
+        };
+        return Ok((
+            records
+                .iter()
+                .filter_map(|record| record.data().cloned())
+                .collect(),
+            ttl.map(|ttl| Duration::from_secs(u64::from(ttl))),
+        ));
+    }
+    Err(error)
+}
+
+/// Sends a query to a DNS-over-TLS server and waits for the response.
+///
+/// # Arguments
+///
+/// * `server`: A `&TlsServer` that specifies the server.
+/// * `config`: An `Arc<ClientConfig>` that holds the TLS configuration, see `http::tls_config`.
+/// * `query`: A `&Message` that holds the query.
+///
+/// # Returns
+///
+/// * A `Result<Message, String>` that holds the response, or an `Err` with a message if the connection or the handshake fails,
+///   or the server does not answer in time.
+fn exchange_tls(
+    server: &TlsServer,
+    config: Arc<ClientConfig>,
+    query: &Message,
+) -> Result<Message, String> {
+    let server_name = ServerName::try_from(server.server_name.clone())
+        .map_err(|e| format!("Invalid server name {}: {}", server.server_name, e))?;
+    let connection = ClientConnection::new(config, server_name)
+        .map_err(|e| format!("Failed to start TLS with {}: {}", server.addr, e))?;
+    let stream = TcpStream::connect_timeout(&server.addr, Duration::from_secs(5))
+        .map_err(|e| format!("Failed to connect to {}: {}", server.addr, e))?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .and_then(|_| stream.set_write_timeout(Some(Duration::from_secs(5))))
+        .map_err(|e| e.to_string())?;
+    exchange_stream(server.addr, StreamOwned::new(connection, stream), query)
+}
+
+/// Sends a query over a stream, framed with its length like DNS over TCP, and waits for the response with the same ID.
+///
+/// Messages that cannot be parsed or have another ID are skipped.
+///
+/// # Arguments
+///
+/// * `server`: A `SocketAddr` that specifies the address of the server, for the error messages.
+/// * `stream`: An `S` that holds the connection to the server.
+/// * `query`: A `&Message` that holds the query.
+///
+/// # Returns
+///
+/// * A `Result<Message, String>` that holds the response, or an `Err` with a message if the server does not answer.
+fn exchange_stream<S: Read + Write>(
+    server: SocketAddr,
+    mut stream: S,
+    query: &Message,
+) -> Result<Message, String> {
+    let request = query.to_vec().map_err(|e| e.to_string())?;
+    stream
+        .write_all(&[&(request.len() as u16).to_be_bytes()[..], &request].concat())
+        .and_then(|_| stream.flush())
+        .map_err(|e| format!("Failed to query {}: {}", server, e))?;
+    loop {
+        let mut length = [0u8; 2];
+        let mut message = Vec::new();
+        stream
+            .read_exact(&mut length)
+            .and_then(|_| {
+                message.resize(u16::from_be_bytes(length) as usize, 0);
+                stream.read_exact(&mut message)
+            })
+            .map_err(|e| format!("No answer from {}: {}", server, e))?;
+        let response = match Message::from_vec(&message) {
+            Ok(response) => response,
+            Err(e) => {
+                log::debug!("Ignoring an invalid answer from {}: {}", server, e);
+                continue;
+            }
+        };
+        if response.id() == query.id() {
+            return Ok(response);
+        }
+    }
+}
+
+/// Transfers a zone from a name server (AXFR), optionally authenticated with TSIG.
+///
+/// The transfer runs over TCP. The messages are read until the SOA record that opened the transfer is repeated.
+/// With a key, the request is signed and every response is verified, see `tsig::Verifier`.
+///
+/// # Arguments
+///
+/// * `server`: A `SocketAddr` that specifies the address and port of the name server, usually the primary.
+/// * `zone`: A `&str` that specifies the zone, e.g. "example.com".
+/// * `key`: An `Option<&TsigKey>` that holds the TSIG key, or `None` for an unauthenticated transfer.
+///
+/// # Returns
+///
+/// * A `Result<Vec<Record>, String>` that holds the records of the zone, starting with the SOA record.
+/// * If the name server cannot be reached, refuses the transfer, or a signature is invalid, it returns an `Err` with a message.
+pub fn transfer_zone(
+    server: SocketAddr,
+    zone: &str,
+    key: Option<&TsigKey>,
+) -> Result<Vec<Record>, String> {
+    let now = || {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default()
+    };
+    let mut query = new_query(zone, RecordType::AXFR)?;
+    query.set_recursion_desired(false);
+    let mut request = query.to_vec().map_err(|e| e.to_string())?;
+    let mut verifier = None;
+    if let Some(key) = key {
+        let (signed, mac) = key.sign(&request, now())?;
+        request = signed;
+        verifier = Some(tsig::Verifier::new(key, mac));
+    }
+
+    let mut stream = TcpStream::connect_timeout(&server, Duration::from_secs(5))
+        .map_err(|e| format!("Failed to connect to {}: {}", server, e))?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .map_err(|e| e.to_string())?;
+    stream
+        .write_all(&[&(request.len() as u16).to_be_bytes()[..], &request].concat())
+        .map_err(|e| format!("Failed to request the transfer of {}: {}", zone, e))?;
+
+    let mut records: Vec<Record> = Vec::new();
+    loop {
+        let mut length = [0u8; 2];
+        let mut message = Vec::new();
+        stream
+            .read_exact(&mut length)
+            .and_then(|_| {
+                message.resize(u16::from_be_bytes(length) as usize, 0);
+                stream.read_exact(&mut message)
+            })
+            .map_err(|e| format!("Transfer of {} from {} failed: {}", zone, server, e))?;
+        if let Some(verifier) = verifier.as_mut() {
+            message = verifier.verify(&message, now())?;
+        }
+        let response = Message::from_vec(&message)
+            .map_err(|e| format!("Invalid answer from {}: {}", server, e))?;
+        if response.response_code() != ResponseCode::NoError {
+            return Err(format!(
+                "{} refused the transfer of {}: {}",
+                server,
+                zone,
+                format!("{:?}", response.response_code()).to_uppercase()
+            ));
+        }
+        records.extend(response.answers().iter().cloned());
+        match records.first().map(|record| record.record_type()) {
+            Some(RecordType::SOA) => {}
+            Some(_) => return Err(format!("Transfer of {} does not start with SOA", zone)),
+            None => return Err(format!("Empty transfer of {} from {}", zone, server)),
+        }
+        if records.len() > 1 && records.last().map(|r| r.record_type()) == Some(RecordType::SOA) {
+            records.pop();
+            break;
+        }
+    }
+    if let Some(verifier) = verifier {
+        verifier.finish()?;
+    }
+    Ok(records)
+}
+
+/// Collects the name server names of the NS records in a section of a response.
+///
+/// # Arguments
+///
+/// * `records`: A `&[Record]` that holds the records, e.g. `Message::name_servers`.
+///
+/// # Returns
+///
+/// * A `BTreeSet<String>` that holds the names in lower case and without the trailing dot.
+pub fn ns_names(records: &[Record]) -> BTreeSet<String> {
+    records
+        .iter()
+        .filter_map(|record| match record.data() {
+            Some(RData::NS(ns)) => Some(normalize_name(&ns.0)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Collects the addresses of the A and AAAA records in a section of a response, e.g. the glue of a referral.
+///
+/// # Arguments
+///
+/// * `records`: A `&[Record]` that holds the records, e.g. `Message::additionals`.
+///
+/// # Returns
+///
+/// * A `BTreeMap<String, BTreeSet<IpAddr>>` that holds the addresses of each name.
+pub fn addresses(records: &[Record]) -> BTreeMap<String, BTreeSet<IpAddr>> {
+    let mut addresses: BTreeMap<String, BTreeSet<IpAddr>> = BTreeMap::new();
+    for record in records {
+        let ip = match record.data() {
+            Some(RData::A(a)) => IpAddr::V4(a.0),
+            Some(RData::AAAA(aaaa)) => IpAddr::V6(aaaa.0),
+            _ => continue,
+        };
+        addresses
+            .entry(normalize_name(record.name()))
+            .or_default()
+            .insert(ip);
+    }
+    addresses
+}
+
+/// Converts a name to lower case and removes the trailing dot, e.g. "NS1.Example.com." to "ns1.example.com".
+fn normalize_name(name: &Name) -> String {
+    name.to_ascii().trim_end_matches('.').to_lowercase()
+}
+
+/// Synthesizes a random name below a zone for probing wildcard records.
+///
+/// This function takes a zone as an argument and prepends a random label,
+/// e.g. "dnschecker-5f3a9c1e.example.com", that is very unlikely to have a record of its own.
+///
+/// # Arguments
+///
+/// * `zone`: A `&str` that specifies the zone.
+///
+/// # Returns
+///
+/// * A `String` that holds the probe name.
+pub fn probe_name(zone: &str) -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos() ^ (duration.as_secs() as u32))
+        .unwrap_or_default();
+    format!(
+        "dnschecker-{:08x}.{}",
+        nanos ^ std::process::id().rotate_left(16),
+        zone.trim_end_matches('.')
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_hostname() {
+        let result = resolve_hostname("localhost");
+
+        assert_eq!(result, "127.0.0.1");
+
+        let result = resolve_hostname("LocalHost");
+
+        assert_eq!(result, "127.0.0.1");
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy {
+            retries: 3,
+            delay: Duration::from_millis(10),
+            backoff: 3.0,
+            max_delay: Duration::from_millis(200),
+        };
+        assert_eq!(policy.delay(0), Duration::from_millis(10));
+        assert_eq!(policy.delay(2), Duration::from_millis(90));
+
+        // Assert that the delay is capped, also when the backoff overflows a Duration
+        assert_eq!(policy.delay(3), Duration::from_millis(200));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_millis(200));
+
+        // Assert that a transient failure is retried until the lookup succeeds
+        let mut attempts = 0;
+        let result = policy.run(|| {
+            attempts += 1;
+            if attempts < 3 {
+                Err("SERVFAIL".to_string())
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(result, Ok(3));
+
+        // Assert that the error of the last attempt is returned when every attempt fails
+        let mut attempts = 0;
+        let result: Result<(), String> = policy.run(|| {
+            attempts += 1;
+            Err(format!("attempt {}", attempts))
+        });
+        assert_eq!(result, Err("attempt 4".to_string()));
+    }
+
+    #[test]
+    fn test_family_answer() {
+        let policy = RetryPolicy {
+            retries: 2,
+            delay: Duration::from_millis(1),
+            backoff: 1.0,
+            max_delay: Duration::from_millis(1),
+        };
+        let no_records = |response_code| {
+            ResolveError::from(ResolveErrorKind::NoRecordsFound {
+                query: Box::new(Query::query(
+                    Name::from_ascii("home.example.com.").unwrap(),
+                    RecordType::A,
+                )),
+                soa: None,
+                negative_ttl: Some(30),
+                response_code,
+                trusted: true,
+            })
+        };
+
+        // Assert that a SERVFAIL answer is a failure, so it is retried
+        let mut attempts = 0;
+        let result = policy.run(|| {
+            attempts += 1;
+            family_answer(
+                "home.example.com",
+                AddressFamily::V4,
+                Err(no_records(ResponseCode::ServFail)),
+            )
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+
+        // Assert that NOERROR and NXDOMAIN answers without records are empty answers, with the negative TTL
+        for response_code in [ResponseCode::NoError, ResponseCode::NXDomain] {
+            let mut attempts = 0;
+            let result = policy.run(|| {
+                attempts += 1;
+                family_answer(
+                    "home.example.com",
+                    AddressFamily::V4,
+                    Err(no_records(response_code)),
+                )
+            });
+            assert_eq!(result, Ok((Vec::new(), Some(Duration::from_secs(30)))));
+            assert_eq!(attempts, 1);
+        }
+    }
+
+    #[test]
+    fn test_records_answer() {
+        env::set_var("DNS_CACHE", "true");
+        let no_records = |response_code| {
+            ResolveError::from(ResolveErrorKind::NoRecordsFound {
+                query: Box::new(Query::query(
+                    Name::from_ascii("missing.example.com.").unwrap(),
+                    RecordType::CAA,
+                )),
+                soa: None,
+                negative_ttl: Some(30),
+                response_code,
+                trusted: true,
+            })
+        };
+
+        // Assert that a REFUSED answer is a failure and is not cached as a negative answer
+        let result = records_answer(
+            "cache-test",
+            "missing.example.com",
+            RecordType::CAA,
+            Err(no_records(ResponseCode::Refused)),
+        );
+        assert!(result.is_err());
+        assert_eq!(
+            DNS_CACHE.get("cache-test", "missing.example.com", "CAA"),
+            None
+        );
+
+        // Assert that an NXDOMAIN answer is cached as a negative answer
+        let result = records_answer(
+            "cache-test",
+            "missing.example.com",
+            RecordType::CAA,
+            Err(no_records(ResponseCode::NXDomain)),
+        );
+        assert_eq!(result, Ok(Vec::new()));
+        assert_eq!(
+            DNS_CACHE.get("cache-test", "missing.example.com", "CAA"),
+            Some(Vec::new())
+        );
+        env::remove_var("DNS_CACHE");
+    }
+
+    #[test]
+    fn test_query_server() {
+        // Answer one query like the name server of a parent zone, with a referral and glue
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut buffer = [0u8; 512];
+            let (length, from) = server.recv_from(&mut buffer).unwrap();
+            let query = Message::from_vec(&buffer[..length]).unwrap();
+            let zone = Name::from_ascii("example.com.").unwrap();
+            let ns = Name::from_ascii("NS1.Example.com.").unwrap();
+            let mut response = Message::new();
+            response
+                .set_id(query.id())
+                .set_message_type(MessageType::Response)
+                .add_queries(query.queries().to_vec())
+                .add_name_server(Record::from_rdata(
+                    zone,
+                    3600,
+                    RData::NS(trust_dns_resolver::proto::rr::rdata::NS(ns.clone())),
+                ))
+                .add_additional(Record::from_rdata(
+                    ns,
+                    3600,
+                    RData::A(trust_dns_resolver::proto::rr::rdata::A::new(192, 0, 2, 1)),
+                ));
+            server.send_to(&response.to_vec().unwrap(), from).unwrap();
+        });
+
+        let response = query_server(addr, "example.com.", RecordType::NS).unwrap();
+        assert!(!response.recursion_desired());
+        assert!(!response.authoritative());
+        assert!(response.answers().is_empty());
+        assert_eq!(
+            ns_names(response.name_servers()),
+            BTreeSet::from(["ns1.example.com".to_string()])
+        );
+        assert_eq!(
+            addresses(response.additionals())["ns1.example.com"],
+            BTreeSet::from(["192.0.2.1".parse().unwrap()])
+        );
+    }
+
+    #[test]
+    fn test_exchange_question() {
+        // Answer first for another name with the ID of the query, like a spoofed response, then for the name
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut buffer = [0u8; 512];
+            let (length, from) = server.recv_from(&mut buffer).unwrap();
+            let query = Message::from_vec(&buffer[..length]).unwrap();
+            for (name, last) in [("evil.example.com.", 66), ("home.example.com.", 1)] {
+                let name = Name::from_ascii(name).unwrap();
+                let mut response = Message::new();
+                response
+                    .set_id(query.id())
+                    .set_message_type(MessageType::Response)
+                    .add_query(Query::query(name.clone(), RecordType::A))
+                    .add_answer(Record::from_rdata(
+                        name,
+                        60,
+                        RData::A(trust_dns_resolver::proto::rr::rdata::A::new(
+                            192, 0, 2, last,
+                        )),
+                    ));
+                server.send_to(&response.to_vec().unwrap(), from).unwrap();
+            }
+        });
+
+        // Assert that the response to another question is ignored
+        let query = new_query("home.example.com.", RecordType::A).unwrap();
+        let response = exchange(addr, &query).unwrap();
+        assert_eq!(response.queries(), query.queries());
+        assert_eq!(
+            response.answers()[0].data(),
+            Some(&RData::A(trust_dns_resolver::proto::rr::rdata::A::new(
+                192, 0, 2, 1
+            )))
+        );
+    }
+
+    #[test]
+    fn test_exchange_deadline() {
+        // Answer with a stream of responses to another query for longer than the timeout, like stray datagrams
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut buffer = [0u8; 512];
+            let (length, from) = server.recv_from(&mut buffer).unwrap();
+            let query = Message::from_vec(&buffer[..length]).unwrap();
+            let mut response = Message::new();
+            response
+                .set_id(query.id().wrapping_add(1))
+                .set_message_type(MessageType::Response)
+                .add_queries(query.queries().to_vec());
+            let response = response.to_vec().unwrap();
+            for _ in 0..50 {
+                let _ = server.send_to(&response, from);
+                std::thread::sleep(Duration::from_millis(100));
+            }
+        });
+
+        // Assert that the ignored datagrams do not extend the wait for the response
+        let started = Instant::now();
+        let query = new_query("home.example.com.", RecordType::A).unwrap();
+        assert!(exchange(addr, &query).is_err());
+        assert!(started.elapsed() < EXCHANGE_TIMEOUT + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_exchange_invalid() {
+        // Answer with a datagram that is not a DNS message, then with the response
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut buffer = [0u8; 512];
+            let (length, from) = server.recv_from(&mut buffer).unwrap();
+            let query = Message::from_vec(&buffer[..length]).unwrap();
+            server.send_to(&[0xff; 3], from).unwrap();
+            let mut response = Message::new();
+            response
+                .set_id(query.id())
+                .set_message_type(MessageType::Response)
+                .add_queries(query.queries().to_vec());
+            server.send_to(&response.to_vec().unwrap(), from).unwrap();
+        });
+
+        // Assert that the garbage is skipped instead of failing the query
+        let query = new_query("home.example.com.", RecordType::A).unwrap();
+        assert_eq!(exchange(addr, &query).unwrap().id(), query.id());
+    }
+
+    #[test]
+    fn test_query_addresses() {
+        // Answer like a GeoDNS name server, with an address depending on the client network
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut buffer = [0u8; 512];
+            for _ in 0..3 {
+                let (length, from) = server.recv_from(&mut buffer).unwrap();
+                let query = Message::from_vec(&buffer[..length]).unwrap();
+                let asia = ClientSubnet::new("203.0.113.0".parse().unwrap(), 24, 0);
+                let europe = ClientSubnet::new("198.51.100.0".parse().unwrap(), 24, 0);
+                let last = match query.extensions().as_ref().and_then(|edns| {
+                    edns.option(trust_dns_resolver::proto::rr::rdata::opt::EdnsCode::Subnet)
+                }) {
+                    Some(EdnsOption::Subnet(subnet)) if *subnet == asia => 1,
+                    Some(EdnsOption::Subnet(subnet)) if *subnet == europe => 2,
+                    _ => 3,
+                };
+                let mut response = Message::new();
+                response
+                    .set_id(query.id())
+                    .set_message_type(MessageType::Response)
+                    .set_recursion_desired(query.recursion_desired())
+                    .set_recursion_available(true)
+                    .add_queries(query.queries().to_vec())
+                    .add_answer(Record::from_rdata(
+                        query.queries()[0].name().clone(),
+                        60,
+                        RData::A(trust_dns_resolver::proto::rr::rdata::A::new(
+                            192, 0, 2, last,
+                        )),
+                    ));
+                server.send_to(&response.to_vec().unwrap(), from).unwrap();
+            }
+        });
+
+        let resolver = addr.to_string();
+        let response = query_addresses(
+            "home.example.com",
+            &resolver,
+            Some("203.0.113.7/24".parse().unwrap()),
+        )
+        .unwrap();
+        assert_eq!(response.addresses(), ["192.0.2.1"]);
+        assert_eq!(response.server, addr);
+        assert_eq!(response.response_code, "NOERROR");
+        assert!(!response.authoritative && !response.truncated && response.recursion_available);
+        assert_eq!(
+            response.summary(),
+            format!(
+                "NOERROR from {}, flags: ra, answers: home.example.com. 60 A 192.0.2.1",
+                addr
+            )
+        );
+
+        let response = query_addresses(
+            "home.example.com",
+            &resolver,
+            Some("198.51.100.0/24".parse().unwrap()),
+        )
+        .unwrap();
+        assert_eq!(response.addresses(), ["192.0.2.2"]);
+
+        let response = query_addresses("home.example.com", &resolver, None).unwrap();
+        assert_eq!(response.addresses(), ["192.0.2.3"]);
+    }
+
+    #[test]
+    fn test_transfer_zone() {
+        // Transfer the zone in two messages, like a primary name server
+        let server = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = server.accept().unwrap();
+            let mut length = [0u8; 2];
+            stream.read_exact(&mut length).unwrap();
+            let mut request = vec![0u8; u16::from_be_bytes(length) as usize];
+            stream.read_exact(&mut request).unwrap();
+            let query = Message::from_vec(&request).unwrap();
+            assert_eq!(query.queries()[0].query_type(), RecordType::AXFR);
+
+            let zone = Name::from_ascii("example.com.").unwrap();
+            let soa = Record::from_rdata(
+                zone.clone(),
+                3600,
+                RData::SOA(trust_dns_resolver::proto::rr::rdata::SOA::new(
+                    Name::from_ascii("ns1.example.com.").unwrap(),
+                    Name::from_ascii("hostmaster.example.com.").unwrap(),
+                    1,
+                    3600,
+                    600,
+                    86400,
+                    300,
+                )),
+            );
+            let a = |name: &str, last| {
+                Record::from_rdata(
+                    Name::from_ascii(name).unwrap(),
+                    300,
+                    RData::A(trust_dns_resolver::proto::rr::rdata::A::new(
+                        192, 0, 2, last,
+                    )),
+                )
+            };
+            for answers in [
+                vec![soa.clone(), a("home.example.com.", 1)],
+                vec![a("nas.example.com.", 2), soa],
+            ] {
+                let mut response = Message::new();
+                response
+                    .set_id(query.id())
+                    .set_message_type(MessageType::Response)
+                    .add_queries(query.queries().to_vec())
+                    .add_answers(answers);
+                let response = response.to_vec().unwrap();
+                stream
+                    .write_all(&(response.len() as u16).to_be_bytes())
+                    .unwrap();
+                stream.write_all(&response).unwrap();
+            }
+        });
+
+        let records = transfer_zone(addr, "example.com", None).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].record_type(), RecordType::SOA);
+        assert_eq!(
+            addresses(&records)["nas.example.com"],
+            BTreeSet::from(["192.0.2.2".parse().unwrap()])
+        );
+    }
+
+    #[test]
+    fn test_resolver_config() {
+        let (config, _) = resolver_config("192.168.1.1, 127.0.0.1:5353,[::1]:5353").unwrap();
+        let servers: Vec<SocketAddr> = config
+            .name_servers()
+            .iter()
+            .map(|server| server.socket_addr)
+            .collect();
+        assert!(servers.contains(&"192.168.1.1:53".parse().unwrap()));
+        assert!(servers.contains(&"127.0.0.1:5353".parse().unwrap()));
+        assert!(servers.contains(&"[::1]:5353".parse().unwrap()));
+
+        let (config, _) = resolver_config("opendns").unwrap();
+        assert!(config
+            .name_servers()
+            .iter()
+            .any(|server| server.socket_addr == "208.67.222.222:53".parse().unwrap()));
+
+        assert!(resolver_config("127.0.0.1:domain").is_err());
+        assert!(resolver_config(" , ").is_err());
+
+        assert_eq!(resolvers_or_default(None, false), "google");
+        assert_eq!(resolvers_or_default(Some(" ".to_string()), true), "system");
+        assert_eq!(
+            resolvers_or_default(Some("quad9".to_string()), false),
+            "quad9"
+        );
+
+        // Assert that a resolver is built once per description and strategy
+        let built = || {
+            RESOLVERS
+                .lock()
+                .unwrap()
+                .keys()
+                .filter(|key| key.starts_with("192.0.2.250/"))
+                .count()
+        };
+        assert!(shared_resolver("192.0.2.250", LookupIpStrategy::Ipv4Only).is_ok());
+        assert!(shared_resolver("192.0.2.250", LookupIpStrategy::Ipv4Only).is_ok());
+        assert_eq!(built(), 1);
+        assert!(shared_resolver("192.0.2.250", LookupIpStrategy::Ipv6Only).is_ok());
+        assert_eq!(built(), 2);
+        assert!(shared_resolver("cloudflare-tls", LookupIpStrategy::Ipv4Only).is_err());
+    }
+
+    #[test]
+    fn test_tls_servers() {
+        assert!(is_tls_resolver("cloudflare-tls"));
+        assert!(!is_tls_resolver("cloudflare"));
+        assert_eq!(
+            tls_servers("quad9-tls").unwrap()[0],
+            TlsServer {
+                addr: "9.9.9.9:853".parse().unwrap(),
+                server_name: "dns.quad9.net".to_string(),
+            }
+        );
+        assert_eq!(
+            tls_servers("tls:192.0.2.53#dns.example.com, [2001:db8::53]:8853").unwrap(),
+            vec![
+                TlsServer {
+                    addr: "192.0.2.53:853".parse().unwrap(),
+                    server_name: "dns.example.com".to_string(),
+                },
+                TlsServer {
+                    addr: "[2001:db8::53]:8853".parse().unwrap(),
+                    server_name: "2001:db8::53".to_string(),
+                },
+            ]
+        );
+
+        assert!(tls_servers("tls:dns.example.com").is_err());
+        assert!(tls_servers("tls:").is_err());
+        assert!(validate_resolver("tls:192.0.2.53").is_ok());
+        assert!(resolver_config("tls:192.0.2.53").is_err());
+    }
+
+    #[test]
+    fn test_exchange_stream() {
+        // Answer one query over TCP, framed like DNS-over-TLS
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut length = [0u8; 2];
+            stream.read_exact(&mut length).unwrap();
+            let mut request = vec![0u8; u16::from_be_bytes(length) as usize];
+            stream.read_exact(&mut request).unwrap();
+            let query = Message::from_vec(&request).unwrap();
+            let mut response = Message::new();
+            response
+                .set_id(query.id())
+                .set_message_type(MessageType::Response)
+                .add_queries(query.queries().to_vec())
+                .add_answer(Record::from_rdata(
+                    Name::from_ascii("home.example.com.").unwrap(),
+                    60,
+                    RData::A(trust_dns_resolver::proto::rr::rdata::A::new(192, 0, 2, 1)),
+                ));
+            let response = response.to_vec().unwrap();
+            stream
+                .write_all(&[&(response.len() as u16).to_be_bytes()[..], &response].concat())
+                .unwrap();
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let query = new_query("home.example.com.", RecordType::A).unwrap();
+        let response = exchange_stream(addr, stream, &query).unwrap();
+        assert_eq!(response.id(), query.id());
+        assert_eq!(
+            response.answers()[0].data().and_then(RData::ip_addr),
+            Some("192.0.2.1".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_probe_name() {
+        let result = probe_name("example.com.");
+
+        assert!(result.starts_with("dnschecker-"));
+        assert!(result.ends_with(".example.com"));
+        assert_eq!(result.split('.').next().unwrap().len(), 19);
     }
 }
@@ -0,0 +1,280 @@
+use crate::dns;
+use crate::events;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::vars::display_hostname;
+use std::env;
+use trust_dns_resolver::config::Protocol;
+use trust_dns_resolver::proto::op::{Message as DnsMessage, ResponseCode};
+use trust_dns_resolver::proto::rr::rdata::opt::{EdnsCode, EdnsOption};
+
+/// The option code of Extended DNS Errors (RFC 8914).
+const EXTENDED_DNS_ERROR: u16 = 15;
+
+/// The Extended DNS Error codes that report a failed DNSSEC validation, with their names from RFC 8914.
+const DNSSEC_ERRORS: [(u16, &str); 7] = [
+    (6, "DNSSEC Bogus"),
+    (7, "Signature Expired"),
+    (8, "Signature Not Yet Valid"),
+    (9, "DNSKEY Missing"),
+    (10, "RRSIGs Missing"),
+    (11, "No Zone Key Bit Set"),
+    (12, "NSEC Missing"),
+];
+
+/// The outcome of the DNSSEC validation of an answer.
+#[derive(Debug, PartialEq)]
+enum Validation {
+    /// The answer was validated, the resolver set the "AD" bit.
+    Secure,
+    /// The zone is not signed, or the resolver does not validate.
+    Insecure,
+    /// The validation failed, with the reason.
+    Bogus(String),
+}
+
+impl Validation {
+    /// The name of the outcome, as stored in the state file.
+    fn state(&self) -> &'static str {
+        match self {
+            Validation::Secure => "secure",
+            Validation::Insecure => "insecure",
+            Validation::Bogus(_) => "bogus",
+        }
+    }
+}
+
+/// Returns whether DNSSEC validation is enabled with the "DNSSEC_VALIDATION" environment variable.
+fn enabled() -> bool {
+    env::var("DNSSEC_VALIDATION").is_ok_and(|value| value == "true")
+}
+
+/// Verifies that the answer for the hostname passes DNSSEC validation.
+///
+/// This function returns immediately unless "DNSSEC_VALIDATION" is "true".
+///
+/// It queries the name servers of the upstream resolvers (see `dns::default_resolver`) over UDP with the "DNSSEC OK" bit,
+/// so they must validate, as Google, Cloudflare and Quad9 do (see `validate`).
+/// A failed validation is a "dnssec" event of its own: the resolvers answer "SERVFAIL", so the hostname has no address
+/// and the addresses are not compared, instead of raising a mismatch.
+/// The result is compared with the state stored in the DNSSEC file ("DNSSEC_FILE", defaulting to "dnssec_state" in the state directory),
+/// and a message is sent when the validation starts failing and when it passes again.
+///
+/// # Arguments
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+pub fn check_dnssec(hostname: &str) {
+    if !enabled() {
+        return;
+    }
+    let resolver = dns::default_resolver();
+    if dns::is_tls_resolver(&resolver) {
+        log::warn!(
+            "DNSSEC validation queries are sent over UDP, they cannot use a DNS-over-TLS resolver"
+        );
+        return;
+    }
+    let dnssec_file = state_path("DNSSEC_FILE", "dnssec_state");
+
+    let validation = match validate(hostname, &resolver) {
+        Ok(validation) => validation,
+        Err(e) => {
+            log::warn!("Failed to validate {}: {}", hostname, e);
+            return;
+        }
+    };
+    log::debug!("DNSSEC validation of {}: {:?}", hostname, validation);
+
+    let state = validation.state();
+    let previous = read_state(&dnssec_file);
+    if previous == state {
+        return;
+    }
+    if let Validation::Bogus(reason) = &validation {
+        let text = format!(
+            "DNSSEC validation failed!\nHostname: {}\nReason: {}",
+            display_hostname(hostname),
+            reason
+        );
+        log::info!("{}", text);
+        let message = Message::new("dnssec", &text)
+            .with_field("hostname", hostname)
+            .with_field("reason", reason);
+        if !events::alert(&message) {
+            log::warn!("Failed to send DNSSEC message");
+            return;
+        }
+    } else if previous == "bogus" {
+        let text = format!(
+            "DNSSEC validation passes again\nHostname: {}\nResult: {}",
+            display_hostname(hostname),
+            state
+        );
+        log::info!("{}", text);
+        let message = Message::new("dnssec", &text).with_field("hostname", hostname);
+        if !events::alert(&message) {
+            log::warn!("Failed to send DNSSEC message");
+            return;
+        }
+    } else {
+        log::info!("DNSSEC validation of {}: {}", hostname, state);
+    }
+    write_state(&dnssec_file, state);
+}
+
+/// Validates the answer for a hostname with a validating resolver.
+///
+/// The name servers of the resolver are queried one after the other until one answers, see `classify`.
+/// A "SERVFAIL" without an Extended DNS Error can also be an outage of the authoritative name servers,
+/// so the query is repeated with checking disabled: if the resolver then answers, the failure was the validation.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the hostname.
+/// * `resolver`: A `&str` that describes the resolver, see `dns::resolver_config`.
+///
+/// # Returns
+///
+/// * A `Result<Validation, String>` that holds the outcome.
+/// * If the resolver cannot be built or none of its name servers answers, it returns an `Err` with a message.
+fn validate(hostname: &str, resolver: &str) -> Result<Validation, String> {
+    let (config, _) = dns::resolver_config(resolver)
+        .map_err(|err| format!("Failed to build resolver: {}", err))?;
+    let hostname = idna::domain_to_ascii(hostname).unwrap_or_else(|_| hostname.to_string());
+
+    let mut error = format!("Resolver {} has no name server", resolver);
+    for server in config
+        .name_servers()
+        .iter()
+        .filter(|server| server.protocol == Protocol::Udp)
+    {
+        let result = dns::query_dnssec(server.socket_addr, &hostname, false).and_then(|response| {
+            match classify(&response) {
+                Some(validation) => Ok(validation),
+                None => {
+                    let unchecked = dns::query_dnssec(server.socket_addr, &hostname, true)?;
+                    match unchecked.response_code() {
+                        ResponseCode::NoError | ResponseCode::NXDomain => Ok(Validation::Bogus(
+                            "the resolver only answers with checking disabled".to_string(),
+                        )),
+                        code => Err(format!("{} answered {}", server.socket_addr, code)),
+                    }
+                }
+            }
+        });
+        match result {
+            Ok(validation) => return Ok(validation),
+            Err(err) => error = err,
+        }
+    }
+    Err(error)
+}
+
+/// Classifies the response of a validating resolver.
+///
+/// # Arguments
+///
+/// * `response`: A `&DnsMessage` that holds the response to a query with the "DNSSEC OK" bit.
+///
+/// # Returns
+///
+/// * An `Option<Validation>` that holds `Bogus` if the response carries a DNSSEC Extended DNS Error,
+///   `Secure` or `Insecure` for an answer depending on the "AD" bit,
+///   or `None` for any other error, which needs a query with checking disabled to tell.
+fn classify(response: &DnsMessage) -> Option<Validation> {
+    if let Some(reason) = extended_error(response) {
+        return Some(Validation::Bogus(reason));
+    }
+    match response.response_code() {
+        ResponseCode::NoError | ResponseCode::NXDomain if response.authentic_data() => {
+            Some(Validation::Secure)
+        }
+        ResponseCode::NoError | ResponseCode::NXDomain => Some(Validation::Insecure),
+        _ => None,
+    }
+}
+
+/// Retrieves the DNSSEC Extended DNS Error of a response.
+///
+/// # Arguments
+///
+/// * `response`: A `&DnsMessage` that holds the response.
+///
+/// # Returns
+///
+/// * An `Option<String>` that holds the name of the error and its extra text, if the response carries a DNSSEC error.
+fn extended_error(response: &DnsMessage) -> Option<String> {
+    let option = response
+        .extensions()
+        .as_ref()?
+        .options()
+        .get(EdnsCode::from(EXTENDED_DNS_ERROR))?;
+    let data = match option {
+        EdnsOption::Unknown(_, data) if data.len() >= 2 => data,
+        _ => return None,
+    };
+    let code = u16::from_be_bytes([data[0], data[1]]);
+    let (_, name) = DNSSEC_ERRORS.iter().find(|(known, _)| *known == code)?;
+    let text = String::from_utf8_lossy(&data[2..]);
+    let text = text.trim_end_matches('\0');
+    if text.is_empty() {
+        Some(name.to_string())
+    } else {
+        Some(format!("{} ({})", name, text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trust_dns_resolver::proto::op::Edns;
+
+    fn response(code: ResponseCode, authentic: bool, error: Option<&[u8]>) -> DnsMessage {
+        let mut response = DnsMessage::new();
+        response
+            .set_response_code(code)
+            .set_authentic_data(authentic);
+        if let Some(error) = error {
+            let mut edns = Edns::new();
+            edns.options_mut()
+                .insert(EdnsOption::Unknown(EXTENDED_DNS_ERROR, error.to_vec()));
+            response.set_edns(edns);
+        }
+        response
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(
+            classify(&response(ResponseCode::NoError, true, None)),
+            Some(Validation::Secure)
+        );
+        assert_eq!(
+            classify(&response(ResponseCode::NXDomain, false, None)),
+            Some(Validation::Insecure)
+        );
+        assert_eq!(
+            classify(&response(ResponseCode::ServFail, false, None)),
+            None
+        );
+        assert_eq!(
+            classify(&response(
+                ResponseCode::ServFail,
+                false,
+                Some(b"\x00\x07signature expired")
+            )),
+            Some(Validation::Bogus(
+                "Signature Expired (signature expired)".to_string()
+            ))
+        );
+        assert_eq!(
+            classify(&response(ResponseCode::ServFail, false, Some(b"\x00\x06"))),
+            Some(Validation::Bogus("DNSSEC Bogus".to_string()))
+        );
+
+        // Assert that other Extended DNS Errors are not validation failures
+        assert_eq!(
+            classify(&response(ResponseCode::ServFail, false, Some(b"\x00\x16"))),
+            None
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use crate::check::{Check, Context};
+use crate::config::SETTINGS;
+use crate::dependencies::{status_lines, DEPENDENCIES};
+use crate::redact::redact;
+use crate::{alarm, leader, notify, passive};
+use chrono::Local;
+use std::env;
+use std::time::{Duration, Instant};
+
+/// The scheduler state of the checker loop at the time of a dump.
+#[derive(Debug, Clone, Copy)]
+pub struct Schedule {
+    /// When the next verification runs.
+    pub next_check: Instant,
+    /// When the next heartbeat is logged, `None` if the heartbeat is disabled.
+    pub next_heartbeat: Option<Instant>,
+    /// How long ago the last verification finished, `None` if none finished yet.
+    pub last_check: Option<Duration>,
+}
+
+/// Writes a dump of the internal state, as requested by SIGUSR1.
+///
+/// This function builds the dump using the `state_dump` function.
+/// If the "DUMP_FILE" environment variable is set (e.g. "/tmp/dnschecker.dump"), the dump replaces the contents of that file;
+/// otherwise it is logged. Secrets are redacted in both cases.
+///
+/// # Arguments
+///
+/// * `context`: An `Option<&Context>` that holds the result of the last verification.
+/// * `checks`: A `&[Box<dyn Check>]` that holds the check pipeline.
+/// * `schedule`: A `Schedule` that holds the timing of the checker loop.
+pub fn write_dump(context: Option<&Context>, checks: &[Box<dyn Check>], schedule: Schedule) {
+    let dump = redact(&state_dump(context, checks, schedule));
+    match env::var("DUMP_FILE") {
+        Ok(path) => match std::fs::write(&path, &dump) {
+            Ok(_) => log::info!("State dumped to {}", path),
+            Err(e) => log::warn!("Failed to write state dump to {}: {}", path, e),
+        },
+        Err(_) => log::info!("State dump:\n{}", dump),
+    }
+}
+
+/// Builds a dump of the internal state.
+///
+/// The dump lists the settings taken from the environment, the result of the last verification,
+/// the alarm state (see `alarm::status`), the health of the dependencies (see `dependencies::status_lines`) and the timing of the checker loop.
+/// The values are not redacted yet, see `write_dump`.
+///
+/// # Arguments
+///
+/// * `context`: An `Option<&Context>` that holds the result of the last verification.
+/// * `checks`: A `&[Box<dyn Check>]` that holds the check pipeline.
+/// * `schedule`: A `Schedule` that holds the timing of the checker loop.
+///
+/// # Returns
+///
+/// * A `String` that holds the dump, one section per block.
+fn state_dump(context: Option<&Context>, checks: &[Box<dyn Check>], schedule: Schedule) -> String {
+    let mut lines = vec![format!("Time: {}", Local::now().to_rfc3339())];
+
+    lines.push("\n[config]".to_string());
+    for setting in SETTINGS {
+        if let Ok(value) = env::var(setting.name) {
+            lines.push(format!("{}={}", setting.name, value));
+        }
+    }
+    lines.push(format!("WAN IP sources: {}", passive::sources().join(", ")));
+
+    lines.push("\n[last result]".to_string());
+    match context {
+        Some(context) => {
+            lines.push(format!("Hostname: {}", context.hostname));
+            lines.push(format!("Severity: {}", notify::severity(&context.hostname)));
+            lines.push(format!("DNS IPs: {}", context.dns_ips.join(", ")));
+            if let Some(response) = &context.dns_response {
+                lines.push(format!("DNS response: {}", response.summary()));
+            }
+            let wan_ips: Vec<String> = context
+                .wan_ips
+                .iter()
+                .map(|(interface, ip)| format!("{} ({})", ip, interface))
+                .collect();
+            lines.push(format!(
+                "WAN IPs: {} (from {})",
+                wan_ips.join(", "),
+                context.wan_source
+            ));
+            lines.push(format!(
+                "Selected: {} ({})",
+                context.wan_ip, context.interface
+            ));
+            let in_sync = match context.in_sync {
+                Some(in_sync) => in_sync.to_string(),
+                None => "not compared".to_string(),
+            };
+            lines.push(format!("In sync: {}", in_sync));
+        }
+        None => lines.push("No verification finished yet".to_string()),
+    }
+
+    lines.push("\n[alarm]".to_string());
+    lines.extend(alarm::status());
+
+    lines.push("\n[dependencies]".to_string());
+    lines.extend(status_lines(&DEPENDENCIES.snapshot()));
+
+    lines.push("\n[scheduler]".to_string());
+    let names: Vec<&str> = checks.iter().map(|check| check.name()).collect();
+    lines.push(format!("Checks: {}", names.join(", ")));
+    if let Some(role) = leader::role() {
+        lines.push(format!("Role: {}", role));
+    }
+    let now = Instant::now();
+    lines.push(format!(
+        "Next check in: {}s",
+        schedule.next_check.saturating_duration_since(now).as_secs()
+    ));
+    match schedule.next_heartbeat {
+        Some(next) => lines.push(format!(
+            "Next heartbeat in: {}s",
+            next.saturating_duration_since(now).as_secs()
+        )),
+        None => lines.push("Heartbeat: disabled".to_string()),
+    }
+    match schedule.last_check {
+        Some(elapsed) => lines.push(format!("Last check: {}s ago", elapsed.as_secs())),
+        None => lines.push("Last check: never".to_string()),
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Named;
+
+    impl Check for Named {
+        fn name(&self) -> &'static str {
+            "resolve_dns"
+        }
+
+        fn run(&self, _context: &mut Context) {}
+    }
+
+    #[test]
+    fn test_state_dump() {
+        let context = Context {
+            dns_ips: vec!["1.1.1.1".to_string(), "2.2.2.2".to_string()],
+            wan_ips: vec![("igb3".to_string(), "1.1.1.1".to_string())],
+            wan_ip: "1.1.1.1".to_string(),
+            interface: "igb3".to_string(),
+            dns_response: Some(crate::dns::DnsResponse {
+                server: "8.8.8.8:53".parse().unwrap(),
+                response_code: "NOERROR".to_string(),
+                authoritative: false,
+                truncated: true,
+                recursion_available: true,
+                answers: vec![],
+            }),
+            ..Context::new("home.example.com")
+        };
+        let checks: Vec<Box<dyn Check>> = vec![Box::new(Named)];
+        let schedule = Schedule {
+            next_check: Instant::now() + Duration::from_secs(5),
+            next_heartbeat: None,
+            last_check: Some(Duration::from_secs(5)),
+        };
+
+        let result = state_dump(Some(&context), &checks, schedule);
+        assert!(result.contains("DNS IPs: 1.1.1.1, 2.2.2.2"));
+        assert!(
+            result.contains("DNS response: NOERROR from 8.8.8.8:53, flags: tc ra, answers: none")
+        );
+        assert!(result.contains("Selected: 1.1.1.1 (igb3)"));
+        assert!(result.contains("In sync: not compared"));
+        assert!(result.contains("Checks: resolve_dns"));
+        assert!(result.contains("Heartbeat: disabled"));
+        assert!(result.contains("Last check: 5s ago"));
+
+        let result = state_dump(None, &checks, schedule);
+        assert!(result.contains("No verification finished yet"));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::http;
+use serde::Deserialize;
+use std::env;
+use std::time::Duration;
+
+/// What is known about an IP address from a GeoIP service.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct IpInfo {
+    /// The autonomous system, e.g. "AS3320 Deutsche Telekom AG".
+    #[serde(default, rename = "as")]
+    pub asn: String,
+    /// The reverse DNS name of the address.
+    #[serde(default, rename = "reverse")]
+    pub rdns: String,
+    /// The city the address is located in.
+    #[serde(default)]
+    pub city: String,
+    /// The country the address is located in.
+    #[serde(default)]
+    pub country: String,
+    /// The latitude of the location, if known.
+    #[serde(default, rename = "lat")]
+    pub latitude: Option<f64>,
+    /// The longitude of the location, if known.
+    #[serde(default, rename = "lon")]
+    pub longitude: Option<f64>,
+}
+
+/// Looks up an IP address using the GeoIP service.
+///
+/// This function retrieves the URL of the service from the "GEOIP_API" environment variable,
+/// defaulting to "http://ip-api.com/json". Any service answering "GET <url>/<ip>" in the ip-api.com format works.
+///
+/// # Arguments
+///
+/// * `ip`: A `&str` that specifies the IP address.
+///
+/// # Returns
+///
+/// * An `Option<IpInfo>` that holds the details of the address, or `None` if the lookup fails.
+pub fn lookup(ip: &str) -> Option<IpInfo> {
+    let api = env::var("GEOIP_API").unwrap_or("http://ip-api.com/json".to_string());
+    match fetch_ip_info(&api, ip) {
+        Ok(info) => Some(info),
+        Err(e) => {
+            log::warn!("{}", e);
+            None
+        }
+    }
+}
+
+/// Retrieves the details of an IP address from an ip-api.com compatible service.
+///
+/// # Arguments
+///
+/// * `api`: A `&str` that specifies the URL of the service.
+/// * `ip`: A `&str` that specifies the IP address.
+///
+/// # Returns
+///
+/// * A `Result<IpInfo, String>` that holds the details, or an `Err` with a message if the request fails.
+fn fetch_ip_info(api: &str, ip: &str) -> Result<IpInfo, String> {
+    let client = http::client()?;
+    let response = client
+        .get(format!("{}/{}", api.trim_end_matches('/'), ip))
+        .query(&[("fields", "status,message,as,reverse,city,country,lat,lon")])
+        .timeout(Duration::from_secs(10))
+        .send()
+        .map_err(|e| format!("Failed to look up {}: {}", ip, e))?;
+    if !response.status().is_success() {
+        return Err(format!("GeoIP service returned {}", response.status()));
+    }
+    let json: serde_json::Value = response
+        .json()
+        .map_err(|e| format!("Invalid answer from GeoIP service: {}", e))?;
+    if json["status"].as_str() == Some("fail") {
+        return Err(format!(
+            "GeoIP lookup of {} failed: {}",
+            ip,
+            json["message"].as_str().unwrap_or("no message")
+        ));
+    }
+    serde_json::from_value(json).map_err(|e| format!("Invalid answer from GeoIP service: {}", e))
+}
+
+/// Formats the details of an IP address as a block of text for a notification.
+///
+/// # Arguments
+///
+/// * `info`: A `&IpInfo` that holds the details of the address.
+///
+/// # Returns
+///
+/// * A `String` that holds one line per known detail, empty if nothing is known.
+pub fn context_block(info: &IpInfo) -> String {
+    let location = [info.city.as_str(), info.country.as_str()]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join(", ");
+    [
+        ("ASN", info.asn.as_str()),
+        ("rDNS", info.rdns.as_str()),
+        ("Location", location.as_str()),
+    ]
+    .into_iter()
+    .filter(|(_, value)| !value.is_empty())
+    .map(|(label, value)| format!("{}: {}", label, value))
+    .collect::<Vec<String>>()
+    .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    #[test]
+    fn test_fetch_ip_info() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/json/203.0.113.7");
+            then.status(200).body(
+                r#"{"status": "success", "as": "AS64500 Example Net", "reverse": "host.example.net", "city": "Berlin", "country": "Germany", "lat": 52.52, "lon": 13.4}"#,
+            );
+        });
+        let fail = server.mock(|when, then| {
+            when.method(GET).path("/json/10.0.0.1");
+            then.status(200)
+                .body(r#"{"status": "fail", "message": "private range"}"#);
+        });
+
+        let info = fetch_ip_info(&server.url("/json"), "203.0.113.7").unwrap();
+        mock.assert();
+        assert_eq!(info.asn, "AS64500 Example Net");
+        assert_eq!(info.latitude, Some(52.52));
+        assert_eq!(
+            context_block(&info),
+            "ASN: AS64500 Example Net\nrDNS: host.example.net\nLocation: Berlin, Germany"
+        );
+
+        // Assert that a failed lookup is an error
+        assert!(fetch_ip_info(&server.url("/json"), "10.0.0.1")
+            .unwrap_err()
+            .contains("private range"));
+        fail.assert();
+    }
+}
@@ -0,0 +1,253 @@
+use crate::check::Context;
+use crate::dependencies::DependencySubscriber;
+use crate::history::HistorySubscriber;
+use crate::hook::HookSubscriber;
+use crate::icinga::IcingaSubscriber;
+use crate::metrics::MetricsSubscriber;
+use crate::notify::{Message, NotifierSubscriber};
+use crate::replay::ReplaySubscriber;
+use crate::zabbix::ZabbixSubscriber;
+use std::sync::{OnceLock, RwLock};
+
+/// Something that happened in the checker, published to every subscriber.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event<'a> {
+    /// A verification finished.
+    Checked(&'a Context),
+    /// An alert was raised, e.g. a "mismatch", "recovery", "wan_change" or "failover" message.
+    Alert(&'a Message),
+    /// A check failed to retrieve its input, e.g. no resolver answered.
+    CheckError {
+        /// The name of the check, as used in "CHECKS".
+        check: &'a str,
+        /// The error.
+        error: &'a str,
+    },
+    /// The periodic status of the checker, see "HEARTBEAT_INTERVAL".
+    Heartbeat {
+        /// The monitored hostname.
+        hostname: &'a str,
+        /// The IP address from the latest DNS lookup, empty if unknown.
+        dns_ip: &'a str,
+        /// The latest WAN IP address, empty if unknown.
+        wan_ip: &'a str,
+    },
+}
+
+impl Event<'_> {
+    /// Returns the kind of the event: "checked", "check_error", "heartbeat", or the event of the alert, e.g. "mismatch".
+    pub fn kind(&self) -> &str {
+        match self {
+            Event::Checked(_) => "checked",
+            Event::Alert(message) => &message.event,
+            Event::CheckError { .. } => "check_error",
+            Event::Heartbeat { .. } => "heartbeat",
+        }
+    }
+
+    /// Describes the event as a message, for subscribers that handle every kind alike, e.g. hooks.
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<Message>` that holds the message, or `None` for "checked" events, which carry a whole verification.
+    pub fn to_message(&self) -> Option<Message> {
+        match self {
+            Event::Checked(_) => None,
+            Event::Alert(message) => Some((*message).clone()),
+            Event::CheckError { check, error } => Some(
+                Message::new("check_error", &format!("Check {} failed: {}", check, error))
+                    .with_field("check", check)
+                    .with_field("error", error),
+            ),
+            Event::Heartbeat {
+                hostname,
+                dns_ip,
+                wan_ip,
+            } => Some(
+                Message::new("heartbeat", &format!("Heartbeat for {}", hostname))
+                    .with_field("hostname", hostname)
+                    .with_field("dns_ip", dns_ip)
+                    .with_field("wan_ip", wan_ip),
+            ),
+        }
+    }
+}
+
+/// A consumer of events, e.g. the notification channels, the metrics or the history.
+pub trait Subscriber: Send + Sync {
+    /// The name of the subscriber, used in log messages.
+    fn name(&self) -> &str;
+
+    /// Returns whether the subscriber handles events of this kind. By default it handles all of them.
+    fn handles(&self, _event: &Event) -> bool {
+        true
+    }
+
+    /// Handles an event and returns whether it succeeded.
+    fn receive(&self, event: &Event) -> bool;
+}
+
+/// Retrieves the process-wide list of subscribers.
+///
+/// On first use, the list is filled with the subscribers every checker has:
+///
+/// * The notification channels, which send alerts (see `notify::NotifierSubscriber`).
+/// * The hooks, which run the "ON_<EVENT>" commands (see `hook::HookSubscriber`).
+/// * The metrics, which count verifications and check errors (see `metrics::MetricsSubscriber`).
+/// * The history, which records verifications (see `history::HistorySubscriber`).
+/// * The health of the dependencies, which is stored after every verification (see `dependencies::DependencySubscriber`).
+/// * The recording of verifications for `replay` (see `replay::ReplaySubscriber`).
+/// * The monitoring systems, which receive every verification (see `zabbix::ZabbixSubscriber` and `icinga::IcingaSubscriber`).
+fn subscribers() -> &'static RwLock<Vec<Box<dyn Subscriber>>> {
+    static SUBSCRIBERS: OnceLock<RwLock<Vec<Box<dyn Subscriber>>>> = OnceLock::new();
+    SUBSCRIBERS.get_or_init(|| {
+        RwLock::new(vec![
+            Box::new(NotifierSubscriber),
+            Box::new(HookSubscriber::from_env()),
+            Box::new(MetricsSubscriber),
+            Box::new(HistorySubscriber),
+            Box::new(DependencySubscriber),
+            Box::new(ReplaySubscriber),
+            Box::new(ZabbixSubscriber),
+            Box::new(IcingaSubscriber),
+        ])
+    })
+}
+
+/// Registers a subscriber, which receives every event published from then on.
+///
+/// # Arguments
+///
+/// * `subscriber`: A `Box<dyn Subscriber>` that handles the events.
+pub fn subscribe(subscriber: Box<dyn Subscriber>) {
+    log::debug!("Subscribing {} to events", subscriber.name());
+    subscribers()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(subscriber);
+}
+
+/// Publishes an event to every subscriber that handles it.
+///
+/// A failing subscriber is logged and does not prevent delivery to the other subscribers.
+///
+/// # Arguments
+///
+/// * `event`: An `&Event` that holds the event.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether at least one subscriber handled the event successfully,
+///   e.g. whether an alert reached a channel or a hook.
+pub fn publish(event: &Event) -> bool {
+    dispatch(
+        &subscribers().read().unwrap_or_else(|e| e.into_inner()),
+        event,
+    )
+}
+
+/// Publishes an alert, see `publish`.
+///
+/// # Arguments
+///
+/// * `message`: A `&Message` that holds the alert.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether the alert reached at least one channel or hook, or was suppressed.
+pub fn alert(message: &Message) -> bool {
+    publish(&Event::Alert(message))
+}
+
+/// Delivers an event to the given subscribers.
+///
+/// # Arguments
+///
+/// * `subscribers`: A `&[Box<dyn Subscriber>]` that holds the subscribers; only those that handle the event are used.
+/// * `event`: An `&Event` that holds the event.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether at least one subscriber handled the event successfully.
+fn dispatch(subscribers: &[Box<dyn Subscriber>], event: &Event) -> bool {
+    let mut handled = false;
+    for subscriber in subscribers.iter().filter(|s| s.handles(event)) {
+        if subscriber.receive(event) {
+            log::trace!("{} handled {} event", subscriber.name(), event.kind());
+            handled = true;
+        } else {
+            log::warn!(
+                "{} failed to handle {} event",
+                subscriber.name(),
+                event.kind()
+            );
+        }
+    }
+    handled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Recorder {
+        kinds: &'static [&'static str],
+    }
+
+    impl Subscriber for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn handles(&self, event: &Event) -> bool {
+            self.kinds.contains(&event.kind())
+        }
+
+        fn receive(&self, _event: &Event) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let subscribers: Vec<Box<dyn Subscriber>> = vec![
+            Box::new(Recorder {
+                kinds: &["mismatch", "recovery"],
+            }),
+            Box::new(Recorder {
+                kinds: &["checked"],
+            }),
+        ];
+        let message = Message::new("mismatch", "text");
+        let context = Context::new("home.example.com");
+
+        assert!(dispatch(&subscribers, &Event::Alert(&message)));
+        assert!(dispatch(&subscribers, &Event::Checked(&context)));
+
+        // Assert that an event no subscriber handles is not handled
+        assert!(!dispatch(
+            &subscribers,
+            &Event::CheckError {
+                check: "resolve_dns",
+                error: "timeout"
+            }
+        ));
+    }
+
+    #[test]
+    fn test_to_message() {
+        let message = Event::CheckError {
+            check: "fetch_wan_ip",
+            error: "Failed to get WAN IP address",
+        }
+        .to_message()
+        .unwrap();
+        assert_eq!(message.event, "check_error");
+        assert!(message
+            .fields
+            .contains(&("check".to_string(), "fetch_wan_ip".to_string())));
+
+        let context = Context::new("home.example.com");
+        assert_eq!(Event::Checked(&context).to_message(), None);
+    }
+}
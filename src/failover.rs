@@ -0,0 +1,284 @@
+use crate::api;
+use crate::events;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::vars::{display_hostname, split_list};
+use std::env;
+use std::net::IpAddr;
+
+/// Detects failover between WAN uplinks and reports transitions.
+///
+/// This function takes the WAN IP addresses of the configured interfaces,
+/// the hostname and the IP address from DNS as arguments.
+/// If fewer than two interfaces are configured, failover tracking is disabled and the function returns immediately.
+///
+/// The active uplink is the first configured interface that has an address.
+/// It is compared with the uplink stored in the uplink file ("UPLINK_FILE", defaulting to "active_uplink" in the state directory).
+/// If the active uplink changed, it sends an informational message that also states
+/// whether the DNS record follows the new uplink, and writes the new uplink to the uplink file.
+///
+/// # Arguments
+/// * `wan_ips`: A `&[(String, String)]` that holds the interface name and IPv4 address of each interface.
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `dns_ip`: A `&str` that specifies the IP address from DNS.
+pub fn check_uplink(wan_ips: &[(String, String)], hostname: &str, dns_ip: &str) {
+    let interfaces = env::var("INTERFACE").unwrap_or_default();
+    if split_list(&interfaces).len() < 2 {
+        return;
+    }
+    let (interface, wan_ip) = match wan_ips.first() {
+        Some(active) => active,
+        None => {
+            log::warn!("No WAN uplink has an address");
+            return;
+        }
+    };
+    log::debug!("Active uplink is {} ({})", interface, wan_ip);
+
+    let uplink_file = state_path("UPLINK_FILE", "active_uplink");
+    let previous = read_state(&uplink_file);
+    if previous == *interface {
+        return;
+    }
+
+    if previous.is_empty() {
+        log::info!("Tracking active uplink {}", interface);
+    } else {
+        log::info!("WAN failover from {} to {}", previous, interface);
+        let text = failover_message(&previous, interface, wan_ip, hostname, dns_ip);
+        let message = Message::new("failover", &text)
+            .with_field("previous_interface", &previous)
+            .with_field("interface", interface)
+            .with_field("wan_ip", wan_ip);
+        if !events::alert(&message) {
+            log::warn!("Failed to send failover message");
+            // Keep the old uplink so the transition is reported again on the next check
+            return;
+        }
+    }
+    write_state(&uplink_file, interface);
+}
+
+/// Detects changes of the WAN IP address and reports them.
+///
+/// This function takes the selected interface and its WAN IP address as arguments.
+/// If the WAN IP address is empty, the function returns immediately.
+///
+/// The WAN IP address is compared with the address stored in the WAN IP file ("WAN_IP_FILE", defaulting to "wan_ip" in the state directory).
+/// If it changed, it sends a "wan_change" message, e.g. to run hooks that restart a VPN,
+/// and writes the new address to the WAN IP file. The first address seen is only stored.
+///
+/// # Arguments
+/// * `interface`: A `&str` that specifies the selected interface.
+/// * `wan_ip`: A `&str` that specifies the WAN IP address of the interface.
+pub fn check_wan_ip(interface: &str, wan_ip: &str) {
+    if wan_ip.is_empty() {
+        return;
+    }
+    let wan_ip_file = state_path("WAN_IP_FILE", "wan_ip");
+    let previous = read_state(&wan_ip_file);
+    if previous == wan_ip {
+        return;
+    }
+
+    if previous.is_empty() {
+        log::info!("Tracking WAN IP address {}", wan_ip);
+    } else {
+        log::info!("WAN IP address changed from {} to {}", previous, wan_ip);
+        let text = format!(
+            "WAN IP address changed\nPrevious: {}\nCurrent: {} ({})",
+            previous, wan_ip, interface
+        );
+        let message = Message::new("wan_change", &text)
+            .with_field("previous_ip", &previous)
+            .with_field("wan_ip", wan_ip)
+            .with_field("interface", interface);
+        if !events::alert(&api::with_router_context(message)) {
+            log::warn!("Failed to send WAN change message");
+            // Keep the old address so the change is reported again on the next check
+            return;
+        }
+    }
+    write_state(&wan_ip_file, wan_ip);
+}
+
+/// Classifies the WAN IP address reported by the router.
+///
+/// When the uplink is down, routers often still report the interface, but with an address that cannot be reached
+/// from the Internet, e.g. "0.0.0.0" or a private address handed out by the modem.
+/// A DNS record can never match such an address, so a mismatch alarm would be misleading.
+///
+/// # Arguments
+///
+/// * `wan_ip`: A `&str` that specifies the WAN IP address.
+///
+/// # Returns
+///
+/// * An `Option<&'static str>` that holds the reason the uplink is considered down, or `None` if the address is plausible.
+pub fn wan_down_reason(wan_ip: &str) -> Option<&'static str> {
+    let ip = match wan_ip.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => ip,
+        Ok(IpAddr::V6(ip)) => {
+            let first = ip.segments()[0];
+            return if ip.is_unspecified() {
+                Some("the router reports no address")
+            } else if ip.is_loopback() || first & 0xffc0 == 0xfe80 {
+                Some("the router reports a link-local address")
+            } else if first & 0xfe00 == 0xfc00 {
+                Some("the router reports a private address")
+            } else {
+                None
+            };
+        }
+        Err(_) => return Some("the router reports an invalid address"),
+    };
+    let [first, second, ..] = ip.octets();
+    if ip.is_unspecified() || ip.is_broadcast() {
+        Some("the router reports no address")
+    } else if ip.is_loopback() || ip.is_link_local() {
+        Some("the router reports a link-local address")
+    } else if ip.is_private() || (first == 100 && second & 0xc0 == 64) {
+        Some("the router reports a private address")
+    } else {
+        None
+    }
+}
+
+/// Reports when the WAN uplink goes down or comes back.
+///
+/// This function takes the hostname, the selected interface, its WAN IP address and the reason the uplink is down
+/// (see `wan_down_reason`) as arguments. The state is compared with the state stored in the WAN down file
+/// ("WAN_DOWN_FILE", defaulting to "wan_down" in the state directory), and a "wan_down" message is sent when the uplink goes down
+/// and when it is up again.
+///
+/// # Arguments
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `interface`: A `&str` that specifies the selected interface.
+/// * `wan_ip`: A `&str` that specifies the WAN IP address of the interface.
+/// * `reason`: An `Option<&str>` that holds the reason the uplink is down, or `None` if it is up.
+pub fn check_wan_down(hostname: &str, interface: &str, wan_ip: &str, reason: Option<&str>) {
+    let wan_down_file = state_path("WAN_DOWN_FILE", "wan_down");
+    let state = if reason.is_some() { "down" } else { "" };
+    let previous = read_state(&wan_down_file);
+    if previous == state {
+        return;
+    }
+    let text = wan_down_message(hostname, interface, wan_ip, reason);
+    log::info!("{}", text);
+    let message = Message::new("wan_down", &text)
+        .with_field("hostname", hostname)
+        .with_field("interface", interface)
+        .with_field("wan_ip", wan_ip)
+        .with_field("state", if reason.is_some() { "down" } else { "up" });
+    if !events::alert(&message) {
+        log::warn!("Failed to send WAN down message");
+        return;
+    }
+    write_state(&wan_down_file, state);
+}
+
+/// Builds the text of the WAN down message.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `interface`: A `&str` that specifies the selected interface.
+/// * `wan_ip`: A `&str` that specifies the WAN IP address of the interface.
+/// * `reason`: An `Option<&str>` that holds the reason the uplink is down, or `None` if it is up again.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message.
+fn wan_down_message(hostname: &str, interface: &str, wan_ip: &str, reason: Option<&str>) -> String {
+    match reason {
+        Some(reason) => format!(
+            "WAN uplink is down!
+Interface: {}
+Router IP: {}
+Reason: {}
+Mismatch alarms for {} are suppressed until it is back",
+            interface,
+            wan_ip,
+            reason,
+            display_hostname(hostname)
+        ),
+        None => format!(
+            "WAN uplink is up again
+Interface: {}
+Router IP: {}",
+            interface, wan_ip
+        ),
+    }
+}
+
+/// Builds the text of the failover message.
+///
+/// # Arguments
+///
+/// * `previous`: A `&str` that specifies the previously active uplink.
+/// * `interface`: A `&str` that specifies the active uplink.
+/// * `wan_ip`: A `&str` that specifies the address of the active uplink.
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `dns_ip`: A `&str` that specifies the IP address from DNS.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message.
+fn failover_message(
+    previous: &str,
+    interface: &str,
+    wan_ip: &str,
+    hostname: &str,
+    dns_ip: &str,
+) -> String {
+    let hostname = display_hostname(hostname);
+    let dns_state = if dns_ip.is_empty() {
+        format!("{} could not be resolved", hostname)
+    } else if dns_ip == wan_ip {
+        format!("{} already points at the new uplink", hostname)
+    } else {
+        format!("{} still points at {}", hostname, dns_ip)
+    };
+    format!(
+        "WAN failover detected!\nActive uplink: {} ({})\nPrevious uplink: {}\n{}",
+        interface, wan_ip, previous, dns_state
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wan_down_reason() {
+        assert_eq!(wan_down_reason("1.1.1.1"), None);
+        assert_eq!(wan_down_reason("100.128.0.1"), None);
+        assert!(wan_down_reason("").is_some());
+        assert!(wan_down_reason("0.0.0.0").is_some());
+        assert!(wan_down_reason("169.254.1.1").is_some());
+        assert!(wan_down_reason("192.168.1.1").is_some());
+        // Assert that carrier-grade NAT addresses are not reachable
+        assert!(wan_down_reason("100.64.0.1").is_some());
+        assert_eq!(wan_down_reason("2001:db8::1"), None);
+        assert!(wan_down_reason("fe80::1").is_some());
+
+        let result = wan_down_message("home.example.com", "igb3", "0.0.0.0", Some("no address"));
+        assert!(result.starts_with("WAN uplink is down!"));
+        assert!(result.contains("home.example.com are suppressed"));
+        assert!(wan_down_message("home.example.com", "igb3", "1.1.1.1", None).contains("up again"));
+    }
+
+    #[test]
+    fn test_failover_message() {
+        let result = failover_message("igb3", "igb4", "2.2.2.2", "home.example.com", "1.1.1.1");
+        assert!(result.contains("Active uplink: igb4 (2.2.2.2)"));
+        assert!(result.contains("home.example.com still points at 1.1.1.1"));
+
+        let result = failover_message("igb3", "igb4", "2.2.2.2", "home.example.com", "2.2.2.2");
+        assert!(result.contains("already points at the new uplink"));
+
+        // Assert that internationalized hostnames are shown in their Unicode form
+        let result = failover_message("igb3", "igb4", "2.2.2.2", "xn--mnchen-3ya.example", "");
+        assert!(result.contains("münchen.example could not be resolved"));
+    }
+}
@@ -0,0 +1,188 @@
+use crate::http;
+use crate::notify::{Message, Notifier};
+use base64::Engine;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::env;
+use std::time::Duration;
+
+/// Delivers notifications to a Feishu or Lark group through a custom bot webhook.
+pub struct FeishuNotifier {
+    url: String,
+    secret: Option<String>,
+}
+
+impl FeishuNotifier {
+    /// Creates a Feishu notifier from the environment.
+    ///
+    /// The notifier is enabled by setting "FEISHU_WEBHOOK_URL" to the webhook of a custom bot, e.g.
+    /// "https://open.feishu.cn/open-apis/bot/v2/hook/<token>" (or "https://open.larksuite.com/…" for Lark).
+    /// If the bot has signature verification enabled, "FEISHU_SECRET" holds its signing secret (see `sign`).
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<FeishuNotifier>` that holds the notifier, or `None` if "FEISHU_WEBHOOK_URL" is not set.
+    pub fn from_env() -> Option<FeishuNotifier> {
+        let url = env::var("FEISHU_WEBHOOK_URL")
+            .ok()
+            .filter(|url| !url.is_empty())?;
+        Some(FeishuNotifier {
+            url,
+            secret: env::var("FEISHU_SECRET")
+                .ok()
+                .filter(|secret| !secret.is_empty()),
+        })
+    }
+}
+
+impl Notifier for FeishuNotifier {
+    fn name(&self) -> &str {
+        "feishu"
+    }
+
+    /// Posts the message to the webhook, see `body`.
+    ///
+    /// Feishu answers errors such as a wrong signature with status 200 and a non-zero "code",
+    /// so the message only counts as delivered if the code is 0.
+    fn send(&self, message: &Message) -> bool {
+        let client = match http::client() {
+            Ok(client) => client,
+            Err(e) => {
+                log::warn!("{}", e);
+                return false;
+            }
+        };
+        let response = client
+            .post(&self.url)
+            .json(&body(
+                &message.text,
+                self.secret.as_deref(),
+                Utc::now().timestamp(),
+            ))
+            .timeout(Duration::from_secs(10))
+            .send();
+        match response {
+            Ok(response) if response.status().is_success() => {
+                let answer: Value = response.json().unwrap_or_default();
+                match answer["code"].as_i64() {
+                    Some(0) => true,
+                    _ => {
+                        log::warn!(
+                            "Feishu returned code {}: {}",
+                            answer["code"],
+                            answer["msg"].as_str().unwrap_or("no message")
+                        );
+                        false
+                    }
+                }
+            }
+            Ok(response) => {
+                log::warn!("Feishu returned {}", response.status());
+                false
+            }
+            Err(e) => {
+                log::warn!("Failed to make HTTPS request: {:?}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Builds the body of a Feishu text message.
+///
+/// # Arguments
+///
+/// * `text`: A `&str` that specifies the text of the message.
+/// * `secret`: An `Option<&str>` that holds the signing secret of the bot, if it verifies signatures.
+/// * `timestamp`: An `i64` that specifies the current time in seconds since the epoch.
+///
+/// # Returns
+///
+/// * A `Value` that holds the JSON body, with "timestamp" and "sign" if a secret is given.
+fn body(text: &str, secret: Option<&str>, timestamp: i64) -> Value {
+    let mut body = json!({
+        "msg_type": "text",
+        "content": {"text": text},
+    });
+    if let Some(secret) = secret {
+        body["timestamp"] = Value::from(timestamp.to_string());
+        body["sign"] = Value::from(sign(secret, timestamp));
+    }
+    body
+}
+
+/// Signs a Feishu request.
+///
+/// Feishu uses "<timestamp>\n<secret>" as the HMAC-SHA256 key of an empty message.
+///
+/// # Arguments
+///
+/// * `secret`: A `&str` that specifies the signing secret.
+/// * `timestamp`: An `i64` that specifies the time of the request in seconds since the epoch.
+///
+/// # Returns
+///
+/// * A `String` that holds the base64 encoded signature.
+fn sign(secret: &str, timestamp: i64) -> String {
+    let mac = Hmac::<Sha256>::new_from_slice(format!("{}\n{}", timestamp, secret).as_bytes())
+        .expect("HMAC accepts keys of any length");
+    base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+
+    #[test]
+    fn test_body() {
+        assert_eq!(
+            body("IP address mismatch!", Some("secret"), 1700000000),
+            json!({
+                "msg_type": "text",
+                "content": {"text": "IP address mismatch!"},
+                "timestamp": "1700000000",
+                "sign": "fiWS2+gh28DOydAv7hzONH/mDn9+b1Y4Y5ivXWXy8vA=",
+            })
+        );
+        assert_eq!(
+            body("IP address mismatch!", None, 1700000000).get("sign"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_send() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method("POST")
+                .path("/open-apis/bot/v2/hook/token")
+                .json_body_partial(
+                    r#"{"msg_type": "text", "content": {"text": "IP address mismatch!"}}"#,
+                );
+            then.status(200)
+                .body(r#"{"code": 0, "msg": "success", "data": {}}"#);
+        });
+        let notifier = FeishuNotifier {
+            url: server.url("/open-apis/bot/v2/hook/token"),
+            secret: None,
+        };
+        assert!(notifier.send(&Message::new("mismatch", "IP address mismatch!")));
+        mock.assert();
+
+        // Assert that an error code fails the delivery
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method("POST");
+            then.status(200)
+                .body(r#"{"code": 19021, "msg": "sign match fail or timestamp is not within one hour from current time"}"#);
+        });
+        let notifier = FeishuNotifier {
+            url: server.url("/open-apis/bot/v2/hook/token"),
+            secret: Some("wrong".to_string()),
+        };
+        assert!(!notifier.send(&Message::new("mismatch", "IP address mismatch!")));
+    }
+}
@@ -0,0 +1,76 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// The health of the checker, as reported by the liveness and readiness endpoints.
+#[derive(Debug)]
+pub struct Health {
+    /// When the checker started.
+    started: Instant,
+    /// When the main loop last ran, in milliseconds since `started`.
+    last_tick: AtomicU64,
+    /// Whether the checker is ready, i.e. it completed a verification and is not shutting down.
+    ready: AtomicBool,
+}
+
+impl Default for Health {
+    fn default() -> Health {
+        Health::new()
+    }
+}
+
+impl Health {
+    /// Creates the health of a checker that just started and is not ready yet.
+    pub fn new() -> Health {
+        Health {
+            started: Instant::now(),
+            last_tick: AtomicU64::new(0),
+            ready: AtomicBool::new(false),
+        }
+    }
+
+    /// Records that the main loop is running.
+    pub fn tick(&self) {
+        let elapsed = self.started.elapsed().as_millis() as u64;
+        self.last_tick.store(elapsed, Ordering::SeqCst);
+    }
+
+    /// Marks the checker as ready or not ready.
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::SeqCst);
+    }
+
+    /// Returns whether the checker is ready.
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether the main loop ran within the given time, i.e. it is not stuck.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_age`: A `Duration` that specifies how long ago the main loop may have last run.
+    pub fn is_alive(&self, max_age: Duration) -> bool {
+        let last_tick = Duration::from_millis(self.last_tick.load(Ordering::SeqCst));
+        self.started.elapsed().saturating_sub(last_tick) <= max_age
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health() {
+        let health = Health::new();
+        health.tick();
+
+        assert!(!health.is_ready());
+        assert!(health.is_alive(Duration::from_secs(1)));
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!health.is_alive(Duration::from_millis(10)));
+
+        health.set_ready(true);
+        assert!(health.is_ready());
+    }
+}
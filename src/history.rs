@@ -0,0 +1,533 @@
+use crate::check;
+use crate::events::{Event, Subscriber};
+use crate::state::{append_state, read_state, state_path, write_state};
+use chrono::{DateTime, Duration as ChronoDuration, FixedOffset, Local};
+use serde_json::Value;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The result of a single comparison between DNS and the WAN IP address.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckRecord {
+    pub timestamp: DateTime<FixedOffset>,
+    pub hostname: String,
+    pub dns_ip: String,
+    pub wan_ip: String,
+    pub in_sync: bool,
+    /// How long each check of the verification took in milliseconds, empty for records written before it was tracked.
+    pub latencies: BTreeMap<String, u64>,
+}
+
+impl CheckRecord {
+    /// Converts the record to a JSON object.
+    pub fn to_json(&self) -> Value {
+        serde_json::json!({
+            "timestamp": self.timestamp.to_rfc3339(),
+            "hostname": self.hostname,
+            "dns_ip": self.dns_ip,
+            "wan_ip": self.wan_ip,
+            "in_sync": self.in_sync,
+            "latencies_ms": self.latencies,
+        })
+    }
+
+    /// Creates the record of a verification, at the current time.
+    ///
+    /// If the addresses were compared, the result of the comparison is recorded;
+    /// otherwise they are in sync if they are equal.
+    /// A verification missing either address, e.g. because the lookup failed with NXDOMAIN or SERVFAIL
+    /// or the WAN IP address could not be retrieved, is recorded as out of sync with the empty address,
+    /// so the time until the next verification does not count as in sync (see `in_sync_percentage`).
+    ///
+    /// # Returns
+    ///
+    /// * A `CheckRecord` that holds the record.
+    pub fn from_context(context: &check::Context) -> CheckRecord {
+        let failed = context.dns_ip.is_empty() || context.wan_ip.is_empty();
+        CheckRecord {
+            timestamp: Local::now().fixed_offset(),
+            hostname: context.hostname.clone(),
+            dns_ip: context.dns_ip.clone(),
+            wan_ip: context.wan_ip.clone(),
+            in_sync: !failed && context.in_sync.unwrap_or(context.dns_ip == context.wan_ip),
+            latencies: context
+                .durations
+                .iter()
+                .map(|(check, duration)| (check.clone(), duration.as_millis() as u64))
+                .collect(),
+        }
+    }
+
+    /// Parses a record from a line of the history file.
+    ///
+    /// Returns `None` if the line is not a valid record.
+    pub fn from_line(line: &str) -> Option<CheckRecord> {
+        let json: Value = serde_json::from_str(line).ok()?;
+        let field = |name: &str| json.get(name).and_then(Value::as_str).map(String::from);
+        Some(CheckRecord {
+            timestamp: DateTime::parse_from_rfc3339(&field("timestamp")?).ok()?,
+            hostname: field("hostname")?,
+            dns_ip: field("dns_ip")?,
+            wan_ip: field("wan_ip")?,
+            in_sync: json.get("in_sync")?.as_bool()?,
+            latencies: json
+                .get("latencies_ms")
+                .and_then(|latencies| serde_json::from_value(latencies.clone()).ok())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Records the verifications published on the event bus (see `events::publish`) in the history file,
+/// if "history" is one of the configured checks (see `check::enabled`).
+///
+/// Verifications missing either address are recorded as out of sync, see `CheckRecord::from_context`.
+pub struct HistorySubscriber;
+
+impl Subscriber for HistorySubscriber {
+    fn name(&self) -> &str {
+        "history"
+    }
+
+    fn handles(&self, event: &Event) -> bool {
+        matches!(event, Event::Checked(_)) && check::enabled("history")
+    }
+
+    fn receive(&self, event: &Event) -> bool {
+        if let Event::Checked(context) = event {
+            let record = CheckRecord::from_context(context);
+            // Load the cache before appending, so the record is not counted twice
+            cache_uptime(&record);
+            append_state(&history_file(), &record.to_json().to_string());
+        }
+        true
+    }
+}
+
+/// Retrieves the path of the history file.
+///
+/// This function returns the value of the "HISTORY_FILE" environment variable,
+/// defaulting to "dnschecker_history.jsonl" in the state directory.
+pub fn history_file() -> String {
+    state_path("HISTORY_FILE", "dnschecker_history.jsonl")
+}
+
+/// Loads the records from the history file.
+///
+/// This function takes the path of the history file as an argument.
+/// Lines that cannot be parsed are skipped.
+/// If the file does not exist, it returns an empty `Vec`.
+///
+/// # Arguments
+///
+/// * `path`: A `&str` that specifies the path of the history file.
+///
+/// # Returns
+///
+/// * A `Vec<CheckRecord>` that holds the records in the order they were written.
+pub fn load_history(path: &str) -> Vec<CheckRecord> {
+    read_state(path)
+        .lines()
+        .filter_map(CheckRecord::from_line)
+        .collect()
+}
+
+/// Removes records older than the retention period from the history file.
+///
+/// This function takes the path of the history file and the retention period in days as arguments.
+/// It rewrites the file with only the records that are newer than the retention period.
+///
+/// # Arguments
+///
+/// * `path`: A `&str` that specifies the path of the history file.
+/// * `days`: An `i64` that specifies the retention period in days.
+pub fn prune_history(path: &str, days: i64) {
+    let records = load_history(path);
+    let cutoff = Local::now() - ChronoDuration::try_days(days).unwrap_or_default();
+    let kept: Vec<String> = records
+        .iter()
+        .filter(|record| record.timestamp > cutoff)
+        .map(|record| record.to_json().to_string())
+        .collect();
+    if kept.len() == records.len() {
+        return;
+    }
+    let contents: String = kept.iter().map(|line| format!("{}\n", line)).collect();
+    if write_state(path, &contents) {
+        log::debug!("Pruned {} history records", records.len() - kept.len());
+    }
+}
+
+/// The longest time a check counts for in `in_sync_percentage`: twice the interval between checks.
+const MAX_RECORD_WEIGHT: Duration = Duration::from_secs(2 * check::CHECK_INTERVAL.as_secs());
+
+/// Calculates the percentage of time DNS was in sync since a point in time.
+///
+/// Each check counts for the time until the next check, the latest one until now,
+/// so the percentage does not depend on how often was checked, e.g. while checks were triggered more often.
+/// A check counts for at most `MAX_RECORD_WEIGHT`, so the time the checker was not running is not counted at all.
+///
+/// # Arguments
+///
+/// * `records`: A `&[CheckRecord]` that holds the history.
+/// * `since`: A `DateTime<Local>` that specifies the start of the period.
+///
+/// # Returns
+///
+/// * An `Option<f64>` that holds the percentage, or `None` if there are no checks in the period.
+pub fn in_sync_percentage(records: &[CheckRecord], since: DateTime<Local>) -> Option<f64> {
+    weighted_in_sync(&samples(records), since)
+}
+
+/// The time of a check and whether DNS was in sync, as weighted by `in_sync_percentage`.
+type Sample = (DateTime<FixedOffset>, bool);
+
+/// Returns the samples of the records, in chronological order.
+fn samples(records: &[CheckRecord]) -> Vec<Sample> {
+    let mut samples: Vec<Sample> = records
+        .iter()
+        .map(|record| (record.timestamp, record.in_sync))
+        .collect();
+    samples.sort_by_key(|(timestamp, _)| *timestamp);
+    samples
+}
+
+/// Calculates the percentage of time DNS was in sync since a point in time, see `in_sync_percentage`.
+///
+/// # Arguments
+///
+/// * `samples`: A `&[Sample]` that holds the checks in chronological order.
+/// * `since`: A `DateTime<Local>` that specifies the start of the period.
+///
+/// # Returns
+///
+/// * An `Option<f64>` that holds the percentage, or `None` if there are no checks in the period.
+fn weighted_in_sync(samples: &[Sample], since: DateTime<Local>) -> Option<f64> {
+    let period = &samples[samples.partition_point(|(timestamp, _)| *timestamp < since)..];
+    if period.is_empty() {
+        return None;
+    }
+    let now = Local::now().fixed_offset();
+    let (mut in_sync, mut total) = (0, 0);
+    for (index, (timestamp, synced)) in period.iter().enumerate() {
+        let until = period.get(index + 1).map_or(now, |(next, _)| *next);
+        // Checks at the same instant still count, equally
+        let weight = (until - *timestamp)
+            .num_milliseconds()
+            .clamp(1, MAX_RECORD_WEIGHT.as_millis() as i64);
+        total += weight;
+        if *synced {
+            in_sync += weight;
+        }
+    }
+    Some(in_sync as f64 * 100.0 / total as f64)
+}
+
+/// Calculates the in-sync percentage over the last 24 hours, 7 days and 30 days, see `in_sync_percentage`.
+///
+/// # Arguments
+///
+/// * `records`: A `&[CheckRecord]` that holds the history.
+///
+/// # Returns
+///
+/// * A `Vec<(&str, Option<f64>)>` that holds the label and the percentage of each period.
+pub fn uptime(records: &[CheckRecord]) -> Vec<(&'static str, Option<f64>)> {
+    uptime_of(&samples(records))
+}
+
+/// Calculates the in-sync percentage over the last 24 hours, 7 days and 30 days of samples in chronological order.
+fn uptime_of(samples: &[Sample]) -> Vec<(&'static str, Option<f64>)> {
+    let now = Local::now();
+    UPTIME_PERIODS
+        .into_iter()
+        .map(|(label, days)| {
+            let since = now - ChronoDuration::try_days(days).unwrap_or_default();
+            (label, weighted_in_sync(samples, since))
+        })
+        .collect()
+}
+
+/// The periods of `uptime`, with their length in days.
+const UPTIME_PERIODS: [(&str, i64); 3] = [("24h", 1), ("7d", 7), ("30d", 30)];
+
+/// The checks of the last 30 days and their in-sync percentages, see `cached_uptime`.
+struct UptimeCache {
+    samples: VecDeque<Sample>,
+    uptime: Vec<(&'static str, Option<f64>)>,
+}
+
+/// The uptime kept up to date by `HistorySubscriber`, loaded from the history file on first use.
+static UPTIME: Mutex<Option<UptimeCache>> = Mutex::new(None);
+
+/// Runs a function on the uptime cache, loading it from the history file on first use.
+fn with_uptime_cache<T>(f: impl FnOnce(&mut UptimeCache) -> T) -> T {
+    let mut cache = UPTIME.lock().unwrap_or_else(|e| e.into_inner());
+    let cache = cache.get_or_insert_with(|| {
+        let samples = samples(&load_history(&history_file()));
+        UptimeCache {
+            uptime: uptime_of(&samples),
+            samples: samples.into(),
+        }
+    });
+    f(cache)
+}
+
+/// Adds a check to the uptime cache and recalculates the percentages.
+///
+/// Checks older than the longest period are dropped, so the cache does not grow with the history.
+fn cache_uptime(record: &CheckRecord) {
+    with_uptime_cache(|cache| {
+        cache.samples.push_back((record.timestamp, record.in_sync));
+        let cutoff = Local::now() - ChronoDuration::try_days(30).unwrap_or_default();
+        while cache
+            .samples
+            .front()
+            .is_some_and(|(timestamp, _)| *timestamp < cutoff)
+        {
+            cache.samples.pop_front();
+        }
+        cache.uptime = uptime_of(cache.samples.make_contiguous());
+    });
+}
+
+/// Retrieves the in-sync percentage over the last 24 hours, 7 days and 30 days, see `uptime`.
+///
+/// The percentages are recalculated by `HistorySubscriber` after every check instead of reading the history,
+/// so this function is cheap enough for every request to "/status".
+///
+/// # Returns
+///
+/// * A `Vec<(&str, Option<f64>)>` that holds the label and the percentage of each period.
+pub fn cached_uptime() -> Vec<(&'static str, Option<f64>)> {
+    with_uptime_cache(|cache| cache.uptime.clone())
+}
+
+/// Builds a summary of the in-sync percentage over the last 24 hours, 7 days and 30 days.
+///
+/// # Arguments
+///
+/// * `records`: A `&[CheckRecord]` that holds the history.
+///
+/// # Returns
+///
+/// * A `String` that holds the summary, e.g. "24h: 100.00%, 7d: 99.52%, 30d: n/a".
+pub fn uptime_summary(records: &[CheckRecord]) -> String {
+    uptime(records)
+        .into_iter()
+        .map(|(label, percentage)| match percentage {
+            Some(percentage) => format!("{}: {:.2}%", label, percentage),
+            None => format!("{}: n/a", label),
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Exports history records as CSV or JSON.
+///
+/// This function takes the history, the export format and the start of the exported period as arguments.
+/// If `changes_only` is set, only records where the DNS or WAN IP address differs from the previous record are exported,
+/// which gives the history of IP changes instead of every check.
+///
+/// # Arguments
+///
+/// * `records`: A `&[CheckRecord]` that holds the history.
+/// * `format`: A `&str` that specifies the format, either "csv" or "json".
+/// * `since`: A `DateTime<Local>` that specifies the start of the exported period.
+/// * `changes_only`: A `bool` that specifies whether only IP changes are exported.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds the exported history.
+/// * If the format is unknown, it returns an `Err` with a message.
+pub fn export_history(
+    records: &[CheckRecord],
+    format: &str,
+    since: DateTime<Local>,
+    changes_only: bool,
+) -> Result<String, String> {
+    let mut previous: Option<&CheckRecord> = None;
+    let mut exported: Vec<&CheckRecord> = Vec::new();
+    for record in records {
+        let changed = previous.is_none_or(|previous| {
+            previous.dns_ip != record.dns_ip || previous.wan_ip != record.wan_ip
+        });
+        previous = Some(record);
+        if record.timestamp >= since && (changed || !changes_only) {
+            exported.push(record);
+        }
+    }
+
+    match format {
+        "csv" => {
+            let mut csv = String::from("timestamp,hostname,dns_ip,wan_ip,in_sync\n");
+            for record in exported {
+                csv.push_str(&format!(
+                    "{},{},{},{},{}\n",
+                    record.timestamp.to_rfc3339(),
+                    record.hostname,
+                    record.dns_ip,
+                    record.wan_ip,
+                    record.in_sync
+                ));
+            }
+            Ok(csv)
+        }
+        "json" => {
+            let json: Vec<Value> = exported.iter().map(|record| record.to_json()).collect();
+            serde_json::to_string_pretty(&json).map_err(|e| e.to_string())
+        }
+        _ => Err(format!("Unknown export format: {}", format)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(hours_ago: i64, in_sync: bool) -> CheckRecord {
+        CheckRecord {
+            timestamp: (Local::now() - ChronoDuration::try_hours(hours_ago).unwrap())
+                .fixed_offset(),
+            hostname: "home.example.com".to_string(),
+            dns_ip: "1.1.1.1".to_string(),
+            wan_ip: if in_sync { "1.1.1.1" } else { "2.2.2.2" }.to_string(),
+            in_sync,
+            latencies: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_record_and_load_history() {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap().to_string();
+
+        let latencies = BTreeMap::from([("resolve_dns".to_string(), 42)]);
+        let in_sync = CheckRecord {
+            latencies: latencies.clone(),
+            ..record(0, true)
+        };
+        append_state(&path, &in_sync.to_json().to_string());
+        append_state(&path, &record(0, false).to_json().to_string());
+        let records = load_history(&path);
+
+        assert_eq!(records.len(), 2);
+        assert!(records[0].in_sync);
+        assert_eq!(records[0].latencies, latencies);
+        assert!(!records[1].in_sync);
+        assert_eq!(records[1].wan_ip, "2.2.2.2");
+
+        // Assert that records written before the latencies were tracked are still read
+        let line = r#"{"timestamp":"2024-05-01T12:00:00+00:00","hostname":"home.example.com","dns_ip":"1.1.1.1","wan_ip":"1.1.1.1","in_sync":true}"#;
+        assert!(CheckRecord::from_line(line).unwrap().latencies.is_empty());
+    }
+
+    #[test]
+    fn test_in_sync_percentage() {
+        let records = vec![record(48, false), record(2, true), record(1, false)];
+
+        // Assert that a check counts for at most twice the check interval, not the hours the checker was down
+        let day = in_sync_percentage(
+            &records,
+            Local::now() - ChronoDuration::try_days(1).unwrap(),
+        );
+        assert!((day.unwrap() - 50.0).abs() < 0.01);
+
+        let week = in_sync_percentage(
+            &records,
+            Local::now() - ChronoDuration::try_days(7).unwrap(),
+        );
+        assert!((week.unwrap() - 33.33).abs() < 0.01);
+
+        // Assert that each check counts for the time until the next one, the latest one until now
+        let now = Local::now();
+        let at = |seconds_ago: i64, in_sync: bool| CheckRecord {
+            timestamp: (now - ChronoDuration::try_seconds(seconds_ago).unwrap()).fixed_offset(),
+            ..record(0, in_sync)
+        };
+        let recent = vec![at(16, true), at(12, true), at(4, false)];
+        let since = now - ChronoDuration::try_minutes(1).unwrap();
+        assert!((in_sync_percentage(&recent, since).unwrap() - 75.0).abs() < 0.1);
+
+        // Assert that checking more often while in sync does not raise the percentage
+        let mut frequent = records.clone();
+        frequent.extend((0..10).map(|_| record(2, true)));
+        let day = in_sync_percentage(
+            &frequent,
+            Local::now() - ChronoDuration::try_days(1).unwrap(),
+        );
+        assert!((day.unwrap() - 50.0).abs() < 0.1);
+
+        assert_eq!(in_sync_percentage(&records, Local::now()), None);
+    }
+
+    #[test]
+    fn test_uptime_cache() {
+        let record = |hours_ago: i64, in_sync: bool| CheckRecord {
+            hostname: "cache.example.com".to_string(),
+            ..record(hours_ago, in_sync)
+        };
+        *UPTIME.lock().unwrap() = Some(UptimeCache {
+            samples: samples(&[record(24 * 40, true), record(2, true)]).into(),
+            uptime: Vec::new(),
+        });
+
+        // Assert that a check updates the percentages and drops the checks older than 30 days
+        cache_uptime(&record(1, false));
+        let uptime = cached_uptime();
+        assert_eq!(uptime.len(), 3);
+        assert!((uptime[0].1.unwrap() - 50.0).abs() < 0.1);
+        assert_eq!(UPTIME.lock().unwrap().as_ref().unwrap().samples.len(), 2);
+    }
+
+    #[test]
+    fn test_nxdomain_gap() {
+        let mut context = check::Context::new("home.example.com");
+        context.dns_ip = "1.1.1.1".to_string();
+        context.wan_ip = "1.1.1.1".to_string();
+        let in_sync = CheckRecord::from_context(&context);
+        assert!(in_sync.in_sync);
+
+        // Assert that a failed lookup is recorded as out of sync with the empty address
+        context.dns_ip = String::new();
+        let nxdomain = CheckRecord::from_context(&context);
+        assert!(!nxdomain.in_sync);
+        assert_eq!(nxdomain.dns_ip, "");
+
+        // Assert that the in-sync check before the NXDOMAIN gap is not credited with the gap
+        let now = Local::now();
+        let at = |record: &CheckRecord, seconds_ago: i64| CheckRecord {
+            timestamp: (now - ChronoDuration::try_seconds(seconds_ago).unwrap()).fixed_offset(),
+            ..record.clone()
+        };
+        let records = vec![at(&in_sync, 30), at(&nxdomain, 20), at(&nxdomain, 10)];
+        let since = now - ChronoDuration::try_minutes(1).unwrap();
+        assert!((in_sync_percentage(&records, since).unwrap() - 33.33).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_export_history() {
+        let records = vec![
+            record(48, true),
+            record(3, true),
+            record(2, false),
+            record(1, false),
+        ];
+        let since = Local::now() - ChronoDuration::try_days(1).unwrap();
+
+        // Assert that only records in the period are exported
+        let csv = export_history(&records, "csv", since, false).unwrap();
+        assert_eq!(csv.lines().count(), 4);
+        assert!(csv.starts_with("timestamp,hostname,dns_ip,wan_ip,in_sync\n"));
+        assert!(csv.contains(",home.example.com,1.1.1.1,2.2.2.2,false"));
+
+        // Assert that only the IP change is exported in changes mode
+        let json = export_history(&records, "json", since, true).unwrap();
+        let json: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        assert_eq!(json[0]["wan_ip"], "2.2.2.2");
+
+        assert!(export_history(&records, "xml", since, false).is_err());
+    }
+}
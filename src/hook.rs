@@ -0,0 +1,221 @@
+use crate::events::{Event, Subscriber};
+use crate::notify::Message;
+use crate::vars::parse_duration;
+use std::env;
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Runs a configured command when an event is published on the event bus (see `events::publish`).
+///
+/// The command for an event is taken from the "ON_<EVENT>" environment variable,
+/// e.g. "ON_MISMATCH", "ON_RECOVERY", "ON_WAN_CHANGE", "ON_FAILOVER", "ON_CHECK_ERROR" or "ON_HEARTBEAT".
+/// It is run with `sh -c`, so it may contain arguments and shell syntax.
+///
+/// Hooks subscribe independently of the notification channels, so they run even while alerts are muted
+/// or the alert limit is reached.
+pub struct HookSubscriber {
+    timeout: Duration,
+}
+
+impl HookSubscriber {
+    /// Creates the hook subscriber.
+    ///
+    /// The time a command may run is read from "HOOK_TIMEOUT" (e.g. "30s"), defaulting to 30 seconds.
+    pub fn from_env() -> HookSubscriber {
+        let timeout = match env::var("HOOK_TIMEOUT") {
+            Ok(value) => parse_duration(&value).unwrap_or_else(|| {
+                log::warn!("Invalid HOOK_TIMEOUT {}, using 30s", value);
+                Duration::from_secs(30)
+            }),
+            Err(_) => Duration::from_secs(30),
+        };
+        HookSubscriber { timeout }
+    }
+}
+
+impl Subscriber for HookSubscriber {
+    fn name(&self) -> &str {
+        "hook"
+    }
+
+    fn handles(&self, event: &Event) -> bool {
+        !matches!(event, Event::Checked(_)) && env::var(hook_var(event.kind())).is_ok()
+    }
+
+    fn receive(&self, event: &Event) -> bool {
+        let (command, message) = match (env::var(hook_var(event.kind())), event.to_message()) {
+            (Ok(command), Some(message)) => (command, message),
+            _ => return true,
+        };
+        match run_hook(&command, &message, self.timeout) {
+            Ok(output) => {
+                log::info!("Hook for {} succeeded", message.event);
+                log_output(&output);
+                true
+            }
+            Err(e) => {
+                log::warn!("Hook for {} failed: {}", message.event, e);
+                false
+            }
+        }
+    }
+}
+
+/// Retrieves the name of the variable that holds the command of an event, e.g. "ON_WAN_CHANGE" for "wan_change".
+fn hook_var(event: &str) -> String {
+    format!("ON_{}", event.to_uppercase())
+}
+
+/// Retrieves the environment variables passed to a hook.
+///
+/// # Arguments
+///
+/// * `message`: A `&Message` that holds the event.
+///
+/// # Returns
+///
+/// * A `Vec<(String, String)>` that holds "DNSCHECKER_EVENT", "DNSCHECKER_TEXT"
+///   and a "DNSCHECKER_<FIELD>" variable for each field of the message, e.g. "DNSCHECKER_DNS_IP".
+fn hook_env(message: &Message) -> Vec<(String, String)> {
+    let mut vars = vec![
+        ("DNSCHECKER_EVENT".to_string(), message.event.clone()),
+        ("DNSCHECKER_TEXT".to_string(), message.text.clone()),
+    ];
+    for (name, value) in &message.fields {
+        vars.push((format!("DNSCHECKER_{}", name.to_uppercase()), value.clone()));
+    }
+    vars
+}
+
+/// Runs the command of a hook.
+///
+/// This function runs the command with `sh -c`, passing the details of the event in environment variables (see `hook_env`).
+/// Its standard output and standard error are captured. If it runs longer than the timeout, it is killed
+/// together with the processes it started.
+///
+/// # Arguments
+///
+/// * `command`: A `&str` that holds the command.
+/// * `message`: A `&Message` that holds the event.
+/// * `timeout`: A `Duration` that specifies how long the command may run.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds the captured output if the command exits successfully.
+/// * If the command cannot be started, times out or fails, it returns an `Err` with a message that includes the captured output.
+fn run_hook(command: &str, message: &Message, timeout: Duration) -> Result<String, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(hook_env(message))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()
+        .map_err(|e| format!("Failed to start {}: {}", command, e))?;
+
+    // Read the output on separate threads, so a chatty command cannot block on a full pipe
+    let readers: Vec<_> = [
+        child
+            .stdout
+            .take()
+            .map(|pipe| Box::new(pipe) as Box<dyn Read + Send>),
+        child
+            .stderr
+            .take()
+            .map(|pipe| Box::new(pipe) as Box<dyn Read + Send>),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|mut pipe| {
+        std::thread::spawn(move || {
+            let mut output = String::new();
+            let _ = pipe.read_to_string(&mut output);
+            output
+        })
+    })
+    .collect();
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) if started.elapsed() >= timeout => {
+                // Kill the whole process group, so commands started by the shell don't keep the output open
+                // SAFETY: kill has no memory safety requirements; the group was created for this command
+                unsafe { libc::kill(-(child.id() as i32), libc::SIGKILL) };
+                let _ = child.wait();
+                break None;
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(50)),
+            Err(e) => return Err(format!("Failed to wait for {}: {}", command, e)),
+        }
+    };
+    let output: String = readers
+        .into_iter()
+        .filter_map(|reader| reader.join().ok())
+        .collect();
+
+    match status {
+        Some(status) if status.success() => Ok(output),
+        Some(status) => Err(format!(
+            "{} exited with {}: {}",
+            command,
+            status,
+            output.trim()
+        )),
+        None => Err(format!(
+            "{} timed out after {:?}: {}",
+            command,
+            timeout,
+            output.trim()
+        )),
+    }
+}
+
+/// Logs the captured output of a hook, one log line per output line.
+fn log_output(output: &str) {
+    for line in output.lines().filter(|line| !line.trim().is_empty()) {
+        log::info!("hook: {}", line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_env() {
+        let message = Message::new("wan_change", "text").with_field("wan_ip", "203.0.113.7");
+
+        assert_eq!(hook_var(&message.event), "ON_WAN_CHANGE");
+        assert!(hook_env(&message)
+            .contains(&("DNSCHECKER_WAN_IP".to_string(), "203.0.113.7".to_string())));
+    }
+
+    #[test]
+    fn test_run_hook() {
+        let message = Message::new("mismatch", "text").with_field("dns_ip", "198.51.100.4");
+
+        // Assert that the details are passed and the output is captured
+        let output = run_hook(
+            "echo $DNSCHECKER_EVENT $DNSCHECKER_DNS_IP",
+            &message,
+            Duration::from_secs(5),
+        );
+        assert_eq!(output, Ok("mismatch 198.51.100.4\n".to_string()));
+
+        // Assert that a failing command is reported with its output
+        let result = run_hook("echo oops >&2; exit 3", &message, Duration::from_secs(5));
+        assert!(result.is_err_and(|e| e.contains("oops")));
+
+        // Assert that a command is killed after the timeout
+        let started = Instant::now();
+        let result = run_hook("sleep 5", &message, Duration::from_millis(200));
+        assert!(result.is_err_and(|e| e.contains("timed out")));
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+}
@@ -0,0 +1,399 @@
+use crate::vars::parse_duration;
+use reqwest::blocking::{Client, ClientBuilder};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::Certificate;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::CertificateDer;
+use rustls::{ClientConfig, RootCertStore};
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// The root certificates loaded from "EXTRA_CA_DIR", read once on first use.
+static EXTRA_CAS: OnceLock<Vec<Certificate>> = OnceLock::new();
+
+/// Creates a `reqwest::blocking::ClientBuilder` with the settings shared by all outbound HTTP calls.
+///
+/// Every HTTP client of the application is created from this builder, so settings like additional
+/// root certificates apply to the router API, Telegram and webhooks alike.
+///
+/// If an address family is configured (see `ip_family`), host names are resolved to addresses of that family only,
+/// so no connection is attempted over the other family.
+/// The connections can also be bound to a source address ("OUTBOUND_ADDRESS") or a network interface ("OUTBOUND_INTERFACE"),
+/// so the traffic of the checker itself can use a different path than the monitored WAN.
+///
+/// Some router HTTP stacks misbehave with reused connections, so the connection handling can be tuned:
+/// the HTTP version ("OUTBOUND_HTTP_VERSION", see `http_version`), whether idle connections are kept for reuse ("OUTBOUND_KEEPALIVE"),
+/// how long they are kept ("OUTBOUND_POOL_IDLE_TIMEOUT") and the interval of TCP keep-alive probes ("OUTBOUND_TCP_KEEPALIVE").
+///
+/// # Returns
+///
+/// * A `reqwest::blocking::ClientBuilder` that trusts the certificates of the `extra_cas` function in addition to the built-in roots.
+pub fn client_builder() -> ClientBuilder {
+    let mut builder = Client::builder();
+    for certificate in extra_cas() {
+        builder = builder.add_root_certificate(certificate.clone());
+    }
+    let family = ip_family();
+    if family != IpFamily::Any {
+        builder = builder.dns_resolver(Arc::new(FamilyResolver(family)));
+    }
+    if let Some(address) = local_address() {
+        builder = builder.local_address(address);
+    }
+    if let Ok(interface) = env::var("OUTBOUND_INTERFACE") {
+        builder = bind_interface(builder, &interface);
+    }
+    builder = match http_version() {
+        HttpVersion::Auto => builder,
+        HttpVersion::Http1 => builder.http1_only(),
+        HttpVersion::Http2 => builder.http2_prior_knowledge(),
+    };
+    if env::var("OUTBOUND_KEEPALIVE").as_deref() == Ok("false") {
+        builder = builder.pool_max_idle_per_host(0);
+    }
+    if let Some(timeout) = duration_var("OUTBOUND_POOL_IDLE_TIMEOUT") {
+        builder = builder.pool_idle_timeout(timeout);
+    }
+    if let Some(interval) = duration_var("OUTBOUND_TCP_KEEPALIVE") {
+        builder = builder.tcp_keepalive(interval);
+    }
+    builder
+}
+
+/// The HTTP version of outbound connections.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HttpVersion {
+    /// Negotiate HTTP/2 or HTTP/1.1 with the server.
+    Auto,
+    /// Use HTTP/1.1 only.
+    Http1,
+    /// Use HTTP/2 without negotiating it.
+    Http2,
+}
+
+/// Retrieves the HTTP version of outbound connections.
+///
+/// This function parses the "OUTBOUND_HTTP_VERSION" environment variable ("auto", "http1" or "http2").
+/// If it is not set, it defaults to "auto". If it cannot be parsed, it logs a warning and uses the default.
+///
+/// # Returns
+///
+/// * An `HttpVersion` that specifies the HTTP version.
+pub fn http_version() -> HttpVersion {
+    let value = env::var("OUTBOUND_HTTP_VERSION").unwrap_or("auto".to_string());
+    match value.to_lowercase().as_str() {
+        "auto" => HttpVersion::Auto,
+        "http1" => HttpVersion::Http1,
+        "http2" => HttpVersion::Http2,
+        _ => {
+            log::warn!("Invalid OUTBOUND_HTTP_VERSION {}, using auto", value);
+            HttpVersion::Auto
+        }
+    }
+}
+
+/// Retrieves a duration from an environment variable, e.g. "30s".
+///
+/// # Arguments
+///
+/// * `name`: A `&str` that specifies the environment variable.
+///
+/// # Returns
+///
+/// * An `Option<Duration>` that holds the duration, or `None` if it is not set. If it cannot be parsed, it logs a warning and returns `None`.
+fn duration_var(name: &str) -> Option<Duration> {
+    let value = env::var(name).ok()?;
+    let duration = parse_duration(&value);
+    if duration.is_none() {
+        log::warn!("Invalid {} {}, ignoring it", name, value);
+    }
+    duration
+}
+
+/// Retrieves the source address of outbound connections.
+///
+/// This function parses the "OUTBOUND_ADDRESS" environment variable (e.g. "192.168.10.2").
+/// If it cannot be parsed, it logs a warning and the source address is chosen by the operating system.
+///
+/// # Returns
+///
+/// * An `Option<IpAddr>` that holds the source address if it is configured.
+fn local_address() -> Option<IpAddr> {
+    let value = env::var("OUTBOUND_ADDRESS").ok()?;
+    match value.parse::<IpAddr>() {
+        Ok(address) => Some(address),
+        Err(_) => {
+            log::warn!("Invalid OUTBOUND_ADDRESS {}, ignoring it", value);
+            None
+        }
+    }
+}
+
+/// Binds outbound connections to a network interface (SO_BINDTODEVICE), e.g. a management VLAN.
+///
+/// # Arguments
+///
+/// * `builder`: A `reqwest::blocking::ClientBuilder` that is configured.
+/// * `interface`: A `&str` that specifies the name of the interface, e.g. "eth0.10".
+///
+/// # Returns
+///
+/// * A `reqwest::blocking::ClientBuilder` whose connections use the interface.
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+fn bind_interface(builder: ClientBuilder, interface: &str) -> ClientBuilder {
+    builder.interface(interface)
+}
+
+/// Binding to an interface is only supported on Linux, so this logs a warning and leaves the builder unchanged.
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+fn bind_interface(builder: ClientBuilder, interface: &str) -> ClientBuilder {
+    log::warn!(
+        "OUTBOUND_INTERFACE {} is only supported on Linux, ignoring it",
+        interface
+    );
+    builder
+}
+
+/// The address family of outbound connections.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IpFamily {
+    /// Connect over IPv4 or IPv6, whichever works.
+    Any,
+    /// Connect over IPv4 only.
+    V4,
+    /// Connect over IPv6 only.
+    V6,
+}
+
+/// Retrieves the address family of outbound connections.
+///
+/// This function parses the "OUTBOUND_IP_FAMILY" environment variable ("ipv4", "ipv6" or "any").
+/// If it is not set, it defaults to "any". If it cannot be parsed, it logs a warning and uses the default.
+///
+/// # Returns
+///
+/// * An `IpFamily` that specifies the address family.
+pub fn ip_family() -> IpFamily {
+    let value = env::var("OUTBOUND_IP_FAMILY").unwrap_or("any".to_string());
+    match value.to_lowercase().as_str() {
+        "any" => IpFamily::Any,
+        "ipv4" => IpFamily::V4,
+        "ipv6" => IpFamily::V6,
+        _ => {
+            log::warn!("Invalid OUTBOUND_IP_FAMILY {}, using any", value);
+            IpFamily::Any
+        }
+    }
+}
+
+/// Keeps the addresses of the given family.
+///
+/// # Arguments
+///
+/// * `addrs`: A `Vec<SocketAddr>` that holds the resolved addresses.
+/// * `family`: An `IpFamily` that specifies the address family to keep.
+///
+/// # Returns
+///
+/// * A `Vec<SocketAddr>` that holds the addresses of the family, in their original order.
+fn filter_family(addrs: Vec<SocketAddr>, family: IpFamily) -> Vec<SocketAddr> {
+    addrs
+        .into_iter()
+        .filter(|addr| match family {
+            IpFamily::Any => true,
+            IpFamily::V4 => addr.is_ipv4(),
+            IpFamily::V6 => addr.is_ipv6(),
+        })
+        .collect()
+}
+
+/// A resolver that only returns addresses of one family.
+///
+/// It uses the system resolver, like reqwest does by default, and drops the addresses of the other family.
+/// The lookup blocks, so it runs on the blocking pool of the runtime (see `tokio::net::lookup_host`)
+/// instead of stalling the worker that drives the request.
+struct FamilyResolver(IpFamily);
+
+impl Resolve for FamilyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let family = self.0;
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            let addrs = filter_family(addrs, family);
+            if addrs.is_empty() {
+                return Err(format!("No {:?} address for {}", family, name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Creates a `reqwest::blocking::Client` from the shared builder.
+///
+/// # Returns
+///
+/// * A `Result<reqwest::blocking::Client, String>` that holds the client, or an `Err` with a message if it cannot be built.
+pub fn client() -> Result<Client, String> {
+    client_builder()
+        .build()
+        .map_err(|e| format!("Failed to build client: {}", e))
+}
+
+/// Retrieves the additional root certificates.
+///
+/// On first use this function loads them from the directory in the "EXTRA_CA_DIR" environment variable
+/// using the `load_cas` function. If it is not set, there are no additional root certificates.
+///
+/// # Returns
+///
+/// * A `&'static [Certificate]` that holds the additional root certificates.
+fn extra_cas() -> &'static [Certificate] {
+    EXTRA_CAS.get_or_init(|| match env::var("EXTRA_CA_DIR") {
+        Ok(dir) => {
+            let certificates = load_cas(&dir);
+            log::info!(
+                "Loaded {} additional root certificates from {}",
+                certificates.len(),
+                dir
+            );
+            certificates
+        }
+        Err(_) => Vec::new(),
+    })
+}
+
+/// Loads the PEM encoded certificates of a directory.
+///
+/// This function reads every file ending in ".pem" or ".crt" in the directory, in name order.
+/// A file may contain several certificates. Files that cannot be read or parsed are skipped with a warning.
+///
+/// # Arguments
+///
+/// * `dir`: A `&str` that specifies the directory.
+///
+/// # Returns
+///
+/// * A `Vec<Certificate>` that holds the certificates.
+fn load_cas(dir: &str) -> Vec<Certificate> {
+    let mut certificates = Vec::new();
+    for path in ca_files(dir) {
+        match std::fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|pem| Certificate::from_pem_bundle(&pem).map_err(|e| e.to_string()))
+        {
+            Ok(bundle) if !bundle.is_empty() => certificates.extend(bundle),
+            Ok(_) => log::warn!("No certificate found in {}", path.display()),
+            Err(e) => log::warn!("Failed to load certificate {}: {}", path.display(), e),
+        }
+    }
+    certificates
+}
+
+/// Lists the certificate files of a directory of additional root certificates, e.g. "EXTRA_CA_DIR".
+///
+/// # Arguments
+///
+/// * `dir`: A `&str` that specifies the directory.
+///
+/// # Returns
+///
+/// * A `Vec<PathBuf>` that holds the files ending in ".pem" or ".crt", in name order; empty if the directory cannot be read.
+fn ca_files(dir: &str) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "pem" || extension == "crt")
+            })
+            .collect(),
+        Err(e) => {
+            log::warn!("Failed to read EXTRA_CA_DIR {}: {}", dir, e);
+            return Vec::new();
+        }
+    };
+    paths.sort();
+    paths
+}
+
+/// Builds the TLS configuration of the connections that are not made with `reqwest`, e.g. to XMPP servers
+/// or DNS-over-TLS resolvers.
+///
+/// It trusts the built-in roots and, like the HTTP client, the certificates in the "EXTRA_CA_DIR" directory.
+///
+/// # Returns
+///
+/// * A `Result<Arc<rustls::ClientConfig>, String>` that holds the configuration, or an `Err` with a message if it cannot be built.
+pub fn tls_config() -> Result<Arc<ClientConfig>, String> {
+    let mut roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    if let Ok(dir) = env::var("EXTRA_CA_DIR") {
+        for path in ca_files(&dir) {
+            let pem = std::fs::read(&path).unwrap_or_default();
+            for certificate in CertificateDer::pem_slice_iter(&pem).flatten() {
+                if let Err(e) = roots.add(certificate) {
+                    log::warn!("Failed to load certificate {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+    let config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| format!("Failed to configure TLS: {}", e))?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_family() {
+        let addrs: Vec<SocketAddr> = vec![
+            "[2001:db8::1]:443".parse().unwrap(),
+            "192.0.2.1:443".parse().unwrap(),
+        ];
+
+        assert_eq!(
+            filter_family(addrs.clone(), IpFamily::V4),
+            vec!["192.0.2.1:443".parse().unwrap()]
+        );
+        assert_eq!(
+            filter_family(addrs.clone(), IpFamily::V6),
+            vec!["[2001:db8::1]:443".parse().unwrap()]
+        );
+        assert_eq!(filter_family(addrs.clone(), IpFamily::Any), addrs);
+    }
+
+    #[test]
+    fn test_family_resolver() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let addrs: Vec<SocketAddr> = runtime
+            .block_on(FamilyResolver(IpFamily::V4).resolve("localhost".parse().unwrap()))
+            .unwrap()
+            .collect();
+
+        assert_eq!(addrs, vec!["127.0.0.1:0".parse().unwrap()]);
+    }
+
+    #[test]
+    fn test_load_cas() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("README.txt"), "not a certificate").unwrap();
+        std::fs::write(dir.path().join("empty.pem"), "").unwrap();
+
+        // Assert that files without certificates are skipped
+        assert!(load_cas(dir.path().to_str().unwrap()).is_empty());
+
+        // Assert that a missing directory yields no certificates
+        assert!(load_cas("/nonexistent/dnschecker-ca").is_empty());
+    }
+}
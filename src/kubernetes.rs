@@ -0,0 +1,64 @@
+use std::env;
+
+/// Returns whether the checker runs in Kubernetes mode ("DEPLOYMENT_MODE" is "kubernetes").
+pub fn is_kubernetes() -> bool {
+    env::var("DEPLOYMENT_MODE").is_ok_and(|mode| mode == "kubernetes")
+}
+
+/// Applies the defaults of Kubernetes mode.
+///
+/// In Kubernetes mode, this function sets the following environment variables unless they are already set:
+///
+/// * "LISTEN_ADDR" to "0.0.0.0:8080", so the "/healthz" and "/readyz" probes can be reached.
+/// * "LOG_FORMAT" to "json", for the log collector of the cluster.
+///
+/// It must be called before the logger is initialized.
+pub fn apply_defaults() {
+    if !is_kubernetes() {
+        return;
+    }
+    for (name, value) in [("LISTEN_ADDR", "0.0.0.0:8080"), ("LOG_FORMAT", "json")] {
+        if env::var(name).is_err() {
+            env::set_var(name, value);
+        }
+    }
+}
+
+/// Retrieves the identity of the pod from the downward API.
+///
+/// This function reads the "POD_NAMESPACE" and "POD_NAME" environment variables,
+/// which the pod spec can populate from `metadata.namespace` and `metadata.name`.
+///
+/// # Returns
+///
+/// * An `Option<String>` that holds "namespace/name", or just the name without a namespace, or `None` without a name.
+pub fn pod_identity() -> Option<String> {
+    identity(env::var("POD_NAMESPACE").ok(), env::var("POD_NAME").ok())
+}
+
+/// Builds the identity of a pod from its namespace and name.
+fn identity(namespace: Option<String>, name: Option<String>) -> Option<String> {
+    let name = name.filter(|name| !name.is_empty())?;
+    match namespace.filter(|namespace| !namespace.is_empty()) {
+        Some(namespace) => Some(format!("{}/{}", namespace, name)),
+        None => Some(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity() {
+        assert_eq!(
+            identity(Some("dns".to_string()), Some("dnschecker-0".to_string())),
+            Some("dns/dnschecker-0".to_string())
+        );
+        assert_eq!(
+            identity(None, Some("dnschecker-0".to_string())),
+            Some("dnschecker-0".to_string())
+        );
+        assert_eq!(identity(Some("dns".to_string()), None), None);
+    }
+}
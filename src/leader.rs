@@ -0,0 +1,188 @@
+use crate::store::{instance_id, store};
+use crate::vars::parse_duration;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The name of the lock the instances compete for.
+const LEASE: &str = "leader";
+
+/// The state of the election of this instance.
+static ELECTION: Mutex<Election> = Mutex::new(Election::new());
+
+/// The role of this instance in the leader election.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Election {
+    /// Whether this instance leads, `None` before the first attempt to take the lease.
+    leader: Option<bool>,
+    /// When the lease was last taken or renewed.
+    renewed: Option<Instant>,
+    /// When the lease was last attempted.
+    attempted: Option<Instant>,
+}
+
+impl Election {
+    /// Creates the state before the first attempt.
+    const fn new() -> Election {
+        Election {
+            leader: None,
+            renewed: None,
+            attempted: None,
+        }
+    }
+
+    /// Returns whether the lease should be attempted again, three times per lease so it is renewed before it expires.
+    fn is_due(&self, now: Instant, lease: Duration) -> bool {
+        self.attempted
+            .is_none_or(|attempted| now.duration_since(attempted) >= lease / 3)
+    }
+
+    /// Records an attempt to take the lease.
+    ///
+    /// If the backend of the state failed, the role is kept while the last lease is valid;
+    /// after that, this instance leads, so alerts are sent twice rather than not at all.
+    ///
+    /// # Arguments
+    ///
+    /// * `result`: A `Result<bool, String>` that holds whether the lease was taken, or the error of the backend.
+    /// * `now`: An `Instant` that specifies the time of the attempt.
+    /// * `lease`: A `Duration` that specifies how long the lease is valid.
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<bool>` that holds the new role if it changed.
+    fn record(
+        &mut self,
+        result: &Result<bool, String>,
+        now: Instant,
+        lease: Duration,
+    ) -> Option<bool> {
+        self.attempted = Some(now);
+        let leader = match result {
+            Ok(true) => {
+                self.renewed = Some(now);
+                true
+            }
+            Ok(false) => false,
+            Err(_) => match self.renewed {
+                Some(renewed) if now.duration_since(renewed) < lease => {
+                    self.leader.unwrap_or(false)
+                }
+                _ => true,
+            },
+        };
+        let previous = self.leader.replace(leader);
+        (previous != Some(leader)).then_some(leader)
+    }
+}
+
+/// Returns whether the leader election is enabled with the "LEADER_ELECTION" environment variable.
+fn enabled() -> bool {
+    env::var("LEADER_ELECTION").is_ok_and(|value| value == "true")
+}
+
+/// Retrieves how long a lease is valid from the "LEADER_LEASE" environment variable (e.g. "30s", the default).
+fn lease() -> Duration {
+    env::var("LEADER_LEASE")
+        .ok()
+        .and_then(|value| parse_duration(&value))
+        .filter(|lease| !lease.is_zero())
+        .unwrap_or(Duration::from_secs(30))
+}
+
+/// Takes or renews the lease of the leader, if "LEADER_ELECTION" is "true".
+///
+/// This function is called on every iteration of the checker loop. The lease is a lock in the backend of the state
+/// (see `store::StateStore::try_lock`) held by one instance, which renews it three times per "LEADER_LEASE";
+/// when the leader stops, another instance takes over once the lease expires. Every instance keeps checking,
+/// but only the leader sends notifications (see `is_leader`). Changes of the role are logged.
+pub fn renew() {
+    if !enabled() {
+        return;
+    }
+    let lease = lease();
+    let now = Instant::now();
+    let mut election = ELECTION.lock().unwrap_or_else(|e| e.into_inner());
+    if !election.is_due(now, lease) {
+        return;
+    }
+    if election.attempted.is_none() && store().name() == "file" {
+        log::warn!("LEADER_ELECTION needs a shared STATE_BACKEND, every instance leads with files");
+    }
+    let result = store().try_lock(LEASE, instance_id(), lease);
+    if let Err(e) = &result {
+        log::warn!("Failed to renew the leader lease: {}", e);
+    }
+    match election.record(&result, now, lease) {
+        Some(true) => log::info!("{} leads, sending notifications", instance_id()),
+        Some(false) => log::info!(
+            "Another instance leads, {} keeps checking without sending notifications",
+            instance_id()
+        ),
+        None => {}
+    }
+}
+
+/// Returns whether this instance sends notifications.
+///
+/// # Returns
+///
+/// * A `bool` that is `false` only while the leader election is enabled and another instance holds the lease;
+///   before the first election, e.g. for a command, it is `true`.
+pub fn is_leader() -> bool {
+    !enabled()
+        || ELECTION
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .leader
+            .unwrap_or(true)
+}
+
+/// Describes the role of this instance for the state dump.
+///
+/// # Returns
+///
+/// * An `Option<String>` that holds "leader" or "follower" with the instance, or `None` if the election is disabled.
+pub fn role() -> Option<String> {
+    if !enabled() {
+        return None;
+    }
+    let role = if is_leader() { "leader" } else { "follower" };
+    Some(format!("{} ({})", role, instance_id()))
+}
+
+/// Gives up the lease on shutdown, so another instance takes over without waiting for it to expire.
+pub fn release() {
+    if !enabled() || !is_leader() {
+        return;
+    }
+    match store().try_lock(LEASE, instance_id(), Duration::ZERO) {
+        Ok(_) => log::info!("Released the leader lease"),
+        Err(e) => log::warn!("Failed to release the leader lease: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_election() {
+        let lease = Duration::from_secs(30);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut election = Election::new();
+        assert!(election.is_due(start, lease));
+
+        assert_eq!(election.record(&Ok(true), at(0), lease), Some(true));
+        assert!(!election.is_due(at(9), lease));
+        assert!(election.is_due(at(10), lease));
+        assert_eq!(election.record(&Ok(true), at(10), lease), None);
+
+        // Assert that the role is kept while the backend fails within the lease, and taken after it
+        let failed = Err("connection refused".to_string());
+        assert_eq!(election.record(&Ok(false), at(20), lease), Some(false));
+        assert_eq!(election.record(&failed, at(30), lease), None);
+        assert_eq!(election.record(&failed, at(40), lease), Some(true));
+    }
+}
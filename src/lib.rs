@@ -0,0 +1,121 @@
+//! Verifies that the DNS record of a hostname matches the WAN IP address of a router,
+//! and raises alerts through Telegram, webhooks and other channels when it does not.
+//!
+//! The `dnschecker` binary runs the checker loop. Other programs can embed the same engine
+//! through `Checker`, see the `checker` module. Note that `Checker::new` sets its settings
+//! as environment variables of the process, so only one checker can be created per process.
+
+pub mod checker;
+pub mod config;
+// Programs can subscribe to the event bus, see the README
+pub mod events;
+
+// Used by the dnschecker binary and the benchmarks, not part of the public API
+#[doc(hidden)]
+pub mod agent;
+#[doc(hidden)]
+pub mod alarm;
+#[doc(hidden)]
+pub mod api;
+#[doc(hidden)]
+pub mod cache;
+#[doc(hidden)]
+pub mod chaos;
+#[doc(hidden)]
+pub mod check;
+#[doc(hidden)]
+pub mod cluster;
+#[doc(hidden)]
+pub mod crypt;
+#[doc(hidden)]
+pub mod dependencies;
+#[doc(hidden)]
+pub mod digest;
+#[doc(hidden)]
+pub mod dns;
+#[doc(hidden)]
+pub mod dump;
+#[doc(hidden)]
+pub mod history;
+#[doc(hidden)]
+pub mod icinga;
+#[doc(hidden)]
+pub mod kubernetes;
+#[doc(hidden)]
+pub mod leader;
+#[doc(hidden)]
+pub mod logging;
+#[doc(hidden)]
+pub mod metrics;
+#[doc(hidden)]
+pub mod netlink;
+#[doc(hidden)]
+pub mod notify;
+#[doc(hidden)]
+pub mod passive;
+#[doc(hidden)]
+pub mod redact;
+#[doc(hidden)]
+pub mod replay;
+#[doc(hidden)]
+pub mod secrets;
+#[doc(hidden)]
+pub mod server;
+#[doc(hidden)]
+pub mod simulate;
+#[doc(hidden)]
+pub mod state;
+#[doc(hidden)]
+pub mod stats;
+#[doc(hidden)]
+pub mod store;
+#[doc(hidden)]
+pub mod telegram;
+#[cfg(feature = "tray")]
+#[doc(hidden)]
+pub mod tray;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
+pub mod tui;
+#[doc(hidden)]
+pub mod vars;
+#[doc(hidden)]
+pub mod zabbix;
+
+mod apprise;
+mod axfr;
+mod backends;
+mod breaker;
+mod caa;
+mod chart;
+mod client_subnet;
+mod compare;
+mod ct;
+mod delegation;
+mod dingtalk;
+mod dnssec;
+mod enrich;
+mod failover;
+mod feishu;
+mod health;
+mod hook;
+mod http;
+mod mail;
+mod nameservers;
+mod planned;
+mod prefix;
+mod propagation;
+mod script;
+mod signal;
+mod slack;
+mod slo;
+mod snmp;
+mod split_horizon;
+mod tsig;
+mod ttl;
+mod webhook;
+mod whatsapp;
+mod wildcard;
+mod xmpp;
+
+pub use checker::{CheckOutcome, Checker, Event};
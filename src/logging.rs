@@ -0,0 +1,432 @@
+use chrono::Local;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde_json::json;
+use simple_logger::SimpleLogger;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// The path of the native protocol socket of systemd-journald.
+#[cfg(unix)]
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// The number of lines kept by the `BufferLogger`.
+const BUFFER_LINES: usize = 500;
+
+/// The most recent lines of the `BufferLogger`, oldest first.
+static BUFFER: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Creates the logging backend selected by the "LOG_FORMAT" environment variable.
+///
+/// * "text" (the default): The `SimpleLogger` writing text lines to stdout.
+/// * "json": A `JsonLogger` writing one JSON object per line to stdout.
+/// * "journald": A `JournaldLogger` sending structured entries to the systemd journal (Linux).
+/// * "eventlog": An `EventLogLogger` writing warnings and errors to the Windows Event Log as well (Windows).
+/// * "buffer": A `BufferLogger` keeping the recent lines in memory, used by the TUI which shows them itself.
+///
+/// If the selected backend is not available on the platform or cannot be opened, the text logger is used.
+///
+/// # Arguments
+///
+/// * `format`: A `&str` that specifies the value of "LOG_FORMAT".
+/// * `filter`: A `SimpleLogger` configured from the environment, which decides the levels of every backend.
+///
+/// # Returns
+///
+/// * A `(Box<dyn Log>, LevelFilter)` that holds the backend and the most verbose level it logs.
+pub fn backend(format: &str, filter: SimpleLogger) -> (Box<dyn Log>, LevelFilter) {
+    let max_level = filter.max_level();
+    let logger: Box<dyn Log> = match format {
+        "json" => Box::new(JsonLogger::new(filter)),
+        "buffer" => Box::new(BufferLogger::new(filter)),
+        #[cfg(unix)]
+        "journald" => Box::new(JournaldLogger::new(filter)),
+        #[cfg(windows)]
+        "eventlog" => match EventLogLogger::new(filter) {
+            Ok(logger) => Box::new(logger),
+            Err(filter) => {
+                eprintln!("Failed to open the Event Log, logging to stdout");
+                Box::new(filter)
+            }
+        },
+        "text" => Box::new(filter),
+        other => {
+            eprintln!(
+                "LOG_FORMAT {} is not available here, logging to stdout",
+                other
+            );
+            Box::new(filter)
+        }
+    };
+    (logger, max_level)
+}
+
+/// A logger that writes one JSON object per line, for log collectors such as those of Kubernetes.
+///
+/// The levels are configured like the text logger, from the "RUST_LOG" environment variable.
+pub struct JsonLogger {
+    filter: SimpleLogger,
+}
+
+impl JsonLogger {
+    /// Creates a JSON logger with the levels of the given text logger.
+    pub fn new(filter: SimpleLogger) -> JsonLogger {
+        JsonLogger { filter }
+    }
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format_record(record, &Local::now().to_rfc3339());
+        let _ = writeln!(std::io::stdout().lock(), "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// A logger that keeps the most recent lines in memory instead of writing them out, see `recent_lines`.
+///
+/// The levels are configured like the text logger, from the "RUST_LOG" environment variable.
+pub struct BufferLogger {
+    filter: SimpleLogger,
+}
+
+impl BufferLogger {
+    /// Creates a buffer logger with the levels of the given text logger.
+    pub fn new(filter: SimpleLogger) -> BufferLogger {
+        BufferLogger { filter }
+    }
+}
+
+impl Log for BufferLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "{} {:<5} {}",
+            Local::now().format("%H:%M:%S"),
+            record.level(),
+            record.args()
+        );
+        let mut buffer = BUFFER.lock().unwrap_or_else(|e| e.into_inner());
+        if buffer.len() == BUFFER_LINES {
+            buffer.pop_front();
+        }
+        buffer.push_back(line);
+    }
+
+    fn flush(&self) {}
+}
+
+/// Returns the most recent lines logged by the `BufferLogger`, oldest first.
+///
+/// # Arguments
+///
+/// * `count`: A `usize` that specifies the maximum number of lines.
+///
+/// # Returns
+///
+/// * A `Vec<String>` that holds the lines.
+pub fn recent_lines(count: usize) -> Vec<String> {
+    let buffer = BUFFER.lock().unwrap_or_else(|e| e.into_inner());
+    buffer
+        .iter()
+        .skip(buffer.len().saturating_sub(count))
+        .cloned()
+        .collect()
+}
+
+/// A logger that sends structured entries to systemd-journald over its native protocol.
+///
+/// Besides the message, every entry carries its "PRIORITY" (see `journal_priority`), the "SYSLOG_IDENTIFIER"
+/// "dnschecker" and the source of the record, so `journalctl -p warning` or `journalctl DNSCHECKER_TARGET=…` work.
+/// Entries the journal does not accept, e.g. because it is not running, are written to stdout as text.
+#[cfg(unix)]
+pub struct JournaldLogger {
+    filter: SimpleLogger,
+    socket: Option<std::os::unix::net::UnixDatagram>,
+}
+
+#[cfg(unix)]
+impl JournaldLogger {
+    /// Creates a journald logger with the levels of the given text logger.
+    pub fn new(filter: SimpleLogger) -> JournaldLogger {
+        let socket = std::os::unix::net::UnixDatagram::unbound().ok();
+        JournaldLogger { filter, socket }
+    }
+}
+
+#[cfg(unix)]
+impl Log for JournaldLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let sent = self.socket.as_ref().is_some_and(|socket| {
+            socket
+                .send_to(&journal_entry(record), JOURNALD_SOCKET)
+                .is_ok()
+        });
+        if !sent {
+            self.filter.log(record);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Maps a log level to a syslog priority, as used by the "PRIORITY" field of the journal.
+#[cfg(unix)]
+fn journal_priority(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Encodes a log record as an entry of the journald native protocol.
+///
+/// Every field is a "NAME=value" line; values with a newline are written as the name, a newline,
+/// the length of the value as a little-endian 64-bit integer and the value.
+///
+/// # Arguments
+///
+/// * `record`: A `&log::Record` that holds the log record.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` that holds the datagram.
+#[cfg(unix)]
+fn journal_entry(record: &Record) -> Vec<u8> {
+    let mut fields = vec![
+        ("MESSAGE", record.args().to_string()),
+        ("PRIORITY", journal_priority(record.level()).to_string()),
+        ("SYSLOG_IDENTIFIER", "dnschecker".to_string()),
+        ("DNSCHECKER_TARGET", record.target().to_string()),
+    ];
+    if let Some(file) = record.file() {
+        fields.push(("CODE_FILE", file.to_string()));
+    }
+    if let Some(line) = record.line() {
+        fields.push(("CODE_LINE", line.to_string()));
+    }
+    let mut entry = Vec::new();
+    for (name, value) in fields {
+        entry.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    }
+    entry
+}
+
+/// A logger that writes warnings and errors to the Windows Event Log, in addition to the text logger.
+///
+/// The entries are reported with the source "dnschecker"; every record is still written to stdout.
+#[cfg(windows)]
+pub struct EventLogLogger {
+    filter: SimpleLogger,
+    /// The handle returned by "RegisterEventSourceW", kept open for the lifetime of the process.
+    handle: usize,
+}
+
+#[cfg(windows)]
+mod eventlog {
+    use std::ffi::c_void;
+
+    pub const EVENTLOG_ERROR_TYPE: u16 = 0x0001;
+    pub const EVENTLOG_WARNING_TYPE: u16 = 0x0002;
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        pub fn RegisterEventSourceW(server: *const u16, source: *const u16) -> *mut c_void;
+        pub fn ReportEventW(
+            handle: *mut c_void,
+            event_type: u16,
+            category: u16,
+            event_id: u32,
+            sid: *mut c_void,
+            num_strings: u16,
+            data_size: u32,
+            strings: *const *const u16,
+            data: *mut c_void,
+        ) -> i32;
+    }
+
+    /// Encodes a string as a null-terminated UTF-16 string.
+    pub fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+}
+
+#[cfg(windows)]
+impl EventLogLogger {
+    /// Registers the "dnschecker" event source.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<EventLogLogger, SimpleLogger>` that holds the logger, or the text logger back if the source cannot be registered.
+    pub fn new(filter: SimpleLogger) -> Result<EventLogLogger, SimpleLogger> {
+        let source = eventlog::wide("dnschecker");
+        // SAFETY: the source is a valid null-terminated UTF-16 string; a null server means the local computer
+        let handle = unsafe { eventlog::RegisterEventSourceW(std::ptr::null(), source.as_ptr()) };
+        if handle.is_null() {
+            return Err(filter);
+        }
+        Ok(EventLogLogger {
+            filter,
+            handle: handle as usize,
+        })
+    }
+}
+
+#[cfg(windows)]
+impl Log for EventLogLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        self.filter.log(record);
+        let event_type = match record.level() {
+            Level::Error => eventlog::EVENTLOG_ERROR_TYPE,
+            Level::Warn => eventlog::EVENTLOG_WARNING_TYPE,
+            _ => return,
+        };
+        let message = eventlog::wide(&record.args().to_string());
+        let strings = [message.as_ptr()];
+        // SAFETY: the handle stays registered for the lifetime of the process and the string is null-terminated
+        unsafe {
+            eventlog::ReportEventW(
+                self.handle as *mut std::ffi::c_void,
+                event_type,
+                0,
+                0,
+                std::ptr::null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                std::ptr::null_mut(),
+            );
+        }
+    }
+
+    fn flush(&self) {
+        self.filter.flush();
+    }
+}
+
+/// Formats a log record as a JSON object.
+///
+/// # Arguments
+///
+/// * `record`: A `&log::Record` that holds the log record.
+/// * `timestamp`: A `&str` that holds the time of the record.
+///
+/// # Returns
+///
+/// * A `String` that holds the JSON object with the "timestamp", "level", "target" and "message" of the record.
+fn format_record(record: &Record, timestamp: &str) -> String {
+    json!({
+        "timestamp": timestamp,
+        "level": record.level().to_string(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_journal_entry() {
+        let record = Record::builder()
+            .args(format_args!("Alarm sent"))
+            .level(log::Level::Warn)
+            .target("dnschecker::alarm")
+            .build();
+        assert_eq!(
+            journal_entry(&record),
+            b"MESSAGE=Alarm sent\nPRIORITY=4\nSYSLOG_IDENTIFIER=dnschecker\nDNSCHECKER_TARGET=dnschecker::alarm\n"
+        );
+
+        // Assert that multi-line messages are length-prefixed
+        let record = Record::builder()
+            .args(format_args!("State dump:\nTime"))
+            .level(log::Level::Info)
+            .build();
+        let entry = journal_entry(&record);
+        assert!(entry.starts_with(b"MESSAGE\n\x10\0\0\0\0\0\0\0State dump:\nTime\nPRIORITY=6\n"));
+    }
+
+    #[test]
+    fn test_format_record() {
+        let record = Record::builder()
+            .args(format_args!("Verifying \"IPs\""))
+            .level(log::Level::Info)
+            .target("dnschecker")
+            .build();
+
+        let line = format_record(&record, "2024-05-01T12:00:00+02:00");
+
+        assert_eq!(
+            line,
+            r#"{"level":"INFO","message":"Verifying \"IPs\"","target":"dnschecker","timestamp":"2024-05-01T12:00:00+02:00"}"#
+        );
+    }
+
+    #[test]
+    fn test_buffer_logger() {
+        let logger = BufferLogger::new(SimpleLogger::new().with_level(LevelFilter::Info));
+        for number in 0..BUFFER_LINES + 2 {
+            logger.log(
+                &Record::builder()
+                    .args(format_args!("Line {}", number))
+                    .level(log::Level::Info)
+                    .build(),
+            );
+        }
+        logger.log(
+            &Record::builder()
+                .args(format_args!("Hidden"))
+                .level(log::Level::Debug)
+                .build(),
+        );
+
+        let lines = recent_lines(2);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].ends_with(&format!("INFO  Line {}", BUFFER_LINES + 1)));
+        assert_eq!(recent_lines(usize::MAX).len(), BUFFER_LINES);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::dns;
+use crate::events;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::vars::{display_hostname, split_list};
+use std::collections::BTreeMap;
+use std::env;
+use trust_dns_resolver::proto::rr::RecordType;
+
+/// Detects drift of the mail-related TXT records (SPF, DMARC and DKIM).
+///
+/// This function retrieves the comma-separated list of names in the "MAIL_RECORDS" environment variable,
+/// e.g. "example.com,_dmarc.example.com,mail._domainkey.example.com".
+/// If it is not set, the mail record check is disabled and the function returns immediately.
+///
+/// It looks up the TXT records of every name and keeps the relevant ones (see `relevant_records`).
+/// They are compared with the records stored in the mail records file ("MAIL_RECORDS_FILE", defaulting to "mail_records" in the state directory),
+/// which hold the expected values: the records seen on the first check, updated after every reported change.
+/// A "mail_records" message is sent for every name whose records drifted.
+/// Names whose lookup fails are skipped and keep their expected values.
+pub fn check_mail_records() {
+    let names = match env::var("MAIL_RECORDS") {
+        Ok(names) => split_list(&names),
+        Err(_) => return,
+    };
+    let mail_records_file = state_path("MAIL_RECORDS_FILE", "mail_records");
+    let previous = read_state(&mail_records_file);
+    let mut expected: BTreeMap<String, Vec<String>> = if previous.is_empty() {
+        BTreeMap::new()
+    } else {
+        match serde_json::from_str(&previous) {
+            Ok(expected) => expected,
+            Err(e) => {
+                log::warn!("Ignoring invalid {}: {}", mail_records_file, e);
+                BTreeMap::new()
+            }
+        }
+    };
+
+    let mut changed = false;
+    for name in &names {
+        let records = match dns::lookup_records(name, RecordType::TXT) {
+            Ok(records) => relevant_records(name, &records),
+            Err(e) => {
+                log::warn!("{}", e);
+                continue;
+            }
+        };
+        match expected.get(name) {
+            Some(previous) if *previous == records => continue,
+            Some(previous) => {
+                let text = drift_message(name, previous, &records);
+                log::info!("{}", text);
+                if !events::alert(&Message::new("mail_records", &text).with_field("name", name)) {
+                    log::warn!("Failed to send mail record message");
+                    // Keep the expected records so the drift is reported again on the next check
+                    continue;
+                }
+            }
+            None => log::info!("Tracking {} mail records of {}", records.len(), name),
+        }
+        expected.insert(name.clone(), records);
+        changed = true;
+    }
+    if changed {
+        match serde_json::to_string(&expected) {
+            Ok(state) => {
+                write_state(&mail_records_file, &state);
+            }
+            Err(e) => log::warn!("Failed to store mail records: {}", e),
+        }
+    }
+}
+
+/// Selects the TXT records of a name that are relevant for mail.
+///
+/// DMARC ("_dmarc.") and DKIM ("._domainkey.") names are dedicated to mail, so all their records are kept.
+/// Other names usually carry unrelated TXT records, e.g. domain verifications, so only the SPF record ("v=spf1") is kept.
+///
+/// # Arguments
+///
+/// * `name`: A `&str` that specifies the name the records belong to.
+/// * `records`: A `&[String]` that holds the TXT records of the name.
+///
+/// # Returns
+///
+/// * A `Vec<String>` that holds the relevant records.
+fn relevant_records(name: &str, records: &[String]) -> Vec<String> {
+    let name = name.to_lowercase();
+    if name.starts_with("_dmarc.") || name.contains("._domainkey.") {
+        return records.to_vec();
+    }
+    records
+        .iter()
+        .filter(|record| record.to_lowercase().starts_with("v=spf1"))
+        .cloned()
+        .collect()
+}
+
+/// Builds the text of the mail record message.
+///
+/// # Arguments
+///
+/// * `name`: A `&str` that specifies the name whose records drifted.
+/// * `previous`: A `&[String]` that holds the expected records.
+/// * `records`: A `&[String]` that holds the published records.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message.
+fn drift_message(name: &str, previous: &[String], records: &[String]) -> String {
+    let mut lines = vec![format!(
+        "Mail records of {} changed!",
+        display_hostname(name)
+    )];
+    lines.extend(
+        previous
+            .iter()
+            .filter(|record| !records.contains(record))
+            .map(|record| format!("Removed: {}", record)),
+    );
+    lines.extend(
+        records
+            .iter()
+            .filter(|record| !previous.contains(record))
+            .map(|record| format!("Added: {}", record)),
+    );
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(records: &[&str]) -> Vec<String> {
+        records.iter().map(|record| record.to_string()).collect()
+    }
+
+    #[test]
+    fn test_relevant_records() {
+        let records = list(&["google-site-verification=abc", "v=spf1 mx -all"]);
+        assert_eq!(
+            relevant_records("example.com", &records),
+            ["v=spf1 mx -all"]
+        );
+
+        let records = list(&["v=DMARC1; p=reject"]);
+        assert_eq!(relevant_records("_DMARC.example.com", &records), records);
+        assert_eq!(
+            relevant_records("mail._domainkey.example.com", &records),
+            records
+        );
+    }
+
+    #[test]
+    fn test_drift_message() {
+        let result = drift_message(
+            "_dmarc.example.com",
+            &list(&["v=DMARC1; p=reject"]),
+            &list(&["v=DMARC1; p=none"]),
+        );
+        assert_eq!(
+            result,
+            "Mail records of _dmarc.example.com changed!\nRemoved: v=DMARC1; p=reject\nAdded: v=DMARC1; p=none"
+        );
+    }
+}
@@ -1,65 +1,733 @@
+use dnschecker::vars::*;
+use dnschecker::{
+    agent, alarm, cache, chaos, check, cluster, config, crypt, dependencies, digest, dns, dump,
+    events, history, kubernetes, leader, logging, metrics, netlink, notify, passive, redact,
+    replay, secrets, server, simulate, state, stats, store, telegram,
+};
 use shuteye::sleep;
-use std::time::Duration;
-mod api;
-mod dns;
-mod telegram;
-use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::consts::{SIGINT, SIGTERM, SIGUSR1};
 use signal_hook::iterator::Signals;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-
-mod vars;
-use crate::vars::*;
+use std::time::{Duration, Instant};
 
 fn main() {
     let sig_received = Arc::new(AtomicBool::new(false));
     let r = sig_received.clone();
+    let shared = Arc::new(server::Shared::default());
+    let s = shared.clone();
 
-    // Set the SIGTERM and SIGINT handler
-    let mut signals = Signals::new(&[SIGTERM, SIGINT]).unwrap();
+    // Set the SIGTERM and SIGINT handler, and the SIGUSR1 handler that requests a state dump
+    let mut signals = Signals::new([SIGTERM, SIGINT, SIGUSR1]).unwrap();
     std::thread::spawn(move || {
-        for _ in signals.forever() {
+        for signal in signals.forever() {
+            if signal == SIGUSR1 {
+                s.dump.store(true, Ordering::SeqCst);
+                continue;
+            }
             r.store(true, Ordering::SeqCst);
+            s.health.set_ready(false);
+            start_shutdown_timer();
         }
     });
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(at) = args.iter().position(|arg| arg == "--profile") {
+        // The profile of the config file, the same as setting "CONFIG_PROFILE"
+        match args.get(at + 1) {
+            Some(profile) => std::env::set_var("CONFIG_PROFILE", profile),
+            None => {
+                eprintln!("--profile expects the name of a profile of the config file");
+                std::process::exit(2);
+            }
+        }
+        args.drain(at..at + 2);
+    }
+    // Apply the config before the logger is set up, so it can set "RUST_LOG" and "LOG_FORMAT"
+    let config = config::load_config();
+    kubernetes::apply_defaults();
+    if args.iter().any(|arg| arg == "--no-cache") {
+        // Diagnostics should see what the resolvers answer now, not what the cache remembers
+        args.retain(|arg| arg != "--no-cache");
+        cache::DNS_CACHE.set_bypass(true);
+    }
+    if args.first().map(String::as_str) == Some("tui") {
+        // The TUI shows the log itself, writing it to the terminal would garble the screen
+        std::env::set_var("LOG_FORMAT", "buffer");
+    }
     init();
+    if args.first().map(String::as_str) == Some("config") {
+        std::process::exit(config_command(&args[1..], config));
+    }
+    if let Err(e) = config.and_then(|_| store::open_store()) {
+        log::error!("{}", e);
+        std::process::exit(1);
+    }
+    if !args.is_empty() {
+        std::process::exit(run_command(&args));
+    }
+    if let Ok(url) = std::env::var("AGGREGATOR_URL") {
+        server::start_server(shared.clone());
+        shared.health.set_ready(true);
+        agent::run_agent(&url, CHECK_INTERVAL, &sig_received, &|| {
+            shared.health.tick()
+        });
+        return;
+    }
+    let peers = cluster::peers().unwrap_or_else(|e| {
+        log::error!("{}", e);
+        std::process::exit(1);
+    });
+    if !peers.is_empty() {
+        server::start_server(shared.clone());
+        shared.health.set_ready(true);
+        cluster::run_cluster(&peers, &shared.cluster, &sig_received, &|| {
+            shared.health.tick()
+        });
+        return;
+    }
     let (hostname, token) = verify_env_vars();
-    let mut counter: i32 = 1;
+    let chat_id = telegram::get_chat_id();
+    if !telegram::validate_telegram(&token, &chat_id) {
+        log::error!("Telegram validation failed, alerts will not be delivered");
+    }
+    let checks = check::pipeline(&shared).unwrap_or_else(|e| {
+        log::error!("{}", e);
+        std::process::exit(1);
+    });
+    server::start_server(shared.clone());
+    netlink::start_watcher(shared.clone());
+    let heartbeat_interval = heartbeat_interval();
+    let mut next_check = Instant::now();
+    let mut next_heartbeat = Instant::now() + heartbeat_interval;
+    let digest_interval = digest::digest_interval();
+    let mut next_digest = Instant::now() + digest_interval;
+    let mut last_digest = chrono::Local::now();
+    state::check_state_dir();
+    metrics::METRICS.load(&metrics::metrics_file());
+    dependencies::DEPENDENCIES.load(&dependencies::dependencies_file());
+    events::subscribe(Box::new(HeartbeatLog));
+    let mut last_context: Option<check::Context> = None;
+    let mut last_check: Option<Instant> = None;
+    chaos::announce();
+    dns::prepare_resolver();
+    if let Some(pod) = kubernetes::pod_identity() {
+        log::info!("Running in pod {}", pod);
+    }
     loop {
-        counter = verify_ips(&hostname, &token, counter);
+        shared.health.tick();
+        leader::renew();
+        notify::flush_suppressed();
+        if shared.trigger.swap(false, Ordering::SeqCst) || Instant::now() >= next_check {
+            let context = verify_ips(&hostname, &checks);
+            shared.record_check(&context);
+            events::publish(&events::Event::Checked(&context));
+            last_context = Some(context);
+            last_check = Some(Instant::now());
+            next_check = Instant::now() + CHECK_INTERVAL;
+            if !sig_received.load(Ordering::SeqCst) {
+                shared.health.set_ready(true);
+            }
+        }
+        if !heartbeat_interval.is_zero() && Instant::now() >= next_heartbeat {
+            let (dns_ip, wan_ip) = match &last_context {
+                Some(context) => (context.dns_ip.as_str(), context.wan_ip.as_str()),
+                None => ("", ""),
+            };
+            events::publish(&events::Event::Heartbeat {
+                hostname: &hostname,
+                dns_ip,
+                wan_ip,
+            });
+            next_heartbeat = Instant::now() + heartbeat_interval;
+        }
+        if !digest_interval.is_zero() && Instant::now() >= next_digest {
+            if !digest::send_digest(&hostname, last_digest) {
+                log::warn!("Failed to send digest");
+            }
+            last_digest = chrono::Local::now();
+            next_digest = Instant::now() + digest_interval;
+        }
+        if shared.dump.swap(false, Ordering::SeqCst) {
+            let schedule = dump::Schedule {
+                next_check,
+                next_heartbeat: (!heartbeat_interval.is_zero()).then_some(next_heartbeat),
+                last_check: last_check.map(|last_check| last_check.elapsed()),
+            };
+            dump::write_dump(last_context.as_ref(), &checks, schedule);
+        }
         if sig_received.load(Ordering::SeqCst) {
             break;
         }
+        log::trace!("Sleeping for 1 second");
+        sleep(Duration::new(1, 0));
+    }
+    leader::release();
+}
+
+/// Exits the process if the shutdown does not complete in time.
+///
+/// This function retrieves the time allowed for the shutdown from the "SHUTDOWN_TIMEOUT" environment variable (e.g. "25s"),
+/// defaulting to 25 seconds, which is within the default termination grace period of Kubernetes.
+/// It then starts a thread that exits the process when the time is up,
+/// e.g. when a verification is still waiting for a slow router API.
+fn start_shutdown_timer() {
+    let timeout = std::env::var("SHUTDOWN_TIMEOUT")
+        .ok()
+        .and_then(|value| parse_duration(&value))
+        .unwrap_or(Duration::from_secs(25));
+    log::info!("Shutting down");
+    std::thread::spawn(move || {
+        std::thread::sleep(timeout);
+        log::warn!("Shutdown did not complete within {:?}, exiting", timeout);
+        std::process::exit(1);
+    });
+}
+
+/// The time between two verifications, see `check::CHECK_INTERVAL`.
+const CHECK_INTERVAL: Duration = check::CHECK_INTERVAL;
+
+/// Retrieves the interval of the heartbeat log line.
+///
+/// This function parses the "HEARTBEAT_INTERVAL" environment variable (e.g. "30m" or "1h") using the `parse_duration` function.
+/// If it is not set, it defaults to 30 minutes. If it cannot be parsed, it logs a warning and uses the default.
+/// An interval of "0" disables the heartbeat.
+///
+/// # Returns
+///
+/// * A `Duration` that holds the heartbeat interval.
+fn heartbeat_interval() -> Duration {
+    let default = Duration::from_secs(30 * 60);
+    match std::env::var("HEARTBEAT_INTERVAL") {
+        Ok(value) => parse_duration(&value).unwrap_or_else(|| {
+            log::warn!("Invalid HEARTBEAT_INTERVAL \"{}\", using 30m", value);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+/// Logs the periodic status line on every heartbeat published on the event bus, see `log_heartbeat`.
+struct HeartbeatLog;
+
+impl events::Subscriber for HeartbeatLog {
+    fn name(&self) -> &str {
+        "heartbeat log"
+    }
+
+    fn handles(&self, event: &events::Event) -> bool {
+        matches!(event, events::Event::Heartbeat { .. })
+    }
+
+    fn receive(&self, event: &events::Event) -> bool {
+        if let events::Event::Heartbeat {
+            hostname,
+            dns_ip,
+            wan_ip,
+        } = event
+        {
+            log_heartbeat(hostname, dns_ip, wan_ip);
+        }
+        true
+    }
+}
+
+/// Logs the periodic status line.
+///
+/// This function logs the latest IP addresses and the in-sync percentage from the history.
+/// It also prunes history records older than 30 days using the `history::prune_history` function.
+///
+/// # Arguments
+///
+/// * `hostname` - A string slice that holds the hostname.
+/// * `dns_ip` - A string slice that holds the IP address from the latest DNS lookup.
+/// * `wan_ip` - A string slice that holds the latest WAN IP address.
+fn log_heartbeat(hostname: &str, dns_ip: &str, wan_ip: &str) {
+    let history_file = history::history_file();
+    history::prune_history(&history_file, 30);
+    let records = history::load_history(&history_file);
+    log::info!(
+        "{}",
+        heartbeat_line(hostname, dns_ip, wan_ip, &history::uptime_summary(&records))
+    );
+}
+
+/// Builds the periodic status line.
+///
+/// # Arguments
+///
+/// * `hostname` - A string slice that holds the hostname.
+/// * `dns_ip` - A string slice that holds the IP address from the latest DNS lookup.
+/// * `wan_ip` - A string slice that holds the latest WAN IP address.
+/// * `uptime` - A string slice that holds the in-sync summary.
+///
+/// # Returns
+///
+/// * A `String` that holds the status line.
+fn heartbeat_line(hostname: &str, dns_ip: &str, wan_ip: &str, uptime: &str) -> String {
+    let or_unknown = |ip: &str| {
+        if ip.is_empty() {
+            "unknown".to_string()
+        } else {
+            ip.to_string()
+        }
+    };
+    format!(
+        "Heartbeat: {} resolves to {}, WAN IP is {}, DNS in sync: {}",
+        hostname,
+        or_unknown(dns_ip),
+        or_unknown(wan_ip),
+        uptime
+    )
+}
+
+/// Runs a "config" command.
+///
+/// The "config schema" command prints the JSON Schema of the config file, e.g. for editor completion.
+/// The "config check" command validates the config file in "CONFIG_FILE" and prints the problems found;
+/// if "TELEGRAM_TOKEN" is set, it also verifies the bot and the chat using the `telegram::validate_telegram` function.
+/// The "config keygen" command prints a new key for "CONFIG_KEY".
+/// The "config encrypt <value>" command prints the value encrypted with the key in "CONFIG_KEY" or "CONFIG_KEY_FILE".
+///
+/// # Arguments
+///
+/// * `args` - A slice that holds the command line arguments after "config".
+/// * `loaded` - The result of loading the config file.
+///
+/// # Returns
+///
+/// * An `i32` that holds the exit code of the command.
+fn config_command(args: &[String], loaded: Result<(), String>) -> i32 {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match (args.as_slice(), loaded) {
+        (["schema"], _) => {
+            println!("{:#}", config::schema());
+            0
+        }
+        (["check"], Ok(())) => {
+            println!("Config is valid");
+            match std::env::var("TELEGRAM_TOKEN") {
+                Ok(token) if !token.is_empty() => {
+                    if telegram::validate_telegram(&token, &telegram::get_chat_id()) {
+                        println!("Telegram bot and chat are valid");
+                        0
+                    } else {
+                        eprintln!("Telegram validation failed, alerts will not be delivered");
+                        1
+                    }
+                }
+                _ => 0,
+            }
+        }
+        (["check"], Err(e)) => {
+            eprintln!("{}", redact::redact(&e));
+            1
+        }
+        (["keygen"], _) => {
+            println!("{}", crypt::generate_key());
+            0
+        }
+        (["encrypt", value], _) => match crypt::config_key() {
+            Ok(key) => {
+                println!("{}", crypt::encrypt(&key, value));
+                0
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        },
+        _ => {
+            eprintln!("Usage: dnschecker config [schema | check | keygen | encrypt <value>]");
+            2
+        }
+    }
+}
+
+/// Stores or deletes a secret in the OS keyring and prints the outcome.
+///
+/// # Arguments
+///
+/// * `name` - The keyring entry name of the setting, e.g. "telegram-token".
+/// * `value` - The secret, or `None` to delete it.
+///
+/// # Returns
+///
+/// * An `i32` that holds the exit code of the command.
+fn secret_command(name: &str, value: Option<&str>) -> i32 {
+    match secrets::store_secret(name, value) {
+        Ok(()) if value.is_some() => {
+            println!("Stored {} in the keyring", name);
+            0
+        }
+        Ok(()) => {
+            println!("Deleted {} from the keyring", name);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// Shows the state of the checker in the system tray until it is closed from its menu.
+///
+/// This function creates a checker from the environment and the config already applied,
+/// and runs it with the `tray::run_tray` function. Tray support is only part of builds with the "tray" feature.
+///
+/// # Returns
+///
+/// * An `i32` that holds the exit code of the command.
+fn tray_command() -> i32 {
+    #[cfg(feature = "tray")]
+    {
+        let result = dnschecker::Checker::new(config::Config::default())
+            .and_then(dnschecker::tray::run_tray);
+        match result {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("{}", redact::redact(&e));
+                1
+            }
+        }
+    }
+    #[cfg(not(feature = "tray"))]
+    {
+        eprintln!(
+            "Tray support is not part of this build, build with --features tray to enable it"
+        );
+        1
+    }
+}
+
+/// Shows the state of the checker in the terminal until "q" is pressed.
+///
+/// This function creates a checker from the environment and the config already applied,
+/// and runs it with the `tui::run_tui` function. The TUI is only part of builds with the "tui" feature.
+///
+/// # Returns
+///
+/// * An `i32` that holds the exit code of the command.
+fn tui_command() -> i32 {
+    #[cfg(feature = "tui")]
+    {
+        let result =
+            dnschecker::Checker::new(config::Config::default()).and_then(dnschecker::tui::run_tui);
+        match result {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("{}", redact::redact(&e));
+                1
+            }
+        }
+    }
+    #[cfg(not(feature = "tui"))]
+    {
+        eprintln!("The TUI is not part of this build, build with --features tui to enable it");
+        1
+    }
+}
+
+/// Injects a synthetic check result into the comparison to rehearse the handling of alerts.
+///
+/// This function takes the name of the scenario as an argument: "mismatch", "recovery" or "nxdomain".
+/// It verifies the environment like the checker loop using the `verify_env_vars` function,
+/// runs the scenario using the `simulate::simulate` function and prints the result and the alarm state.
+///
+/// # Arguments
+///
+/// * `scenario` - A `&str` that specifies the name of the scenario.
+///
+/// # Returns
+///
+/// * An `i32` that holds the exit code of the command.
+fn simulate_command(scenario: &str) -> i32 {
+    let scenario = match simulate::Scenario::parse(scenario) {
+        Some(scenario) => scenario,
+        None => {
+            eprintln!(
+                "Unknown scenario {}, expected mismatch, recovery or nxdomain",
+                scenario
+            );
+            return 2;
+        }
+    };
+    let (hostname, _) = verify_env_vars();
+    let shared = std::sync::Arc::new(server::Shared::default());
+    match simulate::simulate(&hostname, scenario, &shared) {
+        Ok(context) => {
+            let in_sync = match context.in_sync {
+                Some(true) => "in sync",
+                Some(false) => "mismatch",
+                None => "not compared",
+            };
+            println!(
+                "DNS: {}, WAN: {}, result: {}, severity: {}",
+                context.dns_ips.join(", "),
+                context.wan_ip,
+                in_sync,
+                notify::severity(&hostname)
+            );
+            for line in alarm::status() {
+                println!("{}", line);
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", redact::redact(&e));
+            1
+        }
+    }
+}
+
+/// Runs a one-off command instead of the checker loop.
+///
+/// This function takes the command line arguments as an argument.
+/// The "history" command prints the percentage of checks where DNS was in sync over the last 24 hours, 7 days and 30 days.
+/// The "history export" command prints the history as CSV or JSON, see `export_history_command`.
+/// The "status" command prints the health of the dependencies stored by the running checker, see `dependencies::status_lines`;
+/// it exits with 1 if a dependency is failing.
+/// The "stats" command prints statistics for ISP complaints, see `stats_command`.
+/// The "reset" command clears the alarm state; with "--notify" a "manually acknowledged" message is sent.
+/// The "secret set <name>" command stores a secret read from stdin in the OS keyring, "secret delete <name>" removes it.
+/// The "tray" command shows the state in the system tray, see `tray_command`.
+/// The "tui" command shows the state in the terminal, see `tui_command`.
+/// The "simulate <scenario>" command injects a synthetic check result, see `simulate_command`.
+/// The "replay <file>" command re-runs the decision logic against a recording, see `replay::replay`.
+///
+/// # Arguments
+///
+/// * `args` - A slice that holds the command line arguments, without the program name.
+///
+/// # Returns
+///
+/// * An `i32` that holds the exit code of the command.
+fn run_command(args: &[String]) -> i32 {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["history"] => {
+            let records = history::load_history(&history::history_file());
+            println!("DNS in sync: {}", history::uptime_summary(&records));
+            0
+        }
+        ["history", "export", options @ ..] => export_history_command(options),
+        ["stats", options @ ..] => stats_command(options),
+        ["status"] => match dependencies::load_dependencies(&dependencies::dependencies_file()) {
+            Ok(entries) => {
+                for line in dependencies::status_lines(&entries) {
+                    println!("{}", redact::redact(&line));
+                }
+                i32::from(entries.values().any(|health| health.is_failing()))
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        },
+        ["cluster"] => match cluster::peers() {
+            Ok(peers) if !peers.is_empty() => {
+                let view = cluster::ClusterView::default();
+                cluster::poll(&peers, &view);
+                let statuses = view.snapshot();
+                for line in cluster::dashboard_lines(&statuses) {
+                    println!("{}", redact::redact(&line));
+                }
+                i32::from(statuses.iter().any(|status| status.state() != "in_sync"))
+            }
+            Ok(_) => {
+                eprintln!("CLUSTER_PEERS is not set");
+                1
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        },
+        ["reset"] | ["reset", "--notify"] => match alarm::force_reset(args.len() == 2) {
+            Ok(message) => {
+                println!("{}", message);
+                0
+            }
+            Err(message) => {
+                eprintln!("{}", redact::redact(&message));
+                1
+            }
+        },
+        ["secret", "set", name] => {
+            let mut value = String::new();
+            eprintln!("Enter the value of {} and press Enter:", name);
+            if let Err(e) = std::io::stdin().read_line(&mut value) {
+                eprintln!("Failed to read the value: {}", e);
+                return 1;
+            }
+            secret_command(name, Some(value.trim_end_matches(['\r', '\n'])))
+        }
+        ["secret", "delete", name] => secret_command(name, None),
+        ["tray"] => tray_command(),
+        ["tui"] => tui_command(),
+        ["simulate", scenario] => simulate_command(scenario),
+        ["replay", path] => {
+            match replay::load_cycles(path).and_then(|cycles| replay::replay(&cycles)) {
+                Ok(lines) => {
+                    for line in lines {
+                        println!("{}", line);
+                    }
+                    0
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    1
+                }
+            }
+        }
+        _ => {
+            log::error!("Unknown command: {}", args.join(" "));
+            eprintln!(
+                "Usage: dnschecker [--no-cache] [--profile <name>] [status | stats [--since 30d] | history | history export [--format csv|json] [--since 30d] [--changes] | cluster | reset [--notify] | config [schema | check | keygen | encrypt <value>] | secret [set | delete] <name> | tray | tui | simulate [mismatch | recovery | nxdomain] | replay <file>]"
+            );
+            2
+        }
+    }
+}
+
+/// Prints the check history for spreadsheet analysis.
+///
+/// This function takes the options of the "history export" command as an argument.
+/// "--format" selects CSV (the default) or JSON, "--since" limits the export to a period such as "30d"
+/// (all history by default), and "--changes" exports only the records where an IP address changed.
+///
+/// # Arguments
+///
+/// * `options` - A slice that holds the options of the command.
+///
+/// # Returns
+///
+/// * An `i32` that holds the exit code of the command.
+fn export_history_command(options: &[&str]) -> i32 {
+    let mut format = "csv";
+    let mut since = chrono::DateTime::<chrono::Local>::MIN_UTC.into();
+    let mut changes_only = false;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match *option {
+            "--format" => format = options.next().copied().unwrap_or_default(),
+            "--since" => match parse_since(options.next().copied().unwrap_or_default()) {
+                Ok(start) => since = start,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return 2;
+                }
+            },
+            "--changes" => changes_only = true,
+            _ => {
+                eprintln!("Unknown option: {}", option);
+                return 2;
+            }
+        }
+    }
+
+    let records = history::load_history(&history::history_file());
+    match history::export_history(&records, format, since, changes_only) {
+        Ok(exported) => {
+            print!("{}", exported);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", redact::redact(&e));
+            2
+        }
+    }
+}
+
+/// Prints statistics aggregated from the history and the health of the dependencies.
+///
+/// This function takes the options of the "stats" command as an argument.
+/// "--since" limits the statistics to a period such as "30d" (all history by default).
+/// It prints the average latency of each check, the failure rate of each dependency (since start, not limited by "--since"),
+/// the number of WAN IP address changes per month and the longest outage, see `stats::stats_lines`.
+///
+/// # Arguments
+///
+/// * `options` - A slice that holds the options of the command.
+///
+/// # Returns
+///
+/// * An `i32` that holds the exit code of the command: 2 if an option is invalid.
+fn stats_command(options: &[&str]) -> i32 {
+    let since = match options {
+        [] => chrono::DateTime::<chrono::Local>::MIN_UTC.into(),
+        ["--since", value] => match parse_since(value) {
+            Ok(start) => start,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 2;
+            }
+        },
+        _ => {
+            eprintln!("Unknown options: {}", options.join(" "));
+            return 2;
+        }
+    };
+    let records = history::load_history(&history::history_file());
+    let dependencies = dependencies::load_dependencies(&dependencies::dependencies_file())
+        .unwrap_or_else(|e| {
+            log::warn!("{}", e);
+            Default::default()
+        });
+    for line in stats::stats_lines(&stats::statistics(&records, since), &dependencies) {
+        println!("{}", redact::redact(&line));
     }
+    0
+}
+
+/// Parses the value of the "--since" option of the "stats" and "history export" commands.
+///
+/// # Arguments
+///
+/// * `value` - A `&str` that holds a period such as "30d".
+///
+/// # Returns
+///
+/// * A `Result<DateTime<Local>, String>` that holds the start of the period ending now,
+///   or an error if the value is not a duration or reaches back beyond the dates chrono can represent.
+fn parse_since(value: &str) -> Result<chrono::DateTime<chrono::Local>, String> {
+    let period =
+        parse_duration(value).ok_or("--since expects a duration such as 30d, 12h or 15m")?;
+    chrono::Duration::from_std(period)
+        .ok()
+        .and_then(|period| chrono::Local::now().checked_sub_signed(period))
+        .ok_or_else(|| format!("--since reaches too far back: {}", value))
 }
 
 /// Verifies the presence of certain environment variables and retrieves their values.
 ///
 /// This function checks if the following environment variables are set:
 /// "TELEGRAM_TOKEN", "DNS_HOSTNAME", "API_KEY", "API_SECRET", "URL", "CHAT_ID", "INTERFACE".
+/// In passive mode (see `passive::is_passive`) the router API variables "API_KEY", "API_SECRET", "URL" and "INTERFACE" are not needed.
 ///
 /// It does this by calling the `get_vars_from_env` function with a vector of these variable names.
 /// If any of these variables are not set (indicated by `get_vars_from_env` returning true),
 /// it logs an error message and exits the program with a status code of 1.
 ///
-/// If all variables are set, it retrieves the values of "TELEGRAM_TOKEN" and "DNS_HOSTNAME"
-/// using the `get_var_from_env` function and returns them.
+/// If all variables are set, it expands "DNS_HOSTNAME" set to "@" to the zone in "DNS_ZONE"
+/// and validates "CHAT_ID", "DNS_HOSTNAME" and "URL" using the
+/// `validate_chat_id`, `normalize_hostname` and `validate_url` functions.
+/// If any of them is invalid, it logs an error message and exits the program with a status code of 1.
+/// The normalized values are written back to the environment.
+///
+/// It then retrieves the values of "TELEGRAM_TOKEN" and "DNS_HOSTNAME" and returns them.
 ///
 /// # Returns
 ///
 /// * `hostname`: The value of the "DNS_HOSTNAME" environment variable.
 /// * `token`: The value of the "TELEGRAM_TOKEN" environment variable.
 fn verify_env_vars() -> (String, String) {
-    // Define the environment variables to check
-    let envvars: Vec<&str> = vec![
-        "TELEGRAM_TOKEN",
-        "DNS_HOSTNAME",
-        "API_KEY",
-        "API_SECRET",
-        "URL",
-        "CHAT_ID",
-        "INTERFACE",
-    ];
+    // Define the environment variables to check, the router API is not used in passive mode
+    let passive = passive::is_passive();
+    let mut envvars: Vec<&str> = vec!["TELEGRAM_TOKEN", "DNS_HOSTNAME", "CHAT_ID"];
+    if !passive {
+        envvars.extend(["API_KEY", "API_SECRET", "URL", "INTERFACE"]);
+    }
 
     // Check if the environment variables are set
     let error: bool = get_vars_from_env(envvars);
@@ -68,6 +736,36 @@ fn verify_env_vars() -> (String, String) {
         std::process::exit(1);
     }
 
+    // Validate and normalize "CHAT_ID", "DNS_HOSTNAME" and "URL"
+    let mut validated = vec![
+        (
+            "CHAT_ID",
+            validate_chat_id(&get_var_from_env("CHAT_ID").unwrap_or_default()),
+        ),
+        (
+            "DNS_HOSTNAME",
+            normalize_hostname(&expand_apex(
+                &get_var_from_env("DNS_HOSTNAME").unwrap_or_default(),
+                &std::env::var("DNS_ZONE").unwrap_or_default(),
+            )),
+        ),
+    ];
+    if !passive {
+        validated.push((
+            "URL",
+            validate_url(&get_var_from_env("URL").unwrap_or_default()),
+        ));
+    }
+    for (name, result) in validated {
+        match result {
+            Ok(normalized) => std::env::set_var(name, normalized),
+            Err(e) => {
+                log::error!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
     // Retrieve the values of "TELEGRAM_TOKEN" and "DNS_HOSTNAME"
     let token: String =
         get_var_from_env("TELEGRAM_TOKEN").unwrap_or_else(|_| std::process::exit(1));
@@ -78,84 +776,24 @@ fn verify_env_vars() -> (String, String) {
     (hostname, token)
 }
 
-/// Verifies the IP addresses associated with a given hostname and a token.
-///
-/// This function first resolves the hostname to an IP address using the `dns::resolve_hostname` function.
-/// It then retrieves the WAN IP address using the `api::get_api` function.
+/// Verifies the IP addresses associated with a given hostname.
 ///
-/// If either the resolved IP address or the WAN IP address is empty (checked using the `is_empty` method),
-/// it logs a warning and skips the comparison.
-///
-/// If both IP addresses are not empty and they don't match (checked using the `!=` operator),
-/// it logs that the IP address is different. If the token is not empty,
-/// it attempts to send a Telegram message with the `telegram::send_telegram` function.
-///
-/// If the IP addresses match, it attempts to send a successful update Telegram message.
-///
-/// The function then sleeps for 10 seconds using the `thread::sleep` function before incrementing a counter.
-///
-/// If the counter reaches 180 (indicating 30 minutes have passed), it resets the counter to 1 and logs that 30 minutes have passed.
+/// This function runs the check pipeline (see `check::pipeline`) for the hostname using the `check::run_pipeline` function.
+/// By default, the pipeline resolves the hostname, retrieves the WAN IP address, compares them and raises or clears the alarm,
+/// and runs the failover, wildcard and IPv6 prefix checks. The caller publishes the result on the event bus,
+/// which records it in the history and the metrics.
 ///
 /// # Arguments
 ///
 /// * `hostname` - A string slice that holds the hostname.
-/// * `token` - A string slice that holds the token.
-/// * `counter` - A 32-bit integer that holds the counter.
+/// * `checks` - A slice of the checks to run, in order.
 ///
 /// # Returns
 ///
-/// * A 32-bit integer that holds the updated counter.
-fn verify_ips(hostname: &String, token: &String, counter: i32) -> i32 {
-    // Log that IPs are being verified if counter is 0
-    if counter % 10 == 0 {
-        log::info!("Verifying IPs");
-
-        // Resolve the hostname to an IP address
-        let ip_address = dns::resolve_hostname(hostname);
-        if ip_address.is_empty() {
-            log::warn!("Failed to get IP address");
-        }
-
-        // Retrieve the WAN IP address
-        let wan_ip = api::get_api();
-        if wan_ip.is_empty() {
-            log::warn!("Failed to get WAN IP address");
-        }
-
-        // Log the IP addresses
-        log::debug!(
-            "The IP address of {} is: {}, WAN IP address is: {}",
-            hostname,
-            ip_address,
-            wan_ip
-        );
-
-        // Compare the IP addresses
-        if ip_address.is_empty() || wan_ip.is_empty() {
-            log::warn!("Since one of the IP addresses is empty, skipping comparison");
-        } else if ip_address != wan_ip {
-            log::info!("IP address is different");
-            if !token.is_empty() && !telegram::send_telegram(token, &wan_ip, &ip_address) {
-                log::warn!("Failed to send telegram");
-            } else {
-                log::info!("Telegram sent");
-            }
-        } else if !telegram::send_telegram(token, &ip_address, &wan_ip) {
-            log::warn!("Failed to send successful update telegram");
-        }
-    }
-    // Sleep for 10 seconds
-    log::debug!("Sleeping for 1 seconds");
-    sleep(Duration::new(1, 0));
-    // Increment the counter
-    let counter: i32 = counter + 1;
-    if counter >= 1800 {
-        log::info!("30 minutes passed");
-        return 1;
-    }
-
-    // Return the updated counter
-    counter
+/// * A `check::Context` that holds the results of the checks, e.g. the IP address from DNS and the WAN IP address.
+fn verify_ips(hostname: &str, checks: &[Box<dyn check::Check>]) -> check::Context {
+    log::info!("Verifying IPs");
+    check::run_pipeline(checks, hostname)
 }
 
 /// Initializes the logging for the application.
@@ -164,15 +802,20 @@ fn verify_ips(hostname: &String, token: &String, counter: i32) -> i32 {
 /// If the "RUST_LOG" environment variable is not set (indicated by `std::env::var` returning an `Err`),
 /// it sets the "RUST_LOG" environment variable to "INFO" using the `std::env::set_var` function.
 ///
-/// It then initializes the logger with the environment variables using the `simple_logger::init_with_env` function.
-/// If `simple_logger::init_with_env` fails, it will panic and terminate the program.
+/// It then initializes a `simple_logger::SimpleLogger` configured from the environment variables,
+/// or the backend selected by the "LOG_FORMAT" environment variable with the same levels (see `logging::backend`),
+/// wrapped in a `redact::RedactingLogger` so tokens, API secrets and credentials never appear in the log.
+/// If the logger cannot be installed, it will panic and terminate the program.
 ///
 /// Finally, it logs that the DNS checker is starting using the `log::info` function.
 fn init() {
     if std::env::var("RUST_LOG").is_err() {
         std::env::set_var("RUST_LOG", "INFO");
     }
-    simple_logger::init_with_env().unwrap();
+    let format = std::env::var("LOG_FORMAT").unwrap_or("text".to_string());
+    let (logger, max_level) = logging::backend(&format, simple_logger::SimpleLogger::new().env());
+    log::set_max_level(max_level);
+    log::set_boxed_logger(Box::new(redact::RedactingLogger::new(logger))).unwrap();
     log::info!("Starting DNS checker");
 }
 
@@ -194,4 +837,32 @@ mod tests {
         // Assert that the function sets the "RUST_LOG" environment variable to "INFO"
         assert_eq!(std::env::var("RUST_LOG").unwrap(), "INFO");
     }
+
+    /// Tests the `heartbeat_line` function.
+    #[test]
+    fn test_heartbeat_line() {
+        let result = heartbeat_line("home.example.com", "1.1.1.1", "", "24h: 100.00%");
+
+        assert_eq!(
+            result,
+            "Heartbeat: home.example.com resolves to 1.1.1.1, WAN IP is unknown, DNS in sync: 24h: 100.00%"
+        );
+    }
+
+    /// Tests the `parse_since` function.
+    #[test]
+    fn test_parse_since() {
+        let start = parse_since("1h").unwrap();
+        let age = chrono::Local::now() - start;
+        assert!(age >= chrono::Duration::hours(1) && age < chrono::Duration::hours(2));
+
+        assert!(parse_since("soon").is_err());
+        assert!(parse_since("").is_err());
+
+        // Assert that a period beyond the representable dates is rejected instead of panicking
+        assert_eq!(
+            parse_since("999999999999d"),
+            Err("--since reaches too far back: 999999999999d".to_string())
+        );
+    }
 }
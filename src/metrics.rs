@@ -0,0 +1,212 @@
+use crate::check::Context;
+use crate::events::{Event, Subscriber};
+use crate::state::{read_state, state_path, write_state};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The counters of the process, shared by every module.
+pub static METRICS: Metrics = Metrics::new();
+
+/// Counters of the checker, exported in the Prometheus text format on "/metrics".
+///
+/// The counters are persisted in the metrics file (see `metrics_file`),
+/// so they do not reset to zero when the container restarts.
+#[derive(Debug)]
+pub struct Metrics {
+    /// The number of verifications run.
+    checks: AtomicU64,
+    /// The number of verifications where DNS did not match the WAN IP address.
+    mismatches: AtomicU64,
+    /// The number of messages delivered to at least one channel.
+    alerts: AtomicU64,
+    /// The number of checks that failed to retrieve their input.
+    check_errors: AtomicU64,
+}
+
+impl Default for Metrics {
+    fn default() -> Metrics {
+        Metrics::new()
+    }
+}
+
+impl Metrics {
+    /// Creates counters that start at zero.
+    pub const fn new() -> Metrics {
+        Metrics {
+            checks: AtomicU64::new(0),
+            mismatches: AtomicU64::new(0),
+            alerts: AtomicU64::new(0),
+            check_errors: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the name, help text and value of each counter, in the order they are exported.
+    fn counters(&self) -> [(&'static str, &'static str, &AtomicU64); 4] {
+        [
+            ("checks_total", "Verifications run", &self.checks),
+            (
+                "mismatches_total",
+                "Verifications where DNS did not match the WAN IP address",
+                &self.mismatches,
+            ),
+            (
+                "alerts_sent_total",
+                "Messages delivered to at least one channel",
+                &self.alerts,
+            ),
+            (
+                "check_errors_total",
+                "Checks that failed to retrieve their input",
+                &self.check_errors,
+            ),
+        ]
+    }
+
+    /// Counts a finished verification and, if DNS did not match, a mismatch.
+    pub fn record_check(&self, context: &Context) {
+        self.checks.fetch_add(1, Ordering::SeqCst);
+        if context.in_sync == Some(false) {
+            self.mismatches.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Counts a delivered message.
+    pub fn record_alert(&self) {
+        self.alerts.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Counts a failed check.
+    pub fn record_check_error(&self) {
+        self.check_errors.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Restores the counters from a file written by `save`.
+    ///
+    /// If the file does not exist or cannot be parsed, the counters are left as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: A `&str` that specifies the path of the metrics file.
+    pub fn load(&self, path: &str) {
+        let stored = read_state(path);
+        if stored.is_empty() {
+            return;
+        }
+        let values: Value = match serde_json::from_str(&stored) {
+            Ok(values) => values,
+            Err(e) => {
+                log::warn!("Ignoring invalid metrics file {}: {}", path, e);
+                return;
+            }
+        };
+        for (name, _, counter) in self.counters() {
+            if let Some(value) = values[name].as_u64() {
+                counter.store(value, Ordering::SeqCst);
+            }
+        }
+        log::debug!("Restored metrics from {}", path);
+    }
+
+    /// Stores the counters in a file as JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: A `&str` that specifies the path of the metrics file.
+    pub fn save(&self, path: &str) {
+        let mut values = json!({});
+        for (name, _, counter) in self.counters() {
+            values[name] = json!(counter.load(Ordering::SeqCst));
+        }
+        write_state(path, &values.to_string());
+    }
+
+    /// Renders the counters in the Prometheus text format.
+    ///
+    /// # Returns
+    ///
+    /// * A `String` that holds the help, type and value of every counter, prefixed with "dnschecker_".
+    pub fn render(&self) -> String {
+        self.counters()
+            .iter()
+            .map(|(name, help, counter)| {
+                format!(
+                    "# HELP dnschecker_{name} {help}\n# TYPE dnschecker_{name} counter\ndnschecker_{name} {}\n",
+                    counter.load(Ordering::SeqCst)
+                )
+            })
+            .collect()
+    }
+}
+
+/// Counts the verifications and check errors published on the event bus (see `events::publish`) in `METRICS`,
+/// and stores the counters in the metrics file after every verification.
+pub struct MetricsSubscriber;
+
+impl Subscriber for MetricsSubscriber {
+    fn name(&self) -> &str {
+        "metrics"
+    }
+
+    fn handles(&self, event: &Event) -> bool {
+        matches!(event, Event::Checked(_) | Event::CheckError { .. })
+    }
+
+    fn receive(&self, event: &Event) -> bool {
+        match event {
+            Event::Checked(context) => {
+                METRICS.record_check(context);
+                METRICS.save(&metrics_file());
+            }
+            Event::CheckError { .. } => METRICS.record_check_error(),
+            _ => {}
+        }
+        true
+    }
+}
+
+/// Retrieves the path of the metrics file from the "METRICS_FILE" environment variable.
+///
+/// # Returns
+///
+/// * A `String` that holds the path, defaulting to "metrics_state" in the state directory.
+pub fn metrics_file() -> String {
+    state_path("METRICS_FILE", "metrics_state")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let metrics = Metrics::new();
+        let mismatch = Context {
+            in_sync: Some(false),
+            ..Context::new("home.example.com")
+        };
+        metrics.record_check(&Context::new("home.example.com"));
+        metrics.record_check(&mismatch);
+        metrics.record_alert();
+        metrics.record_check_error();
+
+        let rendered = metrics.render();
+        assert!(rendered
+            .contains("# TYPE dnschecker_checks_total counter\ndnschecker_checks_total 2\n"));
+        assert!(rendered.contains("dnschecker_mismatches_total 1\n"));
+        assert!(rendered.contains("dnschecker_alerts_sent_total 1\n"));
+        assert!(rendered.contains("dnschecker_check_errors_total 1\n"));
+
+        // Assert that the counters survive a restart
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        metrics.save(path);
+        let restarted = Metrics::new();
+        restarted.load(path);
+        assert_eq!(restarted.render(), rendered);
+
+        // Assert that an invalid file leaves the counters alone
+        std::fs::write(path, "not json").unwrap();
+        restarted.load(path);
+        assert_eq!(restarted.render(), rendered);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::dns;
+use crate::events;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::vars::split_list;
+use std::env;
+
+/// Watches the name servers of the zone and reports changes.
+///
+/// This function is enabled by setting the "NS_WATCH" environment variable to "true".
+/// The zone is taken from "NS_ZONE", defaulting to "DNS_ZONE"; if neither is set, it is the closest name
+/// above or at the hostname that has NS records (see `dns::find_zone`).
+///
+/// The NS set is compared with the set stored in the name server file ("NS_FILE", defaulting to "ns_state" in the state directory).
+/// On the first check the set is only stored. After that, a "nameservers" message is sent whenever it changes,
+/// which can mean that the domain was transferred to another provider or hijacked at the registrar.
+/// If the name servers cannot be looked up, it logs a warning and keeps the stored set.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+pub fn check_nameservers(hostname: &str) {
+    if env::var("NS_WATCH").as_deref() != Ok("true") {
+        return;
+    }
+    let ns_file = state_path("NS_FILE", "ns_state");
+
+    let zone = env::var("NS_ZONE")
+        .or_else(|_| env::var("DNS_ZONE"))
+        .ok()
+        .filter(|zone| !zone.is_empty())
+        .map(|zone| zone.trim_end_matches('.').to_lowercase());
+    let lookup = match zone {
+        Some(zone) => dns::lookup_ns(&zone).map(|servers| (zone, servers)),
+        None => dns::find_zone(
+            &idna::domain_to_ascii(hostname)
+                .unwrap_or_else(|_| hostname.to_string())
+                .trim_end_matches('.')
+                .to_lowercase(),
+        ),
+    };
+    let (zone, mut servers) = match lookup {
+        Ok((_, servers)) if servers.is_empty() => {
+            log::warn!("No name servers found for {}", hostname);
+            return;
+        }
+        Ok(lookup) => lookup,
+        Err(e) => {
+            log::warn!("{}", e);
+            return;
+        }
+    };
+    servers.sort();
+    servers.dedup();
+
+    let state = servers.join(",");
+    let previous = read_state(&ns_file);
+    if previous == state {
+        return;
+    }
+    if previous.is_empty() {
+        log::info!("Tracking the name servers of {}: {}", zone, state);
+    } else {
+        let text = nameservers_message(&zone, &split_list(&previous), &servers);
+        log::info!("{}", text);
+        let message = Message::new("nameservers", &text)
+            .with_field("zone", &zone)
+            .with_field("previous", &previous)
+            .with_field("nameservers", &state);
+        if !events::alert(&message) {
+            log::warn!("Failed to send name server message");
+            return;
+        }
+    }
+    write_state(&ns_file, &state);
+}
+
+/// Builds the text of the name server message.
+///
+/// # Arguments
+///
+/// * `zone`: A `&str` that specifies the zone.
+/// * `previous`: A `&[String]` that holds the name servers stored on the previous check.
+/// * `current`: A `&[String]` that holds the current name servers.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message, listing the added and removed name servers.
+fn nameservers_message(zone: &str, previous: &[String], current: &[String]) -> String {
+    let mut lines = vec![
+        "Name servers of the zone changed!".to_string(),
+        format!("Zone: {}", zone),
+    ];
+    lines.extend(
+        current
+            .iter()
+            .filter(|server| !previous.contains(server))
+            .map(|server| format!("+ {}", server)),
+    );
+    lines.extend(
+        previous
+            .iter()
+            .filter(|server| !current.contains(server))
+            .map(|server| format!("- {}", server)),
+    );
+    lines.push(
+        "Check that the domain was not transferred or hijacked at the registrar.".to_string(),
+    );
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nameservers_message() {
+        let names = |list: &[&str]| -> Vec<String> { list.iter().map(|s| s.to_string()).collect() };
+        let result = nameservers_message(
+            "example.com",
+            &names(&["ns1.provider.net", "ns2.provider.net"]),
+            &names(&["ns1.attacker.net", "ns2.provider.net"]),
+        );
+        assert!(result.starts_with("Name servers of the zone changed!\nZone: example.com\n"));
+        assert!(result.contains("\n+ ns1.attacker.net\n- ns1.provider.net\n"));
+        assert!(!result.contains("ns2.provider.net"));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::server::Shared;
+use crate::vars::split_list;
+use std::env;
+use std::sync::Arc;
+
+/// The netlink message types of route and address changes (see rtnetlink(7)).
+const RTM_NEWROUTE: u16 = 24;
+const RTM_DELROUTE: u16 = 25;
+const RTM_NEWADDR: u16 = 20;
+const RTM_DELADDR: u16 = 21;
+/// The routing table of the default route.
+const RT_TABLE_MAIN: u8 = 254;
+/// The route attribute holding the output interface.
+const RTA_OIF: u16 = 4;
+/// The sizes of the netlink message header, the route message and the address message.
+const NLMSG_HDRLEN: usize = 16;
+const RTMSG_LEN: usize = 12;
+const IFADDRMSG_LEN: usize = 8;
+
+/// Starts watching the routes and addresses of the host if it is configured.
+///
+/// This function is enabled by setting the "NETLINK_WATCH" environment variable to "true" (Linux only).
+/// The interfaces to watch can be limited with the comma-separated list in "NETLINK_INTERFACES" (e.g. "eth0,ppp0");
+/// by default all interfaces are watched.
+///
+/// It subscribes to the route and address events of the kernel on a background thread.
+/// When the default route or the address of a watched interface changes, it schedules an immediate check,
+/// like a request to "/trigger", instead of waiting for the next interval.
+///
+/// # Arguments
+///
+/// * `shared`: An `Arc<Shared>` that holds the state shared with the checker loop.
+pub fn start_watcher(shared: Arc<Shared>) {
+    if env::var("NETLINK_WATCH").as_deref() != Ok("true") {
+        return;
+    }
+    let names = split_list(&env::var("NETLINK_INTERFACES").unwrap_or_default());
+    if let Err(e) = linux::watch(shared, &names) {
+        log::error!("Failed to watch routes and addresses: {}", e);
+    }
+}
+
+/// Checks whether a batch of netlink messages holds a relevant change.
+///
+/// Relevant changes are added or removed default routes of the main routing table,
+/// and added or removed addresses. Without watched interfaces, changes on every interface are relevant.
+///
+/// # Arguments
+///
+/// * `buffer`: A `&[u8]` that holds the messages as received from the netlink socket.
+/// * `interfaces`: A `&[u32]` that holds the indexes of the watched interfaces, empty to watch all interfaces.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether any message is a relevant change.
+fn is_relevant(buffer: &[u8], interfaces: &[u32]) -> bool {
+    let watched = |index: Option<u32>| match index {
+        Some(index) => interfaces.is_empty() || interfaces.contains(&index),
+        None => interfaces.is_empty(),
+    };
+    let mut offset = 0;
+    while offset + NLMSG_HDRLEN <= buffer.len() {
+        let length = u32_at(buffer, offset) as usize;
+        if length < NLMSG_HDRLEN || offset + length > buffer.len() {
+            break;
+        }
+        let message_type = u16::from_ne_bytes([buffer[offset + 4], buffer[offset + 5]]);
+        let payload = &buffer[offset + NLMSG_HDRLEN..offset + length];
+        let relevant = match message_type {
+            RTM_NEWROUTE | RTM_DELROUTE if payload.len() >= RTMSG_LEN => {
+                let default_route = payload[1] == 0 && payload[4] == RT_TABLE_MAIN;
+                default_route && watched(route_interface(&payload[RTMSG_LEN..]))
+            }
+            RTM_NEWADDR | RTM_DELADDR if payload.len() >= IFADDRMSG_LEN => {
+                watched(Some(u32_at(payload, 4)))
+            }
+            _ => false,
+        };
+        if relevant {
+            return true;
+        }
+        // Messages are aligned to 4 bytes
+        offset += (length + 3) & !3;
+    }
+    false
+}
+
+/// Retrieves the output interface from the attributes of a route message.
+fn route_interface(attributes: &[u8]) -> Option<u32> {
+    let mut offset = 0;
+    while offset + 4 <= attributes.len() {
+        let length = u16::from_ne_bytes([attributes[offset], attributes[offset + 1]]) as usize;
+        let kind = u16::from_ne_bytes([attributes[offset + 2], attributes[offset + 3]]);
+        if length < 4 || offset + length > attributes.len() {
+            return None;
+        }
+        if kind == RTA_OIF && length >= 8 {
+            return Some(u32_at(attributes, offset + 4));
+        }
+        offset += (length + 3) & !3;
+    }
+    None
+}
+
+/// Reads a native-endian `u32` at an offset.
+fn u32_at(buffer: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes([
+        buffer[offset],
+        buffer[offset + 1],
+        buffer[offset + 2],
+        buffer[offset + 3],
+    ])
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::is_relevant;
+    use crate::server::Shared;
+    use std::ffi::CString;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    /// Subscribes to the route and address events and schedules a check on every relevant change.
+    ///
+    /// # Arguments
+    ///
+    /// * `shared`: An `Arc<Shared>` that holds the state shared with the checker loop.
+    /// * `names`: A `&[String]` that holds the names of the watched interfaces, empty to watch all interfaces.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<(), String>` that is `Ok` if the subscription is set up, or an `Err` with a message.
+    pub fn watch(shared: Arc<Shared>, names: &[String]) -> Result<(), String> {
+        let mut interfaces = Vec::new();
+        for name in names {
+            let c_name = CString::new(name.as_str()).map_err(|e| e.to_string())?;
+            // SAFETY: the name is a valid C string
+            match unsafe { libc::if_nametoindex(c_name.as_ptr()) } {
+                0 => return Err(format!("Unknown interface {} in NETLINK_INTERFACES", name)),
+                index => interfaces.push(index),
+            }
+        }
+
+        // SAFETY: plain system calls on a socket owned by this function and the watcher thread
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        // SAFETY: sockaddr_nl is plain data for which all zeroes is valid
+        let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups = (libc::RTMGRP_IPV4_ROUTE
+            | libc::RTMGRP_IPV4_IFADDR
+            | libc::RTMGRP_IPV6_ROUTE
+            | libc::RTMGRP_IPV6_IFADDR) as u32;
+        // SAFETY: the address is a valid sockaddr_nl of the given size
+        let bound = unsafe {
+            libc::bind(
+                fd,
+                (&address as *const libc::sockaddr_nl).cast(),
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if bound < 0 {
+            let error = std::io::Error::last_os_error().to_string();
+            // SAFETY: the socket was opened above and is not used elsewhere
+            unsafe { libc::close(fd) };
+            return Err(error);
+        }
+
+        log::info!("Watching routes and addresses for changes");
+        std::thread::spawn(move || {
+            let mut buffer = [0u8; 8192];
+            loop {
+                // SAFETY: the buffer is valid for its length
+                let received =
+                    unsafe { libc::recv(fd, buffer.as_mut_ptr().cast(), buffer.len(), 0) };
+                if received < 0 {
+                    let error = std::io::Error::last_os_error();
+                    if error.kind() == std::io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    // Overflows of the socket buffer lose events, so check to be safe
+                    if error.raw_os_error() == Some(libc::ENOBUFS) {
+                        shared.trigger.store(true, Ordering::SeqCst);
+                        continue;
+                    }
+                    log::error!("Stopped watching routes and addresses: {}", error);
+                    break;
+                }
+                if is_relevant(&buffer[..received as usize], &interfaces) {
+                    log::info!("Route or address changed, scheduling an immediate check");
+                    shared.trigger.store(true, Ordering::SeqCst);
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod linux {
+    use crate::server::Shared;
+    use std::sync::Arc;
+
+    /// Watching routes and addresses is only supported on Linux.
+    pub fn watch(_shared: Arc<Shared>, _names: &[String]) -> Result<(), String> {
+        Err("NETLINK_WATCH is only supported on Linux".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a netlink message with a payload.
+    fn message(message_type: u16, payload: &[u8]) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        buffer.extend(((NLMSG_HDRLEN + payload.len()) as u32).to_ne_bytes());
+        buffer.extend(message_type.to_ne_bytes());
+        buffer.extend([0u8; 10]);
+        buffer.extend(payload);
+        while buffer.len() % 4 != 0 {
+            buffer.push(0);
+        }
+        buffer
+    }
+
+    /// Builds the payload of a route message with a destination prefix length, table and output interface.
+    fn route(dst_len: u8, table: u8, interface: u32) -> Vec<u8> {
+        let mut payload = vec![2, dst_len, 0, 0, table, 0, 0, 1, 0, 0, 0, 0];
+        payload.extend(8u16.to_ne_bytes());
+        payload.extend(RTA_OIF.to_ne_bytes());
+        payload.extend(interface.to_ne_bytes());
+        payload
+    }
+
+    /// Builds the payload of an address message for an interface.
+    fn address(interface: u32) -> Vec<u8> {
+        let mut payload = vec![2, 24, 0, 0];
+        payload.extend(interface.to_ne_bytes());
+        payload
+    }
+
+    #[test]
+    fn test_is_relevant() {
+        let default_route = message(RTM_NEWROUTE, &route(0, RT_TABLE_MAIN, 2));
+
+        // Assert that default routes of the main table are relevant, on watched interfaces
+        assert!(is_relevant(&default_route, &[]));
+        assert!(is_relevant(&default_route, &[2]));
+        assert!(!is_relevant(&default_route, &[3]));
+
+        // Assert that other routes are not relevant
+        assert!(!is_relevant(
+            &message(RTM_DELROUTE, &route(24, RT_TABLE_MAIN, 2)),
+            &[]
+        ));
+        assert!(!is_relevant(&message(RTM_NEWROUTE, &route(0, 255, 2)), &[]));
+
+        // Assert that address changes are relevant on watched interfaces
+        let batch = [message(16, &[0; 16]), message(RTM_DELADDR, &address(3))].concat();
+        assert!(is_relevant(&batch, &[3]));
+        assert!(!is_relevant(&batch, &[2]));
+
+        // Assert that truncated messages are ignored
+        assert!(!is_relevant(&default_route[..20], &[]));
+    }
+}
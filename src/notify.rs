@@ -0,0 +1,642 @@
+use crate::apprise::AppriseNotifier;
+use crate::dependencies::DEPENDENCIES;
+use crate::dingtalk::DingTalkNotifier;
+use crate::events::{self, Event, Subscriber};
+use crate::feishu::FeishuNotifier;
+use crate::kubernetes;
+use crate::leader;
+use crate::metrics::METRICS;
+use crate::signal::SignalNotifier;
+use crate::slack::SlackNotifier;
+use crate::snmp::SnmpNotifier;
+use crate::store::{instance_id, store};
+use crate::telegram::TelegramNotifier;
+use crate::vars::{normalize_hostname, parse_duration, split_list};
+use crate::webhook::WebhookNotifier;
+use crate::whatsapp::WhatsAppNotifier;
+use crate::xmpp::XmppNotifier;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::env;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Set while alerts are muted, see `set_muted`.
+static MUTED: AtomicBool = AtomicBool::new(false);
+
+/// A notification sent to every configured channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// The kind of event, e.g. "mismatch", "recovery" or "failover".
+    pub event: String,
+    /// The human readable text of the notification.
+    pub text: String,
+    /// Structured details of the event, e.g. the hostname and the IP addresses.
+    pub fields: Vec<(String, String)>,
+    /// A PNG image sent after the text by the channels that support images, e.g. the chart of a digest.
+    pub image: Option<Vec<u8>>,
+}
+
+impl Message {
+    /// Creates a message without structured details.
+    pub fn new(event: &str, text: &str) -> Message {
+        Message {
+            event: event.to_string(),
+            text: text.to_string(),
+            fields: Vec::new(),
+            image: None,
+        }
+    }
+
+    /// Attaches a PNG image to the message.
+    pub fn with_image(mut self, png: Vec<u8>) -> Message {
+        self.image = Some(png);
+        self
+    }
+
+    /// Adds a structured detail to the message.
+    pub fn with_field(mut self, name: &str, value: &str) -> Message {
+        self.fields.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Returns the value of a structured detail, e.g. "hostname", if the message has it.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// How the alerts of a hostname are routed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// The alerts are sent to every configured channel.
+    Page,
+    /// The alerts are only logged; the checks, metrics, history and hooks still see them.
+    WarnOnly,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Page => write!(f, "page"),
+            Severity::WarnOnly => write!(f, "warn-only"),
+        }
+    }
+}
+
+/// The severity of every hostname.
+#[derive(Debug, Default)]
+struct SeverityPolicy {
+    /// The normalized hostnames whose alerts are never paged.
+    warn_only: BTreeSet<String>,
+}
+
+impl SeverityPolicy {
+    /// Creates the policy from the environment.
+    ///
+    /// This function parses the comma-separated list of hostnames in the "WARN_ONLY_HOSTNAMES" environment variable,
+    /// e.g. "old.example.com" for a record that is still being migrated. Invalid hostnames are logged and ignored.
+    fn from_env() -> SeverityPolicy {
+        let mut warn_only = BTreeSet::new();
+        for entry in split_list(&env::var("WARN_ONLY_HOSTNAMES").unwrap_or_default()) {
+            match normalize_hostname(&entry) {
+                Ok(hostname) => {
+                    warn_only.insert(hostname);
+                }
+                Err(_) => log::warn!(
+                    "Ignoring invalid hostname in WARN_ONLY_HOSTNAMES: {}",
+                    entry
+                ),
+            }
+        }
+        SeverityPolicy { warn_only }
+    }
+
+    /// Returns the severity of a hostname.
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname`: A `&str` that specifies the hostname; it is normalized before the lookup.
+    fn for_hostname(&self, hostname: &str) -> Severity {
+        let hostname = normalize_hostname(hostname).unwrap_or_else(|_| hostname.to_string());
+        if self.warn_only.contains(&hostname) {
+            Severity::WarnOnly
+        } else {
+            Severity::Page
+        }
+    }
+}
+
+/// Returns the severity of the alerts of a hostname, see "WARN_ONLY_HOSTNAMES".
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the hostname.
+pub fn severity(hostname: &str) -> Severity {
+    static POLICY: OnceLock<SeverityPolicy> = OnceLock::new();
+    POLICY
+        .get_or_init(SeverityPolicy::from_env)
+        .for_hostname(hostname)
+}
+
+/// A channel that notifications can be delivered to.
+pub trait Notifier {
+    /// The name of the channel, used in log messages.
+    fn name(&self) -> &str;
+
+    /// Returns whether the channel handles messages of this kind. By default it handles all of them.
+    fn handles(&self, _message: &Message) -> bool {
+        true
+    }
+
+    /// Delivers a message and returns whether the channel accepted it.
+    fn send(&self, message: &Message) -> bool;
+}
+
+/// Builds the notifiers for every configured channel.
+///
+/// This function checks the environment for the configuration of each channel:
+///
+/// * Telegram, if "TELEGRAM_TOKEN" is set.
+/// * A generic webhook, if "WEBHOOK_URL" is set.
+/// * Slack, if "SLACK_WEBHOOK_URL" is set, uploading images if "SLACK_BOT_TOKEN" and "SLACK_CHANNEL_ID" are set as well.
+/// * SNMP traps on mismatches and recoveries, if "SNMP_MANAGER" is set (see `snmp::SnmpNotifier`).
+/// * An Apprise API server, if "APPRISE_URL" is set (see `apprise::AppriseNotifier`).
+/// * Signal, if "SIGNAL_API_URL" is set (see `signal::SignalNotifier`).
+/// * WhatsApp, if "WHATSAPP_PHONE_NUMBER_ID" is set (see `whatsapp::WhatsAppNotifier`).
+/// * XMPP, if "XMPP_JID" is set (see `xmpp::XmppNotifier`).
+/// * A Feishu or Lark group, if "FEISHU_WEBHOOK_URL" is set (see `feishu::FeishuNotifier`).
+/// * A DingTalk group, if "DINGTALK_WEBHOOK_URL" is set (see `dingtalk::DingTalkNotifier`).
+///
+/// The commands run on events are not a channel but a subscriber of their own, see `hook::HookSubscriber`.
+///
+/// # Returns
+///
+/// * A `Vec<Box<dyn Notifier>>` that holds a notifier for every configured channel.
+pub fn notifiers() -> Vec<Box<dyn Notifier>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    if let Ok(token) = env::var("TELEGRAM_TOKEN") {
+        if !token.is_empty() {
+            notifiers.push(Box::new(TelegramNotifier::new(&token)));
+        }
+    }
+    if let Ok(url) = env::var("WEBHOOK_URL") {
+        let secret = env::var("WEBHOOK_SECRET").ok();
+        notifiers.push(Box::new(WebhookNotifier::new(&url, secret)));
+    }
+    if let Ok(url) = env::var("SLACK_WEBHOOK_URL") {
+        let link_url = env::var("SLACK_LINK_URL").ok();
+        let mut slack = SlackNotifier::new(&url, link_url);
+        if let (Ok(token), Ok(channel_id)) =
+            (env::var("SLACK_BOT_TOKEN"), env::var("SLACK_CHANNEL_ID"))
+        {
+            slack = slack.with_uploads(&token, &channel_id);
+        }
+        notifiers.push(Box::new(slack));
+    }
+    if let Some(snmp) = SnmpNotifier::from_env() {
+        notifiers.push(Box::new(snmp));
+    }
+    if let Some(apprise) = AppriseNotifier::from_env() {
+        notifiers.push(Box::new(apprise));
+    }
+    if let Some(signal) = SignalNotifier::from_env() {
+        notifiers.push(Box::new(signal));
+    }
+    if let Some(whatsapp) = WhatsAppNotifier::from_env() {
+        notifiers.push(Box::new(whatsapp));
+    }
+    if let Some(xmpp) = XmppNotifier::from_env() {
+        notifiers.push(Box::new(xmpp));
+    }
+    if let Some(feishu) = FeishuNotifier::from_env() {
+        notifiers.push(Box::new(feishu));
+    }
+    if let Some(dingtalk) = DingTalkNotifier::from_env() {
+        notifiers.push(Box::new(dingtalk));
+    }
+    notifiers
+}
+
+/// Sends the alerts published on the event bus (see `events::alert`) to every configured channel using `send`.
+///
+/// The overflow summary is delivered by `flush_suppressed` itself, as it must not count against the alert limit again.
+pub struct NotifierSubscriber;
+
+impl Subscriber for NotifierSubscriber {
+    fn name(&self) -> &str {
+        "notifiers"
+    }
+
+    fn handles(&self, event: &Event) -> bool {
+        matches!(event, Event::Alert(message) if message.event != "suppressed")
+    }
+
+    fn receive(&self, event: &Event) -> bool {
+        match event {
+            Event::Alert(message) => send(message),
+            _ => true,
+        }
+    }
+}
+
+/// Sends a message to every configured channel.
+///
+/// This function builds the notifiers using the `notifiers` function and delivers the message to each of them.
+/// When running in a Kubernetes pod, the pod is added to the fields of the message (see `kubernetes::pod_identity`).
+/// A failing channel is logged and does not prevent delivery to the other channels.
+///
+/// If the alert limit is reached (see `RateLimiter::from_env`), the message is not delivered but counted
+/// for the overflow summary, and it is treated as sent so the checks do not retry it.
+/// Messages sent while alerts are muted (see `set_muted`), or by an instance that does not lead (see `leader::is_leader`),
+/// are dropped and treated as sent as well.
+/// So are messages about a warn-only hostname (see `severity`), which are logged instead:
+/// a mismatch of such a hostname is still counted and recorded, but never paged.
+/// When several replicas share their state, a message another replica just sent is dropped as well (see `claim_alert`).
+///
+/// # Arguments
+///
+/// * `message`: A `&Message` that holds the notification.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether at least one channel accepted the message, or whether it was suppressed.
+pub fn send(message: &Message) -> bool {
+    if MUTED.load(Ordering::SeqCst) {
+        log::info!("Alerts are muted, not sending {} message", message.event);
+        return true;
+    }
+    if !leader::is_leader() {
+        log::info!(
+            "Another instance leads, not sending {} message",
+            message.event
+        );
+        return true;
+    }
+    if let Some(hostname) = message.field("hostname") {
+        if severity(hostname) == Severity::WarnOnly {
+            log::warn!(
+                "{} is warn-only, not sending {} message: {}",
+                hostname,
+                message.event,
+                message.text.replace('\n', ", ")
+            );
+            return true;
+        }
+    }
+    if !claim_alert(message) {
+        log::info!(
+            "{} message was already sent by another instance",
+            message.event
+        );
+        return true;
+    }
+    if let Some(limiter) = limiter() {
+        flush_suppressed();
+        let mut limiter = limiter.lock().unwrap_or_else(|e| e.into_inner());
+        if !limiter.admit(Instant::now()) {
+            log::warn!("Alert limit reached, suppressing {} message", message.event);
+            limiter.suppress(&message.event);
+            return true;
+        }
+    }
+    deliver_to_all(message)
+}
+
+/// Mutes or unmutes the alerts of the process, e.g. from the tray menu.
+///
+/// While muted, `send` drops every message, so a mismatch raised meanwhile is not alerted again after unmuting.
+pub fn set_muted(muted: bool) {
+    MUTED.store(muted, Ordering::SeqCst);
+}
+
+/// Returns whether this instance delivers alerts, i.e. it is neither muted nor a follower of another instance.
+///
+/// Channels that act outside of `send`, like editing an open incident, check this before doing so.
+pub fn is_delivering() -> bool {
+    !MUTED.load(Ordering::SeqCst) && leader::is_leader()
+}
+
+/// Sends the overflow summary if messages were suppressed and the alert limit allows it again.
+///
+/// This function is called before every message and on every iteration of the checker loop,
+/// so the summary is sent as soon as the window has room, even if no further message follows.
+/// The summary is also published on the event bus, e.g. for the "ON_SUPPRESSED" hook.
+pub fn flush_suppressed() {
+    let summary = match limiter() {
+        Some(limiter) => {
+            let mut limiter = limiter.lock().unwrap_or_else(|e| e.into_inner());
+            if limiter.has_suppressed() && limiter.admit(Instant::now()) {
+                limiter.take_summary()
+            } else {
+                None
+            }
+        }
+        None => None,
+    };
+    if let Some(summary) = summary {
+        log::info!("{}", summary);
+        let message = Message::new("suppressed", &summary);
+        let delivered = deliver_to_all(&message);
+        if !events::alert(&message) && !delivered {
+            log::warn!("Failed to send suppressed message summary");
+        }
+    }
+}
+
+/// Delivers a message to every configured channel, see `send`.
+fn deliver_to_all(message: &Message) -> bool {
+    let delivered = match kubernetes::pod_identity() {
+        Some(pod) => deliver(&notifiers(), &message.clone().with_field("pod", &pod)),
+        None => deliver(&notifiers(), message),
+    };
+    if delivered {
+        METRICS.record_alert();
+    }
+    delivered
+}
+
+/// Claims a message for this instance, so replicas that share their state (see `store::store`) send it once.
+///
+/// The claim is a lock named after the event and the hostname of the message, held for "ALERT_DEDUP_WINDOW"
+/// (e.g. "2m", defaulting to one minute, "0" disables it): the same alert raised by another instance within the window is dropped.
+/// With the state in files, every instance claims its own messages. If the lock cannot be taken, e.g. while Redis is unreachable,
+/// the message is sent rather than lost.
+///
+/// # Arguments
+///
+/// * `message`: A `&Message` that holds the notification.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether this instance sends the message.
+fn claim_alert(message: &Message) -> bool {
+    let window = env::var("ALERT_DEDUP_WINDOW")
+        .ok()
+        .and_then(|value| parse_duration(&value))
+        .unwrap_or(Duration::from_secs(60));
+    if window.is_zero() {
+        return true;
+    }
+    let name = format!(
+        "alert:{}:{}",
+        message.event,
+        message.field("hostname").unwrap_or_default()
+    );
+    match store().try_lock(&name, instance_id(), window) {
+        Ok(claimed) => claimed,
+        Err(e) => {
+            log::warn!("Failed to claim {} message: {}", message.event, e);
+            true
+        }
+    }
+}
+
+/// Retrieves the process-wide alert limiter.
+///
+/// # Returns
+///
+/// * An `Option<&Mutex<RateLimiter>>` that holds the limiter, or `None` if no alert limit is configured.
+fn limiter() -> Option<&'static Mutex<RateLimiter>> {
+    static LIMITER: OnceLock<Option<Mutex<RateLimiter>>> = OnceLock::new();
+    LIMITER
+        .get_or_init(|| RateLimiter::from_env().map(Mutex::new))
+        .as_ref()
+}
+
+/// A sliding-window limit on the number of messages, across all checks and channels.
+///
+/// It protects against pathological flapping, where every check raises and clears an alert.
+/// Unlike the cooldown of an alarm, it bounds the total number of messages, whatever their kind.
+#[derive(Debug)]
+struct RateLimiter {
+    /// The maximum number of messages per window.
+    limit: usize,
+    /// The length of the window.
+    window: Duration,
+    /// When the messages within the window were sent, oldest first.
+    sent: VecDeque<Instant>,
+    /// The number of suppressed messages of each event since the last summary.
+    suppressed: BTreeMap<String, u32>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter with a limit and a window.
+    fn new(limit: usize, window: Duration) -> RateLimiter {
+        RateLimiter {
+            limit,
+            window,
+            sent: VecDeque::new(),
+            suppressed: BTreeMap::new(),
+        }
+    }
+
+    /// Creates the limiter from the environment.
+    ///
+    /// This function retrieves the maximum number of messages per window from the "ALERT_LIMIT" environment variable
+    /// and the window from "ALERT_WINDOW" (e.g. "1h"), defaulting to one hour.
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<RateLimiter>` that holds the limiter, or `None` if "ALERT_LIMIT" is not set, "0" or invalid.
+    fn from_env() -> Option<RateLimiter> {
+        let limit = env::var("ALERT_LIMIT")
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+            .filter(|limit| *limit > 0)?;
+        let window = env::var("ALERT_WINDOW")
+            .ok()
+            .and_then(|value| parse_duration(&value))
+            .unwrap_or(Duration::from_secs(60 * 60));
+        Some(RateLimiter::new(limit, window))
+    }
+
+    /// Records a message if the window has room for it.
+    ///
+    /// # Arguments
+    ///
+    /// * `now`: An `Instant` that specifies the time of the message.
+    ///
+    /// # Returns
+    ///
+    /// * A `bool` that indicates whether the message may be sent.
+    fn admit(&mut self, now: Instant) -> bool {
+        while self
+            .sent
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) >= self.window)
+        {
+            self.sent.pop_front();
+        }
+        if self.sent.len() >= self.limit {
+            return false;
+        }
+        self.sent.push_back(now);
+        true
+    }
+
+    /// Counts a suppressed message of an event.
+    fn suppress(&mut self, event: &str) {
+        *self.suppressed.entry(event.to_string()).or_default() += 1;
+    }
+
+    /// Returns whether messages were suppressed since the last summary.
+    fn has_suppressed(&self) -> bool {
+        !self.suppressed.is_empty()
+    }
+
+    /// Builds the overflow summary and resets the suppressed counts.
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<String>` that holds the text of the summary, or `None` if no message was suppressed.
+    fn take_summary(&mut self) -> Option<String> {
+        if self.suppressed.is_empty() {
+            return None;
+        }
+        let total: u32 = self.suppressed.values().sum();
+        let events: Vec<String> = std::mem::take(&mut self.suppressed)
+            .into_iter()
+            .map(|(event, count)| format!("{}: {}", event, count))
+            .collect();
+        Some(format!(
+            "Alert limit of {} messages per {}s reached, {} messages were suppressed\n{}",
+            self.limit,
+            self.window.as_secs(),
+            total,
+            events.join("\n")
+        ))
+    }
+}
+
+/// Delivers a message to the given notifiers.
+///
+/// # Arguments
+///
+/// * `notifiers`: A `&[Box<dyn Notifier>]` that holds the channels; only those that handle the message are used.
+/// * `message`: A `&Message` that holds the notification.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether at least one channel accepted the message.
+fn deliver(notifiers: &[Box<dyn Notifier>], message: &Message) -> bool {
+    let notifiers: Vec<&Box<dyn Notifier>> = notifiers
+        .iter()
+        .filter(|notifier| notifier.handles(message))
+        .collect();
+    if notifiers.is_empty() {
+        log::warn!(
+            "No notification channel configured, dropping {} message",
+            message.event
+        );
+        return false;
+    }
+    let mut delivered = false;
+    for notifier in notifiers {
+        let dependency = format!("notifier {}", notifier.name());
+        if notifier.send(message) {
+            log::debug!("Sent {} message to {}", message.event, notifier.name());
+            DEPENDENCIES.record_success(&dependency);
+            delivered = true;
+        } else {
+            DEPENDENCIES.record_failure(
+                &dependency,
+                &format!("Failed to send {} message", message.event),
+            );
+            log::warn!(
+                "Failed to send {} message to {}",
+                message.event,
+                notifier.name()
+            );
+        }
+    }
+    delivered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    struct Recorder {
+        accept: bool,
+        sent: RefCell<Vec<String>>,
+    }
+
+    impl Notifier for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn send(&self, message: &Message) -> bool {
+            self.sent.borrow_mut().push(message.text.clone());
+            self.accept
+        }
+    }
+
+    #[test]
+    fn test_deliver() {
+        let message = Message::new("mismatch", "text").with_field("hostname", "home.example.com");
+        assert_eq!(
+            message.fields,
+            vec![("hostname".to_string(), "home.example.com".to_string())]
+        );
+
+        // Assert that one accepting channel is enough
+        let notifiers: Vec<Box<dyn Notifier>> = vec![
+            Box::new(Recorder {
+                accept: false,
+                sent: RefCell::new(Vec::new()),
+            }),
+            Box::new(Recorder {
+                accept: true,
+                sent: RefCell::new(Vec::new()),
+            }),
+        ];
+        assert!(deliver(&notifiers, &message));
+
+        // Assert that no channels means the message was not delivered
+        assert!(!deliver(&[], &message));
+    }
+
+    #[test]
+    fn test_severity_policy() {
+        let policy = SeverityPolicy {
+            warn_only: BTreeSet::from(["old.example.com".to_string()]),
+        };
+        assert_eq!(policy.for_hostname("old.example.com"), Severity::WarnOnly);
+        assert_eq!(policy.for_hostname("OLD.example.com."), Severity::WarnOnly);
+        assert_eq!(policy.for_hostname("home.example.com"), Severity::Page);
+
+        let message = Message::new("mismatch", "text").with_field("hostname", "old.example.com");
+        assert_eq!(message.field("hostname"), Some("old.example.com"));
+        assert_eq!(message.field("dns_ip"), None);
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+
+        // Assert that messages beyond the limit are refused within the window
+        assert!(limiter.admit(start));
+        assert!(limiter.admit(start + Duration::from_secs(10)));
+        assert!(!limiter.admit(start + Duration::from_secs(20)));
+        assert!(limiter.take_summary().is_none());
+        limiter.suppress("mismatch");
+        limiter.suppress("recovery");
+        limiter.suppress("mismatch");
+
+        // Assert that the window slides and the summary lists the suppressed events
+        assert!(limiter.admit(start + Duration::from_secs(60)));
+        assert!(!limiter.admit(start + Duration::from_secs(61)));
+        let summary = limiter.take_summary().unwrap();
+        assert!(summary.contains("3 messages were suppressed"));
+        assert!(summary.ends_with("mismatch: 2\nrecovery: 1"));
+        assert!(!limiter.has_suppressed());
+    }
+}
@@ -0,0 +1,389 @@
+use crate::vars::split_list;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::env;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// How much of the end of a log file is searched for the latest address.
+const LOG_TAIL: u64 = 64 * 1024;
+
+/// Retrieves the sources of the WAN IP address in order of preference.
+///
+/// This function parses the comma-separated list in the "WAN_IP_SOURCE" environment variable, e.g. "api,file".
+/// The sources are "api" (the router API), "file" (see `FileSource`) and "hostname" (the addresses of "REFERENCE_HOSTNAME",
+/// to make the monitored hostname follow another record). If it is not set, it defaults to "api".
+///
+/// # Returns
+///
+/// * A `Vec<String>` that holds the names of the sources.
+pub fn sources() -> Vec<String> {
+    let sources = split_list(&env::var("WAN_IP_SOURCE").unwrap_or_default());
+    if sources.is_empty() {
+        vec!["api".to_string()]
+    } else {
+        sources
+    }
+}
+
+/// Returns whether the WAN IP address is taken without the router API, e.g. only from a file (see `sources`).
+pub fn is_passive() -> bool {
+    !sources().iter().any(|source| source == "api")
+}
+
+/// The formats of the files written by other DDNS clients.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Format {
+    /// The file holds the address, e.g. the cache file of inadyn.
+    Plain,
+    /// The cache file of ddclient, with "ip=" or "ipv4=" entries.
+    Ddclient,
+    /// A JSON state file; the address is at the given dot-separated key path, e.g. "wan.ip".
+    Json(String),
+    /// A log file; the address is the last one logged.
+    Log,
+}
+
+impl Format {
+    /// Parses the name of a format.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: A `&str` that holds "plain", "ddclient", "json" or "log".
+    /// * `key`: A `&str` that holds the key path for the "json" format.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<Format, String>` that holds the format, or an `Err` with a message if the name is unknown.
+    pub fn parse(name: &str, key: &str) -> Result<Format, String> {
+        match name {
+            "plain" => Ok(Format::Plain),
+            "ddclient" => Ok(Format::Ddclient),
+            "json" => Ok(Format::Json(key.to_string())),
+            "log" => Ok(Format::Log),
+            _ => Err(format!(
+                "Invalid SOURCE_FORMAT {}, expected plain, ddclient, json or log",
+                name
+            )),
+        }
+    }
+
+    /// Extracts the WAN IP address from the contents of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `contents`: A `&str` that holds the contents of the file.
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<String>` that holds the IPv4 address, or `None` if the file holds none.
+    pub fn extract(&self, contents: &str) -> Option<String> {
+        match self {
+            Format::Plain => ipv4_addresses(contents).into_iter().next(),
+            Format::Ddclient => contents
+                .lines()
+                .filter(|line| !line.trim_start().starts_with('#'))
+                .flat_map(|line| line.split([',', ' ']))
+                .filter_map(|entry| {
+                    entry
+                        .strip_prefix("ipv4=")
+                        .or_else(|| entry.strip_prefix("ip="))
+                })
+                .find_map(|value| value.parse::<Ipv4Addr>().ok())
+                .map(|ip| ip.to_string()),
+            Format::Json(key) => {
+                let json: Value = serde_json::from_str(contents).ok()?;
+                let value = key
+                    .split('.')
+                    .filter(|part| !part.is_empty())
+                    .try_fold(&json, |value, part| value.get(part))?;
+                value
+                    .as_str()
+                    .and_then(|ip| ip.parse::<Ipv4Addr>().ok())
+                    .map(|ip| ip.to_string())
+            }
+            Format::Log => ipv4_addresses(contents).pop(),
+        }
+    }
+}
+
+/// Finds the IPv4 addresses in a text, in order.
+fn ipv4_addresses(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_ascii_digit() && c != '.')
+        .map(|token| token.trim_matches('.'))
+        .filter_map(|token| token.parse::<Ipv4Addr>().ok())
+        .map(|ip| ip.to_string())
+        .collect()
+}
+
+/// The WAN IP address as known to another DDNS client, read from a file it writes.
+///
+/// The file is only read again after it changed, which is detected with inotify on Linux.
+/// On other platforms it is read on every check.
+pub struct FileSource {
+    path: PathBuf,
+    format: Format,
+    changed: Option<Arc<AtomicBool>>,
+    cached: RefCell<String>,
+}
+
+impl FileSource {
+    /// Creates the file source from the environment.
+    ///
+    /// This function retrieves the following environment variables:
+    ///
+    /// * "SOURCE_FILE": the path of the file, e.g. "/var/cache/ddclient/ddclient.cache".
+    /// * "SOURCE_FORMAT": the format of the file (see `Format::parse`), defaulting to "plain".
+    /// * "SOURCE_JSON_KEY": the key path of the address for the "json" format, defaulting to "ip".
+    ///
+    /// It then starts watching the file for changes.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<FileSource, String>` that holds the source, or an `Err` with a message if a variable is missing or invalid.
+    pub fn from_env() -> Result<FileSource, String> {
+        let path = env::var("SOURCE_FILE")
+            .map_err(|_| "SOURCE_FILE must be set when WAN_IP_SOURCE includes file".to_string())?;
+        let format = Format::parse(
+            &env::var("SOURCE_FORMAT").unwrap_or("plain".to_string()),
+            &env::var("SOURCE_JSON_KEY").unwrap_or("ip".to_string()),
+        )?;
+        Ok(FileSource::new(Path::new(&path), format))
+    }
+
+    /// Creates a file source and starts watching the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: A `&Path` that specifies the file.
+    /// * `format`: A `Format` that specifies the format of the file.
+    pub fn new(path: &Path, format: Format) -> FileSource {
+        FileSource {
+            path: path.to_path_buf(),
+            format,
+            changed: watch(path),
+            cached: RefCell::new(String::new()),
+        }
+    }
+
+    /// Retrieves the WAN IP address from the file.
+    ///
+    /// If the file did not change since the last call, the previous address is returned.
+    /// Log files are only read from the last 64 KiB.
+    ///
+    /// # Returns
+    ///
+    /// * A `String` that holds the IPv4 address. If the file cannot be read or holds no address, it logs a warning and returns an empty `String`.
+    pub fn wan_ip(&self) -> String {
+        let changed = self
+            .changed
+            .as_ref()
+            .is_none_or(|changed| changed.swap(false, Ordering::SeqCst));
+        if !changed && !self.cached.borrow().is_empty() {
+            return self.cached.borrow().clone();
+        }
+        let wan_ip = match read_tail(&self.path, self.format == Format::Log) {
+            Ok(contents) => self.format.extract(&contents).unwrap_or_else(|| {
+                log::warn!("No IPv4 address found in {}", self.path.display());
+                String::new()
+            }),
+            Err(e) => {
+                log::warn!("Failed to read {}: {}", self.path.display(), e);
+                String::new()
+            }
+        };
+        log::debug!("{} holds WAN IP address {}", self.path.display(), wan_ip);
+        self.cached.replace(wan_ip.clone());
+        wan_ip
+    }
+}
+
+/// Reads a file, or only its end for log files.
+fn read_tail(path: &Path, tail: bool) -> std::io::Result<String> {
+    use std::io::{Read, Seek, SeekFrom};
+    let mut file = std::fs::File::open(path)?;
+    if tail {
+        let length = file.metadata()?.len();
+        file.seek(SeekFrom::Start(length.saturating_sub(LOG_TAIL)))?;
+    }
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+    Ok(String::from_utf8_lossy(&contents).into_owned())
+}
+
+/// Watches a file for changes.
+///
+/// On Linux this function watches the directory of the file with inotify on a background thread,
+/// so files that are replaced by renaming (as most DDNS clients do) are noticed too.
+/// Every change in the directory sets the returned flag.
+///
+/// # Arguments
+///
+/// * `path`: A `&Path` that specifies the file.
+///
+/// # Returns
+///
+/// * An `Option<Arc<AtomicBool>>` that is set when the file may have changed,
+///   or `None` if the file cannot be watched and must be read on every check.
+#[cfg(target_os = "linux")]
+fn watch(path: &Path) -> Option<Arc<AtomicBool>> {
+    let changed = Arc::new(AtomicBool::new(true));
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    match inotify::watch_directory(&directory, changed.clone()) {
+        Ok(()) => Some(changed),
+        Err(e) => {
+            log::warn!(
+                "Failed to watch {}, reading it on every check: {}",
+                directory.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Watches a file for changes, which is only supported on Linux.
+#[cfg(not(target_os = "linux"))]
+fn watch(_path: &Path) -> Option<Arc<AtomicBool>> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// Watches a directory with inotify and sets the flag on every change.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory`: A `&Path` that specifies the directory.
+    /// * `changed`: An `Arc<AtomicBool>` that is set on every change.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<(), String>` that is `Ok` if the watch is set up, or an `Err` with a message.
+    pub fn watch_directory(directory: &Path, changed: Arc<AtomicBool>) -> Result<(), String> {
+        let directory =
+            CString::new(directory.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+        // SAFETY: plain system calls on a descriptor owned by this function and the watcher thread
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        let mask = libc::IN_CLOSE_WRITE
+            | libc::IN_MOVED_TO
+            | libc::IN_CREATE
+            | libc::IN_DELETE
+            | libc::IN_MODIFY;
+        // SAFETY: the path is a valid C string
+        if unsafe { libc::inotify_add_watch(fd, directory.as_ptr(), mask) } < 0 {
+            let error = std::io::Error::last_os_error().to_string();
+            // SAFETY: the descriptor was opened above and is not used elsewhere
+            unsafe { libc::close(fd) };
+            return Err(error);
+        }
+        std::thread::spawn(move || {
+            let mut buffer = [0u8; 4096];
+            loop {
+                // SAFETY: the buffer is valid for its length
+                let read = unsafe { libc::read(fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+                if read < 0 {
+                    if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    log::warn!(
+                        "Stopped watching for changes: {}",
+                        std::io::Error::last_os_error()
+                    );
+                    // Fall back to reading the file every second
+                    loop {
+                        changed.store(true, Ordering::SeqCst);
+                        std::thread::sleep(std::time::Duration::from_secs(1));
+                    }
+                }
+                changed.store(true, Ordering::SeqCst);
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_extract() {
+        assert_eq!(
+            Format::Plain.extract("203.0.113.7\n"),
+            Some("203.0.113.7".to_string())
+        );
+        assert_eq!(Format::Plain.extract("no address"), None);
+
+        let ddclient =
+            "## ddclient-3.10.0\nipv4=203.0.113.7,mtime=1714550400,status=good home.example.com\n";
+        assert_eq!(
+            Format::Ddclient.extract(ddclient),
+            Some("203.0.113.7".to_string())
+        );
+        let ddclient = "ip=198.51.100.4,mtime=1714550400,status=good home.example.com";
+        assert_eq!(
+            Format::Ddclient.extract(ddclient),
+            Some("198.51.100.4".to_string())
+        );
+
+        let json = r#"{"wan": {"ip": "203.0.113.7"}}"#;
+        assert_eq!(
+            Format::Json("wan.ip".to_string()).extract(json),
+            Some("203.0.113.7".to_string())
+        );
+        assert_eq!(Format::Json("ip".to_string()).extract(json), None);
+
+        let log = "inadyn[1]: Current IP# 198.51.100.4 at default\ninadyn[1]: Current IP# 203.0.113.7 at default.\n";
+        assert_eq!(Format::Log.extract(log), Some("203.0.113.7".to_string()));
+
+        assert!(Format::parse("xml", "ip").is_err());
+    }
+
+    proptest! {
+        /// Asserts that every format only extracts valid IPv4 addresses from arbitrary files.
+        #[test]
+        fn test_extract_arbitrary(contents in ".*|(ip=|ipv4=|\\{\"wan\": \\{\"ip\": )[0-9.\"}]{0,20}") {
+            for format in [Format::Plain, Format::Ddclient, Format::Json("wan.ip".to_string()), Format::Log] {
+                if let Some(ip) = format.extract(&contents) {
+                    prop_assert!(ip.parse::<Ipv4Addr>().is_ok());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_file_source() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("inadyn.cache");
+        std::fs::write(&path, "198.51.100.4").unwrap();
+        let source = FileSource::new(&path, Format::Plain);
+
+        assert_eq!(source.wan_ip(), "198.51.100.4");
+
+        // Assert that a replaced file is noticed
+        let next = directory.path().join("inadyn.cache.tmp");
+        std::fs::write(&next, "203.0.113.7").unwrap();
+        std::fs::rename(&next, &path).unwrap();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while source.wan_ip() != "203.0.113.7" && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(source.wan_ip(), "203.0.113.7");
+    }
+}
@@ -0,0 +1,243 @@
+use crate::events;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::vars::{display_hostname, normalize_hostname, split_list};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, TimeZone};
+use std::env;
+use std::net::IpAddr;
+
+/// A planned move of a record to a new address, e.g. to a new uplink.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedChange {
+    /// The normalized hostname.
+    pub hostname: String,
+    /// The address the record moves to.
+    pub address: IpAddr,
+    /// When the window of the change opens.
+    pub start: DateTime<FixedOffset>,
+    /// When the window of the change closes.
+    pub end: DateTime<FixedOffset>,
+}
+
+impl PlannedChange {
+    /// Parses a planned change.
+    ///
+    /// The entry is "hostname=address@start", or "hostname=address@start..end" for a window of several days.
+    /// The start and the end are dates ("2024-07-01", midnight in local time) or RFC 3339 times ("2024-07-01T22:00:00+02:00").
+    /// A window that ends on a date includes the whole day, so "home.example.com=5.6.7.8@2024-07-01" is accepted on July 1st.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry`: A `&str` that holds the entry.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<PlannedChange, String>` that holds the change, or an `Err` with a message if the entry is invalid.
+    pub fn parse(entry: &str) -> Result<PlannedChange, String> {
+        let invalid = || {
+            format!(
+                "Invalid entry in PLANNED_CHANGES, expected hostname=address@start[..end]: {}",
+                entry
+            )
+        };
+        let (hostname, rest) = entry.split_once('=').ok_or_else(invalid)?;
+        let (address, window) = rest.split_once('@').ok_or_else(invalid)?;
+        let hostname = normalize_hostname(hostname.trim()).map_err(|_| invalid())?;
+        let address = address.trim().parse::<IpAddr>().map_err(|_| invalid())?;
+        let (start, end) = match window.split_once("..") {
+            Some((start, end)) => (start.trim(), end.trim()),
+            None => (window.trim(), window.trim()),
+        };
+        let start = parse_time(start, false).ok_or_else(invalid)?;
+        let end = parse_time(end, true).ok_or_else(invalid)?;
+        if end <= start {
+            return Err(format!("Planned change ends before it starts: {}", entry));
+        }
+        Ok(PlannedChange {
+            hostname,
+            address,
+            start,
+            end,
+        })
+    }
+
+    /// Returns whether the window of the change is open at a time.
+    pub fn is_active(&self, now: DateTime<FixedOffset>) -> bool {
+        self.start <= now && now < self.end
+    }
+
+    /// Returns whether the addresses from DNS show the change.
+    ///
+    /// # Arguments
+    ///
+    /// * `dns_ips`: A `&[String]` that holds the addresses from DNS.
+    ///
+    /// # Returns
+    ///
+    /// * A `bool` that indicates whether every address of the family of the new address is the new address;
+    ///   the addresses of the other family, e.g. the AAAA records of a dual-stack hostname, are not compared.
+    pub fn observed(&self, dns_ips: &[String]) -> bool {
+        let family: Vec<IpAddr> = dns_ips
+            .iter()
+            .filter_map(|ip| ip.parse::<IpAddr>().ok())
+            .filter(|ip| ip.is_ipv4() == self.address.is_ipv4())
+            .collect();
+        !family.is_empty() && family.iter().all(|ip| *ip == self.address)
+    }
+
+    /// The key of the change in the state file.
+    fn key(&self) -> String {
+        format!(
+            "{}={}@{}",
+            self.hostname,
+            self.address,
+            self.start.to_rfc3339()
+        )
+    }
+}
+
+/// Parses the start or the end of a window.
+///
+/// # Arguments
+///
+/// * `value`: A `&str` that holds a date or an RFC 3339 time.
+/// * `end`: A `bool` that indicates whether the value ends the window, so a date means the end of that day.
+///
+/// # Returns
+///
+/// * An `Option<DateTime<FixedOffset>>` that holds the time, or `None` if the value is invalid.
+fn parse_time(value: &str, end: bool) -> Option<DateTime<FixedOffset>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time);
+    }
+    let mut date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    if end {
+        date = date.succ_opt()?;
+    }
+    let midnight = date.and_hms_opt(0, 0, 0)?;
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|time| time.fixed_offset())
+}
+
+/// Retrieves the planned changes.
+///
+/// This function parses the comma-separated list of entries in the "PLANNED_CHANGES" environment variable,
+/// e.g. "home.example.com=5.6.7.8@2024-07-01", see `PlannedChange::parse`. If it is not set, the list is empty.
+///
+/// # Returns
+///
+/// * A `Result<Vec<PlannedChange>, String>` that holds the changes, or an `Err` with a message if an entry is invalid.
+pub fn planned_changes() -> Result<Vec<PlannedChange>, String> {
+    split_list(&env::var("PLANNED_CHANGES").unwrap_or_default())
+        .iter()
+        .map(|entry| PlannedChange::parse(entry))
+        .collect()
+}
+
+/// Returns the planned change of a hostname whose window is open.
+///
+/// # Arguments
+///
+/// * `changes`: A `&[PlannedChange]` that holds the planned changes.
+/// * `hostname`: A `&str` that specifies the normalized hostname.
+/// * `now`: A `DateTime<FixedOffset>` that specifies the time of the verification.
+///
+/// # Returns
+///
+/// * An `Option<&PlannedChange>` that holds the change, or `None` if no window of the hostname is open.
+pub fn active_change<'a>(
+    changes: &'a [PlannedChange],
+    hostname: &str,
+    now: DateTime<FixedOffset>,
+) -> Option<&'a PlannedChange> {
+    changes
+        .iter()
+        .find(|change| change.hostname == hostname && change.is_active(now))
+}
+
+/// Confirms a planned change once DNS shows it.
+///
+/// If the window of a change of the hostname is open and the addresses from DNS are the new address
+/// (see `PlannedChange::observed`), a "planned_change" message is sent, once per change: the confirmed changes
+/// are stored in the planned changes file ("PLANNED_CHANGES_FILE", defaulting to "planned_changes" in the state directory).
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `dns_ips`: A `&[String]` that holds the addresses from DNS.
+/// * `changes`: A `&[PlannedChange]` that holds the planned changes.
+pub fn confirm_change(hostname: &str, dns_ips: &[String], changes: &[PlannedChange]) {
+    let change = match active_change(changes, hostname, Local::now().fixed_offset()) {
+        Some(change) if change.observed(dns_ips) => change,
+        _ => return,
+    };
+    let planned_file = state_path("PLANNED_CHANGES_FILE", "planned_changes");
+    let confirmed = read_state(&planned_file);
+    let key = change.key();
+    if confirmed.lines().any(|line| line == key) {
+        return;
+    }
+
+    let text = format!(
+        "Planned change observed\nHostname: {}\nNew IP: {}\nWindow: {} - {}",
+        display_hostname(hostname),
+        change.address,
+        change.start.format("%Y-%m-%d %H:%M"),
+        change.end.format("%Y-%m-%d %H:%M")
+    );
+    log::info!("{}", text);
+    let message = Message::new("planned_change", &text)
+        .with_field("hostname", hostname)
+        .with_field("new_ip", &change.address.to_string());
+    if !events::alert(&message) {
+        log::warn!("Failed to send planned change message");
+        return;
+    }
+    let mut lines: Vec<&str> = confirmed.lines().collect();
+    lines.push(&key);
+    write_state(&planned_file, &lines.join("\n"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration as ChronoDuration;
+
+    #[test]
+    fn test_parse() {
+        let change = PlannedChange::parse(
+            "HOME.example.com=5.6.7.8@2024-07-01T22:00:00+02:00..2024-07-02T06:00:00+02:00",
+        )
+        .unwrap();
+        assert_eq!(change.hostname, "home.example.com");
+        assert_eq!(change.address, "5.6.7.8".parse::<IpAddr>().unwrap());
+        let at = |time| DateTime::parse_from_rfc3339(time).unwrap();
+        assert!(!change.is_active(at("2024-07-01T21:59:59+02:00")));
+        assert!(change.is_active(at("2024-07-01T22:00:00+02:00")));
+        assert!(!change.is_active(at("2024-07-02T06:00:00+02:00")));
+
+        // Assert that a date covers the whole day in local time
+        let change = PlannedChange::parse("home.example.com=5.6.7.8@2024-07-01").unwrap();
+        assert_eq!(
+            change.end - change.start,
+            ChronoDuration::try_hours(24).unwrap()
+        );
+
+        assert!(PlannedChange::parse("home.example.com=5.6.7.8").is_err());
+        assert!(PlannedChange::parse("home.example.com=5.6.7@2024-07-01").is_err());
+        assert!(PlannedChange::parse("home.example.com=5.6.7.8@2024-07-02..2024-07-01").is_err());
+    }
+
+    #[test]
+    fn test_observed() {
+        let change = PlannedChange::parse("home.example.com=5.6.7.8@2024-07-01").unwrap();
+        let ips = |list: &[&str]| list.iter().map(|ip| ip.to_string()).collect::<Vec<_>>();
+        assert!(change.observed(&ips(&["5.6.7.8"])));
+        assert!(change.observed(&ips(&["5.6.7.8", "2001:db8::1"])));
+        assert!(!change.observed(&ips(&["1.2.3.4"])));
+        assert!(!change.observed(&ips(&["5.6.7.8", "1.2.3.4"])));
+        assert!(!change.observed(&[]));
+    }
+}
@@ -0,0 +1,85 @@
+use crate::api;
+use crate::events;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use std::env;
+
+/// Checks whether the delegated IPv6 prefix has changed since the last check.
+///
+/// This function retrieves the value of the "PREFIX_INTERFACE" environment variable.
+/// If it is not set, IPv6 prefix tracking is disabled and the function returns immediately.
+///
+/// It then retrieves the current prefix of the interface from the router API using the `api::get_ipv6_prefix` function,
+/// optionally masked to the length in the "PREFIX_LENGTH" environment variable.
+/// The prefix is compared with the one stored in the prefix file ("PREFIX_FILE", defaulting to "ipv6_prefix" in the state directory).
+///
+/// If the prefix changed, it sends a message to every notification channel so internal AAAA records can be updated,
+/// and writes the new prefix to the prefix file.
+///
+/// # Arguments
+pub fn check_prefix() {
+    let interface = match env::var("PREFIX_INTERFACE") {
+        Ok(interface) => interface,
+        Err(_) => return,
+    };
+    let prefix_file = state_path("PREFIX_FILE", "ipv6_prefix");
+    let prefix_len = env::var("PREFIX_LENGTH")
+        .ok()
+        .and_then(|value| value.parse::<u8>().ok())
+        .filter(|value| *value <= 128);
+
+    let prefix = api::get_ipv6_prefix(&interface, prefix_len);
+    if prefix.is_empty() {
+        log::warn!("Failed to get IPv6 prefix");
+        return;
+    }
+
+    let previous = read_state(&prefix_file);
+    if previous == prefix {
+        log::trace!("IPv6 prefix unchanged: {}", prefix);
+        return;
+    }
+
+    if previous.is_empty() {
+        log::info!("Tracking IPv6 prefix {}", prefix);
+    } else {
+        log::info!("IPv6 prefix changed from {} to {}", previous, prefix);
+        let text = prefix_message(&previous, &prefix);
+        if !events::alert(&Message::new("prefix", &text)) {
+            log::warn!("Failed to send prefix change message");
+            // Keep the old prefix so the change is reported again on the next check
+            return;
+        }
+    }
+    write_state(&prefix_file, &prefix);
+}
+
+/// Builds the text of the prefix change message.
+///
+/// # Arguments
+///
+/// * `previous`: A `&str` that specifies the previous prefix.
+/// * `current`: A `&str` that specifies the current prefix.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message.
+fn prefix_message(previous: &str, current: &str) -> String {
+    format!(
+        "IPv6 prefix changed!\nOld prefix: {}\nNew prefix: {}\nInternal AAAA records need to be updated.",
+        previous, current
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_message() {
+        let result = prefix_message("2001:db8:1::/56", "2001:db8:2::/56");
+
+        assert!(result.contains("Old prefix: 2001:db8:1::/56"));
+        assert!(result.contains("New prefix: 2001:db8:2::/56"));
+    }
+}
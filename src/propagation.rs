@@ -0,0 +1,244 @@
+use crate::compare::ComparePolicy;
+use crate::dns;
+use crate::events;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::vars::{display_hostname, split_list};
+use std::env;
+
+/// The answer of one resolver in the propagation check.
+#[derive(Debug, Clone, PartialEq)]
+struct Answer {
+    /// The description of the resolver, e.g. "cloudflare".
+    resolver: String,
+    /// The addresses of the hostname, sorted, or `None` if the resolver did not answer.
+    addresses: Option<Vec<String>>,
+    /// Whether the addresses match the WAN IP address.
+    current: bool,
+}
+
+/// Retrieves the resolvers whose answers are compared by the propagation check.
+///
+/// This function parses the comma-separated list of resolvers in the "PROPAGATION_RESOLVERS" environment variable.
+/// Each entry is one resolver as accepted by `dns::resolver_config` or `dns::tls_servers`, e.g. "google", "opendns",
+/// "192.0.2.53" or "quad9-tls"; "public" stands for the public resolvers in `dns::PUBLIC_RESOLVERS`.
+/// If it is not set, the list is empty and the check is disabled.
+///
+/// # Returns
+///
+/// * A `Result<Vec<String>, String>` that holds the resolvers, without duplicates.
+/// * If an entry is invalid, or only one resolver is given, it returns an `Err` with a message.
+pub fn resolvers() -> Result<Vec<String>, String> {
+    let mut resolvers: Vec<String> = Vec::new();
+    for entry in split_list(&env::var("PROPAGATION_RESOLVERS").unwrap_or_default()) {
+        let entries = if entry == "public" {
+            dns::PUBLIC_RESOLVERS
+                .iter()
+                .map(|r| r.to_string())
+                .collect()
+        } else {
+            dns::validate_resolver(&entry)
+                .map_err(|e| format!("Invalid resolver in PROPAGATION_RESOLVERS: {}", e))?;
+            vec![entry]
+        };
+        for resolver in entries {
+            if !resolvers.contains(&resolver) {
+                resolvers.push(resolver);
+            }
+        }
+    }
+    if resolvers.len() == 1 {
+        return Err("PROPAGATION_RESOLVERS needs at least two resolvers to compare".to_string());
+    }
+    Ok(resolvers)
+}
+
+/// Verifies that a DNS update reached every resolver.
+///
+/// This function returns immediately if no resolvers are given (see `resolvers`).
+/// The hostname is resolved with every resolver at once (see `dns::resolve_with_each`), and each answer is compared
+/// with the WAN IP address under the "COMPARE_POLICY" (see `compare::ComparePolicy`). A resolver whose answer differs
+/// is stale, e.g. because it still caches the previous record; resolvers that did not answer are skipped.
+/// The stale resolvers are compared with the state stored in the propagation file ("PROPAGATION_FILE",
+/// defaulting to "propagation_state" in the state directory), and a "propagation" message with the answer of every resolver
+/// is sent when the set of stale resolvers changes, so a partial propagation is reported until every resolver is current.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `wan_ip`: A `&str` that specifies the WAN IP address.
+/// * `resolvers`: A `&[String]` that holds the resolvers to compare.
+pub fn check_propagation(hostname: &str, wan_ip: &str, resolvers: &[String]) {
+    if resolvers.is_empty() {
+        return;
+    }
+    if wan_ip.is_empty() {
+        log::debug!("No WAN IP address, skipping propagation check");
+        return;
+    }
+    let propagation_file = state_path("PROPAGATION_FILE", "propagation_state");
+
+    let policy = ComparePolicy::from_env().unwrap_or(ComparePolicy::Exact);
+    let answers: Vec<Answer> = dns::resolve_with_each(hostname, resolvers)
+        .into_iter()
+        .map(|(resolver, result)| {
+            let addresses = match result {
+                Ok(addresses) => Some(addresses),
+                Err(err) => {
+                    log::warn!("{}", err);
+                    None
+                }
+            };
+            let current = addresses
+                .as_ref()
+                .is_some_and(|addresses| policy.in_sync(addresses, wan_ip, &[]));
+            Answer {
+                resolver,
+                addresses,
+                current,
+            }
+        })
+        .collect();
+    log::debug!("Answers for {}: {:?}", hostname, answers);
+    if answers.iter().all(|answer| answer.addresses.is_none()) {
+        log::debug!("No resolver answered, skipping propagation check");
+        return;
+    }
+
+    let stale = stale_resolvers(&answers);
+    let state = if stale.is_empty() {
+        "ok".to_string()
+    } else {
+        stale.join(",")
+    };
+    let previous = read_state(&propagation_file);
+    if previous == state {
+        return;
+    }
+    if previous.is_empty() && stale.is_empty() {
+        log::info!(
+            "Every resolver answers {} with the WAN IP address",
+            hostname
+        );
+    } else {
+        let text = propagation_message(hostname, wan_ip, &answers, &stale);
+        log::info!("{}", text);
+        let message = Message::new("propagation", &text)
+            .with_field("hostname", hostname)
+            .with_field("wan_ip", wan_ip)
+            .with_field("stale_resolvers", &stale.join(", "));
+        if !events::alert(&message) {
+            log::warn!("Failed to send propagation message");
+            return;
+        }
+    }
+    write_state(&propagation_file, &state);
+}
+
+/// Returns the resolvers that answered with addresses other than the WAN IP address.
+///
+/// # Arguments
+///
+/// * `answers`: A `&[Answer]` that holds the answer of every resolver.
+///
+/// # Returns
+///
+/// * A `Vec<String>` that holds the stale resolvers in the order of the answers; resolvers without an answer are not stale.
+fn stale_resolvers(answers: &[Answer]) -> Vec<String> {
+    answers
+        .iter()
+        .filter(|answer| answer.addresses.is_some() && !answer.current)
+        .map(|answer| answer.resolver.clone())
+        .collect()
+}
+
+/// Builds the text of the propagation message.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `wan_ip`: A `&str` that specifies the WAN IP address.
+/// * `answers`: A `&[Answer]` that holds the answer of every resolver.
+/// * `stale`: A `&[String]` that holds the stale resolvers, see `stale_resolvers`.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message, with a line per resolver.
+fn propagation_message(
+    hostname: &str,
+    wan_ip: &str,
+    answers: &[Answer],
+    stale: &[String],
+) -> String {
+    let headline = if stale.is_empty() {
+        "DNS update has propagated to every resolver".to_string()
+    } else {
+        format!(
+            "DNS update has not propagated to {} of {} resolvers!",
+            stale.len(),
+            answers.len()
+        )
+    };
+    let lines: Vec<String> = answers
+        .iter()
+        .map(|answer| match &answer.addresses {
+            None => format!("{}: no answer", answer.resolver),
+            Some(addresses) if addresses.is_empty() => {
+                format!("{}: no address (stale)", answer.resolver)
+            }
+            Some(addresses) => format!(
+                "{}: {} ({})",
+                answer.resolver,
+                addresses.join(", "),
+                if answer.current { "current" } else { "stale" }
+            ),
+        })
+        .collect();
+    format!(
+        "{}\nHostname: {}\nWAN IP: {}\n{}",
+        headline,
+        display_hostname(hostname),
+        wan_ip,
+        lines.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(resolver: &str, addresses: Option<&[&str]>, current: bool) -> Answer {
+        Answer {
+            resolver: resolver.to_string(),
+            addresses: addresses
+                .map(|addresses| addresses.iter().map(|ip| ip.to_string()).collect()),
+            current,
+        }
+    }
+
+    #[test]
+    fn test_propagation_message() {
+        let answers = vec![
+            answer("google", Some(&["1.1.1.1"]), true),
+            answer("cloudflare", Some(&["2.2.2.2"]), false),
+            answer("quad9", None, false),
+            answer("opendns", Some(&[]), false),
+        ];
+        let stale = stale_resolvers(&answers);
+        assert_eq!(stale, vec!["cloudflare", "opendns"]);
+        assert_eq!(
+            propagation_message("home.example.com", "1.1.1.1", &answers, &stale),
+            "DNS update has not propagated to 2 of 4 resolvers!\nHostname: home.example.com\nWAN IP: 1.1.1.1\n\
+             google: 1.1.1.1 (current)\ncloudflare: 2.2.2.2 (stale)\nquad9: no answer\nopendns: no address (stale)"
+        );
+
+        let answers = vec![
+            answer("google", Some(&["1.1.1.1"]), true),
+            answer("quad9", None, false),
+        ];
+        assert!(
+            propagation_message("home.example.com", "1.1.1.1", &answers, &[])
+                .starts_with("DNS update has propagated to every resolver\n")
+        );
+    }
+}
@@ -0,0 +1,298 @@
+use crate::alarm::{self, Action};
+use crate::api;
+use crate::check::{self, Context};
+use crate::compare::ComparePolicies;
+use crate::events::{Event, Subscriber};
+use crate::failover;
+use crate::script::CompareScript;
+use crate::vars::split_list;
+use chrono::{DateTime, Duration as ChronoDuration, FixedOffset, Local};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// The body of the last router API response of the running cycle, see `capture_api_response`.
+static API_RESPONSE: Mutex<Option<String>> = Mutex::new(None);
+
+/// The raw inputs of one check cycle, as recorded in the record file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cycle {
+    /// When the cycle ran, in RFC 3339 format.
+    pub timestamp: String,
+    /// The monitored hostname.
+    pub hostname: String,
+    /// The addresses from DNS in the order of the answer.
+    pub dns_ips: Vec<String>,
+    /// The source the WAN IP addresses were taken from.
+    pub wan_source: String,
+    /// The interface name and address of each interface, as retrieved from the source.
+    pub wan_ips: Vec<(String, String)>,
+    /// The body of the router API response, if the addresses were taken from the router API.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_response: Option<String>,
+}
+
+/// Retrieves the path of the record file from the "RECORD_FILE" environment variable, if recording is enabled.
+fn record_file() -> Option<String> {
+    env::var("RECORD_FILE").ok().filter(|path| !path.is_empty())
+}
+
+/// Keeps the body of a router API response for the record of the running cycle, if recording is enabled.
+///
+/// # Arguments
+///
+/// * `response`: A `&str` that holds the body of the response.
+pub fn capture_api_response(response: &str) {
+    if record_file().is_some() {
+        *API_RESPONSE.lock().unwrap_or_else(|e| e.into_inner()) = Some(response.to_string());
+    }
+}
+
+/// Records the verifications published on the event bus (see `events::publish`) in the record file,
+/// if "RECORD_FILE" is set, see `record_cycle`.
+pub struct ReplaySubscriber;
+
+impl Subscriber for ReplaySubscriber {
+    fn name(&self) -> &str {
+        "replay"
+    }
+
+    fn handles(&self, event: &Event) -> bool {
+        matches!(event, Event::Checked(_)) && record_file().is_some()
+    }
+
+    fn receive(&self, event: &Event) -> bool {
+        if let Event::Checked(context) = event {
+            record_cycle(context);
+        }
+        true
+    }
+}
+
+/// Appends the raw inputs of a check cycle to the record file.
+///
+/// This function retrieves the path of the record file from the "RECORD_FILE" environment variable.
+/// If it is not set, the function returns immediately.
+/// The addresses from DNS and the WAN IP addresses are taken from the context, and the body of the router API response
+/// from `capture_api_response`. Each cycle is appended as a line of JSON, so a recording can be replayed with `replay`.
+///
+/// # Arguments
+///
+/// * `context`: A `&Context` that holds the result of the cycle.
+pub fn record_cycle(context: &Context) {
+    let path = match record_file() {
+        Some(path) => path,
+        None => return,
+    };
+    let api_response = API_RESPONSE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take();
+    let cycle = Cycle {
+        timestamp: Local::now().to_rfc3339(),
+        hostname: context.hostname.clone(),
+        dns_ips: context.dns_ips.clone(),
+        wan_source: context.wan_source.clone(),
+        wan_ips: context.wan_ips.clone(),
+        api_response: api_response.filter(|_| context.wan_source == "api"),
+    };
+    let line = match serde_json::to_string(&cycle) {
+        Ok(line) => line,
+        Err(e) => {
+            log::warn!("Failed to record cycle: {}", e);
+            return;
+        }
+    };
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{}", line));
+    if let Err(e) = written {
+        log::warn!("Failed to write record file {}: {}", path, e);
+    }
+}
+
+/// Loads a recording.
+///
+/// # Arguments
+///
+/// * `path`: A `&str` that specifies the path of the record file.
+///
+/// # Returns
+///
+/// * A `Result<Vec<Cycle>, String>` that holds the cycles in order, or an `Err` with a message if the file
+///   cannot be read or a line is not a cycle.
+pub fn load_cycles(path: &str) -> Result<Vec<Cycle>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read record file {}: {}", path, e))?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| format!("Invalid cycle on line {}: {}", index + 1, e))
+        })
+        .collect()
+}
+
+/// Re-runs the decision logic against a recording, without sending anything.
+///
+/// For each cycle, the router API response is parsed again for the interfaces in "INTERFACE" (falling back to the
+/// recorded WAN IP addresses if there is no response), the interface is selected (see `check::select_interface`),
+/// and the addresses are compared with the comparison script and policies of the environment
+/// (see `check::compare_addresses`). The alarm state machine (see `alarm::next_action`) then runs
+/// on the times of the recording, starting without an alarm.
+///
+/// # Arguments
+///
+/// * `cycles`: A `&[Cycle]` that holds the recording.
+///
+/// # Returns
+///
+/// * A `Result<Vec<String>, String>` that holds a line per cycle describing the decision,
+///   or an `Err` with a message if the comparison script or policies or a timestamp are invalid.
+pub fn replay(cycles: &[Cycle]) -> Result<Vec<String>, String> {
+    let script = CompareScript::from_env()?;
+    let policies = ComparePolicies::from_env()?;
+    let interfaces = split_list(&env::var("INTERFACE").unwrap_or_default());
+    let mut alarm_at: Option<DateTime<FixedOffset>> = None;
+    let mut recovery_at: Option<DateTime<FixedOffset>> = None;
+    let within = |sent: Option<DateTime<FixedOffset>>, now: DateTime<FixedOffset>, hours| {
+        sent.is_some_and(|sent| now - sent < ChronoDuration::try_hours(hours).unwrap_or_default())
+    };
+    let mut lines = Vec::new();
+    for cycle in cycles {
+        let now = DateTime::parse_from_rfc3339(&cycle.timestamp)
+            .map_err(|e| format!("Invalid timestamp {}: {}", cycle.timestamp, e))?;
+        let mut context = Context {
+            dns_ip: cycle.dns_ips.first().cloned().unwrap_or_default(),
+            dns_ips: cycle.dns_ips.clone(),
+            wan_source: cycle.wan_source.clone(),
+            wan_ips: match (&cycle.api_response, interfaces.is_empty()) {
+                (Some(response), false) => api::parse_wan_ips(response, &interfaces),
+                _ => cycle.wan_ips.clone(),
+            },
+            ..Context::new(&cycle.hostname)
+        };
+        check::select_interface(&mut context);
+        let addresses = format!(
+            "DNS {}, WAN {} ({})",
+            or_dash(&context.dns_ips.join(", ")),
+            or_dash(&context.wan_ip),
+            or_dash(&context.interface)
+        );
+        let in_sync = check::compare_addresses(script.as_ref(), &policies, &context, now);
+        let reason = match in_sync {
+            Some(false) if context.wan_source != "hostname" => {
+                failover::wan_down_reason(&context.wan_ip)
+            }
+            _ => None,
+        };
+        let decision = match (in_sync, reason) {
+            (_, Some(reason)) => format!("WAN down, {}", reason),
+            (None, _) => "not compared".to_string(),
+            (Some(in_sync), None) => {
+                let action = alarm::next_action(
+                    within(alarm_at, now, alarm::ALARM_HOURS),
+                    within(recovery_at, now, alarm::RECOVERY_HOURS),
+                    in_sync,
+                );
+                match action {
+                    Action::Alarm => {
+                        alarm_at = Some(now);
+                        "mismatch, alarm sent".to_string()
+                    }
+                    Action::Recovery => {
+                        alarm_at = None;
+                        recovery_at = Some(now);
+                        "in sync, recovery sent".to_string()
+                    }
+                    Action::Unchanged if in_sync => "in sync".to_string(),
+                    Action::Unchanged => "mismatch, no alarm sent".to_string(),
+                }
+            }
+        };
+        lines.push(format!(
+            "{} {}: {}",
+            now.format("%Y-%m-%d %H:%M:%S"),
+            addresses,
+            decision
+        ));
+    }
+    Ok(lines)
+}
+
+/// Returns the value, or "-" if it is empty.
+fn or_dash(value: &str) -> String {
+    match value {
+        "" => "-".to_string(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    fn cycle(minutes: i64, dns_ip: &str, wan_ip: &str) -> Cycle {
+        let start = DateTime::parse_from_rfc3339("2024-05-01T12:00:00+02:00").unwrap();
+        Cycle {
+            timestamp: (start + ChronoDuration::try_minutes(minutes).unwrap()).to_rfc3339(),
+            hostname: "home.example.com".to_string(),
+            dns_ips: vec![dns_ip.to_string()],
+            wan_source: "api".to_string(),
+            wan_ips: vec![("igb0".to_string(), wan_ip.to_string())],
+            api_response: None,
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let cycles = vec![
+            cycle(0, "1.1.1.1", "1.1.1.1"),
+            cycle(1, "1.1.1.1", "2.2.2.2"),
+            cycle(2, "1.1.1.1", "2.2.2.2"),
+            cycle(3, "2.2.2.2", "2.2.2.2"),
+            // Assert that a mismatch shortly after a recovery is not alerted
+            cycle(4, "1.1.1.1", "2.2.2.2"),
+            cycle(5, "", "2.2.2.2"),
+        ];
+        let lines = replay(&cycles).unwrap();
+        assert_eq!(
+            lines,
+            [
+                "2024-05-01 12:00:00 DNS 1.1.1.1, WAN 1.1.1.1 (igb0): in sync",
+                "2024-05-01 12:01:00 DNS 1.1.1.1, WAN 2.2.2.2 (igb0): mismatch, alarm sent",
+                "2024-05-01 12:02:00 DNS 1.1.1.1, WAN 2.2.2.2 (igb0): mismatch, no alarm sent",
+                "2024-05-01 12:03:00 DNS 2.2.2.2, WAN 2.2.2.2 (igb0): in sync, recovery sent",
+                "2024-05-01 12:04:00 DNS 1.1.1.1, WAN 2.2.2.2 (igb0): mismatch, no alarm sent",
+                "2024-05-01 12:05:00 DNS -, WAN 2.2.2.2 (igb0): not compared",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_cycle() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        env::set_var("RECORD_FILE", &path);
+        capture_api_response(r#"{"igb0": {"ipv4": [{"ipaddr": "1.1.1.1"}]}}"#);
+        let context = Context {
+            dns_ips: vec!["1.1.1.1".to_string()],
+            wan_source: "api".to_string(),
+            wan_ips: vec![("igb0".to_string(), "1.1.1.1".to_string())],
+            ..Context::new("home.example.com")
+        };
+        record_cycle(&context);
+        env::remove_var("RECORD_FILE");
+
+        let cycles = load_cycles(&path).unwrap();
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].dns_ips, ["1.1.1.1"]);
+        assert!(cycles[0].api_response.as_ref().unwrap().contains("igb0"));
+    }
+}
@@ -0,0 +1,214 @@
+use crate::check::Context;
+use chrono::{Datelike, Local, Timelike};
+use ipnet::IpNet;
+use rhai::{Dynamic, Engine, Scope, AST};
+use std::env;
+use std::net::IpAddr;
+
+/// The maximum number of operations a script may run per check, so a runaway loop cannot stall the checker.
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// The decision of a comparison script.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    /// Compare the IP addresses as usual.
+    Default,
+    /// Treat the IP addresses as in sync.
+    InSync,
+    /// Treat the IP addresses as mismatched.
+    Mismatch,
+    /// Skip the comparison of this check.
+    Skip,
+}
+
+/// A Rhai script that decides how the IP addresses are compared.
+///
+/// The script sees the variables `hostname`, `dns_ip`, `wan_ip`, `interface`, `hour`, `minute` and `weekday`
+/// (1 for Monday to 7 for Sunday, local time), and can call `in_network(ip, "192.0.2.0/24")`.
+/// Its last value is the decision: `true` (in sync), `false` (mismatch), `"skip"` or `()` (compare as usual).
+pub struct CompareScript {
+    path: String,
+    engine: Engine,
+    ast: AST,
+}
+
+impl CompareScript {
+    /// Loads the script in the "COMPARE_SCRIPT" environment variable, if it is set.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<Option<CompareScript>, String>` that holds the compiled script, or `None` if no script is configured.
+    /// * If the script cannot be read or compiled, it returns an `Err` with a message.
+    pub fn from_env() -> Result<Option<CompareScript>, String> {
+        match env::var("COMPARE_SCRIPT") {
+            Ok(path) => {
+                let source = std::fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to read COMPARE_SCRIPT {}: {}", path, e))?;
+                CompareScript::compile(&path, &source).map(Some)
+            }
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Compiles a script.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: A `&str` that specifies where the script was loaded from, used in messages.
+    /// * `source`: A `&str` that holds the source of the script.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<CompareScript, String>` that holds the compiled script, or an `Err` with the compile error.
+    fn compile(path: &str, source: &str) -> Result<CompareScript, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.register_fn("in_network", in_network);
+        let ast = engine
+            .compile(source)
+            .map_err(|e| format!("Failed to compile COMPARE_SCRIPT {}: {}", path, e))?;
+        Ok(CompareScript {
+            path: path.to_string(),
+            engine,
+            ast,
+        })
+    }
+
+    /// Runs the script for a check.
+    ///
+    /// If the script fails or returns an unexpected value, it logs a warning and the IP addresses are compared as usual.
+    ///
+    /// # Arguments
+    ///
+    /// * `context`: A `&Context` that holds the results of the previous checks.
+    ///
+    /// # Returns
+    ///
+    /// * A `Decision` that specifies how the IP addresses are compared.
+    pub fn decide(&self, context: &Context) -> Decision {
+        let now = Local::now();
+        let mut scope = Scope::new();
+        scope.push_constant("hostname", context.hostname.clone());
+        scope.push_constant("dns_ip", context.dns_ip.clone());
+        scope.push_constant("wan_ip", context.wan_ip.clone());
+        scope.push_constant("interface", context.interface.clone());
+        scope.push_constant("hour", now.hour() as i64);
+        scope.push_constant("minute", now.minute() as i64);
+        scope.push_constant("weekday", now.weekday().number_from_monday() as i64);
+
+        match self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast)
+        {
+            Ok(value) => to_decision(value).unwrap_or_else(|value| {
+                log::warn!(
+                    "COMPARE_SCRIPT {} returned {}, expected true, false, \"skip\" or ()",
+                    self.path,
+                    value
+                );
+                Decision::Default
+            }),
+            Err(e) => {
+                log::warn!("COMPARE_SCRIPT {} failed: {}", self.path, e);
+                Decision::Default
+            }
+        }
+    }
+}
+
+/// Converts the value of a script to a decision.
+///
+/// # Arguments
+///
+/// * `value`: A `rhai::Dynamic` that holds the value of the script.
+///
+/// # Returns
+///
+/// * A `Result<Decision, String>` that holds the decision, or an `Err` with the unexpected value.
+fn to_decision(value: Dynamic) -> Result<Decision, String> {
+    if value.is_unit() {
+        return Ok(Decision::Default);
+    }
+    if let Some(in_sync) = value.clone().try_cast::<bool>() {
+        return Ok(if in_sync {
+            Decision::InSync
+        } else {
+            Decision::Mismatch
+        });
+    }
+    match value.clone().into_string().as_deref() {
+        Ok("skip") => Ok(Decision::Skip),
+        _ => Err(value.to_string()),
+    }
+}
+
+/// Checks whether an IP address is part of a network, e.g. `in_network("192.0.2.7", "192.0.2.0/24")`.
+///
+/// Invalid addresses or networks are never part of each other.
+fn in_network(ip: &str, network: &str) -> bool {
+    match (ip.parse::<IpAddr>(), network.parse::<IpNet>()) {
+        (Ok(ip), Ok(network)) => network.contains(&ip),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(dns_ip: &str, wan_ip: &str) -> Context {
+        Context {
+            hostname: "home.example.com".to_string(),
+            dns_ip: dns_ip.to_string(),
+            wan_ip: wan_ip.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_decide() {
+        let script = CompareScript::compile(
+            "test.rhai",
+            r#"
+                if dns_ip == wan_ip { return (); }
+                if in_network(dns_ip, "203.0.113.0/24") && in_network(wan_ip, "203.0.113.0/24") {
+                    return true;
+                }
+                if hostname == "skip.example.com" { return "skip"; }
+                false
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            script.decide(&context("1.1.1.1", "1.1.1.1")),
+            Decision::Default
+        );
+        assert_eq!(
+            script.decide(&context("203.0.113.7", "203.0.113.9")),
+            Decision::InSync
+        );
+        assert_eq!(
+            script.decide(&context("198.51.100.4", "203.0.113.9")),
+            Decision::Mismatch
+        );
+    }
+
+    #[test]
+    fn test_decide_errors() {
+        // Assert that compile errors are reported
+        assert!(CompareScript::compile("test.rhai", "if {").is_err());
+
+        // Assert that unexpected values and runaway scripts fall back to the default comparison
+        let script = CompareScript::compile("test.rhai", "42").unwrap();
+        assert_eq!(
+            script.decide(&context("1.1.1.1", "2.2.2.2")),
+            Decision::Default
+        );
+        let script = CompareScript::compile("test.rhai", "loop {}").unwrap();
+        assert_eq!(
+            script.decide(&context("1.1.1.1", "2.2.2.2")),
+            Decision::Default
+        );
+    }
+}
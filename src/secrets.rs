@@ -0,0 +1,695 @@
+use crate::config::SETTINGS;
+use crate::redact::SECRET_VARS;
+use std::env;
+
+/// The prefix of a reference to a secret in AWS Secrets Manager.
+const AWS_PREFIX: &str = "aws-sm:";
+
+/// The prefix of a reference to a secret in GCP Secret Manager.
+const GCP_PREFIX: &str = "gcp-sm:";
+
+/// The service the secrets are stored under in the OS keyring.
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "dnschecker";
+
+/// Replaces references to secrets in cloud secret managers with the secrets.
+///
+/// This function checks the environment variable of every setting, including those set from the config.
+/// A value of the form "aws-sm:<secret ID or ARN>[#<JSON key>]" is read from AWS Secrets Manager
+/// (see `fetch_aws_secret`), a value of the form "gcp-sm:<project>/<secret>[/<version>]" from GCP Secret Manager
+/// (see `fetch_gcp_secret`). Both use the credentials of the instance, so no credentials need to be configured.
+///
+/// The backends are behind the "aws-secrets" and "gcp-secrets" cargo features.
+/// With the "keyring" feature, secrets that are not set at all are first read from the OS keyring (see `read_keyring`).
+///
+/// # Returns
+///
+/// * A `Result<(), String>` that is `Ok` if every reference was replaced, or an `Err` with a message for each failure.
+pub fn resolve_references() -> Result<(), String> {
+    #[cfg(feature = "keyring")]
+    read_keyring();
+    let mut errors = Vec::new();
+    for setting in SETTINGS {
+        let value = match env::var(setting.name) {
+            Ok(value) if is_reference(&value) => value,
+            _ => continue,
+        };
+        match resolve(&value) {
+            Ok(secret) => {
+                log::debug!("Read {} from the secret manager", setting.name);
+                env::set_var(setting.name, secret);
+            }
+            Err(e) => errors.push(format!("{}: {}", setting.name, e)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+/// Looks up the secret setting of a keyring entry name, e.g. "telegram-token" for "TELEGRAM_TOKEN".
+///
+/// # Arguments
+///
+/// * `name`: A `&str` that holds the entry name, in lower case with dashes or as the environment variable.
+///
+/// # Returns
+///
+/// * A `Result<&'static str, String>` that holds the name of the environment variable,
+///   or an `Err` with a message if it is not a secret setting.
+pub fn secret_setting(name: &str) -> Result<&'static str, String> {
+    let variable = name.to_uppercase().replace('-', "_");
+    SECRET_VARS
+        .iter()
+        .find(|secret| **secret == variable && SETTINGS.iter().any(|s| s.name == variable))
+        .copied()
+        .ok_or(format!(
+            "{} is not a secret setting, expected one of: {}",
+            name,
+            keyring_names().join(", ")
+        ))
+}
+
+/// Returns the keyring entry names of the secret settings, e.g. "telegram-token".
+fn keyring_names() -> Vec<String> {
+    SECRET_VARS
+        .iter()
+        .filter(|secret| SETTINGS.iter().any(|s| s.name == **secret))
+        .map(|secret| secret.to_lowercase().replace('_', "-"))
+        .collect()
+}
+
+/// Reads the secret settings that are not set from the OS keyring.
+///
+/// The secrets are stored by `store_secret` in the keyring of the user (Secret Service on Linux,
+/// the Keychain on macOS, the Credential Manager on Windows) under the service "dnschecker",
+/// with the entry name of the setting (see `keyring_names`). Settings that are set take precedence.
+#[cfg(feature = "keyring")]
+fn read_keyring() {
+    for name in keyring_names() {
+        let variable = name.to_uppercase().replace('-', "_");
+        if env::var(&variable).is_ok() {
+            continue;
+        }
+        let secret = keyring::Entry::new(KEYRING_SERVICE, &name).and_then(|e| e.get_password());
+        match secret {
+            Ok(secret) => env::set_var(variable, secret),
+            Err(keyring::Error::NoEntry) => {}
+            Err(e) => eprintln!("Failed to read {} from the keyring: {}", name, e),
+        }
+    }
+}
+
+/// Stores a secret setting in the OS keyring, see `read_keyring`.
+///
+/// # Arguments
+///
+/// * `name`: A `&str` that holds the entry name, e.g. "telegram-token".
+/// * `value`: An `Option<&str>` that holds the secret, or `None` to delete it.
+///
+/// # Returns
+///
+/// * A `Result<(), String>` that is `Ok` if the keyring was updated, or an `Err` with a message if not.
+pub fn store_secret(name: &str, value: Option<&str>) -> Result<(), String> {
+    let variable = secret_setting(name)?;
+    #[cfg(feature = "keyring")]
+    {
+        let entry_name = variable.to_lowercase().replace('_', "-");
+        let entry = keyring::Entry::new(KEYRING_SERVICE, &entry_name).map_err(|e| e.to_string())?;
+        match value {
+            Some(value) => entry.set_password(value),
+            None => entry.delete_credential(),
+        }
+        .map_err(|e| format!("Failed to update {} in the keyring: {}", entry_name, e))
+    }
+    #[cfg(not(feature = "keyring"))]
+    {
+        let _ = value;
+        Err(format!(
+            "Cannot store {}, built without the keyring feature",
+            variable
+        ))
+    }
+}
+
+/// Returns whether a value references a secret in a cloud secret manager.
+fn is_reference(value: &str) -> bool {
+    value.starts_with(AWS_PREFIX) || value.starts_with(GCP_PREFIX)
+}
+
+/// Reads a referenced secret.
+///
+/// # Arguments
+///
+/// * `value`: A `&str` that holds the reference, see `resolve_references`.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds the secret, or an `Err` with a message if it cannot be read.
+fn resolve(value: &str) -> Result<String, String> {
+    if let Some(reference) = value.strip_prefix(AWS_PREFIX) {
+        #[cfg(feature = "aws-secrets")]
+        return aws::fetch_aws_secret(reference);
+        #[cfg(not(feature = "aws-secrets"))]
+        return Err(format!(
+            "cannot read {}, built without the aws-secrets feature",
+            reference
+        ));
+    }
+    if let Some(reference) = value.strip_prefix(GCP_PREFIX) {
+        #[cfg(feature = "gcp-secrets")]
+        return gcp::fetch_gcp_secret(reference);
+        #[cfg(not(feature = "gcp-secrets"))]
+        return Err(format!(
+            "cannot read {}, built without the gcp-secrets feature",
+            reference
+        ));
+    }
+    Ok(value.to_string())
+}
+
+/// Selects a key of a secret that holds a JSON object, as AWS Secrets Manager stores key/value secrets.
+///
+/// # Arguments
+///
+/// * `secret`: A `&str` that holds the secret.
+/// * `key`: An `Option<&str>` that specifies the key, `None` to use the whole secret.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds the value, or an `Err` with a message if the secret has no such key.
+#[cfg_attr(not(feature = "aws-secrets"), allow(dead_code))]
+fn select_key(secret: &str, key: Option<&str>) -> Result<String, String> {
+    let key = match key {
+        Some(key) => key,
+        None => return Ok(secret.to_string()),
+    };
+    let json: serde_json::Value =
+        serde_json::from_str(secret).map_err(|_| "secret is not a JSON object".to_string())?;
+    match &json[key] {
+        serde_json::Value::String(value) => Ok(value.clone()),
+        serde_json::Value::Null => Err(format!("secret has no key {}", key)),
+        value => Ok(value.to_string()),
+    }
+}
+
+#[cfg(feature = "aws-secrets")]
+mod aws {
+    use super::select_key;
+    use crate::http;
+    use chrono::{DateTime, Utc};
+    use hmac::{Hmac, Mac};
+    use serde_json::{json, Value};
+    use sha2::{Digest, Sha256};
+    use std::env;
+    use std::time::Duration;
+
+    /// The instance metadata service of EC2.
+    const IMDS: &str = "http://169.254.169.254";
+
+    /// The credentials endpoint of ECS tasks.
+    const ECS_CREDENTIALS: &str = "http://169.254.170.2";
+
+    /// Temporary or long-term AWS credentials.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Credentials {
+        pub access_key_id: String,
+        pub secret_access_key: String,
+        pub session_token: Option<String>,
+    }
+
+    /// Reads a secret from AWS Secrets Manager.
+    ///
+    /// The credentials are taken from the "AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY" and "AWS_SESSION_TOKEN"
+    /// environment variables, or else from the role of the ECS task or the EC2 instance (see `credentials`).
+    /// The region is taken from the ARN of the secret, the "AWS_REGION" or "AWS_DEFAULT_REGION" environment variables,
+    /// or else from the instance metadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `reference`: A `&str` that holds the secret ID or ARN, optionally followed by "#" and a JSON key.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<String, String>` that holds the secret, or an `Err` with a message if it cannot be read.
+    pub fn fetch_aws_secret(reference: &str) -> Result<String, String> {
+        let (secret_id, key) = match reference.split_once('#') {
+            Some((secret_id, key)) => (secret_id, Some(key)),
+            None => (reference, None),
+        };
+        let region = match secret_id.split(':').nth(3) {
+            Some(region) if secret_id.starts_with("arn:") => region.to_string(),
+            _ => region()?,
+        };
+        let credentials = credentials()?;
+        let endpoint = format!("https://secretsmanager.{}.amazonaws.com", region);
+        let secret = get_secret_value(&endpoint, &region, &credentials, secret_id, Utc::now())?;
+        select_key(&secret, key)
+    }
+
+    /// Retrieves the region of the instance.
+    fn region() -> Result<String, String> {
+        if let Ok(region) = env::var("AWS_REGION").or(env::var("AWS_DEFAULT_REGION")) {
+            return Ok(region);
+        }
+        imds_get("/latest/meta-data/placement/region")
+            .map_err(|e| format!("cannot determine the AWS region, set AWS_REGION: {}", e))
+    }
+
+    /// Retrieves the AWS credentials from the environment, the ECS task role or the EC2 instance role.
+    fn credentials() -> Result<Credentials, String> {
+        if let (Ok(access_key_id), Ok(secret_access_key)) = (
+            env::var("AWS_ACCESS_KEY_ID"),
+            env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            return Ok(Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: env::var("AWS_SESSION_TOKEN").ok(),
+            });
+        }
+        let document = match env::var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI") {
+            Ok(uri) => http_get(&format!("{}{}", ECS_CREDENTIALS, uri), &[])?,
+            Err(_) => {
+                let role = imds_get("/latest/meta-data/iam/security-credentials/")?;
+                let role = role.lines().next().unwrap_or_default().trim().to_string();
+                imds_get(&format!(
+                    "/latest/meta-data/iam/security-credentials/{}",
+                    role
+                ))?
+            }
+        };
+        parse_credentials(&document)
+    }
+
+    /// Parses the credentials document of the instance metadata service or the ECS credentials endpoint.
+    pub fn parse_credentials(document: &str) -> Result<Credentials, String> {
+        let json: Value = serde_json::from_str(document)
+            .map_err(|e| format!("invalid AWS credentials document: {}", e))?;
+        let field = |name: &str| json[name].as_str().map(String::from);
+        match (field("AccessKeyId"), field("SecretAccessKey")) {
+            (Some(access_key_id), Some(secret_access_key)) => Ok(Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: field("Token"),
+            }),
+            _ => Err("AWS credentials document has no access key".to_string()),
+        }
+    }
+
+    /// Reads a path of the EC2 instance metadata service, using an IMDSv2 session token.
+    fn imds_get(path: &str) -> Result<String, String> {
+        let client = http::client()?;
+        let token = client
+            .put(format!("{}/latest/api/token", IMDS))
+            .header("X-aws-ec2-metadata-token-ttl-seconds", "60")
+            .timeout(Duration::from_secs(2))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text())
+            .map_err(|e| format!("instance metadata service is not available: {}", e))?;
+        http_get(
+            &format!("{}{}", IMDS, path),
+            &[("X-aws-ec2-metadata-token", &token)],
+        )
+    }
+
+    /// Sends a GET request and returns the body of a successful response.
+    fn http_get(url: &str, headers: &[(&str, &str)]) -> Result<String, String> {
+        let mut request = http::client()?.get(url).timeout(Duration::from_secs(2));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text())
+            .map_err(|e| format!("failed to read {}: {}", url, e))
+    }
+
+    /// Calls the "GetSecretValue" action of AWS Secrets Manager.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint`: A `&str` that specifies the URL of the Secrets Manager endpoint of the region.
+    /// * `region`: A `&str` that specifies the region.
+    /// * `credentials`: A `&Credentials` that holds the credentials the request is signed with.
+    /// * `secret_id`: A `&str` that specifies the secret ID or ARN.
+    /// * `now`: A `DateTime<Utc>` that specifies the time of the request.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<String, String>` that holds the "SecretString", or an `Err` with a message if the call fails.
+    pub fn get_secret_value(
+        endpoint: &str,
+        region: &str,
+        credentials: &Credentials,
+        secret_id: &str,
+        now: DateTime<Utc>,
+    ) -> Result<String, String> {
+        let body = json!({"SecretId": secret_id}).to_string();
+        let host = endpoint
+            .split("://")
+            .nth(1)
+            .unwrap_or(endpoint)
+            .trim_end_matches('/');
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1".to_string()),
+            ("host", host.to_string()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        headers.push(("x-amz-target", "secretsmanager.GetSecretValue".to_string()));
+        let authorization = authorization(credentials, region, &amz_date, &headers, &body);
+
+        let mut request = http::client()?
+            .post(endpoint)
+            .header("Authorization", authorization)
+            .timeout(Duration::from_secs(10));
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            request = request.header(*name, value);
+        }
+        let response = request
+            .body(body)
+            .send()
+            .map_err(|e| format!("Failed to make HTTPS request: {}", e))?;
+        let status = response.status();
+        let answer: Value = response
+            .json()
+            .map_err(|e| format!("invalid answer from AWS Secrets Manager: {}", e))?;
+        if !status.is_success() {
+            return Err(format!(
+                "AWS Secrets Manager returned {}: {}",
+                status,
+                answer["message"]
+                    .as_str()
+                    .or(answer["Message"].as_str())
+                    .unwrap_or("no message")
+            ));
+        }
+        answer["SecretString"]
+            .as_str()
+            .map(String::from)
+            .ok_or("secret has no string value".to_string())
+    }
+
+    /// Builds the "Authorization" header of a request to Secrets Manager with AWS Signature Version 4.
+    ///
+    /// # Arguments
+    ///
+    /// * `credentials`: A `&Credentials` that holds the credentials.
+    /// * `region`: A `&str` that specifies the region.
+    /// * `amz_date`: A `&str` that holds the time of the request, e.g. "20240501T120000Z".
+    /// * `headers`: A `&[(&str, String)]` that holds the signed headers, with lower case names in sorted order.
+    /// * `body`: A `&str` that holds the body of the request.
+    ///
+    /// # Returns
+    ///
+    /// * A `String` that holds the value of the header.
+    pub fn authorization(
+        credentials: &Credentials,
+        region: &str,
+        amz_date: &str,
+        headers: &[(&str, String)],
+        body: &str,
+    ) -> String {
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/secretsmanager/aws4_request", date, region);
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<&str>>()
+            .join(";");
+        let canonical_request = format!(
+            "POST\n/\n\n{}\n{}\n{}",
+            canonical_headers,
+            signed_headers,
+            hex::encode(Sha256::digest(body.as_bytes()))
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = signing_key(
+            &credentials.secret_access_key,
+            date,
+            region,
+            "secretsmanager",
+        );
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id,
+            scope,
+            signed_headers,
+            hex::encode(hmac(&key, string_to_sign.as_bytes()))
+        )
+    }
+
+    /// Derives the Signature Version 4 signing key of a day, region and service.
+    pub fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+        let key = hmac(format!("AWS4{}", secret).as_bytes(), date.as_bytes());
+        let key = hmac(&key, region.as_bytes());
+        let key = hmac(&key, service.as_bytes());
+        hmac(&key, b"aws4_request")
+    }
+
+    /// Computes an HMAC-SHA256.
+    fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(data);
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+#[cfg(feature = "gcp-secrets")]
+mod gcp {
+    use crate::http;
+    use base64::Engine;
+    use serde_json::Value;
+    use std::env;
+    use std::time::Duration;
+
+    /// Reads a secret from GCP Secret Manager.
+    ///
+    /// The access token is taken from the metadata server of the instance ("GCE_METADATA_HOST",
+    /// defaulting to "metadata.google.internal"), i.e. from its service account.
+    ///
+    /// # Arguments
+    ///
+    /// * `reference`: A `&str` that holds "<project>/<secret>", optionally followed by "/<version>" (defaulting to "latest").
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<String, String>` that holds the secret, or an `Err` with a message if it cannot be read.
+    pub fn fetch_gcp_secret(reference: &str) -> Result<String, String> {
+        let metadata = format!(
+            "http://{}",
+            env::var("GCE_METADATA_HOST").unwrap_or("metadata.google.internal".to_string())
+        );
+        let token = access_token(&metadata)?;
+        access_secret(
+            "https://secretmanager.googleapis.com",
+            &token,
+            &secret_version_name(reference)?,
+        )
+    }
+
+    /// Builds the resource name of a secret version from a reference.
+    pub fn secret_version_name(reference: &str) -> Result<String, String> {
+        match reference.split('/').collect::<Vec<&str>>().as_slice() {
+            [project, secret] => Ok(format!(
+                "projects/{}/secrets/{}/versions/latest",
+                project, secret
+            )),
+            [project, secret, version] => Ok(format!(
+                "projects/{}/secrets/{}/versions/{}",
+                project, secret, version
+            )),
+            _ => Err(format!(
+                "expected <project>/<secret>[/<version>], got {}",
+                reference
+            )),
+        }
+    }
+
+    /// Retrieves an access token of the service account of the instance from the metadata server.
+    pub fn access_token(metadata: &str) -> Result<String, String> {
+        let url = format!(
+            "{}/computeMetadata/v1/instance/service-accounts/default/token",
+            metadata
+        );
+        let answer: Value = http::client()?
+            .get(&url)
+            .header("Metadata-Flavor", "Google")
+            .timeout(Duration::from_secs(2))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json())
+            .map_err(|e| format!("metadata server is not available: {}", e))?;
+        answer["access_token"]
+            .as_str()
+            .map(String::from)
+            .ok_or("metadata server returned no access token".to_string())
+    }
+
+    /// Calls the "access" method of a secret version.
+    ///
+    /// # Arguments
+    ///
+    /// * `api`: A `&str` that specifies the URL of the Secret Manager API.
+    /// * `token`: A `&str` that holds the access token.
+    /// * `name`: A `&str` that specifies the resource name of the secret version.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<String, String>` that holds the payload of the secret, or an `Err` with a message if the call fails.
+    pub fn access_secret(api: &str, token: &str, name: &str) -> Result<String, String> {
+        let response = http::client()?
+            .get(format!("{}/v1/{}:access", api, name))
+            .bearer_auth(token)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .map_err(|e| format!("Failed to make HTTPS request: {}", e))?;
+        let status = response.status();
+        let answer: Value = response
+            .json()
+            .map_err(|e| format!("invalid answer from GCP Secret Manager: {}", e))?;
+        if !status.is_success() {
+            return Err(format!(
+                "GCP Secret Manager returned {}: {}",
+                status,
+                answer["error"]["message"].as_str().unwrap_or("no message")
+            ));
+        }
+        let data = answer["payload"]["data"]
+            .as_str()
+            .ok_or("secret has no payload".to_string())?;
+        let payload = base64::engine::general_purpose::STANDARD
+            .decode(data)
+            .map_err(|_| "secret payload is not valid base64".to_string())?;
+        String::from_utf8(payload).map_err(|_| "secret payload is not UTF-8".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_key() {
+        let secret = r#"{"telegram_token": "123456:ABC-DEF", "port": 443}"#;
+        assert_eq!(select_key(secret, None).unwrap(), secret);
+        assert_eq!(
+            select_key(secret, Some("telegram_token")).unwrap(),
+            "123456:ABC-DEF"
+        );
+        assert_eq!(select_key(secret, Some("port")).unwrap(), "443");
+        assert!(select_key(secret, Some("chat_id")).is_err());
+        assert!(select_key("plain", Some("chat_id")).is_err());
+        assert!(is_reference("aws-sm:dnschecker"));
+        assert!(!is_reference("123456:ABC-DEF"));
+    }
+
+    #[test]
+    fn test_secret_setting() {
+        assert_eq!(secret_setting("telegram-token").unwrap(), "TELEGRAM_TOKEN");
+        assert_eq!(secret_setting("API_SECRET").unwrap(), "API_SECRET");
+        // Not a setting, although it is redacted
+        assert!(secret_setting("config-key").is_err());
+        assert!(secret_setting("dns-hostname")
+            .unwrap_err()
+            .contains("telegram-token"));
+    }
+
+    #[cfg(feature = "aws-secrets")]
+    #[test]
+    fn test_aws() {
+        use httpmock::prelude::*;
+
+        // The signing key example of the AWS Signature Version 4 documentation
+        let key = aws::signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex::encode(key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .header("x-amz-target", "secretsmanager.GetSecretValue")
+                .header_exists("authorization")
+                .json_body_partial(r#"{"SecretId": "dnschecker"}"#);
+            then.status(200)
+                .body(r#"{"Name": "dnschecker", "SecretString": "123456:ABC-DEF"}"#);
+        });
+        let credentials = aws::parse_credentials(
+            r#"{"AccessKeyId": "AKIDEXAMPLE", "SecretAccessKey": "secret", "Token": "token"}"#,
+        )
+        .unwrap();
+        assert_eq!(credentials.session_token.as_deref(), Some("token"));
+        let result = aws::get_secret_value(
+            &server.base_url(),
+            "eu-central-1",
+            &credentials,
+            "dnschecker",
+            chrono::Utc::now(),
+        );
+        assert_eq!(result.unwrap(), "123456:ABC-DEF");
+        mock.assert();
+    }
+
+    #[cfg(feature = "gcp-secrets")]
+    #[test]
+    fn test_gcp() {
+        use httpmock::prelude::*;
+
+        assert_eq!(
+            gcp::secret_version_name("my-project/telegram-token").unwrap(),
+            "projects/my-project/secrets/telegram-token/versions/latest"
+        );
+        assert!(gcp::secret_version_name("telegram-token").is_err());
+
+        let server = MockServer::start();
+        let token = server.mock(|when, then| {
+            when.method(GET)
+                .path("/computeMetadata/v1/instance/service-accounts/default/token")
+                .header("Metadata-Flavor", "Google");
+            then.status(200)
+                .body(r#"{"access_token": "ya29.token", "expires_in": 3599}"#);
+        });
+        let access = server.mock(|when, then| {
+            when.method(GET)
+                .path("/v1/projects/my-project/secrets/telegram-token/versions/latest:access")
+                .header("Authorization", "Bearer ya29.token");
+            then.status(200)
+                .body(r#"{"payload": {"data": "MTIzNDU2OkFCQy1ERUY="}}"#);
+        });
+
+        let access_token = gcp::access_token(&server.base_url()).unwrap();
+        let result = gcp::access_secret(
+            &server.base_url(),
+            &access_token,
+            "projects/my-project/secrets/telegram-token/versions/latest",
+        );
+        assert_eq!(result.unwrap(), "123456:ABC-DEF");
+        token.assert();
+        access.assert();
+    }
+}
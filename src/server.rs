@@ -0,0 +1,1123 @@
+use crate::agent::{AgentRegistry, AgentReport};
+use crate::alarm;
+use crate::check::Context;
+use crate::cluster::{self, ClusterView};
+use crate::dependencies::{self, DEPENDENCIES};
+use crate::health::Health;
+use crate::history::{self, CheckRecord};
+use crate::metrics::METRICS;
+use crate::notify;
+use crate::redact::redact;
+use crate::store::instance_id;
+use crate::vars::split_list;
+use base64::Engine;
+use ipnet::IpNet;
+use serde_json::{json, Value};
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
+use std::env;
+use std::io::Read;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server, SslConfig};
+
+/// How long the checker loop may be busy before the liveness probe fails.
+/// A verification with slow router API calls and hooks can take a few minutes.
+const LIVENESS_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The largest body of an agent report that is read, in bytes.
+/// A report is a few hundred bytes, anything larger is rejected without being buffered.
+const MAX_REPORT_SIZE: u64 = 64 * 1024;
+
+/// The page served for a GET request to "/reset", e.g. from the "Acknowledge" button of a Slack alert.
+/// The form has no action, so it posts to the same URL, including the query parameters.
+const RESET_PAGE: &str = "<!DOCTYPE html>
+<html>
+<head><meta charset=\"utf-8\"><title>dnschecker</title></head>
+<body>
+<form method=\"post\">
+<p>Reset the alarm state?</p>
+<button type=\"submit\">Reset</button>
+</form>
+</body>
+</html>
+";
+
+/// The state shared between the checker loop and the HTTP listener.
+#[derive(Debug, Default)]
+pub struct Shared {
+    /// Set when an immediate check is requested.
+    pub trigger: AtomicBool,
+    /// Set when a dump of the internal state is requested with SIGUSR1.
+    pub dump: AtomicBool,
+    /// The health reported by the probes.
+    pub health: Health,
+    /// The latest reports of the agents, when running as aggregator.
+    pub agents: AgentRegistry,
+    /// The result of the latest verification, served on "/status".
+    pub latest: Mutex<Option<CheckRecord>>,
+    /// The latest status of the other instances, when running as cluster view.
+    pub cluster: ClusterView,
+}
+
+impl Shared {
+    /// Stores the result of a verification for the status endpoint, see `CheckRecord::from_context`.
+    pub fn record_check(&self, context: &Context) {
+        *self.latest.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some(CheckRecord::from_context(context));
+    }
+
+    /// Builds the status served on "/status": the instance, the latest verification with the severity of its hostname
+    /// (see `notify::severity`), the in-sync percentage over the last 24 hours, 7 days and 30 days
+    /// (see `history::cached_uptime`) and the health of the dependencies.
+    ///
+    /// # Returns
+    ///
+    /// * A `Value` that holds the status, with `null` as check before the first verification.
+    pub fn status(&self) -> Value {
+        let uptime: serde_json::Map<String, Value> = history::cached_uptime()
+            .into_iter()
+            .map(|(label, percentage)| (label.to_string(), json!(percentage)))
+            .collect();
+        let check = self
+            .latest
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .map(|record| {
+                let mut check = record.to_json();
+                check["severity"] = json!(notify::severity(&record.hostname).to_string());
+                check
+            });
+        json!({
+            "instance": instance_id(),
+            "check": check,
+            "in_sync": uptime,
+            "dependencies": dependencies::to_json(&DEPENDENCIES.snapshot()),
+        })
+    }
+}
+
+/// Starts the HTTP listener if it is configured.
+///
+/// This function retrieves the value of the "LISTEN_ADDR" environment variable (e.g. "0.0.0.0:8080").
+/// If it is not set, the listener is disabled and the function returns immediately.
+///
+/// The access control of all endpoints is read once using the `HttpAuth::from_env` function.
+/// If it is invalid, the listener is not started.
+///
+/// If the "TLS_CERT" and "TLS_KEY" environment variables are set, the listener serves HTTPS
+/// with the PEM encoded certificate chain and private key from these files,
+/// and reloads them when the process receives SIGHUP (see `supervise`).
+///
+/// It then binds the listener and handles requests on a background thread using the `serve` function.
+/// If the listener cannot be bound, it logs an error.
+///
+/// # Arguments
+///
+/// * `shared`: An `Arc<Shared>` that holds the state shared with the checker loop.
+pub fn start_server(shared: Arc<Shared>) {
+    let addr = match env::var("LISTEN_ADDR") {
+        Ok(addr) => addr,
+        Err(_) => return,
+    };
+    let auth = match HttpAuth::from_env() {
+        Ok(auth) => auth,
+        Err(e) => {
+            log::error!(
+                "Invalid HTTP access configuration, listener disabled: {}",
+                e
+            );
+            return;
+        }
+    };
+    if !auth.has_credentials() {
+        log::warn!(
+            "Neither HTTP_TOKEN nor HTTP_BASIC_AUTH is set, /trigger, /reset, /status, /metrics and /cluster are disabled"
+        );
+    }
+
+    let tls = match tls_paths() {
+        Ok(tls) => tls,
+        Err(e) => {
+            log::error!("{}, listener disabled", e);
+            return;
+        }
+    };
+    let ssl = match tls
+        .as_ref()
+        .map(|(cert, key)| load_tls(cert, key))
+        .transpose()
+    {
+        Ok(ssl) => ssl,
+        Err(e) => {
+            log::error!("{}, listener disabled", e);
+            return;
+        }
+    };
+
+    let server = match bind(&addr, ssl.as_ref().map(copy_ssl)) {
+        Ok(server) => server,
+        Err(e) => {
+            log::error!("Failed to listen on {}: {}", addr, e);
+            return;
+        }
+    };
+    log::info!(
+        "Listening on {} ({})",
+        addr,
+        if tls.is_some() { "https" } else { "http" }
+    );
+    match (ssl, tls) {
+        (Some(ssl), Some(paths)) => {
+            std::thread::spawn(move || supervise(server, &addr, ssl, paths, shared, auth));
+        }
+        _ => {
+            std::thread::spawn(move || serve(&server, &shared, &auth));
+        }
+    }
+}
+
+/// Retrieves the paths of the TLS certificate and private key.
+///
+/// # Returns
+///
+/// * A `Result<Option<(String, String)>, String>` that holds the values of the "TLS_CERT" and "TLS_KEY"
+///   environment variables, or `None` if neither is set.
+/// * If only one of them is set, it returns an `Err` with a message.
+fn tls_paths() -> Result<Option<(String, String)>, String> {
+    match (env::var("TLS_CERT"), env::var("TLS_KEY")) {
+        (Ok(cert), Ok(key)) => Ok(Some((cert, key))),
+        (Err(_), Err(_)) => Ok(None),
+        _ => Err("TLS_CERT and TLS_KEY must be set together".to_string()),
+    }
+}
+
+/// Reads the TLS certificate and private key.
+///
+/// # Arguments
+///
+/// * `cert`: A `&str` that specifies the path of the PEM encoded certificate chain.
+/// * `key`: A `&str` that specifies the path of the PEM encoded private key.
+///
+/// # Returns
+///
+/// * A `Result<SslConfig, String>` that holds the certificate and the key if both files could be read.
+/// * If a file cannot be read, it returns an `Err` with a message.
+fn load_tls(cert: &str, key: &str) -> Result<SslConfig, String> {
+    let certificate = std::fs::read(cert)
+        .map_err(|e| format!("Failed to read TLS certificate {}: {}", cert, e))?;
+    let private_key =
+        std::fs::read(key).map_err(|e| format!("Failed to read TLS key {}: {}", key, e))?;
+    Ok(SslConfig {
+        certificate,
+        private_key,
+    })
+}
+
+/// Binds the listener, serving HTTPS if a certificate is given.
+///
+/// # Arguments
+///
+/// * `addr`: A `&str` that specifies the listen address.
+/// * `ssl`: An `Option<SslConfig>` that holds the certificate and key, or `None` for plain HTTP.
+///
+/// # Returns
+///
+/// * A `Result<Server, String>` that holds the listener, or an `Err` with a message if it cannot be bound
+///   or the certificate is invalid.
+fn bind(addr: &str, ssl: Option<SslConfig>) -> Result<Server, String> {
+    match ssl {
+        Some(ssl) => Server::https(addr, ssl),
+        None => Server::http(addr),
+    }
+    .map_err(|e| e.to_string())
+}
+
+/// Serves HTTPS and reloads the certificate on SIGHUP.
+///
+/// This function handles requests on a background thread using the `serve` function and waits for SIGHUP.
+/// On SIGHUP it reads the certificate and key again. If they cannot be read, it logs an error and keeps the running listener.
+/// Otherwise it stops the running listener and binds a new one with the new certificate.
+/// If the new certificate is rejected, the listener is bound again with the previous one.
+///
+/// # Arguments
+///
+/// * `server`: A `tiny_http::Server` that receives the requests.
+/// * `addr`: A `&str` that specifies the listen address.
+/// * `ssl`: A `SslConfig` that holds the certificate and key the listener was bound with.
+/// * `paths`: A `(String, String)` that holds the paths of the certificate chain and the private key.
+/// * `shared`: An `Arc<Shared>` that holds the state shared with the checker loop.
+/// * `auth`: An `HttpAuth` that holds the access control.
+fn supervise(
+    server: Server,
+    addr: &str,
+    mut ssl: SslConfig,
+    paths: (String, String),
+    shared: Arc<Shared>,
+    auth: HttpAuth,
+) {
+    let mut signals = match Signals::new([SIGHUP]) {
+        Ok(signals) => signals,
+        Err(e) => {
+            log::warn!(
+                "Failed to handle SIGHUP, TLS certificate reload disabled: {}",
+                e
+            );
+            serve(&server, &shared, &auth);
+            return;
+        }
+    };
+    let auth = Arc::new(auth);
+    let mut server = Arc::new(server);
+    loop {
+        let handle = {
+            let (server, shared, auth) = (server.clone(), shared.clone(), auth.clone());
+            std::thread::spawn(move || serve(&server, &shared, &auth))
+        };
+        let reloaded = loop {
+            if signals.forever().next().is_none() {
+                return;
+            }
+            log::info!("Received SIGHUP, reloading TLS certificate");
+            match load_tls(&paths.0, &paths.1) {
+                Ok(reloaded) => break reloaded,
+                Err(e) => log::error!("{}, keeping the current certificate", e),
+            }
+        };
+
+        // Stop the running listener so the address can be bound again
+        server.unblock();
+        if handle.join().is_err() {
+            log::warn!("HTTP listener thread panicked");
+        }
+        drop(server);
+
+        server = match rebind(addr, copy_ssl(&reloaded)) {
+            Ok(new_server) => {
+                log::info!("TLS certificate reloaded");
+                ssl = reloaded;
+                Arc::new(new_server)
+            }
+            Err(e) => {
+                log::error!(
+                    "Failed to reload TLS certificate, keeping the previous one: {}",
+                    e
+                );
+                match rebind(addr, copy_ssl(&ssl)) {
+                    Ok(new_server) => Arc::new(new_server),
+                    Err(e) => {
+                        log::error!("Failed to listen on {}: {}", addr, e);
+                        return;
+                    }
+                }
+            }
+        };
+    }
+}
+
+/// Binds the listener again after the previous one was stopped.
+///
+/// The previous listener releases the address asynchronously, so binding is retried a few times.
+///
+/// # Arguments
+///
+/// * `addr`: A `&str` that specifies the listen address.
+/// * `ssl`: A `SslConfig` that holds the certificate and key.
+///
+/// # Returns
+///
+/// * A `Result<Server, String>` that holds the listener, or an `Err` with the message of the last attempt.
+fn rebind(addr: &str, ssl: SslConfig) -> Result<Server, String> {
+    let mut result = bind(addr, Some(copy_ssl(&ssl)));
+    for _ in 0..10 {
+        match &result {
+            Err(e) if e.contains("in use") => {
+                std::thread::sleep(Duration::from_millis(100));
+                result = bind(addr, Some(copy_ssl(&ssl)));
+            }
+            _ => break,
+        }
+    }
+    result
+}
+
+/// Copies a certificate and key, since `SslConfig` does not implement `Clone`.
+fn copy_ssl(ssl: &SslConfig) -> SslConfig {
+    SslConfig {
+        certificate: ssl.certificate.clone(),
+        private_key: ssl.private_key.clone(),
+    }
+}
+
+/// The access control shared by all endpoints of the HTTP listener.
+#[derive(Debug, Default)]
+pub struct HttpAuth {
+    /// The bearer token, empty if token authentication is disabled.
+    token: String,
+    /// The user name and password for basic authentication.
+    basic: Option<(String, String)>,
+    /// The networks clients may connect from, empty if every client may connect.
+    allow: Vec<IpNet>,
+    /// The token shared with the agents, empty if agent reports are disabled.
+    agent_token: String,
+    /// Whether "/status" is served without credentials.
+    public_status: bool,
+}
+
+impl HttpAuth {
+    /// Reads the access control from the environment.
+    ///
+    /// This function retrieves the following environment variables, all of which are optional:
+    ///
+    /// * "HTTP_TOKEN": the bearer token. For compatibility, "TRIGGER_TOKEN" is used if it is not set.
+    /// * "HTTP_BASIC_AUTH": the credentials for basic authentication in the form "user:password".
+    /// * "HTTP_ALLOW": a comma-separated list of addresses or networks (e.g. "127.0.0.1,192.168.1.0/24")
+    ///   that may connect. Requests from other addresses are rejected on every endpoint.
+    /// * "AGENT_TOKEN": the token the agents authenticate their reports with (see the `agent` module).
+    /// * "HTTP_PUBLIC_STATUS": "true" to serve "/status" without credentials, since it exposes the WAN IP address.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<HttpAuth, String>` that holds the access control if the variables are valid.
+    /// * If a variable is invalid, it returns an `Err` with a message.
+    pub fn from_env() -> Result<HttpAuth, String> {
+        let token = env::var("HTTP_TOKEN")
+            .or_else(|_| env::var("TRIGGER_TOKEN"))
+            .unwrap_or_default();
+        let basic = match env::var("HTTP_BASIC_AUTH") {
+            Ok(credentials) => match credentials.split_once(':') {
+                Some((user, password)) if !user.is_empty() && !password.is_empty() => {
+                    Some((user.to_string(), password.to_string()))
+                }
+                _ => return Err("HTTP_BASIC_AUTH must have the form user:password".to_string()),
+            },
+            Err(_) => None,
+        };
+        let allow = split_list(&env::var("HTTP_ALLOW").unwrap_or_default())
+            .iter()
+            .map(|entry| parse_network(entry))
+            .collect::<Result<Vec<IpNet>, String>>()?;
+        Ok(HttpAuth {
+            token,
+            basic,
+            allow,
+            agent_token: env::var("AGENT_TOKEN").unwrap_or_default(),
+            public_status: env::var("HTTP_PUBLIC_STATUS").is_ok_and(|value| value == "true"),
+        })
+    }
+
+    /// Returns whether any credentials are configured.
+    pub fn has_credentials(&self) -> bool {
+        !self.token.is_empty() || self.basic.is_some()
+    }
+
+    /// Checks whether a client address may connect.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr`: An `Option<&SocketAddr>` that holds the address of the client, if known.
+    ///
+    /// # Returns
+    ///
+    /// * A `bool` that indicates whether the client may connect. Unknown addresses are only permitted without an allowlist.
+    pub fn permits(&self, addr: Option<&SocketAddr>) -> bool {
+        if self.allow.is_empty() {
+            return true;
+        }
+        match addr {
+            Some(addr) => {
+                let ip = canonical_ip(addr.ip());
+                self.allow.iter().any(|network| network.contains(&ip))
+            }
+            None => false,
+        }
+    }
+
+    /// Returns whether a path is served without credentials, which is only "/status" with "HTTP_PUBLIC_STATUS".
+    pub fn is_public(&self, path: &str) -> bool {
+        path == "/status" && self.public_status
+    }
+
+    /// Checks whether a request carries valid credentials.
+    ///
+    /// This function accepts the bearer token from an "Authorization: Bearer <token>" header or a "token" query parameter,
+    /// since many DDNS providers cannot set headers on their webhooks,
+    /// and the basic authentication credentials from an "Authorization: Basic" header.
+    ///
+    /// # Arguments
+    ///
+    /// * `request`: A `&tiny_http::Request` that holds the request.
+    ///
+    /// # Returns
+    ///
+    /// * A `bool` that indicates whether the request is authorized. Without configured credentials no request is authorized.
+    pub fn authorize(&self, request: &Request) -> bool {
+        let authorization = request
+            .headers()
+            .iter()
+            .find(|header: &&Header| header.field.equiv("Authorization"))
+            .map(|header| header.value.as_str().to_string());
+        if !self.token.is_empty() {
+            let header_token = authorization
+                .as_deref()
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .map(String::from);
+            let query_token = query_param(request.url(), "token");
+            if [header_token, query_token]
+                .iter()
+                .flatten()
+                .any(|candidate| constant_time_eq(candidate.as_bytes(), self.token.as_bytes()))
+            {
+                return true;
+            }
+        }
+        if let Some((user, password)) = &self.basic {
+            let expected = format!("{}:{}", user, password);
+            let credentials = authorization
+                .as_deref()
+                .and_then(|value| value.strip_prefix("Basic "))
+                .and_then(|value| base64::engine::general_purpose::STANDARD.decode(value).ok());
+            if let Some(credentials) = credentials {
+                return constant_time_eq(&credentials, expected.as_bytes());
+            }
+        }
+        false
+    }
+
+    /// Checks whether a request carries the token shared with the agents as a bearer token.
+    ///
+    /// # Arguments
+    ///
+    /// * `request`: A `&tiny_http::Request` that holds the request.
+    ///
+    /// # Returns
+    ///
+    /// * A `bool` that indicates whether the request comes from an agent. Without an agent token no request is authorized.
+    pub fn authorize_agent(&self, request: &Request) -> bool {
+        !self.agent_token.is_empty()
+            && request
+                .headers()
+                .iter()
+                .find(|header: &&Header| header.field.equiv("Authorization"))
+                .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+                .is_some_and(|token| {
+                    constant_time_eq(token.as_bytes(), self.agent_token.as_bytes())
+                })
+    }
+}
+
+/// Parses an entry of the "HTTP_ALLOW" list.
+///
+/// # Arguments
+///
+/// * `entry`: A `&str` that holds an address (e.g. "192.168.1.10") or a network (e.g. "192.168.1.0/24").
+///
+/// # Returns
+///
+/// * A `Result<IpNet, String>` that holds the network; a single address is a network of one address.
+/// * If the entry is invalid, it returns an `Err` with a message.
+fn parse_network(entry: &str) -> Result<IpNet, String> {
+    entry
+        .parse::<IpNet>()
+        .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("Invalid address or network in HTTP_ALLOW: {}", entry))
+}
+
+/// Converts IPv4-mapped IPv6 addresses (e.g. "::ffff:192.168.1.10") to IPv4,
+/// so a listener bound to "[::]" matches IPv4 entries of the allowlist.
+fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        ip => ip,
+    }
+}
+
+/// Handles incoming HTTP requests.
+///
+/// This function takes a `tiny_http::Server`, the shared state and the access control as arguments.
+/// It answers requests until the server is shut down.
+///
+/// * `/healthz` answers 200 while the checker loop is running and 503 if it is stuck (see `Health::is_alive`).
+/// * `/readyz` answers 200 once the first verification completed and 503 before that and during shutdown.
+///
+/// The probes need no credentials and are answered for every client, since they expose no details.
+/// Requests from clients outside the "HTTP_ALLOW" list are answered with 403 on every other path.
+///
+/// * `/metrics` serves the counters in the Prometheus text format if the request is authorized.
+/// * `/trigger` schedules an immediate check on a POST request if it is authorized (see `HttpAuth::authorize`).
+/// * `/reset` clears the alarm state on a POST request if it is authorized.
+///   With the "notify=true" query parameter a "manually acknowledged" message is sent.
+///   A GET request is answered with a confirmation page (see `RESET_PAGE`) that posts to the same URL,
+///   so following a link does not change the state.
+///
+/// Requests to `/trigger`, `/reset` and `/report` with any other method are answered with 405.
+/// * `/status` serves the latest verification and the health of the dependencies as JSON (see `Shared::status`)
+///   if the request is authorized, or to every client with "HTTP_PUBLIC_STATUS" (see `HttpAuth::is_public`).
+/// * `/cluster` serves the combined status of the other instances as JSON, when running as cluster view
+///   (see `cluster::to_json`), if the request is authorized.
+/// * `/report` stores the report of an agent (see `agent::AgentReport`) if it is a POST request that
+///   carries the agent token (see `HttpAuth::authorize_agent`). Bodies larger than `MAX_REPORT_SIZE`
+///   are answered with 413.
+/// * Any other path is answered with 404.
+///
+/// # Arguments
+///
+/// * `server`: A `&tiny_http::Server` that receives the requests.
+/// * `shared`: A `&Shared` that holds the state shared with the checker loop.
+/// * `auth`: A `&HttpAuth` that holds the access control.
+fn serve(server: &Server, shared: &Shared, auth: &HttpAuth) {
+    for mut request in server.incoming_requests() {
+        let path = request.url().split('?').next().unwrap_or("").to_string();
+        let post = *request.method() == Method::Post;
+        let mut html = false;
+        let (status, body) = match path.as_str() {
+            "/healthz" if shared.health.is_alive(LIVENESS_TIMEOUT) => (200, "ok".to_string()),
+            "/healthz" => (503, "Checker loop is stuck".to_string()),
+            "/readyz" if shared.health.is_ready() => (200, "ok".to_string()),
+            "/readyz" => (503, "Not ready".to_string()),
+            _ if !auth.permits(request.remote_addr()) => {
+                log::warn!(
+                    "Rejected {} from {:?}, address not allowed",
+                    path,
+                    request.remote_addr()
+                );
+                (403, "Forbidden".to_string())
+            }
+            "/trigger" | "/reset" | "/status" | "/metrics" | "/cluster"
+                if !auth.is_public(&path) && !auth.authorize(&request) =>
+            {
+                log::warn!(
+                    "Rejected unauthorized {} from {:?}",
+                    path,
+                    request.remote_addr()
+                );
+                (401, "Unauthorized".to_string())
+            }
+            "/status" => (200, redact(&shared.status().to_string())),
+            "/metrics" => (200, METRICS.render()),
+            "/cluster" => (
+                200,
+                redact(&cluster::to_json(&shared.cluster.snapshot()).to_string()),
+            ),
+            "/report" if !auth.authorize_agent(&request) => {
+                log::warn!(
+                    "Rejected unauthorized report from {:?}",
+                    request.remote_addr()
+                );
+                (401, "Unauthorized".to_string())
+            }
+            "/trigger" | "/report" if !post => (405, "Method not allowed".to_string()),
+            "/reset" if *request.method() == Method::Get => {
+                html = true;
+                (200, RESET_PAGE.to_string())
+            }
+            "/reset" if !post => (405, "Method not allowed".to_string()),
+            "/trigger" => {
+                log::info!("Immediate check requested");
+                shared.trigger.store(true, Ordering::SeqCst);
+                (202, "Check scheduled".to_string())
+            }
+            "/report" => match read_body(&mut request, MAX_REPORT_SIZE) {
+                Ok(Some(body)) => match serde_json::from_slice::<AgentReport>(&body) {
+                    Ok(report) => {
+                        log::debug!("Agent {} reports {}", report.agent, report.ip);
+                        shared.agents.record(report);
+                        (202, "Report accepted".to_string())
+                    }
+                    Err(e) => (400, format!("Invalid report: {}", e)),
+                },
+                Ok(None) => {
+                    log::warn!("Rejected oversized report from {:?}", request.remote_addr());
+                    (413, "Report too large".to_string())
+                }
+                Err(e) => (400, format!("Invalid report: {}", e)),
+            },
+            "/reset" => {
+                let notify = query_param(request.url(), "notify").as_deref() == Some("true");
+                match alarm::force_reset(notify) {
+                    Ok(message) => (200, message),
+                    Err(message) => (500, message),
+                }
+            }
+            _ => (404, "Not found".to_string()),
+        };
+        let mut response = Response::from_string(body).with_status_code(status);
+        if status == 405 {
+            let allow = if path == "/reset" {
+                "GET, POST"
+            } else {
+                "POST"
+            };
+            let header = Header::from_bytes("Allow", allow);
+            response.add_header(header.expect("static header is valid"));
+        }
+        if html {
+            let header = Header::from_bytes("Content-Type", "text/html; charset=utf-8");
+            response.add_header(header.expect("static header is valid"));
+        }
+        if status == 401 && auth.basic.is_some() {
+            let challenge = Header::from_bytes("WWW-Authenticate", "Basic realm=\"dnschecker\"");
+            response.add_header(challenge.expect("static header is valid"));
+        }
+        if let Err(e) = request.respond(response) {
+            log::warn!("Failed to send response: {:?}", e);
+        }
+    }
+}
+
+/// Reads the body of a request up to a size limit.
+///
+/// At most one byte more than the limit is read, so an oversized body is detected without buffering it.
+///
+/// # Arguments
+///
+/// * `request`: A `&mut Request` whose body is read.
+/// * `limit`: A `u64` that specifies the largest accepted body in bytes.
+///
+/// # Returns
+///
+/// * A `Result<Option<Vec<u8>>, String>` that holds the body, `None` if it exceeds the limit,
+///   or an error message if reading failed.
+fn read_body(request: &mut Request, limit: u64) -> Result<Option<Vec<u8>>, String> {
+    if request
+        .body_length()
+        .is_some_and(|length| length as u64 > limit)
+    {
+        return Ok(None);
+    }
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(limit + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| e.to_string())?;
+    Ok((body.len() as u64 <= limit).then_some(body))
+}
+
+/// Retrieves a query parameter from a request URL.
+///
+/// # Arguments
+///
+/// * `url`: A `&str` that holds the request URL, e.g. "/reset?notify=true".
+/// * `name`: A `&str` that specifies the name of the query parameter.
+///
+/// # Returns
+///
+/// * An `Option<String>` that holds the decoded value of the parameter if it is present.
+fn query_param(url: &str, name: &str) -> Option<String> {
+    url.split_once('?').and_then(|(_, query)| {
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    })
+}
+
+/// Compares two byte strings in constant time.
+///
+/// # Arguments
+///
+/// * `a`: A `&[u8]` that holds the first byte string.
+/// * `b`: A `&[u8]` that holds the second byte string.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether the byte strings are equal.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status() {
+        let shared = Shared::default();
+        assert_eq!(shared.status()["check"], Value::Null);
+
+        // Assert that the latest verification says whether its hostname pages
+        shared.record_check(&Context::new("home.example.com"));
+        let check = &shared.status()["check"];
+        assert_eq!(check["hostname"], "home.example.com");
+        assert_eq!(check["severity"], "page");
+    }
+
+    #[test]
+    fn test_serve_trigger() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let shared = Arc::new(Shared::default());
+        let state = shared.clone();
+        let auth = HttpAuth {
+            token: "secret".to_string(),
+            ..Default::default()
+        };
+        std::thread::spawn(move || serve(&server, &state, &auth));
+        let client = reqwest::blocking::Client::new();
+
+        // Assert that a request without the token is rejected
+        let response = client
+            .post(format!("http://{}/trigger", addr))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        assert!(!shared.trigger.load(Ordering::SeqCst));
+
+        // Assert that a request with the bearer token schedules a check
+        let response = client
+            .post(format!("http://{}/trigger", addr))
+            .bearer_auth("secret")
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 202);
+        assert!(shared.trigger.swap(false, Ordering::SeqCst));
+
+        // Assert that other methods do not schedule a check, even with a valid token
+        let response = client
+            .get(format!("http://{}/trigger?token=secret", addr))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 405);
+        assert_eq!(response.headers()["Allow"], "POST");
+        assert!(!shared.trigger.load(Ordering::SeqCst));
+
+        // Assert that the token is also accepted as a query parameter
+        let response = client
+            .post(format!("http://{}/trigger?token=secret", addr))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 202);
+        assert!(shared.trigger.load(Ordering::SeqCst));
+
+        // Assert that a GET request to /reset only serves the confirmation page
+        let response = client
+            .get(format!("http://{}/reset?notify=true&token=secret", addr))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(response.headers()["Content-Type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+        assert!(response.text().unwrap().contains("<form method=\"post\">"));
+        let response = client
+            .delete(format!("http://{}/reset?token=secret", addr))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 405);
+        assert_eq!(response.headers()["Allow"], "GET, POST");
+
+        // Assert that unknown paths are not found
+        let response = client.get(format!("http://{}/", addr)).send().unwrap();
+        assert_eq!(response.status(), 404);
+    }
+
+    #[test]
+    fn test_serve_basic_auth() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let shared = Arc::new(Shared::default());
+        let state = shared.clone();
+        let auth = HttpAuth {
+            basic: Some(("admin".to_string(), "hunter2".to_string())),
+            ..Default::default()
+        };
+        std::thread::spawn(move || serve(&server, &state, &auth));
+        let client = reqwest::blocking::Client::new();
+
+        // Assert that a request without credentials is challenged
+        let response = client
+            .post(format!("http://{}/trigger", addr))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        assert!(response.headers().contains_key("WWW-Authenticate"));
+
+        // Assert that wrong credentials are rejected
+        let response = client
+            .post(format!("http://{}/trigger", addr))
+            .basic_auth("admin", Some("wrong"))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 401);
+
+        // Assert that valid credentials schedule a check
+        let response = client
+            .post(format!("http://{}/trigger", addr))
+            .basic_auth("admin", Some("hunter2"))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 202);
+        assert!(shared.trigger.load(Ordering::SeqCst));
+
+        // Assert that the metrics need credentials too
+        let response = client
+            .get(format!("http://{}/metrics", addr))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        let response = client
+            .get(format!("http://{}/metrics", addr))
+            .basic_auth("admin", Some("hunter2"))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(response.text().unwrap().contains("dnschecker_checks_total"));
+    }
+
+    #[test]
+    fn test_serve_report() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let shared = Arc::new(Shared::default());
+        let state = shared.clone();
+        let auth = HttpAuth {
+            token: "secret".to_string(),
+            agent_token: "agents".to_string(),
+            ..Default::default()
+        };
+        std::thread::spawn(move || serve(&server, &state, &auth));
+        let client = reqwest::blocking::Client::new();
+        let report = AgentReport {
+            agent: "office".to_string(),
+            hostname: "home.example.com".to_string(),
+            ip: "1.1.1.1".to_string(),
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+        };
+
+        // Assert that the HTTP token does not authorize reports
+        let response = client
+            .post(format!("http://{}/report", addr))
+            .bearer_auth("secret")
+            .json(&report)
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 401);
+
+        // Assert that an invalid report is rejected
+        let response = client
+            .post(format!("http://{}/report", addr))
+            .bearer_auth("agents")
+            .body("{}")
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 400);
+
+        // Assert that a report with the agent token is stored
+        let response = client
+            .post(format!("http://{}/report", addr))
+            .bearer_auth("agents")
+            .json(&report)
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 202);
+        assert_eq!(
+            shared.agents.fresh(Duration::from_secs(60)),
+            std::slice::from_ref(&report)
+        );
+
+        // Assert that a report is only accepted with POST
+        let response = client
+            .put(format!("http://{}/report", addr))
+            .bearer_auth("agents")
+            .json(&report)
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 405);
+
+        // Assert that an oversized body is rejected, with and without a declared length
+        let oversized = format!("{{\"agent\": \"{}\"}}", "x".repeat(100 * 1024));
+        let response = client
+            .post(format!("http://{}/report", addr))
+            .bearer_auth("agents")
+            .body(oversized.clone())
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 413);
+        // The rest of a streamed body is left unread, so the connection may be reset before the
+        // client sees the response
+        let chunks = std::io::Cursor::new(oversized.into_bytes());
+        let response = client
+            .post(format!("http://{}/report", addr))
+            .bearer_auth("agents")
+            .body(reqwest::blocking::Body::new(chunks))
+            .send();
+        if let Ok(response) = response {
+            assert_eq!(response.status(), 413);
+        }
+        assert_eq!(shared.agents.fresh(Duration::from_secs(60)).len(), 1);
+    }
+
+    #[test]
+    fn test_serve_probes() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let shared = Arc::new(Shared::default());
+        let state = shared.clone();
+        let auth = HttpAuth {
+            allow: vec![parse_network("192.0.2.0/24").unwrap()],
+            ..Default::default()
+        };
+        std::thread::spawn(move || serve(&server, &state, &auth));
+        let client = reqwest::blocking::Client::new();
+
+        // Assert that the probes are answered for clients outside the allowlist
+        shared.health.tick();
+        let response = client
+            .get(format!("http://{}/healthz", addr))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let response = client
+            .get(format!("http://{}/readyz", addr))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 503);
+
+        // Assert that the checker is ready after the first verification
+        shared.health.set_ready(true);
+        let response = client
+            .get(format!("http://{}/readyz", addr))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        // Assert that other paths are still restricted
+        let response = client
+            .get(format!("http://{}/trigger", addr))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 403);
+        let response = client
+            .get(format!("http://{}/metrics", addr))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 403);
+    }
+
+    #[test]
+    fn test_serve_status() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let shared = Arc::new(Shared::default());
+        let state = shared.clone();
+        let auth = HttpAuth {
+            token: "secret".to_string(),
+            ..Default::default()
+        };
+        std::thread::spawn(move || serve(&server, &state, &auth));
+        let client = reqwest::blocking::Client::new();
+        let get = |path: &str| -> Value {
+            client
+                .get(format!("http://{}{}", addr, path))
+                .bearer_auth("secret")
+                .send()
+                .unwrap()
+                .json()
+                .unwrap()
+        };
+
+        // Assert that the status needs credentials, since it carries the WAN IP address
+        let response = client
+            .get(format!("http://{}/status", addr))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        let public = HttpAuth {
+            public_status: true,
+            ..Default::default()
+        };
+        assert!(public.is_public("/status"));
+        assert!(!public.is_public("/trigger"));
+
+        // Assert that the status carries the latest verification once there is one
+        assert_eq!(get("/status")["check"], Value::Null);
+        let mut context = Context::new("home.example.com");
+        context.dns_ip = "192.0.2.1".to_string();
+        context.wan_ip = "192.0.2.2".to_string();
+        shared.record_check(&context);
+        let status = get("/status");
+        assert_eq!(status["instance"], instance_id());
+        assert_eq!(status["check"]["in_sync"], false);
+        assert!(status["in_sync"].get("30d").is_some());
+
+        // Assert that the cluster view serves the polls of the peers to authorized requests
+        let response = client
+            .get(format!("http://{}/cluster", addr))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        let peer = cluster::Peer::parse("office=https://office.example.com").unwrap();
+        shared.cluster.record(&peer, Ok(status));
+        let combined = get("/cluster");
+        assert_eq!(combined["summary"]["out_of_sync"], 1);
+        assert_eq!(combined["instances"][0]["name"], "office");
+    }
+
+    #[test]
+    fn test_permits() {
+        let auth = HttpAuth {
+            allow: vec![
+                parse_network("192.168.1.0/24").unwrap(),
+                parse_network("::1").unwrap(),
+            ],
+            ..Default::default()
+        };
+        let lan: SocketAddr = "192.168.1.10:50000".parse().unwrap();
+        let mapped: SocketAddr = "[::ffff:192.168.1.10]:50000".parse().unwrap();
+        let wan: SocketAddr = "203.0.113.7:50000".parse().unwrap();
+        let loopback: SocketAddr = "[::1]:50000".parse().unwrap();
+
+        assert!(auth.permits(Some(&lan)));
+        assert!(auth.permits(Some(&mapped)));
+        assert!(!auth.permits(Some(&wan)));
+        assert!(auth.permits(Some(&loopback)));
+        assert!(!auth.permits(None));
+
+        // Assert that every client is permitted without an allowlist
+        assert!(HttpAuth::default().permits(Some(&wan)));
+
+        // Assert that invalid entries are rejected
+        assert!(parse_network("192.168.1.0/33").is_err());
+    }
+
+    #[test]
+    fn test_load_tls() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert = dir.path().join("cert.pem");
+        let key = dir.path().join("key.pem");
+        std::fs::write(&cert, "certificate").unwrap();
+
+        // Assert that a missing key is reported
+        let result = load_tls(cert.to_str().unwrap(), key.to_str().unwrap());
+        assert!(result.is_err_and(|e| e.contains("TLS key")));
+
+        // Assert that both files are read
+        std::fs::write(&key, "key").unwrap();
+        let ssl = load_tls(cert.to_str().unwrap(), key.to_str().unwrap()).unwrap();
+        assert_eq!(ssl.certificate, b"certificate");
+        assert_eq!(ssl.private_key, b"key");
+
+        // Assert that an invalid certificate is rejected when binding
+        assert!(bind("127.0.0.1:0", Some(ssl)).is_err());
+    }
+
+    #[test]
+    fn test_query_param() {
+        assert_eq!(
+            query_param("/reset?notify=true&token=a%20b", "token"),
+            Some("a b".to_string())
+        );
+        assert_eq!(query_param("/reset", "notify"), None);
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secrets"));
+    }
+}
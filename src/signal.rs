@@ -0,0 +1,126 @@
+use crate::http;
+use crate::notify::{Message, Notifier};
+use crate::vars::split_list;
+use serde_json::{json, Value};
+use std::env;
+use std::time::Duration;
+
+/// Delivers notifications through a signal-cli-rest-api server to Signal numbers and groups.
+pub struct SignalNotifier {
+    url: String,
+    number: String,
+    recipients: Vec<String>,
+}
+
+impl SignalNotifier {
+    /// Creates a Signal notifier from the environment.
+    ///
+    /// The notifier is enabled by setting "SIGNAL_API_URL" to the base URL of a signal-cli-rest-api server,
+    /// e.g. "http://signal-cli:8080". Messages are sent from the registered number "SIGNAL_NUMBER"
+    /// to "SIGNAL_RECIPIENTS", a list of phone numbers and group IDs (as listed by "GET /v1/groups/<number>").
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<SignalNotifier>` that holds the notifier, or `None` if "SIGNAL_API_URL" is not set
+    ///   or the number or the recipients are missing.
+    pub fn from_env() -> Option<SignalNotifier> {
+        let url = env::var("SIGNAL_API_URL")
+            .ok()
+            .filter(|url| !url.is_empty())?;
+        let number = env::var("SIGNAL_NUMBER").unwrap_or_default();
+        let recipients = split_list(&env::var("SIGNAL_RECIPIENTS").unwrap_or_default());
+        if number.is_empty() || recipients.is_empty() {
+            log::warn!("SIGNAL_API_URL is set, but SIGNAL_NUMBER or SIGNAL_RECIPIENTS is missing");
+            return None;
+        }
+        Some(SignalNotifier {
+            url: url.trim_end_matches('/').to_string(),
+            number,
+            recipients,
+        })
+    }
+}
+
+impl Notifier for SignalNotifier {
+    fn name(&self) -> &str {
+        "signal"
+    }
+
+    /// Posts the message to the "/v2/send" endpoint of the signal-cli-rest-api server, see `body`.
+    fn send(&self, message: &Message) -> bool {
+        let client = match http::client() {
+            Ok(client) => client,
+            Err(e) => {
+                log::warn!("{}", e);
+                return false;
+            }
+        };
+        let response = client
+            .post(format!("{}/v2/send", self.url))
+            .json(&body(&self.number, &self.recipients, &message.text))
+            .timeout(Duration::from_secs(30))
+            .send();
+        match response {
+            Ok(response) if response.status().is_success() => true,
+            Ok(response) => {
+                let status = response.status();
+                log::warn!(
+                    "Signal returned {}: {}",
+                    status,
+                    response.text().unwrap_or_default()
+                );
+                false
+            }
+            Err(e) => {
+                log::warn!("Failed to make HTTP request: {:?}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Builds the body of a signal-cli-rest-api send request.
+///
+/// # Arguments
+///
+/// * `number`: A `&str` that specifies the registered number the message is sent from.
+/// * `recipients`: A `&[String]` that holds the phone numbers and group IDs to send to.
+/// * `text`: A `&str` that specifies the text of the message.
+///
+/// # Returns
+///
+/// * A `Value` that holds the JSON body.
+fn body(number: &str, recipients: &[String], text: &str) -> Value {
+    json!({
+        "number": number,
+        "recipients": recipients,
+        "message": text,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+
+    #[test]
+    fn test_send() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method("POST").path("/v2/send").json_body(json!({
+                "number": "+4915100000000",
+                "recipients": ["+4915111111111", "group.abc="],
+                "message": "IP address mismatch!",
+            }));
+            then.status(201).body("{\"timestamp\": \"1700000000000\"}");
+        });
+        let notifier = SignalNotifier {
+            url: server.base_url(),
+            number: "+4915100000000".to_string(),
+            recipients: vec!["+4915111111111".to_string(), "group.abc=".to_string()],
+        };
+
+        assert!(notifier.send(&Message::new("mismatch", "IP address mismatch!")));
+        mock.assert();
+    }
+}
@@ -0,0 +1,134 @@
+use crate::check::{self, Check, Context};
+use crate::server::Shared;
+use std::sync::Arc;
+
+/// The WAN IP address of every scenario, from the TEST-NET-1 documentation range.
+const WAN_IP: &str = "192.0.2.1";
+
+/// The stale IP address from DNS of the mismatch scenario, from the TEST-NET-2 documentation range.
+const STALE_IP: &str = "198.51.100.1";
+
+/// A synthetic check result injected by `dnschecker simulate`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scenario {
+    /// DNS points at another address than the WAN IP address.
+    Mismatch,
+    /// DNS points at the WAN IP address.
+    Recovery,
+    /// The hostname does not resolve.
+    Nxdomain,
+}
+
+impl Scenario {
+    /// Parses the name of a scenario: "mismatch", "recovery" or "nxdomain".
+    pub fn parse(name: &str) -> Option<Scenario> {
+        match name {
+            "mismatch" => Some(Scenario::Mismatch),
+            "recovery" => Some(Scenario::Recovery),
+            "nxdomain" => Some(Scenario::Nxdomain),
+            _ => None,
+        }
+    }
+
+    /// Returns the addresses from DNS of the scenario.
+    fn dns_ips(&self) -> Vec<String> {
+        match self {
+            Scenario::Mismatch => vec![STALE_IP.to_string()],
+            Scenario::Recovery => vec![WAN_IP.to_string()],
+            Scenario::Nxdomain => Vec::new(),
+        }
+    }
+}
+
+/// Stands in for the "resolve_dns" check with the addresses of a scenario.
+struct SimulatedDns(Scenario);
+
+impl Check for SimulatedDns {
+    fn name(&self) -> &'static str {
+        "resolve_dns"
+    }
+
+    fn run(&self, context: &mut Context) {
+        context.dns_ips = self.0.dns_ips();
+        context.dns_ip = context.dns_ips.first().cloned().unwrap_or_default();
+    }
+}
+
+/// Stands in for the "fetch_wan_ip" check with the WAN IP address of every scenario.
+struct SimulatedWan;
+
+impl Check for SimulatedWan {
+    fn name(&self) -> &'static str {
+        "fetch_wan_ip"
+    }
+
+    fn run(&self, context: &mut Context) {
+        context.wan_ips = vec![("simulated".to_string(), WAN_IP.to_string())];
+        context.wan_source = "simulated".to_string();
+        context.interface = "simulated".to_string();
+        context.wan_ip = WAN_IP.to_string();
+    }
+}
+
+/// Injects a synthetic check result into the comparison, to rehearse the handling of alerts without breaking DNS.
+///
+/// This function assembles the configured pipeline (see `check::pipeline`) and keeps only its "compare" check,
+/// so the comparison script and policies, the alarm state and every configured channel are the real ones.
+/// The lookups are replaced by the addresses of the scenario: DNS points at 198.51.100.1 instead of the WAN IP
+/// address 192.0.2.1 for a mismatch, at 192.0.2.1 for a recovery, and nowhere for "nxdomain".
+/// The other checks do not run, so the simulation does not end up in the history or raise WAN change alerts.
+///
+/// The alarm state is the real one as well: a simulated mismatch raises the alarm until a simulated
+/// (or real) recovery clears it, and a mismatch is not alerted again while the alarm is raised.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `scenario`: A `Scenario` that specifies the injected result.
+/// * `shared`: A `&Arc<Shared>` that holds the state shared with the HTTP listener.
+///
+/// # Returns
+///
+/// * A `Result<Context, String>` that holds the result of the simulated verification.
+/// * If the pipeline cannot be set up or has no "compare" check, it returns an `Err` with a message.
+pub fn simulate(
+    hostname: &str,
+    scenario: Scenario,
+    shared: &Arc<Shared>,
+) -> Result<Context, String> {
+    let compare = check::pipeline(shared)?
+        .into_iter()
+        .find(|check| check.name() == "compare")
+        .ok_or("CHECKS does not include compare, there is nothing to simulate".to_string())?;
+    let checks: Vec<Box<dyn Check>> = vec![
+        Box::new(SimulatedDns(scenario)),
+        Box::new(SimulatedWan),
+        compare,
+    ];
+    log::info!("Simulating {:?} for {}", scenario, hostname);
+    Ok(check::run_pipeline(&checks, hostname))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulated_inputs() {
+        let checks: Vec<Box<dyn Check>> = vec![
+            Box::new(SimulatedDns(Scenario::Mismatch)),
+            Box::new(SimulatedWan),
+        ];
+        let context = check::run_pipeline(&checks, "home.example.com");
+        assert_eq!(context.dns_ip, STALE_IP);
+        assert_eq!(context.wan_ip, WAN_IP);
+
+        let checks: Vec<Box<dyn Check>> = vec![Box::new(SimulatedDns(Scenario::Nxdomain))];
+        let context = check::run_pipeline(&checks, "home.example.com");
+        assert!(context.dns_ips.is_empty());
+        assert_eq!(context.dns_ip, "");
+
+        assert_eq!(Scenario::parse("recovery"), Some(Scenario::Recovery));
+        assert_eq!(Scenario::parse("outage"), None);
+    }
+}
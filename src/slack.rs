@@ -0,0 +1,330 @@
+use crate::http;
+use crate::notify::{Message, Notifier};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// The maximum number of fields Slack accepts in a section block.
+const MAX_FIELDS: usize = 10;
+
+/// The base URL of the Slack Web API, used to upload images.
+const SLACK_API: &str = "https://slack.com/api";
+
+/// Delivers notifications to a Slack incoming webhook, formatted with Block Kit.
+pub struct SlackNotifier {
+    url: String,
+    link_url: Option<String>,
+    /// The bot token and channel ID images are uploaded with, see `with_uploads`.
+    uploads: Option<(String, String)>,
+}
+
+impl SlackNotifier {
+    /// Creates a Slack notifier.
+    ///
+    /// If a link URL is given (the public URL of the HTTP listener), alerts carry buttons linking to it (see `blocks`).
+    pub fn new(url: &str, link_url: Option<String>) -> SlackNotifier {
+        SlackNotifier {
+            url: url.to_string(),
+            link_url: link_url
+                .map(|link_url| link_url.trim_end_matches('/').to_string())
+                .filter(|link_url| !link_url.is_empty()),
+            uploads: None,
+        }
+    }
+
+    /// Uploads the images of messages, e.g. the chart of a digest, to a channel after posting the text.
+    ///
+    /// Incoming webhooks cannot carry files, so images are uploaded with a bot token
+    /// that has the "files:write" scope, to the channel with the given ID.
+    pub fn with_uploads(mut self, bot_token: &str, channel_id: &str) -> SlackNotifier {
+        self.uploads = Some((bot_token.to_string(), channel_id.to_string()));
+        self
+    }
+}
+
+impl Notifier for SlackNotifier {
+    fn name(&self) -> &str {
+        "slack"
+    }
+
+    /// Posts the message to the Slack webhook.
+    ///
+    /// The body holds the Block Kit blocks built by the `blocks` function,
+    /// and the plain text of the message for notifications and clients without Block Kit support.
+    /// An image of the message is then uploaded if uploads are configured, see `with_uploads`.
+    fn send(&self, message: &Message) -> bool {
+        let body = json!({
+            "text": message.text,
+            "blocks": blocks(message, self.link_url.as_deref()),
+        });
+        let client = match http::client() {
+            Ok(client) => client,
+            Err(e) => {
+                log::warn!("{}", e);
+                return false;
+            }
+        };
+        let response = client
+            .post(&self.url)
+            .json(&body)
+            .timeout(Duration::from_secs(10))
+            .send();
+        let sent = match response {
+            Ok(response) if response.status().is_success() => true,
+            Ok(response) => {
+                log::warn!("Slack returned {}", response.status());
+                false
+            }
+            Err(e) => {
+                log::warn!("Failed to make HTTPS request: {:?}", e);
+                false
+            }
+        };
+        if let (true, Some(png), Some((token, channel_id))) = (sent, &message.image, &self.uploads)
+        {
+            if let Err(e) = upload_image(SLACK_API, token, channel_id, png) {
+                log::warn!("Failed to upload image to Slack: {}", e);
+            }
+        }
+        sent
+    }
+}
+
+/// Uploads a PNG image to a channel with the external upload flow of the Slack Web API.
+///
+/// The upload URL is requested with "files.getUploadURLExternal", the image is posted to it,
+/// and "files.completeUploadExternal" shares it in the channel.
+///
+/// # Arguments
+///
+/// * `api`: A `&str` that specifies the base URL of the Slack Web API.
+/// * `token`: A `&str` that specifies the bot token.
+/// * `channel_id`: A `&str` that specifies the ID of the channel, e.g. "C0123456789".
+/// * `png`: A `&[u8]` that holds the image.
+///
+/// # Returns
+///
+/// * A `Result<(), String>` that is `Ok` if the image was shared, or an `Err` with a message if a step failed.
+fn upload_image(api: &str, token: &str, channel_id: &str, png: &[u8]) -> Result<(), String> {
+    let client = http::client()?;
+    let call = |request: reqwest::blocking::RequestBuilder| -> Result<Value, String> {
+        let json: Value = request
+            .bearer_auth(token)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .and_then(|response| response.json())
+            .map_err(|e| e.to_string())?;
+        if json["ok"] == Value::Bool(true) {
+            Ok(json)
+        } else {
+            Err(json["error"]
+                .as_str()
+                .unwrap_or("no error given")
+                .to_string())
+        }
+    };
+    let length = png.len().to_string();
+    let upload = call(
+        client
+            .post(format!("{}/files.getUploadURLExternal", api))
+            .form(&[("filename", "chart.png"), ("length", length.as_str())]),
+    )?;
+    let (Some(upload_url), Some(file_id)) =
+        (upload["upload_url"].as_str(), upload["file_id"].as_str())
+    else {
+        return Err("No upload URL in the answer".to_string());
+    };
+    let response = client
+        .post(upload_url)
+        .header("Content-Type", "image/png")
+        .body(png.to_vec())
+        .timeout(Duration::from_secs(30))
+        .send()
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("Upload returned {}", response.status()));
+    }
+    call(
+        client
+            .post(format!("{}/files.completeUploadExternal", api))
+            .json(&json!({
+                "files": [{ "id": file_id, "title": "Chart" }],
+                "channel_id": channel_id,
+            })),
+    )
+    .map(|_| ())
+}
+
+/// Builds the Block Kit blocks of a message.
+///
+/// The text of the message is a section, followed by a section with a field per structured detail
+/// (the hostname, the IP addresses, how long a mismatch lasted, …).
+/// If a link URL is given, mismatch alerts end with an "Acknowledge" button linking to "/reset?notify=true",
+/// which opens a confirmation page that resets the alarm state, and a "Metrics" button linking to "/metrics"
+/// of the HTTP listener.
+///
+/// # Arguments
+///
+/// * `message`: A `&Message` that holds the notification.
+/// * `link_url`: An `Option<&str>` that specifies the public URL of the HTTP listener, without a trailing slash.
+///
+/// # Returns
+///
+/// * A `Value` that holds the array of blocks.
+fn blocks(message: &Message, link_url: Option<&str>) -> Value {
+    let mut blocks = vec![json!({
+        "type": "section",
+        "text": {"type": "mrkdwn", "text": escape(&message.text)},
+    })];
+    let fields: Vec<Value> = message
+        .fields
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .take(MAX_FIELDS)
+        .map(|(name, value)| {
+            json!({"type": "mrkdwn", "text": format!("*{}*\n{}", field_label(name), escape(value))})
+        })
+        .collect();
+    if !fields.is_empty() {
+        blocks.push(json!({"type": "section", "fields": fields}));
+    }
+    if let Some(link_url) = link_url.filter(|_| message.event == "mismatch") {
+        blocks.push(json!({
+            "type": "actions",
+            "elements": [
+                {
+                    "type": "button",
+                    "text": {"type": "plain_text", "text": "Acknowledge"},
+                    "style": "primary",
+                    "url": format!("{}/reset?notify=true", link_url),
+                },
+                {
+                    "type": "button",
+                    "text": {"type": "plain_text", "text": "Metrics"},
+                    "url": format!("{}/metrics", link_url),
+                },
+            ],
+        }));
+    }
+    Value::from(blocks)
+}
+
+/// Turns the name of a structured detail into a label, e.g. "router_ip" into "Router IP".
+fn field_label(name: &str) -> String {
+    name.split('_')
+        .map(|word| match word {
+            "ip" | "dns" | "wan" => word.to_uppercase(),
+            _ => {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect(),
+                    None => String::new(),
+                }
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Escapes the characters that have a special meaning in Slack mrkdwn.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+
+    #[test]
+    fn test_blocks() {
+        let message = Message::new("mismatch", "IP address mismatch <!>")
+            .with_field("hostname", "home.example.com")
+            .with_field("router_ip", "203.0.113.7")
+            .with_field("dns_ip", "");
+
+        let result = blocks(&message, Some("https://checker.example.com"));
+        assert_eq!(result[0]["text"]["text"], "IP address mismatch &lt;!&gt;");
+        assert_eq!(
+            result[1]["fields"],
+            json!([
+                {"type": "mrkdwn", "text": "*Hostname*\nhome.example.com"},
+                {"type": "mrkdwn", "text": "*Router IP*\n203.0.113.7"},
+            ])
+        );
+        assert_eq!(
+            result[2]["elements"][0]["url"],
+            "https://checker.example.com/reset?notify=true"
+        );
+
+        // Assert that other events and messages without fields only hold the text
+        let result = blocks(
+            &Message::new("reset", "Alarm acknowledged"),
+            Some("https://checker.example.com"),
+        );
+        assert_eq!(result.as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_send() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method("POST")
+                .path("/services/T000/B000")
+                .json_body_partial(r#"{"text": "IP addresses are the same again"}"#);
+            then.status(200).body("ok");
+        });
+        let notifier = SlackNotifier::new(&server.url("/services/T000/B000"), None);
+
+        assert!(notifier.send(&Message::new("recovery", "IP addresses are the same again")));
+        mock.assert();
+    }
+
+    #[test]
+    fn test_upload_image() {
+        let server = MockServer::start();
+        let get_url = server.mock(|when, then| {
+            when.method("POST")
+                .path("/api/files.getUploadURLExternal")
+                .header("Authorization", "Bearer xoxb-token")
+                .body("filename=chart.png&length=3");
+            then.status(200).json_body(json!({
+                "ok": true,
+                "upload_url": server.url("/upload/F000"),
+                "file_id": "F000",
+            }));
+        });
+        let upload = server.mock(|when, then| {
+            when.method("POST").path("/upload/F000").body("PNG");
+            then.status(200).body("OK");
+        });
+        let complete = server.mock(|when, then| {
+            when.method("POST")
+                .path("/api/files.completeUploadExternal")
+                .json_body(json!({
+                    "files": [{ "id": "F000", "title": "Chart" }],
+                    "channel_id": "C000",
+                }));
+            then.status(200).json_body(json!({ "ok": true }));
+        });
+
+        let api = server.url("/api");
+        assert_eq!(upload_image(&api, "xoxb-token", "C000", b"PNG"), Ok(()));
+        get_url.assert();
+        upload.assert();
+        complete.assert();
+
+        // Assert that an error of the Web API is returned
+        let other = MockServer::start();
+        other.mock(|when, then| {
+            when.method("POST").path("/api/files.getUploadURLExternal");
+            then.status(200)
+                .json_body(json!({ "ok": false, "error": "not_authed" }));
+        });
+        assert_eq!(
+            upload_image(&other.url("/api"), "xoxb-token", "C000", b"PNG"),
+            Err("not_authed".to_string())
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use crate::events;
+use crate::history::{self, CheckRecord};
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::vars::{display_hostname, parse_duration};
+use chrono::{DateTime, Duration as ChronoDuration, FixedOffset, Local, Utc};
+use std::env;
+use std::time::Duration;
+
+/// The check whose latency is the latency of a lookup.
+const LOOKUP_CHECK: &str = "resolve_dns";
+
+/// A service level objective for the latency of the DNS lookups, e.g. 99% of lookups under 200 ms over an hour.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencySlo {
+    /// The latency a lookup must stay under, in milliseconds.
+    pub threshold: u64,
+    /// The percentage of lookups that must stay under the threshold.
+    pub target: f64,
+    /// The rolling window the lookups are counted over.
+    pub window: Duration,
+    /// The number of lookups in the window below which the objective is not evaluated.
+    pub min_lookups: usize,
+}
+
+/// The lookups of a window, as counted by `LatencySlo::evaluate`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Compliance {
+    /// The number of lookups in the window.
+    lookups: usize,
+    /// The number of lookups under the threshold.
+    fast: usize,
+}
+
+impl Compliance {
+    /// The percentage of lookups under the threshold.
+    fn percentage(&self) -> f64 {
+        self.fast as f64 * 100.0 / self.lookups as f64
+    }
+}
+
+impl LatencySlo {
+    /// Creates the objective from the environment.
+    ///
+    /// This function retrieves the threshold in milliseconds from the "SLO_LATENCY_MS" environment variable,
+    /// the percentage of lookups that must stay under it from "SLO_TARGET" (defaulting to 99),
+    /// the rolling window from "SLO_WINDOW" (e.g. "1h", the default) and the number of lookups needed
+    /// in the window before the objective is evaluated from "SLO_MIN_LOOKUPS" (defaulting to 10).
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<Option<LatencySlo>, String>` that holds the objective, or `None` if "SLO_LATENCY_MS" is not set.
+    /// * If a value is invalid, it returns an `Err` with a message.
+    pub fn from_env() -> Result<Option<LatencySlo>, String> {
+        let threshold = match env::var("SLO_LATENCY_MS") {
+            Ok(value) => value
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|threshold| *threshold > 0)
+                .ok_or_else(|| format!("Invalid SLO_LATENCY_MS: {}", value))?,
+            Err(_) => return Ok(None),
+        };
+        let target = match env::var("SLO_TARGET") {
+            Ok(value) => value
+                .trim()
+                .trim_end_matches('%')
+                .parse::<f64>()
+                .ok()
+                .filter(|target| *target > 0.0 && *target <= 100.0)
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid SLO_TARGET {}, expected a percentage such as 99.5",
+                        value
+                    )
+                })?,
+            Err(_) => 99.0,
+        };
+        let window = match env::var("SLO_WINDOW") {
+            Ok(value) => parse_duration(&value)
+                .filter(|window| !window.is_zero())
+                .ok_or_else(|| format!("Invalid SLO_WINDOW: {}", value))?,
+            Err(_) => Duration::from_secs(60 * 60),
+        };
+        let min_lookups = match env::var("SLO_MIN_LOOKUPS") {
+            Ok(value) => value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid SLO_MIN_LOOKUPS: {}", value))?,
+            Err(_) => 10,
+        };
+        Ok(Some(LatencySlo {
+            threshold,
+            target,
+            window,
+            min_lookups,
+        }))
+    }
+
+    /// Counts the lookups of a hostname in the window that ends at a point in time.
+    ///
+    /// # Arguments
+    ///
+    /// * `records`: A `&[CheckRecord]` that holds the history.
+    /// * `hostname`: A `&str` that specifies the monitored hostname.
+    /// * `now`: A `DateTime<FixedOffset>` that specifies the end of the window.
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<Compliance>` that holds the lookups, or `None` if there are fewer than `min_lookups` of them;
+    ///   records without the latency of the lookup, e.g. written before it was tracked, are not counted.
+    ///   A window reaching back beyond the dates chrono can represent covers the whole history.
+    fn evaluate(
+        &self,
+        records: &[CheckRecord],
+        hostname: &str,
+        now: DateTime<FixedOffset>,
+    ) -> Option<Compliance> {
+        let since = ChronoDuration::from_std(self.window)
+            .ok()
+            .and_then(|window| now.checked_sub_signed(window))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC.fixed_offset());
+        let latencies: Vec<u64> = records
+            .iter()
+            .filter(|record| record.hostname == hostname && record.timestamp >= since)
+            .filter_map(|record| record.latencies.get(LOOKUP_CHECK).copied())
+            .collect();
+        if latencies.is_empty() || latencies.len() < self.min_lookups {
+            return None;
+        }
+        Some(Compliance {
+            lookups: latencies.len(),
+            fast: latencies
+                .iter()
+                .filter(|latency| **latency < self.threshold)
+                .count(),
+        })
+    }
+
+    /// Describes the objective, e.g. "99% of lookups under 200 ms over 3600s".
+    fn describe(&self) -> String {
+        format!(
+            "{}% of lookups under {} ms over {}s",
+            self.target,
+            self.threshold,
+            self.window.as_secs()
+        )
+    }
+}
+
+/// Verifies that the latency of the DNS lookups meets the objective.
+///
+/// The latencies of the lookups of the hostname are read from the history (see `history::history_file`)
+/// and counted over the rolling window of the objective (see `LatencySlo::evaluate`).
+/// The result is compared with the state stored in the SLO file ("SLO_FILE", defaulting to "slo_state" in the state directory),
+/// and an "slo" message is sent when the objective is breached and when it is met again,
+/// as an early warning of a slow resolver or ISP before lookups start failing.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `slo`: A `&LatencySlo` that holds the objective.
+pub fn check_slo(hostname: &str, slo: &LatencySlo) {
+    let slo_file = state_path("SLO_FILE", "slo_state");
+    let records = history::load_history(&history::history_file());
+    let compliance = match slo.evaluate(&records, hostname, Local::now().fixed_offset()) {
+        Some(compliance) => compliance,
+        None => {
+            log::debug!("Not enough lookups to evaluate the latency SLO");
+            return;
+        }
+    };
+    log::debug!("Latency SLO of {}: {:?}", hostname, compliance);
+
+    let breached = compliance.percentage() < slo.target;
+    let state = if breached { "breached" } else { "met" };
+    let previous = read_state(&slo_file);
+    if previous == state {
+        return;
+    }
+    if breached || previous == "breached" {
+        let text = slo_message(hostname, slo, &compliance, breached);
+        log::info!("{}", text);
+        let message = Message::new("slo", &text)
+            .with_field("hostname", hostname)
+            .with_field("compliance", &format!("{:.2}", compliance.percentage()));
+        if !events::alert(&message) {
+            log::warn!("Failed to send SLO message");
+            return;
+        }
+    } else {
+        log::info!("Latency SLO of {} is met", hostname);
+    }
+    write_state(&slo_file, state);
+}
+
+/// Builds the text of the SLO message.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `slo`: A `&LatencySlo` that holds the objective.
+/// * `compliance`: A `&Compliance` that holds the lookups of the window.
+/// * `breached`: A `bool` that indicates whether the objective is breached.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message.
+fn slo_message(
+    hostname: &str,
+    slo: &LatencySlo,
+    compliance: &Compliance,
+    breached: bool,
+) -> String {
+    let headline = if breached {
+        "DNS lookup latency SLO breached!"
+    } else {
+        "DNS lookup latency SLO met again"
+    };
+    format!(
+        "{}\nHostname: {}\nObjective: {}\nActual: {:.2}% ({} of {} lookups)",
+        headline,
+        display_hostname(hostname),
+        slo.describe(),
+        compliance.percentage(),
+        compliance.fast,
+        compliance.lookups
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn record(now: DateTime<FixedOffset>, minutes_ago: i64, latency: Option<u64>) -> CheckRecord {
+        CheckRecord {
+            timestamp: now - ChronoDuration::try_minutes(minutes_ago).unwrap(),
+            hostname: "home.example.com".to_string(),
+            dns_ip: "1.1.1.1".to_string(),
+            wan_ip: "1.1.1.1".to_string(),
+            in_sync: true,
+            latencies: latency
+                .map(|latency| BTreeMap::from([(LOOKUP_CHECK.to_string(), latency)]))
+                .unwrap_or_default(),
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let slo = LatencySlo {
+            threshold: 200,
+            target: 75.0,
+            window: Duration::from_secs(60 * 60),
+            min_lookups: 3,
+        };
+        let now = DateTime::parse_from_rfc3339("2024-07-01T12:00:00+02:00").unwrap();
+        let records = vec![
+            record(now, 90, Some(900)),
+            record(now, 50, Some(50)),
+            record(now, 40, None),
+            record(now, 30, Some(250)),
+            record(now, 20, Some(80)),
+            record(now, 10, Some(199)),
+        ];
+        let compliance = slo.evaluate(&records, "home.example.com", now).unwrap();
+        assert_eq!(
+            compliance,
+            Compliance {
+                lookups: 4,
+                fast: 3
+            }
+        );
+        assert_eq!(compliance.percentage(), 75.0);
+        assert_eq!(
+            slo_message("home.example.com", &slo, &compliance, false),
+            "DNS lookup latency SLO met again\nHostname: home.example.com\n\
+             Objective: 75% of lookups under 200 ms over 3600s\nActual: 75.00% (3 of 4 lookups)"
+        );
+
+        // Assert that a window with too few lookups or of another hostname is not evaluated
+        assert_eq!(slo.evaluate(&records[..3], "home.example.com", now), None);
+        assert_eq!(slo.evaluate(&records, "other.example.com", now), None);
+
+        // Assert that a window too long for chrono counts every lookup instead of panicking
+        let slo = LatencySlo {
+            window: Duration::MAX,
+            ..slo
+        };
+        assert_eq!(
+            slo.evaluate(&records, "home.example.com", now)
+                .unwrap()
+                .lookups,
+            5
+        );
+    }
+}
@@ -0,0 +1,312 @@
+use crate::notify::{Message, Notifier};
+use std::env;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// The default base OID of the traps, in the NET-SNMP experimental subtree.
+const DEFAULT_OID: &str = "1.3.6.1.4.1.8072.9999.9999.5353";
+
+/// The OID of "sysUpTime.0", the first variable of every SNMPv2 trap.
+const SYS_UP_TIME: &str = "1.3.6.1.2.1.1.3.0";
+
+/// The OID of "snmpTrapOID.0", the second variable of every SNMPv2 trap, holding the OID of the trap.
+const SNMP_TRAP_OID: &str = "1.3.6.1.6.3.1.1.4.1.0";
+
+/// The default port of the trap receiver of an SNMP manager.
+const DEFAULT_PORT: u16 = 162;
+
+/// The request ID of the next trap.
+static REQUEST_ID: AtomicI32 = AtomicI32::new(1);
+
+/// Sends SNMPv2c traps to an SNMP manager on mismatches and recoveries.
+pub struct SnmpNotifier {
+    manager: String,
+    community: String,
+    oid: Vec<u32>,
+}
+
+impl SnmpNotifier {
+    /// Creates the SNMP notifier from the environment.
+    ///
+    /// This function retrieves the address of the SNMP manager from the "SNMP_MANAGER" environment variable,
+    /// e.g. "nms.example.com" or "nms.example.com:162". If it is not set, there is no SNMP notifier.
+    /// The community is taken from "SNMP_COMMUNITY" (defaulting to "public")
+    /// and the base OID of the traps from "SNMP_TRAP_OID" (see `trap`).
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<SnmpNotifier>` that holds the notifier, or `None` if no manager is configured or the OID is invalid.
+    pub fn from_env() -> Option<SnmpNotifier> {
+        let manager = env::var("SNMP_MANAGER").ok().filter(|m| !m.is_empty())?;
+        let oid = env::var("SNMP_TRAP_OID").unwrap_or(DEFAULT_OID.to_string());
+        let oid = match parse_oid(&oid) {
+            Some(oid) => oid,
+            None => {
+                log::warn!("Invalid SNMP_TRAP_OID: {}", oid);
+                return None;
+            }
+        };
+        Some(SnmpNotifier {
+            manager,
+            community: env::var("SNMP_COMMUNITY").unwrap_or("public".to_string()),
+            oid,
+        })
+    }
+}
+
+impl Notifier for SnmpNotifier {
+    fn name(&self) -> &str {
+        "snmp"
+    }
+
+    fn handles(&self, message: &Message) -> bool {
+        trap_number(&message.event).is_some()
+    }
+
+    fn send(&self, message: &Message) -> bool {
+        let number = match trap_number(&message.event) {
+            Some(number) => number,
+            None => return true,
+        };
+        let address = if self.manager.contains(':') {
+            self.manager.clone()
+        } else {
+            format!("{}:{}", self.manager, DEFAULT_PORT)
+        };
+        let packet = trap(
+            &self.community,
+            &self.oid,
+            number,
+            message,
+            uptime(),
+            REQUEST_ID.fetch_add(1, Ordering::SeqCst),
+        );
+        let sent = address
+            .to_socket_addrs()
+            .map_err(|e| e.to_string())
+            .and_then(|mut addresses| addresses.next().ok_or("no address".to_string()))
+            .and_then(|target| {
+                let local = if target.is_ipv6() {
+                    "[::]:0"
+                } else {
+                    "0.0.0.0:0"
+                };
+                UdpSocket::bind(local)
+                    .and_then(|socket| socket.send_to(&packet, target))
+                    .map_err(|e| e.to_string())
+            });
+        match sent {
+            Ok(_) => true,
+            Err(e) => {
+                log::warn!("Failed to send SNMP trap to {}: {}", address, e);
+                false
+            }
+        }
+    }
+}
+
+/// Returns the number of the trap of an event: 1 for a mismatch and 2 for a recovery.
+fn trap_number(event: &str) -> Option<u32> {
+    match event {
+        "mismatch" => Some(1),
+        "recovery" => Some(2),
+        _ => None,
+    }
+}
+
+/// Returns the time since the first trap in hundredths of a second, as sent in "sysUpTime.0".
+fn uptime() -> u32 {
+    static STARTED: OnceLock<Instant> = OnceLock::new();
+    let elapsed = STARTED.get_or_init(Instant::now).elapsed();
+    (elapsed.as_millis() / 10) as u32
+}
+
+/// Parses a dotted OID, e.g. "1.3.6.1.4.1.8072".
+fn parse_oid(oid: &str) -> Option<Vec<u32>> {
+    let arcs: Vec<u32> = oid
+        .trim_start_matches('.')
+        .split('.')
+        .map(|arc| arc.parse().ok())
+        .collect::<Option<Vec<u32>>>()?;
+    if arcs.len() < 2 || arcs[0] > 2 || (arcs[0] < 2 && arcs[1] >= 40) {
+        return None;
+    }
+    Some(arcs)
+}
+
+/// Encodes an SNMPv2c trap.
+///
+/// The trap OID is the base OID followed by ".0.<number>", as SMIv2 notifications are defined.
+/// Besides "sysUpTime.0" and "snmpTrapOID.0", the trap carries these variables as strings:
+///
+/// * `<base>.1.1`: The text of the message.
+/// * `<base>.1.2`: The hostname.
+/// * `<base>.1.3`: The router IP address.
+/// * `<base>.1.4`: The IP address from DNS.
+///
+/// # Arguments
+///
+/// * `community`: A `&str` that specifies the community.
+/// * `oid`: A `&[u32]` that holds the base OID.
+/// * `number`: A `u32` that specifies the number of the trap, see `trap_number`.
+/// * `message`: A `&Message` that holds the notification.
+/// * `uptime`: A `u32` that specifies the uptime in hundredths of a second.
+/// * `request_id`: An `i32` that specifies the request ID.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` that holds the BER encoded message.
+fn trap(
+    community: &str,
+    oid: &[u32],
+    number: u32,
+    message: &Message,
+    uptime: u32,
+    request_id: i32,
+) -> Vec<u8> {
+    let child = |suffix: &[u32]| [oid, suffix].concat();
+    let field = |name: &str| {
+        message
+            .fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_default()
+    };
+    let mut varbinds = vec![
+        varbind(
+            &parse_oid(SYS_UP_TIME).unwrap(),
+            tlv(0x43, &unsigned(uptime)),
+        ),
+        varbind(
+            &parse_oid(SNMP_TRAP_OID).unwrap(),
+            tlv(0x06, &encode_oid(&child(&[0, number]))),
+        ),
+    ];
+    let values = [
+        message.text.as_str(),
+        field("hostname"),
+        field("router_ip"),
+        field("dns_ip"),
+    ];
+    for (index, value) in (1..).zip(values) {
+        varbinds.push(varbind(&child(&[1, index]), tlv(0x04, value.as_bytes())));
+    }
+    let pdu = [
+        tlv(0x02, &integer(request_id as i64)),
+        tlv(0x02, &integer(0)),
+        tlv(0x02, &integer(0)),
+        tlv(0x30, &varbinds.concat()),
+    ]
+    .concat();
+    let snmp_message = [
+        // Version 1 is SNMPv2c
+        tlv(0x02, &integer(1)),
+        tlv(0x04, community.as_bytes()),
+        tlv(0xa7, &pdu),
+    ]
+    .concat();
+    tlv(0x30, &snmp_message)
+}
+
+/// Encodes a variable binding of an OID and an encoded value.
+fn varbind(oid: &[u32], value: Vec<u8>) -> Vec<u8> {
+    tlv(0x30, &[tlv(0x06, &encode_oid(oid)), value].concat())
+}
+
+/// Encodes a BER type, length and value.
+fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    if value.len() < 0x80 {
+        encoded.push(value.len() as u8);
+    } else {
+        let length = (value.len() as u32).to_be_bytes();
+        let skip = length.iter().take_while(|byte| **byte == 0).count();
+        encoded.push(0x80 | (length.len() - skip) as u8);
+        encoded.extend_from_slice(&length[skip..]);
+    }
+    encoded.extend_from_slice(value);
+    encoded
+}
+
+/// Encodes the contents of a BER integer in the fewest bytes.
+fn integer(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let mut start = 0;
+    // A leading byte is redundant if it only repeats the sign bit of the next byte
+    while start < bytes.len() - 1
+        && ((bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0))
+    {
+        start += 1;
+    }
+    bytes[start..].to_vec()
+}
+
+/// Encodes the contents of an unsigned BER integer, e.g. TimeTicks.
+fn unsigned(value: u32) -> Vec<u8> {
+    integer(value as i64)
+}
+
+/// Encodes the contents of a BER object identifier.
+fn encode_oid(oid: &[u32]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    let first = oid[0] * 40 + oid.get(1).copied().unwrap_or(0);
+    for arc in std::iter::once(first).chain(oid.iter().skip(2).copied()) {
+        let mut bytes = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            bytes.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        encoded.extend(bytes.iter().rev());
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoding() {
+        assert_eq!(integer(0), [0x00]);
+        assert_eq!(integer(127), [0x7f]);
+        assert_eq!(integer(128), [0x00, 0x80]);
+        assert_eq!(integer(-1), [0xff]);
+        assert_eq!(
+            encode_oid(&parse_oid("1.3.6.1.4.1.8072").unwrap()),
+            [0x2b, 0x06, 0x01, 0x04, 0x01, 0xbf, 0x08]
+        );
+        assert_eq!(tlv(0x04, &[0u8; 200])[..3], [0x04, 0x81, 200]);
+        assert_eq!(parse_oid(".1.3.6"), Some(vec![1, 3, 6]));
+        assert_eq!(parse_oid("1.3.x"), None);
+        assert_eq!(parse_oid("1.40"), None);
+    }
+
+    #[test]
+    fn test_trap() {
+        let message = Message::new("mismatch", "IP address mismatch!")
+            .with_field("hostname", "home.example.com")
+            .with_field("router_ip", "203.0.113.7")
+            .with_field("dns_ip", "198.51.100.4");
+        let result = trap("public", &[1, 3, 6, 1, 4, 1, 8072], 1, &message, 100, 7);
+
+        // SEQUENCE, version 1, community "public", SNMPv2-Trap-PDU
+        assert_eq!(result[..2], [0x30, 0x81]);
+        assert_eq!(result[2] as usize, result.len() - 3);
+        let body = &result[3..];
+        assert_eq!(
+            body[..11],
+            [0x02, 0x01, 0x01, 0x04, 0x06, b'p', b'u', b'b', b'l', b'i', b'c']
+        );
+        assert_eq!(body[11], 0xa7);
+        // The trap OID is the base OID followed by .0.1
+        let trap_oid = tlv(0x06, &encode_oid(&[1, 3, 6, 1, 4, 1, 8072, 0, 1]));
+        assert!(result.windows(trap_oid.len()).any(|w| w == trap_oid));
+        let hostname = tlv(0x04, b"home.example.com");
+        assert!(result.windows(hostname.len()).any(|w| w == hostname));
+    }
+}
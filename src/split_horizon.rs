@@ -0,0 +1,215 @@
+use crate::dns;
+use crate::events;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::vars::{display_hostname, split_list};
+use ipnet::IpNet;
+use std::env;
+use std::net::IpAddr;
+
+/// Retrieves the internal answers that are expected to differ from the external answer.
+///
+/// This function parses the comma-separated list of addresses or networks in the "SPLIT_HORIZON_ALLOW" environment variable
+/// (e.g. "192.168.1.10" or "192.168.0.0/16"). If it is not set, the list is empty.
+///
+/// # Returns
+///
+/// * A `Result<Vec<IpNet>, String>` that holds the networks; a single address is a network of one address.
+/// * If an entry is invalid, it returns an `Err` with a message.
+pub fn allowlist() -> Result<Vec<IpNet>, String> {
+    split_list(&env::var("SPLIT_HORIZON_ALLOW").unwrap_or_default())
+        .iter()
+        .map(|entry| {
+            entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| {
+                    format!(
+                        "Invalid address or network in SPLIT_HORIZON_ALLOW: {}",
+                        entry
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Verifies that the internal and the external resolver agree on the hostname.
+///
+/// This function retrieves the value of the "INTERNAL_RESOLVER" environment variable (e.g. "192.168.1.1").
+/// If it is not set, the split-horizon check is disabled and the function returns immediately.
+/// The external resolver is taken from "EXTERNAL_RESOLVER", defaulting to the upstream resolvers (see `dns::default_resolver`).
+///
+/// It resolves the hostname with both resolvers. The answers agree if they hold the same addresses, in any order,
+/// or if the internal answer is in the allowlist, e.g. for a deliberate override with a LAN address.
+/// The result is compared with the state stored in the split-horizon file ("SPLIT_HORIZON_FILE", defaulting to "split_horizon_state" in the state directory),
+/// and a "split_horizon" message is sent when the answers start or stop disagreeing.
+///
+/// # Arguments
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `allow`: A `&[IpNet]` that holds the expected internal answers, see `allowlist`.
+pub fn check_split_horizon(hostname: &str, allow: &[IpNet]) {
+    let internal = match env::var("INTERNAL_RESOLVER") {
+        Ok(internal) => internal,
+        Err(_) => return,
+    };
+    let external = env::var("EXTERNAL_RESOLVER").unwrap_or_else(|_| dns::default_resolver());
+    let split_horizon_file = state_path("SPLIT_HORIZON_FILE", "split_horizon_state");
+
+    let internal_ips = dns::resolve_addresses_using(hostname, &internal);
+    let external_ips = dns::resolve_addresses_using(hostname, &external);
+    log::debug!(
+        "{} resolves to {:?} internally and {:?} externally",
+        hostname,
+        internal_ips,
+        external_ips
+    );
+    if internal_ips.is_empty() && external_ips.is_empty() {
+        log::debug!("Neither resolver answered, skipping split-horizon check");
+        return;
+    }
+
+    let agree = answers_agree(&internal_ips, &external_ips, allow);
+    let state = if agree { "ok" } else { "mismatch" };
+    let previous = read_state(&split_horizon_file);
+    if previous == state {
+        return;
+    }
+    if previous.is_empty() && state == "ok" {
+        log::info!("Internal and external answers for {} agree", hostname);
+    } else {
+        let internal_ip = internal_ips.join(", ");
+        let external_ip = external_ips.join(", ");
+        let text = split_horizon_message(hostname, &internal_ip, &external_ip, agree);
+        log::info!("{}", text);
+        let message = Message::new("split_horizon", &text)
+            .with_field("hostname", hostname)
+            .with_field("internal_ip", &internal_ip)
+            .with_field("external_ip", &external_ip);
+        if !events::alert(&message) {
+            log::warn!("Failed to send split-horizon message");
+            return;
+        }
+    }
+    write_state(&split_horizon_file, state);
+}
+
+/// Checks whether the internal answer agrees with the external answer.
+///
+/// # Arguments
+///
+/// * `internal_ips`: A `&[String]` that holds the answer of the internal resolver, sorted.
+/// * `external_ips`: A `&[String]` that holds the answer of the external resolver, sorted.
+/// * `allow`: A `&[IpNet]` that holds the expected internal answers.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether the answers hold the same addresses or every internal address is expected.
+fn answers_agree(internal_ips: &[String], external_ips: &[String], allow: &[IpNet]) -> bool {
+    internal_ips == external_ips
+        || (!internal_ips.is_empty()
+            && internal_ips.iter().all(|internal_ip| {
+                internal_ip
+                    .parse::<IpAddr>()
+                    .is_ok_and(|ip| allow.iter().any(|network| network.contains(&ip)))
+            }))
+}
+
+/// Builds the text of the split-horizon message.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `internal_ip`: A `&str` that specifies the answer of the internal resolver.
+/// * `external_ip`: A `&str` that specifies the answer of the external resolver.
+/// * `agree`: A `bool` that indicates whether the answers agree, see `answers_agree`.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message.
+fn split_horizon_message(
+    hostname: &str,
+    internal_ip: &str,
+    external_ip: &str,
+    agree: bool,
+) -> String {
+    let or_none = |ip: &str| {
+        if ip.is_empty() {
+            "no answer".to_string()
+        } else {
+            ip.to_string()
+        }
+    };
+    let headline = if agree {
+        "Internal and external DNS agree again"
+    } else {
+        "Internal and external DNS disagree!"
+    };
+    format!(
+        "{}\nHostname: {}\nInternal: {}\nExternal: {}",
+        headline,
+        display_hostname(hostname),
+        or_none(internal_ip),
+        or_none(external_ip)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_answers_agree() {
+        let allow = vec![
+            "192.168.1.10/32".parse().unwrap(),
+            "10.0.0.0/8".parse().unwrap(),
+        ];
+
+        let ips = |ips: &[&str]| ips.iter().map(|ip| ip.to_string()).collect::<Vec<String>>();
+
+        assert!(answers_agree(&ips(&["1.1.1.1"]), &ips(&["1.1.1.1"]), &[]));
+        assert!(!answers_agree(&ips(&["1.1.1.1"]), &ips(&["2.2.2.2"]), &[]));
+        assert!(!answers_agree(&[], &ips(&["2.2.2.2"]), &allow));
+
+        // Assert that round-robin answers agree as sets, but not when an address is missing
+        assert!(answers_agree(
+            &ips(&["1.1.1.1", "2.2.2.2"]),
+            &ips(&["1.1.1.1", "2.2.2.2"]),
+            &[]
+        ));
+        assert!(!answers_agree(
+            &ips(&["1.1.1.1"]),
+            &ips(&["1.1.1.1", "2.2.2.2"]),
+            &[]
+        ));
+
+        // Assert that expected overrides agree, and stale ones do not
+        assert!(answers_agree(
+            &ips(&["192.168.1.10"]),
+            &ips(&["2.2.2.2"]),
+            &allow
+        ));
+        assert!(answers_agree(
+            &ips(&["10.1.2.3"]),
+            &ips(&["2.2.2.2"]),
+            &allow
+        ));
+        assert!(!answers_agree(
+            &ips(&["192.168.1.11"]),
+            &ips(&["2.2.2.2"]),
+            &allow
+        ));
+        assert!(!answers_agree(
+            &ips(&["10.1.2.3", "192.168.1.11"]),
+            &ips(&["2.2.2.2"]),
+            &allow
+        ));
+    }
+
+    #[test]
+    fn test_split_horizon_message() {
+        let result = split_horizon_message("home.example.com", "192.168.1.11", "", false);
+        assert!(result.starts_with("Internal and external DNS disagree!"));
+        assert!(result.contains("Internal: 192.168.1.11"));
+        assert!(result.contains("External: no answer"));
+    }
+}
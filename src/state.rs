@@ -0,0 +1,258 @@
+use crate::store::store;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// The state of the files that could not be written, by path; `None` for a removed file.
+///
+/// On a read-only filesystem, or while the backend of the state is unreachable, state is kept here instead,
+/// so the alarm still works until the process restarts.
+static MEMORY: Mutex<BTreeMap<String, Option<String>>> = Mutex::new(BTreeMap::new());
+
+/// The paths that were reported as not writable, so each is only warned about once.
+static UNWRITABLE: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Retrieves the directory of the state files from the "STATE_DIR" environment variable, defaulting to "/tmp".
+pub fn state_dir() -> String {
+    env::var("STATE_DIR")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .unwrap_or("/tmp".to_string())
+}
+
+/// Retrieves the path of a state file.
+///
+/// # Arguments
+///
+/// * `var`: A `&str` that specifies the environment variable that overrides the path, e.g. "LOCKFILE".
+/// * `file_name`: A `&str` that specifies the name of the file in the state directory (see `state_dir`), e.g. "telegram.lock".
+///
+/// # Returns
+///
+/// * A `String` that holds the value of the variable if it is set, or the file in the state directory.
+pub fn state_path(var: &str, file_name: &str) -> String {
+    match env::var(var) {
+        Ok(path) if !path.is_empty() => path,
+        _ => Path::new(&state_dir())
+            .join(file_name)
+            .to_string_lossy()
+            .into_owned(),
+    }
+}
+
+/// Warns that the state directory is not writable, e.g. on a read-only root filesystem.
+///
+/// This function is called at startup. It creates and removes a probe file in the state directory (see `state_dir`),
+/// or a probe value if the state is kept in another backend (see `store::store`).
+/// If that fails, state is kept in memory (see `write_state`), so alarms and recoveries still work,
+/// but the state is lost on restart and an alarm may be sent again.
+pub fn check_state_dir() {
+    let store = store();
+    if store.name() != "file" {
+        let probe = format!(".dnschecker-{}", std::process::id());
+        if let Err(e) = store
+            .write(&probe, "")
+            .and_then(|_| store.remove(&probe).map(|_| ()))
+        {
+            log::warn!(
+                "State backend {} is not writable ({}), keeping state in memory until it is",
+                store.name(),
+                e
+            );
+        }
+        return;
+    }
+    let dir = state_dir();
+    let probe = Path::new(&dir).join(format!(".dnschecker-{}", std::process::id()));
+    match std::fs::write(&probe, "") {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&probe);
+        }
+        Err(e) => log::warn!(
+            "State directory {} is not writable ({}), keeping state in memory; set STATE_DIR to a writable directory to keep it across restarts",
+            dir,
+            e
+        ),
+    }
+}
+
+/// Logs that a state file cannot be written, with a warning the first time and at debug level afterwards.
+///
+/// # Arguments
+///
+/// * `path`: A `&str` that specifies the path of the file.
+/// * `error`: A `&dyn std::fmt::Display` that holds the error.
+pub fn report_unwritable(path: &str, error: &dyn std::fmt::Display) {
+    let first = UNWRITABLE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(path.to_string());
+    if first {
+        log::warn!(
+            "Failed to write state file {}: {}, keeping its state in memory",
+            path,
+            error
+        );
+    } else {
+        log::debug!("Failed to write state file {}: {}", path, error);
+    }
+}
+
+/// Reads a value from a state file.
+///
+/// This function takes the path of a state file as an argument.
+/// If the state of the file is kept in memory because it could not be written (see `write_state`), it returns that state.
+/// Otherwise it reads the file from the backend of the state (see `store::store`) and trims surrounding whitespace.
+/// If the file does not exist or cannot be read, it returns an empty `String`.
+///
+/// # Arguments
+///
+/// * `path`: A `&str` that specifies the path of the state file.
+///
+/// # Returns
+///
+/// * A `String` that holds the stored value, or an empty `String` if there is none.
+pub fn read_state(path: &str) -> String {
+    if let Some(value) = MEMORY.lock().unwrap_or_else(|e| e.into_inner()).get(path) {
+        return value.as_deref().unwrap_or_default().trim().to_string();
+    }
+    match store().read(path) {
+        Ok(Some(value)) => value.trim().to_string(),
+        Ok(None) => {
+            log::trace!("No state in {}", path);
+            String::new()
+        }
+        Err(e) => {
+            log::warn!("Failed to read state {}: {}", path, e);
+            String::new()
+        }
+    }
+}
+
+/// Writes a value to a state file.
+///
+/// This function takes the path of a state file and a value as arguments.
+/// It replaces the contents of the file in the backend of the state (see `store::store`) with the value.
+/// If writing fails, e.g. on a read-only filesystem, the value is kept in memory instead and read back by `read_state`
+/// until the process restarts or the value is written; the failure is logged with `report_unwritable`.
+///
+/// # Arguments
+///
+/// * `path`: A `&str` that specifies the path of the state file.
+/// * `value`: A `&str` that specifies the value to store.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether the value was written to the file.
+pub fn write_state(path: &str, value: &str) -> bool {
+    let mut memory = MEMORY.lock().unwrap_or_else(|e| e.into_inner());
+    match store().write(path, value) {
+        Ok(_) => {
+            memory.remove(path);
+            true
+        }
+        Err(e) => {
+            report_unwritable(path, &e);
+            memory.insert(path.to_string(), Some(value.to_string()));
+            false
+        }
+    }
+}
+
+/// Appends a line to a state file, e.g. a record to the history.
+///
+/// If the state of the file is kept in memory (see `write_state`), the line is appended there.
+/// If appending fails, the line is dropped and the failure is logged with `report_unwritable`.
+///
+/// # Arguments
+///
+/// * `path`: A `&str` that specifies the path of the state file.
+/// * `line`: A `&str` that specifies the line, without the line break.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether the line was appended to the file.
+pub fn append_state(path: &str, line: &str) -> bool {
+    let mut memory = MEMORY.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(Some(value)) = memory.get_mut(path) {
+        value.push_str(line);
+        value.push('\n');
+        return false;
+    }
+    match store().append(path, line) {
+        Ok(_) => true,
+        Err(e) => {
+            report_unwritable(path, &e);
+            false
+        }
+    }
+}
+
+/// Removes a state file, or its state kept in memory.
+///
+/// # Arguments
+///
+/// * `path`: A `&str` that specifies the path of the state file.
+///
+/// # Returns
+///
+/// * A `Result<(), String>` that is `Ok` if the state was removed, or an `Err` with a message if the file
+///   cannot be removed, e.g. because it does not exist.
+pub fn remove_state(path: &str) -> Result<(), String> {
+    let mut memory = MEMORY.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(value) = memory.get_mut(path) {
+        if value.take().is_none() {
+            return Err(format!("Failed to remove {}: no state", path));
+        }
+        // Remember the removal while the file that could not be written still holds older state
+        if store().remove(path).is_ok() {
+            memory.remove(path);
+        }
+        return Ok(());
+    }
+    match store().remove(path) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!("Failed to remove {}: no state", path)),
+        Err(e) => Err(format!("Failed to remove {}: {}", path, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state() {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap().to_string();
+
+        // Write a value and read it back
+        assert!(write_state(&path, "igb4\n"));
+        assert_eq!(read_state(&path), "igb4");
+
+        // Assert that a missing file reads as empty
+        assert_eq!(read_state("/nonexistent/state"), "");
+        assert!(remove_state(&path).is_ok());
+        assert!(remove_state(&path).is_err());
+    }
+
+    #[test]
+    fn test_memory_fallback() {
+        // Assert that state that cannot be written is kept in memory
+        let path = "/nonexistent/telegram.lock";
+        assert!(!write_state(path, "Wed, 1 May 2024 12:00:00 +0200"));
+        assert_eq!(read_state(path), "Wed, 1 May 2024 12:00:00 +0200");
+        assert!(remove_state(path).is_ok());
+        assert_eq!(read_state(path), "");
+        assert!(remove_state(path).is_err());
+    }
+
+    #[test]
+    fn test_state_path() {
+        env::set_var("STATE_TEST_FILE", "/data/state");
+        assert_eq!(state_path("STATE_TEST_FILE", "state"), "/data/state");
+        env::remove_var("STATE_TEST_FILE");
+        assert!(state_path("STATE_TEST_FILE", "state").ends_with("/state"));
+    }
+}
@@ -0,0 +1,307 @@
+use crate::dependencies::DependencyHealth;
+use crate::history::{self, CheckRecord};
+use chrono::{DateTime, Duration as ChronoDuration, FixedOffset, Local};
+use std::collections::BTreeMap;
+
+/// The longest period DNS was out of sync, including periods the verification failed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outage {
+    /// The first check that found DNS out of sync or failed.
+    pub start: DateTime<FixedOffset>,
+    /// The time until the first check that found DNS in sync again, or until the last check if it is ongoing.
+    pub duration: ChronoDuration,
+    /// Whether DNS was still out of sync at the last check.
+    pub ongoing: bool,
+}
+
+/// Statistics aggregated from the history, as printed by the `stats` command.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Statistics {
+    /// The number of checks in the period.
+    pub checks: usize,
+    /// The percentage of time DNS was in sync, see `history::in_sync_percentage`, `None` if there are no checks.
+    pub in_sync: Option<f64>,
+    /// The average time each check took in milliseconds, by name, over the checks that recorded it.
+    pub latencies: BTreeMap<String, f64>,
+    /// The average time of a whole verification in milliseconds, `None` if no check recorded its latency.
+    pub total_latency: Option<f64>,
+    /// The number of WAN IP address changes per month, e.g. "2024-05".
+    pub changes_per_month: BTreeMap<String, usize>,
+    /// The longest period DNS was out of sync, `None` if it never was.
+    pub longest_outage: Option<Outage>,
+}
+
+/// Aggregates the history since a point in time.
+///
+/// A WAN IP address change is counted in the month of the first check that saw the new address;
+/// checks that could not retrieve the WAN IP address are skipped.
+/// An outage runs from the first check that found DNS out of sync to the first check that found it in sync again.
+/// A failed verification, e.g. a lookup that failed with NXDOMAIN, is recorded as out of sync
+/// (see `CheckRecord::from_context`), so it is part of an outage.
+///
+/// # Arguments
+///
+/// * `records`: A `&[CheckRecord]` that holds the history, oldest first.
+/// * `since`: A `DateTime<Local>` that specifies the start of the period.
+///
+/// # Returns
+///
+/// * A `Statistics` that holds the aggregates of the period.
+pub fn statistics(records: &[CheckRecord], since: DateTime<Local>) -> Statistics {
+    let period: Vec<&CheckRecord> = records
+        .iter()
+        .filter(|record| record.timestamp >= since)
+        .collect();
+    let mut stats = Statistics {
+        checks: period.len(),
+        in_sync: history::in_sync_percentage(records, since),
+        ..Default::default()
+    };
+
+    let mut sums: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+    let mut totals = Vec::new();
+    for record in &period {
+        for (check, latency) in &record.latencies {
+            let sum = sums.entry(check).or_default();
+            sum.0 += latency;
+            sum.1 += 1;
+        }
+        if !record.latencies.is_empty() {
+            totals.push(record.latencies.values().sum::<u64>());
+        }
+    }
+    stats.latencies = sums
+        .into_iter()
+        .map(|(check, (sum, count))| (check.to_string(), sum as f64 / count as f64))
+        .collect();
+    stats.total_latency =
+        (!totals.is_empty()).then(|| totals.iter().sum::<u64>() as f64 / totals.len() as f64);
+
+    let addresses: Vec<&CheckRecord> = period
+        .iter()
+        .copied()
+        .filter(|record| !record.wan_ip.is_empty())
+        .collect();
+    for pair in addresses.windows(2) {
+        if pair[0].wan_ip != pair[1].wan_ip {
+            let month = pair[1].timestamp.format("%Y-%m").to_string();
+            *stats.changes_per_month.entry(month).or_default() += 1;
+        }
+    }
+
+    let mut start: Option<DateTime<FixedOffset>> = None;
+    let mut outages = Vec::new();
+    for record in &period {
+        match (start, record.in_sync) {
+            (None, false) => start = Some(record.timestamp),
+            (Some(began), true) => {
+                outages.push(Outage {
+                    start: began,
+                    duration: record.timestamp - began,
+                    ongoing: false,
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let (Some(began), Some(last)) = (start, period.last()) {
+        outages.push(Outage {
+            start: began,
+            duration: last.timestamp - began,
+            ongoing: true,
+        });
+    }
+    stats.longest_outage = outages.into_iter().max_by_key(|outage| outage.duration);
+    stats
+}
+
+/// Describes the statistics and the failure rate of each dependency, one line each.
+///
+/// The failure rate comes from the call counters of the dependencies, which are kept since the
+/// checker first ran with the state directory (see `Dependencies::load`), not from the history.
+/// It is therefore labeled "since start" and not limited to the period of the statistics.
+///
+/// # Arguments
+///
+/// * `stats`: A `&Statistics` that holds the aggregates of the history.
+/// * `dependencies`: A `&BTreeMap<String, DependencyHealth>` that holds the health of the dependencies, see `dependencies::load_dependencies`.
+///
+/// # Returns
+///
+/// * A `Vec<String>` that holds the lines, grouped in sections.
+pub fn stats_lines(
+    stats: &Statistics,
+    dependencies: &BTreeMap<String, DependencyHealth>,
+) -> Vec<String> {
+    let mut lines = vec![format!("Checks: {}", stats.checks)];
+    if let Some(in_sync) = stats.in_sync {
+        lines.push(format!("DNS in sync: {:.2}%", in_sync));
+    }
+
+    lines.push("\nAverage latency:".to_string());
+    match stats.total_latency {
+        Some(total) => lines.push(format!("  verification: {:.0} ms", total)),
+        None => lines.push("  not recorded".to_string()),
+    }
+    for (check, latency) in &stats.latencies {
+        lines.push(format!("  {}: {:.0} ms", check, latency));
+    }
+
+    lines.push("\nFailure rate (since start):".to_string());
+    let rates: Vec<String> = dependencies
+        .iter()
+        .filter_map(|(name, health)| {
+            health.failure_rate().map(|rate| {
+                format!(
+                    "  {}: {:.2}% ({} of {} calls)",
+                    name,
+                    rate,
+                    health.failures,
+                    health.successes + health.failures
+                )
+            })
+        })
+        .collect();
+    if rates.is_empty() {
+        lines.push("  no dependency called yet".to_string());
+    }
+    lines.extend(rates);
+
+    lines.push("\nWAN IP changes:".to_string());
+    if stats.changes_per_month.is_empty() {
+        lines.push("  none".to_string());
+    }
+    for (month, changes) in &stats.changes_per_month {
+        lines.push(format!("  {}: {}", month, changes));
+    }
+
+    let minutes = |duration: ChronoDuration| duration.num_minutes().max(0);
+    match &stats.longest_outage {
+        Some(outage) => lines.push(format!(
+            "\nLongest outage: {}h {}m from {}{}",
+            minutes(outage.duration) / 60,
+            minutes(outage.duration) % 60,
+            outage.start.to_rfc3339(),
+            if outage.ongoing { " (ongoing)" } else { "" }
+        )),
+        None => lines.push("\nLongest outage: none".to_string()),
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed(minutes_ago: i64) -> CheckRecord {
+        CheckRecord {
+            latencies: BTreeMap::new(),
+            ..record(minutes_ago, "", false, 0)
+        }
+    }
+
+    fn record(minutes_ago: i64, wan_ip: &str, in_sync: bool, latency: u64) -> CheckRecord {
+        CheckRecord {
+            timestamp: (Local::now() - ChronoDuration::try_minutes(minutes_ago).unwrap())
+                .fixed_offset(),
+            hostname: "home.example.com".to_string(),
+            dns_ip: "1.1.1.1".to_string(),
+            wan_ip: wan_ip.to_string(),
+            in_sync,
+            latencies: BTreeMap::from([
+                ("fetch_wan_ip".to_string(), latency),
+                ("resolve_dns".to_string(), 10),
+            ]),
+        }
+    }
+
+    #[test]
+    fn test_statistics() {
+        let records = vec![
+            record(300, "1.1.1.1", true, 20),
+            record(200, "2.2.2.2", false, 40),
+            record(80, "2.2.2.2", true, 60),
+            record(60, "3.3.3.3", false, 80),
+            record(30, "3.3.3.3", false, 100),
+        ];
+        let stats = statistics(
+            &records,
+            Local::now() - ChronoDuration::try_days(1).unwrap(),
+        );
+
+        assert_eq!(stats.checks, 5);
+        assert_eq!(stats.latencies["fetch_wan_ip"], 60.0);
+        assert_eq!(stats.total_latency, Some(70.0));
+        assert_eq!(stats.changes_per_month.values().sum::<usize>(), 2);
+
+        // Assert that the closed outage of 2 hours beats the ongoing one of 30 minutes
+        let outage = stats.longest_outage.unwrap();
+        assert_eq!(outage.duration.num_minutes(), 120);
+        assert!(!outage.ongoing);
+
+        let lines = stats_lines(&statistics(&records[3..], Local::now()), &BTreeMap::new());
+        assert!(lines.contains(&"Checks: 0".to_string()));
+        assert!(lines.contains(&"  not recorded".to_string()));
+        assert!(lines.contains(&"\nLongest outage: none".to_string()));
+    }
+
+    #[test]
+    fn test_stats_lines() {
+        let records = vec![
+            record(60, "1.1.1.1", true, 20),
+            record(30, "1.1.1.1", false, 40),
+        ];
+        let dependencies = BTreeMap::from([(
+            "resolver google".to_string(),
+            DependencyHealth {
+                successes: 3,
+                failures: 1,
+                ..Default::default()
+            },
+        )]);
+        let lines = stats_lines(
+            &statistics(
+                &records,
+                Local::now() - ChronoDuration::try_days(1).unwrap(),
+            ),
+            &dependencies,
+        );
+
+        assert!(lines.contains(&"DNS in sync: 50.00%".to_string()));
+        assert!(lines.contains(&"  fetch_wan_ip: 30 ms".to_string()));
+        assert!(lines.contains(&"\nFailure rate (since start):".to_string()));
+        assert!(lines.contains(&"  resolver google: 25.00% (1 of 4 calls)".to_string()));
+        assert!(lines.last().unwrap().ends_with(" (ongoing)"));
+    }
+
+    #[test]
+    fn test_statistics_since() {
+        let records = vec![
+            record(600, "1.1.1.1", true, 20),
+            record(500, "1.1.1.1", false, 20),
+            record(200, "1.1.1.1", true, 20),
+            record(120, "1.1.1.1", true, 20),
+            failed(90),
+            failed(80),
+            record(30, "1.1.1.1", true, 40),
+            record(10, "2.2.2.2", true, 60),
+        ];
+        let stats = statistics(
+            &records,
+            Local::now() - ChronoDuration::try_minutes(150).unwrap(),
+        );
+
+        // Assert that only the checks of the period are aggregated
+        assert_eq!(stats.checks, 5);
+        assert_eq!(stats.latencies["fetch_wan_ip"], 40.0);
+
+        // Assert that the failed lookups are an outage, while the longer one before the period is ignored
+        let outage = stats.longest_outage.unwrap();
+        assert_eq!(outage.duration.num_minutes(), 60);
+        assert!(!outage.ongoing);
+
+        // Assert that the failed checks do not count as WAN IP changes
+        assert_eq!(stats.changes_per_month.values().sum::<usize>(), 1);
+    }
+}
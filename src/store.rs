@@ -0,0 +1,468 @@
+use crate::kubernetes;
+use crate::state::state_path;
+use rusqlite::{Connection, OptionalExtension};
+use std::env;
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The backend of the state, created on first use, see `store`.
+static STORE: OnceLock<Box<dyn StateStore>> = OnceLock::new();
+
+/// The identity of this instance, see `instance_id`.
+static INSTANCE: OnceLock<String> = OnceLock::new();
+
+/// A backend the state of the checker is kept in: the alarm lockfiles, the state of the checks and the history.
+///
+/// Every value is stored by key; the key of a value is the path of its state file (see `state::state_path`),
+/// so the path settings such as "LOCKFILE" keep working with every backend.
+/// The shared backends store a value under the file name of its path (see `shared_key`),
+/// so replicas with different state directories still share it.
+pub trait StateStore: Send + Sync {
+    /// The name of the backend, as used in "STATE_BACKEND".
+    fn name(&self) -> &str;
+
+    /// Reads a value, `None` if there is none.
+    fn read(&self, key: &str) -> Result<Option<String>, String>;
+
+    /// Replaces a value.
+    fn write(&self, key: &str, value: &str) -> Result<(), String>;
+
+    /// Removes a value, returning whether there was one.
+    fn remove(&self, key: &str) -> Result<bool, String>;
+
+    /// Appends a line to a value, e.g. a record to the history.
+    fn append(&self, key: &str, line: &str) -> Result<(), String>;
+
+    /// Acquires a lock, or renews it if the owner already holds it.
+    ///
+    /// The lock expires after `ttl` unless it is renewed, so an instance that stops does not hold it forever.
+    /// Returns whether the owner holds the lock.
+    fn try_lock(&self, name: &str, owner: &str, ttl: Duration) -> Result<bool, String>;
+}
+
+/// Keeps the state in files, the default.
+///
+/// The files are local to the instance, so locks are always acquired.
+pub struct FileStore;
+
+impl StateStore for FileStore {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn read(&self, key: &str) -> Result<Option<String>, String> {
+        match std::fs::read_to_string(key) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), String> {
+        std::fs::write(key, value).map_err(|e| e.to_string())
+    }
+
+    fn remove(&self, key: &str) -> Result<bool, String> {
+        match std::fs::remove_file(key) {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn append(&self, key: &str, line: &str) -> Result<(), String> {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(key)
+            .and_then(|mut file| writeln!(file, "{}", line))
+            .map_err(|e| e.to_string())
+    }
+
+    fn try_lock(&self, _name: &str, _owner: &str, _ttl: Duration) -> Result<bool, String> {
+        Ok(true)
+    }
+}
+
+/// Returns the key a shared backend stores a value under: the file name of its path, e.g. "telegram.lock".
+///
+/// The directory of a state file differs between replicas, e.g. with a different "STATE_DIR",
+/// so keying by the whole path would keep a separate alarm state per replica.
+fn shared_key(key: &str) -> &str {
+    std::path::Path::new(key)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(key)
+}
+
+/// Keeps the state in an SQLite database, e.g. on a volume shared by the instances.
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Opens the database, creating its tables if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: A `&str` that specifies the path of the database.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<SqliteStore, String>` that holds the store, or an `Err` with a message if the database cannot be opened.
+    pub fn open(path: &str) -> Result<SqliteStore, String> {
+        let connection = Connection::open(path)
+            .map_err(|e| format!("Failed to open state database {}: {}", path, e))?;
+        connection
+            .busy_timeout(Duration::from_secs(5))
+            .and_then(|_| {
+                connection.execute_batch(
+                    "CREATE TABLE IF NOT EXISTS state (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+                     CREATE TABLE IF NOT EXISTS locks (name TEXT PRIMARY KEY, owner TEXT NOT NULL, expires INTEGER NOT NULL);",
+                )
+            })
+            .map_err(|e| format!("Failed to set up state database {}: {}", path, e))?;
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Locks the connection.
+    fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl StateStore for SqliteStore {
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+
+    fn read(&self, key: &str) -> Result<Option<String>, String> {
+        self.connection()
+            .query_row(
+                "SELECT value FROM state WHERE key = ?1",
+                [shared_key(key)],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), String> {
+        self.connection()
+            .execute(
+                "INSERT INTO state (key, value) VALUES (?1, ?2)
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                [shared_key(key), value],
+            )
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn remove(&self, key: &str) -> Result<bool, String> {
+        self.connection()
+            .execute("DELETE FROM state WHERE key = ?1", [shared_key(key)])
+            .map(|removed| removed > 0)
+            .map_err(|e| e.to_string())
+    }
+
+    fn append(&self, key: &str, line: &str) -> Result<(), String> {
+        self.connection()
+            .execute(
+                "INSERT INTO state (key, value) VALUES (?1, ?2 || char(10))
+                 ON CONFLICT (key) DO UPDATE SET value = value || excluded.value",
+                [shared_key(key), line],
+            )
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn try_lock(&self, name: &str, owner: &str, ttl: Duration) -> Result<bool, String> {
+        let now = unix_millis();
+        let expires = now.saturating_add(ttl.as_millis() as i64);
+        self.connection()
+            .execute(
+                "INSERT INTO locks (name, owner, expires) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (name) DO UPDATE SET owner = excluded.owner, expires = excluded.expires
+                 WHERE locks.owner = excluded.owner OR locks.expires <= ?4",
+                rusqlite::params![name, owner, expires, now],
+            )
+            .map(|changed| changed > 0)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Keeps the state in Redis, so several replicas share the alarm state.
+#[cfg(feature = "redis")]
+pub struct RedisStore {
+    client: redis::Client,
+    /// The connection reused by the commands, `None` until the first one or after it broke.
+    connection: Mutex<Option<redis::Connection>>,
+    prefix: String,
+}
+
+/// The size in bytes an appended value is kept under in Redis; the oldest lines are dropped beyond it.
+///
+/// The history is also pruned to 30 days on every heartbeat, but not while the heartbeat is disabled.
+#[cfg(feature = "redis")]
+const REDIS_MAX_APPENDED: usize = 64 * 1024 * 1024;
+
+#[cfg(feature = "redis")]
+impl RedisStore {
+    /// Connects to Redis.
+    ///
+    /// # Arguments
+    ///
+    /// * `url`: A `&str` that specifies the URL of the server, e.g. "redis://redis:6379/0".
+    /// * `prefix`: A `&str` that specifies the prefix of the keys, e.g. "dnschecker:".
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<RedisStore, String>` that holds the store, or an `Err` with a message if the URL is invalid.
+    pub fn open(url: &str, prefix: &str) -> Result<RedisStore, String> {
+        let client = redis::Client::open(url).map_err(|e| format!("Invalid REDIS_URL: {}", e))?;
+        Ok(RedisStore {
+            client,
+            connection: Mutex::new(None),
+            prefix: prefix.to_string(),
+        })
+    }
+
+    /// Returns the key of a value in Redis, see `shared_key`.
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, shared_key(key))
+    }
+
+    /// Runs a command.
+    fn query<T: redis::FromRedisValue>(&self, command: &redis::Cmd) -> Result<T, String> {
+        self.run(|connection| command.query(connection))
+    }
+
+    /// Runs commands on the connection, connecting first if there is none.
+    ///
+    /// A connection that broke is dropped, so the next command reconnects.
+    fn run<T>(
+        &self,
+        commands: impl FnOnce(&mut redis::Connection) -> redis::RedisResult<T>,
+    ) -> Result<T, String> {
+        let mut slot = self.connection.lock().unwrap_or_else(|e| e.into_inner());
+        let mut connection = match slot.take() {
+            Some(connection) => connection,
+            None => {
+                let timeout = Some(Duration::from_secs(5));
+                self.client
+                    .get_connection_with_timeout(Duration::from_secs(5))
+                    .and_then(|connection| {
+                        connection.set_read_timeout(timeout)?;
+                        connection.set_write_timeout(timeout)?;
+                        Ok(connection)
+                    })
+                    .map_err(|e| format!("Failed to connect to Redis: {}", e))?
+            }
+        };
+        let result = commands(&mut connection);
+        if !matches!(&result, Err(e) if e.is_io_error() || e.is_connection_dropped() || e.is_timeout())
+        {
+            *slot = Some(connection);
+        }
+        result.map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(feature = "redis")]
+impl StateStore for RedisStore {
+    fn name(&self) -> &str {
+        "redis"
+    }
+
+    fn read(&self, key: &str) -> Result<Option<String>, String> {
+        self.query(redis::cmd("GET").arg(self.key(key)))
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), String> {
+        self.query(redis::cmd("SET").arg(self.key(key)).arg(value))
+    }
+
+    fn remove(&self, key: &str) -> Result<bool, String> {
+        self.query::<i64>(redis::cmd("DEL").arg(self.key(key)))
+            .map(|removed| removed > 0)
+    }
+
+    fn append(&self, key: &str, line: &str) -> Result<(), String> {
+        // Drop the oldest lines once the value outgrows the limit, so it stays bounded
+        let script = redis::Script::new(
+            r"local length = redis.call('APPEND', KEYS[1], ARGV[1])
+              local max = tonumber(ARGV[2])
+              if length > max then
+                local value = redis.call('GET', KEYS[1])
+                local newline = string.find(value, '\n', length - max, true)
+                redis.call('SET', KEYS[1], string.sub(value, newline + 1))
+              end
+              return length",
+        );
+        self.run(|connection| {
+            script
+                .key(self.key(key))
+                .arg(format!("{}\n", line))
+                .arg(REDIS_MAX_APPENDED)
+                .invoke::<i64>(connection)
+        })
+        .map(|_| ())
+    }
+
+    fn try_lock(&self, name: &str, owner: &str, ttl: Duration) -> Result<bool, String> {
+        let script = redis::Script::new(
+            r"local current = redis.call('GET', KEYS[1])
+              if current == false or current == ARGV[1] then
+                redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+                return 1
+              end
+              return 0",
+        );
+        self.run(|connection| {
+            script
+                .key(format!("{}lock:{}", self.prefix, name))
+                .arg(owner)
+                .arg(ttl.as_millis().max(1) as u64)
+                .invoke::<i64>(connection)
+        })
+        .map(|acquired| acquired == 1)
+    }
+}
+
+/// Creates the backend selected with the "STATE_BACKEND" environment variable.
+///
+/// "file" (the default) keeps the state in files, "sqlite" in the database at "STATE_DATABASE"
+/// (defaulting to "dnschecker.db" in the state directory), and "redis" in the Redis server at "REDIS_URL",
+/// with the keys prefixed with "REDIS_PREFIX" (defaulting to "dnschecker:"). Redis needs the "redis" feature.
+///
+/// # Returns
+///
+/// * A `Result<Box<dyn StateStore>, String>` that holds the backend.
+/// * If the backend is unknown or cannot be opened, it returns an `Err` with a message.
+fn create_store() -> Result<Box<dyn StateStore>, String> {
+    match env::var("STATE_BACKEND").as_deref() {
+        Err(_) | Ok("") | Ok("file") => Ok(Box::new(FileStore)),
+        Ok("sqlite") => Ok(Box::new(SqliteStore::open(&state_path(
+            "STATE_DATABASE",
+            "dnschecker.db",
+        ))?)),
+        #[cfg(feature = "redis")]
+        Ok("redis") => {
+            let url = env::var("REDIS_URL")
+                .map_err(|_| "STATE_BACKEND redis needs REDIS_URL".to_string())?;
+            let prefix = env::var("REDIS_PREFIX").unwrap_or("dnschecker:".to_string());
+            Ok(Box::new(RedisStore::open(&url, &prefix)?))
+        }
+        #[cfg(not(feature = "redis"))]
+        Ok("redis") => Err(
+            "STATE_BACKEND redis is not part of this build, build with the redis feature"
+                .to_string(),
+        ),
+        Ok(backend) => Err(format!(
+            "Invalid STATE_BACKEND {}, expected file, sqlite or redis",
+            backend
+        )),
+    }
+}
+
+/// Opens the backend of the state at startup, so an invalid backend stops the checker.
+///
+/// # Returns
+///
+/// * A `Result<(), String>` that is `Ok` if the backend was opened, or an `Err` with a message.
+pub fn open_store() -> Result<(), String> {
+    if STORE.get().is_none() {
+        let store = create_store()?;
+        log::info!("Keeping the state in {}", store.name());
+        let _ = STORE.set(store);
+    }
+    Ok(())
+}
+
+/// Retrieves the backend of the state.
+///
+/// The backend is opened on first use if `open_store` was not called, e.g. by a command;
+/// if it cannot be opened, the state is kept in files.
+pub fn store() -> &'static dyn StateStore {
+    STORE
+        .get_or_init(|| {
+            create_store().unwrap_or_else(|e| {
+                log::error!("{}, keeping the state in files", e);
+                Box::new(FileStore)
+            })
+        })
+        .as_ref()
+}
+
+/// Retrieves the identity of this instance, the owner of its locks.
+///
+/// # Returns
+///
+/// * A `&str` that holds the pod (see `kubernetes::pod_identity`), or the host name and the process ID.
+pub fn instance_id() -> &'static str {
+    INSTANCE.get_or_init(|| {
+        kubernetes::pod_identity().unwrap_or_else(|| {
+            format!(
+                "{}:{}",
+                env::var("HOSTNAME").unwrap_or("localhost".to_string()),
+                std::process::id()
+            )
+        })
+    })
+}
+
+/// Returns the current time in milliseconds since the epoch.
+fn unix_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqlite_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.db");
+        let store = SqliteStore::open(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(store.read("/tmp/telegram.lock").unwrap(), None);
+        store.write("/tmp/telegram.lock", "first").unwrap();
+        store.write("/tmp/telegram.lock", "second").unwrap();
+        assert_eq!(
+            store.read("/tmp/telegram.lock").unwrap().as_deref(),
+            Some("second")
+        );
+        // Assert that a value is shared by the replicas whatever their state directory
+        assert_eq!(
+            store
+                .read("/var/lib/dnschecker/telegram.lock")
+                .unwrap()
+                .as_deref(),
+            Some("second")
+        );
+        assert!(store.remove("/var/lib/dnschecker/telegram.lock").unwrap());
+        assert!(!store.remove("/tmp/telegram.lock").unwrap());
+
+        store.append("/tmp/history.jsonl", "{\"a\":1}").unwrap();
+        store.append("/tmp/history.jsonl", "{\"a\":2}").unwrap();
+        assert_eq!(
+            store.read("/tmp/history.jsonl").unwrap().as_deref(),
+            Some("{\"a\":1}\n{\"a\":2}\n")
+        );
+
+        // Assert that a lock is held by its owner until it expires
+        let ttl = Duration::from_secs(60);
+        assert!(store.try_lock("alert", "a", ttl).unwrap());
+        assert!(store.try_lock("alert", "a", ttl).unwrap());
+        assert!(!store.try_lock("alert", "b", ttl).unwrap());
+        assert!(store.try_lock("expired", "a", Duration::ZERO).unwrap());
+        assert!(store.try_lock("expired", "b", ttl).unwrap());
+    }
+}
@@ -1,127 +1,802 @@
-use crate::vars::get_var_from_env;
-use chrono::Duration as ChronoDuration;
+use crate::chaos::{self, Fault};
+use crate::enrich::{self, IpInfo};
+use crate::http;
+use crate::notify::{self, Message, Notifier};
+use crate::state::{read_state, remove_state, state_path, write_state};
+use crate::vars::{get_var_from_env, parse_duration};
 use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::env;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::time::Duration;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-/// Sends a message to a Telegram chat when there is an IP address mismatch between the router and the DNS server.
-///
-/// This function takes a Telegram bot token, a router IP address, and a DNS server IP address as arguments.
-/// It first retrieves the lockfile path and chat ID from environment variables.
-/// It then constructs the URL for the Telegram API and the text of the message.
-/// It checks if an alarm has already been sent by reading the timestamp from the lockfile.
-/// If an alarm has already been sent and the IP addresses are the same again, it resets the alarm.
-/// If an alarm has not been sent and the IP addresses are different, it sends an alarm.
+const TELEGRAM_API: &str = "https://api.telegram.org";
+
+/// Whether alerts currently go to the backup bot or chat, shared by every `TelegramNotifier`.
+static FALLBACK: Mutex<Fallback> = Mutex::new(Fallback::new());
+
+/// Delivers notifications to the configured Telegram chat.
+pub struct TelegramNotifier {
+    token: String,
+}
+
+impl TelegramNotifier {
+    /// Creates a Telegram notifier for the given bot token.
+    pub fn new(token: &str) -> TelegramNotifier {
+        TelegramNotifier {
+            token: token.to_string(),
+        }
+    }
+}
+
+impl Notifier for TelegramNotifier {
+    fn name(&self) -> &str {
+        "telegram"
+    }
+
+    /// Delivers a message with the primary bot and, if a backup is configured (see `Backup::from_env`),
+    /// falls back to the backup bot or chat after the primary failed for several consecutive deliveries.
+    ///
+    /// While falling back, the primary is tried again at most once per retry interval, so a dead bot does not
+    /// delay every alert by its timeout. A notice is sent when switching to the backup and when the primary works again.
+    fn send(&self, message: &Message) -> bool {
+        let backup = match Backup::from_env(&self.token) {
+            Some(backup) => backup,
+            None => return self.send_primary(message),
+        };
+        let now = Instant::now();
+        let try_primary = lock_fallback().should_try_primary(now, backup.retry);
+        if try_primary {
+            let sent = self.send_primary(message);
+            let switch = lock_fallback().record(sent, backup.after, now);
+            match switch {
+                Some(Switch::ToBackup) => {
+                    log::warn!(
+                        "Telegram failed {} deliveries in a row, falling back to the backup",
+                        backup.after
+                    );
+                    let text = format!(
+                        "The primary Telegram bot failed {} deliveries in a row. Alerts are sent here until it recovers.",
+                        backup.after
+                    );
+                    send_to(TELEGRAM_API, &backup.token, &backup.chat_id, &text);
+                }
+                Some(Switch::ToPrimary) => {
+                    log::info!("Telegram delivers again, leaving the backup");
+                    send_notice(
+                        &self.token,
+                        "Telegram alerts are delivered by the primary bot again.",
+                    );
+                }
+                None => {}
+            }
+            if sent || !lock_fallback().active {
+                return sent;
+            }
+        }
+        backup.deliver(TELEGRAM_API, message)
+    }
+}
+
+impl TelegramNotifier {
+    /// Delivers a message with the primary bot, adding the context and location and editing incidents as configured.
+    fn send_primary(&self, message: &Message) -> bool {
+        let info = ip_info(message);
+        let text = match &info {
+            Some(info) if flag_enabled("TELEGRAM_CONTEXT") => {
+                with_context(&message.text, &enrich::context_block(info))
+            }
+            _ => message.text.clone(),
+        };
+        let sent = if !editing_enabled() {
+            send_notice(&self.token, &text)
+        } else {
+            match message.event.as_str() {
+                "mismatch" => open_incident(TELEGRAM_API, &self.token, &text),
+                "recovery" => close_incident(TELEGRAM_API, &self.token, &text),
+                _ => send_notice(&self.token, &text),
+            }
+        };
+        if let Some(info) = info.filter(|_| sent && flag_enabled("TELEGRAM_LOCATION")) {
+            send_location(TELEGRAM_API, &self.token, &info);
+        }
+        if let Some(png) = message.image.as_ref().filter(|_| sent) {
+            send_photo(TELEGRAM_API, &self.token, &get_chat_id(), png);
+        }
+        sent
+    }
+}
+
+/// The bot and chat alerts fall back to while the primary bot fails.
+#[derive(Debug, Clone, PartialEq)]
+struct Backup {
+    /// The token of the backup bot.
+    token: String,
+    /// The chat the backup bot sends to.
+    chat_id: String,
+    /// The number of consecutive failed deliveries after which the backup is used.
+    after: u32,
+    /// The time between two attempts with the primary bot while the backup is used.
+    retry: Duration,
+}
+
+impl Backup {
+    /// Reads the backup from the environment.
+    ///
+    /// The backup is configured with a backup bot ("TELEGRAM_BACKUP_TOKEN"), a backup chat ("TELEGRAM_BACKUP_CHAT_ID"), or both;
+    /// the other one defaults to the primary. It is used after "TELEGRAM_FALLBACK_AFTER" consecutive failures (default 3),
+    /// and the primary is tried again once per "TELEGRAM_FALLBACK_RETRY" (default 15 minutes).
+    ///
+    /// # Arguments
+    ///
+    /// * `token`: A `&str` that specifies the token of the primary bot.
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<Backup>` that holds the backup, or `None` if neither a backup bot nor a backup chat is configured.
+    fn from_env(token: &str) -> Option<Backup> {
+        let var = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+        let (backup_token, backup_chat_id) =
+            (var("TELEGRAM_BACKUP_TOKEN"), var("TELEGRAM_BACKUP_CHAT_ID"));
+        if backup_token.is_none() && backup_chat_id.is_none() {
+            return None;
+        }
+        Some(Backup {
+            token: backup_token.unwrap_or(token.to_string()),
+            chat_id: backup_chat_id.unwrap_or_else(get_chat_id),
+            after: var("TELEGRAM_FALLBACK_AFTER")
+                .and_then(|value| value.parse::<u32>().ok())
+                .unwrap_or(3)
+                .max(1),
+            retry: var("TELEGRAM_FALLBACK_RETRY")
+                .and_then(|value| parse_duration(&value))
+                .unwrap_or(Duration::from_secs(15 * 60)),
+        })
+    }
+
+    /// Delivers a message with the backup bot to the backup chat, followed by its image if it has one.
+    ///
+    /// # Arguments
+    ///
+    /// * `api`: A `&str` that specifies the base URL of the Telegram API.
+    /// * `message`: A `&Message` that holds the notification.
+    ///
+    /// # Returns
+    ///
+    /// * A `bool` that indicates whether Telegram accepted the text of the message.
+    fn deliver(&self, api: &str, message: &Message) -> bool {
+        let sent = send_to(api, &self.token, &self.chat_id, &message.text);
+        if let Some(png) = message.image.as_ref().filter(|_| sent) {
+            send_photo(api, &self.token, &self.chat_id, png);
+        }
+        sent
+    }
+}
+
+/// A change of the bot that delivers the alerts, see `Fallback::record`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Switch {
+    /// The primary bot failed too often, alerts go to the backup.
+    ToBackup,
+    /// The primary bot delivered again, alerts go to it again.
+    ToPrimary,
+}
+
+/// The delivery failures of the primary bot and whether alerts go to the backup.
+#[derive(Debug, Clone, PartialEq)]
+struct Fallback {
+    /// The number of consecutive failed deliveries of the primary bot.
+    failures: u32,
+    /// Whether alerts go to the backup.
+    active: bool,
+    /// When the primary bot was last tried.
+    last_attempt: Option<Instant>,
+}
+
+impl Fallback {
+    /// Creates the state of a primary bot that has not failed.
+    const fn new() -> Fallback {
+        Fallback {
+            failures: 0,
+            active: false,
+            last_attempt: None,
+        }
+    }
+
+    /// Returns whether the primary bot should be tried: always, unless alerts go to the backup and it was tried within the retry interval.
+    fn should_try_primary(&self, now: Instant, retry: Duration) -> bool {
+        !self.active
+            || self
+                .last_attempt
+                .is_none_or(|last| now.saturating_duration_since(last) >= retry)
+    }
+
+    /// Records a delivery attempt of the primary bot.
+    ///
+    /// # Arguments
+    ///
+    /// * `sent`: A `bool` that indicates whether the primary bot delivered the message.
+    /// * `after`: A `u32` that specifies the number of consecutive failures after which the backup is used.
+    /// * `now`: An `Instant` that specifies when the primary bot was tried.
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<Switch>` that holds the change of the bot delivering the alerts, if any.
+    fn record(&mut self, sent: bool, after: u32, now: Instant) -> Option<Switch> {
+        self.last_attempt = Some(now);
+        if sent {
+            self.failures = 0;
+            let was_active = std::mem::replace(&mut self.active, false);
+            return was_active.then_some(Switch::ToPrimary);
+        }
+        self.failures = self.failures.saturating_add(1);
+        if !self.active && self.failures >= after {
+            self.active = true;
+            return Some(Switch::ToBackup);
+        }
+        None
+    }
+}
+
+/// Locks the fallback state, recovering it if a thread panicked while holding it.
+fn lock_fallback() -> std::sync::MutexGuard<'static, Fallback> {
+    FALLBACK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Sends a message to a chat with a bot, without following chat migrations.
 ///
 /// # Arguments
 ///
+/// * `api`: A `&str` that specifies the base URL of the Telegram API.
 /// * `token`: A `&str` that specifies the Telegram bot token.
-/// * `router_ip`: A `&str` that specifies the router IP address.
-/// * `dns_ip`: A `&str` that specifies the DNS server IP address.
+/// * `chat_id`: A `&str` that specifies the chat.
+/// * `text`: A `&str` that specifies the text of the message.
 ///
 /// # Returns
 ///
-/// * A `bool` that indicates whether the function succeeded.
-/// * If the function succeeds, it returns `true`.
-/// * If the function fails, it returns `false`.
-pub fn send_telegram(token: &str, router_ip: &str, dns_ip: &str) -> bool {
-    let lockfile = env::var("LOCKFILE").unwrap_or("/tmp/telegram.lock".to_string());
-    let ok_lockfile = env::var("LOCKFILE").unwrap_or("/tmp/telegram_ok.lock".to_string());
-    let chat_id = get_var_from_env("CHAT_ID").unwrap();
-    let url = format!("https://api.telegram.org/bot{}/sendMessage", &token);
-    let text = format!(
-        "IP address mismatch between router and DNS server!\nRouter IP: {}\nDNS IP: {}",
-        router_ip, dns_ip
-    );
+/// * A `bool` that indicates whether Telegram accepted the message.
+fn send_to(api: &str, token: &str, chat_id: &str, text: &str) -> bool {
+    let url = format!("{}/bot{}/sendMessage", api, token);
     let json = serde_json::json!({"chat_id": chat_id, "text": text, "disable_notification": false});
+    do_request(url, json)
+        .and_then(parse_response)
+        .is_ok_and(parse_json)
+}
 
-    let alarm_sent = read_timestamp_from_file(&lockfile, 6);
-    let ok_sent = read_timestamp_from_file(&ok_lockfile, 1);
-    if alarm_sent && router_ip == dns_ip {
-        log::debug!("IP addresses are the same again, resetting alarm");
-        create_timestamp(&ok_lockfile);
-        reset_alarm(&lockfile, &chat_id, url).is_ok()
-    } else if !alarm_sent && router_ip != dns_ip && !ok_sent {
-        log::info!("Sending alarm");
-        if let Ok(response) = do_request(url, json) {
-            if let Ok(response_text) = parse_response(response) {
-                create_timestamp(&lockfile);
-                parse_json(response_text)
-            } else {
-                false
-            }
-        } else {
+/// Returns whether a boolean environment variable is set to "true".
+fn flag_enabled(name: &str) -> bool {
+    env::var(name).is_ok_and(|value| value == "true")
+}
+
+/// Looks up the router IP address of a mismatch alert when extra context or a location pin is enabled.
+///
+/// # Arguments
+///
+/// * `message`: A `&Message` that holds the notification.
+///
+/// # Returns
+///
+/// * An `Option<IpInfo>` that holds the details of the new WAN IP address (see `enrich::lookup`),
+///   or `None` for other events, when both "TELEGRAM_CONTEXT" and "TELEGRAM_LOCATION" are disabled or the lookup fails.
+fn ip_info(message: &Message) -> Option<IpInfo> {
+    if message.event != "mismatch"
+        || !(flag_enabled("TELEGRAM_CONTEXT") || flag_enabled("TELEGRAM_LOCATION"))
+    {
+        return None;
+    }
+    let (_, router_ip) = message
+        .fields
+        .iter()
+        .find(|(name, value)| name == "router_ip" && !value.is_empty())?;
+    enrich::lookup(router_ip)
+}
+
+/// Appends a context block to the text of a message.
+///
+/// # Arguments
+///
+/// * `text`: A `&str` that specifies the text of the message.
+/// * `block`: A `&str` that holds the context block, see `enrich::context_block`.
+///
+/// # Returns
+///
+/// * A `String` that holds the text followed by the block, or the text alone if the block is empty.
+fn with_context(text: &str, block: &str) -> String {
+    if block.is_empty() {
+        text.to_string()
+    } else {
+        format!("{}\n\n{}", text, block)
+    }
+}
+
+/// Sends the location of an IP address as a map pin.
+///
+/// If the location of the address is not known, nothing is sent.
+///
+/// # Arguments
+///
+/// * `api`: A `&str` that specifies the base URL of the Telegram API.
+/// * `token`: A `&str` that specifies the Telegram bot token.
+/// * `info`: A `&IpInfo` that holds the details of the address.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether Telegram accepted the location.
+fn send_location(api: &str, token: &str, info: &IpInfo) -> bool {
+    let (Some(latitude), Some(longitude)) = (info.latitude, info.longitude) else {
+        log::debug!("Location of the WAN IP address is unknown, not sending a pin");
+        return false;
+    };
+    let json = serde_json::json!({
+        "chat_id": get_chat_id(),
+        "latitude": latitude,
+        "longitude": longitude,
+        "disable_notification": true,
+    });
+    call_method(api, token, "sendLocation", json).is_some()
+}
+
+/// Sends a PNG image to a chat, e.g. the chart of a digest, as a "multipart/form-data" upload.
+///
+/// # Arguments
+///
+/// * `api`: A `&str` that specifies the base URL of the Telegram API.
+/// * `token`: A `&str` that specifies the Telegram bot token.
+/// * `chat_id`: A `&str` that specifies the chat.
+/// * `png`: A `&[u8]` that holds the image.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether Telegram accepted the image.
+fn send_photo(api: &str, token: &str, chat_id: &str, png: &[u8]) -> bool {
+    let mut nonce = [0u8; 16];
+    if getrandom::getrandom(&mut nonce).is_err() {
+        log::warn!("Failed to draw a multipart boundary, not sending the image");
+        return false;
+    }
+    let boundary = format!("dnschecker-{}", hex::encode(nonce));
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"chat_id\"\r\n\r\n{chat_id}\r\n\
+         --{boundary}\r\nContent-Disposition: form-data; name=\"photo\"; filename=\"chart.png\"\r\n\
+         Content-Type: image/png\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(png);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+    let client = match http::client() {
+        Ok(client) => client,
+        Err(e) => {
+            log::warn!("{}", e);
+            return false;
+        }
+    };
+    let response = client
+        .post(format!("{}/bot{}/sendPhoto", api, token))
+        .header(
+            "Content-Type",
+            format!("multipart/form-data; boundary={}", boundary),
+        )
+        .body(body)
+        .timeout(Duration::from_secs(10))
+        .send();
+    match response.map(|response| response.text()) {
+        Ok(Ok(response_text)) => parse_json(response_text),
+        Ok(Err(e)) | Err(e) => {
+            log::warn!("Failed to send image: {:?}", e);
             false
         }
-    } else {
-        log::trace!("IP addresses are the same, not sending alarm");
-        true
     }
 }
 
-/// Sends a reset message to a Telegram chat when the IP addresses of the router and the DNS server are the same again.
+/// The alert message of an ongoing mismatch, edited with updates while it lasts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Incident {
+    /// The chat the alert was sent to.
+    chat_id: String,
+    /// The ID of the alert message in the chat.
+    message_id: i64,
+    /// The original text of the alert.
+    text: String,
+    /// When the alert was sent, in RFC 3339 format.
+    started: String,
+    /// The update appended to the text, empty if there is none yet.
+    status: String,
+}
+
+/// Returns whether ongoing incidents are edited in place ("TELEGRAM_EDIT" is "true").
+fn editing_enabled() -> bool {
+    flag_enabled("TELEGRAM_EDIT")
+}
+
+/// Retrieves the path of the incident file from the "TELEGRAM_INCIDENT_FILE" environment variable,
+/// defaulting to "telegram_incident" in the state directory.
+fn incident_file() -> String {
+    state_path("TELEGRAM_INCIDENT_FILE", "telegram_incident")
+}
+
+/// Sends the alert of a mismatch and remembers its message ID for later updates.
 ///
-/// This function takes the lockfile path, chat ID, and the URL for the Telegram API as arguments.
-/// It first constructs the JSON payload for the Telegram API request, which includes the chat ID, the text of the message, and a flag to disable notification.
-/// It then sends the request to the Telegram API using the `do_request` function.
-/// If the function fails, it logs a warning and returns an `Err` with a message.
+/// # Arguments
 ///
-/// It then parses the response from the Telegram API using the `parse_response` function.
-/// If the function fails, it logs a warning and returns an `Err` with a message.
+/// * `api`: A `&str` that specifies the base URL of the Telegram API.
+/// * `token`: A `&str` that specifies the Telegram bot token.
+/// * `text`: A `&str` that specifies the text of the alert.
 ///
-/// It then parses the JSON response from the Telegram API using the `parse_json` function.
-/// If the function fails, it logs a warning and returns an `Err` with a message.
+/// # Returns
 ///
-/// If the function succeeds, it logs an info message, resets the lockfile using the `reset_lockfile` function, and returns an `Ok` with a message.
+/// * A `bool` that indicates whether Telegram accepted the message.
+fn open_incident(api: &str, token: &str, text: &str) -> bool {
+    let chat_id_file = state_path("CHAT_ID_FILE", "telegram_chat_id");
+    let url = format!("{}/bot{}/sendMessage", api, token);
+    let json =
+        serde_json::json!({"chat_id": get_chat_id(), "text": text, "disable_notification": false});
+    let response_text = match deliver(&url, json, &chat_id_file) {
+        Ok(response_text) => response_text,
+        Err(_) => return false,
+    };
+    if !parse_json(response_text.clone()) {
+        return false;
+    }
+    match parse_message(&response_text) {
+        Some((chat_id, message_id)) => {
+            let incident = Incident {
+                chat_id,
+                message_id,
+                text: text.to_string(),
+                started: Local::now().to_rfc3339(),
+                status: String::new(),
+            };
+            let stored = serde_json::to_string(&incident).unwrap_or_default();
+            write_state(&incident_file(), &stored);
+        }
+        None => log::warn!("Telegram did not return the ID of the alert message"),
+    }
+    true
+}
+
+/// Appends how long the mismatch lasts to the alert message of the ongoing incident.
+///
+/// This function is called on every check while the alarm is raised (see `alarm::process`).
+/// The message is only edited when the status changes, i.e. once per hour.
+/// If editing is disabled, there is no ongoing incident, or alerts are muted or sent by another instance,
+/// it does nothing.
+pub fn update_incident() {
+    if !editing_enabled() || !notify::is_delivering() {
+        return;
+    }
+    let token = match env::var("TELEGRAM_TOKEN") {
+        Ok(token) => token,
+        Err(_) => return,
+    };
+    let path = incident_file();
+    let mut incident = match serde_json::from_str::<Incident>(&read_state(&path)) {
+        Ok(incident) => incident,
+        Err(_) => return,
+    };
+    let hours = DateTime::parse_from_rfc3339(&incident.started)
+        .map(|started| Local::now().signed_duration_since(started).num_hours())
+        .unwrap_or(0);
+    if hours < 1 {
+        return;
+    }
+    let status = format!("Still mismatched after {}h", hours);
+    if status == incident.status {
+        return;
+    }
+    if edit_message(TELEGRAM_API, &token, &incident, &status) {
+        incident.status = status;
+        let stored = serde_json::to_string(&incident).unwrap_or_default();
+        write_state(&path, &stored);
+    }
+}
+
+/// Marks the ongoing incident as recovered by editing its alert message.
+///
+/// If there is no ongoing incident or the message cannot be edited, e.g. because it was deleted,
+/// the recovery is sent as a new message instead.
+///
+/// # Arguments
+///
+/// * `api`: A `&str` that specifies the base URL of the Telegram API.
+/// * `token`: A `&str` that specifies the Telegram bot token.
+/// * `text`: A `&str` that specifies the text of the recovery message.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether Telegram accepted the edit or the message.
+fn close_incident(api: &str, token: &str, text: &str) -> bool {
+    let path = incident_file();
+    let incident = match serde_json::from_str::<Incident>(&read_state(&path)) {
+        Ok(incident) => incident,
+        Err(_) => return send_notice(token, text),
+    };
+    let elapsed = DateTime::parse_from_rfc3339(&incident.started)
+        .map(|started| Local::now().signed_duration_since(started).num_minutes())
+        .unwrap_or(0);
+    let status = format!(
+        "Recovered after {}h {}m\n{}",
+        elapsed / 60,
+        elapsed % 60,
+        text
+    );
+    let edited = edit_message(api, token, &incident, &status);
+    if let Err(e) = remove_state(&path) {
+        log::warn!("Failed to remove incident file: {}", e);
+    }
+    edited || send_notice(token, text)
+}
+
+/// Replaces the text of an alert message with its original text and a status.
 ///
 /// # Arguments
 ///
-/// * `lockfile`: A `&str` that specifies the lockfile path.
+/// * `api`: A `&str` that specifies the base URL of the Telegram API.
+/// * `token`: A `&str` that specifies the Telegram bot token.
+/// * `incident`: A `&Incident` that holds the alert message.
+/// * `status`: A `&str` that specifies the status appended to the original text.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether Telegram accepted the edit.
+fn edit_message(api: &str, token: &str, incident: &Incident, status: &str) -> bool {
+    let json = serde_json::json!({
+        "chat_id": incident.chat_id,
+        "message_id": incident.message_id,
+        "text": format!("{}\n\n{}", incident.text, status),
+    });
+    call_method(api, token, "editMessageText", json).is_some()
+}
+
+/// Extracts the chat ID and message ID from the response of a sent message.
+///
+/// # Arguments
+///
+/// * `response_text`: A `&str` that holds the JSON response of the "sendMessage" method.
+///
+/// # Returns
+///
+/// * An `Option<(String, i64)>` that holds the chat ID and message ID, or `None` if they are missing.
+pub fn parse_message(response_text: &str) -> Option<(String, i64)> {
+    let json: Value = serde_json::from_str(response_text).ok()?;
+    let result = json.get("result")?;
+    let chat_id = match result.get("chat")?.get("id")? {
+        Value::String(chat_id) => chat_id.clone(),
+        chat_id => chat_id.to_string(),
+    };
+    Some((chat_id, result.get("message_id")?.as_i64()?))
+}
+
+/// Sends an informational message to the Telegram chat.
+///
+/// This function takes a Telegram bot token and the text of the message as arguments.
+/// It does not consult or update the alarm lockfiles, so every call sends a message.
+///
+/// # Arguments
+///
+/// * `token`: A `&str` that specifies the Telegram bot token.
+/// * `text`: A `&str` that specifies the text of the message.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether Telegram accepted the message.
+pub fn send_notice(token: &str, text: &str) -> bool {
+    let chat_id_file = state_path("CHAT_ID_FILE", "telegram_chat_id");
+    let url = format!("{}/bot{}/sendMessage", TELEGRAM_API, &token);
+    let json =
+        serde_json::json!({"chat_id": get_chat_id(), "text": text, "disable_notification": false});
+    match deliver(&url, json, &chat_id_file) {
+        Ok(response_text) => parse_json(response_text),
+        Err(_) => false,
+    }
+}
+
+/// Retrieves the chat ID that messages should be sent to.
+///
+/// This function first checks the chat ID file (the "CHAT_ID_FILE" environment variable, defaulting to "telegram_chat_id" in the state directory).
+/// The file is written when Telegram reports that the chat was migrated to a supergroup.
+/// If the file does not exist or is empty, it returns the value of the "CHAT_ID" environment variable.
+///
+/// # Returns
+///
+/// * A `String` that holds the chat ID, or an empty `String` if none is configured.
+pub fn get_chat_id() -> String {
+    let chat_id_file = state_path("CHAT_ID_FILE", "telegram_chat_id");
+    match read_state(&chat_id_file) {
+        chat_id if !chat_id.is_empty() => {
+            log::debug!("Using migrated chat ID from {}", chat_id_file);
+            chat_id
+        }
+        _ => get_var_from_env("CHAT_ID").unwrap_or_default(),
+    }
+}
+
+/// Validates the Telegram bot token and chat ID.
+///
+/// This function takes a Telegram bot token and a chat ID as arguments.
+/// It calls the `getMe` method to verify that the token is accepted by Telegram and logs the bot username.
+/// It then calls the `getChat` method to verify that the bot can reach the configured chat and logs the chat title.
+/// If either call fails, it logs an error describing the likely misconfiguration and returns `false`.
+///
+/// # Arguments
+///
+/// * `token`: A `&str` that specifies the Telegram bot token.
+/// * `chat_id`: A `&str` that specifies the chat ID.
+///
+/// # Returns
+///
+/// * A `bool` that indicates whether both the token and the chat ID are valid.
+pub fn validate_telegram(token: &str, chat_id: &str) -> bool {
+    validate_bot(TELEGRAM_API, token, chat_id)
+}
+
+/// Validates the Telegram bot token and chat ID against the given API base URL.
+///
+/// See `validate_telegram` for details.
+///
+/// # Arguments
+///
+/// * `api`: A `&str` that specifies the base URL of the Telegram API.
+/// * `token`: A `&str` that specifies the Telegram bot token.
 /// * `chat_id`: A `&str` that specifies the chat ID.
-/// * `url`: A `String` that specifies the URL for the Telegram API.
 ///
 /// # Returns
 ///
-/// * A `Result<String, String>` that holds a message if the function succeeds.
-/// * If the function fails, it returns an `Err` with a message.
-fn reset_alarm(lockfile: &str, chat_id: &str, url: String) -> Result<String, String> {
-    let json = serde_json::json!({"chat_id": chat_id, "text": "IP addresses are the same again", "disable_notification": false}); // Define the json variable
-    let response = match do_request(url, json) {
-        Ok(value) => value,
-        Err(_) => return Err("failed to send reset alarm".to_string()),
+/// * A `bool` that indicates whether both the token and the chat ID are valid.
+fn validate_bot(api: &str, token: &str, chat_id: &str) -> bool {
+    let me = match call_method(api, token, "getMe", serde_json::json!({})) {
+        Some(me) => me,
+        None => {
+            log::error!("Telegram rejected the bot token, check TELEGRAM_TOKEN");
+            return false;
+        }
     };
-    let response_text = match parse_response(response) {
-        Ok(value) => value,
-        Err(_) => return Err("failed to parse response".to_string()),
+    let username = me.get("username").and_then(Value::as_str).unwrap_or("");
+    log::info!("Telegram bot authenticated as @{}", username);
+
+    let chat = match call_method(
+        api,
+        token,
+        "getChat",
+        serde_json::json!({"chat_id": chat_id}),
+    ) {
+        Some(chat) => chat,
+        None => {
+            log::error!(
+                "Telegram bot cannot access chat {}, check CHAT_ID and that the bot is a member of the chat",
+                chat_id
+            );
+            return false;
+        }
     };
-    let result = parse_json(response_text);
-    if result {
-        log::info!("Alarm has been reset");
-        match reset_lockfile(lockfile) {
-            Ok(value) => value,
-            Err(value) => return Err(value.into()),
-        };
-        Ok("Alarm has been reset".to_string())
+    let title = ["title", "username", "first_name"]
+        .iter()
+        .find_map(|field| chat.get(field).and_then(Value::as_str))
+        .unwrap_or("");
+    log::info!("Telegram chat {} is \"{}\"", chat_id, title);
+    true
+}
+
+/// Calls a Telegram Bot API method and returns its result.
+///
+/// This function takes the base URL of the Telegram API, a bot token, a method name and a JSON payload as arguments.
+/// It sends the payload to the method using the `do_request` function and parses the response.
+/// If the response has `"ok": true`, it returns the value of the "result" field.
+/// Otherwise it logs a warning with the description returned by Telegram and returns `None`.
+///
+/// # Arguments
+///
+/// * `api`: A `&str` that specifies the base URL of the Telegram API.
+/// * `token`: A `&str` that specifies the Telegram bot token.
+/// * `method`: A `&str` that specifies the Bot API method to call.
+/// * `json`: A `serde_json::Value` that specifies the JSON payload.
+///
+/// # Returns
+///
+/// * An `Option<Value>` that holds the "result" field if the call succeeds.
+/// * If the call fails, it returns `None`.
+fn call_method(api: &str, token: &str, method: &str, json: Value) -> Option<Value> {
+    let url = format!("{}/bot{}/{}", api, token, method);
+    let response = do_request(url, json).ok()?;
+    let response_text = parse_response(response).ok()?;
+    let json: Value = match serde_json::from_str(&response_text) {
+        Ok(json) => json,
+        Err(e) => {
+            log::warn!("Failed to parse JSON: {:?}", e);
+            return None;
+        }
+    };
+    if json.get("ok").and_then(Value::as_bool) == Some(true) {
+        json.get("result").cloned()
     } else {
-        log::warn!("Failed to reset alarm");
-        Err("Failed to reset alarm".to_string())
+        let description = json
+            .get("description")
+            .and_then(Value::as_str)
+            .unwrap_or("no description");
+        log::warn!("Telegram {} failed: {}", method, description);
+        None
     }
 }
 
+/// Sends a message to Telegram, following a chat migration if Telegram reports one.
+///
+/// This function takes the URL for the Telegram API, the JSON payload and the chat ID file path as arguments.
+/// It sends the request using the `do_request` function and extracts the response text using the `parse_response` function.
+///
+/// If the response contains a "migrate_to_chat_id" parameter (the group was upgraded to a supergroup),
+/// it persists the new chat ID to the chat ID file, retries the request with the new chat ID
+/// and sends a notice about the migration to the new chat.
+///
+/// # Arguments
+///
+/// * `url`: A `&str` that specifies the URL for the Telegram API.
+/// * `json`: A `serde_json::Value` that specifies the JSON payload.
+/// * `chat_id_file`: A `&str` that specifies where a migrated chat ID is persisted.
+///
+/// # Returns
+///
+/// * A `Result<String, bool>` that holds the text of the final HTTP response if the request was sent.
+/// * If the request fails, it returns an `Err` with `false`.
+fn deliver(url: &str, json: Value, chat_id_file: &str) -> Result<String, bool> {
+    let response = do_request(url.to_string(), json.clone())?;
+    let response_text = parse_response(response)?;
+    let new_chat_id = match migrated_chat_id(&response_text) {
+        Some(new_chat_id) => new_chat_id,
+        None => return Ok(response_text),
+    };
+
+    log::warn!("Telegram chat was migrated to {}, retrying", new_chat_id);
+    if write_state(chat_id_file, &new_chat_id) {
+        log::info!("Migrated chat ID written to {}", chat_id_file);
+    }
+    let mut json = json;
+    json["chat_id"] = Value::from(new_chat_id.clone());
+    let response = do_request(url.to_string(), json)?;
+    let response_text = parse_response(response)?;
+
+    let text = format!(
+        "This chat was upgraded to a supergroup, alerts are now sent to chat ID {}. Please update CHAT_ID.",
+        new_chat_id
+    );
+    let notice =
+        serde_json::json!({"chat_id": new_chat_id, "text": text, "disable_notification": false});
+    if do_request(url.to_string(), notice).is_err() {
+        log::warn!("Failed to send chat migration notice");
+    }
+    Ok(response_text)
+}
+
+/// Extracts the new chat ID from a Telegram error response about a chat migration.
+///
+/// This function takes a JSON string as an argument.
+/// It returns the value of the "parameters.migrate_to_chat_id" field if it is present.
+///
+/// # Arguments
+///
+/// * `response_text`: A `&str` that specifies the JSON string to parse.
+///
+/// # Returns
+///
+/// * An `Option<String>` that holds the new chat ID if the chat was migrated.
+pub fn migrated_chat_id(response_text: &str) -> Option<String> {
+    let json: Value = serde_json::from_str(response_text).ok()?;
+    json.get("parameters")?
+        .get("migrate_to_chat_id")
+        .map(|chat_id| match chat_id {
+            Value::String(chat_id) => chat_id.clone(),
+            chat_id => chat_id.to_string(),
+        })
+}
+
 /// Parses a JSON string and extracts the value of the "ok" field.
 ///
 /// This function takes a JSON string as an argument.
 /// It attempts to parse the JSON string into a `serde_json::Value` using the `serde_json::from_str` function.
 /// If the function fails, it logs a warning and returns `false`.
 ///
-/// It then attempts to get the value of the "ok" field from the `serde_json::Value` using the `Value::get` method.
-/// If the function fails, it logs a warning and returns `false`.
-///
-/// It then attempts to convert the value of the "ok" field to a `bool` using the `Value::as_bool` method.
-/// If the function fails, it logs a warning and returns `false`.
+/// It then gets the value of the "ok" field from the `serde_json::Value` using the `Value::get` method.
+/// If the field is missing or not a `bool`, it logs a warning and returns `false`.
 ///
 /// If all steps succeed, it returns the value of the "ok" field as a `bool`.
 ///
@@ -133,7 +808,7 @@ fn reset_alarm(lockfile: &str, chat_id: &str, url: String) -> Result<String, Str
 ///
 /// * A `bool` that holds the value of the "ok" field if the function succeeds.
 /// * If any step fails, it returns `false`.
-fn parse_json(response_text: String) -> bool {
+pub fn parse_json(response_text: String) -> bool {
     let json: Value = match serde_json::from_str(&response_text) {
         Ok(json) => json,
         Err(e) => {
@@ -142,15 +817,13 @@ fn parse_json(response_text: String) -> bool {
         }
     };
     let ok = json.get("ok");
-    let ok = match ok {
-        Some(ok) => ok.as_bool().unwrap(),
+    match ok.and_then(Value::as_bool) {
+        Some(ok) => ok,
         None => {
             log::warn!("Failed to get \"ok\" from JSON");
-            return false;
+            false
         }
-    };
-
-    ok
+    }
 }
 
 /// Extracts the text from an HTTP response.
@@ -184,7 +857,7 @@ fn parse_response(response: reqwest::blocking::Response) -> Result<String, bool>
 /// Makes an HTTP POST request with a JSON payload.
 ///
 /// This function takes a URL and a JSON value as arguments.
-/// It first creates a new `reqwest::blocking::Client`.
+/// It first creates a `reqwest::blocking::Client` using the `http::client` function.
 /// It then sets the timeout duration for the request to 10 seconds.
 /// It then attempts to make the HTTP POST request using the `reqwest::blocking::Client::post` method, the `RequestBuilder::json` method to set the JSON payload, the `RequestBuilder::timeout` method to set the timeout duration, and the `RequestBuilder::send` method to send the request.
 /// If the method fails, it logs a warning and returns an `Err` with `false`.
@@ -201,7 +874,17 @@ fn parse_response(response: reqwest::blocking::Response) -> Result<String, bool>
 /// * A `Result<reqwest::blocking::Response, bool>` that holds the HTTP response if the function succeeds.
 /// * If the function fails, it returns an `Err` with `false`.
 fn do_request(url: String, json: Value) -> Result<reqwest::blocking::Response, bool> {
-    let client = reqwest::blocking::Client::new();
+    if chaos::inject(Fault::TelegramRateLimit) {
+        log::warn!("Telegram returned 429 Too Many Requests");
+        return Err(false);
+    }
+    let client = match http::client() {
+        Ok(client) => client,
+        Err(e) => {
+            log::warn!("{}", e);
+            return Err(false);
+        }
+    };
     let timeout_duration = Duration::from_secs(10); // Set the timeout duration to 10 seconds
     let response = client
         .post(&url)
@@ -218,117 +901,12 @@ fn do_request(url: String, json: Value) -> Result<reqwest::blocking::Response, b
     Ok(response)
 }
 
-/// Creates a timestamp and writes it to a lockfile.
-///
-/// This function takes a lockfile path as an argument.
-/// It first creates a new file at the lockfile path using the `File::create` method.
-/// It then sets the length of the file to 0 using the `File::set_len` method to ensure that the file is empty.
-/// It then creates a timestamp using the `DateTime::to_rfc2822` method and the current local time.
-/// It then writes the timestamp to the file using the `Write::write_all` method.
-///
-/// If any step fails, it logs a warning.
-/// If all steps succeed, it logs an info message.
-///
-/// # Arguments
-///
-/// * `lockfile`: A `&str` that specifies the lockfile path.
-fn create_timestamp(lockfile: &str) {
-    let mut file = File::create(lockfile).unwrap();
-    match file.set_len(0) {
-        Ok(_) => log::info!("Lockfile created"),
-        Err(e) => log::warn!("Failed to create lockfile: {:?}", e),
-    }
-    let timestamp = DateTime::to_rfc2822(&Local::now());
-    let written = file.write_all(timestamp.as_bytes());
-    match written {
-        Ok(_) => log::info!("Timestamp written to file"),
-        Err(e) => log::warn!("Failed to write timestamp to file: {:?}", e),
-    }
-}
-
-/// Removes a lockfile.
-///
-/// This function takes a lockfile path as an argument.
-/// It attempts to remove the file at the lockfile path using the `std::fs::remove_file` function.
-/// If the function fails, it logs a warning and returns an `Err` with a message.
-///
-/// If the function succeeds, it logs an info message and returns an `Ok` with a message.
-///
-/// # Arguments
-///
-/// * `lockfile`: A `&str` that specifies the lockfile path.
-///
-/// # Returns
-///
-/// * A `Result<String, String>` that holds a message if the function succeeds.
-/// * If the function fails, it returns an `Err` with a message.
-fn reset_lockfile(lockfile: &str) -> Result<String, String> {
-    match std::fs::remove_file(lockfile) {
-        Ok(_) => Ok("Lockfile reset".to_string()),
-        Err(e) => Err(format!("Failed to reset lockfile: {:?}", e)),
-    }
-}
-
-/// Reads a timestamp from a lockfile and checks if it's less than 24 hours old.
-///
-/// This function takes a lockfile path as an argument.
-/// It first attempts to open the file at the lockfile path using the `File::open` method.
-/// If the method fails, it logs a warning and returns `false`.
-///
-/// It then reads the contents of the file into a `String` using the `Read::read_to_string` method.
-/// If the method fails, it logs a warning and returns `false`.
-///
-/// It then attempts to parse the contents of the file into a `DateTime` using the `DateTime::parse_from_rfc2822` method.
-/// If the method fails, it logs a warning and returns `false`.
-///
-/// It then gets the current local time and checks if the duration since the timestamp is less than 24 hours.
-/// If it is, it logs an info message and returns `true`.
-/// If it's not, it logs an info message and returns `false`.
-///
-/// # Arguments
-///
-/// * `lockfile`: A `&str` that specifies the lockfile path.
-///
-/// # Returns
-///
-/// * A `bool` that indicates whether the timestamp is less than 24 hours old.
-pub fn read_timestamp_from_file(lockfile: &str, hours: i64) -> bool {
-    if let Ok(mut file) = File::open(lockfile) {
-        let mut contents = String::new();
-        let readtimestamp = file.read_to_string(&mut contents);
-        match readtimestamp {
-            Ok(_) => log::info!("Timestamp read from file"),
-            Err(e) => log::warn!("Failed to read timestamp from file: {:?}", e),
-        }
-        log::info!("Timestamp: {:?}", contents);
-
-        if let Ok(timestamp) = DateTime::parse_from_rfc2822(&contents) {
-            let current = Local::now();
-            if current.signed_duration_since(timestamp) < ChronoDuration::try_hours(hours).unwrap()
-            {
-                log::info!("Less than 24 hours since last alarm, not sending alarm!");
-                true
-            } else {
-                log::info!("More than 24 hours since last alarm, sending alarm!");
-                false
-            }
-        } else {
-            log::info!("Failed to parse timestamp, creating new timestamp file");
-            false
-        }
-    } else {
-        log::debug!("No lockfile found, alarm not previously sent");
-        false
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::offset::TimeZone;
     use httpmock::MockServer;
-    use std::fs::File;
-    use std::io::Write;
+    use proptest::prelude::*;
+    use serde_json::json;
     #[test]
     fn test_parse_response() {
         let server = MockServer::start();
@@ -374,91 +952,300 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_json() {
-        // Call the function with a JSON string that has "ok": true
-        let result = parse_json(String::from("{\"ok\": true}"));
+    fn test_validate_bot() {
+        let server = MockServer::start();
 
-        // Assert that the function returns true
-        assert_eq!(result, true);
+        // Create mocks for getMe and getChat
+        let get_me = server.mock(|when, then| {
+            when.method("POST").path("/bottoken/getMe");
+            then.status(200)
+                .body("{\"ok\": true, \"result\": {\"username\": \"dnsbot\"}}");
+        });
+        let get_chat = server.mock(|when, then| {
+            when.method("POST")
+                .path("/bottoken/getChat")
+                .json_body(serde_json::json!({"chat_id": "111"}));
+            then.status(200)
+                .body("{\"ok\": true, \"result\": {\"title\": \"Home\"}}");
+        });
+        server.mock(|when, then| {
+            when.method("POST")
+                .path("/bottoken/getChat")
+                .json_body(serde_json::json!({"chat_id": "222"}));
+            then.status(400)
+                .body("{\"ok\": false, \"description\": \"Bad Request: chat not found\"}");
+        });
 
-        // Call the function with a JSON string that has "ok": false
-        let result = parse_json(String::from("{\"ok\": false}"));
+        // Assert that a reachable chat validates and an unknown chat does not
+        assert!(validate_bot(&server.base_url(), "token", "111"));
+        assert!(!validate_bot(&server.base_url(), "token", "222"));
+        get_me.assert_hits(2);
+        get_chat.assert();
+    }
 
-        // Assert that the function returns false
-        assert_eq!(result, false);
+    #[test]
+    fn test_deliver_follows_migration() {
+        let server = MockServer::start();
 
-        // Call the function with a JSON string that does not have "ok"
-        let result = parse_json(String::from("{\"foo\": \"bar\"}"));
+        // The old group chat reports a migration, the supergroup accepts the message
+        let old_chat = server.mock(|when, then| {
+            when.method("POST")
+                .path("/sendMessage")
+                .json_body_partial("{\"chat_id\": \"111\"}");
+            then.status(400)
+                .body("{\"ok\": false, \"parameters\": {\"migrate_to_chat_id\": -100222}}");
+        });
+        let new_chat = server.mock(|when, then| {
+            when.method("POST")
+                .path("/sendMessage")
+                .json_body_partial("{\"chat_id\": \"-100222\"}");
+            then.status(200).body("{\"ok\": true}");
+        });
 
-        // Assert that the function returns false
-        assert_eq!(result, false);
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let chat_id_file = temp_file.path().to_str().unwrap().to_string();
+        let json = serde_json::json!({"chat_id": "111", "text": "text"});
+        let result = deliver(&server.url("/sendMessage"), json, &chat_id_file);
 
-        // Call the function with a string that is not valid JSON
-        let result = parse_json(String::from("not valid JSON"));
+        // Assert that the message was delivered to the new chat and the chat ID persisted
+        assert!(parse_json(result.unwrap()));
+        assert_eq!(std::fs::read_to_string(&chat_id_file).unwrap(), "-100222");
+        old_chat.assert();
+        // The retried message and the migration notice
+        new_chat.assert_hits(2);
+    }
 
-        // Assert that the function returns false
-        assert_eq!(result, false);
+    #[test]
+    fn test_fallback() {
+        let mut fallback = Fallback::new();
+        let now = Instant::now();
+        let retry = Duration::from_secs(900);
+
+        // Assert that the backup is used after the configured number of consecutive failures
+        assert_eq!(fallback.record(false, 2, now), None);
+        assert!(fallback.should_try_primary(now, retry));
+        assert_eq!(fallback.record(false, 2, now), Some(Switch::ToBackup));
+        assert_eq!(fallback.record(false, 2, now), None);
+        assert!(fallback.active);
+
+        // Assert that the primary is only tried again after the retry interval
+        assert!(!fallback.should_try_primary(now + Duration::from_secs(60), retry));
+        assert!(fallback.should_try_primary(now + retry, retry));
+        assert_eq!(
+            fallback.record(true, 2, now + retry),
+            Some(Switch::ToPrimary)
+        );
+        assert_eq!(
+            fallback,
+            Fallback {
+                failures: 0,
+                active: false,
+                last_attempt: Some(now + retry),
+            }
+        );
+    }
+
+    #[test]
+    fn test_send_to() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method("POST")
+                .path("/botbackup/sendMessage")
+                .json_body_partial("{\"chat_id\": \"-100333\", \"text\": \"Alert\"}");
+            then.status(200).body("{\"ok\": true, \"result\": {}}");
+        });
+
+        assert!(send_to(&server.base_url(), "backup", "-100333", "Alert"));
+        mock.assert();
+        assert!(!send_to(&server.base_url(), "unknown", "-100333", "Alert"));
     }
 
     #[test]
-    fn test_create_timestamp() {
-        // Create a temporary file
+    fn test_incident() {
+        let server = MockServer::start();
+        let send = server.mock(|when, then| {
+            when.method("POST").path("/bottoken/sendMessage");
+            then.status(200).body(
+                "{\"ok\": true, \"result\": {\"message_id\": 42, \"chat\": {\"id\": -100111}}}",
+            );
+        });
+        let edit = server.mock(|when, then| {
+            when.method("POST")
+                .path("/bottoken/editMessageText")
+                .json_body_partial("{\"chat_id\": \"-100111\", \"message_id\": 42}");
+            then.status(200).body("{\"ok\": true, \"result\": {}}");
+        });
         let temp_file = tempfile::NamedTempFile::new().unwrap();
-        let file_path = temp_file.path().to_str().unwrap().to_string();
+        env::set_var("TELEGRAM_INCIDENT_FILE", temp_file.path());
 
-        // Call the function with the temporary file
-        create_timestamp(&file_path);
+        // Assert that the alert message is remembered
+        assert!(open_incident(
+            &server.base_url(),
+            "token",
+            "IP address mismatch!"
+        ));
+        let incident: Incident =
+            serde_json::from_str(&read_state(temp_file.path().to_str().unwrap())).unwrap();
+        assert_eq!(incident.message_id, 42);
+        assert_eq!(incident.chat_id, "-100111");
+        send.assert();
 
-        // Open the file and read its contents
-        let mut file = File::open(&file_path).unwrap();
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).unwrap();
+        // Assert that the recovery edits the alert message and ends the incident
+        assert!(close_incident(&server.base_url(), "token", "Recovered"));
+        edit.assert();
+        assert!(!temp_file.path().exists());
+        env::remove_var("TELEGRAM_INCIDENT_FILE");
+    }
+
+    #[test]
+    fn test_send_location() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method("POST")
+                .path("/bottoken/sendLocation")
+                .json_body_partial("{\"latitude\": 52.52, \"longitude\": 13.4}");
+            then.status(200).body("{\"ok\": true, \"result\": {}}");
+        });
+        let info = IpInfo {
+            latitude: Some(52.52),
+            longitude: Some(13.4),
+            ..IpInfo::default()
+        };
 
-        // Check if the contents can be parsed as a timestamp
-        let parsed = DateTime::parse_from_rfc2822(&contents);
-        assert!(parsed.is_ok());
+        assert!(send_location(&server.base_url(), "token", &info));
+        mock.assert();
 
-        // Check if the timestamp is recent (within the last minute)
-        let timestamp = parsed.unwrap();
-        let current = Local::now();
-        assert!(
-            current.signed_duration_since(timestamp) < chrono::Duration::try_minutes(1).unwrap()
+        // Assert that no pin is sent without a location
+        assert!(!send_location(
+            &server.base_url(),
+            "token",
+            &IpInfo::default()
+        ));
+        assert_eq!(with_context("Alert", ""), "Alert");
+        assert_eq!(
+            with_context("Alert", "ASN: AS64500"),
+            "Alert\n\nASN: AS64500"
         );
     }
 
     #[test]
-    fn test_read_timestamp_from_file() {
-        // Create a temporary file
-        let temp_file = tempfile::NamedTempFile::new().unwrap();
-        let file_path = temp_file.path().to_str().unwrap().to_string();
+    fn test_send_photo() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method("POST")
+                .path("/bottoken/sendPhoto")
+                .header_exists("Content-Type")
+                .body_contains("name=\"chat_id\"\r\n\r\n-1001234\r\n")
+                .body_contains(
+                    "filename=\"chart.png\"\r\nContent-Type: image/png\r\n\r\nPNG\r\n--",
+                );
+            then.status(200).body("{\"ok\": true, \"result\": {}}");
+        });
 
-        // Write a known timestamp to the file (older than 24 hours)
-        let mut file = File::create(&file_path).unwrap();
-        let timestamp_old = chrono::Local
-            .with_ymd_and_hms(2022, 1, 1, 0, 0, 0)
-            .unwrap()
-            .to_rfc2822();
-        writeln!(file, "{}", timestamp_old).unwrap();
+        assert!(send_photo(&server.base_url(), "token", "-1001234", b"PNG"));
+        mock.assert();
+    }
+
+    #[test]
+    fn test_backup_deliver() {
+        let server = MockServer::start();
+        let text = server.mock(|when, then| {
+            when.method("POST")
+                .path("/botbackup/sendMessage")
+                .json_body_partial("{\"chat_id\": \"-100999\", \"text\": \"Digest\"}");
+            then.status(200).body("{\"ok\": true, \"result\": {}}");
+        });
+        let photo = server.mock(|when, then| {
+            when.method("POST")
+                .path("/botbackup/sendPhoto")
+                .body_contains("name=\"chat_id\"\r\n\r\n-100999\r\n");
+            then.status(200).body("{\"ok\": true, \"result\": {}}");
+        });
+        let backup = Backup {
+            token: "backup".to_string(),
+            chat_id: "-100999".to_string(),
+            after: 3,
+            retry: Duration::from_secs(60),
+        };
 
-        // Call the function with the temporary file
-        let result_old = read_timestamp_from_file(&file_path, 24);
+        // Assert that the image is not dropped while falling back
+        let message = Message::new("digest", "Digest").with_image(b"PNG".to_vec());
+        assert!(backup.deliver(&server.base_url(), &message));
+        text.assert();
+        photo.assert();
+    }
 
-        // Assert that the function returns false (because the timestamp is more than 24 hours ago)
-        assert_eq!(result_old, false);
+    #[test]
+    fn test_migrated_chat_id() {
+        let result =
+            migrated_chat_id("{\"ok\": false, \"parameters\": {\"migrate_to_chat_id\": -1001234}}");
+        assert_eq!(result, Some("-1001234".to_string()));
 
-        let temp_file = tempfile::NamedTempFile::new().unwrap();
-        let file_path = temp_file.path().to_str().unwrap().to_string();
+        let result = migrated_chat_id("{\"ok\": true}");
+        assert_eq!(result, None);
+    }
 
-        // Write a known timestamp to the file (older than 24 hours)
-        let mut file = File::create(&file_path).unwrap();
+    #[test]
+    fn test_parse_json() {
+        // Call the function with a JSON string that has "ok": true
+        let result = parse_json(String::from("{\"ok\": true}"));
+
+        // Assert that the function returns true
+        assert!(result);
+
+        // Call the function with a JSON string that has "ok": false
+        let result = parse_json(String::from("{\"ok\": false}"));
+
+        // Assert that the function returns false
+        assert!(!result);
+
+        // Call the function with a JSON string that does not have "ok"
+        let result = parse_json(String::from("{\"foo\": \"bar\"}"));
+
+        // Assert that the function returns false
+        assert!(!result);
+
+        // Call the function with a string that is not valid JSON
+        let result = parse_json(String::from("not valid JSON"));
+
+        // Assert that the function returns false
+        assert!(!result);
 
-        let timestamp = DateTime::to_rfc2822(&Local::now());
-        file.write_all(timestamp.as_bytes()).unwrap();
+        // Assert that an "ok" field that is not a bool is not accepted
+        assert!(!parse_json(String::from("{\"ok\": \"true\"}")));
+    }
 
-        // Call the function with the temporary file again
-        let result_new = read_timestamp_from_file(&file_path, 24);
+    proptest! {
+        /// Asserts that the response parsers do not panic on input that is not JSON.
+        #[test]
+        fn test_parse_arbitrary_text(response in ".*") {
+            parse_json(response.clone());
+            parse_message(&response);
+            migrated_chat_id(&response);
+        }
 
-        // Assert that the function returns true (because the timestamp is within 24 hours)
-        assert_eq!(result_new, true);
+        /// Asserts that the response parsers do not panic when the fields have unexpected types.
+        #[test]
+        fn test_parse_unexpected_types(
+            value in prop_oneof![
+                Just(json!(null)),
+                any::<bool>().prop_map(|value| json!(value)),
+                any::<i64>().prop_map(|value| json!(value)),
+                any::<f64>().prop_map(|value| json!(value)),
+                ".{0,20}".prop_map(|value| json!(value)),
+                Just(json!([])),
+                Just(json!({})),
+            ]
+        ) {
+            let response = json!({
+                "ok": value,
+                "result": {"chat": {"id": value}, "message_id": value},
+                "parameters": {"migrate_to_chat_id": value},
+            })
+            .to_string();
+            prop_assert_eq!(parse_json(response.clone()), value == json!(true));
+            parse_message(&response);
+            migrated_chat_id(&response);
+        }
     }
 }
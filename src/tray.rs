@@ -0,0 +1,232 @@
+use crate::checker::{CheckOutcome, Checker, Event};
+use crate::notify;
+use crate::vars::display_hostname;
+use ksni::blocking::TrayMethods;
+use ksni::menu::{CheckmarkItem, StandardItem};
+use ksni::{Icon, MenuItem, Status, ToolTip};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+
+/// The width and height of the tray icon in pixels.
+const ICON_SIZE: i32 = 22;
+
+/// A request from the tray menu to the checker loop.
+enum Command {
+    /// Runs a verification right away.
+    Check,
+    /// Removes the tray icon and stops checking.
+    Quit,
+}
+
+/// The state shown in the tray.
+struct DnsTray {
+    hostname: String,
+    outcome: Option<CheckOutcome>,
+    muted: bool,
+    commands: Sender<Command>,
+}
+
+impl ksni::Tray for DnsTray {
+    fn id(&self) -> String {
+        "dnschecker".to_string()
+    }
+
+    fn title(&self) -> String {
+        "DNS Checker".to_string()
+    }
+
+    fn status(&self) -> Status {
+        match self.outcome.as_ref().and_then(|outcome| outcome.in_sync) {
+            Some(false) => Status::NeedsAttention,
+            _ => Status::Active,
+        }
+    }
+
+    fn icon_pixmap(&self) -> Vec<Icon> {
+        vec![icon(self.outcome.as_ref())]
+    }
+
+    fn tool_tip(&self) -> ToolTip {
+        ToolTip {
+            title: format!("DNS Checker: {}", display_hostname(&self.hostname)),
+            description: tooltip(self.outcome.as_ref()),
+            ..Default::default()
+        }
+    }
+
+    fn menu(&self) -> Vec<MenuItem<Self>> {
+        vec![
+            StandardItem {
+                label: "Check now".to_string(),
+                activate: Box::new(|tray: &mut DnsTray| {
+                    tray.commands.send(Command::Check).ok();
+                }),
+                ..Default::default()
+            }
+            .into(),
+            CheckmarkItem {
+                label: "Mute alerts".to_string(),
+                checked: self.muted,
+                activate: Box::new(|tray: &mut DnsTray| {
+                    tray.muted = !tray.muted;
+                    notify::set_muted(tray.muted);
+                }),
+                ..Default::default()
+            }
+            .into(),
+            MenuItem::Separator,
+            StandardItem {
+                label: "Quit".to_string(),
+                activate: Box::new(|tray: &mut DnsTray| {
+                    tray.commands.send(Command::Quit).ok();
+                }),
+                ..Default::default()
+            }
+            .into(),
+        ]
+    }
+}
+
+/// Shows the state of a checker in the system tray until "Quit" is chosen from its menu.
+///
+/// This function shows a tray icon through the StatusNotifierItem D-Bus interface, which KDE Plasma, Xfce and
+/// GNOME (with the AppIndicator extension) display. The icon is green while DNS matches the WAN IP address,
+/// red while it does not and grey until they were compared. Its tooltip shows the IP addresses of the last verification
+/// (see `tooltip`), and its menu forces a verification, mutes the alerts (see `notify::set_muted`) or quits.
+///
+/// The checker verifies right away and then once per interval, like `Checker::watch`, updating the tray with every event.
+///
+/// # Arguments
+///
+/// * `checker`: A `Checker` that verifies the hostname.
+///
+/// # Returns
+///
+/// * A `Result<(), String>` that is `Ok` when the tray was closed, or an `Err` with a message if the tray cannot be shown,
+///   e.g. because there is no D-Bus session or no tray host.
+pub fn run_tray(mut checker: Checker) -> Result<(), String> {
+    let (commands, requests) = mpsc::channel();
+    let tray = DnsTray {
+        hostname: checker.hostname().to_string(),
+        outcome: None,
+        muted: false,
+        commands,
+    };
+    let handle = tray
+        .spawn()
+        .map_err(|e| format!("Failed to show the tray icon: {}", e))?;
+    loop {
+        let event = checker.check_event();
+        match &event {
+            Event::Mismatch(_) => log::info!("DNS does not match the WAN IP address"),
+            Event::Recovered(_) => log::info!("DNS matches the WAN IP address again"),
+            Event::Checked(_) => {}
+        }
+        if handle
+            .update(|tray| tray.outcome = Some(event.outcome().clone()))
+            .is_none()
+        {
+            return Err("The tray icon was removed".to_string());
+        }
+        match requests.recv_timeout(checker.interval()) {
+            Ok(Command::Check) | Err(RecvTimeoutError::Timeout) => continue,
+            Ok(Command::Quit) | Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    handle.shutdown().wait();
+    Ok(())
+}
+
+/// Draws the tray icon: a filled circle, green if DNS is in sync, red if not, and grey if it was not compared.
+///
+/// # Arguments
+///
+/// * `outcome`: An `Option<&CheckOutcome>` that holds the result of the last verification, `None` before the first one.
+///
+/// # Returns
+///
+/// * An `Icon` that holds the ARGB32 pixels of the icon.
+fn icon(outcome: Option<&CheckOutcome>) -> Icon {
+    let (red, green, blue) = match outcome.and_then(|outcome| outcome.in_sync) {
+        Some(true) => (0x2e, 0xa0, 0x43),
+        Some(false) => (0xd7, 0x3a, 0x49),
+        None => (0x8c, 0x8c, 0x8c),
+    };
+    let center = (ICON_SIZE - 1) as f32 / 2.0;
+    let radius = ICON_SIZE as f32 / 2.0 - 1.0;
+    let mut data = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let distance = ((x as f32 - center).powi(2) + (y as f32 - center).powi(2)).sqrt();
+            let alpha = if distance <= radius { 0xff } else { 0x00 };
+            data.extend_from_slice(&[alpha, red, green, blue]);
+        }
+    }
+    Icon {
+        width: ICON_SIZE,
+        height: ICON_SIZE,
+        data,
+    }
+}
+
+/// Builds the description of the tooltip from the result of the last verification.
+///
+/// # Arguments
+///
+/// * `outcome`: An `Option<&CheckOutcome>` that holds the result of the last verification, `None` before the first one.
+///
+/// # Returns
+///
+/// * A `String` that holds the state, the IP addresses and the time of the verification, one per line.
+fn tooltip(outcome: Option<&CheckOutcome>) -> String {
+    let outcome = match outcome {
+        Some(outcome) => outcome,
+        None => return "Checking…".to_string(),
+    };
+    let or_dash = |value: &str| match value {
+        "" => "-".to_string(),
+        value => value.to_string(),
+    };
+    let state = match outcome.in_sync {
+        Some(true) => "In sync",
+        Some(false) => "Mismatch",
+        None => "Not compared",
+    };
+    let wan_ip = match outcome.interface.as_str() {
+        "" => or_dash(&outcome.wan_ip),
+        interface => format!("{} ({})", or_dash(&outcome.wan_ip), interface),
+    };
+    format!(
+        "{}\nDNS: {}\nWAN: {}\nChecked at {}",
+        state,
+        or_dash(&outcome.dns_ips.join(", ")),
+        wan_ip,
+        outcome.checked_at.format("%H:%M:%S")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::Context;
+
+    #[test]
+    fn test_tooltip() {
+        let outcome = CheckOutcome::from(Context {
+            dns_ips: vec!["2.2.2.2".to_string()],
+            interface: "igb0".to_string(),
+            wan_ip: "1.1.1.1".to_string(),
+            in_sync: Some(false),
+            ..Context::new("home.example.com")
+        });
+        let result = tooltip(Some(&outcome));
+        assert!(result.starts_with("Mismatch\nDNS: 2.2.2.2\nWAN: 1.1.1.1 (igb0)\nChecked at "));
+        assert_eq!(tooltip(None), "Checking…");
+
+        // Assert that the icon is red and transparent around the circle
+        let result = icon(Some(&outcome));
+        assert_eq!(result.data.len(), (ICON_SIZE * ICON_SIZE * 4) as usize);
+        assert_eq!(result.data[..4], [0x00, 0xd7, 0x3a, 0x49]);
+        let center = ((ICON_SIZE / 2 * ICON_SIZE + ICON_SIZE / 2) * 4) as usize;
+        assert_eq!(result.data[center..center + 4], [0xff, 0xd7, 0x3a, 0x49]);
+    }
+}
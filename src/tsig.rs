@@ -0,0 +1,533 @@
+use base64::prelude::*;
+use hmac::{Hmac, Mac};
+use sha2::{Sha256, Sha384, Sha512};
+use std::env;
+
+/// The record type of TSIG records (RFC 8945).
+const TSIG_TYPE: u16 = 250;
+
+/// The class of TSIG records, ANY.
+const TSIG_CLASS: u16 = 255;
+
+/// The time in seconds the clocks of the checker and the name server may differ.
+const FUDGE: u16 = 300;
+
+/// The number of messages of a zone transfer that may follow each other without a signature (RFC 8945, section 5.3.1).
+const MAX_UNSIGNED: usize = 99;
+
+/// The TSIG algorithms that can be configured in "AXFR_TSIG_ALGORITHM".
+pub const ALGORITHMS: [&str; 3] = ["hmac-sha256", "hmac-sha384", "hmac-sha512"];
+
+/// A shared secret key that authenticates DNS messages with TSIG (RFC 8945).
+#[derive(Clone, PartialEq)]
+pub struct TsigKey {
+    /// The name of the key, as configured on the name server, e.g. "dnschecker".
+    pub name: String,
+    /// The algorithm, one of `ALGORITHMS`.
+    pub algorithm: String,
+    /// The decoded secret.
+    secret: Vec<u8>,
+}
+
+impl std::fmt::Debug for TsigKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TsigKey")
+            .field("name", &self.name)
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TsigKey {
+    /// Creates a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: A `&str` that specifies the name of the key.
+    /// * `algorithm`: A `&str` that specifies the algorithm, one of `ALGORITHMS`.
+    /// * `secret`: A `&str` that holds the secret in base64, as in the key files of BIND and Knot.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<TsigKey, String>` that holds the key, or an `Err` with a message if the algorithm or secret is invalid.
+    pub fn new(name: &str, algorithm: &str, secret: &str) -> Result<TsigKey, String> {
+        let algorithm = algorithm.trim_end_matches('.').to_lowercase();
+        if !ALGORITHMS.contains(&algorithm.as_str()) {
+            return Err(format!(
+                "Unsupported TSIG algorithm {}, expected one of: {}",
+                algorithm,
+                ALGORITHMS.join(", ")
+            ));
+        }
+        let secret = BASE64_STANDARD
+            .decode(secret.trim())
+            .map_err(|e| format!("Invalid TSIG secret: {}", e))?;
+        Ok(TsigKey {
+            name: name.trim_end_matches('.').to_lowercase(),
+            algorithm,
+            secret,
+        })
+    }
+
+    /// Creates the key from the "AXFR_TSIG_KEY", "AXFR_TSIG_ALGORITHM" (defaulting to "hmac-sha256") and "AXFR_TSIG_SECRET" environment variables.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<Option<TsigKey>, String>` that holds the key, or `None` if "AXFR_TSIG_KEY" is not set.
+    /// * If the secret is missing or invalid, it returns an `Err` with a message.
+    pub fn from_env() -> Result<Option<TsigKey>, String> {
+        let name = match env::var("AXFR_TSIG_KEY") {
+            Ok(name) if !name.is_empty() => name,
+            _ => return Ok(None),
+        };
+        let algorithm = env::var("AXFR_TSIG_ALGORITHM").unwrap_or("hmac-sha256".to_string());
+        let secret = env::var("AXFR_TSIG_SECRET")
+            .map_err(|_| "AXFR_TSIG_KEY is set, but AXFR_TSIG_SECRET is not".to_string())?;
+        TsigKey::new(&name, &algorithm, &secret).map(Some)
+    }
+
+    /// Signs a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `message`: A `&[u8]` that holds the request in wire format, without a TSIG record.
+    /// * `time`: A `u64` that specifies the time of signing in seconds since the epoch.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<(Vec<u8>, Vec<u8>), String>` that holds the signed request and its MAC, which the response is verified with,
+    ///   or an `Err` with a message if the message is too short.
+    pub fn sign(&self, message: &[u8], time: u64) -> Result<(Vec<u8>, Vec<u8>), String> {
+        if message.len() < 12 {
+            return Err("DNS message too short".to_string());
+        }
+        let mut data = message.to_vec();
+        data.extend(self.variables(time, FUDGE, 0, &[]));
+        let mac = self.mac(&data);
+
+        let mut signed = message.to_vec();
+        let additionals = u16::from_be_bytes([signed[10], signed[11]]) + 1;
+        signed[10..12].copy_from_slice(&additionals.to_be_bytes());
+        let id = [message[0], message[1]];
+        signed.extend(self.record(time, &mac, id));
+        Ok((signed, mac))
+    }
+
+    /// Builds the TSIG variables that are covered by the MAC (RFC 8945, section 4.3.3).
+    fn variables(&self, time: u64, fudge: u16, error: u16, other: &[u8]) -> Vec<u8> {
+        let mut variables = encode_name(&self.name);
+        variables.extend(TSIG_CLASS.to_be_bytes());
+        variables.extend(0u32.to_be_bytes());
+        variables.extend(encode_name(&self.algorithm));
+        variables.extend(timers(time, fudge));
+        variables.extend(error.to_be_bytes());
+        variables.extend((other.len() as u16).to_be_bytes());
+        variables.extend(other);
+        variables
+    }
+
+    /// Builds a TSIG record without error and other data.
+    fn record(&self, time: u64, mac: &[u8], id: [u8; 2]) -> Vec<u8> {
+        let mut rdata = encode_name(&self.algorithm);
+        rdata.extend(timers(time, FUDGE));
+        rdata.extend((mac.len() as u16).to_be_bytes());
+        rdata.extend(mac);
+        rdata.extend(id);
+        rdata.extend(0u16.to_be_bytes());
+        rdata.extend(0u16.to_be_bytes());
+
+        let mut record = encode_name(&self.name);
+        record.extend(TSIG_TYPE.to_be_bytes());
+        record.extend(TSIG_CLASS.to_be_bytes());
+        record.extend(0u32.to_be_bytes());
+        record.extend((rdata.len() as u16).to_be_bytes());
+        record.extend(rdata);
+        record
+    }
+
+    /// Computes the MAC of the data with the secret.
+    fn mac(&self, data: &[u8]) -> Vec<u8> {
+        match self.algorithm.as_str() {
+            "hmac-sha384" => hmac::<Hmac<Sha384>>(&self.secret, data),
+            "hmac-sha512" => hmac::<Hmac<Sha512>>(&self.secret, data),
+            _ => hmac::<Hmac<Sha256>>(&self.secret, data),
+        }
+    }
+}
+
+/// Encodes the time signed (48 bits) and the fudge.
+fn timers(time: u64, fudge: u16) -> Vec<u8> {
+    let mut timers = time.to_be_bytes()[2..].to_vec();
+    timers.extend(fudge.to_be_bytes());
+    timers
+}
+
+/// Computes an HMAC.
+fn hmac<M: Mac + hmac::digest::KeyInit>(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Verifies the TSIG signatures of the responses to a signed request, e.g. the messages of a zone transfer.
+pub struct Verifier<'a> {
+    key: &'a TsigKey,
+    /// The MAC of the request, then of the last signed response.
+    previous_mac: Vec<u8>,
+    /// The responses received since the last signed response, without signature.
+    unsigned: Vec<u8>,
+    /// The number of responses in `unsigned`.
+    unsigned_count: usize,
+    /// Whether a signed response was received.
+    signed: bool,
+}
+
+impl Verifier<'_> {
+    /// Creates a verifier for the responses to a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: A `&TsigKey` that holds the key the request was signed with.
+    /// * `request_mac`: A `Vec<u8>` that holds the MAC of the request, see `TsigKey::sign`.
+    pub fn new(key: &TsigKey, request_mac: Vec<u8>) -> Verifier<'_> {
+        Verifier {
+            key,
+            previous_mac: request_mac,
+            unsigned: Vec::new(),
+            unsigned_count: 0,
+            signed: false,
+        }
+    }
+
+    /// Verifies a response and removes its TSIG record.
+    ///
+    /// The first response must be signed. Later responses may be unsigned, as long as a signed one follows within 99 messages,
+    /// whose MAC then covers them as well (RFC 8945, section 5.3.1).
+    ///
+    /// # Arguments
+    ///
+    /// * `message`: A `&[u8]` that holds the response in wire format.
+    /// * `now`: A `u64` that specifies the current time in seconds since the epoch.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<Vec<u8>, String>` that holds the response without its TSIG record.
+    /// * If the signature is missing, invalid, made with another key, or too old, it returns an `Err` with a message.
+    pub fn verify(&mut self, message: &[u8], now: u64) -> Result<Vec<u8>, String> {
+        let offset = match tsig_offset(message)? {
+            Some(offset) => offset,
+            None if !self.signed => return Err("The response is not signed".to_string()),
+            None if self.unsigned_count >= MAX_UNSIGNED => {
+                return Err(format!("More than {} unsigned responses", MAX_UNSIGNED))
+            }
+            None => {
+                self.unsigned.extend(message);
+                self.unsigned_count += 1;
+                return Ok(message.to_vec());
+            }
+        };
+
+        let (name, rdata_offset) = read_name(message, offset)?;
+        if name != self.key.name {
+            return Err(format!("The response is signed with another key: {}", name));
+        }
+        let rdata = message
+            .get(rdata_offset + 10..)
+            .ok_or("Truncated TSIG record")?;
+        let tsig = TsigRecord::parse(rdata)?;
+        if tsig.algorithm != self.key.algorithm {
+            return Err(format!(
+                "The response is signed with another algorithm: {}",
+                tsig.algorithm
+            ));
+        }
+        if tsig.error != 0 {
+            return Err(format!(
+                "The name server rejected the signature: {}",
+                tsig_error(tsig.error)
+            ));
+        }
+
+        let mut stripped = message[..offset].to_vec();
+        let additionals = u16::from_be_bytes([stripped[10], stripped[11]]).saturating_sub(1);
+        stripped[10..12].copy_from_slice(&additionals.to_be_bytes());
+        stripped[0..2].copy_from_slice(&tsig.original_id);
+
+        let mut data = (self.previous_mac.len() as u16).to_be_bytes().to_vec();
+        data.extend(&self.previous_mac);
+        data.extend(&self.unsigned);
+        data.extend(&stripped);
+        if self.signed {
+            data.extend(timers(tsig.time, tsig.fudge));
+        } else {
+            data.extend(
+                self.key
+                    .variables(tsig.time, tsig.fudge, tsig.error, &tsig.other),
+            );
+        }
+        if self.key.mac(&data) != tsig.mac {
+            return Err("Invalid TSIG signature in the response".to_string());
+        }
+        if now.abs_diff(tsig.time) > u64::from(tsig.fudge) {
+            return Err(format!(
+                "The signature of the response is {}s off",
+                now.abs_diff(tsig.time)
+            ));
+        }
+
+        self.previous_mac = tsig.mac;
+        self.unsigned.clear();
+        self.unsigned_count = 0;
+        self.signed = true;
+        Ok(stripped)
+    }
+
+    /// Checks that the last response was signed, so no unsigned messages were appended.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<(), String>` that is `Ok` if every response is covered by a signature, or an `Err` with a message.
+    pub fn finish(&self) -> Result<(), String> {
+        if self.signed && self.unsigned_count == 0 {
+            Ok(())
+        } else {
+            Err("The last response is not signed".to_string())
+        }
+    }
+}
+
+/// The fields of a TSIG record that are needed for verification.
+struct TsigRecord {
+    algorithm: String,
+    time: u64,
+    fudge: u16,
+    mac: Vec<u8>,
+    original_id: [u8; 2],
+    error: u16,
+    other: Vec<u8>,
+}
+
+impl TsigRecord {
+    /// Parses the data of a TSIG record.
+    fn parse(rdata: &[u8]) -> Result<TsigRecord, String> {
+        let (algorithm, mut offset) = read_name(rdata, 0)?;
+        let field = |offset: usize, length: usize| {
+            rdata
+                .get(offset..offset + length)
+                .ok_or("Truncated TSIG record".to_string())
+        };
+        let mut time = [0u8; 8];
+        time[2..].copy_from_slice(field(offset, 6)?);
+        let fudge = u16::from_be_bytes(field(offset + 6, 2)?.try_into().unwrap());
+        let mac_size = u16::from_be_bytes(field(offset + 8, 2)?.try_into().unwrap()) as usize;
+        let mac = field(offset + 10, mac_size)?.to_vec();
+        offset += 10 + mac_size;
+        let original_id = field(offset, 2)?.try_into().unwrap();
+        let error = u16::from_be_bytes(field(offset + 2, 2)?.try_into().unwrap());
+        let other_len = u16::from_be_bytes(field(offset + 4, 2)?.try_into().unwrap()) as usize;
+        let other = field(offset + 6, other_len)?.to_vec();
+        Ok(TsigRecord {
+            algorithm,
+            time: u64::from_be_bytes(time),
+            fudge,
+            mac,
+            original_id,
+            error,
+            other,
+        })
+    }
+}
+
+/// Describes a TSIG error code (RFC 8945, section 5.3.2).
+fn tsig_error(error: u16) -> String {
+    match error {
+        16 => "BADSIG".to_string(),
+        17 => "BADKEY".to_string(),
+        18 => "BADTIME".to_string(),
+        22 => "BADTRUNC".to_string(),
+        error => format!("error {}", error),
+    }
+}
+
+/// Encodes a name in the canonical wire format: lower case, uncompressed, with the root label.
+fn encode_name(name: &str) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for label in name
+        .trim_end_matches('.')
+        .split('.')
+        .filter(|l| !l.is_empty())
+    {
+        encoded.push(label.len() as u8);
+        encoded.extend(label.to_lowercase().as_bytes());
+    }
+    encoded.push(0);
+    encoded
+}
+
+/// Reads an uncompressed name.
+///
+/// # Returns
+///
+/// * A `Result<(String, usize), String>` that holds the name in lower case without the trailing dot and the offset after it.
+fn read_name(data: &[u8], mut offset: usize) -> Result<(String, usize), String> {
+    let mut labels = Vec::new();
+    loop {
+        let length = *data.get(offset).ok_or("Truncated name")? as usize;
+        if length == 0 {
+            return Ok((labels.join(".").to_lowercase(), offset + 1));
+        }
+        if length & 0xc0 != 0 {
+            return Err("Compressed name in TSIG record".to_string());
+        }
+        let label = data
+            .get(offset + 1..offset + 1 + length)
+            .ok_or("Truncated name")?;
+        labels.push(String::from_utf8_lossy(label).to_string());
+        offset += 1 + length;
+    }
+}
+
+/// Skips a possibly compressed name.
+fn skip_name(data: &[u8], mut offset: usize) -> Result<usize, String> {
+    loop {
+        let length = *data.get(offset).ok_or("Truncated DNS message")?;
+        match length {
+            0 => return Ok(offset + 1),
+            length if length & 0xc0 == 0xc0 => return Ok(offset + 2),
+            length => offset += 1 + length as usize,
+        }
+    }
+}
+
+/// Finds the TSIG record of a message, which must be the last additional record.
+///
+/// # Returns
+///
+/// * A `Result<Option<usize>, String>` that holds the offset of the TSIG record, or `None` if the message is not signed.
+/// * If the message is malformed, it returns an `Err` with a message.
+fn tsig_offset(message: &[u8]) -> Result<Option<usize>, String> {
+    let count = |index: usize| -> Result<usize, String> {
+        message
+            .get(index..index + 2)
+            .map(|count| u16::from_be_bytes([count[0], count[1]]) as usize)
+            .ok_or("DNS message too short".to_string())
+    };
+    let (questions, answers, authorities, additionals) =
+        (count(4)?, count(6)?, count(8)?, count(10)?);
+    if additionals == 0 {
+        return Ok(None);
+    }
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = skip_name(message, offset)? + 4;
+    }
+    let mut last = offset;
+    for _ in 0..answers + authorities + additionals {
+        last = offset;
+        let end = skip_name(message, offset)?;
+        let rdlength = count(end + 8)?;
+        offset = end + 10 + rdlength;
+    }
+    if offset > message.len() {
+        return Err("Truncated DNS message".to_string());
+    }
+    let end = skip_name(message, last)?;
+    Ok((count(end)? == TSIG_TYPE as usize).then_some(last))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Signs a response like a name server, see RFC 8945, section 4.3.
+    ///
+    /// `unsigned` holds the responses sent since the last signed one, which the MAC covers as well.
+    fn sign_response(
+        key: &TsigKey,
+        unsigned: &[u8],
+        message: &[u8],
+        previous_mac: &[u8],
+        first: bool,
+        time: u64,
+    ) -> (Vec<u8>, Vec<u8>) {
+        let mut data = (previous_mac.len() as u16).to_be_bytes().to_vec();
+        data.extend(previous_mac);
+        data.extend(unsigned);
+        data.extend(message);
+        if first {
+            data.extend(key.variables(time, FUDGE, 0, &[]));
+        } else {
+            data.extend(timers(time, FUDGE));
+        }
+        let mac = key.mac(&data);
+        let mut signed = message.to_vec();
+        signed[11] += 1;
+        signed.extend(key.record(time, &mac, [message[0], message[1]]));
+        (signed, mac)
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = TsigKey::new("Transfer.Key.", "hmac-sha256", "c2VjcmV0").unwrap();
+        // A query for the AXFR of "example.com"
+        let query = [
+            &[0x12, 0x34, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0][..],
+            &encode_name("example.com"),
+            &[0, 252, 0, 1],
+        ]
+        .concat();
+        let (signed, request_mac) = key.sign(&query, 1_700_000_000).unwrap();
+        assert_eq!(request_mac.len(), 32);
+        assert_eq!(tsig_offset(&signed), Ok(Some(query.len())));
+        assert_eq!(signed[11], 1);
+
+        // Assert that a transfer signed on the first and the last message verifies, covering the message in between
+        let response = [
+            &query[..2],
+            &[0x84, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+            &query[12..],
+        ]
+        .concat();
+        let (first, mac) = sign_response(&key, &[], &response, &request_mac, true, 1_700_000_001);
+        let (last, _) = sign_response(&key, &response, &response, &mac, false, 1_700_000_002);
+
+        let mut verifier = Verifier::new(&key, request_mac.clone());
+        assert_eq!(verifier.verify(&first, 1_700_000_001), Ok(response.clone()));
+        assert_eq!(
+            verifier.verify(&response, 1_700_000_002),
+            Ok(response.clone())
+        );
+        assert!(verifier.finish().is_err());
+        assert_eq!(verifier.verify(&last, 1_700_000_002), Ok(response.clone()));
+        assert!(verifier.finish().is_ok());
+
+        // Assert that unsigned, tampered, stale and foreign responses are rejected
+        assert!(Verifier::new(&key, request_mac.clone())
+            .verify(&response, 1_700_000_001)
+            .is_err());
+        let mut tampered = first.clone();
+        tampered[3] = 0x80;
+        assert!(Verifier::new(&key, request_mac.clone())
+            .verify(&tampered, 1_700_000_001)
+            .is_err());
+        assert!(Verifier::new(&key, request_mac.clone())
+            .verify(&first, 1_700_001_000)
+            .is_err());
+        let other = TsigKey::new("transfer.key", "hmac-sha256", "b3RoZXI=").unwrap();
+        assert!(Verifier::new(&other, request_mac)
+            .verify(&first, 1_700_000_001)
+            .is_err());
+    }
+
+    #[test]
+    fn test_new() {
+        assert!(TsigKey::new("key", "hmac-md5", "c2VjcmV0").is_err());
+        assert!(TsigKey::new("key", "hmac-sha256", "not base64!").is_err());
+        assert_eq!(
+            TsigKey::new("Key.", "HMAC-SHA512.", "c2VjcmV0")
+                .unwrap()
+                .algorithm,
+            "hmac-sha512"
+        );
+    }
+}
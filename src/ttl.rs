@@ -0,0 +1,175 @@
+use crate::dns;
+use crate::events;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::vars::display_hostname;
+use std::env;
+use std::net::SocketAddr;
+use trust_dns_resolver::proto::rr::{Record, RecordType};
+
+/// Watches the TTL of the monitored record and reports changes.
+///
+/// This function is enabled by setting the "TTL_WATCH" environment variable to "true".
+/// It retrieves the TTL from the authoritative name servers using the `authoritative_ttl` function,
+/// because resolvers count the TTL of cached answers down.
+///
+/// The TTL is compared with the TTL stored in the TTL file ("TTL_FILE", defaulting to "ttl_state" in the state directory).
+/// On the first check the TTL is only stored. After that, a "ttl" message is sent whenever it changes, e.g. from 300 to 86400 seconds,
+/// which usually means that someone edited the record or the DNS provider changed its behavior.
+/// If the TTL cannot be retrieved, it logs a warning and keeps the stored TTL.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+pub fn check_ttl(hostname: &str) {
+    if env::var("TTL_WATCH").as_deref() != Ok("true") {
+        return;
+    }
+    let ttl_file = state_path("TTL_FILE", "ttl_state");
+
+    let ttl = match authoritative_ttl(hostname) {
+        Ok(ttl) => ttl,
+        Err(e) => {
+            log::warn!("Failed to retrieve the TTL of {}: {}", hostname, e);
+            return;
+        }
+    };
+    let previous = read_state(&ttl_file);
+    if previous == ttl.to_string() {
+        return;
+    }
+    match previous.parse::<u32>() {
+        Ok(previous_ttl) => {
+            let text = ttl_message(hostname, previous_ttl, ttl);
+            log::info!("{}", text);
+            let message = Message::new("ttl", &text)
+                .with_field("hostname", hostname)
+                .with_field("previous_ttl", &previous_ttl.to_string())
+                .with_field("ttl", &ttl.to_string());
+            if !events::alert(&message) {
+                log::warn!("Failed to send TTL message");
+                return;
+            }
+        }
+        Err(_) => log::info!("Tracking the TTL of {}: {}s", hostname, ttl),
+    }
+    write_state(&ttl_file, &ttl.to_string());
+}
+
+/// Retrieves the TTL of the A record of a hostname as published by its authoritative name servers.
+///
+/// The zone is taken from the "DNS_ZONE" environment variable. If it is not set, it is the closest name
+/// above or at the hostname that has NS records. The name servers of the zone are queried directly, one after the other,
+/// until one answers authoritatively.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the hostname.
+///
+/// # Returns
+///
+/// * A `Result<u32, String>` that holds the TTL in seconds.
+/// * If no name server answers authoritatively with a record for the hostname, it returns an `Err` with a message.
+pub fn authoritative_ttl(hostname: &str) -> Result<u32, String> {
+    let hostname = idna::domain_to_ascii(hostname)
+        .unwrap_or_else(|_| hostname.to_string())
+        .trim_end_matches('.')
+        .to_lowercase();
+    let servers = match env::var("DNS_ZONE") {
+        Ok(zone) if !zone.is_empty() => dns::lookup_ns(&zone)?,
+        _ => dns::find_zone(&hostname)?.1,
+    };
+    let mut error = "no name server answered".to_string();
+    for ip in servers
+        .iter()
+        .flat_map(|server| dns::lookup_addresses(server))
+    {
+        match dns::query_server(SocketAddr::new(ip, 53), &hostname, RecordType::A) {
+            Ok(response) if response.authoritative() => {
+                return record_ttl(response.answers(), &hostname)
+                    .ok_or(format!("{} has no record on {}", hostname, ip));
+            }
+            Ok(_) => error = format!("{} did not answer authoritatively", ip),
+            Err(e) => error = e,
+        }
+    }
+    Err(error)
+}
+
+/// Selects the TTL of the records for a name in an answer.
+///
+/// # Arguments
+///
+/// * `records`: A `&[Record]` that holds the answer section of a response.
+/// * `name`: A `&str` that specifies the name in lower case and without the trailing dot.
+///
+/// # Returns
+///
+/// * An `Option<u32>` that holds the lowest TTL of the records owned by the name (A or CNAME), or `None` if there is none.
+fn record_ttl(records: &[Record], name: &str) -> Option<u32> {
+    records
+        .iter()
+        .filter(|record| {
+            record
+                .name()
+                .to_ascii()
+                .trim_end_matches('.')
+                .to_lowercase()
+                == name
+        })
+        .map(|record| record.ttl())
+        .min()
+}
+
+/// Builds the text of the TTL message.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that specifies the monitored hostname.
+/// * `previous_ttl`: A `u32` that specifies the TTL stored on the previous check.
+/// * `ttl`: A `u32` that specifies the current TTL.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message.
+fn ttl_message(hostname: &str, previous_ttl: u32, ttl: u32) -> String {
+    format!(
+        "TTL of the DNS record changed!\nHostname: {}\nPrevious: {}s\nCurrent: {}s",
+        display_hostname(hostname),
+        previous_ttl,
+        ttl
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trust_dns_resolver::proto::rr::rdata::A;
+    use trust_dns_resolver::proto::rr::{Name, RData};
+
+    #[test]
+    fn test_record_ttl() {
+        let record = |name: &str, ttl| {
+            Record::from_rdata(
+                Name::from_ascii(name).unwrap(),
+                ttl,
+                RData::A(A::new(192, 0, 2, 1)),
+            )
+        };
+        let records = vec![
+            record("Home.Example.com.", 300),
+            record("home.example.com.", 60),
+            record("other.example.com.", 30),
+        ];
+
+        assert_eq!(record_ttl(&records, "home.example.com"), Some(60));
+        assert_eq!(record_ttl(&records, "www.example.com"), None);
+    }
+
+    #[test]
+    fn test_ttl_message() {
+        let result = ttl_message("home.example.com", 300, 86400);
+        assert!(result.starts_with("TTL of the DNS record changed!"));
+        assert!(result.contains("Previous: 300s\nCurrent: 86400s"));
+    }
+}
@@ -0,0 +1,280 @@
+use crate::alarm;
+use crate::checker::{CheckOutcome, Checker, Event};
+use crate::history::{self, CheckRecord};
+use crate::logging;
+use crate::notify::{self, Severity};
+use crate::vars::display_hostname;
+use ratatui::crossterm::event::{self, Event as TerminalEvent, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph, Sparkline, SparklineBar};
+use ratatui::Frame;
+use std::time::{Duration, Instant};
+
+/// How long to wait for a key press before redrawing.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The state shown on the screen.
+struct Dashboard {
+    hostname: String,
+    /// The result of the last verification, `None` before the first one.
+    outcome: Option<CheckOutcome>,
+    /// The last mismatch or recovery, with the time it was seen.
+    last_change: Option<String>,
+    /// The history of the hostname, oldest first.
+    history: Vec<CheckRecord>,
+    /// The alarm state, see `alarm::status`.
+    alarm: Vec<String>,
+    /// The time until the next verification.
+    next_check: Duration,
+}
+
+impl Dashboard {
+    /// Updates the dashboard with the event of a verification and reloads the history and the alarm state.
+    fn update(&mut self, event: Event) {
+        let time = event.outcome().checked_at.format("%H:%M:%S");
+        match &event {
+            Event::Mismatch(_) => self.last_change = Some(format!("Mismatch at {}", time)),
+            Event::Recovered(_) => self.last_change = Some(format!("Recovered at {}", time)),
+            Event::Checked(_) => {}
+        }
+        self.outcome = Some(event.outcome().clone());
+        self.history = history::load_history(&history::history_file())
+            .into_iter()
+            .filter(|record| record.hostname == self.hostname)
+            .collect();
+        self.alarm = alarm::status();
+    }
+}
+
+/// Shows the checker in the terminal until "q" is pressed.
+///
+/// This function takes over the terminal and draws the result of the last verification, the alarm state,
+/// a sparkline of the history (see `draw`) and the log, which the "buffer" log format keeps in memory.
+/// The checker verifies right away and then once per interval, like `Checker::watch`; "c" verifies right away.
+///
+/// # Arguments
+///
+/// * `checker`: A `Checker` that verifies the hostname.
+///
+/// # Returns
+///
+/// * A `Result<(), String>` that is `Ok` when "q" was pressed, or an `Err` with a message if the terminal fails.
+pub fn run_tui(mut checker: Checker) -> Result<(), String> {
+    let mut terminal = ratatui::init();
+    let mut dashboard = Dashboard {
+        hostname: checker.hostname().to_string(),
+        outcome: None,
+        last_change: None,
+        history: Vec::new(),
+        alarm: alarm::status(),
+        next_check: Duration::ZERO,
+    };
+    let mut next_check = Instant::now();
+    let result = loop {
+        if Instant::now() >= next_check {
+            dashboard.update(checker.check_event());
+            next_check = Instant::now() + checker.interval();
+        }
+        dashboard.next_check = next_check.saturating_duration_since(Instant::now());
+        if let Err(e) = terminal.draw(|frame| draw(frame, &dashboard)) {
+            break Err(format!("Failed to draw: {}", e));
+        }
+        let input = event::poll(POLL_INTERVAL).and_then(|ready| {
+            if ready {
+                event::read().map(Some)
+            } else {
+                Ok(None)
+            }
+        });
+        match input {
+            Ok(Some(TerminalEvent::Key(key))) if key.kind == KeyEventKind::Press => {
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => break Ok(()),
+                    KeyCode::Char('c') => next_check = Instant::now(),
+                    _ => {}
+                }
+            }
+            Ok(_) => {}
+            Err(e) => break Err(format!("Failed to read the terminal: {}", e)),
+        }
+    };
+    ratatui::restore();
+    result
+}
+
+/// Draws the dashboard.
+///
+/// From top to bottom, the screen shows the result of the last verification, the alarm state,
+/// a sparkline of the history with a bar per verification (green if DNS was in sync, red if not),
+/// the most recent log lines that fit, and the keys.
+///
+/// # Arguments
+///
+/// * `frame`: A `&mut Frame` to draw on.
+/// * `dashboard`: A `&Dashboard` that holds the state.
+fn draw(frame: &mut Frame, dashboard: &Dashboard) {
+    let [status_area, alarm_area, history_area, log_area, keys_area] = Layout::vertical([
+        Constraint::Length(7),
+        Constraint::Length(4),
+        Constraint::Length(4),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let title = format!(" {} ", display_hostname(&dashboard.hostname));
+    frame.render_widget(
+        Paragraph::new(status_lines(dashboard)).block(Block::bordered().title(title)),
+        status_area,
+    );
+
+    let mut alarm_lines: Vec<Line> = dashboard
+        .alarm
+        .iter()
+        .map(|line| Line::from(line.as_str()))
+        .collect();
+    if let Some(last_change) = &dashboard.last_change {
+        alarm_lines.insert(0, Line::from(last_change.as_str()));
+        alarm_lines.truncate(2);
+    }
+    frame.render_widget(
+        Paragraph::new(alarm_lines).block(Block::bordered().title(" Alarm ")),
+        alarm_area,
+    );
+
+    let width = history_area.width.saturating_sub(2) as usize;
+    let bars: Vec<SparklineBar> = dashboard
+        .history
+        .iter()
+        .skip(dashboard.history.len().saturating_sub(width))
+        .map(|record| {
+            let color = if record.in_sync {
+                Color::Green
+            } else {
+                Color::Red
+            };
+            SparklineBar::from(1).style(Style::new().fg(color))
+        })
+        .collect();
+    let summary = format!(
+        " History ({}) ",
+        history::uptime_summary(&dashboard.history)
+    );
+    frame.render_widget(
+        Sparkline::default()
+            .block(Block::bordered().title(summary))
+            .data(bars)
+            .max(1),
+        history_area,
+    );
+
+    let lines = logging::recent_lines(log_area.height.saturating_sub(2) as usize);
+    frame.render_widget(
+        Paragraph::new(lines.into_iter().map(Line::from).collect::<Vec<Line>>())
+            .block(Block::bordered().title(" Log ")),
+        log_area,
+    );
+
+    frame.render_widget(
+        Line::from(format!(
+            "q quit · c check now · next check in {}s",
+            dashboard.next_check.as_secs()
+        ))
+        .dim(),
+        keys_area,
+    );
+}
+
+/// Builds the lines of the result of the last verification.
+///
+/// # Arguments
+///
+/// * `dashboard`: A `&Dashboard` that holds the state.
+///
+/// # Returns
+///
+/// * A `Vec<Line>` that holds the state, the IP addresses, their source and the time of the verification.
+fn status_lines(dashboard: &Dashboard) -> Vec<Line<'static>> {
+    let outcome = match &dashboard.outcome {
+        Some(outcome) => outcome,
+        None => return vec![Line::from("Checking…")],
+    };
+    let or_dash = |value: &str| match value {
+        "" => "-".to_string(),
+        value => value.to_string(),
+    };
+    let state = match outcome.in_sync {
+        Some(true) => Span::from("In sync").green().bold(),
+        Some(false) if notify::severity(&dashboard.hostname) == Severity::WarnOnly => {
+            Span::from("MISMATCH (warn-only)").yellow().bold()
+        }
+        Some(false) => Span::from("MISMATCH").red().bold(),
+        None => Span::from("Not compared").yellow(),
+    };
+    let wan_ip = match outcome.interface.as_str() {
+        "" => or_dash(&outcome.wan_ip),
+        interface => format!("{} ({})", or_dash(&outcome.wan_ip), interface),
+    };
+    vec![
+        Line::from(vec![Span::from("State:   "), state]),
+        Line::from(format!("DNS:     {}", or_dash(&outcome.dns_ips.join(", ")))),
+        Line::from(format!("WAN:     {}", wan_ip)),
+        Line::from(format!("Source:  {}", or_dash(&outcome.wan_source))),
+        Line::from(format!(
+            "Checked: {}",
+            outcome.checked_at.format("%Y-%m-%d %H:%M:%S")
+        )),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::Context;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    #[test]
+    fn test_draw() {
+        let outcome = CheckOutcome::from(Context {
+            dns_ips: vec!["2.2.2.2".to_string()],
+            interface: "igb0".to_string(),
+            wan_ip: "1.1.1.1".to_string(),
+            in_sync: Some(false),
+            ..Context::new("home.example.com")
+        });
+        let record = |in_sync| CheckRecord {
+            timestamp: outcome.checked_at.fixed_offset(),
+            hostname: "home.example.com".to_string(),
+            dns_ip: "2.2.2.2".to_string(),
+            wan_ip: "1.1.1.1".to_string(),
+            in_sync,
+            latencies: Default::default(),
+        };
+        let dashboard = Dashboard {
+            hostname: "home.example.com".to_string(),
+            outcome: Some(outcome.clone()),
+            last_change: Some("Mismatch at 12:00:00".to_string()),
+            history: vec![record(true), record(false)],
+            alarm: vec!["Alarm: sent 2024-05-01 (/tmp/lockfile)".to_string()],
+            next_check: Duration::from_secs(7),
+        };
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|frame| draw(frame, &dashboard)).unwrap();
+
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains(" home.example.com "));
+        assert!(screen.contains("State:   MISMATCH"));
+        assert!(screen.contains("WAN:     1.1.1.1 (igb0)"));
+        assert!(screen.contains("Mismatch at 12:00:00"));
+        assert!(screen.contains("next check in 7s"));
+    }
+}
@@ -1,4 +1,5 @@
 use std::env::VarError;
+use std::time::Duration;
 
 /// Retrieves the value of an environment variable.
 ///
@@ -58,6 +59,208 @@ pub fn get_vars_from_env(names: Vec<&str>) -> bool {
     error
 }
 
+/// Splits a comma-separated environment variable value into a list.
+///
+/// This function takes the value of an environment variable as an argument.
+/// It splits the value on commas, trims whitespace from each item and drops empty items.
+///
+/// # Arguments
+///
+/// * `value`: A `&str` that holds the comma-separated value.
+///
+/// # Returns
+///
+/// * A `Vec<String>` that holds the items of the list.
+pub fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Parses a human readable duration such as "30d", "12h", "15m" or "90s".
+///
+/// This function takes a duration string as an argument.
+/// The suffix selects the unit: "d" for days, "h" for hours, "m" for minutes and "s" for seconds.
+/// A number without a suffix is interpreted as seconds.
+///
+/// # Arguments
+///
+/// * `value`: A `&str` that holds the duration string.
+///
+/// # Returns
+///
+/// * An `Option<Duration>` that holds the duration, or `None` if the string is not a valid duration.
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (number, multiplier) = match value.chars().last()? {
+        'd' => (&value[..value.len() - 1], 86400),
+        'h' => (&value[..value.len() - 1], 3600),
+        'm' => (&value[..value.len() - 1], 60),
+        's' => (&value[..value.len() - 1], 1),
+        _ => (value, 1),
+    };
+    let number: u64 = number.parse().ok()?;
+    Some(Duration::from_secs(number.checked_mul(multiplier)?))
+}
+
+/// Validates and normalizes a Telegram chat ID.
+///
+/// This function takes a chat ID as an argument.
+/// A chat ID is either an integer (negative for groups and channels) or a public channel username such as "@mychannel".
+///
+/// # Arguments
+///
+/// * `chat_id`: A `&str` that holds the chat ID.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds the trimmed chat ID if it is valid.
+/// * If the chat ID is invalid, it returns an `Err` with a message.
+pub fn validate_chat_id(chat_id: &str) -> Result<String, String> {
+    let chat_id = chat_id.trim();
+    if let Some(username) = chat_id.strip_prefix('@') {
+        let valid = (5..=32).contains(&username.len())
+            && username
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if valid {
+            return Ok(chat_id.to_string());
+        }
+        return Err(format!(
+            "CHAT_ID \"{}\" is not a valid channel username",
+            chat_id
+        ));
+    }
+    match chat_id.parse::<i64>() {
+        Ok(_) => Ok(chat_id.to_string()),
+        Err(_) => Err(format!(
+            "CHAT_ID \"{}\" must be an integer or a channel username like @mychannel",
+            chat_id
+        )),
+    }
+}
+
+/// Validates a hostname and normalizes it to its ASCII (punycode) form.
+///
+/// This function takes a hostname as an argument.
+/// Internationalized names are converted to punycode using IDNA, e.g. "münchen.example" becomes "xn--mnchen-3ya.example".
+/// A trailing dot is removed. The name must have at least two labels,
+/// each label must be 1 to 63 letters, digits or hyphens and must not start or end with a hyphen.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that holds the hostname.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds the normalized hostname if it is valid.
+/// * If the hostname is invalid, it returns an `Err` with a message.
+pub fn normalize_hostname(hostname: &str) -> Result<String, String> {
+    let trimmed = hostname.trim().trim_end_matches('.');
+    let ascii = match url::Host::parse(trimmed) {
+        Ok(url::Host::Domain(domain)) => domain,
+        _ => {
+            return Err(format!(
+                "DNS_HOSTNAME \"{}\" is not a valid hostname",
+                hostname
+            ))
+        }
+    };
+    let labels: Vec<&str> = ascii.split('.').collect();
+    let valid = ascii.len() <= 253
+        && labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    if valid {
+        Ok(ascii)
+    } else {
+        Err(format!(
+            "DNS_HOSTNAME \"{}\" is not a fully qualified domain name",
+            hostname
+        ))
+    }
+}
+
+/// Expands the zone apex shorthand in a hostname.
+///
+/// This function takes a hostname and a zone as arguments.
+/// If the hostname is "@", it returns the zone, so the apex of the zone can be monitored.
+/// Otherwise it returns the hostname unchanged.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that holds the hostname.
+/// * `zone`: A `&str` that holds the zone.
+///
+/// # Returns
+///
+/// * A `String` that holds the expanded hostname.
+pub fn expand_apex(hostname: &str, zone: &str) -> String {
+    if hostname.trim() == "@" {
+        zone.to_string()
+    } else {
+        hostname.to_string()
+    }
+}
+
+/// Converts a hostname to its Unicode form for display.
+///
+/// This function takes a hostname as an argument and converts punycode labels to Unicode using IDNA,
+/// e.g. "xn--mnchen-3ya.example" becomes "münchen.example".
+/// If the conversion fails, it returns the hostname unchanged.
+///
+/// # Arguments
+///
+/// * `hostname`: A `&str` that holds the hostname.
+///
+/// # Returns
+///
+/// * A `String` that holds the Unicode form of the hostname.
+pub fn display_hostname(hostname: &str) -> String {
+    match idna::domain_to_unicode(hostname) {
+        (unicode, Ok(())) => unicode,
+        (_, Err(_)) => hostname.to_string(),
+    }
+}
+
+/// Validates the URL of the router API.
+///
+/// This function takes a URL as an argument.
+/// The URL must parse and use the https scheme. A plain http URL is accepted with a warning,
+/// since the API credentials are then sent unencrypted.
+///
+/// # Arguments
+///
+/// * `url`: A `&str` that holds the URL.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds the trimmed URL if it is valid.
+/// * If the URL is invalid, it returns an `Err` with a message.
+pub fn validate_url(url: &str) -> Result<String, String> {
+    let url = url.trim();
+    let parsed = match url::Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(e) => return Err(format!("URL \"{}\" is not a valid URL: {}", url, e)),
+    };
+    match parsed.scheme() {
+        "https" => Ok(url.to_string()),
+        "http" => {
+            log::warn!("URL uses http, API credentials are sent unencrypted");
+            Ok(url.to_string())
+        }
+        scheme => Err(format!("URL \"{}\" must use https, not {}", url, scheme)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -78,4 +281,76 @@ mod tests {
         // Assert that the function returns the value of the environment variable
         assert_eq!(result, "");
     }
+
+    #[test]
+    fn test_split_list() {
+        let result = split_list(" igb3, igb4,,");
+
+        assert_eq!(result, vec!["igb3", "igb4"]);
+    }
+
+    #[test]
+    fn test_validate_chat_id() {
+        assert_eq!(validate_chat_id(" -100123 "), Ok("-100123".to_string()));
+        assert_eq!(
+            validate_chat_id("@my_channel"),
+            Ok("@my_channel".to_string())
+        );
+        assert!(validate_chat_id("@me").is_err());
+        assert!(validate_chat_id("my channel").is_err());
+    }
+
+    #[test]
+    fn test_normalize_hostname() {
+        assert_eq!(
+            normalize_hostname("Home.Example.com."),
+            Ok("home.example.com".to_string())
+        );
+        assert_eq!(
+            normalize_hostname("münchen.example"),
+            Ok("xn--mnchen-3ya.example".to_string())
+        );
+        assert!(normalize_hostname("localhost").is_err());
+        assert!(normalize_hostname("-bad.example.com").is_err());
+        assert!(normalize_hostname("https://home.example.com").is_err());
+    }
+
+    #[test]
+    fn test_expand_apex() {
+        assert_eq!(expand_apex("@", "example.com"), "example.com");
+        assert_eq!(
+            expand_apex("home.example.com", "example.com"),
+            "home.example.com"
+        );
+    }
+
+    #[test]
+    fn test_display_hostname() {
+        assert_eq!(
+            display_hostname("xn--mnchen-3ya.example"),
+            "münchen.example"
+        );
+        assert_eq!(display_hostname("home.example.com"), "home.example.com");
+    }
+
+    #[test]
+    fn test_validate_url() {
+        assert!(
+            validate_url("https://192.168.1.1/api/diagnostics/interface/getInterfaceConfig")
+                .is_ok()
+        );
+        assert!(validate_url("http://192.168.1.1/api").is_ok());
+        assert!(validate_url("ftp://192.168.1.1/api").is_err());
+        assert!(validate_url("192.168.1.1/api").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30d"), Some(Duration::from_secs(30 * 86400)));
+        assert_eq!(parse_duration("12h"), Some(Duration::from_secs(12 * 3600)));
+        assert_eq!(parse_duration("15m"), Some(Duration::from_secs(900)));
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_duration(""), None);
+    }
 }
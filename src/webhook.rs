@@ -0,0 +1,149 @@
+use crate::http;
+use crate::notify::{Message, Notifier};
+use chrono::Local;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::Duration;
+
+/// Delivers notifications as JSON to a generic webhook.
+pub struct WebhookNotifier {
+    url: String,
+    secret: Option<String>,
+}
+
+impl WebhookNotifier {
+    /// Creates a webhook notifier.
+    ///
+    /// If a secret is given, every payload is signed with HMAC-SHA256 (see `sign`).
+    pub fn new(url: &str, secret: Option<String>) -> WebhookNotifier {
+        WebhookNotifier {
+            url: url.to_string(),
+            secret: secret.filter(|secret| !secret.is_empty()),
+        }
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    /// Posts the message to the webhook.
+    ///
+    /// The body is a JSON object with the "event", "text", "timestamp" and "fields" of the message.
+    /// If a secret is configured, the "X-Dnschecker-Signature" header carries "sha256=" followed by
+    /// the hex encoded HMAC-SHA256 of the body, so the receiver can authenticate the alert.
+    fn send(&self, message: &Message) -> bool {
+        let body = payload(message).to_string();
+        let client = match http::client() {
+            Ok(client) => client,
+            Err(e) => {
+                log::warn!("{}", e);
+                return false;
+            }
+        };
+        let mut request = client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .timeout(Duration::from_secs(10));
+        if let Some(secret) = &self.secret {
+            request = request.header("X-Dnschecker-Signature", sign(secret, body.as_bytes()));
+        }
+        match request.body(body).send() {
+            Ok(response) if response.status().is_success() => true,
+            Ok(response) => {
+                log::warn!("Webhook returned {}", response.status());
+                false
+            }
+            Err(e) => {
+                log::warn!("Failed to make HTTPS request: {:?}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Builds the JSON payload of a message.
+///
+/// # Arguments
+///
+/// * `message`: A `&Message` that holds the notification.
+///
+/// # Returns
+///
+/// * A `serde_json::Value` that holds the payload.
+fn payload(message: &Message) -> serde_json::Value {
+    let fields: serde_json::Map<String, serde_json::Value> = message
+        .fields
+        .iter()
+        .map(|(name, value)| (name.clone(), serde_json::Value::from(value.clone())))
+        .collect();
+    serde_json::json!({
+        "event": message.event,
+        "text": message.text,
+        "timestamp": Local::now().to_rfc3339(),
+        "fields": fields,
+    })
+}
+
+/// Signs a payload with HMAC-SHA256.
+///
+/// # Arguments
+///
+/// * `secret`: A `&str` that specifies the shared secret.
+/// * `body`: A `&[u8]` that holds the payload.
+///
+/// # Returns
+///
+/// * A `String` that holds the signature in the form "sha256=<hex>".
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+
+    #[test]
+    fn test_sign() {
+        // Test vector from RFC 4231, test case 2
+        let result = sign("Jefe", b"what do ya want for nothing?");
+
+        assert_eq!(
+            result,
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_send_signed() {
+        let server = MockServer::start();
+
+        // Create a mock that requires the signature of the body with the secret
+        let mock = server.mock(|when, then| {
+            when.method("POST")
+                .path("/hook")
+                .matches(|request| {
+                    let body = request.body.as_deref().unwrap_or_default();
+                    request.headers.iter().flatten().any(|(name, value)| {
+                        name.eq_ignore_ascii_case("X-Dnschecker-Signature")
+                            && *value == sign("secret", body)
+                    })
+                })
+                .json_body_partial(
+                    "{\"event\": \"mismatch\", \"fields\": {\"hostname\": \"home.example.com\"}}",
+                );
+            then.status(204);
+        });
+
+        let notifier = WebhookNotifier::new(&server.url("/hook"), Some("secret".to_string()));
+        let message = Message::new("mismatch", "text").with_field("hostname", "home.example.com");
+
+        assert!(notifier.send(&message));
+        mock.assert();
+    }
+}
@@ -0,0 +1,226 @@
+use crate::http;
+use crate::notify::{Message, Notifier};
+use crate::vars::split_list;
+use serde_json::{json, Value};
+use std::env;
+use std::time::Duration;
+
+/// The base URL of the WhatsApp Business Cloud API.
+const GRAPH_API: &str = "https://graph.facebook.com/v19.0";
+
+/// The longest text WhatsApp accepts in a template parameter.
+const MAX_PARAMETER_LEN: usize = 1024;
+
+/// Delivers notifications to WhatsApp numbers through the WhatsApp Business Cloud API.
+pub struct WhatsAppNotifier {
+    api: String,
+    phone_number_id: String,
+    token: String,
+    recipients: Vec<String>,
+    template: Option<(String, String)>,
+}
+
+impl WhatsAppNotifier {
+    /// Creates a WhatsApp notifier from the environment.
+    ///
+    /// The notifier is enabled by setting "WHATSAPP_PHONE_NUMBER_ID" to the ID of the business phone number,
+    /// "WHATSAPP_TOKEN" to an access token with the "whatsapp_business_messaging" permission,
+    /// and "WHATSAPP_RECIPIENTS" to a list of phone numbers in international format without "+", e.g. "4915111111111".
+    ///
+    /// WhatsApp only delivers free-form messages within 24 hours of the last message of the recipient,
+    /// so alerts are usually sent with an approved template: "WHATSAPP_TEMPLATE" names it,
+    /// and "WHATSAPP_LANGUAGE" (defaulting to "en_US") selects its language. See `body`.
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<WhatsAppNotifier>` that holds the notifier, or `None` if "WHATSAPP_PHONE_NUMBER_ID" is not set
+    ///   or the token or the recipients are missing.
+    pub fn from_env() -> Option<WhatsAppNotifier> {
+        let phone_number_id = env::var("WHATSAPP_PHONE_NUMBER_ID")
+            .ok()
+            .filter(|id| !id.is_empty())?;
+        let token = env::var("WHATSAPP_TOKEN").unwrap_or_default();
+        let recipients = split_list(&env::var("WHATSAPP_RECIPIENTS").unwrap_or_default());
+        if token.is_empty() || recipients.is_empty() {
+            log::warn!(
+                "WHATSAPP_PHONE_NUMBER_ID is set, but WHATSAPP_TOKEN or WHATSAPP_RECIPIENTS is missing"
+            );
+            return None;
+        }
+        let template = env::var("WHATSAPP_TEMPLATE")
+            .ok()
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                let language = env::var("WHATSAPP_LANGUAGE").unwrap_or("en_US".to_string());
+                (name, language)
+            });
+        Some(WhatsAppNotifier {
+            api: GRAPH_API.to_string(),
+            phone_number_id,
+            token,
+            recipients,
+            template,
+        })
+    }
+}
+
+impl Notifier for WhatsAppNotifier {
+    fn name(&self) -> &str {
+        "whatsapp"
+    }
+
+    /// Sends the message to every recipient and returns whether all of them were accepted.
+    fn send(&self, message: &Message) -> bool {
+        let client = match http::client() {
+            Ok(client) => client,
+            Err(e) => {
+                log::warn!("{}", e);
+                return false;
+            }
+        };
+        let url = format!("{}/{}/messages", self.api, self.phone_number_id);
+        let mut sent = true;
+        for recipient in &self.recipients {
+            let response = client
+                .post(&url)
+                .bearer_auth(&self.token)
+                .json(&body(recipient, &message.text, self.template.as_ref()))
+                .timeout(Duration::from_secs(10))
+                .send();
+            match response {
+                Ok(response) if response.status().is_success() => {}
+                Ok(response) => {
+                    let status = response.status();
+                    let error: Value = response.json().unwrap_or_default();
+                    log::warn!(
+                        "WhatsApp returned {} for {}: {}",
+                        status,
+                        recipient,
+                        error["error"]["message"].as_str().unwrap_or("no message")
+                    );
+                    sent = false;
+                }
+                Err(e) => {
+                    log::warn!("Failed to make HTTPS request: {:?}", e);
+                    sent = false;
+                }
+            }
+        }
+        sent
+    }
+}
+
+/// Builds the body of a WhatsApp message.
+///
+/// With a template, the text of the message is passed as the only parameter of the template body, e.g. a template
+/// with the body "dnschecker: {{1}}". Template parameters cannot hold new lines, tabs or more than four spaces in a row,
+/// so the lines of the text are joined with " | " and it is cut to 1024 characters (see `template_parameter`).
+/// Without a template, the text is sent as a free-form message.
+///
+/// # Arguments
+///
+/// * `recipient`: A `&str` that specifies the phone number of the recipient.
+/// * `text`: A `&str` that specifies the text of the message.
+/// * `template`: An `Option<&(String, String)>` that holds the name and language of the template, if one is configured.
+///
+/// # Returns
+///
+/// * A `Value` that holds the JSON body.
+fn body(recipient: &str, text: &str, template: Option<&(String, String)>) -> Value {
+    match template {
+        Some((name, language)) => json!({
+            "messaging_product": "whatsapp",
+            "to": recipient,
+            "type": "template",
+            "template": {
+                "name": name,
+                "language": {"code": language},
+                "components": [{
+                    "type": "body",
+                    "parameters": [{"type": "text", "text": template_parameter(text)}],
+                }],
+            },
+        }),
+        None => json!({
+            "messaging_product": "whatsapp",
+            "to": recipient,
+            "type": "text",
+            "text": {"body": text},
+        }),
+    }
+}
+
+/// Turns the text of a message into a valid template parameter.
+///
+/// # Arguments
+///
+/// * `text`: A `&str` that specifies the text of the message.
+///
+/// # Returns
+///
+/// * A `String` that holds the lines of the text joined with " | ", without tabs or runs of spaces, at most 1024 characters long.
+fn template_parameter(text: &str) -> String {
+    let parameter = text
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<String>>()
+        .join(" | ");
+    if parameter.chars().count() <= MAX_PARAMETER_LEN {
+        return parameter;
+    }
+    let mut cut: String = parameter.chars().take(MAX_PARAMETER_LEN - 1).collect();
+    cut.push('…');
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+
+    #[test]
+    fn test_template_parameter() {
+        assert_eq!(
+            template_parameter(
+                "IP address mismatch!\nHostname:\thome.example.com\n\nRouter IP:     1.1.1.1"
+            ),
+            "IP address mismatch! | Hostname: home.example.com | Router IP: 1.1.1.1"
+        );
+        let result = template_parameter(&"x".repeat(2000));
+        assert_eq!(result.chars().count(), MAX_PARAMETER_LEN);
+        assert!(result.ends_with('…'));
+    }
+
+    #[test]
+    fn test_send() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method("POST")
+                .path("/123456/messages")
+                .header("authorization", "Bearer token")
+                .json_body_partial(
+                    r#"{"to": "4915111111111", "type": "template", "template": {"name": "dns_alert", "language": {"code": "de"}}}"#,
+                );
+            then.status(200)
+                .body(r#"{"messages": [{"id": "wamid.1"}]}"#);
+        });
+        let notifier = WhatsAppNotifier {
+            api: server.base_url(),
+            phone_number_id: "123456".to_string(),
+            token: "token".to_string(),
+            recipients: vec!["4915111111111".to_string()],
+            template: Some(("dns_alert".to_string(), "de".to_string())),
+        };
+
+        assert!(notifier.send(&Message::new("mismatch", "IP address mismatch!")));
+        mock.assert();
+
+        // Assert that a rejected recipient fails the delivery
+        let notifier = WhatsAppNotifier {
+            recipients: vec!["4915122222222".to_string()],
+            ..notifier
+        };
+        assert!(!notifier.send(&Message::new("mismatch", "IP address mismatch!")));
+    }
+}
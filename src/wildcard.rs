@@ -0,0 +1,130 @@
+use crate::compare::ComparePolicy;
+use crate::dns;
+use crate::events;
+use crate::notify::Message;
+use crate::state::{read_state, state_path, write_state};
+use crate::vars::display_hostname;
+use std::env;
+
+/// Verifies that the wildcard record of a zone resolves consistently with the WAN IP address.
+///
+/// This function retrieves the value of the "WILDCARD_ZONE" environment variable.
+/// If it is not set, the wildcard check is disabled and the function returns immediately.
+///
+/// It resolves a synthesized probe name below the zone (see `dns::probe_name`), which can only be answered by the wildcard record,
+/// and the zone apex, and compares every address of the wildcard answer with the WAN IP address under the "COMPARE_POLICY"
+/// (see `compare::ComparePolicy`), so a round-robin wildcard can be accepted with "any_of".
+/// The result is compared with the state stored in the wildcard file ("WILDCARD_FILE", defaulting to "wildcard_state" in the state directory),
+/// and a message is sent when the wildcard breaks or recovers.
+///
+/// # Arguments
+/// * `wan_ip`: A `&str` that specifies the WAN IP address.
+pub fn check_wildcard(wan_ip: &str) {
+    let zone = match env::var("WILDCARD_ZONE") {
+        Ok(zone) => zone,
+        Err(_) => return,
+    };
+    if wan_ip.is_empty() {
+        log::debug!("No WAN IP address, skipping wildcard check");
+        return;
+    }
+    let wildcard_file = state_path("WILDCARD_FILE", "wildcard_state");
+
+    let probe = dns::probe_name(&zone);
+    let resolver = dns::default_resolver();
+    let wildcard_ips = dns::resolve_addresses_using(&probe, &resolver);
+    let apex_ips = dns::resolve_addresses_using(&zone, &resolver);
+    log::debug!(
+        "Wildcard {} resolves to {:?}, apex {} resolves to {:?}",
+        probe,
+        wildcard_ips,
+        zone,
+        apex_ips
+    );
+
+    let policy = ComparePolicy::from_env().unwrap_or(ComparePolicy::Exact);
+    let in_sync = policy.in_sync(&wildcard_ips, wan_ip, &[]);
+    let state = if in_sync { "ok" } else { "broken" };
+    let previous = read_state(&wildcard_file);
+    if previous == state {
+        return;
+    }
+    if previous.is_empty() && state == "ok" {
+        log::info!("Wildcard record of {} resolves to the WAN IP address", zone);
+    } else {
+        let text = wildcard_message(&zone, &probe, &wildcard_ips, &apex_ips, wan_ip, in_sync);
+        log::info!("{}", text);
+        if !events::alert(&Message::new("wildcard", &text)) {
+            log::warn!("Failed to send wildcard message");
+            return;
+        }
+    }
+    write_state(&wildcard_file, state);
+}
+
+/// Builds the text of the wildcard message.
+///
+/// # Arguments
+///
+/// * `zone`: A `&str` that specifies the zone.
+/// * `probe`: A `&str` that specifies the probe name that was resolved.
+/// * `wildcard_ips`: A `&[String]` that holds the answer for the probe name.
+/// * `apex_ips`: A `&[String]` that holds the answer for the zone apex.
+/// * `wan_ip`: A `&str` that specifies the WAN IP address.
+/// * `in_sync`: A `bool` that indicates whether the wildcard answer matches the WAN IP address.
+///
+/// # Returns
+///
+/// * A `String` that holds the text of the message.
+fn wildcard_message(
+    zone: &str,
+    probe: &str,
+    wildcard_ips: &[String],
+    apex_ips: &[String],
+    wan_ip: &str,
+    in_sync: bool,
+) -> String {
+    let or_none = |ips: &[String]| {
+        if ips.is_empty() {
+            "no answer".to_string()
+        } else {
+            ips.join(", ")
+        }
+    };
+    let headline = if in_sync {
+        "Wildcard record resolves to the WAN IP again"
+    } else {
+        "Wildcard record does not resolve to the WAN IP!"
+    };
+    format!(
+        "{}\nZone: {}\nProbe: {} -> {}\nApex: {}\nWAN IP: {}",
+        headline,
+        display_hostname(zone),
+        probe,
+        or_none(wildcard_ips),
+        or_none(apex_ips),
+        wan_ip
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_message() {
+        let apex = vec!["1.1.1.1".to_string(), "2.2.2.2".to_string()];
+        let result = wildcard_message(
+            "example.com",
+            "dnschecker-1.example.com",
+            &[],
+            &apex,
+            "1.1.1.1",
+            false,
+        );
+
+        assert!(result.starts_with("Wildcard record does not resolve to the WAN IP!"));
+        assert!(result.contains("Probe: dnschecker-1.example.com -> no answer"));
+        assert!(result.contains("Apex: 1.1.1.1, 2.2.2.2"));
+    }
+}
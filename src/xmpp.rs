@@ -0,0 +1,445 @@
+use crate::dns;
+use crate::http;
+use crate::notify::{Message, Notifier};
+use crate::vars::split_list;
+use base64::Engine;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConnection, StreamOwned};
+use std::env;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use trust_dns_resolver::proto::rr::RecordType;
+
+/// The time allowed for connecting and for each answer of the server.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delivers notifications to XMPP addresses and group chats (MUC) with a short-lived client connection.
+pub struct XmppNotifier {
+    /// The local part of the account, e.g. "alerts" of "alerts@example.com".
+    user: String,
+    /// The domain of the account.
+    domain: String,
+    password: String,
+    /// The server to connect to as host:port, looked up from the domain if `None`.
+    server: Option<String>,
+    recipients: Vec<String>,
+    rooms: Vec<String>,
+    nick: String,
+}
+
+impl XmppNotifier {
+    /// Creates an XMPP notifier from the environment.
+    ///
+    /// The notifier is enabled by setting "XMPP_JID" to the address of the account the alerts are sent from,
+    /// e.g. "alerts@example.com", and "XMPP_PASSWORD" to its password. The alerts are sent to the addresses in
+    /// "XMPP_RECIPIENTS" and to the group chats in "XMPP_ROOMS", which the account joins with the nickname "XMPP_NICK"
+    /// (defaulting to "dnschecker").
+    ///
+    /// The server is taken from "XMPP_SERVER" as host[:port]; if it is not set, it is looked up in the
+    /// "_xmpp-client._tcp" SRV record of the domain, falling back to the domain on port 5222.
+    ///
+    /// # Returns
+    ///
+    /// * An `Option<XmppNotifier>` that holds the notifier, or `None` if "XMPP_JID" is not set,
+    ///   it is invalid, or the password or the recipients are missing.
+    pub fn from_env() -> Option<XmppNotifier> {
+        let jid = env::var("XMPP_JID").ok().filter(|jid| !jid.is_empty())?;
+        let (user, domain) = match jid.split_once('@') {
+            Some((user, domain)) if !user.is_empty() && !domain.is_empty() => {
+                (user.to_string(), domain.to_lowercase())
+            }
+            _ => {
+                log::warn!("Invalid XMPP_JID {}, expected user@domain", jid);
+                return None;
+            }
+        };
+        let password = env::var("XMPP_PASSWORD").unwrap_or_default();
+        let recipients = split_list(&env::var("XMPP_RECIPIENTS").unwrap_or_default());
+        let rooms = split_list(&env::var("XMPP_ROOMS").unwrap_or_default());
+        if password.is_empty() || (recipients.is_empty() && rooms.is_empty()) {
+            log::warn!(
+                "XMPP_JID is set, but XMPP_PASSWORD or XMPP_RECIPIENTS and XMPP_ROOMS are missing"
+            );
+            return None;
+        }
+        Some(XmppNotifier {
+            user,
+            domain,
+            password,
+            server: env::var("XMPP_SERVER")
+                .ok()
+                .filter(|server| !server.is_empty()),
+            recipients,
+            rooms,
+            nick: env::var("XMPP_NICK").unwrap_or("dnschecker".to_string()),
+        })
+    }
+
+    /// Retrieves the server to connect to, see `from_env`.
+    ///
+    /// # Returns
+    ///
+    /// * A `(String, u16)` that holds the host and port of the server.
+    fn server_address(&self) -> (String, u16) {
+        if let Some(server) = &self.server {
+            return match server.rsplit_once(':') {
+                Some((host, port)) if port.parse::<u16>().is_ok() => {
+                    (host.to_string(), port.parse().unwrap_or(5222))
+                }
+                _ => (server.clone(), 5222),
+            };
+        }
+        let srv = dns::lookup_records(
+            &format!("_xmpp-client._tcp.{}", self.domain),
+            RecordType::SRV,
+        )
+        .unwrap_or_default();
+        srv_target(&srv).unwrap_or((self.domain.clone(), 5222))
+    }
+
+    /// Connects to the server, secures the connection with STARTTLS, logs in and sends the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `text`: A `&str` that specifies the text of the message.
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<(), String>` that is `Ok` if the server accepted the login and the stanzas, or an `Err` with a message.
+    fn deliver(&self, text: &str) -> Result<(), String> {
+        let (host, port) = self.server_address();
+        let address = (host.as_str(), port)
+            .to_socket_addrs()
+            .map_err(|e| format!("Failed to resolve XMPP server {}: {}", host, e))?
+            .next()
+            .ok_or(format!("No address found for XMPP server {}", host))?;
+        let stream = TcpStream::connect_timeout(&address, TIMEOUT)
+            .map_err(|e| format!("Failed to connect to XMPP server {}: {}", address, e))?;
+        stream
+            .set_read_timeout(Some(TIMEOUT))
+            .and_then(|_| stream.set_write_timeout(Some(TIMEOUT)))
+            .map_err(|e| e.to_string())?;
+
+        let mut session = Session::new(stream);
+        session.open(&self.domain)?;
+        let features = session.expect(&["</stream:features>"])?;
+        if !features.contains("urn:ietf:params:xml:ns:xmpp-tls") {
+            return Err(format!("XMPP server {} does not offer STARTTLS", host));
+        }
+        session.write("<starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>")?;
+        if !session
+            .expect(&["<proceed", "<failure"])?
+            .ends_with("<proceed")
+        {
+            return Err(format!("XMPP server {} refused STARTTLS", host));
+        }
+
+        let server_name = ServerName::try_from(self.domain.clone())
+            .map_err(|e| format!("Invalid XMPP domain {}: {}", self.domain, e))?;
+        let connection = ClientConnection::new(http::tls_config()?, server_name)
+            .map_err(|e| format!("Failed to start TLS: {}", e))?;
+        let mut session = Session::new(StreamOwned::new(connection, session.stream));
+        login(&mut session, &self.domain, &self.user, &self.password)?;
+        for stanza in stanzas(&self.recipients, &self.rooms, &self.nick, text) {
+            session.write(&stanza)?;
+        }
+        // Closing the stream makes the server process the stanzas before the connection ends
+        session.write("</stream:stream>")?;
+        let _ = session.expect(&["</stream:stream>"]);
+        Ok(())
+    }
+}
+
+impl Notifier for XmppNotifier {
+    fn name(&self) -> &str {
+        "xmpp"
+    }
+
+    fn send(&self, message: &Message) -> bool {
+        match self.deliver(&message.text) {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("{}", e);
+                false
+            }
+        }
+    }
+}
+
+/// An XML stream with the server, read up to known markers instead of being parsed.
+struct Session<S: Read + Write> {
+    stream: S,
+    buffer: String,
+}
+
+impl<S: Read + Write> Session<S> {
+    fn new(stream: S) -> Session<S> {
+        Session {
+            stream,
+            buffer: String::new(),
+        }
+    }
+
+    /// Opens or restarts the stream to the domain.
+    fn open(&mut self, domain: &str) -> Result<(), String> {
+        self.write(&format!(
+            "<?xml version='1.0'?><stream:stream to='{}' version='1.0' xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'>",
+            escape(domain)
+        ))
+    }
+
+    /// Writes raw XML to the stream.
+    fn write(&mut self, xml: &str) -> Result<(), String> {
+        self.stream
+            .write_all(xml.as_bytes())
+            .and_then(|_| self.stream.flush())
+            .map_err(|e| format!("Failed to write to XMPP server: {}", e))
+    }
+
+    /// Reads from the stream until one of the markers arrives.
+    ///
+    /// # Arguments
+    ///
+    /// * `markers`: A `&[&str]` that holds the markers to wait for, e.g. "</stream:features>".
+    ///
+    /// # Returns
+    ///
+    /// * A `Result<String, String>` that holds what was read up to and including the first marker; the rest is kept for the next call.
+    /// * If the server sends a stream error or closes the connection first, it returns an `Err` with a message.
+    fn expect(&mut self, markers: &[&str]) -> Result<String, String> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            let found = markers
+                .iter()
+                .filter_map(|marker| self.buffer.find(marker).map(|at| at + marker.len()))
+                .min();
+            if let Some(end) = found {
+                let rest = self.buffer.split_off(end);
+                return Ok(std::mem::replace(&mut self.buffer, rest));
+            }
+            if let Some(at) = self.buffer.find("<stream:error") {
+                return Err(format!("XMPP stream error: {}", &self.buffer[at..]));
+            }
+            let read = self
+                .stream
+                .read(&mut chunk)
+                .map_err(|e| format!("Failed to read from XMPP server: {}", e))?;
+            if read == 0 {
+                return Err("XMPP server closed the connection".to_string());
+            }
+            self.buffer
+                .push_str(&String::from_utf8_lossy(&chunk[..read]));
+        }
+    }
+}
+
+/// Logs in on a secured stream with SASL PLAIN and binds a resource.
+///
+/// # Arguments
+///
+/// * `session`: A `&mut Session<S>` that holds the stream, secured with TLS.
+/// * `domain`: A `&str` that specifies the domain of the account.
+/// * `user`: A `&str` that specifies the local part of the account.
+/// * `password`: A `&str` that specifies the password of the account.
+///
+/// # Returns
+///
+/// * A `Result<(), String>` that is `Ok` once the resource is bound, or an `Err` with a message.
+fn login<S: Read + Write>(
+    session: &mut Session<S>,
+    domain: &str,
+    user: &str,
+    password: &str,
+) -> Result<(), String> {
+    session.open(domain)?;
+    let features = session.expect(&["</stream:features>"])?;
+    if !features.contains("<mechanism>PLAIN</mechanism>") {
+        return Err("XMPP server does not offer SASL PLAIN".to_string());
+    }
+    let credentials =
+        base64::engine::general_purpose::STANDARD.encode(format!("\0{}\0{}", user, password));
+    session.write(&format!(
+        "<auth xmlns='urn:ietf:params:xml:ns:xmpp-sasl' mechanism='PLAIN'>{}</auth>",
+        credentials
+    ))?;
+    if !session
+        .expect(&["<success", "<failure"])?
+        .ends_with("<success")
+    {
+        return Err(format!(
+            "XMPP server rejected the login of {}@{}",
+            user, domain
+        ));
+    }
+
+    session.open(domain)?;
+    session.expect(&["</stream:features>"])?;
+    session.write("<iq type='set' id='bind'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'><resource>dnschecker</resource></bind></iq>")?;
+    let answer = session.expect(&["</iq>"])?;
+    if answer.contains("type='error'") || answer.contains("type=\"error\"") {
+        return Err("XMPP server refused to bind a resource".to_string());
+    }
+    Ok(())
+}
+
+/// Builds the stanzas that deliver a message.
+///
+/// Each group chat is joined without history before the message is sent to it.
+///
+/// # Arguments
+///
+/// * `recipients`: A `&[String]` that holds the addresses that receive the message.
+/// * `rooms`: A `&[String]` that holds the group chats that receive the message.
+/// * `nick`: A `&str` that specifies the nickname in the group chats.
+/// * `text`: A `&str` that specifies the text of the message.
+///
+/// # Returns
+///
+/// * A `Vec<String>` that holds the stanzas in the order they are sent.
+fn stanzas(recipients: &[String], rooms: &[String], nick: &str, text: &str) -> Vec<String> {
+    let body = escape(text);
+    let mut stanzas: Vec<String> = recipients
+        .iter()
+        .map(|recipient| {
+            format!(
+                "<message to='{}' type='chat'><body>{}</body></message>",
+                escape(recipient),
+                body
+            )
+        })
+        .collect();
+    for room in rooms {
+        stanzas.push(format!(
+            "<presence to='{}/{}'><x xmlns='http://jabber.org/protocol/muc'><history maxstanzas='0'/></x></presence>",
+            escape(room),
+            escape(nick)
+        ));
+        stanzas.push(format!(
+            "<message to='{}' type='groupchat'><body>{}</body></message>",
+            escape(room),
+            body
+        ));
+    }
+    stanzas
+}
+
+/// Selects the server with the lowest priority from the answer of an SRV lookup.
+///
+/// # Arguments
+///
+/// * `records`: A `&[String]` that holds the SRV records in presentation format, e.g. "5 0 5222 xmpp.example.com.".
+///
+/// # Returns
+///
+/// * An `Option<(String, u16)>` that holds the host and port, or `None` if there is no valid record.
+fn srv_target(records: &[String]) -> Option<(String, u16)> {
+    records
+        .iter()
+        .filter_map(|record| {
+            let fields: Vec<&str> = record.split_whitespace().collect();
+            match fields[..] {
+                [priority, _, port, target] if target != "." => Some((
+                    priority.parse::<u16>().ok()?,
+                    target.trim_end_matches('.').to_string(),
+                    port.parse::<u16>().ok()?,
+                )),
+                _ => None,
+            }
+        })
+        .min_by_key(|(priority, _, _)| *priority)
+        .map(|(_, host, port)| (host, port))
+}
+
+/// Escapes the characters that have a special meaning in XML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&apos;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A stream that answers with a script and records what is written.
+    struct Script {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Script {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Script {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn scripted(answers: &str) -> Session<Script> {
+        Session::new(Script {
+            input: Cursor::new(answers.as_bytes().to_vec()),
+            output: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn test_login() {
+        let mut session = scripted(concat!(
+            "<stream:stream id='1'><stream:features><mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'>",
+            "<mechanism>SCRAM-SHA-1</mechanism><mechanism>PLAIN</mechanism></mechanisms></stream:features>",
+            "<success xmlns='urn:ietf:params:xml:ns:xmpp-sasl'/>",
+            "<stream:stream id='2'><stream:features><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'/></stream:features>",
+            "<iq type='result' id='bind'><bind><jid>alerts@example.com/dnschecker</jid></bind></iq>",
+        ));
+        login(&mut session, "example.com", "alerts", "secret").unwrap();
+
+        let output = String::from_utf8(session.stream.output).unwrap();
+        // base64 of "\0alerts\0secret"
+        assert!(output.contains("mechanism='PLAIN'>AGFsZXJ0cwBzZWNyZXQ=</auth>"));
+        assert_eq!(output.matches("<stream:stream to='example.com'").count(), 2);
+        assert!(output.ends_with("<resource>dnschecker</resource></bind></iq>"));
+
+        // Assert that a rejected login fails
+        let mut session = scripted(concat!(
+            "<stream:features><mechanisms><mechanism>PLAIN</mechanism></mechanisms></stream:features>",
+            "<failure xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><not-authorized/></failure>",
+        ));
+        assert!(login(&mut session, "example.com", "alerts", "wrong").is_err());
+    }
+
+    #[test]
+    fn test_stanzas() {
+        let result = stanzas(
+            &["admin@example.com".to_string()],
+            &["ops@conference.example.com".to_string()],
+            "dnschecker",
+            "IP address mismatch <1.1.1.1 & 2.2.2.2>",
+        );
+        assert_eq!(result, vec![
+            "<message to='admin@example.com' type='chat'><body>IP address mismatch &lt;1.1.1.1 &amp; 2.2.2.2&gt;</body></message>",
+            "<presence to='ops@conference.example.com/dnschecker'><x xmlns='http://jabber.org/protocol/muc'><history maxstanzas='0'/></x></presence>",
+            "<message to='ops@conference.example.com' type='groupchat'><body>IP address mismatch &lt;1.1.1.1 &amp; 2.2.2.2&gt;</body></message>",
+        ]);
+
+        assert_eq!(
+            srv_target(&[
+                "10 0 5222 backup.example.com.".to_string(),
+                "5 0 5223 xmpp.example.com.".to_string(),
+            ]),
+            Some(("xmpp.example.com".to_string(), 5223))
+        );
+        assert_eq!(srv_target(&["0 0 0 .".to_string()]), None);
+    }
+}
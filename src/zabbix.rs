@@ -0,0 +1,238 @@
+use crate::check::Context;
+use crate::events::{Event, Subscriber};
+use serde_json::{json, Value};
+use std::env;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// The header of every message of the Zabbix sender protocol, followed by the length of the data.
+const HEADER: &[u8; 5] = b"ZBXD\x01";
+
+/// The default port of the Zabbix server trapper.
+const DEFAULT_PORT: u16 = 10051;
+
+/// Sends the verifications published on the event bus (see `events::publish`) to the Zabbix server
+/// in "ZABBIX_SERVER", if it is set, see `send_results`.
+pub struct ZabbixSubscriber;
+
+impl Subscriber for ZabbixSubscriber {
+    fn name(&self) -> &str {
+        "zabbix"
+    }
+
+    fn handles(&self, event: &Event) -> bool {
+        matches!(event, Event::Checked(_))
+            && env::var("ZABBIX_SERVER").is_ok_and(|server| !server.is_empty())
+    }
+
+    fn receive(&self, event: &Event) -> bool {
+        if let Event::Checked(context) = event {
+            send_results(context);
+        }
+        true
+    }
+}
+
+/// Sends the result of a verification to a Zabbix server.
+///
+/// This function retrieves the address of the Zabbix server from the "ZABBIX_SERVER" environment variable,
+/// e.g. "zabbix.example.com" or "zabbix.example.com:10051". If it is not set, the function returns immediately.
+///
+/// The values are sent with the sender protocol (like `zabbix_sender`) to the Zabbix host named by "ZABBIX_HOST",
+/// defaulting to the monitored hostname, so Zabbix can alert with its own triggers (see `items`).
+/// The host needs trapper items with the same keys.
+///
+/// # Arguments
+///
+/// * `context`: A `&Context` that holds the result of the verification.
+pub fn send_results(context: &Context) {
+    let server = match env::var("ZABBIX_SERVER") {
+        Ok(server) if !server.is_empty() => server,
+        _ => return,
+    };
+    let host = env::var("ZABBIX_HOST").unwrap_or(context.hostname.clone());
+    match send_items(&server, &items(context, &host)) {
+        Ok(info) => log::debug!("Zabbix server processed the results: {}", info),
+        Err(e) => log::warn!("Failed to send results to Zabbix: {}", e),
+    }
+}
+
+/// Builds the Zabbix items of a verification.
+///
+/// The item keys carry the monitored hostname as their parameter:
+///
+/// * `dnschecker.in_sync[<hostname>]`: 1 if DNS matches the WAN IP address, 0 if not. Left out if they were not compared.
+/// * `dnschecker.dns_ip[<hostname>]`: The addresses from DNS, comma-separated.
+/// * `dnschecker.wan_ip[<hostname>]`: The selected WAN IP address.
+///
+/// # Arguments
+///
+/// * `context`: A `&Context` that holds the result of the verification.
+/// * `host`: A `&str` that specifies the name of the host in Zabbix.
+///
+/// # Returns
+///
+/// * A `Vec<Value>` that holds the items in the format of the sender protocol.
+fn items(context: &Context, host: &str) -> Vec<Value> {
+    let mut values = Vec::new();
+    if let Some(in_sync) = context.in_sync {
+        values.push(("in_sync", u8::from(in_sync).to_string()));
+    }
+    values.push(("dns_ip", context.dns_ips.join(",")));
+    values.push(("wan_ip", context.wan_ip.clone()));
+    values
+        .into_iter()
+        .map(|(name, value)| {
+            json!({
+                "host": host,
+                "key": format!("dnschecker.{}[{}]", name, context.hostname),
+                "value": value,
+            })
+        })
+        .collect()
+}
+
+/// Frames data for the Zabbix sender protocol.
+///
+/// # Arguments
+///
+/// * `data`: A `&[u8]` that holds the JSON data.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` that holds the header, the length of the data as a little-endian 64-bit integer and the data.
+fn encode(data: &[u8]) -> Vec<u8> {
+    let mut packet = HEADER.to_vec();
+    packet.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    packet.extend_from_slice(data);
+    packet
+}
+
+/// Sends items to a Zabbix server and reads its answer.
+///
+/// # Arguments
+///
+/// * `server`: A `&str` that specifies the address of the server, with an optional port.
+/// * `items`: A `&[Value]` that holds the items, see `items`.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds the "info" of the answer (e.g. "processed: 3; failed: 0; …"),
+///   or an `Err` with a message if the server cannot be reached or did not accept the items.
+fn send_items(server: &str, items: &[Value]) -> Result<String, String> {
+    let address = if server.contains(':') {
+        server.to_string()
+    } else {
+        format!("{}:{}", server, DEFAULT_PORT)
+    };
+    let socket_address = address
+        .to_socket_addrs()
+        .map_err(|e| format!("Invalid Zabbix server {}: {}", address, e))?
+        .next()
+        .ok_or(format!("Zabbix server {} has no address", address))?;
+    let timeout = Duration::from_secs(10);
+    let mut stream = TcpStream::connect_timeout(&socket_address, timeout)
+        .map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
+    stream.set_read_timeout(Some(timeout)).ok();
+    stream.set_write_timeout(Some(timeout)).ok();
+
+    let request = json!({"request": "sender data", "data": items}).to_string();
+    stream
+        .write_all(&encode(request.as_bytes()))
+        .map_err(|e| format!("Failed to send to {}: {}", address, e))?;
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .map_err(|e| format!("Failed to read from {}: {}", address, e))?;
+    parse_response(&response)
+}
+
+/// Parses the answer of a Zabbix server.
+///
+/// # Arguments
+///
+/// * `response`: A `&[u8]` that holds the framed answer.
+///
+/// # Returns
+///
+/// * A `Result<String, String>` that holds the "info" of the answer, or an `Err` with a message
+///   if the answer is malformed, the request failed or some items were not processed.
+fn parse_response(response: &[u8]) -> Result<String, String> {
+    if response.len() < 13 || !response.starts_with(HEADER) {
+        return Err("Invalid answer from Zabbix server".to_string());
+    }
+    let json: Value = serde_json::from_slice(&response[13..])
+        .map_err(|e| format!("Invalid answer from Zabbix server: {}", e))?;
+    let info = json["info"].as_str().unwrap_or_default().to_string();
+    if json["response"].as_str() != Some("success") {
+        return Err(format!("Zabbix server refused the results: {}", info));
+    }
+    if !info.contains("failed: 0") {
+        return Err(format!(
+            "Zabbix server did not process every item, check the trapper items of the host: {}",
+            info
+        ));
+    }
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_items() {
+        let context = Context {
+            dns_ips: vec!["1.1.1.1".to_string(), "2.2.2.2".to_string()],
+            wan_ip: "1.1.1.1".to_string(),
+            in_sync: Some(false),
+            ..Context::new("home.example.com")
+        };
+        let result = items(&context, "router");
+        assert_eq!(result.len(), 3);
+        assert_eq!(
+            result[0],
+            json!({"host": "router", "key": "dnschecker.in_sync[home.example.com]", "value": "0"})
+        );
+        assert_eq!(result[1]["value"], "1.1.1.1,2.2.2.2");
+
+        // Assert that an uncompared result has no in_sync item
+        assert_eq!(items(&Context::new("home.example.com"), "router").len(), 2);
+    }
+
+    #[test]
+    fn test_send_items() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut header = [0u8; 13];
+            stream.read_exact(&mut header).unwrap();
+            let length = u64::from_le_bytes(header[5..].try_into().unwrap());
+            let mut data = vec![0u8; length as usize];
+            stream.read_exact(&mut data).unwrap();
+            let request: Value = serde_json::from_slice(&data).unwrap();
+            let answer =
+                json!({"response": "success", "info": "processed: 1; failed: 0; total: 1"});
+            stream
+                .write_all(&encode(answer.to_string().as_bytes()))
+                .unwrap();
+            request
+        });
+
+        let item = json!({"host": "router", "key": "dnschecker.wan_ip[home.example.com]", "value": "1.1.1.1"});
+        let result = send_items(&address, std::slice::from_ref(&item));
+        assert_eq!(result.unwrap(), "processed: 1; failed: 0; total: 1");
+        let request = server.join().unwrap();
+        assert_eq!(request["request"], "sender data");
+        assert_eq!(request["data"][0], item);
+
+        // Assert that unprocessed items are reported
+        let answer = json!({"response": "success", "info": "processed: 0; failed: 1; total: 1"});
+        assert!(parse_response(&encode(answer.to_string().as_bytes())).is_err());
+        assert!(parse_response(b"garbage").is_err());
+    }
+}